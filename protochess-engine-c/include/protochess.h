@@ -0,0 +1,99 @@
+/*
+ * C bindings for the protochess engine.
+ *
+ * Functions that take a ProtochessEngine require a pointer returned by protochess_engine_new() that has not been
+ * freed yet, and an engine must not be used by 2 threads at the same time. Strings are NUL-terminated and UTF-8
+ * encoded. The strings returned by the engine must be freed with protochess_string_free(), except the one
+ * returned by protochess_last_error().
+ */
+
+#ifndef PROTOCHESS_H
+#define PROTOCHESS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Returned by the functions that fail, the error message can be read with protochess_last_error() */
+#define PROTOCHESS_ERROR (-1)
+
+/* Result of a move (MakeMoveResultFlag) */
+#define PROTOCHESS_RESULT_OK 0
+#define PROTOCHESS_RESULT_ILLEGAL_MOVE 1
+#define PROTOCHESS_RESULT_CHECKMATE 2
+#define PROTOCHESS_RESULT_LEADER_CAPTURED 3
+#define PROTOCHESS_RESULT_ALL_PIECES_CAPTURED 4
+#define PROTOCHESS_RESULT_PIECE_IN_WIN_SQUARE 5
+#define PROTOCHESS_RESULT_WIN_CONDITION_MET 6
+#define PROTOCHESS_RESULT_CHECK_LIMIT 7
+#define PROTOCHESS_RESULT_CAPTURE_LIMIT 8
+#define PROTOCHESS_RESULT_MOVE_LIMIT 9
+#define PROTOCHESS_RESULT_STALEMATE 10
+#define PROTOCHESS_RESULT_REPETITION 11
+#define PROTOCHESS_RESULT_NO_PROGRESS 12
+#define PROTOCHESS_RESULT_INSUFFICIENT_MATERIAL 13
+#define PROTOCHESS_RESULT_RESIGNATION 14
+#define PROTOCHESS_RESULT_DRAW_AGREEMENT 15
+#define PROTOCHESS_RESULT_ADJUDICATION 16
+
+typedef struct ProtochessEngine ProtochessEngine;
+
+/*
+ * A move: the origin and destination squares, and the id of the promoted piece (0 if there is no promotion).
+ * Drops have the same origin and destination, and the id of the dropped piece in `promotion`.
+ */
+typedef struct ProtochessMove {
+    uint8_t from_x;
+    uint8_t from_y;
+    uint8_t to_x;
+    uint8_t to_y;
+    uint32_t promotion;
+} ProtochessMove;
+
+/* Creates an engine with the standard chess starting position */
+ProtochessEngine *protochess_engine_new(void);
+/* Frees an engine, does nothing if it's NULL */
+void protochess_engine_free(ProtochessEngine *engine);
+/* Message of the last error, or an empty string. Valid until the next call that modifies the engine. */
+const char *protochess_last_error(const ProtochessEngine *engine);
+/* Frees a string returned by the engine, does nothing if it's NULL */
+void protochess_string_free(char *s);
+
+/* Sets up a game state in JSON format. Returns the result of the last move of the history, or PROTOCHESS_ERROR. */
+int32_t protochess_set_state_json(ProtochessEngine *engine, const char *json);
+/* Returns the current game state in JSON format, or NULL */
+char *protochess_get_state_json(ProtochessEngine *engine);
+/* Loads a position in FEN format, keeping the current variant. Returns 0 or PROTOCHESS_ERROR. */
+int32_t protochess_load_fen(ProtochessEngine *engine, const char *fen);
+/* Returns the current position in FEN format, or NULL */
+char *protochess_get_fen(ProtochessEngine *engine);
+/* Returns 0 if it's white's turn, 1 if it's black's turn, or PROTOCHESS_ERROR */
+int32_t protochess_player_to_move(ProtochessEngine *engine);
+
+/* Plays a move. Returns a PROTOCHESS_RESULT_* code, or PROTOCHESS_ERROR if the move is invalid. */
+int32_t protochess_make_move(ProtochessEngine *engine, ProtochessMove mv);
+/* Same as protochess_make_move(), with a move in long or short algebraic notation ("e2e4", "Nf3") */
+int32_t protochess_make_move_str(ProtochessEngine *engine, const char *mv);
+/* Undoes the last move. Returns 0 or PROTOCHESS_ERROR. */
+int32_t protochess_undo(ProtochessEngine *engine);
+
+/*
+ * Searches the best move for `seconds` seconds and writes it to `out_move`. The score (for the player to move) and
+ * the search depth are written to `out_score` and `out_depth`, if they are not NULL. Returns 0, the
+ * PROTOCHESS_RESULT_* code of the result if the game is already over (the outputs are not written), or PROTOCHESS_ERROR.
+ */
+int32_t protochess_best_move_timeout(ProtochessEngine *engine, uint64_t seconds, ProtochessMove *out_move, int32_t *out_score, uint8_t *out_depth);
+/*
+ * Writes up to `capacity` legal moves to `out_moves`, and returns the total number of legal moves (which can be
+ * larger than `capacity`), or PROTOCHESS_ERROR. Call it with capacity 0 and NULL to get the size of the array.
+ */
+int32_t protochess_legal_moves(ProtochessEngine *engine, ProtochessMove *out_moves, size_t capacity);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* PROTOCHESS_H */
@@ -0,0 +1,269 @@
+//! C bindings for the protochess engine, to embed it in native apps (iOS, Android, Unity...).
+//! The API mirrors the one of the WASM crate, see `include/protochess.h`.
+//!
+//! # Safety
+//! All the functions that take a `ProtochessEngine` pointer require a pointer returned by `protochess_engine_new()`
+//! that has not been freed yet, and the engine must not be used by 2 threads at the same time.
+//! Strings are NUL-terminated and UTF-8 encoded. The strings returned by the engine must be freed with
+//! `protochess_string_free()`, except the one returned by `protochess_last_error()`.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+
+use protochess_engine_rs::prelude::{Engine, GameState, MakeMoveResultFlag, MoveInfo, SearchOutcome};
+
+/// Returned by the functions that fail. The error message can be read with `protochess_last_error()`.
+pub const PROTOCHESS_ERROR: i32 = -1;
+
+/// Engine and the last error, opaque for C
+pub struct ProtochessEngine {
+    engine: Engine,
+    last_error: CString,
+}
+
+/// A move: the origin and destination squares, and the id of the promoted piece (0 if there is no promotion).
+/// Drops have the same origin and destination, and the id of the dropped piece in `promotion`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtochessMove {
+    pub from_x: u8,
+    pub from_y: u8,
+    pub to_x: u8,
+    pub to_y: u8,
+    pub promotion: u32,
+}
+
+impl From<MoveInfo> for ProtochessMove {
+    fn from(mv: MoveInfo) -> Self {
+        ProtochessMove {
+            from_x: mv.from.0,
+            from_y: mv.from.1,
+            to_x: mv.to.0,
+            to_y: mv.to.1,
+            promotion: mv.promotion.map_or(0, |id| id as u32),
+        }
+    }
+}
+
+impl TryFrom<ProtochessMove> for MoveInfo {
+    type Error = String;
+    fn try_from(mv: ProtochessMove) -> Result<Self, String> {
+        let promotion = match mv.promotion {
+            0 => None,
+            id => Some(char::from_u32(id).ok_or_else(|| format!("Invalid piece id: {}", id))?),
+        };
+        Ok(MoveInfo { from: (mv.from_x, mv.from_y), to: (mv.to_x, mv.to_y), promotion })
+    }
+}
+
+/// Creates an engine with the standard chess starting position
+#[no_mangle]
+pub extern "C" fn protochess_engine_new() -> *mut ProtochessEngine {
+    let engine = ProtochessEngine { engine: Engine::default(), last_error: CString::default() };
+    Box::into_raw(Box::new(engine))
+}
+
+/// Frees an engine created with `protochess_engine_new()`. Does nothing if `engine` is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_engine_free(engine: *mut ProtochessEngine) {
+    if !engine.is_null() {
+        drop(Box::from_raw(engine));
+    }
+}
+
+/// Returns the message of the last error, or an empty string. The string belongs to the engine, and it's valid
+/// until the next call that modifies the engine.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_last_error(engine: *const ProtochessEngine) -> *const c_char {
+    match engine.as_ref() {
+        Some(engine) => engine.last_error.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Frees a string returned by the engine. Does nothing if `s` is NULL.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Sets up the engine with a game state in the JSON format of `GameState::to_json()`.
+/// Returns the result of the last move of the history (see `protochess_make_move()`), or `PROTOCHESS_ERROR`.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_set_state_json(engine: *mut ProtochessEngine, json: *const c_char) -> i32 {
+    call(engine, |engine| {
+        let state = GameState::from_json(to_str(json)?)?;
+        Ok(flag_code(engine.set_state(state)?.flag))
+    })
+}
+
+/// Returns the current game state in JSON format, or NULL if there is an error
+#[no_mangle]
+pub unsafe extern "C" fn protochess_get_state_json(engine: *mut ProtochessEngine) -> *mut c_char {
+    call_string(engine, |engine| Ok(engine.get_state().to_json()))
+}
+
+/// Loads a position in FEN format, keeping the current variant. Returns 0 or `PROTOCHESS_ERROR`.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_load_fen(engine: *mut ProtochessEngine, fen: *const c_char) -> i32 {
+    call(engine, |engine| {
+        engine.load_fen(to_str(fen)?)?;
+        Ok(0)
+    })
+}
+
+/// Returns the current position in FEN format, or NULL if there is an error
+#[no_mangle]
+pub unsafe extern "C" fn protochess_get_fen(engine: *mut ProtochessEngine) -> *mut c_char {
+    call_string(engine, |engine| Ok(engine.get_state_diff().fen))
+}
+
+/// Returns 0 if it's white's turn, 1 if it's black's turn, or `PROTOCHESS_ERROR`
+#[no_mangle]
+pub unsafe extern "C" fn protochess_player_to_move(engine: *mut ProtochessEngine) -> i32 {
+    call(engine, |engine| Ok(engine.player_to_move() as i32))
+}
+
+/// Plays a move. Returns one of the `PROTOCHESS_RESULT_*` codes (`PROTOCHESS_RESULT_ILLEGAL_MOVE` if the move
+/// is illegal), or `PROTOCHESS_ERROR` if the move is invalid.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_make_move(engine: *mut ProtochessEngine, mv: ProtochessMove) -> i32 {
+    call(engine, |engine| {
+        let mv = MoveInfo::try_from(mv)?;
+        Ok(flag_code(engine.make_move(&mv).flag))
+    })
+}
+
+/// Same as `protochess_make_move()`, with a move in long or short algebraic notation (`"e2e4"`, `"Nf3"`)
+#[no_mangle]
+pub unsafe extern "C" fn protochess_make_move_str(engine: *mut ProtochessEngine, mv: *const c_char) -> i32 {
+    call(engine, |engine| Ok(flag_code(engine.make_move_str(to_str(mv)?)?.flag)))
+}
+
+/// Searches the best move for `seconds` seconds, and writes it to `out_move`. The score (for the player to move)
+/// and the search depth are written to `out_score` and `out_depth`, if they are not NULL.
+/// Returns 0, the `PROTOCHESS_RESULT_*` code of the result if the game is already over (the outputs are not
+/// written), or `PROTOCHESS_ERROR`.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_best_move_timeout(engine: *mut ProtochessEngine, seconds: u64, out_move: *mut ProtochessMove, out_score: *mut i32, out_depth: *mut u8) -> i32 {
+    call(engine, |engine| {
+        if out_move.is_null() {
+            return Err("out_move cannot be NULL".to_string());
+        }
+        let (mv, score, depth) = match engine.get_best_move_timeout(seconds)? {
+            SearchOutcome::BestMove { mv, score, depth } => (mv, score, depth),
+            SearchOutcome::GameOver { result } => return Ok(flag_code(result.flag)),
+        };
+        *out_move = mv.into();
+        if let Some(out_score) = out_score.as_mut() {
+            *out_score = score;
+        }
+        if let Some(out_depth) = out_depth.as_mut() {
+            *out_depth = depth;
+        }
+        Ok(0)
+    })
+}
+
+/// Writes up to `capacity` legal moves of the current position to `out_moves`, and returns the total number of
+/// legal moves (which can be larger than `capacity`), or `PROTOCHESS_ERROR`.
+/// Call it with `capacity = 0` (and `out_moves = NULL`) to get the size of the array.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_legal_moves(engine: *mut ProtochessEngine, out_moves: *mut ProtochessMove, capacity: usize) -> i32 {
+    call(engine, |engine| {
+        let moves: Vec<ProtochessMove> = engine.legal_moves().into_iter()
+            .flat_map(|list| list.moves)
+            .map(ProtochessMove::from)
+            .collect();
+        if !out_moves.is_null() {
+            let count = moves.len().min(capacity);
+            ptr::copy_nonoverlapping(moves.as_ptr(), out_moves, count);
+        }
+        Ok(moves.len() as i32)
+    })
+}
+
+/// Undoes the last move. Returns 0 or `PROTOCHESS_ERROR`.
+#[no_mangle]
+pub unsafe extern "C" fn protochess_undo(engine: *mut ProtochessEngine) -> i32 {
+    call(engine, |engine| {
+        engine.undo()?;
+        Ok(0)
+    })
+}
+
+// Code of each MakeMoveResultFlag, the PROTOCHESS_RESULT_* constants of the header
+fn flag_code(flag: MakeMoveResultFlag) -> i32 {
+    match flag {
+        MakeMoveResultFlag::Ok => 0,
+        MakeMoveResultFlag::IllegalMove => 1,
+        MakeMoveResultFlag::Checkmate => 2,
+        MakeMoveResultFlag::LeaderCaptured => 3,
+        MakeMoveResultFlag::AllPiecesCaptured => 4,
+        MakeMoveResultFlag::PieceInWinSquare => 5,
+        MakeMoveResultFlag::WinConditionMet => 6,
+        MakeMoveResultFlag::CheckLimit => 7,
+        MakeMoveResultFlag::CaptureLimit => 8,
+        MakeMoveResultFlag::MoveLimit => 9,
+        MakeMoveResultFlag::Stalemate => 10,
+        MakeMoveResultFlag::Repetition => 11,
+        MakeMoveResultFlag::NoProgress => 12,
+        MakeMoveResultFlag::InsufficientMaterial => 13,
+        MakeMoveResultFlag::Resignation => 14,
+        MakeMoveResultFlag::DrawAgreement => 15,
+        MakeMoveResultFlag::Adjudication => 16,
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, String> {
+    if s.is_null() {
+        return Err("The string cannot be NULL".to_string());
+    }
+    CStr::from_ptr(s).to_str().map_err(|e| format!("Invalid UTF-8 string: {}", e))
+}
+
+// Runs `f` with the engine, storing the error (or the panic message) in `last_error`
+unsafe fn call(engine: *mut ProtochessEngine, f: impl FnOnce(&mut Engine) -> Result<i32, String>) -> i32 {
+    let engine = match engine.as_mut() {
+        Some(engine) => engine,
+        None => return PROTOCHESS_ERROR,
+    };
+    let result = catch_unwind(AssertUnwindSafe(|| f(&mut engine.engine)));
+    let error = match result {
+        Ok(Ok(code)) => {
+            engine.last_error = CString::default();
+            return code;
+        },
+        Ok(Err(error)) => error,
+        Err(panic) => match panic.downcast_ref::<&str>() {
+            Some(msg) => format!("The engine panicked: {}", msg),
+            None => match panic.downcast_ref::<String>() {
+                Some(msg) => format!("The engine panicked: {}", msg),
+                None => "The engine panicked".to_string(),
+            },
+        },
+    };
+    // Interior NUL bytes can't be represented in a C string
+    engine.last_error = CString::new(error.replace('\0', " ")).unwrap_or_default();
+    PROTOCHESS_ERROR
+}
+
+// Same as call(), for the functions that return a string (NULL if there is an error)
+unsafe fn call_string(engine: *mut ProtochessEngine, f: impl FnOnce(&mut Engine) -> Result<String, String>) -> *mut c_char {
+    let mut output = None;
+    let code = call(engine, |engine| {
+        output = Some(f(engine)?);
+        Ok(0)
+    });
+    match output {
+        Some(s) if code == 0 => CString::new(s.replace('\0', " ")).unwrap_or_default().into_raw(),
+        _ => ptr::null_mut(),
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod ffi {
+    use std::ffi::{CStr, CString};
+    use std::ptr;
+
+    use protochess::*;
+
+    #[test]
+    fn play_and_search() {
+        unsafe {
+            let engine = protochess_engine_new();
+            assert_eq!(protochess_player_to_move(engine), 0);
+            let count = protochess_legal_moves(engine, ptr::null_mut(), 0);
+            assert_eq!(count, 20);
+            let mut moves = vec![ProtochessMove { from_x: 0, from_y: 0, to_x: 0, to_y: 0, promotion: 0 }; count as usize];
+            assert_eq!(protochess_legal_moves(engine, moves.as_mut_ptr(), moves.len()), count);
+            assert!(moves.contains(&ProtochessMove { from_x: 4, from_y: 1, to_x: 4, to_y: 3, promotion: 0 }));
+
+            assert_eq!(protochess_make_move(engine, moves[0]), 0);
+            assert_eq!(protochess_player_to_move(engine), 1);
+            let illegal = CString::new("e2e4").unwrap();
+            assert_eq!(protochess_make_move_str(engine, illegal.as_ptr()), 1);
+            let invalid = CString::new("xyz").unwrap();
+            assert_eq!(protochess_make_move_str(engine, invalid.as_ptr()), PROTOCHESS_ERROR);
+            assert!(!CStr::from_ptr(protochess_last_error(engine)).to_bytes().is_empty());
+
+            // Copy the game to another engine
+            let json = protochess_get_state_json(engine);
+            let engine2 = protochess_engine_new();
+            assert_eq!(protochess_set_state_json(engine2, json), 0);
+            assert_eq!(protochess_player_to_move(engine2), 1);
+            protochess_string_free(json);
+
+            let fen = CString::new("k7/8/8/8/8/8/8/KR6 w - - 0 1").unwrap();
+            assert_eq!(protochess_load_fen(engine2, fen.as_ptr()), 0);
+            let mut best = moves[0];
+            let mut score = 0;
+            assert_eq!(protochess_best_move_timeout(engine2, 1, &mut best, &mut score, ptr::null_mut()), 0);
+            assert!(score > 0);
+            assert_ne!(protochess_make_move(engine2, best), 1);
+            let fen = protochess_get_fen(engine2);
+            assert!(CStr::from_ptr(fen).to_str().unwrap().contains(" b "));
+            protochess_string_free(fen);
+            // The game is already over: the result (PROTOCHESS_RESULT_CHECKMATE) is returned instead of a move
+            let fen = CString::new("R6k/6pp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+            assert_eq!(protochess_load_fen(engine2, fen.as_ptr()), 0);
+            assert_eq!(protochess_best_move_timeout(engine2, 1, &mut best, &mut score, ptr::null_mut()), 2);
+
+            protochess_engine_free(engine);
+            protochess_engine_free(engine2);
+            assert_eq!(protochess_player_to_move(ptr::null_mut()), PROTOCHESS_ERROR);
+        }
+    }
+}
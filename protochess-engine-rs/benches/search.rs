@@ -0,0 +1,19 @@
+// Run with `cargo bench`, or `cargo bench -- <depth>` to search to a different depth.
+// Compare the signature (total number of nodes) and the NPS with the ones of the previous version.
+
+use protochess_engine_rs::Engine;
+use protochess_engine_rs::utils::bench::BENCH_DEPTH;
+
+fn main() {
+    // cargo passes "--bench" to the benchmark
+    let depth = std::env::args().skip(1).find(|arg| !arg.starts_with('-'))
+        .map(|depth| depth.parse().expect("Invalid depth"))
+        .unwrap_or(BENCH_DEPTH);
+    let result = Engine::bench(depth).expect("The benchmark failed");
+    println!();
+    for position in &result.positions {
+        println!("{:<12} {:>10} nodes {:>7} ms", position.variant, position.nodes, position.elapsed_ms);
+    }
+    println!("\nSignature: {} nodes at depth {}", result.nodes, result.depth);
+    println!("NPS: {} ({} ms)", result.nps(), result.elapsed_ms);
+}
@@ -1,35 +1,69 @@
+//! A chess engine with user-defined piece movement rules.
+//!
+//! The stable public interface is re-exported in the [`prelude`] module (`use protochess_engine_rs::prelude::*`).
+//! The rest of the modules are internal: they are public so that they can be tested and benchmarked,
+//! but they can change at any time.
+
 #[macro_use]
 extern crate lazy_static;
 extern crate impl_ops;
 
+#[doc(hidden)]
 pub mod piece;
+#[doc(hidden)]
 pub mod move_generator;
+#[doc(hidden)]
 pub mod types;
+#[doc(hidden)]
 pub mod position;
+#[doc(hidden)]
 pub mod searcher;
+#[doc(hidden)]
 pub mod utils;
+pub mod prelude;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
-use std::convert::TryFrom;
 
 use position::create::position_factory::PositionFactory;
-use types::{BCoord, Centipawns, Depth, Player, ZobKey};
+use types::{BCoord, Bitboard, Centipawns, Depth, Move, Player, ZobKey};
+use move_generator::LegalityInfo;
 use searcher::Searcher;
 use utils::{to_index, from_index};
 
-pub use position::Position;
+#[doc(hidden)]
+pub use position::{Position, PieceSquares};
 pub use position::create::game_state::*;
-pub use position::global_rules::GlobalRules;
+pub use position::create::pgn::PgnHeaders;
+pub use position::create::game_tree::{GameTree, GameLine};
+pub use position::create::variant_registry::VariantRegistry;
+pub use position::create::variant_gen::{VariantGenerator, VariantConstraints};
+pub use position::global_rules::{GlobalRules, LeaderCaptureRule, StalemateRule, WinCondition, SquareEffect};
+pub use position::feature_planes::FeaturePlanes;
+pub use position::listener::{PositionListener, PieceChange};
+#[doc(hidden)]
 pub use move_generator::MoveGen;
-pub use piece::{Piece, PieceId, PieceDefinition};
-pub use types::{MoveInfo, MoveList, MakeMoveResult, MakeMoveResultFlag, MakeMoveResultWinner};
+#[doc(hidden)]
+pub use piece::Piece;
+pub use piece::{PieceId, PieceDefinition, PieceDisplay, CaptureMode, HopMove, HopLanding, CastlingRule, ValidationError};
+pub use types::{MoveInfo, MoveList, MoveStr, MakeMoveResult, MakeMoveResultFlag, MakeMoveResultWinner, GameResult, BoardDelta, SquareChange};
+pub use searcher::{SearchHandle, MAX_SKILL_LEVEL};
+pub use searcher::eval::{Evaluator, HandCraftedEvaluator, EvalWeights};
+pub use piece::MaterialWeights;
+pub use utils::tuning::TuningParams;
+pub use utils::render::RenderOptions;
+pub use types::{Algorithm, ScoreType, MATE_SCORE, SearchOutcome, SearchResult, TimeControl, SearchStats, SearchTreeNode, PvLine, BestMoveExplanation, AlternativeMove, PuzzleCandidate, MoveAnnotation, MoveClassification, EvalBreakdown, PieceEval};
 
 /// Starting point for the engine
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Engine{
     position: Position,
     factory: PositionFactory,
     num_threads: u32,
+    deterministic: bool,
+    search_handle: SearchHandle,
+    // Player that has offered a draw, until the opponent moves
+    draw_offer: Option<Player>,
 }
 
 impl Engine {
@@ -39,13 +73,16 @@ impl Engine {
     pub fn set_state(&mut self, state: GameState) -> wrap_res!(MakeMoveResult) {
         let new_pos = self.factory.set_state(state, Some(&mut self.position))?;
         if let Some(new_pos) = new_pos {
-            self.position = new_pos;
+            self.replace_position(new_pos);
         }
+        self.draw_offer = None;
         Ok(self.factory.get_last_result())
     }
     /// Updates the engine by loading a fen string. The variant is unchanged.
     pub fn load_fen(&mut self, fen: &str) -> wrap_res!() {
-        self.position = self.factory.load_fen(fen)?;
+        let new_pos = self.factory.load_fen(fen)?;
+        self.replace_position(new_pos);
+        self.draw_offer = None;
         Ok(())
     }
     /// Returns the current `GameState`, which can later be used in `set_state()`
@@ -56,10 +93,52 @@ impl Engine {
     pub fn get_state_diff(&mut self) -> StateDiff {
         StateDiff::from(&mut self.position)
     }
+    /// Same as `get_state_diff()`, but hiding the enemy pieces that the player cannot see (fog of war)
+    pub fn get_state_for_player(&mut self, player: Player) -> StateDiff {
+        StateDiff::for_player(&mut self.position, player)
+    }
+    /// Returns the squares that the player can see in fog of war variants, see `GameMode::DarkChess`
+    pub fn visible_squares(&mut self, player: Player) -> Bitboard {
+        MoveGen::visible_squares(&mut self.position, player)
+    }
     /// Returns the current move history in algebraic notation
     pub fn get_move_history(&mut self) -> &Vec<String> {
         self.factory.get_notation()
     }
+    /// Returns the algebraic notation of the `ply`-th move of the move history (starting at 1)
+    pub fn notation_at_ply(&self, ply: usize) -> wrap_res!(&str) {
+        self.factory.notation_at_ply(ply)
+    }
+    /// Returns the FEN of the position after the first `ply` moves of the move history (0 for the initial
+    /// position), e.g. for showing the position of each move in a move list
+    pub fn fen_at_ply(&self, ply: usize) -> wrap_res!(String) {
+        self.factory.fen_at_ply(ply, &self.position)
+    }
+    /// Sets up the engine with a game in PGN format. See `GameState::from_pgn()`.
+    pub fn load_pgn(&mut self, pgn: &str) -> wrap_res!(MakeMoveResult) {
+        self.set_state(GameState::from_pgn(pgn)?)
+    }
+    /// Exports the current game in PGN format
+    pub fn to_pgn(&self) -> String {
+        self.factory.to_pgn()
+    }
+    /// Same as `to_pgn()`, but includes the given tag pairs. See `PositionFactory::to_pgn_with_headers()`.
+    pub fn to_pgn_with_headers(&self, headers: &PgnHeaders) -> String {
+        self.factory.to_pgn_with_headers(headers)
+    }
+    /// Same as `to_pgn()`, but includes the annotations returned by `annotate_history()`.
+    /// See `PositionFactory::to_annotated_pgn()`.
+    pub fn to_annotated_pgn(&self, annotations: &[MoveAnnotation]) -> String {
+        self.factory.to_annotated_pgn(&PgnHeaders::new(), annotations)
+    }
+    /// Draws the current position as text, see `utils::render::to_text()`
+    pub fn render_text(&self, options: &RenderOptions) -> String {
+        utils::render::to_text(&self.position, options)
+    }
+    /// Draws the current position as an SVG image, see `utils::render::to_svg()`
+    pub fn render_svg(&self, options: &RenderOptions) -> String {
+        utils::render::to_svg(&self.position, options)
+    }
     
     /// Returns the id (can be uppercase or lowercase) of the piece at the given coordinates
     pub fn get_piece_at(&self, position: (BCoord, BCoord)) -> wrap_res!(PieceId) {
@@ -67,6 +146,17 @@ impl Engine {
         err_assert!(piece.is_some(), "No piece at the given coordinates");
         Ok(piece.unwrap().get_piece_id())
     }
+    
+    /// Returns the coordinates of all the pieces on the board, grouped by piece type and player.
+    /// Use this instead of calling `get_piece_at()` on each square.
+    pub fn get_piece_bitboards(&self) -> Vec<PieceSquares> {
+        self.position.get_piece_bitboards()
+    }
+    
+    /// Returns the display information (name, glyphs, image) of a piece type, if its definition has any
+    pub fn get_piece_display(&self, piece_id: PieceId) -> Option<PieceDisplay> {
+        self.position.lookup_piece(piece_id)?.get_movement().display.clone()
+    }
 
     /// Adds a new piece on the board. If the piece is not used for castling, `has_moved` is ignored.
     pub fn add_piece(&mut self, piece_id: PieceId, x: BCoord, y: BCoord, has_moved: bool) -> wrap_res!() {
@@ -81,23 +171,49 @@ impl Engine {
         Ok(())
     }
 
+    /// Turns an empty square into a wall (shown as `'*'` in the FEN), which pieces cannot enter or slide through
+    pub fn add_wall(&mut self, x: BCoord, y: BCoord) -> wrap_res!() {
+        self.position.public_add_wall(x, y)
+    }
+
+    /// Turns a wall back into an empty square
+    pub fn remove_wall(&mut self, x: BCoord, y: BCoord) -> wrap_res!() {
+        self.position.public_remove_wall(x, y)
+    }
+
     /// Attempts a move on the current board position
     pub fn make_move(&mut self, target_move: &MoveInfo) -> MakeMoveResult {
+        // No more moves can be made after resigning or agreeing to a draw
+        if self.factory.get_termination().is_some() {
+            return MakeMoveResult::illegal_move();
+        }
+        let mover = self.position.whos_turn;
         let result = self.position.pub_make_move(target_move);
         if result.flag != MakeMoveResultFlag::IllegalMove {
             self.factory.add_move(target_move, &result);
+            // Moving declines the draw offer of the opponent
+            if self.draw_offer.is_some_and(|player| player != mover) {
+                self.draw_offer = None;
+            }
         }
         result
     }
     
-    /// Attempts a move on the current board position, given a string in the format "e2e4"
+    /// Attempts a move on the current board position, given a string in the format "e2e4", in Standard Algebraic
+    /// Notation (like "Nf3" or "N@f3") or a castling move like "e1g1c" (see `MoveInfo::parse()`)
     pub fn make_move_str(&mut self, target_move: &str) -> wrap_res!(MakeMoveResult) {
-        let mv = MoveInfo::try_from(target_move)?;
+        let mv = MoveInfo::parse(target_move, &mut self.position)?;
         Ok(self.make_move(&mv))
     }
-
-    /// Undoes the most recent move on the current board position
+    
+    /// Undoes the most recent move on the current board position.
+    /// If the game has been terminated (see `resign()`), the termination is undone instead.
     pub fn undo(&mut self) -> wrap_res!() {
+        self.draw_offer = None;
+        if self.factory.get_termination().is_some() {
+            self.factory.set_termination(None);
+            return Ok(());
+        }
         if !self.position.can_unmake_move() {
             return Err("There is no move to undo".to_string());
         }
@@ -106,27 +222,333 @@ impl Engine {
         Ok(())
     }
     
+    /// Makes again the next move that was undone with `undo()` or `goto_ply()`.
+    /// Making a different move discards the moves that could be redone.
+    pub fn redo(&mut self) -> wrap_res!(MakeMoveResult) {
+        let next_move = self.factory.get_full_history().get(self.current_ply()).copied();
+        match next_move {
+            Some(mv) => Ok(self.make_move(&mv)),
+            None => err!("There is no move to redo"),
+        }
+    }
+    
+    /// Undoes or redoes moves until `ply` moves of `history()` have been played
+    pub fn goto_ply(&mut self, ply: usize) -> wrap_res!() {
+        let length = self.history().len();
+        err_assert!(ply <= length, "Cannot go to ply {ply}, the game only has {length} plies");
+        while self.current_ply() > ply {
+            self.undo()?;
+        }
+        while self.current_ply() < ply {
+            let _result = self.redo()?;
+        }
+        Ok(())
+    }
+    
+    /// Returns the number of moves that have been played to reach the current position
+    pub fn current_ply(&self) -> usize {
+        self.factory.get_state().move_history.len()
+    }
+    
+    /// Returns all the moves of the game, including the ones after the current ply that can be redone
+    pub fn history(&self) -> &[MoveInfo] {
+        self.factory.get_full_history()
+    }
+    
+    /// Returns the mainline and the variations of the game. `history()` contains the moves of the selected line.
+    pub fn game_tree(&self) -> &GameTree {
+        self.factory.get_game_tree()
+    }
+    
+    /// Adds a variation that branches from the selected line after `at_ply` moves, and returns its index.
+    /// The current position and the selected line don't change.
+    pub fn add_variation(&mut self, at_ply: usize, moves: &[MoveInfo]) -> wrap_res!(usize) {
+        self.factory.add_variation(at_ply, moves, &self.position)
+    }
+    
+    /// Selects another line of the game tree (see `PositionFactory::select_line()`)
+    pub fn select_line(&mut self, index: usize) -> wrap_res!() {
+        self.factory.select_line(index, &mut self.position)
+    }
+    
+    /// Returns the lines with an alternative to the last move played, including the selected line.
+    /// Use `select_line()` to switch between them.
+    pub fn sibling_lines(&self) -> Vec<usize> {
+        match self.current_ply() {
+            0 => Vec::new(),
+            ply => self.game_tree().lines_at(ply - 1),
+        }
+    }
+    
     /// Returns `0` if it's white's turn, `1` if it's black's turn
     pub fn player_to_move(&self) -> Player {
         self.position.whos_turn
     }
     
-    /// Returns the best move for the current position, along with the evaluation score
-    pub fn get_best_move(&mut self, depth: Depth) -> wrap_res!(MoveInfo, Centipawns) {
+    /// Returns the best move for the current position, along with the evaluation score and the search depth.
+    /// If the game is over because the player to move cannot move (checkmate, stalemate, captured leader) or it has
+    /// been terminated (see `resign()`), returns the result of the game instead. Positions that are drawn by a rule
+    /// with legal moves left (repetition, insufficient material...) are searched.
+    /// The search stops before `depth` if it finds a forced mate that is shorter than the searched depth.
+    pub fn get_best_move(&mut self, depth: Depth) -> wrap_res!(SearchOutcome) {
+        self.get_best_move_with_callback(depth, |_| {})
+    }
+    
+    /// Same as `get_best_move()`, but returns an error if the game is over
+    #[deprecated(note = "use get_best_move(), which returns SearchOutcome::GameOver instead of an error")]
+    pub fn get_best_move_or_err(&mut self, depth: Depth) -> wrap_res!(MoveInfo, Centipawns) {
+        self.validate_position()?;
+        match self.get_best_move(depth)? {
+            SearchOutcome::BestMove { mv, score, .. } => Ok((mv, score)),
+            SearchOutcome::GameOver { result } => err!("The game is already over ({:?})", result.flag),
+        }
+    }
+
+    /// Same as `get_best_move()`, but the search stops after (approximately) `max_sec` seconds.
+    /// Part of the time is only used if the best move keeps changing, so a stable search can stop earlier.
+    /// If the time runs out while the best move is changing, the deepest stable best move is returned instead.
+    pub fn get_best_move_timeout(&mut self, max_sec: u64) -> wrap_res!(SearchOutcome) {
+        self.get_best_move_timeout_with_callback(max_sec, |_| {})
+    }
+    
+    /// Same as `get_best_move()`, but calls `on_result` each time a new depth has been searched,
+    /// allowing the user to display the progress of the search
+    pub fn get_best_move_with_callback(&mut self, depth: Depth, mut on_result: impl FnMut(SearchResult)) -> wrap_res!(SearchOutcome) {
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        if let Some(game_over) = self.game_over_outcome() {
+            return Ok(game_over);
+        }
+        let _running = self.search_handle.start();
+        let (pv, score, search_depth) = Searcher::get_best_move_with_callback(&self.position, depth, self.search_threads(), &self.search_handle, &mut on_result);
+        // A stopped search can return a lower depth. MCTS only uses the depth as the limit of the tree,
+        // and the skill level can limit the depth. Forced mates also stop the search early.
+        let depth_is_limit = self.search_handle.algorithm() != Algorithm::AlphaBeta || self.search_handle.skill_level() < MAX_SKILL_LEVEL;
+        let mate_found = ScoreType::from(score).is_mate();
+        err_assert!(search_depth == depth || self.search_handle.stop_requested() || depth_is_limit || mate_found, "Search depth ({search_depth}) != requested depth ({depth})");
+        err_assert!(!pv.is_empty(), "No moves found");
+        Ok(SearchOutcome::BestMove { mv: pv[0].into(), score, depth: search_depth })
+    }
+    
+    /// Same as `get_best_move()`, but only searches the given moves (like the `searchmoves` option of UCI).
+    /// Returns an error if any of the moves is illegal, or if the list is empty.
+    pub fn get_best_move_among(&mut self, moves: &[MoveInfo], depth: Depth) -> wrap_res!(MoveInfo, Centipawns) {
         self.validate_position()?;
+        let legal_moves = MoveGen::get_legal_moves(&mut self.position);
+        for target_move in moves {
+            err_assert!(legal_moves.iter().any(|&mv| *target_move == mv), "Illegal move: {target_move}");
+        }
+        let avoided_moves: Vec<Move> = legal_moves.into_iter()
+            .filter(|&mv| !moves.iter().any(|target_move| *target_move == mv))
+            .collect();
+        self.get_best_move_avoiding_impl(&avoided_moves, depth)
+    }
+    
+    /// Same as `get_best_move()`, but doesn't play any of the given moves. Returns an error if any of the moves
+    /// is illegal, or if there are no other legal moves.
+    pub fn get_best_move_avoiding(&mut self, avoid_moves: &[MoveInfo], depth: Depth) -> wrap_res!(MoveInfo, Centipawns) {
+        self.validate_position()?;
+        let legal_moves = MoveGen::get_legal_moves(&mut self.position);
+        for target_move in avoid_moves {
+            err_assert!(legal_moves.iter().any(|&mv| *target_move == mv), "Illegal move: {target_move}");
+        }
+        let avoided_moves: Vec<Move> = legal_moves.into_iter()
+            .filter(|&mv| avoid_moves.iter().any(|target_move| *target_move == mv))
+            .collect();
+        self.get_best_move_avoiding_impl(&avoided_moves, depth)
+    }
+    
+    // Searches the legal moves that are not in `avoided_moves`
+    fn get_best_move_avoiding_impl(&mut self, avoided_moves: &[Move], depth: Depth) -> wrap_res!(MoveInfo, Centipawns) {
+        let _running = self.search_handle.start();
         err_assert!(depth != 0, "Depth must be greater than 0");
-        let (pv, score, search_depth) = Searcher::get_best_move(&self.position, depth, self.num_threads);
-        err_assert!(search_depth == depth, "Search depth ({search_depth}) != requested depth ({depth})");
+        let num_legal_moves = MoveGen::get_legal_moves(&mut self.position).len();
+        err_assert!(avoided_moves.len() < num_legal_moves, "There are no moves to search");
+        let (pv, score, search_depth) = Searcher::get_best_move_avoiding(&self.position, avoided_moves, depth, self.search_threads(), &self.search_handle, &mut |_| {});
+        // A stopped search can return a lower depth, and the skill level can limit the depth. Forced mates also stop the search early.
+        let depth_is_limit = self.search_handle.skill_level() < MAX_SKILL_LEVEL;
+        let mate_found = ScoreType::from(score).is_mate();
+        err_assert!(search_depth == depth || self.search_handle.stop_requested() || depth_is_limit || mate_found, "Search depth ({search_depth}) != requested depth ({depth})");
         err_assert!(!pv.is_empty(), "No moves found");
         Ok((pv[0].into(), score))
     }
-
-    /// Returns the best move for the current position, along with the evaluation score and the search depth
-    pub fn get_best_move_timeout(&mut self, max_sec: u64) -> wrap_res!(MoveInfo, Centipawns, Depth) {
+    
+    /// Same as `get_best_move()`, but returns the result of the last iteration, which includes the principal
+    /// variation, the number of nodes searched and the elapsed time. Returns an error if the game is over.
+    pub fn get_best_move_result(&mut self, depth: Depth) -> wrap_res!(SearchResult) {
+        self.validate_position()?;
+        let mut last_result = None;
+        let _outcome = self.get_best_move_with_callback(depth, |result| last_result = Some(result))?;
+        last_result.ok_or_else(|| "The search was stopped before completing depth 1".to_string())
+    }
+    
+    /// Same as `get_best_move_with_callback()`, but each `SearchResult` also contains the first `tree_plies` plies
+    /// of the search tree (see `SearchResult::tree`), for debugging the search. Keep `tree_plies` small (2 or 3),
+    /// since the size of the tree grows exponentially.
+    pub fn get_best_move_with_tree_callback(&mut self, depth: Depth, tree_plies: u8, mut on_result: impl FnMut(SearchResult)) -> wrap_res!(MoveInfo, Centipawns) {
+        self.validate_position()?;
+        let _running = self.search_handle.start();
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        let (pv, score, search_depth) = Searcher::get_best_move_with_tree_callback(&self.position, depth, tree_plies, self.search_threads(), &self.search_handle, &mut on_result);
+        // A stopped search can return a lower depth, and so can a forced mate
+        let mate_found = ScoreType::from(score).is_mate();
+        err_assert!(search_depth == depth || self.search_handle.stop_requested() || mate_found, "Search depth ({search_depth}) != requested depth ({depth})");
+        err_assert!(!pv.is_empty(), "No moves found");
+        Ok((pv[0].into(), score))
+    }
+    
+    /// Same as `get_best_move_timeout()`, but calls `on_result` each time a new depth has been searched
+    pub fn get_best_move_timeout_with_callback(&mut self, max_sec: u64, on_result: impl FnMut(SearchResult)) -> wrap_res!(SearchOutcome) {
+        self.get_best_move_timeout_ms_with_callback(max_sec.saturating_mul(1000), on_result)
+    }
+    
+    /// Same as `get_best_move_timeout_with_callback()`, but the search stops after (approximately) `max_ms` milliseconds
+    pub fn get_best_move_timeout_ms_with_callback(&mut self, max_ms: u64, mut on_result: impl FnMut(SearchResult)) -> wrap_res!(SearchOutcome) {
+        if let Some(game_over) = self.game_over_outcome() {
+            return Ok(game_over);
+        }
+        let _running = self.search_handle.start();
+        let (pv, score, search_depth) = Searcher::get_best_move_timeout_ms_with_callback(&self.position, max_ms, self.search_threads(), &self.search_handle, &mut on_result);
+        err_assert!(!pv.is_empty(), "No moves found");
+        Ok(SearchOutcome::BestMove { mv: pv[0].into(), score, depth: search_depth })
+    }
+    
+    /// Same as `get_best_move()`, but the thinking time is chosen from the clock of the player to move: the search
+    /// takes longer when the best move is unstable, and plays forced moves and obvious recaptures quickly.
+    pub fn get_best_move_clock(&mut self, clock: TimeControl) -> wrap_res!(SearchOutcome) {
+        self.get_best_move_clock_with_callback(clock, |_| {})
+    }
+    
+    /// Same as `get_best_move_clock()`, but calls `on_result` each time a new depth has been searched
+    pub fn get_best_move_clock_with_callback(&mut self, clock: TimeControl, mut on_result: impl FnMut(SearchResult)) -> wrap_res!(SearchOutcome) {
+        if let Some(game_over) = self.game_over_outcome() {
+            return Ok(game_over);
+        }
+        let _running = self.search_handle.start();
+        let (pv, score, search_depth) = Searcher::get_best_move_clock_with_callback(&self.position, &clock, self.search_threads(), &self.search_handle, &mut on_result);
+        err_assert!(!pv.is_empty(), "No moves found");
+        Ok(SearchOutcome::BestMove { mv: pv[0].into(), score, depth: search_depth })
+    }
+    
+    /// Same as `get_best_move()`, but the search stops after (approximately) `max_nodes` nodes, which makes the
+    /// result reproducible regardless of the speed of the machine (when using a single thread).
+    pub fn get_best_move_nodes(&mut self, max_nodes: u64) -> wrap_res!(SearchOutcome) {
+        if let Some(game_over) = self.game_over_outcome() {
+            return Ok(game_over);
+        }
+        let _running = self.search_handle.start();
+        let (pv, score, search_depth) = Searcher::get_best_move_nodes(&self.position, max_nodes, self.search_threads(), &self.search_handle);
+        err_assert!(!pv.is_empty(), "No moves found");
+        Ok(SearchOutcome::BestMove { mv: pv[0].into(), score, depth: search_depth })
+    }
+    
+    /// Searches for a mate in `mate_in` moves (or less) for the player to move.
+    /// Returns the mating line, or an error if there is no such mate.
+    pub fn get_best_move_mate(&mut self, mate_in: Depth) -> wrap_res!(Vec<MoveInfo>) {
         self.validate_position()?;
-        let (pv, score, search_depth) = Searcher::get_best_move_timeout(&self.position, max_sec, self.num_threads);
+        let _running = self.search_handle.start();
+        err_assert!(mate_in != 0, "The number of moves must be greater than 0");
+        let (pv, score, _search_depth) = Searcher::get_best_move_mate(&self.position, mate_in, self.search_threads(), &self.search_handle);
         err_assert!(!pv.is_empty(), "No moves found");
-        Ok((pv[0].into(), score, search_depth))
+        err_assert!(Searcher::is_mate_score(score, mate_in), "No mate in {mate_in} found");
+        Ok(pv.into_iter().map(Into::into).collect())
+    }
+    
+    /// Multi-PV search: returns the best `num_lines` lines for the current position, sorted from best to worst.
+    /// If there are fewer legal moves than `num_lines`, one line per legal move is returned.
+    pub fn get_best_moves(&mut self, depth: Depth, num_lines: u32) -> wrap_res!(Vec<PvLine>) {
+        self.validate_position()?;
+        let _running = self.search_handle.start();
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        err_assert!(num_lines != 0, "Number of lines must be greater than 0");
+        let lines = Searcher::get_best_moves(&self.position, depth, num_lines, self.search_threads(), &self.search_handle);
+        let (best_pv, score, search_depth) = &lines[0];
+        let mate_found = ScoreType::from(*score).is_mate();
+        err_assert!(*search_depth == depth || self.search_handle.stop_requested() || mate_found, "Search depth ({search_depth}) != requested depth ({depth})");
+        err_assert!(!best_pv.is_empty(), "No moves found");
+        Ok(lines.into_iter().map(|(pv, score, _depth)| {
+            PvLine { score, score_type: score.into(), pv: pv.into_iter().map(Into::into).collect() }
+        }).collect())
+    }
+    
+    /// Returns the best move for the current position, along with up to `num_alternatives` of the most
+    /// promising alternative moves and the line that refutes each of them (why they are worse than the best move).
+    /// Each alternative requires an additional search, so this is much slower than `get_best_move()`.
+    pub fn explain_best_move(&mut self, depth: Depth, num_alternatives: usize) -> wrap_res!(BestMoveExplanation) {
+        self.validate_position()?;
+        let _running = self.search_handle.start();
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        let explanation = Searcher::explain_best_move(&self.position, depth, num_alternatives, self.search_threads(), &self.search_handle);
+        err_assert!(explanation.is_some(), "No moves found");
+        Ok(explanation.unwrap())
+    }
+    
+    /// Searches each position of the current game (from the initial position to the current one) for puzzles: positions
+    /// in which the best move is at least `threshold` centipawns better than the second best move.
+    /// If no moves have been played, only the current position is searched. Each position requires a multi-PV search
+    /// of the given depth, so this is slow.
+    pub fn find_tactics(&mut self, depth: Depth, threshold: Centipawns) -> wrap_res!(Vec<PuzzleCandidate>) {
+        self.validate_position()?;
+        let _running = self.search_handle.start();
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        let current_ply = self.current_ply();
+        let mut puzzles = Vec::new();
+        for ply in 0..=current_ply {
+            self.goto_ply(ply)?;
+            if self.search_handle.stop_requested() {
+                break;
+            }
+            if self.game_result().is_some() {
+                continue;
+            }
+            let tactic = Searcher::find_tactic(&self.position, depth, threshold, self.search_threads(), &self.search_handle);
+            if let Some(((pv, score, _depth), gain)) = tactic {
+                puzzles.push(PuzzleCandidate {
+                    ply,
+                    fen: self.get_state_diff().fen,
+                    score,
+                    gain,
+                    themes: Searcher::puzzle_themes(&self.position, &pv, score),
+                    solution: pv.into_iter().map(Into::into).collect(),
+                });
+            }
+        }
+        self.goto_ply(current_ply)?;
+        Ok(puzzles)
+    }
+    
+    /// Compares each move of the current game with the best move found by a search of the given depth,
+    /// and classifies it as a blunder, a mistake, an inaccuracy or a good move. Each move requires up to 2 searches,
+    /// so this is slow. See `PositionFactory::annotate_history()`.
+    pub fn annotate_history(&mut self, depth: Depth) -> wrap_res!(Vec<MoveAnnotation>) {
+        let _running = self.search_handle.start();
+        self.factory.annotate_history_with(depth, self.search_threads(), &self.search_handle)
+    }
+    
+    /// Encodes the current position as planes, to be used as the input of a neural network.
+    /// See `FeaturePlanes` for the layout of the planes.
+    pub fn to_feature_planes(&self) -> FeaturePlanes {
+        self.position.to_feature_planes()
+    }
+    
+    /// Returns the number of times that the current position has occurred (including now) since the last capture,
+    /// also counting the positions before the initial FEN (see `GameState::prior_fens`)
+    pub fn repetition_count(&self) -> u32 {
+        self.position.repetition_count()
+    }
+    
+    /// Returns the static evaluation of the current position (without searching), for the player to move
+    pub fn evaluate_position(&self) -> Centipawns {
+        let position = self.weighted_position();
+        match self.search_handle.evaluator() {
+            Some(evaluator) => evaluator.evaluate(&position),
+            None => searcher::eval::evaluate_with_weights(&position, &self.eval_weights().eval),
+        }
+    }
+    
+    /// Same as `evaluate_position()`, but returns how each piece and each term contributes to the score
+    /// of the built-in evaluation
+    pub fn evaluate_position_detailed(&self) -> EvalBreakdown {
+        searcher::eval::evaluate_breakdown_with_weights(&self.weighted_position(), &self.eval_weights().eval)
     }
     
     /// Returns an error if the current position is invalid
@@ -134,7 +556,7 @@ impl Engine {
         let player = self.position.whos_turn;
         let player_str = if player == 0 { "White" } else { "Black" };
         if self.position.leader_is_captured() {
-            let has_leader = self.position.pieces[player as usize].get_leader().is_some();
+            let has_leader = self.position.pieces[player as usize].has_leader();
             let piece_str = if has_leader { "leaders" } else { "pieces" };
             err!("All the {piece_str} of the player to move ({player_str}) have already been captured");
         }
@@ -144,21 +566,156 @@ impl Engine {
     
     /// Returns a list of all squares (x,y) from which the given piece can move, along with the moves themselves
     pub fn legal_moves(&mut self) -> Vec<MoveList> {
+        group_by_origin(MoveGen::get_legal_moves(&mut self.position))
+    }
+    
+    /// Returns `true` if the move is legal in the current position, without making it
+    pub fn would_be_legal(&mut self, target_move: &MoveInfo) -> bool {
+        MoveGen::get_pseudo_moves(&mut self.position, true).into_iter()
+            .any(|mv| *target_move == mv && MoveGen::is_move_legal(mv, &mut self.position))
+    }
+    
+    /// Returns the legal replies of the opponent after the given move (in the format of `legal_moves()`), without
+    /// changing the state of the engine. Useful to validate premoves and puzzle solutions.
+    /// If the player has more moves left in the turn, returns the next moves of the same player.
+    /// Returns an empty list if the move is illegal or ends the game.
+    pub fn legal_moves_after(&mut self, target_move: &MoveInfo) -> Vec<MoveList> {
+        let result = self.position.pub_make_move(target_move);
+        match result.flag {
+            MakeMoveResultFlag::IllegalMove => Vec::new(),
+            MakeMoveResultFlag::Ok => {
+                let moves = group_by_origin(MoveGen::get_legal_moves(&mut self.position));
+                self.position.unmake_move();
+                moves
+            },
+            _ => {
+                self.position.unmake_move();
+                Vec::new()
+            },
+        }
+    }
+
+    /// Returns the squares that the piece at `(x, y)` can move to, sorted by index and without duplicates (a move
+    /// with several promotions is only included once). Cheaper than `legal_moves()` when a UI only needs to
+    /// highlight the destinations of the selected piece. Castling moves use the square of the rook, like `MoveInfo`.
+    pub fn legal_targets_from(&mut self, x: BCoord, y: BCoord) -> Vec<(BCoord, BCoord)> {
+        if !self.position.in_bounds(x, y) {
+            return Vec::new();
+        }
+        let from = to_index(x, y);
+        let info = LegalityInfo::new(&mut self.position);
+        let mut targets = Bitboard::zero();
+        for mv in MoveGen::get_pseudo_moves(&mut self.position, true) {
+            // Only test the legality of the moves of this piece
+            if mv.get_from() != from || mv.get_drop_piece().is_some() || mv.is_pass() {
+                continue;
+            }
+            if MoveGen::is_move_legal_with(mv, &mut self.position, &info) {
+                let (to_x, to_y) = MoveInfo::from(mv).to;
+                targets.set_bit(to_index(to_x, to_y));
+            }
+        }
+        targets.to_coords()
+    }
+    
+    /// Returns the result of the game if it's over in the current position, or `None` if the game continues.
+    /// The result is derived from the position, so it also works after loading a finished game with `set_state()`.
+    pub fn game_result(&mut self) -> Option<GameResult> {
+        if let Some(termination) = self.factory.get_termination() {
+            return Some(termination.result());
+        }
+        self.position.game_result()
+    }
+    
+    /// Ends the game with the resignation of `player`, and returns the result
+    pub fn resign(&mut self, player: Player) -> wrap_res!(GameResult) {
+        err_assert!(player < 2, "Invalid player: {player}");
+        self.terminate(GameTermination::Resignation { player })
+    }
+    
+    /// Offers a draw to the opponent of `player`, which can be accepted with `accept_draw()`.
+    /// The offer is declined when the opponent makes a move.
+    pub fn offer_draw(&mut self, player: Player) -> wrap_res!() {
+        err_assert!(player < 2, "Invalid player: {player}");
+        err_assert!(self.game_result().is_none(), "The game is already over");
+        self.draw_offer = Some(player);
+        Ok(())
+    }
+    
+    /// Returns the player that has offered a draw, if the offer has not been declined yet
+    pub fn draw_offer(&self) -> Option<Player> {
+        self.draw_offer
+    }
+    
+    /// Accepts the draw offered with `offer_draw()`, ending the game. Returns an error if there is no offer.
+    pub fn accept_draw(&mut self) -> wrap_res!(GameResult) {
+        err_assert!(self.draw_offer.is_some(), "No draw has been offered");
+        self.terminate(GameTermination::DrawAgreement)
+    }
+    
+    /// Ends the game with the given result, decided by an arbiter (e.g. when a player runs out of time or
+    /// disconnects). `MakeMoveResultWinner::None` is a draw.
+    pub fn adjudicate(&mut self, winner: MakeMoveResultWinner) -> wrap_res!(GameResult) {
+        let winner = match winner {
+            MakeMoveResultWinner::White => Some(0),
+            MakeMoveResultWinner::Black => Some(1),
+            MakeMoveResultWinner::None => None,
+        };
+        self.terminate(GameTermination::Adjudication { winner })
+    }
+    
+    /// Returns `true` if the player to move is in check (one of the leaders whose capture would lose the game is attacked)
+    pub fn is_in_check(&mut self) -> bool {
+        !self.position.leader_is_captured() && MoveGen::in_check(&mut self.position)
+    }
+    
+    /// Returns the squares of the enemy pieces that are giving check to the player to move
+    pub fn checking_pieces(&mut self) -> Vec<(BCoord, BCoord)> {
+        if self.position.leader_is_captured() {
+            return Vec::new();
+        }
+        MoveGen::checking_pieces(&mut self.position).to_coords()
+    }
+    
+    /// Returns the squares of the pieces of `player` that attack the square `(x, y)`, whether the square is empty
+    /// or not. Useful for highlighting hanging pieces.
+    pub fn attackers_of(&mut self, x: BCoord, y: BCoord, player: Player) -> Vec<(BCoord, BCoord)> {
+        if !self.position.in_bounds(x, y) || player > 1 {
+            return Vec::new();
+        }
+        MoveGen::attackers_of(to_index(x, y), &mut self.position, player).to_coords()
+    }
+    
+    /// Returns `true` if the player to move has at least one legal move. Stops at the first legal move found.
+    pub fn has_any_legal_move(&mut self) -> bool {
+        let info = LegalityInfo::new(&mut self.position);
+        MoveGen::get_pseudo_moves(&mut self.position, true).into_iter()
+            .any(|mv| MoveGen::is_move_legal_with(mv, &mut self.position, &info))
+    }
+
+    /// Returns a flat list of all legal moves in string form (long and short algebraic notation),
+    /// sorted by their long notation. Useful for scripting and debugging.
+    pub fn legal_moves_str(&mut self) -> Vec<MoveStr> {
         let all_moves = MoveGen::get_legal_moves(&mut self.position);
-        let mut moves_from_map = HashMap::new();
+        let mut output = Vec::with_capacity(all_moves.len());
         for mv in all_moves {
-            let from = mv.get_from();
-            let coords = from_index(from);
-            let from_moves = moves_from_map.entry(coords).or_insert_with(Vec::new);
-            from_moves.push(MoveInfo::from(mv));
-        }
-        let mut output = Vec::new();
-        for ((x,y), moves) in moves_from_map {
-            output.push(MoveList{x, y, moves});
+            let mv = MoveInfo::from(mv);
+            // Play the move to find out the full notation (including check and checkmate indicators)
+            let result = self.position.pub_make_move(&mv);
+            self.position.unmake_move();
+            let san = result.move_notation.expect("Legal move should have a notation");
+            output.push(MoveStr { long: mv.to_string(), san });
         }
+        output.sort_by(|a, b| a.long.cmp(&b.long));
         output
     }
-    
+
+    /// Converts a line of moves starting at the current position (e.g. the `pv` of a `SearchResult`) to algebraic
+    /// notation, including the check and checkmate indicators. The line ends at the first illegal move.
+    pub fn line_to_san(&self, line: &[MoveInfo]) -> Vec<String> {
+        utils::notation::line_to_san(&self.position, line)
+    }
+
     /// Returns a list of all possible promotions for the given move
     pub fn possible_promotions(&mut self, from: (BCoord, BCoord), to: (BCoord, BCoord)) -> Vec<PieceId> {
         MoveGen::get_legal_moves(&mut self.position)
@@ -188,8 +745,8 @@ impl Engine {
             rayon::current_num_threads() as u32
         }
     }
-    /// Sets the number of threads to use. This does not resize the global thread pool,
-    /// but rather changes the number of tasks that will be submitted to the pool.
+    /// Sets the number of threads to use. This does not resize the global thread pool: the search runs on the
+    /// calling thread and on its own helper threads, which are kept alive between searches.
     /// By default, all available threads are used (see `get_max_threads()`).
     pub fn set_num_threads(&mut self, num_threads: u32) -> wrap_res!() {
         if num_threads > Self::get_max_threads() {
@@ -199,6 +756,179 @@ impl Engine {
         Ok(())
     }
     
+    /// Enables or disables the deterministic mode, disabled by default. In deterministic mode, searching the same
+    /// position with the same parameters always returns the same result: the search uses a single thread
+    /// (regardless of `set_num_threads()`), so there are no races between threads.
+    /// Searches limited by time (`get_best_move_timeout()` and `get_best_move_clock()`) are still not reproducible,
+    /// use a fixed depth or `get_best_move_nodes()` instead.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+    /// Returns `true` if the deterministic mode is enabled (see `set_deterministic()`)
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+    
+    /// Returns a handle that can stop the searches of this engine from another thread
+    pub fn search_handle(&self) -> SearchHandle {
+        self.search_handle.clone()
+    }
+    
+    /// Evaluates the positions with `evaluator` (in the searches and in `evaluate_position()`), instead of
+    /// the built-in hand-crafted evaluation. Clones of the engine use the same evaluator.
+    pub fn set_evaluator(&mut self, evaluator: Box<dyn Evaluator + Send + Sync>) {
+        self.search_handle.set_evaluator(Some(evaluator.into()));
+    }
+    
+    /// Goes back to the built-in evaluation, after calling `set_evaluator()`
+    pub fn use_default_evaluator(&mut self) {
+        self.search_handle.set_evaluator(None);
+    }
+    
+    /// Sets the weights of the built-in evaluation, e.g. the ones found by `Tuner::tune()`. They are used by the
+    /// searches and by `evaluate_position()`. A custom evaluator (`set_evaluator()`) ignores the evaluation weights,
+    /// but the material weights are still used by the move ordering. Clones of the engine use the same weights.
+    pub fn set_eval_weights(&mut self, weights: TuningParams) {
+        self.search_handle.set_eval_weights(weights);
+    }
+    /// Returns the weights set with `set_eval_weights()`
+    pub fn eval_weights(&self) -> TuningParams {
+        self.search_handle.eval_weights()
+    }
+    
+    /// Selects the algorithm of `get_best_move()` and the other searches that return a single line (by depth,
+    /// time, clock or nodes). With `Algorithm::Mcts`, the depth is the maximum depth of the tree, the node limit
+    /// is the maximum number of simulations, and a single thread is used. The mate searches, the multi-PV searches
+    /// and the search trees always use alpha-beta. Clones of the engine use the same algorithm.
+    pub fn set_search_algorithm(&mut self, algorithm: Algorithm) -> wrap_res!() {
+        if let Algorithm::Mcts { simulations, c_puct } = algorithm {
+            err_assert!(simulations > 0, "The number of simulations must be greater than 0");
+            err_assert!(c_puct.is_finite() && c_puct > 0.0, "The exploration constant must be positive, not {c_puct}");
+        }
+        self.search_handle.set_algorithm(algorithm);
+        Ok(())
+    }
+    /// Returns the algorithm selected with `set_search_algorithm()`
+    pub fn search_algorithm(&self) -> Algorithm {
+        self.search_handle.algorithm()
+    }
+
+    /// Sets the contempt of the searches: the draws (repetition, stalemate, insufficient material, etc.) are scored
+    /// as `-contempt` for the player to move when the search starts, instead of 0. A positive contempt avoids the
+    /// draws, a negative one seeks them. The contempt must be between -10000 and 10000, so that it cannot be
+    /// confused with a mate score. Clones of the engine use the same contempt.
+    pub fn set_contempt(&mut self, contempt: Centipawns) -> wrap_res!() {
+        err_assert!(contempt.abs() <= 10_000, "The contempt must be between -10000 and 10000, not {contempt}");
+        self.search_handle.set_contempt(contempt);
+        Ok(())
+    }
+    /// Returns the contempt set with `set_contempt()`
+    pub fn contempt(&self) -> Centipawns {
+        self.search_handle.contempt()
+    }
+
+    /// In the "play for a win" mode (used by match runners to avoid drawn games), the contempt is at least
+    /// 1000 centipawns, so the engine only accepts a draw when the alternative is losing a lot of material.
+    pub fn set_play_for_win(&mut self, play_for_win: bool) {
+        self.search_handle.set_play_for_win(play_for_win);
+    }
+    /// Returns true if the "play for a win" mode is enabled, see `set_play_for_win()`
+    pub fn plays_for_win(&self) -> bool {
+        self.search_handle.plays_for_win()
+    }
+
+    /// Weakens the engine, for playing against humans. The skill level goes from 0 (weakest) to `MAX_SKILL_LEVEL`
+    /// (20, full strength, the default). Below full strength, the searches that return a single line search at
+    /// most `level + 1` plies, and the move is chosen among the best 4 lines: the lower the level, the more likely
+    /// it is to play a worse move. The choice is deterministic (the same position always gets the same move).
+    /// The mate searches, the multi-PV searches and the analysis (`explain_best_move()`, `annotate_game()`, etc.)
+    /// always play at full strength. Clones of the engine use the same skill level.
+    pub fn set_skill_level(&mut self, level: u8) -> wrap_res!() {
+        err_assert!(level <= MAX_SKILL_LEVEL, "The skill level must be between 0 and {MAX_SKILL_LEVEL}, not {level}");
+        self.search_handle.set_skill_level(level);
+        Ok(())
+    }
+    /// Returns the skill level set with `set_skill_level()`
+    pub fn skill_level(&self) -> u8 {
+        self.search_handle.skill_level()
+    }
+
+    /// Sets the seed of the random choices of the searches (`set_skill_level()` and `set_opening_variety()`).
+    /// With the same seed, the same position always gets the same move. The default seed is 0.
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.search_handle.set_random_seed(seed);
+    }
+    /// Returns the seed set with `set_random_seed()`
+    pub fn random_seed(&self) -> u64 {
+        self.search_handle.random_seed()
+    }
+
+    /// During the first `turns` turns since the initial position, the searches that return a single line choose
+    /// uniformly at random (see `set_random_seed()`) among the best 8 moves whose score is at most `margin`
+    /// centipawns below the best one, so that the games don't always start in the same way.
+    /// `turns == 0` disables the opening variety (the default). The searches that return several lines and the
+    /// analysis always return the best move.
+    pub fn set_opening_variety(&mut self, turns: u32, margin: Centipawns) -> wrap_res!() {
+        err_assert!(margin >= 0, "The margin must not be negative, not {margin}");
+        self.search_handle.set_opening_variety(if turns == 0 { None } else { Some((turns, margin)) });
+        Ok(())
+    }
+    /// Returns the number of turns and the margin set with `set_opening_variety()`, or None if disabled
+    pub fn opening_variety(&self) -> Option<(u32, Centipawns)> {
+        self.search_handle.opening_variety()
+    }
+
+    /// Registers a listener that receives the changes of the pieces on the board (also during the searches),
+    /// see `Position::set_listener()`. The listener is kept (and reset) when a new position is loaded.
+    pub fn set_position_listener(&mut self, listener: Option<Box<dyn PositionListener>>) {
+        self.position.set_listener(listener);
+    }
+    
+    // Marks the game as over, unless it has already ended
+    fn terminate(&mut self, termination: GameTermination) -> wrap_res!(GameResult) {
+        err_assert!(self.game_result().is_none(), "The game is already over");
+        self.factory.set_termination(Some(termination));
+        self.draw_offer = None;
+        Ok(termination.result())
+    }
+    
+    // Result of the game for the searches that return a single move, if the player to move cannot move
+    fn game_over_outcome(&mut self) -> Option<SearchOutcome> {
+        let can_move = !self.position.leader_is_captured() && self.has_any_legal_move();
+        if self.factory.get_termination().is_none() && can_move {
+            return None;
+        }
+        self.game_result().map(|result| SearchOutcome::GameOver { result })
+    }
+    
+    // Replaces the current position, moving the listener to the new one
+    fn replace_position(&mut self, mut position: Position) {
+        position.set_listener(self.position.take_listener());
+        self.position = position;
+    }
+    
+    // Current position, with the material weights of `set_eval_weights()`
+    fn weighted_position(&self) -> Cow<'_, Position> {
+        if self.eval_weights().material == MaterialWeights::default() {
+            return Cow::Borrowed(&self.position);
+        }
+        let mut position = self.position.clone();
+        self.search_handle.apply_material_weights(&mut position);
+        Cow::Owned(position)
+    }
+    
+    // Number of threads used by the search
+    fn search_threads(&self) -> u32 {
+        if self.deterministic { 1 } else { self.num_threads }
+    }
+    
+    
+    /// Searches a fixed set of positions of several variants to the given depth (see `utils::bench`), and
+    /// returns the number of nodes and the time of each one. The total number of nodes is a signature of the
+    /// engine, and the nodes per second can be used to detect performance regressions.
+    pub fn bench(depth: Depth) -> wrap_res!(utils::bench::BenchResult) {
+        utils::bench::bench(depth)
+    }
     
     
     // Debugging functions
@@ -211,8 +941,52 @@ impl Engine {
     pub fn perft_divide(&mut self, depth: Depth) -> usize {
         utils::perft::perft_divide(&mut self.position, depth)
     }
+    pub fn perft_hashed(&mut self, depth: Depth) -> usize {
+        utils::perft::perft_hashed(&mut self.position, depth)
+    }
+    /// Same as `perft()`, using a transposition table to count the transpositions only once (see `utils::perft::perft_tt()`)
+    pub fn perft_tt(&mut self, depth: Depth) -> usize {
+        utils::perft::perft_tt(&mut self.position, depth)
+    }
+    /// Same as `perft()`, counting the legal moves in parallel (see `utils::perft::perft_parallel()`)
+    pub fn perft_parallel(&mut self, depth: Depth, num_threads: usize) -> usize {
+        utils::perft::perft_parallel(&mut self.position, depth, num_threads)
+    }
+    /// Returns the number of nodes after each legal move (see `utils::perft::divide()`)
+    pub fn divide(&mut self, depth: Depth) -> utils::perft::Divide {
+        utils::perft::divide(&mut self.position, depth)
+    }
+    /// Finds the first position where the move generator disagrees with a reference (see `utils::perft::bisect()`)
+    pub fn perft_bisect(&self, depth: Depth, reference: &mut dyn FnMut(&[String], Depth) -> utils::perft::Divide) -> Option<utils::perft::PerftDivergence> {
+        utils::perft::bisect(&self.position, depth, reference)
+    }
+    pub fn perft_copy_make(&mut self, depth: Depth) -> usize {
+        utils::perft::perft_copy_make(&mut self.position, depth)
+    }
+    /// Checks that the incrementally updated state of the current position matches the state computed from scratch
+    /// (see `Position::check_consistency()`)
+    pub fn check_consistency(&self) -> wrap_res!() {
+        self.position.check_consistency()
+    }
+}
+
+// Groups the moves by the square they start from
+fn group_by_origin(all_moves: Vec<Move>) -> Vec<MoveList> {
+    let mut moves_from_map = HashMap::new();
+    for mv in all_moves {
+        let from = mv.get_from();
+        let coords = from_index(from);
+        let from_moves = moves_from_map.entry(coords).or_insert_with(Vec::new);
+        from_moves.push(MoveInfo::from(mv));
+    }
+    let mut output = Vec::new();
+    for ((x,y), moves) in moves_from_map {
+        output.push(MoveList{x, y, moves});
+    }
+    output
 }
 
+
 impl std::fmt::Display for Engine {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.position)
@@ -227,6 +1001,20 @@ impl Default for Engine {
         let position = factory.set_state(state, None).unwrap().unwrap();
         // Use maximum number of threads (usually this is too many, the user should change this later)
         let num_threads = Self::get_max_threads();
-        Engine { position, factory, num_threads }
+        Engine { position, factory, num_threads, deterministic: false, search_handle: SearchHandle::default(), draw_offer: None }
+    }
+}
+
+impl Clone for Engine {
+    // The clone has its own search handle, so that its searches can be stopped independently
+    fn clone(&self) -> Self {
+        Engine {
+            position: self.position.clone(),
+            factory: self.factory.clone(),
+            num_threads: self.num_threads,
+            deterministic: self.deterministic,
+            search_handle: self.search_handle.detached(),
+            draw_offer: self.draw_offer,
+        }
     }
 }
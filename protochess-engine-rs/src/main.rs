@@ -1,146 +1,438 @@
-//#[macro_use] extern crate scan_rules;
+use std::io::{BufRead, Write};
 
-use std::io::Write;
+use protochess_engine_rs::prelude::*;
+use protochess_engine_rs::utils::matchrunner::{self, MatchConfig, MatchLimit, MatchPlayer};
+use protochess_engine_rs::utils::bench::BENCH_DEPTH;
 
-use protochess_engine_rs::{Engine, MoveInfo, MakeMoveResultFlag, MakeMoveResultWinner, GameState};
-use protochess_engine_rs::types::GameMode;
-use protochess_engine_rs::utils::debug::split_debug_fen;
+// Some interesting FENs:
+// "R3b3/4k3/2n5/p4p1p/4p3/2B5/1PP2PPP/5K2 w - - 10 36"
+// "rnbqkbnr/nnnnnnnn/rrrrrrrr/8/8/8/QQQQQQQQ/RNBQKBNR w KQkq - 0 1"
+// "rnbqkbnr/pp4pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
+// "r1b3nr/ppqk1Bbp/2pp4/4P1B1/3n4/3P4/PPP2QPP/R4RK1 w - - 1 0"
+// "1Q6/5pk1/2p3p1/1pbbN2p/4n2P/8/r5P1/5K2 b - - 0 1"
+// "rnbqkbnr/pppppppp/8/8/8/8/8/RNBQKBNR w KQkq - 0 1"
+
+const USAGE: &str = "\
+Usage: cargo run -- <command> [arguments] [options]
+
+Commands:
+  play [fen]                  Play against the engine in the terminal
+  analyze <fen>               Search the position and print the best move
+  perft <depth> [fen]         Count the leaf nodes at a given depth, for each legal move
+  selfplay [fen]              Let the engine play against itself and write the game to pgn.txt
+  match [fen]                 Play a match between two search settings and write the games to match.pgn
+  validate <gamestate.json>   Check that a GameState file (same JSON format as the frontend) is valid
+  bench                       Search a fixed set of positions and print the nodes and the speed (default depth: 6)
+  help                        Print this message
+
+Options:
+  --depth <n>                 Search up to a fixed depth (default: 12, or 8 when playing)
+  --time <seconds>            Search for a fixed amount of time instead of a fixed depth
+  --plies <n>                 Maximum number of plies to play in selfplay (default: 500)
+  --color <white|black>       Side played by the human in play (default: white)
+  --games <n>                 Number of games of the match (default: 10)
+  --opponent-depth <n>        Search depth of the second engine of the match (default: same as --depth)
+
+FENs can end with a variant name (see GameMode), for example:
+  cargo run -- analyze \"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 atomic\"";
+
+const PLAY_HELP: &str = "\
+Enter a move in long algebraic notation (e2e4, e7e8=Q) or one of the following commands:
+  moves    List all legal moves
+  fen      Print the FEN of the current position
+  undo     Undo your last move (and the engine's reply)
+  help     Print this message
+  quit     Exit the game";
 
 pub fn main() {
-    
-    // Some interesting FENs:
-    // "R3b3/4k3/2n5/p4p1p/4p3/2B5/1PP2PPP/5K2 w - - 10 36"
-    // "rnbqkbnr/nnnnnnnn/rrrrrrrr/8/8/8/QQQQQQQQ/RNBQKBNR w KQkq - 0 1"
-    // "rnbqkbnr/pp4pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"
-    // "r1b3nr/ppqk1Bbp/2pp4/4P1B1/3n4/3P4/PPP2QPP/R4RK1 w - - 1 0"
-    // "1Q6/5pk1/2p3p1/1pbbN2p/4n2P/8/r5P1/5K2 b - - 0 1"
-    // "rnbqkbnr/pppppppp/8/8/8/8/8/RNBQKBNR w KQkq - 0 1"
-    
-    
-    // Usage: cargo run -- <depth> <fen> <num_ply>
-    // By default, <depth> is 12, <fen> is the starting position, and <num_ply> is 500
-    // Example: cargo run -- 4 "1Q6/5pk1/2p3p1/1pbbN2p/4n2P/8/r5P1/5K2 b - - 0 1"
-    
-    let mut pgn_file = std::fs::File::create("pgn.txt").expect("create failed");
-
-    
-    let args: Vec<String> = std::env::args().collect();
-    let mut fixed_depth = true;
-    let mut depth = 12;
-    let mut max_ply = 500;
-    if args.len() > 3 {
-        max_ply = args[3].parse::<u32>().unwrap();
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(&args) {
+        eprintln!("Error: {e}\n");
+        eprintln!("{USAGE}");
+        std::process::exit(1);
     }
-    
-    
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let (positional, options) = parse_args(args)?;
+    let command = match positional.first() {
+        Some(command) => command.as_str(),
+        None => "help",
+    };
+    let arg = |i: usize| positional.get(i).map(String::as_str);
+    match command {
+        "play" => play(arg(1), &options),
+        "analyze" => analyze(arg(1).ok_or("Missing the FEN to analyze")?, &options),
+        "perft" => {
+            let depth = arg(1).ok_or("Missing the perft depth")?;
+            let depth = depth.parse::<Depth>().map_err(|_| format!("Invalid depth: '{depth}'"))?;
+            perft(depth, arg(2))
+        },
+        "selfplay" => selfplay(arg(1), &options),
+        "match" => run_match(arg(1), &options),
+        "validate" => validate(arg(1).ok_or("Missing the path to the GameState file")?),
+        "bench" => bench(&options),
+        "help" | "--help" | "-h" => {
+            println!("{USAGE}");
+            Ok(())
+        },
+        _ => Err(format!("Unknown command: '{command}'")),
+    }
+}
+
+
+#[derive(Debug, Clone, Copy)]
+enum SearchLimit {
+    Depth(Depth),
+    Time(u64),
+}
+
+#[derive(Debug, Clone)]
+struct Options {
+    limit: Option<SearchLimit>,
+    max_plies: u32,
+    human_player: Player,
+    num_games: u32,
+    opponent_depth: Option<Depth>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options { limit: None, max_plies: 500, human_player: 0, num_games: 10, opponent_depth: None }
+    }
+}
+
+// Splits the arguments into positional arguments and options
+fn parse_args(args: &[String]) -> Result<(Vec<String>, Options), String> {
+    let mut positional = Vec::new();
+    let mut options = Options::default();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        // `--help` is a command, not an option with a value
+        if !arg.starts_with("--") || arg == "--help" {
+            positional.push(arg.clone());
+            continue;
+        }
+        let value = iter.next().ok_or(format!("Missing value for option '{arg}'"))?;
+        let invalid = || format!("Invalid value for option '{arg}': '{value}'");
+        match arg.as_str() {
+            "--depth" => {
+                let depth = value.parse::<Depth>().map_err(|_| invalid())?;
+                if depth == 0 {
+                    return Err(invalid());
+                }
+                options.limit = Some(SearchLimit::Depth(depth));
+            },
+            "--time" => {
+                let time = value.parse::<u64>().map_err(|_| invalid())?;
+                options.limit = Some(SearchLimit::Time(time));
+            },
+            "--plies" => options.max_plies = value.parse::<u32>().map_err(|_| invalid())?,
+            "--games" => options.num_games = value.parse::<u32>().map_err(|_| invalid())?,
+            "--opponent-depth" => {
+                let depth = value.parse::<Depth>().map_err(|_| invalid())?;
+                if depth == 0 {
+                    return Err(invalid());
+                }
+                options.opponent_depth = Some(depth);
+            },
+            "--color" => {
+                options.human_player = match value.to_lowercase().as_str() {
+                    "white" | "w" => 0,
+                    "black" | "b" => 1,
+                    _ => return Err(invalid()),
+                };
+            },
+            _ => return Err(format!("Unknown option: '{arg}'")),
+        }
+    }
+    Ok((positional, options))
+}
+
+// Creates an engine with the given debug FEN (which may include a variant name), or the default position
+fn create_engine(fen: Option<&str>) -> Result<Engine, String> {
     let mut engine = Engine::default();
-    
-    if args.len() > 2 && args[2] != "default" {
-        let state = GameState::from_debug_fen(&args[2]);
-        print_pgn_header(&args[2], &mut pgn_file);
-        let _result = engine.set_state(state).unwrap_or_else(|e| {
-            println!("Incorrect FEN: {e}");
-            std::process::exit(1);
-        });
+    if let Some(fen) = fen {
+        let state = GameState::from_debug_fen(fen);
+        let _result = engine.set_state(state).map_err(|e| format!("Incorrect FEN: {e}"))?;
+    }
+    Ok(engine)
+}
+
+fn search(engine: &mut Engine, limit: SearchLimit) -> Result<(MoveInfo, Centipawns, Depth), String> {
+    let outcome = match limit {
+        SearchLimit::Depth(depth) => engine.get_best_move(depth)?,
+        SearchLimit::Time(time) => engine.get_best_move_timeout(time)?,
+    };
+    match outcome {
+        SearchOutcome::BestMove { mv, score, depth } => Ok((mv, score, depth)),
+        SearchOutcome::GameOver { result } => Err(format!("The game is already over ({:?})", result.flag)),
     }
-    if args.len() > 1 {
-        if args[1].contains('t') {
-            fixed_depth = false;
-            depth = args[1].replace('t', "").parse::<u8>().unwrap();
-        } else {
-            depth = args[1].parse::<u8>().unwrap();
+}
+
+
+fn play(fen: Option<&str>, options: &Options) -> Result<(), String> {
+    let mut engine = create_engine(fen)?;
+    let limit = options.limit.unwrap_or(SearchLimit::Depth(8));
+    println!("{PLAY_HELP}\n");
+    println!("{engine}\n");
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        let result = {
+            if engine.player_to_move() == options.human_player {
+                print!("> ");
+                std::io::stdout().flush().unwrap();
+                let line = match lines.next() {
+                    Some(line) => line.map_err(|e| e.to_string())?,
+                    None => return Ok(()),
+                };
+                match line.trim() {
+                    "" => continue,
+                    "quit" | "exit" => return Ok(()),
+                    "help" => {
+                        println!("{PLAY_HELP}");
+                        continue;
+                    },
+                    "fen" => {
+                        println!("{}", engine.get_state_diff().fen);
+                        continue;
+                    },
+                    "moves" => {
+                        let moves: Vec<_> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+                        println!("{}", moves.join(" "));
+                        continue;
+                    },
+                    "undo" => {
+                        // Undo the engine's reply (if any) and the human's last move
+                        if engine.get_move_history().len() >= 2 {
+                            engine.undo()?;
+                            engine.undo()?;
+                            println!("{engine}\n");
+                        } else {
+                            println!("There is no move to undo");
+                        }
+                        continue;
+                    },
+                    input => {
+                        let result = match engine.make_move_str(input) {
+                            Ok(result) => result,
+                            Err(e) => {
+                                println!("{e}");
+                                continue;
+                            },
+                        };
+                        if result.flag == MakeMoveResultFlag::IllegalMove {
+                            println!("Illegal move: '{input}' (type 'moves' to list all legal moves)");
+                            continue;
+                        }
+                        result
+                    },
+                }
+            } else {
+                let (mv, score, depth) = search(&mut engine, limit)?;
+                let result = engine.make_move(&mv);
+                let notation = result.move_notation.clone().unwrap_or_else(|| mv.to_string());
+                println!("Engine plays {notation} (eval: {score}, depth: {depth})");
+                result
+            }
+        };
+        println!("{engine}\n");
+        if print_game_over(&result) {
+            return Ok(());
         }
     }
-    
+}
+
+
+fn analyze(fen: &str, options: &Options) -> Result<(), String> {
+    let mut engine = create_engine(Some(fen))?;
+    let limit = options.limit.unwrap_or(SearchLimit::Depth(12));
+    println!("{engine}\n");
+    let (mv, score, depth) = search(&mut engine, limit)?;
+    let result = engine.make_move(&mv);
+    let notation = result.move_notation.unwrap_or_else(|| "!!!".to_string());
+    println!("\nBest move: {mv} ({notation})");
+    println!("Evaluation: {score} (depth {depth})");
+    Ok(())
+}
+
+
+fn perft(depth: Depth, fen: Option<&str>) -> Result<(), String> {
+    let mut engine = create_engine(fen)?;
+    println!("{engine}\n");
+    let start = instant::Instant::now();
+    let nodes = engine.perft_divide(depth);
+    println!("\nTotal: {nodes} nodes at depth {depth} ({:?})", start.elapsed());
+    Ok(())
+}
+
+
+fn selfplay(fen: Option<&str>, options: &Options) -> Result<(), String> {
+    let mut engine = create_engine(fen)?;
+    let limit = options.limit.unwrap_or(SearchLimit::Depth(12));
+
     println!("Start Position:\n{engine}");
     println!("\n----------------------------------------\n");
-    
+
     let start = instant::Instant::now();
-    for ply in 0..max_ply {
-        let mv = {
-            if fixed_depth {
-                engine.get_best_move(depth).unwrap().0
-            } else {
-                engine.get_best_move_timeout(depth as u64).unwrap().0
-            }
-        };
+    for ply in 0..options.max_plies {
+        let (mv, _, _) = search(&mut engine, limit)?;
         println!("\n========================================\n");
         println!("(Time since start: {:?})", start.elapsed());
         println!("PLY: {ply} Engine plays:\n");
         let result = engine.make_move(&mv);
-        let move_str = result.move_notation.unwrap_or_else(|| "!!!".to_string());
-        print_pgn(&mut pgn_file, ply, &fix_notation(move_str, mv));
+        // Rewrite the whole file after each move, so that the game is saved even if it's interrupted
+        std::fs::write("pgn.txt", engine.to_pgn()).map_err(|e| format!("Could not write pgn.txt: {e}"))?;
         println!("{engine}\n");
-        match result.flag {
-            MakeMoveResultFlag::Ok => {
-                println!("----------------------------------------\n");
-            },
-            MakeMoveResultFlag::IllegalMove => {
-                panic!("An illegal move was made");
-            },
-            MakeMoveResultFlag::Checkmate => {
-                println!("CHECKMATE! {:?} wins!", result.winner);
-                break;
-            },
-            MakeMoveResultFlag::LeaderCaptured => {
-                println!("KING HAS BEEN CAPTURED! {:?} wins!", result.winner);
-                break;
-            },
-            MakeMoveResultFlag::AllPiecesCaptured => {
-                println!("ALL PIECES HAVE BEEN CAPTURED! {:?} wins!", result.winner);
-                break;
-            },
-            MakeMoveResultFlag::PieceInWinSquare => {
-                println!("KING IN WINNING SQUARE! {:?} wins!", result.winner);
-                break;
-            },
-            MakeMoveResultFlag::CheckLimit => {
-                println!("CHECK LIMIT REACHED! {:?} wins!", result.winner);
-                break;
-            },
-            MakeMoveResultFlag::Stalemate => {
-                if result.winner == MakeMoveResultWinner::None {
-                    println!("DRAW BY STALEMATE!");
-                } else {
-                    println!("STALEMATE! {:?} wins!", result.winner);
-                }
-                break;
-            },
-            MakeMoveResultFlag::Repetition => {
-                println!("DRAW BY REPETITION!");
-                break;
-            },
+        if print_game_over(&result) {
+            break;
+        }
+        println!("----------------------------------------\n");
+    }
+    Ok(())
+}
+
+
+fn run_match(fen: Option<&str>, options: &Options) -> Result<(), String> {
+    let opening = create_engine(fen)?.get_state().clone();
+    let limit = match options.limit.unwrap_or(SearchLimit::Depth(4)) {
+        SearchLimit::Depth(depth) => MatchLimit::Depth(depth),
+        SearchLimit::Time(time) => MatchLimit::Time(time),
+    };
+    let opponent_limit = options.opponent_depth.map_or(limit, MatchLimit::Depth);
+    let config = MatchConfig {
+        players: [
+            MatchPlayer { name: format!("{limit:?}"), limit, num_threads: None, eval_weights: TuningParams::default() },
+            MatchPlayer { name: format!("{opponent_limit:?}"), limit: opponent_limit, num_threads: None, eval_weights: TuningParams::default() },
+        ],
+        openings: vec![opening],
+        num_games: options.num_games,
+        max_plies: options.max_plies,
+    };
+    let result = matchrunner::run_match(&config, |game| {
+        let white = &config.players[game.white].name;
+        let black = &config.players[1 - game.white].name;
+        match &game.result {
+            Some(result) => println!("{white} vs {black}: {:?} ({:?}) after {} plies", result.winner, result.flag, game.plies),
+            None => println!("{white} vs {black}: draw by adjudication after {} plies", game.plies),
         }
+    })?;
+    std::fs::write("match.pgn", result.to_pgn()).map_err(|e| format!("Could not write match.pgn: {e}"))?;
+
+    let (wins, draws, losses) = result.wins_draws_losses();
+    println!("\n{} vs {}: +{wins} ={draws} -{losses}", config.players[0].name, config.players[1].name);
+    match (result.elo_difference(), result.elo_error_margin()) {
+        (Some(elo), Some(margin)) => println!("Elo difference: {elo:.1} +/- {margin:.1}"),
+        _ => println!("Elo difference: unknown (one of the engines won all the games)"),
     }
+    Ok(())
 }
 
 
-fn print_pgn_header(fen: &str, pgn_file: &mut std::fs::File) {
-    let (fen, variant) = split_debug_fen(fen);
-    if variant != GameMode::Standard {
-        pgn_file.write_all(format!("[Variant \"{variant}\"]\n").as_bytes()).unwrap();
+fn bench(options: &Options) -> Result<(), String> {
+    let depth = match options.limit {
+        None => BENCH_DEPTH,
+        Some(SearchLimit::Depth(depth)) => depth,
+        Some(SearchLimit::Time(_)) => return Err("The benchmark searches to a fixed depth, use --depth".to_string()),
+    };
+    let result = Engine::bench(depth)?;
+    println!();
+    for position in &result.positions {
+        let fen = position.fen.as_deref().unwrap_or("(starting position)");
+        println!("{:<12} {:>10} nodes {:>7} ms  {}", position.variant, position.nodes, position.elapsed_ms, fen);
     }
-    pgn_file.write_all(format!("[FEN \"{fen}\"]\n\n").as_bytes()).unwrap();
+    println!("\nDepth: {}\nNodes: {}\nTime: {} ms\nNPS: {}", result.depth, result.nodes, result.elapsed_ms, result.nps());
+    Ok(())
 }
 
-fn print_pgn(pgn_file: &mut std::fs::File, ply: u32, move_str: &str) {
-    if (ply % 2) == 0 {
-        let round = format!("{}. ", ply/2 + 1);
-        pgn_file.write_all(round.as_bytes()).expect("write failed");
+
+fn validate(path: &str) -> Result<(), String> {
+    let json = std::fs::read_to_string(path).map_err(|e| format!("Could not read '{path}': {e}"))?;
+    let state: GameState = serde_json::from_str(&json).map_err(|e| format!("Invalid GameState JSON: {e}"))?;
+    let initial_state = &state.initial_state;
+    println!("Board size: {}x{}", initial_state.board_width, initial_state.board_height);
+    println!("Piece types: {}", initial_state.piece_types.len());
+    println!("Moves played: {}", state.move_history.len());
+
+    let mut engine = Engine::default();
+    let result = engine.set_state(state)?;
+    println!("\n{engine}\n");
+    if print_game_over(&result) {
+        println!("The GameState is valid, but the game is already over");
+        return Ok(());
     }
-    pgn_file.write_all(move_str.as_bytes()).expect("write failed");
-    pgn_file.write_all(b" ").expect("write failed");
+    engine.validate_position()?;
+    println!("The GameState is valid");
+    Ok(())
 }
 
 
-// Algebraic notation specifies that a pawn capture includes the file of the capturing pawn,
-// even if the capture is unambiguous without it. This is not generalizable at all, so
-// the engine does not include the file in the notation.
-// If we want Lichess to accept our PGNs, we need to add the file back in.
-fn fix_notation(move_str: String, mv: MoveInfo) -> String {
-    if move_str.starts_with('x') {
-        format!("{}{}", (b'a' + mv.from.0) as char, move_str)
-    } else {
-        move_str
+// Prints the outcome of the game if it has ended. Returns true if the game is over.
+fn print_game_over(result: &MakeMoveResult) -> bool {
+    match result.flag {
+        MakeMoveResultFlag::Ok => return false,
+        MakeMoveResultFlag::IllegalMove => {
+            panic!("An illegal move was made");
+        },
+        MakeMoveResultFlag::Checkmate => {
+            println!("CHECKMATE! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::LeaderCaptured => {
+            println!("KING HAS BEEN CAPTURED! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::AllPiecesCaptured => {
+            println!("ALL PIECES HAVE BEEN CAPTURED! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::PieceInWinSquare => {
+            println!("KING IN WINNING SQUARE! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::WinConditionMet => {
+            println!("WIN CONDITION MET! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::CheckLimit => {
+            println!("CHECK LIMIT REACHED! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::CaptureLimit => {
+            println!("CAPTURE LIMIT REACHED! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::MoveLimit => {
+            if result.winner == MakeMoveResultWinner::None {
+                println!("DRAW BY MOVE LIMIT!");
+            } else {
+                println!("MOVE LIMIT REACHED! {:?} wins!", result.winner);
+            }
+        },
+        MakeMoveResultFlag::Stalemate => {
+            if result.winner == MakeMoveResultWinner::None {
+                println!("DRAW BY STALEMATE!");
+            } else {
+                println!("STALEMATE! {:?} wins!", result.winner);
+            }
+        },
+        MakeMoveResultFlag::Repetition => {
+            println!("DRAW BY REPETITION!");
+        },
+        MakeMoveResultFlag::NoProgress => {
+            println!("DRAW BY MOVES WITHOUT PROGRESS!");
+        },
+        MakeMoveResultFlag::InsufficientMaterial => {
+            println!("DRAW BY INSUFFICIENT MATERIAL!");
+        },
+        MakeMoveResultFlag::Resignation => {
+            println!("RESIGNATION! {:?} wins!", result.winner);
+        },
+        MakeMoveResultFlag::DrawAgreement => {
+            println!("DRAW BY AGREEMENT!");
+        },
+        MakeMoveResultFlag::Adjudication => {
+            if result.winner == MakeMoveResultWinner::None {
+                println!("DRAW BY ADJUDICATION!");
+            } else {
+                println!("ADJUDICATION! {:?} wins!", result.winner);
+            }
+        },
     }
+    true
 }
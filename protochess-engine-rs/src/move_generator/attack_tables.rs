@@ -1,12 +1,33 @@
-use crate::types::{Bitboard, BIndex};
-use crate::utils::{from_index, to_index};
+use crate::types::{Bitboard, BIndex, BOARD_WIDTH, BOARD_SIZE};
+#[cfg(not(feature = "large-boards"))]
+use crate::types::BCoord;
+use crate::utils::to_index;
+#[cfg(not(feature = "large-boards"))]
+use crate::utils::from_index;
 
-/// Holds pre-calculated attack tables for the pieces, assuming a 16x16 size board
+// Bits of the first square of each rank in a 64-bit word (4 ranks of 16 squares)
+#[cfg(not(feature = "large-boards"))]
+const FILE_BITS: u64 = 0x0001_0001_0001_0001;
+// Multiplying the file bits of a word by this number moves them to bits 48..51
+#[cfg(all(not(feature = "large-boards"), not(all(target_arch = "x86_64", target_feature = "bmi2"))))]
+const FILE_GATHER_MAGIC: u64 = (1 << 48) | (1 << 33) | (1 << 18) | (1 << 3);
+// Multiplying a 4-bit number by this number (and masking with FILE_BITS) moves bit i to bit 16*i
+#[cfg(all(not(feature = "large-boards"), not(all(target_arch = "x86_64", target_feature = "bmi2"))))]
+const FILE_SCATTER_MAGIC: u64 = 1 | (1 << 15) | (1 << 30) | (1 << 45);
+
+/// Holds pre-calculated attack tables for the pieces, assuming a BOARD_WIDTH x BOARD_WIDTH size board
 /// Only for classical set of pieces
 #[derive(Clone, Debug)]
 pub struct AttackTables {
-    // Precomputed slider moves for a given 16-bit row and index
+    // Precomputed slider moves for a given 16-bit row and index.
+    // With large boards a rank doesn't fit in a lookup table, and horizontal slides are computed square by square.
+    #[cfg(not(feature = "large-boards"))]
     rank_slides: Vec<Vec<u16>>,
+    // Squares on the same diagonal and antidiagonal as a given square
+    #[cfg(not(feature = "large-boards"))]
+    diagonal_masks: Vec<Bitboard>,
+    #[cfg(not(feature = "large-boards"))]
+    antidiagonal_masks: Vec<Bitboard>,
     // Precomputed masks for bits at the left and right of a given square
     left_masks: Vec<Bitboard>,
     right_masks: Vec<Bitboard>,
@@ -18,18 +39,68 @@ pub struct AttackTables {
     // Masks for edges of the board
     horizontal_edges: Bitboard,
     vertical_edges: Bitboard,
+    #[cfg(feature = "large-boards")]
+    left_edge: Bitboard,
+    #[cfg(feature = "large-boards")]
+    right_edge: Bitboard,
 }
 
 impl AttackTables {
     pub fn new() -> AttackTables {
-        //16 * 2^16 possible states; 16 squares in 1 rank, 2^16 possible occupancies per rank
-        let mut slider_attacks = vec![vec![0; 65536]; 16];
         let mut top_bits = Bitboard::zero();
         let mut bottom_bits = Bitboard::zero();
         let mut left_bits = Bitboard::zero();
         let mut right_bits = Bitboard::zero();
-        let mut left_masks = Vec::with_capacity(256);
-        let mut right_masks = Vec::with_capacity(256);
+        let mut left_masks = Vec::with_capacity(BOARD_SIZE);
+        let mut right_masks = Vec::with_capacity(BOARD_SIZE);
+        for i in 0..BOARD_WIDTH {
+            top_bits.set_bit_at(i, BOARD_WIDTH - 1);
+            bottom_bits.set_bit_at(i, 0);
+            left_bits.set_bit_at(0, i);
+            right_bits.set_bit_at(BOARD_WIDTH - 1, i);
+        }
+        for _ in 0..BOARD_SIZE {
+            left_masks.push(Bitboard::zero());
+            right_masks.push(Bitboard::zero());
+        }
+        for x in 0..BOARD_WIDTH {
+            for y in 0..BOARD_WIDTH {
+                let index: usize = to_index(x, y) as usize;
+                for j in 0..x {
+                    left_masks[index].set_bit_at(j, y);
+                }
+                for j in (x + 1)..BOARD_WIDTH {
+                    right_masks[index].set_bit_at(j, y);
+                }
+            }
+        }
+        
+        AttackTables{
+            #[cfg(not(feature = "large-boards"))]
+            rank_slides: Self::generate_rank_slides(),
+            #[cfg(not(feature = "large-boards"))]
+            diagonal_masks: Self::generate_line_masks(1),
+            #[cfg(not(feature = "large-boards"))]
+            antidiagonal_masks: Self::generate_line_masks(-1),
+            left_masks,
+            right_masks,
+            top_right: &top_bits | &right_bits,
+            bottom_left: &bottom_bits | &left_bits,
+            top_left: &top_bits | &left_bits,
+            bottom_right: &bottom_bits | &right_bits,
+            horizontal_edges: &left_bits | &right_bits,
+            vertical_edges: &top_bits | &bottom_bits,
+            #[cfg(feature = "large-boards")]
+            left_edge: left_bits,
+            #[cfg(feature = "large-boards")]
+            right_edge: right_bits,
+        }
+    }
+
+    #[cfg(not(feature = "large-boards"))]
+    fn generate_rank_slides() -> Vec<Vec<u16>> {
+        //16 * 2^16 possible states; 16 squares in 1 rank, 2^16 possible occupancies per rank
+        let mut slider_attacks = vec![vec![0; 65536]; 16];
         //16 squares in 1 rank
         for i in 0..16 {
             //2^16 = 65536 possible occupancies
@@ -64,40 +135,29 @@ impl AttackTables {
                 }
                 slider_attacks[i as usize][occ as usize] = right_attack ^ left_attack;
             }
-            top_bits.set_bit_at(i, 15);
-            bottom_bits.set_bit_at(i, 0);
-            left_bits.set_bit_at(0, i);
-            right_bits.set_bit_at(15, i);
-        }
-        for _ in 0..256 {
-            left_masks.push(Bitboard::zero());
-            right_masks.push(Bitboard::zero());
         }
-        for x in 0..16 {
-            for y in 0..16 {
-                let index: usize = to_index(x, y) as usize;
-                for j in 0..x {
-                    left_masks[index].set_bit_at(j, y);
-                }
-                for j in (x + 1)..16 {
-                    right_masks[index].set_bit_at(j, y);
+        slider_attacks
+    }
+
+    // For each square, the squares (x + t, y + t * dy) for all t
+    #[cfg(not(feature = "large-boards"))]
+    fn generate_line_masks(dy: i8) -> Vec<Bitboard> {
+        let mut masks = Vec::with_capacity(BOARD_SIZE);
+        for index in 0..BOARD_SIZE {
+            let (x, y) = from_index(index as BIndex);
+            let mut mask = Bitboard::zero();
+            for t in -(BOARD_WIDTH as i8)..(BOARD_WIDTH as i8) {
+                let (x2, y2) = (x as i8 + t, y as i8 + t * dy);
+                if (0..BOARD_WIDTH as i8).contains(&x2) && (0..BOARD_WIDTH as i8).contains(&y2) {
+                    mask.set_bit_at(x2 as BCoord, y2 as BCoord);
                 }
             }
+            masks.push(mask);
         }
-        
-        AttackTables{
-            rank_slides: slider_attacks,
-            left_masks,
-            right_masks,
-            top_right: &top_bits | &right_bits,
-            bottom_left: &bottom_bits | &left_bits,
-            top_left: &top_bits | &left_bits,
-            bottom_right: &bottom_bits | &right_bits,
-            horizontal_edges: &left_bits | &right_bits,
-            vertical_edges: &top_bits | &bottom_bits,
-        }
+        masks
     }
-
+    
+    #[cfg(not(feature = "large-boards"))]
     pub fn get_rank_slide(&self, loc_index: BIndex, occ: &Bitboard) -> Bitboard {
         let (x, y) = from_index(loc_index);
         //Isolate the rank
@@ -113,6 +173,20 @@ impl AttackTables {
         return_bb.get_inner_mut()[word_index as usize] = new_word;
         return_bb
     }
+
+    #[cfg(feature = "large-boards")]
+    pub fn get_rank_slide(&self, loc_index: BIndex, occ: &Bitboard) -> Bitboard {
+        // Add the left and right edges to the occupied bitboard to handle end condition
+        let occ = occ | &self.horizontal_edges;
+        let mut return_bb = Bitboard::zero();
+        if !self.right_edge.get_bit(loc_index) {
+            self.add_slide_top_right(&mut return_bb, loc_index, &occ, 1);
+        }
+        if !self.left_edge.get_bit(loc_index) {
+            self.add_slide_bottom_left(&mut return_bb, loc_index, &occ, 1);
+        }
+        return_bb
+    }
     
     #[inline]
     pub fn add_slide_top_right(&self, out: &mut Bitboard, start_index: BIndex, occ: &Bitboard, step: i16) {
@@ -138,8 +212,57 @@ impl AttackTables {
         }
     }
     
+    /// Returns a bitboard of the sliding piece moves.
+    /// Each rank, file and diagonal is collapsed into a 16-bit line, and the slides are computed on the line.
+    #[allow(clippy::too_many_arguments)]
+    #[cfg(not(feature = "large-boards"))]
+    pub fn get_sliding_moves_bb(&self,
+                                index: BIndex,
+                                occ: &Bitboard,
+                                north: bool,
+                                east: bool,
+                                south: bool,
+                                west: bool,
+                                northeast: bool,
+                                northwest: bool,
+                                southeast:bool,
+                                southwest:bool,
+    ) -> Bitboard {
+        let (x, y) = from_index(index);
+        let occ = occ.get_inner();
+        let mut moves = Bitboard::zero();
+        let out = moves.get_inner_mut();
+        
+        if east || west {
+            let word_index = (y / 4) as usize;
+            let shift = (y % 4) * 16;
+            let line = (occ[word_index] >> shift) as u16;
+            out[word_index] |= (line_slide(line, x, east, west) as u64) << shift;
+        }
+        if north || south {
+            // 4 squares of the file in each word
+            let mut line = 0;
+            for (i, word) in occ.iter().enumerate() {
+                line |= gather_file(*word, x) << (4 * i);
+            }
+            let slide = line_slide(line, y, north, south);
+            for (i, word) in out.iter_mut().enumerate() {
+                *word |= scatter_file((slide >> (4 * i)) & 0xF, x);
+            }
+        }
+        if northeast || southwest {
+            diagonal_slide(occ, self.diagonal_masks[index as usize].get_inner(), x, northeast, southwest, out);
+        }
+        if northwest || southeast {
+            // Going northwest decreases the file
+            diagonal_slide(occ, self.antidiagonal_masks[index as usize].get_inner(), x, southeast, northwest, out);
+        }
+        moves
+    }
+    
     /// Returns a bitboard of the sliding piece moves
     #[allow(clippy::too_many_arguments)]
+    #[cfg(feature = "large-boards")]
     pub fn get_sliding_moves_bb(&self,
                                 index: BIndex,
                                 occ: &Bitboard,
@@ -151,6 +274,24 @@ impl AttackTables {
                                 northwest: bool,
                                 southeast:bool,
                                 southwest:bool,
+    ) -> Bitboard {
+        self.get_sliding_moves_bb_classical(index, occ, north, east, south, west, northeast, northwest, southeast, southwest)
+    }
+    
+    /// Same as `get_sliding_moves_bb()`, but following the files and diagonals square by square.
+    /// Used as a reference for testing and benchmarking.
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_sliding_moves_bb_classical(&self,
+                                index: BIndex,
+                                occ: &Bitboard,
+                                north: bool,
+                                east: bool,
+                                south: bool,
+                                west: bool,
+                                northeast: bool,
+                                northwest: bool,
+                                southeast:bool,
+                                southwest:bool,
     ) -> Bitboard {
         let mut moves = {
             if east || west {
@@ -169,27 +310,28 @@ impl AttackTables {
         // For north/south, add top and bottom edges to the occupied bitboard to handle end condition
         let mut occ = occ | &self.vertical_edges;
         
-        if north && index < 240 {
-            self.add_slide_top_right(&mut moves, index, &occ, 16);
+        let width = BOARD_WIDTH as i16;
+        if north && (index as usize) < BOARD_SIZE - BOARD_WIDTH as usize {
+            self.add_slide_top_right(&mut moves, index, &occ, width);
         }
-        if south && index > 15 {
-            self.add_slide_bottom_left(&mut moves, index, &occ, 16);
+        if south && index >= BOARD_WIDTH as BIndex {
+            self.add_slide_bottom_left(&mut moves, index, &occ, width);
         }
         
         // For diagonals, add remaining edges to the occupied bitboard to to handle end condition
         occ |= &self.horizontal_edges;
         
         if northeast && !self.top_right.get_bit(index) {
-            self.add_slide_top_right(&mut moves, index, &occ, 17);
+            self.add_slide_top_right(&mut moves, index, &occ, width + 1);
         }
         if southwest && !self.bottom_left.get_bit(index) {
-            self.add_slide_bottom_left(&mut moves, index, &occ, 17);
+            self.add_slide_bottom_left(&mut moves, index, &occ, width + 1);
         }
         if northwest && !self.top_left.get_bit(index) {
-            self.add_slide_top_right(&mut moves, index, &occ, 15);
+            self.add_slide_top_right(&mut moves, index, &occ, width - 1);
         }
         if southeast && !self.bottom_right.get_bit(index) {
-            self.add_slide_bottom_left(&mut moves, index, &occ, 15);
+            self.add_slide_bottom_left(&mut moves, index, &occ, width - 1);
         }
         
         moves
@@ -197,6 +339,75 @@ impl AttackTables {
 
 }
 
+// Returns the squares attacked by a slider on the bit `index` of a line of 16 squares with the occupancy `occ`,
+// towards the higher bits (`up`) and/or the lower bits (`down`)
+#[cfg(not(feature = "large-boards"))]
+#[inline]
+fn line_slide(occ: u16, index: BCoord, up: bool, down: bool) -> u16 {
+    let square = 1u16 << index;
+    let below_mask = square - 1;
+    let above_mask = !(below_mask | square);
+    let mut slide = 0;
+    // Up to the first blocker with a higher index (included)
+    let above = occ & above_mask;
+    if up {
+        slide |= {
+            if above == 0 { above_mask }
+            else { ((above & above.wrapping_neg()) << 1).wrapping_sub(1) & above_mask }
+        };
+    }
+    // Down to the first blocker with a lower index (included)
+    let below = occ & below_mask;
+    if down {
+        slide |= {
+            if below == 0 { below_mask }
+            else { below_mask & !((1u16 << (15 - below.leading_zeros())) - 1) }
+        };
+    }
+    slide
+}
+
+// Adds the slides along a diagonal (given its mask) to `out`, towards the higher files (`up`) and/or the lower files (`down`)
+#[cfg(not(feature = "large-boards"))]
+#[inline]
+fn diagonal_slide(occ: &[u64; 4], mask: &[u64; 4], x: BCoord, up: bool, down: bool, out: &mut [u64; 4]) {
+    // A diagonal has at most one square in each rank, so the ranks can be OR-ed together into a line
+    // indexed by the file of each square
+    let folded = (occ[0] & mask[0]) | (occ[1] & mask[1]) | (occ[2] & mask[2]) | (occ[3] & mask[3]);
+    let line = (folded | (folded >> 16) | (folded >> 32) | (folded >> 48)) as u16;
+    // Copy the slide to all the ranks and keep only the squares in the diagonal
+    let slide = (line_slide(line, x, up, down) as u64).wrapping_mul(FILE_BITS);
+    for (word, mask) in out.iter_mut().zip(mask) {
+        *word |= slide & mask;
+    }
+}
+
+// Extracts the 4 bits of the file `x` of a word (4 ranks)
+#[cfg(all(not(feature = "large-boards"), all(target_arch = "x86_64", target_feature = "bmi2")))]
+#[inline]
+fn gather_file(word: u64, x: BCoord) -> u16 {
+    // SAFETY: the target supports BMI2
+    unsafe { std::arch::x86_64::_pext_u64(word, FILE_BITS << x) as u16 }
+}
+#[cfg(all(not(feature = "large-boards"), not(all(target_arch = "x86_64", target_feature = "bmi2"))))]
+#[inline]
+fn gather_file(word: u64, x: BCoord) -> u16 {
+    (((word >> x) & FILE_BITS).wrapping_mul(FILE_GATHER_MAGIC) >> 48) as u16
+}
+
+// Inverse of `gather_file()`: places 4 bits on the file `x` of a word
+#[cfg(all(not(feature = "large-boards"), all(target_arch = "x86_64", target_feature = "bmi2")))]
+#[inline]
+fn scatter_file(bits: u16, x: BCoord) -> u64 {
+    // SAFETY: the target supports BMI2
+    unsafe { std::arch::x86_64::_pdep_u64(bits as u64, FILE_BITS << x) }
+}
+#[cfg(all(not(feature = "large-boards"), not(all(target_arch = "x86_64", target_feature = "bmi2"))))]
+#[inline]
+fn scatter_file(bits: u16, x: BCoord) -> u64 {
+    ((bits as u64).wrapping_mul(FILE_SCATTER_MAGIC) & FILE_BITS) << x
+}
+
 impl Default for AttackTables {
     fn default() -> Self {
         Self::new()
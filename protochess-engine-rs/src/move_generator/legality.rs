@@ -0,0 +1,188 @@
+use crate::position::Position;
+use crate::types::{BCoord, BIndex, Bitboard, Move};
+use crate::utils::{from_index, to_index};
+
+use super::MoveGen;
+
+/// Checks and pins of the player to move, computed once per position so that most pseudo-legal moves
+/// can be proven legal with a few bit tests instead of making the move and looking for checks.
+/// Moves that cannot be proven legal this way must still be tested with `MoveGen::make_move_if_legal()`.
+pub struct LegalityInfo {
+    // Pieces of the player to move that may be pinned to a royal piece (it can contain false positives)
+    pinned: Bitboard,
+    // Squares of the leaders whose capture would make the player to move lose
+    royal: Bitboard,
+    in_check: bool,
+    // False if the rules of the position are not supported by the fast test
+    enabled: bool,
+}
+
+impl LegalityInfo {
+    pub fn new(position: &mut Position) -> LegalityInfo {
+        let in_check = MoveGen::in_check(position);
+        Self::with_check(position, in_check)
+    }
+    
+    /// Same as `new()`, when the caller already knows if the player to move is in check
+    pub fn with_check(position: &Position, in_check: bool) -> LegalityInfo {
+        let player = position.whos_turn as usize;
+        let royal = position.pieces[player].get_royal_squares(position.global_rules.leader_capture_rule);
+        let mut info = LegalityInfo {
+            pinned: Bitboard::zero(),
+            royal,
+            in_check,
+            enabled: !in_check && Self::rules_supported(position),
+        };
+        if info.enabled {
+            let royal = info.royal.clone();
+            for index in &royal {
+                info.add_pins(index, position);
+            }
+        }
+        info
+    }
+
+    /// Returns `true` if the player to move is in check
+    pub fn in_check(&self) -> bool {
+        self.in_check
+    }
+
+    /// Returns the pieces of the player to move that may be pinned to a royal piece.
+    /// Only computed (and only needed) when the player to move is not in check.
+    pub fn get_pinned(&self) -> &Bitboard {
+        &self.pinned
+    }
+
+    /// Returns `true` if the pseudo-legal move `mv` is known to be legal without making it.
+    /// A `false` result doesn't mean that the move is illegal.
+    pub fn is_known_legal(&self, mv: Move, position: &Position) -> bool {
+        if !self.enabled || mv.is_castling() || mv.is_null() || mv.is_pass() {
+            return false;
+        }
+        let my_pieces = &position.pieces[position.whos_turn as usize];
+        let is_leader = |id| my_pieces.lookup_piece(id).is_none_or(|piece| piece.is_leader());
+        // Placing a leader on the board could put it in check
+        if let Some(id) = mv.get_drop_piece() {
+            return !is_leader(id);
+        }
+        let from = mv.get_from();
+        if self.royal.get_bit(from) || self.pinned.get_bit(from) {
+            return false;
+        }
+        if mv.get_promotion_piece().is_some_and(is_leader) || mv.get_gate_piece().is_some_and(is_leader) {
+            return false;
+        }
+        if mv.is_capture() {
+            let mover = match my_pieces.piece_at(from) {
+                Some(piece) => piece,
+                None => return false,
+            };
+            // Removing a piece without occupying its square (en passant, explosions, rifle and displacement
+            // captures) could open a line towards a royal piece
+            if mv.get_target() != mv.get_to() || mover.explodes_on_capture() || mover.stays_when_making(mv) {
+                return false;
+            }
+        }
+        true
+    }
+
+    // The fast test assumes that a move can only put a royal piece in check by opening a line of pieces with
+    // regular attacks
+    fn rules_supported(position: &Position) -> bool {
+        if position.global_rules.check_is_forbidden || position.global_rules.leaders_cannot_face {
+            return false;
+        }
+        // Moves across ice or portals don't end where the piece was heading, and one-way squares limit the attacks
+        if !position.get_square_effects().is_empty() {
+            return false;
+        }
+        let enemy_pieces = &position.pieces[1 - position.whos_turn as usize];
+        if enemy_pieces.get_hoppers().next().is_some() || enemy_pieces.get_displacement_capturers().next().is_some() {
+            return false;
+        }
+        // Only straight sliding deltas (nightrider-like) can be followed backwards from the target
+        let (inverse_attack, _) = enemy_pieces.get_inverse_attack(0);
+        inverse_attack.attack_sliding_deltas.iter().all(|run| {
+            run.iter().enumerate().all(|(i, &(dx, dy))| {
+                let steps = i as i8 + 1;
+                (dx, dy) == (run[0].0 * steps, run[0].1 * steps)
+            })
+        })
+    }
+
+    // Adds the pieces that are the only obstacle between the royal piece at `royal_index` and an enemy slider
+    fn add_pins(&mut self, royal_index: BIndex, position: &Position) {
+        let player = position.whos_turn as usize;
+        let my_occupied = position.pieces[player].get_occupied();
+        let enemy_pieces = &position.pieces[1 - player];
+        // Immobile pieces cannot attack, but they block the lines
+        let enemy_attackers = enemy_pieces.get_occupied() & !position.get_immobile_squares();
+        let occupied = &position.occ_or_out_bounds;
+        let (inverse_attack, _) = enemy_pieces.get_inverse_attack(royal_index);
+        let (x, y) = from_index(royal_index);
+
+        let directions = [
+            inverse_attack.attack_north,
+            inverse_attack.attack_east,
+            inverse_attack.attack_south,
+            inverse_attack.attack_west,
+            inverse_attack.attack_northeast,
+            inverse_attack.attack_northwest,
+            inverse_attack.attack_southeast,
+            inverse_attack.attack_southwest,
+        ];
+        for (direction, _) in directions.iter().enumerate().filter(|(_, &attacked)| attacked) {
+            let blocker = match (ray(royal_index, occupied, direction) & my_occupied).lowest_one() {
+                Some(index) => index,
+                None => continue,
+            };
+            let mut without_blocker = occupied.clone();
+            without_blocker.clear_bit(blocker);
+            if let Some(attacker_index) = (ray(royal_index, &without_blocker, direction) & &enemy_attackers).lowest_one() {
+                let attacker = enemy_pieces.piece_at(attacker_index).unwrap();
+                if MoveGen::slide_targets_coords(x, y, attacker, attacker_index) {
+                    self.pinned.set_bit(blocker);
+                }
+            }
+        }
+
+        for run in &inverse_attack.attack_sliding_deltas {
+            let mut blocker = None;
+            for (dx, dy) in run {
+                let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
+                if x2 < 0 || y2 < 0 || !position.in_bounds(x2 as BCoord, y2 as BCoord) {
+                    break;
+                }
+                let index = to_index(x2 as BCoord, y2 as BCoord);
+                if !occupied.get_bit(index) {
+                    continue;
+                }
+                match blocker {
+                    None if my_occupied.get_bit(index) => {
+                        blocker = Some(index);
+                        continue;
+                    },
+                    Some(blocker_index) if enemy_attackers.get_bit(index) => {
+                        let attacker = enemy_pieces.piece_at(index).unwrap();
+                        let mut without_blocker = occupied.clone();
+                        without_blocker.clear_bit(blocker_index);
+                        if MoveGen::sliding_delta_targets_index(attacker, index, royal_index, &without_blocker) {
+                            self.pinned.set_bit(blocker_index);
+                        }
+                    },
+                    _ => {},
+                }
+                break;
+            }
+        }
+    }
+}
+
+// Returns the squares attacked by a slider at `index` in a single direction (in the order of `LegalityInfo::add_pins()`),
+// including the first occupied square
+fn ray(index: BIndex, occupied: &Bitboard, direction: usize) -> Bitboard {
+    let mut flags = [false; 8];
+    flags[direction] = true;
+    let [north, east, south, west, northeast, northwest, southeast, southwest] = flags;
+    MoveGen::attack_tables().get_sliding_moves_bb(index, occupied, north, east, south, west, northeast, northwest, southeast, southwest)
+}
@@ -1,11 +1,17 @@
 use crate::piece::Piece;
 use crate::position::piece_set::PieceSet;
-use crate::types::{BCoord, BIndex, Bitboard, Move, MoveType};
+use crate::position::global_rules::LeaderCaptureRule;
+use crate::types::{BCoord, BIndex, Bitboard, Move, Player, BOARD_WIDTH};
 use crate::position::Position;
 use crate::move_generator::attack_tables::AttackTables;
 use crate::utils::{from_index, to_index};
 
-pub mod attack_tables;
+pub(crate) mod attack_tables;
+pub(crate) mod square_effects;
+mod legality;
+mod see;
+
+pub use legality::LegalityInfo;
 
 
 lazy_static! {
@@ -23,8 +29,9 @@ impl MoveGen {
     
     pub fn get_legal_moves(position: &mut Position) -> Vec<Move> {
         let mut legal_moves = Vec::new();
+        let info = LegalityInfo::new(position);
         for mv in MoveGen::get_pseudo_moves(position, true) {
-            if !MoveGen::is_move_legal(mv, position) {
+            if !MoveGen::is_move_legal_with(mv, position, &info) {
                 continue;
             }
             legal_moves.push(mv);
@@ -34,47 +41,162 @@ impl MoveGen {
 
     /// Iterator that yields pseudo-legal moves from a positon
     pub fn get_pseudo_moves(position: &mut Position, output_translations: bool) -> Vec<Move> {
-        let my_pieces = &position.pieces[position.whos_turn as usize];
-
         let mut out_moves = Vec::with_capacity(50);
-
+        MoveGen::output_captures(position, &mut out_moves);
+        let skip_translations = position.global_rules.capturing_is_forced && !out_moves.is_empty();
+        if output_translations && !skip_translations {
+            MoveGen::output_quiets(position, &mut out_moves);
+        }
+        position.get_square_effects().apply(position, 0, &mut out_moves);
+        if position.global_rules.gating {
+            MoveGen::output_gating_moves(position, &mut out_moves);
+        }
+        if output_translations && !skip_translations && position.global_rules.passing_allowed && !MoveGen::in_check(position) {
+            out_moves.push(Move::pass());
+        }
+        out_moves
+    }
+    
+    /// Returns the pseudo-legal moves that `get_pseudo_moves()` would output after the captures.
+    /// `captures_exist` must be `true` if the player to move can capture (in that case, capturing may be forced).
+    pub fn get_pseudo_quiets(position: &mut Position, captures_exist: bool) -> Vec<Move> {
+        let mut out_moves = Vec::with_capacity(50);
+        if position.global_rules.capturing_is_forced && captures_exist {
+            return out_moves;
+        }
+        MoveGen::output_quiets(position, &mut out_moves);
+        position.get_square_effects().apply(position, 0, &mut out_moves);
+        if position.global_rules.gating {
+            MoveGen::output_gating_moves(position, &mut out_moves);
+        }
+        if position.global_rules.passing_allowed && !MoveGen::in_check(position) {
+            out_moves.push(Move::pass());
+        }
+        out_moves
+    }
+    
+    /// Checks if `get_pseudo_moves()` would output a move, without generating the moves of all the pieces.
+    /// Used for validating moves that come from other positions (for example, killer moves or hash moves).
+    pub fn is_pseudo_legal(mv: Move, position: &mut Position) -> bool {
+        if mv.is_null() {
+            return false;
+        }
+        // These moves depend on the rest of the moves of the position
+        if mv.is_pass() || mv.get_gate_piece().is_some() {
+            return MoveGen::get_pseudo_moves(position, true).contains(&mv);
+        }
+        if !mv.is_capture() && position.global_rules.capturing_is_forced && MoveGen::has_captures(position) {
+            return false;
+        }
+        let my_pieces = &position.pieces[position.whos_turn as usize];
         let enemies_or_out_bounds = &position.occ_or_out_bounds & !my_pieces.get_occupied();
         let occ_or_not_in_bounds = &position.occ_or_out_bounds;
-        
+        let mut moves = Vec::new();
+        if let Some(id) = mv.get_drop_piece() {
+            match my_pieces.lookup_piece(id) {
+                Some(piece) if !position.global_rules.gating => piece.output_drops(occ_or_not_in_bounds, &mut moves),
+                _ => return false,
+            }
+        } else {
+            match my_pieces.piece_at(mv.get_from()) {
+                Some(piece) if mv.is_capture() => piece.output_captures(position, &enemies_or_out_bounds, occ_or_not_in_bounds, &mut moves),
+                Some(piece) => piece.output_translations(position, &enemies_or_out_bounds, occ_or_not_in_bounds, &mut moves),
+                None => return false,
+            }
+        }
+        position.get_square_effects().apply(position, 0, &mut moves);
+        moves.contains(&mv)
+    }
+    
+    /// Returns `true` if the player to move has a pseudo-legal capture, the same as checking if `get_pseudo_moves()`
+    /// outputs any capture. Stops at the first piece type that can capture (used when capturing is forced).
+    pub fn has_captures(position: &Position) -> bool {
+        let my_pieces = &position.pieces[position.whos_turn as usize];
+        let enemies_or_out_bounds = &position.occ_or_out_bounds & !my_pieces.get_occupied();
+        let occ_or_not_in_bounds = &position.occ_or_out_bounds;
+        let mut moves = Vec::new();
         for p in my_pieces.iter() {
-            p.output_captures(position, &enemies_or_out_bounds, occ_or_not_in_bounds, &mut out_moves);
+            p.output_captures(position, &enemies_or_out_bounds, occ_or_not_in_bounds, &mut moves);
+            // One-way squares can block the captures
+            position.get_square_effects().apply(position, 0, &mut moves);
+            if !moves.is_empty() {
+                return true;
+            }
         }
-        let skip_translations = position.global_rules.capturing_is_forced && !out_moves.is_empty();
-        if output_translations && !skip_translations {
+        false
+    }
+    
+    // Outputs the captures of all the pieces of the player to move
+    fn output_captures(position: &Position, out_moves: &mut Vec<Move>) {
+        let my_pieces = &position.pieces[position.whos_turn as usize];
+        let enemies_or_out_bounds = &position.occ_or_out_bounds & !my_pieces.get_occupied();
+        let occ_or_not_in_bounds = &position.occ_or_out_bounds;
+        for p in my_pieces.iter() {
+            p.output_captures(position, &enemies_or_out_bounds, occ_or_not_in_bounds, out_moves);
+        }
+    }
+    
+    // Outputs the translations of all the pieces of the player to move, and the drops of the pieces in hand
+    fn output_quiets(position: &Position, out_moves: &mut Vec<Move>) {
+        let my_pieces = &position.pieces[position.whos_turn as usize];
+        let enemies_or_out_bounds = &position.occ_or_out_bounds & !my_pieces.get_occupied();
+        let occ_or_not_in_bounds = &position.occ_or_out_bounds;
+        for p in my_pieces.iter() {
+            p.output_translations(position, &enemies_or_out_bounds, occ_or_not_in_bounds, out_moves);
+        }
+        // With gating, pieces in hand can only enter the board on a square left by another piece
+        if !position.global_rules.gating {
             for p in my_pieces.iter() {
-                p.output_translations(position, &enemies_or_out_bounds, occ_or_not_in_bounds, &mut out_moves);
+                p.output_drops(occ_or_not_in_bounds, out_moves);
+            }
+        }
+    }
+    
+    // For each move of a piece that leaves the back rank for the first time, add a copy of the move
+    // for each piece in hand that can be gated on the square that it leaves
+    fn output_gating_moves(position: &Position, out_moves: &mut Vec<Move>) {
+        let my_pieces = &position.pieces[position.whos_turn as usize];
+        if my_pieces.hand_is_empty() {
+            return;
+        }
+        for i in 0..out_moves.len() {
+            let mv = out_moves[i];
+            if mv.is_promotion() || mv.is_drop() {
+                continue;
+            }
+            let from = mv.get_from();
+            let piece = my_pieces.piece_at(from).unwrap();
+            // In chess960, the king may stay on its square or the rook may land on it
+            if mv.is_castling() && (mv.get_to() == from || piece.get_castling_partner_to(mv) == from) {
+                continue;
+            }
+            if !piece.can_gate_from(from) || piece.stays_when_making(mv) {
+                continue;
+            }
+            for (piece, _) in my_pieces.iter_hand() {
+                out_moves.push(mv.with_gate(piece.get_piece_id()));
             }
         }
-        out_moves
     }
 
-    /// Checks if the player to move is in check
+    /// Checks if the player to move is in check (one of the leaders whose capture would lose the game is attacked)
     pub fn in_check(position: &mut Position) -> bool {
         let my_pieces = &position.pieces[position.whos_turn as usize];
-        if let Some(my_leader) = my_pieces.get_leader() {
-            if my_leader.get_num_pieces() > 1 {
-                // There are multiple leaders, so the position cannot be in check
-                return false;
+        // If I have no leader, I cannot be in check (only lose when all pieces are captured).
+        // If there are multiple leaders and I only lose when all of them are captured, I cannot be in check either.
+        let royal_squares = my_pieces.get_royal_squares(position.global_rules.leader_capture_rule);
+        for index in &royal_squares {
+            if MoveGen::index_in_check(index, position) {
+                return true;
             }
-            // There is only one bit set to 1 in the bitboard
-            let index = my_leader.get_bitboard().lowest_one().unwrap();
-            MoveGen::index_in_check(index, position)
-        } else {
-            // If I have no leader, I cannot be in check (only lose when all pieces are captured)
-            false
         }
+        false
     }
 
     /// Attempts to make a pseudo-legal move, succeeding and returning true only if the move was legal
     pub fn make_move_if_legal(mv: Move, position: &mut Position) -> bool {
         // Cannot castle while in check or step through check
         if mv.is_castling() {
-            let kingside = mv.get_move_type() == MoveType::KingsideCastle;
             let from = mv.get_from();
             let to = mv.get_to();
             // Edge case in chess960 where castling does not move the king,
@@ -82,15 +204,19 @@ impl MoveGen {
             if from == to && MoveGen::index_in_check(from, position) {
                 return false;
             }
-            let start_index = { if kingside { from } else { to + 1 } };
-            let end_index = { if kingside { to - 1 } else { from } };
+            // The king moves along its rank or its file (vertical castling). The destination itself is checked
+            // after making the move.
+            let vertical = from_index(from).0 == from_index(to).0;
+            let step = if vertical { BOARD_WIDTH as i16 } else { 1 } * (to as i16 - from as i16).signum();
             // Hide the castling piece from the occupied bitboard so that it doesn't get in the way of check detection
             position.occ_or_out_bounds.clear_bit(from);
-            for step_index in start_index..=end_index {
-                if MoveGen::index_in_check(step_index, position) {
+            let mut step_index = from as i16;
+            while step_index != to as i16 {
+                if MoveGen::index_in_check(step_index as BIndex, position) {
                     position.occ_or_out_bounds.set_bit(from);
                     return false;
                 }
+                step_index += step;
             }
             position.occ_or_out_bounds.set_bit(from);
         }
@@ -98,16 +224,27 @@ impl MoveGen {
         // Try the move and skip a turn, then see if we are in check
         // Also, if after making the move the enemy is in check, the move is illegal if check_is_forbidden
         // If the move captures the last enemy leader, it's still legal (technically, the enemy is not in check)
+        // If the player has more moves left in this turn, the enemy cannot be in check and there is no need to skip a turn
+        let mover = position.whos_turn;
         position.make_move(mv);
-        if position.global_rules.check_is_forbidden && !position.leader_is_captured() && MoveGen::in_check(position) {
+        let turn_ended = position.whos_turn != mover;
+        if position.global_rules.leaders_cannot_face && MoveGen::leaders_face(position) {
+            position.unmake_move();
+            return false;
+        }
+        if turn_ended && position.global_rules.check_is_forbidden && !position.leader_is_captured() && MoveGen::in_check(position) {
             position.unmake_move();
             return false;
         }
-        position.make_move(Move::null());
+        if turn_ended {
+            position.make_move(Move::null());
+        }
         // See if we are in check or an explosion has killed the last leader
         // However, if the move causes us to capture the last enemy leader, the move is legal (even if it leaves us in check)
         let legal = !position.leader_is_captured() && (position.enemy_leader_is_captured() || !MoveGen::in_check(position));
-        position.unmake_move();
+        if turn_ended {
+            position.unmake_move();
+        }
         if !legal {
             // If the move is illegal, clean up the position
             position.unmake_move();
@@ -115,6 +252,16 @@ impl MoveGen {
         legal
     }
     
+    /// Same as `make_move_if_legal()`, but uses the checks and pins of the current position (see `LegalityInfo`)
+    /// to avoid testing most of the moves
+    pub fn make_move_if_legal_with(mv: Move, position: &mut Position, info: &LegalityInfo) -> bool {
+        if info.is_known_legal(mv, position) {
+            position.make_move(mv);
+            return true;
+        }
+        Self::make_move_if_legal(mv, position)
+    }
+    
     /// Checks if a move is legal
     pub fn is_move_legal(mv: Move, position: &mut Position) -> bool {
         let legal = Self::make_move_if_legal(mv, position);
@@ -125,12 +272,109 @@ impl MoveGen {
         legal
     }
     
+    /// Same as `is_move_legal()`, but uses the checks and pins of the current position (see `LegalityInfo`)
+    pub fn is_move_legal_with(mv: Move, position: &mut Position, info: &LegalityInfo) -> bool {
+        if info.is_known_legal(mv, position) {
+            return true;
+        }
+        Self::is_move_legal(mv, position)
+    }
+    
+    /// Checks if two leaders of different players are on the same rank, file or diagonal with no pieces between them
+    fn leaders_face(position: &Position) -> bool {
+        let mut enemy_leaders = Bitboard::zero();
+        for leader in position.pieces[1].get_leaders() {
+            enemy_leaders |= leader.get_bitboard();
+        }
+        if enemy_leaders.is_zero() {
+            return false;
+        }
+        for leader in position.pieces[0].get_leaders() {
+            for index in leader.get_bitboard() {
+                // The first piece in each direction is included in the sliding moves
+                let line_of_sight = MoveGen::attack_tables().get_sliding_moves_bb(
+                    index, &position.occ_or_out_bounds, true, true, true, true, true, true, true, true
+                );
+                if !(line_of_sight & &enemy_leaders).is_zero() {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
     /// Checks if a given square is attacked by the enemy
     fn index_in_check(index: BIndex, position: &mut Position) -> bool {
-        let (x, y) = from_index(index);
         let enemy = 1 - position.whos_turn;
+        !Self::index_attackers::<true>(index, position, enemy).is_zero()
+    }
+    
+    /// Returns the pieces of `attacker` that could capture a piece on the given square, even if the square is empty or
+    /// occupied by one of their own pieces (except for pieces that capture by displacement, which need an actual victim)
+    pub fn attackers_of(index: BIndex, position: &mut Position, attacker: Player) -> Bitboard {
+        Self::index_attackers::<false>(index, position, attacker)
+    }
+    
+    /// Returns the squares that a player can see in fog of war variants (see `GameMode::DarkChess`): the squares
+    /// of their pieces and the squares where these pieces could move or capture, ignoring the legality of the moves.
+    /// Drops don't make squares visible.
+    pub fn visible_squares(position: &mut Position, player: Player) -> Bitboard {
+        let whos_turn = position.whos_turn;
+        position.whos_turn = player;
+        let mut moves = Vec::with_capacity(50);
+        MoveGen::output_captures(position, &mut moves);
+        MoveGen::output_quiets(position, &mut moves);
+        position.get_square_effects().apply(position, 0, &mut moves);
+        position.whos_turn = whos_turn;
+        
+        let mut visible = position.pieces[player as usize].get_occupied().clone();
+        for mv in moves.iter().filter(|mv| !mv.is_drop()) {
+            visible.set_bit(mv.get_to());
+            if mv.is_capture() {
+                visible.set_bit(mv.get_target());
+            }
+        }
+        visible
+    }
+    
+    /// Returns the enemy pieces that attack the royal pieces of the player to move
+    pub fn checking_pieces(position: &mut Position) -> Bitboard {
+        let enemy = 1 - position.whos_turn;
+        let royal_squares = position.pieces[position.whos_turn as usize].get_royal_squares(position.global_rules.leader_capture_rule);
+        let mut checkers = Bitboard::zero();
+        for index in &royal_squares {
+            checkers |= Self::index_attackers::<false>(index, position, enemy);
+        }
+        checkers
+    }
+    
+    // Returns the pieces of `enemy` that attack the given square. If `FIRST_ONLY` is true, stops after finding one.
+    fn index_attackers<const FIRST_ONLY: bool>(index: BIndex, position: &mut Position, enemy: Player) -> Bitboard {
+        if !position.get_square_effects().is_one_way(index) {
+            return Self::index_attackers_ignoring_effects::<FIRST_ONLY>(index, position, enemy);
+        }
+        // Only the pieces that can enter a one-way square attack it
+        let candidates = Self::index_attackers_ignoring_effects::<false>(index, position, enemy);
+        let mut attackers = Bitboard::zero();
+        for attacker in &candidates {
+            if position.get_square_effects().can_enter(attacker, index) {
+                attackers.set_bit(attacker);
+                if FIRST_ONLY {
+                    return attackers;
+                }
+            }
+        }
+        attackers
+    }
+    
+    // Same as `index_attackers()`, without applying the square effects
+    fn index_attackers_ignoring_effects<const FIRST_ONLY: bool>(index: BIndex, position: &mut Position, enemy: Player) -> Bitboard {
+        let (x, y) = from_index(index);
+        let mut attackers = Bitboard::zero();
         let enemy_pieces = &position.pieces[enemy as usize];
-        let enemy_occupied = enemy_pieces.get_occupied();
+        let rule = position.global_rules.leader_capture_rule;
+        // Immobile pieces cannot attack
+        let enemy_occupied = &(enemy_pieces.get_occupied() & !position.get_immobile_squares());
         let (inverse_attack, jumps) = enemy_pieces.get_inverse_attack(index);
         // Use inverse attack pattern to get the squares that can potentially attack the square
         let attack_tables = MoveGen::attack_tables();
@@ -149,28 +393,32 @@ impl MoveGen {
             inverse_attack.attack_southwest
         );
         slides &= enemy_occupied;
-        while let Some(enemy_piece_index) = slides.lowest_one() {
+        for enemy_piece_index in &slides {
             // Found an enemy piece that might attack the last leader
             let enemy_piece = enemy_pieces.piece_at(enemy_piece_index).unwrap();
             // If this attack will kill the remaining enemy leaders, the move is illegal so it is not a check
-            let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, enemy_piece_index);
-            if !kills_remaining_leaders && MoveGen::slide_targets_coords(x, y, enemy_piece, enemy_piece_index) {
-                return true;
+            let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, enemy_piece_index, rule);
+            if !kills_remaining_leaders && !enemy_piece.captures_by_displacement() && MoveGen::slide_targets_coords(x, y, enemy_piece, enemy_piece_index) {
+                attackers.set_bit(enemy_piece_index);
+                if FIRST_ONLY {
+                    return attackers;
+                }
             }
-            slides.clear_bit(enemy_piece_index);
         }
         
         // Check jump attacks
-        let mut jump_attacks = jumps & enemy_occupied;
-        while let Some(enemy_piece_index) = jump_attacks.lowest_one() {
+        let jump_attacks = jumps & enemy_occupied;
+        for enemy_piece_index in &jump_attacks {
             // Found an enemy piece that might attack the last leader
             let enemy_piece = enemy_pieces.piece_at(enemy_piece_index).unwrap();
             // If this attack will kill the remaining enemy leaders, the move is illegal so it is not a check
-            let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, enemy_piece_index);
-            if !kills_remaining_leaders && enemy_piece.get_capture_jumps(enemy_piece_index).get_bit(index) {
-                return true;
+            let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, enemy_piece_index, rule);
+            if !kills_remaining_leaders && !enemy_piece.captures_by_displacement() && enemy_piece.get_capture_jumps(enemy_piece_index).get_bit(index) {
+                attackers.set_bit(enemy_piece_index);
+                if FIRST_ONLY {
+                    return attackers;
+                }
             }
-            jump_attacks.clear_bit(enemy_piece_index);
         }
         
         // Check sliding deltas
@@ -190,9 +438,12 @@ impl MoveGen {
                     // Found an enemy piece that might attack the last leader
                     let enemy_piece = enemy_pieces.piece_at(to).unwrap();
                     // If this attack will kill the remaining enemy leaders, the move is illegal so it is not a check
-                    let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, to);
-                    if !kills_remaining_leaders && MoveGen::sliding_delta_targets_index(enemy_piece, to, index, occ_or_not_in_bounds) {
-                        return true;
+                    let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, to, rule);
+                    if !kills_remaining_leaders && !enemy_piece.captures_by_displacement() && MoveGen::sliding_delta_targets_index(enemy_piece, to, index, occ_or_not_in_bounds) {
+                        attackers.set_bit(to);
+                        if FIRST_ONLY {
+                            return attackers;
+                        }
                     }
                     break;
                 }
@@ -202,7 +453,41 @@ impl MoveGen {
                 }
             }
         }
-        false
+        
+        // Hop captures are not in the inverse attack pattern
+        for enemy_piece in enemy_pieces.get_hoppers() {
+            let hoppers = enemy_piece.get_bitboard() & enemy_occupied;
+            for enemy_piece_index in &hoppers {
+                let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, enemy_piece_index, rule);
+                if !kills_remaining_leaders && enemy_piece.hop_attacks(enemy_piece_index, index, position) {
+                    attackers.set_bit(enemy_piece_index);
+                    if FIRST_ONLY {
+                        return attackers;
+                    }
+                }
+            }
+        }
+        
+        // Pieces that capture by withdrawing or advancing are not in the inverse attack pattern, generate their captures
+        if enemy_pieces.get_displacement_capturers().next().is_none() {
+            return attackers;
+        }
+        let my_pieces_or_out_bounds = occ_or_not_in_bounds & !enemy_pieces.get_occupied();
+        let mut captures = Vec::new();
+        for enemy_piece in enemy_pieces.get_displacement_capturers() {
+            captures.clear();
+            enemy_piece.output_captures(position, &my_pieces_or_out_bounds, occ_or_not_in_bounds, &mut captures);
+            for mv in &captures {
+                let kills_remaining_leaders = enemy_piece.explodes_on_capture() && explosion_kills_enemy(index, enemy_pieces, enemy_piece, mv.get_from(), rule);
+                if mv.get_target() == index && !kills_remaining_leaders {
+                    attackers.set_bit(mv.get_from());
+                    if FIRST_ONLY {
+                        return attackers;
+                    }
+                }
+            }
+        }
+        attackers
     }
     fn slide_targets_coords(x: BCoord, y: BCoord, piece: &Piece, piece_index: BIndex) -> bool {
         // We already know that this piece is on the same rank, file, diagonal or antidiagonal as the target (x, y)
@@ -234,7 +519,7 @@ impl MoveGen {
         for run in &piece_movement.attack_sliding_deltas {
             for (dx, dy) in run {
                 let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
-                if x2 < 0 || y2 < 0 || x2 > 15 || y2 > 15 {
+                if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
                     break;
                 }
                 let to = to_index(x2 as BCoord, y2 as BCoord);
@@ -252,26 +537,11 @@ impl MoveGen {
 }
 
 
-/// Returns true if an explosion in this coordinates would kill all the remaining enemy leaders
-fn explosion_kills_enemy(index: BIndex, enemy_pieces: &PieceSet, enemy_piece: &Piece, enemy_piece_index: u8) -> bool {
-    if let Some(enemy_leader) = enemy_pieces.get_leader() {
-        let mut killed_enemy_leaders = 0;
-        let affected_squares = enemy_leader.get_bitboard() & enemy_piece.get_explosion(index);
-        // Take into account that the attacking piece might be a leader from far away
-        if enemy_piece.is_leader() && !affected_squares.get_bit(enemy_piece_index) {
-            killed_enemy_leaders += 1;
-        }
-        killed_enemy_leaders += affected_squares.count_ones();
-        killed_enemy_leaders == enemy_leader.get_num_pieces()
-    } else {
-        // If the enemy has no leaders, then they only lose when all pieces are captured
-        let mut killed_enemies = 0;
-        let affected_squares = enemy_pieces.get_occupied() & enemy_piece.get_explosion(index);
-        // Take into account that the attacking piece might be far away
-        if !affected_squares.get_bit(enemy_piece_index) {
-            killed_enemies += 1;
-        }
-        killed_enemies += affected_squares.count_ones();
-        killed_enemies == enemy_pieces.get_occupied().count_ones()
-    }
+/// Returns true if an explosion in this coordinates would make the enemy lose (for example, by killing all
+/// the remaining enemy leaders)
+fn explosion_kills_enemy(index: BIndex, enemy_pieces: &PieceSet, enemy_piece: &Piece, enemy_piece_index: BIndex, rule: LeaderCaptureRule) -> bool {
+    // Take into account that the attacking piece might be far away
+    let mut removed = enemy_piece.get_explosion(index).clone();
+    removed.set_bit(enemy_piece_index);
+    enemy_pieces.loses_without(rule, &removed)
 }
@@ -0,0 +1,143 @@
+use crate::piece::{Piece, CaptureMode};
+use crate::position::Position;
+use crate::types::{BCoord, BIndex, Bitboard, Centipawns, Move, Player};
+use crate::utils::{from_index, to_index};
+
+use super::MoveGen;
+
+// Value used for leaders in the exchange, so that capturing a leader always ends the sequence
+const LEADER_VALUE: Centipawns = 100_000;
+// Maximum number of captures considered in a single exchange
+const MAX_EXCHANGE_LENGTH: usize = 32;
+
+impl MoveGen {
+    /// Static Exchange Evaluation: returns the expected material balance (from the point of view of the
+    /// player to move) after all the captures on the target square of `mv`, assuming that both players
+    /// always recapture with their least valuable piece and can stop capturing at any point.
+    /// X-ray attacks (e.g. a rook behind another rook) are taken into account.
+    /// Quiet moves return the expected loss of moving the piece to an attacked square (0 or negative).
+    /// Explosions, promotions and recaptures by hopping pieces are not taken into account, and captures by
+    /// pieces that don't capture by replacement only count the captured piece.
+    pub fn see(position: &Position, mv: Move) -> Centipawns {
+        let from = mv.get_from();
+        let to = mv.get_to();
+        let mut player = position.whos_turn;
+        let mover = match position.player_piece_at(player, from) {
+            Some(piece) => piece,
+            None => return 0,
+        };
+        // Pieces that don't move to the square of the captured piece cannot be recaptured there
+        if mv.is_capture() && mover.get_movement().capture_mode != CaptureMode::Replace {
+            return position.player_piece_at(1 - player, mv.get_target()).map_or(0, exchange_value);
+        }
+        let mut occupied = position.occ_or_out_bounds.clone();
+        let mut gain = [0; MAX_EXCHANGE_LENGTH];
+        if mv.is_capture() {
+            let target = mv.get_target();
+            gain[0] = position.player_piece_at(1 - player, target).map_or(0, exchange_value);
+            // The target is different from the destination when capturing en passant
+            occupied.clear_bit(target);
+        }
+        occupied.clear_bit(from);
+        // Value of the piece that currently stands on the destination square
+        let mut on_square = exchange_value(mover);
+
+        let mut depth = 0;
+        while depth + 1 < MAX_EXCHANGE_LENGTH {
+            player = 1 - player;
+            let (index, attacker) = match least_valuable_attacker(position, player, to, &occupied) {
+                Some(attacker) => attacker,
+                None => break,
+            };
+            depth += 1;
+            gain[depth] = on_square - gain[depth - 1];
+            // Neither player can improve their result by continuing the exchange
+            if (-gain[depth - 1]).max(gain[depth]) < 0 {
+                break;
+            }
+            occupied.clear_bit(index);
+            on_square = exchange_value(attacker);
+        }
+        // Each player can choose not to recapture
+        while depth > 0 {
+            gain[depth - 1] = -(-gain[depth - 1]).max(gain[depth]);
+            depth -= 1;
+        }
+        gain[0]
+    }
+}
+
+fn exchange_value(piece: &Piece) -> Centipawns {
+    if piece.is_leader() { LEADER_VALUE } else { piece.get_material_score() }
+}
+
+/// Returns the index and the piece of the least valuable piece of `player` that attacks `target`.
+/// Only the pieces in `occupied` are considered, and they are blocked by the pieces in `occupied`.
+fn least_valuable_attacker<'a>(position: &'a Position, player: Player, target: BIndex, occupied: &Bitboard) -> Option<(BIndex, &'a Piece)> {
+    let pieces = &position.pieces[player as usize];
+    let candidates = pieces.get_occupied() & occupied & !position.get_immobile_squares();
+    let (inverse_attack, jumps) = pieces.get_inverse_attack(target);
+    let mut best: Option<(BIndex, &Piece)> = None;
+    let mut consider = |index: BIndex, piece: &'a Piece| {
+        // Pieces that capture by displacement cannot capture on the square
+        if piece.captures_by_displacement() {
+            return;
+        }
+        if best.is_none_or(|(_, best_piece)| exchange_value(piece) < exchange_value(best_piece)) {
+            best = Some((index, piece));
+        }
+    };
+
+    // Sliding attacks, using the current occupancy (so x-ray attackers are revealed)
+    let (x, y) = from_index(target);
+    let mut slides = MoveGen::attack_tables().get_sliding_moves_bb(
+        target,
+        occupied,
+        inverse_attack.attack_north,
+        inverse_attack.attack_east,
+        inverse_attack.attack_south,
+        inverse_attack.attack_west,
+        inverse_attack.attack_northeast,
+        inverse_attack.attack_northwest,
+        inverse_attack.attack_southeast,
+        inverse_attack.attack_southwest
+    );
+    slides &= &candidates;
+    for index in &slides {
+        let piece = pieces.piece_at(index).unwrap();
+        if MoveGen::slide_targets_coords(x, y, piece, index) {
+            consider(index, piece);
+        }
+    }
+
+    // Jump attacks
+    let jump_attacks = jumps & &candidates;
+    for index in &jump_attacks {
+        let piece = pieces.piece_at(index).unwrap();
+        if piece.get_capture_jumps(index).get_bit(target) {
+            consider(index, piece);
+        }
+    }
+
+    // Sliding deltas
+    for run in &inverse_attack.attack_sliding_deltas {
+        for (dx, dy) in run {
+            let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
+            if x2 < 0 || y2 < 0 || !position.in_bounds(x2 as BCoord, y2 as BCoord) {
+                break;
+            }
+            let index = to_index(x2 as BCoord, y2 as BCoord);
+            if candidates.get_bit(index) {
+                let piece = pieces.piece_at(index).unwrap();
+                if MoveGen::sliding_delta_targets_index(piece, index, target, occupied) {
+                    consider(index, piece);
+                }
+                break;
+            }
+            if occupied.get_bit(index) {
+                break;
+            }
+        }
+    }
+    best
+}
@@ -0,0 +1,133 @@
+// Square effects of the global rules (portals, ice and one-way squares), applied to the pseudo-legal moves
+
+use crate::{Position, SquareEffect};
+use crate::types::{BCoord, BDimensions, BIndex, Bitboard, Move, MoveType};
+use crate::utils::{from_index, to_index};
+
+/// Square effects of the global rules (see `SquareEffect`), with the squares converted to indexes
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SquareEffects {
+    // Each portal square and the square at the other end
+    portals: Vec<(BIndex, BIndex)>,
+    ice: Bitboard,
+    one_way: Bitboard,
+    // Direction in which each one-way square can be entered
+    one_way_directions: Vec<(BIndex, (i8, i8))>,
+}
+
+impl SquareEffects {
+    /// Converts the effects to indexes. The squares that are out of bounds are ignored.
+    pub fn new(effects: &[SquareEffect], dims: &BDimensions) -> SquareEffects {
+        let mut result = SquareEffects::default();
+        let index = |&(x, y): &(BCoord, BCoord)| if dims.in_bounds(x, y) { Some(to_index(x, y)) } else { None };
+        for effect in effects {
+            match effect {
+                SquareEffect::Portal { squares: [a, b] } => {
+                    if let (Some(a), Some(b)) = (index(a), index(b)) {
+                        result.portals.push((a, b));
+                        result.portals.push((b, a));
+                    }
+                },
+                SquareEffect::Ice { squares } => {
+                    for square in squares.iter().filter_map(index) {
+                        result.ice.set_bit(square);
+                    }
+                },
+                SquareEffect::OneWay { square, direction } => {
+                    if let Some(square) = index(square) {
+                        result.one_way.set_bit(square);
+                        result.one_way_directions.push((square, *direction));
+                    }
+                },
+            }
+        }
+        result
+    }
+
+    /// Returns `true` if there are no square effects, so the moves don't need to be modified
+    pub fn is_empty(&self) -> bool {
+        self.portals.is_empty() && self.ice.is_zero() && self.one_way.is_zero()
+    }
+
+    /// Returns `true` if the square can only be entered from some directions, which also restricts the attacks on it
+    pub fn is_one_way(&self, index: BIndex) -> bool {
+        self.one_way.get_bit(index)
+    }
+
+    /// Returns `false` if a piece on `from` cannot move to or capture on `to`, because it's a one-way square
+    /// that cannot be entered from that direction
+    pub fn can_enter(&self, from: BIndex, to: BIndex) -> bool {
+        if from == to || !self.one_way.get_bit(to) {
+            return true;
+        }
+        let (from_x, from_y) = from_index(from);
+        let (to_x, to_y) = from_index(to);
+        let (dx, dy) = (to_x as i32 - from_x as i32, to_y as i32 - from_y as i32);
+        self.one_way_directions.iter()
+            .filter(|(square, _)| *square == to)
+            .all(|&(_, (dir_x, dir_y))| dx * dir_x as i32 + dy * dir_y as i32 > 0)
+    }
+
+    /// Applies the effects to the moves in `out_moves[first_move..]`: removes the moves that enter a one-way square
+    /// from the wrong direction, and changes the destination of the non-capturing moves that end on ice or on a portal
+    pub fn apply(&self, position: &Position, first_move: usize, out_moves: &mut Vec<Move>) {
+        if self.is_empty() {
+            return;
+        }
+        let moves = out_moves.split_off(first_move);
+        for mv in moves {
+            if mv.is_null() || mv.is_pass() || mv.is_drop() {
+                out_moves.push(mv);
+                continue;
+            }
+            let (from, to) = (mv.get_from(), mv.get_to());
+            if !self.can_enter(from, to) || (mv.is_capture() && !self.can_enter(from, mv.get_target())) {
+                continue;
+            }
+            if mv.get_move_type() != MoveType::Quiet {
+                out_moves.push(mv);
+                continue;
+            }
+            let destination = self.destination(position, from, to);
+            // The piece may be able to reach the same square directly and across ice or a portal
+            let same_move = |m: &Move| m.get_from() == from && m.get_to() == destination && m.get_move_type() == MoveType::Quiet;
+            if out_moves[first_move..].iter().any(same_move) {
+                continue;
+            }
+            if destination == to {
+                out_moves.push(mv);
+            } else {
+                out_moves.push(Move::new(from, destination, 0, MoveType::Quiet, None));
+            }
+        }
+    }
+
+    // Final square of a non-capturing move from `from` to `to`, after sliding on ice and going through a portal
+    fn destination(&self, position: &Position, from: BIndex, to: BIndex) -> BIndex {
+        let no_promotions = Bitboard::zero();
+        let promotion_squares = position.piece_at(from).map_or(&no_promotions, |piece| piece.get_promotion_squares());
+        let blocked = |square: BIndex| position.occ_or_out_bounds.get_bit(square) || promotion_squares.get_bit(square);
+        let mut destination = to;
+        if self.ice.get_bit(to) {
+            let (from_x, from_y) = from_index(from);
+            let (mut x, mut y) = from_index(to);
+            let (dx, dy) = (x as i8 - from_x as i8, y as i8 - from_y as i8);
+            let step = if dx == 0 || dy == 0 || dx.abs() == dy.abs() { (dx.signum(), dy.signum()) } else { (dx, dy) };
+            while self.ice.get_bit(destination) {
+                let (next_x, next_y) = (x.wrapping_add_signed(step.0), y.wrapping_add_signed(step.1));
+                if !position.in_bounds(next_x, next_y) {
+                    break;
+                }
+                let next = to_index(next_x, next_y);
+                if blocked(next) || !self.can_enter(destination, next) {
+                    break;
+                }
+                (x, y, destination) = (next_x, next_y, next);
+            }
+        }
+        match self.portals.iter().find(|(entrance, _)| *entrance == destination) {
+            Some(&(_, exit)) if !blocked(exit) => exit,
+            _ => destination,
+        }
+    }
+}
@@ -0,0 +1,353 @@
+// Conversion between the movement of a piece and Betza's funny notation
+// See <https://www.gnu.org/software/xboard/Betza.html>
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::types::BOARD_WIDTH;
+use crate::{wrap_res, err, err_assert};
+
+use super::{PieceDefinition, PieceId, HopMove, HopLanding};
+
+// Number of steps of a rider without range limit (the longest line of the board)
+const RIDER_STEPS: u8 = BOARD_WIDTH - 1;
+
+// Letter of each basic atom and its delta. Compounds (K, R, B, Q) are made of W and F.
+const ATOMS: [(char, (i8, i8)); 9] = [
+    ('W', (0, 1)), ('F', (1, 1)), ('D', (0, 2)), ('N', (1, 2)), ('A', (2, 2)),
+    ('H', (0, 3)), ('C', (1, 3)), ('Z', (2, 3)), ('G', (3, 3)),
+];
+const W: usize = 0;
+const F: usize = 1;
+// Deltas of some of the directions of an atom
+type Directions = BTreeSet<(i8, i8)>;
+
+// Direction modifiers that the writer tries for each kind of atom, the ones that select more directions first
+const ORTHOGONAL_DIRECTIONS: [&str; 6] = ["v", "s", "f", "b", "l", "r"];
+const DIAGONAL_DIRECTIONS: [&str; 8] = ["f", "b", "l", "r", "fl", "fr", "bl", "br"];
+const OBLIQUE_DIRECTIONS: [&str; 26] = [
+    "f", "b", "l", "r", "v", "s", "ff", "bb", "ll", "rr", "fs", "bs", "lv", "rv", "fl", "fr", "bl", "br",
+    "ffl", "ffr", "bbl", "bbr", "llf", "llb", "rrf", "rrb",
+];
+
+impl PieceDefinition {
+    /// Creates a piece with the given ids and the movement described in Betza's funny notation
+    /// (e.g. `"N"` for the knight, `"fmWfcF"` for the pawn or `"BN"` for the archbishop).
+    ///
+    /// Supported atoms are `W F D N A H C Z G` and the compounds `K R B Q`. An atom can be followed by a
+    /// number to limit its range (`R4`), or repeated to make it a rider (`NN`, `0` also means unlimited range).
+    /// Supported modifiers are `m` (move only), `c` (capture only), `p` (hop over a screen and land anywhere
+    /// after it, like the cannon), `g` (land just after the screen, like the grasshopper) and the directions
+    /// `f b l r v s`. Only `R`, `B` and `Q` can hop.
+    ///
+    /// Forward is north, unless the piece only has an id for black. Pieces that move differently forward and
+    /// backward (like pawns) need a separate definition for each player.
+    /// Other properties of the piece (castling, promotions, double jumps, etc.) must be set separately.
+    pub fn from_betza(betza: &str, ids: [Option<PieceId>; 2]) -> wrap_res!(PieceDefinition) {
+        let mut definition = PieceDefinition { ids, ..Default::default() };
+        let mirror = ids[0].is_none() && ids[1].is_some();
+        let mut asymmetric = false;
+        let mut chars = betza.trim().chars().peekable();
+        err_assert!(chars.peek().is_some(), "The Betza notation cannot be empty");
+        while chars.peek().is_some() {
+            let mut modifiers = Vec::new();
+            while let Some(c) = chars.next_if(char::is_ascii_lowercase) {
+                modifiers.push(c);
+            }
+            let letter = match chars.next() {
+                Some(c) => c,
+                None => err!("Missing atom after the modifiers '{}' in '{}'", modifiers.iter().collect::<String>(), betza),
+            };
+            let (atoms, default_steps) = match letter {
+                'K' => (vec![W, F], 1),
+                'R' => (vec![W], 0),
+                'B' => (vec![F], 0),
+                'Q' => (vec![W, F], 0),
+                _ => match ATOMS.iter().position(|(c, _)| *c == letter) {
+                    Some(atom) => (vec![atom], 1),
+                    None => err!("Unknown Betza atom '{}' in '{}'", letter, betza),
+                },
+            };
+            let mut digits = String::new();
+            while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                digits.push(c);
+            }
+            let steps = if !digits.is_empty() {
+                match digits.parse::<u32>() {
+                    Ok(n) if n < RIDER_STEPS as u32 => n as u8,
+                    _ => 0,
+                }
+            } else if chars.next_if_eq(&letter).is_some() {
+                0
+            } else {
+                default_steps
+            };
+
+            let (mut translate, mut attack, mut hop) = (false, false, None);
+            let mut directions = Vec::new();
+            for &c in &modifiers {
+                match c {
+                    'm' => translate = true,
+                    'c' => attack = true,
+                    'p' => hop = Some(HopLanding::AnyAfterScreen),
+                    'g' => hop = Some(HopLanding::ImmediatelyAfter),
+                    'f' | 'b' | 'l' | 'r' | 'v' | 's' => directions.push(c),
+                    _ => err!("Unsupported Betza modifier '{}' in '{}'", c, betza),
+                }
+            }
+            if !translate && !attack {
+                translate = true;
+                attack = true;
+            }
+            for atom in atoms {
+                err_assert!(hop.is_none() || (steps == 0 && (atom == W || atom == F)),
+                    "Only R, B and Q can hop, found '{}' in '{}'", letter, betza);
+                let selected = select_directions(&directions, ATOMS[atom].1);
+                err_assert!(!selected.is_empty(), "The modifiers of '{}' don't select any direction in '{}'", letter, betza);
+                asymmetric |= selected.iter().any(|&(dx, dy)| !selected.contains(&(dx, -dy)));
+                for (dx, dy) in selected {
+                    let delta = if mirror { (dx, -dy) } else { (dx, dy) };
+                    if translate {
+                        definition.add_betza_move(delta, steps, hop, true);
+                    }
+                    if attack {
+                        definition.add_betza_move(delta, steps, hop, false);
+                    }
+                }
+            }
+        }
+        err_assert!(!asymmetric || ids.iter().any(Option::is_none),
+            "'{}' moves differently forward and backward, it needs a separate definition for each player", betza);
+        Ok(definition)
+    }
+
+    /// Returns the movement of the piece in Betza's funny notation (see `from_betza()`).
+    /// Returns an error if the piece has sliding deltas that can't be written in Betza notation.
+    pub fn to_betza(&self) -> wrap_res!(String) {
+        let mirror = self.ids[0].is_none() && self.ids[1].is_some();
+        // For each kind of move (atom, steps, hop landing), the directions in which the piece moves and captures
+        let mut kinds: BTreeMap<(usize, u8, u8), [Directions; 2]> = BTreeMap::new();
+        let mut add = |(dx, dy): (i8, i8), steps: u8, hop: u8, translate: bool| -> wrap_res!() {
+            let (a, b) = (dx.abs().min(dy.abs()), dx.abs().max(dy.abs()));
+            let atom = match ATOMS.iter().position(|(_, delta)| *delta == (a, b)) {
+                Some(atom) => atom,
+                None => err!("The move ({}, {}) can't be written in Betza notation", dx, dy),
+            };
+            let delta = if mirror { (dx, -dy) } else { (dx, dy) };
+            kinds.entry((atom, steps, hop)).or_default()[if translate { 0 } else { 1 }].insert(delta);
+            Ok(())
+        };
+        for translate in [true, false] {
+            let (jumps, runs, hops) = if translate {
+                (&self.translate_jump_deltas, &self.translate_sliding_deltas, &self.translate_hops)
+            } else {
+                (&self.attack_jump_deltas, &self.attack_sliding_deltas, &self.attack_hops)
+            };
+            for (delta, slides) in self.slide_directions(translate) {
+                if slides {
+                    add(delta, 0, 0, translate)?;
+                }
+            }
+            for &delta in jumps {
+                add(delta, 1, 0, translate)?;
+            }
+            for run in runs {
+                let (dx, dy) = match run.first() {
+                    Some(&delta) => delta,
+                    None => continue,
+                };
+                let is_ride = run.iter().zip(1..).all(|(&(x, y), k)| (x, y) == (dx * k, dy * k));
+                err_assert!(is_ride, "The sliding deltas {:?} can't be written in Betza notation", run);
+                let steps = if run.len() >= RIDER_STEPS as usize { 0 } else { run.len() as u8 };
+                add((dx, dy), steps, 0, translate)?;
+            }
+            for hop in hops {
+                let landing = if hop.landing == HopLanding::AnyAfterScreen { 1 } else { 2 };
+                add(hop.direction, 0, landing, translate)?;
+            }
+        }
+
+        // Modifiers, atom and steps of each part of the notation
+        let mut parts: Vec<(String, usize, u8)> = Vec::new();
+        for ((atom, steps, hop), [moves, captures]) in kinds {
+            let hop = ["", "p", "g"][hop as usize];
+            let both: BTreeSet<_> = moves.intersection(&captures).cloned().collect();
+            let only_moves: BTreeSet<_> = moves.difference(&captures).cloned().collect();
+            let only_captures: BTreeSet<_> = captures.difference(&moves).cloned().collect();
+            for (set, modality) in [(both, ""), (only_moves, "m"), (only_captures, "c")] {
+                if set.is_empty() {
+                    continue;
+                }
+                for directions in encode_directions(&set, ATOMS[atom].1) {
+                    parts.push((format!("{directions}{modality}{hop}"), atom, steps));
+                }
+            }
+        }
+        let mut betza = String::new();
+        for i in 0..parts.len() {
+            let (modifiers, atom, steps) = &parts[i];
+            // W and F with the same modifiers are written as a compound (K or Q)
+            let is_compound = |atom_1, atom_2| -> bool {
+                *atom == atom_1 && parts.iter().any(|(m, a, s)| m == modifiers && *a == atom_2 && s == steps)
+            };
+            if is_compound(F, W) {
+                continue;
+            }
+            let letter = match (is_compound(W, F), *atom, *steps) {
+                (true, _, 1) => 'K',
+                (true, _, _) => 'Q',
+                (false, W, s) if s != 1 => 'R',
+                (false, F, s) if s != 1 => 'B',
+                (false, atom, _) => ATOMS[atom].0,
+            };
+            betza.push_str(modifiers);
+            betza.push(letter);
+            match steps {
+                0 if !matches!(letter, 'R' | 'B' | 'Q') => betza.push(letter),
+                0 | 1 => {},
+                n => betza.push_str(&n.to_string()),
+            }
+        }
+        Ok(betza)
+    }
+
+    // Adds a move (translate) or a capture (attack) in the direction `delta`, with a range of `steps` (0 for unlimited)
+    fn add_betza_move(&mut self, delta: (i8, i8), steps: u8, hop: Option<HopLanding>, translate: bool) {
+        if let Some(landing) = hop {
+            let hops = if translate { &mut self.translate_hops } else { &mut self.attack_hops };
+            push_unique(hops, HopMove { direction: delta, landing });
+        } else if steps == 1 {
+            let jumps = if translate { &mut self.translate_jump_deltas } else { &mut self.attack_jump_deltas };
+            push_unique(jumps, delta);
+        } else if let (0, Some(slides)) = (steps, self.slide_direction_mut(delta, translate)) {
+            *slides = true;
+        } else {
+            let steps = if steps == 0 { RIDER_STEPS } else { steps } as i8;
+            let run = (1..=steps).map(|k| (delta.0 * k, delta.1 * k)).collect();
+            let runs = if translate { &mut self.translate_sliding_deltas } else { &mut self.attack_sliding_deltas };
+            push_unique(runs, run);
+        }
+    }
+
+    // Returns the 8 main directions and whether the piece slides in each of them
+    fn slide_directions(&self, translate: bool) -> [((i8, i8), bool); 8] {
+        if translate {
+            [((0, 1), self.translate_north), ((0, -1), self.translate_south), ((1, 0), self.translate_east),
+             ((-1, 0), self.translate_west), ((1, 1), self.translate_northeast), ((-1, 1), self.translate_northwest),
+             ((1, -1), self.translate_southeast), ((-1, -1), self.translate_southwest)]
+        } else {
+            [((0, 1), self.attack_north), ((0, -1), self.attack_south), ((1, 0), self.attack_east),
+             ((-1, 0), self.attack_west), ((1, 1), self.attack_northeast), ((-1, 1), self.attack_northwest),
+             ((1, -1), self.attack_southeast), ((-1, -1), self.attack_southwest)]
+        }
+    }
+
+    // Returns the field that enables sliding in the direction `delta`, if it's one of the 8 main directions
+    fn slide_direction_mut(&mut self, delta: (i8, i8), translate: bool) -> Option<&mut bool> {
+        Some(match (translate, delta) {
+            (true, (0, 1)) => &mut self.translate_north,
+            (true, (0, -1)) => &mut self.translate_south,
+            (true, (1, 0)) => &mut self.translate_east,
+            (true, (-1, 0)) => &mut self.translate_west,
+            (true, (1, 1)) => &mut self.translate_northeast,
+            (true, (-1, 1)) => &mut self.translate_northwest,
+            (true, (1, -1)) => &mut self.translate_southeast,
+            (true, (-1, -1)) => &mut self.translate_southwest,
+            (false, (0, 1)) => &mut self.attack_north,
+            (false, (0, -1)) => &mut self.attack_south,
+            (false, (1, 0)) => &mut self.attack_east,
+            (false, (-1, 0)) => &mut self.attack_west,
+            (false, (1, 1)) => &mut self.attack_northeast,
+            (false, (-1, 1)) => &mut self.attack_northwest,
+            (false, (1, -1)) => &mut self.attack_southeast,
+            (false, (-1, -1)) => &mut self.attack_southwest,
+            _ => return None,
+        })
+    }
+}
+
+fn push_unique<T: PartialEq>(list: &mut Vec<T>, value: T) {
+    if !list.contains(&value) {
+        list.push(value);
+    }
+}
+
+// Returns the 4 or 8 directions of an atom (e.g. `(1, 2)` for the knight)
+fn atom_deltas((a, b): (i8, i8)) -> Vec<(i8, i8)> {
+    let mut deltas = Vec::new();
+    for (x, y) in [(a, b), (b, a)] {
+        for (sx, sy) in [(1, 1), (-1, 1), (1, -1), (-1, -1)] {
+            push_unique(&mut deltas, (x * sx, y * sy));
+        }
+    }
+    deltas
+}
+
+// Returns the directions of an atom selected by the direction modifiers (all of them if there are no modifiers).
+// Consecutive letters of different axes are combined (e.g. "fl" is forward-left for F), and for oblique atoms a
+// doubled letter selects the narrow moves in that direction (e.g. "ff" is the 2 forward moves of N that are more forward).
+fn select_directions(letters: &[char], atom: (i8, i8)) -> Vec<(i8, i8)> {
+    let is_vertical = |c: char| c == 'f' || c == 'b';
+    let is_horizontal = |c: char| c == 'l' || c == 'r';
+    let other_axis = |c1: char, c2: char| is_vertical(c1) && is_horizontal(c2) || is_horizontal(c1) && is_vertical(c2);
+    let orthogonal = atom.0 == 0;
+    let oblique = !orthogonal && atom.0 != atom.1;
+    let mut groups: Vec<Vec<char>> = Vec::new();
+    for &c in letters {
+        let joins = match groups.last().map(|group| &group[..]) {
+            Some(&[last]) if oblique => other_axis(last, c) || (c == last && c != 'v' && c != 's') ||
+                (is_vertical(last) && c == 's') || (is_horizontal(last) && c == 'v'),
+            Some(&[last]) => !orthogonal && other_axis(last, c),
+            Some(&[first, second]) => oblique && first == second && other_axis(first, c),
+            _ => false,
+        };
+        match groups.last_mut() {
+            Some(group) if joins => group.push(c),
+            _ => groups.push(vec![c]),
+        }
+    }
+    let matches = |c: char, (dx, dy): (i8, i8)| match c {
+        'f' => dy > 0,
+        'b' => dy < 0,
+        'l' => dx < 0,
+        'r' => dx > 0,
+        'v' => dy.abs() > dx.abs(),
+        _ => dx.abs() > dy.abs(),
+    };
+    atom_deltas(atom).into_iter().filter(|&delta| groups.is_empty() || groups.iter().any(|group| {
+        let doubled = group.len() >= 2 && group[0] == group[1];
+        let narrow = if is_vertical(group[0]) { 'v' } else { 's' };
+        group.iter().all(|&c| matches(c, delta)) && (!doubled || matches(narrow, delta))
+    })).collect()
+}
+
+// Returns the direction modifiers that select the given directions of an atom. Usually there is a single string,
+// but if the modifiers can't be written together the atom is repeated for each of them (e.g. "fFlF").
+fn encode_directions(directions: &Directions, atom: (i8, i8)) -> Vec<String> {
+    let select = |modifiers: &str| -> Directions {
+        select_directions(&modifiers.chars().collect::<Vec<_>>(), atom).into_iter().collect()
+    };
+    if select("") == *directions {
+        return vec![String::new()];
+    }
+    let candidates: &[&str] = if atom.0 == 0 {
+        &ORTHOGONAL_DIRECTIONS
+    } else if atom.0 == atom.1 {
+        &DIAGONAL_DIRECTIONS
+    } else {
+        &OBLIQUE_DIRECTIONS
+    };
+    let mut chosen = Vec::new();
+    let mut covered = BTreeSet::new();
+    for candidate in candidates {
+        let selected = select(candidate);
+        if selected.is_subset(directions) && !selected.is_subset(&covered) {
+            covered.extend(selected);
+            chosen.push(candidate.to_string());
+        }
+        if covered == *directions {
+            break;
+        }
+    }
+    let joined = chosen.concat();
+    if select(&joined) == *directions { vec![joined] } else { chosen }
+}
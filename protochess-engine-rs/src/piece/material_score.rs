@@ -3,13 +3,54 @@ use crate::utils::to_index;
 use crate::MoveGen;
 use crate::types::{Centipawns, BDimensions};
 
+/// Weights used to compute the material score of a piece from its movement pattern
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MaterialWeights {
+    /// Score of each capturing slide, per square of the average length of the slide
+    pub attack_mul: f32,
+    /// Score of each non-capturing slide, per square of the average length of the slide
+    pub translate_mul: f32,
+    /// Penalty for a slider that is limited to a single color of squares
+    pub color_bound_penalty: Centipawns,
+    /// Penalty for a slider that cannot go back in one of the directions
+    pub one_direction_penalty: Centipawns,
+    /// Score of each jump delta (capturing or non-capturing)
+    pub jump: Centipawns,
+    /// Score of each delta of a delta-based slide
+    pub sliding_delta: Centipawns,
+    /// Score of being able to promote
+    pub promotion: Centipawns,
+}
+
+/// Weights used by `compute_material_score()`
+pub const MATERIAL_WEIGHTS: MaterialWeights = MaterialWeights {
+    attack_mul: 10.0,
+    translate_mul: 6.5,
+    color_bound_penalty: 150,
+    one_direction_penalty: 200,
+    jump: 20,
+    sliding_delta: 20,
+    promotion: 40,
+};
+
+impl Default for MaterialWeights {
+    fn default() -> Self {
+        MATERIAL_WEIGHTS
+    }
+}
+
 /// Returns a score value for a piece, given its movement pattern
 pub fn compute_material_score(mp: &PieceDefinition, dims: &BDimensions) -> Centipawns {
+    compute_material_score_with(mp, dims, &MATERIAL_WEIGHTS)
+}
+
+/// Same as `compute_material_score()`, but with custom weights (used for tuning)
+pub fn compute_material_score_with(mp: &PieceDefinition, dims: &BDimensions, weights: &MaterialWeights) -> Centipawns {
     // This function is called only once, so it's worth it to implement a more complex scoring system
     // https://www.chessprogramming.org/Point_Value
     
-    const ATTACK_MUL: f32 = 10.0;
-    const TRANSLATE_MUL: f32 = 6.5;
+    let attack_mul = weights.attack_mul;
+    let translate_mul = weights.translate_mul;
     
     let mut score = 0;
     
@@ -19,58 +60,71 @@ pub fn compute_material_score(mp: &PieceDefinition, dims: &BDimensions) -> Centi
     let antidiag = 1.4 * average_dimension(dims, false, false, false, true);
     
     // 130 centipawns for each direction (Rook is 4*130 = 520 centipawns, Queen is 8*130 = 1040 centipawns)
-    if mp.attack_north { score += (ATTACK_MUL * height) as Centipawns }
-    if mp.attack_south { score += (ATTACK_MUL * height) as Centipawns }
-    if mp.attack_east  { score += (ATTACK_MUL * width) as Centipawns }
-    if mp.attack_west  { score += (ATTACK_MUL * width) as Centipawns }
-    if mp.translate_north { score += (TRANSLATE_MUL * height) as Centipawns }
-    if mp.translate_south { score += (TRANSLATE_MUL * height) as Centipawns }
-    if mp.translate_east  { score += (TRANSLATE_MUL * width) as Centipawns }
-    if mp.translate_west  { score += (TRANSLATE_MUL * width) as Centipawns }
+    if mp.attack_north { score += (attack_mul * height) as Centipawns }
+    if mp.attack_south { score += (attack_mul * height) as Centipawns }
+    if mp.attack_east  { score += (attack_mul * width) as Centipawns }
+    if mp.attack_west  { score += (attack_mul * width) as Centipawns }
+    if mp.translate_north { score += (translate_mul * height) as Centipawns }
+    if mp.translate_south { score += (translate_mul * height) as Centipawns }
+    if mp.translate_east  { score += (translate_mul * width) as Centipawns }
+    if mp.translate_west  { score += (translate_mul * width) as Centipawns }
+    
+    if mp.attack_northeast { score += (attack_mul * diag) as Centipawns }
+    if mp.attack_southwest { score += (attack_mul * diag) as Centipawns }
+    if mp.attack_northwest { score += (attack_mul * antidiag) as Centipawns }
+    if mp.attack_southeast { score += (attack_mul * antidiag) as Centipawns }
+    if mp.translate_northeast { score += (translate_mul * diag) as Centipawns }
+    if mp.translate_southwest { score += (translate_mul * diag) as Centipawns }
+    if mp.translate_northwest { score += (translate_mul * antidiag) as Centipawns }
+    if mp.translate_southeast { score += (translate_mul * antidiag) as Centipawns }
     
-    if mp.attack_northeast { score += (ATTACK_MUL * diag) as Centipawns }
-    if mp.attack_southwest { score += (ATTACK_MUL * diag) as Centipawns }
-    if mp.attack_northwest { score += (ATTACK_MUL * antidiag) as Centipawns }
-    if mp.attack_southeast { score += (ATTACK_MUL * antidiag) as Centipawns }
-    if mp.translate_northeast { score += (TRANSLATE_MUL * diag) as Centipawns }
-    if mp.translate_southwest { score += (TRANSLATE_MUL * diag) as Centipawns }
-    if mp.translate_northwest { score += (TRANSLATE_MUL * antidiag) as Centipawns }
-    if mp.translate_southeast { score += (TRANSLATE_MUL * antidiag) as Centipawns }
+    // Hops need a screen piece, count them as half a slide
+    for (hops, mul) in [(&mp.attack_hops, attack_mul), (&mp.translate_hops, translate_mul)] {
+        for hop in hops {
+            let length = match hop.direction {
+                (0, _) => height,
+                (_, 0) => width,
+                (dx, dy) if dx == dy => diag,
+                _ => antidiag,
+            };
+            score += (0.5 * mul * length) as Centipawns;
+        }
+    }
     
-    let only_able_to_slide = !mp.can_promote() && !mp.can_jump() && !mp.has_sliding_deltas();
+    let only_able_to_slide = !mp.can_promote() && !mp.can_jump() && !mp.has_sliding_deltas() && !mp.can_hop();
     
     // Debuff for being limited to a single color of squares
     if !mp.can_slide_main_direction() && only_able_to_slide {
         // Bishop is 4*130 - 150 = 370 centipawns
-        score -= 150;
+        score -= weights.color_bound_penalty;
     }
     
     // Debuff for being limited to a single direction
     if mp.can_slide_north_indirectly() && !mp.can_slide_south_indirectly() && only_able_to_slide {
-        score -= 200;
+        score -= weights.one_direction_penalty;
     }
     if mp.can_slide_south_indirectly() && !mp.can_slide_north_indirectly() && only_able_to_slide {
-        score -= 200;
+        score -= weights.one_direction_penalty;
     }
     if mp.can_slide_east_indirectly() && !mp.can_slide_west_indirectly() && only_able_to_slide {
-        score -= 200;
+        score -= weights.one_direction_penalty;
     }
     if mp.can_slide_west_indirectly() && !mp.can_slide_east_indirectly() && only_able_to_slide {
-        score -= 200;
+        score -= weights.one_direction_penalty;
     }
     
     // 40 centipawns for each jump (Knight is 8*40 = 320 centipawns)
-    score += (mp.translate_jump_deltas.len() * 20) as Centipawns;
-    score += (mp.attack_jump_deltas.len() * 20) as Centipawns;
+    score += mp.translate_jump_deltas.len() as Centipawns * weights.jump;
+    score += mp.attack_jump_deltas.len() as Centipawns * weights.jump;
     // 40 centipawns for each delta-based slide group
     for d in mp.translate_sliding_deltas.iter().chain(mp.attack_sliding_deltas.iter()) {
-        score += (d.len() * 20) as Centipawns;
+        score += d.len() as Centipawns * weights.sliding_delta;
     }
     
     // 40 centipawns for being able to promote
     if mp.can_promote() {
         // Pawn is 20*3 + 40 = 100 centipawns
-        score += 40;
+        score += weights.promotion;
     }
     
     if mp.is_leader {
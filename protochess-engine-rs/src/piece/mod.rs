@@ -2,6 +2,8 @@ use rand::rngs::StdRng;
 use rand::{SeedableRng, Rng};
 
 use crate::{types::*, Position};
+use crate::position::StandardMovement;
+use crate::utils::{to_index, from_index};
 
 // Use a unicode character as the id of a piece type
 // Note that in Rust a char is 4 bytes (equivalent to u32)
@@ -13,14 +15,18 @@ mod material_score;
 mod positional_score;
 mod movement;
 mod precomputed_piece_def;
+mod validation;
+mod betza;
 
 pub use piece_factory::PieceFactory;
-pub use piece_definition::PieceDefinition;
+pub use piece_definition::{PieceDefinition, PieceDisplay, CaptureMode, HopMove, HopLanding, CastlingRule};
+pub use validation::ValidationError;
+pub use material_score::{MaterialWeights, MATERIAL_WEIGHTS, compute_material_score_with};
 
 use material_score::compute_material_score;
-use positional_score::compute_piece_square_table;
+use positional_score::{compute_piece_square_table, compute_win_distances};
 use precomputed_piece_def::PrecomputedPieceDef;
-use movement::{output_translations, output_captures};
+use movement::{output_translations, output_captures, output_non_promotions, count_mobility, displacement_target, hop_landing_squares, castling_square};
 
 /// Represents a piece type (e.g. 'Rook'). Individual instances of this piece are represented by a 1 in the bitboard
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
@@ -31,11 +37,13 @@ pub struct Piece {
     type_def: PieceDefinition,
     /// Derived from `type_def`
     precomp: PrecomputedPieceDef,
+    /// Standard piece that moves like this one, derived from `type_def`
+    standard_movement: Option<StandardMovement>,
     /// Occupancy bitboard
     bitboard: Bitboard,
     /// Player num for the owner of this piece
     player_num: Player,
-    /// Zobrist hashes for this piece at each board index
+    /// Zobrist hashes for this piece at each board index, followed by the hashes for each number of pieces in hand
     zobrist_hashes: Vec<ZobKey>,
     
     /// Material score for this piece
@@ -44,14 +52,25 @@ pub struct Piece {
     piece_square_table: Vec<Centipawns>,
     /// Table of positional scores for this piece in the endgame
     piece_square_table_endgame: Vec<Centipawns>,
+    /// Number of squares that this piece can move to from the center of an empty board
+    center_mobility: u32,
+    /// Number of moves needed to reach a win square from each square of an empty board
+    win_distances: Vec<Option<u8>>,
     
     /// Number of `1` bits in the bitboard
     num_pieces: u32,
     /// Result of `num_pieces * material_score`
     total_material_score: Centipawns,
+    /// Number of captured pieces of this type that can be dropped on the board (crazyhouse)
+    num_in_hand: u32,
     
-    /// Positions at which this piece can castle. Used if `can_castle` or `is_castle_rook` are `true`
+    /// Positions at which this piece can castle (or gate). Used if `can_castle` or `is_castle_rook` are `true`,
+    /// or if gating is enabled
     castle_squares: Bitboard,
+    /// Back rank of the player if gating is enabled, empty otherwise
+    gating_squares: Bitboard,
+    /// Result of `type_def.get_castling_rules()`, with the vertical rules mirrored for black
+    castling_rules: Vec<CastlingRule>,
 }
 
 impl Piece {
@@ -62,19 +81,74 @@ impl Piece {
         let zobrist_hashes = Piece::random_zobrist(id, player_num);
         let piece_square_table = compute_piece_square_table(&definition, dims, false);
         let piece_square_table_endgame = compute_piece_square_table(&definition, dims, true);
+        let win_distances = compute_win_distances(&definition, dims);
+        let precomp = PrecomputedPieceDef::from((&definition, dims));
+        let center = to_index(dims.width / 2, dims.height / 2);
+        let castling_rules = Piece::player_castling_rules(&definition, player_num, dims);
+        let center_mobility = count_mobility(&definition, center, &dims.bounds, &!&dims.bounds,
+            &precomp.jump_bitboards_translate[center as usize], &precomp.jump_bitboards_capture[center as usize]);
         Piece {
             id,
-            precomp: PrecomputedPieceDef::from((&definition, dims)),
+            precomp,
+            standard_movement: StandardMovement::of(&definition),
             type_def: definition,
             player_num,
             zobrist_hashes,
             material_score,
             piece_square_table,
             piece_square_table_endgame,
+            center_mobility,
+            win_distances,
             bitboard: Bitboard::zero(),
             num_pieces: 0,
+            num_in_hand: 0,
             total_material_score: 0,
             castle_squares: Bitboard::zero(),
+            gating_squares: Bitboard::zero(),
+            castling_rules,
+        }
+    }
+    
+    // Mirror the vertical castling rules for black, so that the directions and coordinates are absolute
+    fn player_castling_rules(definition: &PieceDefinition, player_num: Player, dims: &BDimensions) -> Vec<CastlingRule> {
+        let mut rules = definition.get_castling_rules();
+        if player_num == 1 {
+            for rule in rules.iter_mut().filter(|rule| rule.is_vertical()) {
+                rule.direction.1 = -rule.direction.1;
+                rule.destination = (dims.height - 1).wrapping_sub(rule.destination);
+                rule.partner_destination = (dims.height - 1).wrapping_sub(rule.partner_destination);
+            }
+        }
+        rules
+    }
+    
+    /// Recomputes the scores and move tables that depend on the squares of the board, after a wall has been
+    /// added or removed. The pieces on the board and in hand are kept.
+    pub fn set_dimensions(&mut self, dims: &BDimensions) {
+        self.material_score = compute_material_score(&self.type_def, dims);
+        self.total_material_score = self.num_pieces as Centipawns * self.material_score;
+        self.piece_square_table = compute_piece_square_table(&self.type_def, dims, false);
+        self.piece_square_table_endgame = compute_piece_square_table(&self.type_def, dims, true);
+        self.win_distances = compute_win_distances(&self.type_def, dims);
+        self.precomp = PrecomputedPieceDef::from((&self.type_def, dims));
+        let center = to_index(dims.width / 2, dims.height / 2);
+        self.center_mobility = count_mobility(&self.type_def, center, &dims.bounds, &!&dims.bounds,
+            &self.precomp.jump_bitboards_translate[center as usize], &self.precomp.jump_bitboards_capture[center as usize]);
+    }
+    
+    /// Recomputes the material score with custom weights (see `Engine::set_eval_weights()`).
+    /// `set_dimensions()` goes back to the default weights.
+    pub fn set_material_weights(&mut self, weights: &MaterialWeights, dims: &BDimensions) {
+        self.material_score = compute_material_score_with(&self.type_def, dims, weights);
+        self.total_material_score = self.num_pieces as Centipawns * self.material_score;
+    }
+    
+    /// Makes this piece keep track of whether it has moved from the back rank, so that pieces in hand
+    /// can be gated in when it leaves it. Must be called before adding any piece.
+    pub fn enable_gating(&mut self, dims: &BDimensions) {
+        let back_rank = if self.player_num == 0 { 0 } else { dims.height - 1 };
+        for x in 0..dims.width {
+            self.gating_squares.set_bit_at(x, back_rank);
         }
     }
     
@@ -85,11 +159,15 @@ impl Piece {
     
     /// Get the prefix of this piece type in algebraic notation
     pub fn get_notation_prefix(&self) -> String {
-        let prefix = self.type_def.notation_prefix[self.player_num as usize].clone();
-        if let Some(prefix) = prefix {
-            prefix
-        } else {
-            self.id.to_string()
+        let mut prefix = String::new();
+        self.push_notation_prefix(&mut prefix);
+        prefix
+    }
+    /// Appends the notation prefix to `out`, without allocating a new string
+    pub fn push_notation_prefix(&self, out: &mut String) {
+        match &self.type_def.notation_prefix[self.player_num as usize] {
+            Some(prefix) => out.push_str(prefix),
+            None => out.push(self.id),
         }
     }
     
@@ -102,6 +180,10 @@ impl Piece {
     pub fn get_bitboard(&self) -> &Bitboard {
         &self.bitboard
     }
+    /// Squares where this piece promotes when it moves to them
+    pub fn get_promotion_squares(&self) -> &Bitboard {
+        &self.precomp.promotion_squares
+    }
     /// Returns `true` if there is an instance of this piece type at the given index
     pub fn is_at_index(&self, index: BIndex) -> bool {
         self.bitboard.get_bit(index)
@@ -109,13 +191,7 @@ impl Piece {
     
     /// Get the indexes of all pieces of this type
     pub fn get_indexes(&self) -> Vec<BIndex> {
-        let mut bb_copy = self.bitboard.clone();
-        let mut indexes = Vec::new();
-        while let Some(index) = bb_copy.lowest_one() {
-            bb_copy.clear_bit(index);
-            indexes.push(index);
-        }
-        indexes
+        self.bitboard.iter().collect()
     }
     
     /// Returns `true` if this piece is a leader (king)
@@ -123,16 +199,47 @@ impl Piece {
         self.type_def.is_leader
     }
     
+    /// Returns the standard piece (king, knight or bishop) that moves exactly like this piece type, if any
+    pub(crate) fn standard_movement(&self) -> Option<StandardMovement> {
+        self.standard_movement
+    }
+    
     /// Returns the number of instances of this piece type on the board
     pub fn get_num_pieces(&self) -> u32 {
         self.num_pieces
     }
     
+    /// Returns the number of pieces of this type in the hand of the player
+    pub fn get_num_in_hand(&self) -> u32 {
+        self.num_in_hand
+    }
+    
     /// Returns `true` if this piece behaves like a rook when castling
     pub fn is_rook(&self) -> bool {
         self.type_def.is_castle_rook
     }
     
+    /// Returns `true` if this piece can be the castling partner of a piece that follows the given rule
+    pub fn is_castling_partner(&self, rule: &CastlingRule) -> bool {
+        self.is_rook() && (rule.partners.is_empty() || rule.partners.contains(&self.id))
+    }
+    
+    /// Get the ways in which this piece can castle. Unlike `PieceDefinition::get_castling_rules()`, the vertical
+    /// directions and coordinates are not relative to the player.
+    pub fn get_castling_rules(&self) -> &[CastlingRule] {
+        &self.castling_rules
+    }
+    
+    /// Returns the square where the castling partner ends after a castling move made by this piece
+    pub fn get_castling_partner_to(&self, mv: Move) -> BIndex {
+        let (from_x, from_y) = from_index(mv.get_from());
+        let (partner_x, partner_y) = from_index(mv.get_target());
+        let direction = ((partner_x as i8 - from_x as i8).signum(), (partner_y as i8 - from_y as i8).signum());
+        let rule = self.castling_rules.iter().find(|rule| rule.direction == direction)
+            .expect("Castling move does not match any castling rule");
+        castling_square(rule, mv.get_from(), rule.partner_destination)
+    }
+    
     /// Returns `true` if this piece has not moved from its starting position (can castle)
     pub fn has_not_moved(&self, index: BIndex) -> bool {
         self.castle_squares.get_bit(index)
@@ -148,6 +255,17 @@ impl Piece {
         !((&self.precomp.instant_win_squares & &self.bitboard).is_zero())
     }
     
+    /// Returns `true` if this piece type has any win square
+    pub fn has_win_squares(&self) -> bool {
+        !self.precomp.instant_win_squares.is_zero()
+    }
+    
+    /// Returns the number of moves that this piece needs to reach a win square from the given index, ignoring
+    /// the other pieces on the board. `None` if it can't reach any.
+    pub fn get_win_distance(&self, index: BIndex) -> Option<u8> {
+        self.win_distances[index as usize]
+    }
+    
     /// Returns `true` if this piece has a win square at the given index
     pub fn wins_at(&self, index: BIndex) -> bool {
         self.precomp.instant_win_squares.get_bit(index)
@@ -158,11 +276,31 @@ impl Piece {
         self.type_def.immune_to_explosion
     }
     
+    /// Returns the forward direction of this piece if it can only move forward (like a pawn), see
+    /// `PieceDefinition::forward_direction()`
+    pub fn get_forward_direction(&self) -> Option<i8> {
+        self.precomp.forward_direction
+    }
+    
+    /// Returns `true` if moving this piece resets the halfmove clock
+    pub fn resets_halfmove_clock(&self) -> bool {
+        self.precomp.resets_halfmove_clock
+    }
+    
     /// Get the zobrist hash for this piece at the given index
     pub fn get_zobrist(&self, index: BIndex) -> ZobKey {
         self.zobrist_hashes[index as usize]
     }
     
+    /// Get the zobrist hash for having `count` pieces of this type in hand (0 if the hand is empty)
+    pub fn get_hand_zobrist(&self, count: u32) -> ZobKey {
+        if count == 0 {
+            return 0;
+        }
+        // There cannot be more pieces in hand than squares on the board
+        self.zobrist_hashes[BOARD_SIZE + count as usize - 1]
+    }
+    
     /// Get the zobrist hash for the castling right of this piece at the given index
     pub fn get_castle_zobrist(&self, index: BIndex) -> ZobKey {
         // This could be implemented with a separate random array, but this is simpler
@@ -179,6 +317,17 @@ impl Piece {
         self.type_def.can_castle() || self.type_def.is_castle_rook
     }
     
+    /// Returns `true` if this piece keeps track of whether it has moved from the given index
+    /// (for castling or gating)
+    pub fn tracks_unmoved(&self, index: BIndex) -> bool {
+        self.used_in_castling() || self.gating_squares.get_bit(index)
+    }
+    
+    /// Returns `true` if a piece in hand can be gated in when this piece leaves the given index
+    pub fn can_gate_from(&self, index: BIndex) -> bool {
+        self.gating_squares.get_bit(index) && self.castle_squares.get_bit(index)
+    }
+    
     /// Move a piece from one index to another
     /// If `set_can_castle` is `true`, set the new index as a castle square.
     /// Returns `true` if the piece could castle before this move
@@ -189,7 +338,7 @@ impl Piece {
         self.bitboard.clear_bit(from);
         self.bitboard.set_bit(to);
         
-        if self.used_in_castling() {
+        if self.used_in_castling() || !self.gating_squares.is_zero() {
             self.castle_squares.clear_bit(from);
             if set_can_castle {
                 self.castle_squares.set_bit(to);
@@ -208,7 +357,7 @@ impl Piece {
         self.num_pieces += 1;
         self.total_material_score += self.material_score;
         
-        if set_can_castle && self.used_in_castling() {
+        if set_can_castle && self.tracks_unmoved(index) {
             self.castle_squares.set_bit(index);
         }
     }
@@ -227,11 +376,30 @@ impl Piece {
         could_castle
     }
     
-    /// Get the material score for all current units of this piece
+    /// Add a piece of this type to the hand of the player.
+    /// **Don't call this directly, use `PieceSet::add_to_hand()` instead.**
+    #[inline]
+    pub fn add_to_hand_(&mut self) {
+        self.num_in_hand += 1;
+    }
+    
+    /// Remove a piece of this type from the hand of the player (assuming there is one).
+    /// **Don't call this directly, use `PieceSet::remove_from_hand()` instead.**
+    #[inline]
+    pub fn remove_from_hand_(&mut self) {
+        self.num_in_hand -= 1;
+    }
+    
+    /// Get the material score for all current units of this piece (on the board)
     pub fn get_material_score_all(&self) -> Centipawns {
         self.total_material_score
     }
     
+    /// Get the material score for all units of this piece in the hand of the player
+    pub fn get_material_score_hand(&self) -> Centipawns {
+        self.num_in_hand as Centipawns * self.material_score
+    }
+    
     /// Get the positional score for 1 unit of this piece at the given index
     #[inline]
     pub fn get_positional_score<const ENDGAME: bool>(&self, index: BIndex) -> Centipawns {
@@ -245,13 +413,7 @@ impl Piece {
     /// Get the positional score for all current units of this piece
     #[inline]
     pub fn get_positional_score_all<const ENDGAME: bool>(&self) -> Centipawns {
-        let mut bb_copy = self.bitboard.clone();
-        let mut score = 0;
-        while let Some(index) = bb_copy.lowest_one() {
-            score += self.get_positional_score::<ENDGAME>(index);
-            bb_copy.clear_bit(index);
-        }
-        score
+        self.bitboard.iter().map(|index| self.get_positional_score::<ENDGAME>(index)).sum()
     }
     
     /// Get all the non-capture moves that all instances of this piece can make.
@@ -259,23 +421,63 @@ impl Piece {
     pub fn output_translations(&self, position: &Position, enemies: &Bitboard,
         occ_or_not_in_bounds: &Bitboard, out_moves: &mut Vec<Move>)
     {
-        let mut bb_copy = self.bitboard.clone();
-        while let Some(index) = bb_copy.lowest_one() {
-            let can_castle = self.type_def.can_castle() && self.castle_squares.get_bit(index);
+        if !self.type_def.capture_mode.captures_by_displacement() {
+            self.output_all_translations(position, enemies, occ_or_not_in_bounds, true, out_moves);
+            return;
+        }
+        // Capturing by displacement is mandatory, the moves that capture are output by output_captures()
+        let first_move = out_moves.len();
+        self.output_all_translations(position, enemies, occ_or_not_in_bounds, true, out_moves);
+        let moves = out_moves.split_off(first_move);
+        out_moves.extend(moves.into_iter().filter(|mv| self.displacement_target(*mv, position, enemies).is_none()));
+    }
+    
+    // Outputs the translations of all instances of this piece, including the ones that capture by displacement
+    fn output_all_translations(&self, position: &Position, enemies: &Bitboard,
+        occ_or_not_in_bounds: &Bitboard, allow_castling: bool, out_moves: &mut Vec<Move>)
+    {
+        let bb_copy = &self.bitboard & !position.get_immobile_squares();
+        let first_move = out_moves.len();
+        for index in &bb_copy {
+            let can_castle = allow_castling && self.type_def.can_castle() && self.castle_squares.get_bit(index);
+            let castling_rules = if can_castle { &self.castling_rules[..] } else { &[] };
             output_translations(
                 &self.type_def,
                 index,
                 position,
                 enemies,
-                &self.precomp.promotion_squares,
+                self.promotion_squares_from(index, position),
                 occ_or_not_in_bounds,
-                can_castle,
+                castling_rules,
                 &self.precomp.double_jump_squares,
                 &self.precomp.jump_bitboards_translate,
                 &self.type_def.promo_vals[self.player_num as usize],
                 out_moves
             );
-            bb_copy.clear_bit(index);
+        }
+        if self.type_def.promotion_is_optional {
+            output_non_promotions(&self.type_def.promo_vals[self.player_num as usize], first_move, out_moves);
+        }
+    }
+    
+    // If this piece captures by displacement, returns the index of the enemy piece captured by a translation move
+    fn displacement_target(&self, mv: Move, position: &Position, enemies: &Bitboard) -> Option<BIndex> {
+        if mv.is_castling() || mv.get_move_type() == MoveType::DoubleJump {
+            return None;
+        }
+        displacement_target(self.type_def.capture_mode, mv.get_from(), mv.get_to(), position)
+            .filter(|target| enemies.get_bit(*target))
+    }
+    
+    /// Get all the moves that drop a piece of this type from the hand of the player (if there is any).
+    /// The moves are added to the `out_moves` vector.
+    pub fn output_drops(&self, occ_or_not_in_bounds: &Bitboard, out_moves: &mut Vec<Move>) {
+        if self.num_in_hand == 0 {
+            return;
+        }
+        let targets = &self.precomp.drop_squares & !occ_or_not_in_bounds;
+        for index in &targets {
+            out_moves.push(Move::drop(index, self.id));
         }
     }
     
@@ -284,21 +486,98 @@ impl Piece {
     pub fn output_captures(&self, position: &Position, enemies: &Bitboard,
         occ_or_not_in_bounds: &Bitboard, out_moves: &mut Vec<Move>)
     {
-        let mut bb_copy = self.bitboard.clone();
-        while let Some(index) = bb_copy.lowest_one() {
+        let capture_mode = self.type_def.capture_mode;
+        if capture_mode.captures_by_displacement() {
+            let mut translations = Vec::new();
+            self.output_all_translations(position, enemies, occ_or_not_in_bounds, false, &mut translations);
+            for mv in translations {
+                if let Some(target) = self.displacement_target(mv, position, enemies) {
+                    let move_type = if mv.is_promotion() { MoveType::PromotionCapture } else { MoveType::Capture };
+                    out_moves.push(Move::new(mv.get_from(), mv.get_to(), target, move_type, mv.get_promotion_piece()));
+                }
+            }
+            return;
+        }
+        let bb_copy = &self.bitboard & !position.get_immobile_squares();
+        let first_move = out_moves.len();
+        // Pieces that capture without moving cannot promote when capturing
+        let no_promotion_squares = Bitboard::zero();
+        for index in &bb_copy {
+            let promotion_squares = {
+                if capture_mode == CaptureMode::RifleStay { &no_promotion_squares }
+                else { self.promotion_squares_from(index, position) }
+            };
             output_captures(
                 &self.type_def,
                 index,
                 position,
                 enemies,
-                &self.precomp.promotion_squares,
+                promotion_squares,
                 occ_or_not_in_bounds,
                 &self.precomp.jump_bitboards_capture[index as usize],
                 &self.type_def.promo_vals[self.player_num as usize],
                 out_moves
             );
-            bb_copy.clear_bit(index);
         }
+        if capture_mode == CaptureMode::RifleStay {
+            // The destination of the move is the captured piece (also in en passant), but the piece doesn't move
+            for mv in &mut out_moves[first_move..] {
+                *mv = Move::new(mv.get_from(), mv.get_target(), mv.get_target(), MoveType::Capture, None);
+            }
+        } else if self.type_def.promotion_is_optional {
+            output_non_promotions(&self.type_def.promo_vals[self.player_num as usize], first_move, out_moves);
+        }
+    }
+    
+    /// Returns the square where a capture made by this piece takes place, which is the center of the explosion
+    /// if the piece explodes. This is the destination of the move, except when capturing by displacement.
+    pub fn get_capture_square(&self, mv: Move) -> BIndex {
+        if self.captures_by_displacement() { mv.get_target() } else { mv.get_to() }
+    }
+    
+    /// Returns `true` if the piece at index `from` can capture the piece at `target` by hopping over a screen
+    pub fn hop_attacks(&self, from: BIndex, target: BIndex, position: &Position) -> bool {
+        self.type_def.attack_hops.iter().any(|hop| {
+            hop_landing_squares(from, hop, position).is_some_and(|landing| landing.get_bit(target))
+        })
+    }
+    
+    /// Returns `true` if this piece captures by withdrawing or advancing, so its attack pattern is not used
+    pub fn captures_by_displacement(&self) -> bool {
+        self.type_def.capture_mode.captures_by_displacement()
+    }
+    
+    /// Returns `true` if this piece stays on its square when making the given move (rifle capture)
+    pub fn stays_when_making(&self, mv: Move) -> bool {
+        mv.is_capture() && self.type_def.capture_mode == CaptureMode::RifleStay
+    }
+    
+    // Returns the squares where this piece can promote when moving from the given index.
+    // If the promotion is optional, the piece can also promote when moving away from a promotion square.
+    #[inline]
+    fn promotion_squares_from<'a>(&'a self, index: BIndex, position: &'a Position) -> &'a Bitboard {
+        if self.type_def.promotion_is_optional && self.precomp.promotion_squares.get_bit(index) {
+            &position.dimensions.bounds
+        } else {
+            &self.precomp.promotion_squares
+        }
+    }
+    
+    /// Returns the number of squares that the piece at the given index can move to (with or without
+    /// capturing), without checking if the moves are legal
+    pub fn get_mobility(&self, index: BIndex, enemies: &Bitboard, occ_or_not_in_bounds: &Bitboard) -> u32 {
+        count_mobility(
+            &self.type_def,
+            index,
+            enemies,
+            occ_or_not_in_bounds,
+            &self.precomp.jump_bitboards_translate[index as usize],
+            &self.precomp.jump_bitboards_capture[index as usize]
+        )
+    }
+    /// Returns the number of squares that this piece can move to from the center of an empty board
+    pub fn get_center_mobility(&self) -> u32 {
+        self.center_mobility
     }
     
     /// Returns the piece definition for this piece type
@@ -321,8 +600,9 @@ impl Piece {
         let seed = (player as u64) << 32 | (piece_id as u64);
         let mut rng = StdRng::seed_from_u64(seed);
         
-        let mut zobrist = Vec::with_capacity(256);
-        for _ in 0..=255 {
+        // Generate the hand keys after the board keys, so that the board keys don't depend on them
+        let mut zobrist = Vec::with_capacity(2 * BOARD_SIZE);
+        for _ in 0..2 * BOARD_SIZE {
             zobrist.push(rng.gen::<ZobKey>());
         }
         zobrist
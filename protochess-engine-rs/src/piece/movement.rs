@@ -1,6 +1,6 @@
 use crate::utils::{to_index, from_index};
-use crate::{PieceDefinition, MoveGen, Position, PieceId};
-use crate::types::{Bitboard, Move, MoveType, BCoord, BIndex};
+use crate::{PieceDefinition, MoveGen, Position, PieceId, CaptureMode, HopMove, HopLanding, CastlingRule};
+use crate::types::{Bitboard, Move, MoveType, BCoord, BIndex, BOARD_WIDTH};
 
 
 /// Outputs all pseudo-legal translation (non-capture) moves for a piece at a given index
@@ -12,7 +12,7 @@ pub fn output_translations(
     enemies: &Bitboard,
     promotion_squares: &Bitboard,
     occ_or_not_in_bounds: &Bitboard,
-    can_castle: bool,
+    castling_rules: &[CastlingRule],
     double_jump_squares: &Bitboard,
     jumps_bitboard: &[Bitboard],
     promotions: &[PieceId],
@@ -44,11 +44,12 @@ pub fn output_translations(
     let jump_moves = &jumps_bitboard[index as usize] & !&position.occ_or_out_bounds;
     // Output double jump moves
     if double_jump_squares.get_bit(index) {
-        let mut jump_moves_copy = jump_moves.clone();
-        while let Some(new_index) = jump_moves_copy.lowest_one() {
-            let double_jump_moves = &jumps_bitboard[new_index as usize] & !&position.occ_or_out_bounds;
+        for new_index in &jump_moves {
+            let mut double_jump_moves = &jumps_bitboard[new_index as usize] & !&position.occ_or_out_bounds;
+            if let Some(steps) = movement.double_jump_steps {
+                double_jump_moves |= long_double_jumps(index, new_index, steps, position, promotion_squares);
+            }
             self::flatten_bb_moves_doublejump(double_jump_moves, index, new_index, promotion_squares, double_jump_squares, promotions, out_moves);
-            jump_moves_copy.clear_bit(new_index);
         }
     }
     // Flatten regular jump moves
@@ -61,7 +62,7 @@ pub fn output_translations(
     for run in &movement.translate_sliding_deltas {
         for (dx, dy) in run {
             let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
-            if x2 < 0 || y2 < 0 || x2 > 15 || y2 > 15 {
+            if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
                 break;
             }
             let to = to_index(x2 as BCoord, y2 as BCoord);
@@ -72,7 +73,7 @@ pub fn output_translations(
             if promotion_squares.get_bit(to) {
                 //Add all the promotion moves
                 for c in promotions {
-                    out_moves.push(Move::new(index, to, 0, MoveType::Quiet, Some(*c)));
+                    out_moves.push(Move::new(index, to, 0, MoveType::Promotion, Some(*c)));
                 }
             } else {
                 out_moves.push(Move::new(index, to, 0, MoveType::Quiet, None));
@@ -80,71 +81,66 @@ pub fn output_translations(
         }
     }
     
+    // HOPS
+    
+    for hop in &movement.translate_hops {
+        if let Some(mut landing) = hop_landing_squares(index, hop, position) {
+            landing &= !&position.occ_or_out_bounds;
+            self::flatten_bb_moves(enemies, landing, index, promotion_squares, promotions, out_moves);
+        }
+    }
+    
     // CASTLING
     
-    if can_castle {
-        // Able to castle, check if there is a rook on the correct square
-        let (_, ky) = from_index(index);
-        let rank_visibility = attack_tables.get_rank_slide(index, &position.occ_or_out_bounds);
-        
-        // King side
-        // Index of the closest piece to the east
-        let kingside_rook_index = rank_visibility.highest_one().unwrap();
-        if let Some(rook) = position.player_piece_at(position.whos_turn, kingside_rook_index) {
-            // There is a piece of the same player
-            if rook.is_rook() && rook.has_not_moved(kingside_rook_index) {
-                // Rook is in direct line of sight of the king and hasn't moved
-                let kingside_file = movement.castle_files.unwrap().1;
-                let to_index = to_index(kingside_file, ky);
-                // Check that the squares between x=(rook_x + 1) and x=6 (both included) are empty
-                let mut empty = true;
-                for i in (kingside_rook_index as i16 + 1)..=(to_index as i16) {
-                    if position.occ_or_out_bounds.get_bit(i as BIndex) {
-                        empty = false;
-                        break;
-                    }
-                }
-                // Check that the squares between x=5 and x=(king_x - 1) (both included) are empty
-                for i in (to_index as i16 - 1)..(index as i16) {
-                    if position.occ_or_out_bounds.get_bit(i as BIndex) {
-                        empty = false;
-                        break;
-                    }
-                }
-                if empty {
-                    out_moves.push(Move::new(index, to_index, kingside_rook_index, MoveType::KingsideCastle, None));
-                }
+    for rule in castling_rules {
+        // The partner is the closest piece in the direction of the rule
+        let (dx, dy) = rule.direction;
+        let (mut x, mut y) = from_index(index);
+        let partner_index = loop {
+            x = x.wrapping_add_signed(dx);
+            y = y.wrapping_add_signed(dy);
+            if !position.in_bounds(x, y) {
+                break None;
+            }
+            if position.occ_or_out_bounds.get_bit_at(x, y) {
+                break Some(to_index(x, y));
             }
+        };
+        let Some(partner_index) = partner_index else { continue };
+        let Some(partner) = position.player_piece_at(position.whos_turn, partner_index) else { continue };
+        if !partner.is_castling_partner(rule) || !partner.has_not_moved(partner_index) {
+            continue;
         }
-        // Queen side (same logic as kingside)
-        let queenside_rook_index = rank_visibility.lowest_one().unwrap();
-        if let Some(rook) = position.player_piece_at(position.whos_turn, queenside_rook_index) {
-            if rook.is_rook() && rook.has_not_moved(queenside_rook_index) {
-                let queenside_file = movement.castle_files.unwrap().0;
-                let to_index = to_index(queenside_file, ky);
-                // Check that the squares between x=2 and x=(rook_x - 1) (both included) are empty
-                let mut empty = true;
-                for i in (to_index as i16)..(queenside_rook_index as i16) {
-                    if position.occ_or_out_bounds.get_bit(i as BIndex) {
-                        empty = false;
-                        break;
-                    }
-                }
-                // Check that the squares between x=(king_x + 1) and x=3 (both included) are empty
-                for i in (index as i16 + 1)..=(to_index as i16 + 1) {
-                    if position.occ_or_out_bounds.get_bit(i as BIndex) {
-                        empty = false;
-                        break;
-                    }
-                }
-                if empty {
-                    out_moves.push(Move::new(index, to_index, queenside_rook_index, MoveType::QueensideCastle, None));
-                }
+        let to_index = castling_square(rule, index, rule.destination);
+        let partner_to = castling_square(rule, index, rule.partner_destination);
+        // All the squares crossed by both pieces (including the destinations) must be empty
+        let squares = [index, to_index, partner_index, partner_to];
+        let mut empty = true;
+        for line_index in castling_line(rule, *squares.iter().min().unwrap(), *squares.iter().max().unwrap()) {
+            if line_index != index && line_index != partner_index && position.occ_or_out_bounds.get_bit(line_index) {
+                empty = false;
+                break;
             }
         }
+        if empty {
+            let move_type = if dx + dy > 0 { MoveType::KingsideCastle } else { MoveType::QueensideCastle };
+            out_moves.push(Move::new(index, to_index, partner_index, move_type, None));
+        }
     }
 }
 
+/// Returns the square at the given coordinate in the line of a castling rule (the rank or file of `index`)
+pub fn castling_square(rule: &CastlingRule, index: BIndex, coord: BCoord) -> BIndex {
+    let (x, y) = from_index(index);
+    if rule.is_vertical() { to_index(x, coord) } else { to_index(coord, y) }
+}
+
+// Returns the squares between `first` and `last` (both included) in the line of a castling rule
+fn castling_line(rule: &CastlingRule, first: BIndex, last: BIndex) -> impl Iterator<Item=BIndex> {
+    let step = if rule.is_vertical() { BOARD_WIDTH as usize } else { 1 };
+    (first..=last).step_by(step)
+}
+
 
 
 /// Outputs all the pseudo-legal capture moves for a piece at a given index
@@ -188,10 +184,26 @@ pub fn output_captures(
     let jump_moves = jumps_bitboard & enemies;
     self::flatten_bb_moves(enemies, jump_moves, index, promotion_squares, promotions, out_moves);
     // En passant capture
-    if let Some(ep_square) = position.get_ep_square() {
-        if movement.can_double_jump() && jumps_bitboard.get_bit(ep_square) {
-            let target = position.get_ep_victim();
-            out_moves.push(Move::new(index, ep_square, target, MoveType::Capture, None));
+    if movement.can_double_jump() && position.has_ep_squares() {
+        let target = position.get_ep_victim();
+        // The EP squares and victim of a FEN are not validated, so they could be wrong
+        if enemies.get_bit(target) {
+            for ep_square in position.get_ep_squares() {
+                if jumps_bitboard.get_bit(ep_square) && !position.occ_or_out_bounds.get_bit(ep_square) {
+                    out_moves.push(Move::new(index, ep_square, target, MoveType::Capture, None));
+                }
+            }
+        }
+    }
+    
+    
+    // HOPS
+    
+    for hop in &movement.attack_hops {
+        if let Some(mut landing) = hop_landing_squares(index, hop, position) {
+            landing &= enemies;
+            landing &= &position.dimensions.bounds;
+            self::flatten_bb_moves(enemies, landing, index, promotion_squares, promotions, out_moves);
         }
     }
     
@@ -202,7 +214,7 @@ pub fn output_captures(
         for (dx, dy) in run {
 
             let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
-            if x2 < 0 || y2 < 0 || x2 > 15 || y2 > 15 {
+            if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
                 break;
             }
 
@@ -231,15 +243,154 @@ pub fn output_captures(
     }
 }
 
+/// Returns the squares where a hop from the given index can land (including the first occupied square after
+/// the screen, which can be captured), or `None` if there is no screen piece in that direction.
+/// Uses the sliding attack tables: the first blocker in the direction is the screen, and the squares after it
+/// are obtained by sliding again from the screen.
+pub fn hop_landing_squares(index: BIndex, hop: &HopMove, position: &Position) -> Option<Bitboard> {
+    let (dx, dy) = hop.direction;
+    let slide = |from: BIndex| MoveGen::attack_tables().get_sliding_moves_bb(
+        from,
+        &position.occ_or_out_bounds,
+        (dx, dy) == (0, 1),
+        (dx, dy) == (1, 0),
+        (dx, dy) == (0, -1),
+        (dx, dy) == (-1, 0),
+        (dx, dy) == (1, 1),
+        (dx, dy) == (-1, 1),
+        (dx, dy) == (1, -1),
+        (dx, dy) == (-1, -1)
+    );
+    // The screen must be a piece (not a wall or the edge of the board)
+    let screen = (slide(index) & &position.occ_or_out_bounds).lowest_one()?;
+    if !position.dimensions.bounds.get_bit(screen) {
+        return None;
+    }
+    let after_screen = slide(screen);
+    match hop.landing {
+        HopLanding::AnyAfterScreen => Some(after_screen),
+        HopLanding::ImmediatelyAfter => {
+            let (x, y) = from_index(screen);
+            let (x2, y2) = (x as i8 + dx, y as i8 + dy);
+            if x2 < 0 || y2 < 0 || !position.in_bounds(x2 as BCoord, y2 as BCoord) {
+                return None;
+            }
+            let mut landing = Bitboard::zero();
+            landing.set_bit_at(x2 as BCoord, y2 as BCoord);
+            Some(landing)
+        },
+    }
+}
+
+/// Returns the index of the piece captured by a piece that moves from `from` to `to` with a displacement
+/// capture mode: the square behind `from` when withdrawing, or the square after `to` when advancing.
+/// Returns `None` if the move is not in a straight line or the square is out of bounds.
+pub fn displacement_target(mode: CaptureMode, from: BIndex, to: BIndex, position: &Position) -> Option<BIndex> {
+    let (x1, y1) = from_index(from);
+    let (x2, y2) = from_index(to);
+    let (dx, dy) = (x2 as i16 - x1 as i16, y2 as i16 - y1 as i16);
+    if dx != 0 && dy != 0 && dx.abs() != dy.abs() {
+        return None;
+    }
+    let (x, y) = match mode {
+        CaptureMode::Withdraw => (x1 as i16 - dx.signum(), y1 as i16 - dy.signum()),
+        CaptureMode::Advance => (x2 as i16 + dx.signum(), y2 as i16 + dy.signum()),
+        CaptureMode::Replace | CaptureMode::RifleStay => return None,
+    };
+    if x < 0 || y < 0 || !position.in_bounds(x as BCoord, y as BCoord) {
+        return None;
+    }
+    Some(to_index(x as BCoord, y as BCoord))
+}
+
+/// Returns the number of squares that a piece at a given index can move to (with or without capturing), ignoring
+/// castling, double jumps, en passant and the legality of the moves
+pub fn count_mobility(
+    movement: &PieceDefinition,
+    index: BIndex,
+    enemies: &Bitboard,
+    occ_or_not_in_bounds: &Bitboard,
+    translate_jumps: &Bitboard,
+    capture_jumps: &Bitboard,
+) -> u32 {
+    let attack_tables = MoveGen::attack_tables();
+    let mut translations = attack_tables.get_sliding_moves_bb(
+        index,
+        occ_or_not_in_bounds,
+        movement.translate_north,
+        movement.translate_east,
+        movement.translate_south,
+        movement.translate_west,
+        movement.translate_northeast,
+        movement.translate_northwest,
+        movement.translate_southeast,
+        movement.translate_southwest
+    );
+    translations |= translate_jumps;
+    let mut captures = attack_tables.get_sliding_moves_bb(
+        index,
+        occ_or_not_in_bounds,
+        movement.attack_north,
+        movement.attack_east,
+        movement.attack_south,
+        movement.attack_west,
+        movement.attack_northeast,
+        movement.attack_northwest,
+        movement.attack_southeast,
+        movement.attack_southwest
+    );
+    captures |= capture_jumps;
+    
+    // Sliding deltas stop at the first occupied square, which can be captured
+    let (x, y) = from_index(index);
+    let add_sliding_deltas = |runs: &Vec<Vec<(i8, i8)>>, out: &mut Bitboard| {
+        for run in runs {
+            for (dx, dy) in run {
+                let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
+                if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
+                    break;
+                }
+                let to = to_index(x2 as BCoord, y2 as BCoord);
+                out.set_bit(to);
+                if occ_or_not_in_bounds.get_bit(to) {
+                    break;
+                }
+            }
+        }
+    };
+    add_sliding_deltas(&movement.translate_sliding_deltas, &mut translations);
+    add_sliding_deltas(&movement.attack_sliding_deltas, &mut captures);
+    
+    translations &= !occ_or_not_in_bounds;
+    captures &= enemies;
+    translations |= &captures;
+    translations.count_ones()
+}
+
+/// For pieces whose promotion is optional, adds a move without promotion for each promotion
+/// move in `out_moves[first_move..]`
+pub fn output_non_promotions(promotions: &[PieceId], first_move: usize, out_moves: &mut Vec<Move>) {
+    // Each destination has one promotion move for each promotion piece, only look at the first one
+    let first_promotion = promotions.first().copied();
+    let non_promotions: Vec<Move> = out_moves[first_move..].iter()
+        .filter(|mv| mv.is_promotion() && mv.get_promotion_piece() == first_promotion)
+        .map(|mv| {
+            let move_type = if mv.is_capture() { MoveType::Capture } else { MoveType::Quiet };
+            Move::new(mv.get_from(), mv.get_to(), mv.get_target(), move_type, None)
+        })
+        .collect();
+    out_moves.extend(non_promotions);
+}
+
 pub fn flatten_bb_moves(
     enemies: &Bitboard,
-    mut moves: Bitboard,
+    moves: Bitboard,
     from_index: BIndex,
     promotion_squares: &Bitboard,
     promotions: &[PieceId],
     out_moves: &mut Vec<Move>
 ) {
-    while let Some(to) = moves.lowest_one() {
+    for to in &moves {
         let promo_here = promotion_squares.get_bit(to);
         let capture_here = enemies.get_bit(to);
         let move_type = {
@@ -260,11 +411,34 @@ pub fn flatten_bb_moves(
             //No promotion chars left, go to next after this
             out_moves.push(Move::new(from_index, to, to, move_type, None));
         }
-        moves.clear_bit(to);
     }
 }
+// Returns the destinations of the double jumps longer than 2 steps, which repeat the first jump (from `index` to
+// `first_jump`) up to `max_steps` times. All the squares in the path must be empty, and it stops at promotion squares.
+fn long_double_jumps(index: BIndex, first_jump: BIndex, max_steps: u8, position: &Position, promotion_squares: &Bitboard) -> Bitboard {
+    let mut result = Bitboard::zero();
+    let (x, y) = from_index(index);
+    let (x1, y1) = from_index(first_jump);
+    let (dx, dy) = (x1 as i8 - x as i8, y1 as i8 - y as i8);
+    let (mut x2, mut y2) = (x1, y1);
+    for step in 2..=max_steps {
+        x2 = x2.wrapping_add_signed(dx);
+        y2 = y2.wrapping_add_signed(dy);
+        if !position.in_bounds(x2, y2) || position.occ_or_out_bounds.get_bit_at(x2, y2) {
+            break;
+        }
+        if step > 2 {
+            result.set_bit_at(x2, y2);
+        }
+        if promotion_squares.get_bit_at(x2, y2) {
+            break;
+        }
+    }
+    result
+}
+
 pub fn flatten_bb_moves_doublejump(
-    mut moves: Bitboard,
+    moves: Bitboard,
     from_index: BIndex,
     ep_square: BIndex,
     promotion_squares: &Bitboard,
@@ -272,7 +446,7 @@ pub fn flatten_bb_moves_doublejump(
     promotions: &[PieceId],
     out_moves: &mut Vec<Move>
 ) {
-    while let Some(to) = moves.lowest_one() {
+    for to in &moves {
         if promotion_squares.get_bit(to) {
             for promo_val in promotions {
                 out_moves.push(Move::new(from_index, to, 0, MoveType::Promotion, Some(*promo_val)));
@@ -283,6 +457,5 @@ pub fn flatten_bb_moves_doublejump(
         } else {
             out_moves.push(Move::new(from_index, to, ep_square, MoveType::DoubleJump, None));
         }
-        moves.clear_bit(to);
     }
 }
@@ -3,31 +3,159 @@ use crate::utils::debug::eq_anyorder;
 use super::PieceId;
 
 
+/// Decides how a piece removes the enemy pieces that it captures
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CaptureMode {
+    /// The piece moves to the square of the captured piece (standard chess)
+    #[default]
+    Replace,
+    /// The piece captures without moving, it stays on its square (Rifle chess)
+    RifleStay,
+    /// The piece captures an adjacent piece by moving in a straight line directly away from it (like the
+    /// Withdrawer in Ultima). The capture is not optional, and the attack pattern of the piece is ignored.
+    Withdraw,
+    /// The piece captures by moving in a straight line to the square in front of the captured piece (like
+    /// the Long Leaper approach in Fanorona). The capture is not optional, and the attack pattern of the piece is ignored.
+    Advance,
+}
+
+impl CaptureMode {
+    /// Returns `true` if the captured piece is next to the path of the capturing piece, instead of being
+    /// on the destination square (`Withdraw` and `Advance`)
+    pub fn captures_by_displacement(self) -> bool {
+        matches!(self, CaptureMode::Withdraw | CaptureMode::Advance)
+    }
+}
+
+
+/// Where a hopping piece lands after jumping over the screen piece
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum HopLanding {
+    /// Any square after the screen, up to the next piece (like the cannon in Xiangqi, which captures the
+    /// first piece after the screen)
+    #[default]
+    AnyAfterScreen,
+    /// Only the square immediately after the screen (like the Grasshopper)
+    ImmediatelyAfter,
+}
+
+/// A move in a straight line that must jump over exactly one piece (the screen)
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HopMove {
+    /// Direction of the move, one of the 8 main directions (e.g. `(0, 1)` for north or `(1, 1)` for northeast)
+    pub direction: (i8, i8),
+    pub landing: HopLanding,
+}
+
+
+/// A way in which a piece can castle with a partner piece of the same player. Both pieces must not have
+/// moved, the partner must be the first piece found from the castling piece in the given direction, and
+/// all the squares that both pieces cross must be empty.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CastlingRule {
+    /// Direction in which the partner is searched: `(1, 0)` (kingside), `(-1, 0)` (queenside), or `(0, 1)` and
+    /// `(0, -1)` for vertical castling. Vertical directions are mirrored for black, so `(0, 1)` is always forward.
+    pub direction: (i8, i8),
+    /// Coordinate where the castling piece ends: its file for horizontal castling, or its rank for
+    /// vertical castling (mirrored for black, like the direction)
+    pub destination: BCoord,
+    /// Coordinate where the partner ends, in the same format as `destination`
+    pub partner_destination: BCoord,
+    /// Ids of the pieces that can be used as partners. If empty, any piece with `is_castle_rook` can be used.
+    /// Partners must also have `is_castle_rook` set, so that they keep track of whether they have moved.
+    #[serde(default)]
+    pub partners: Vec<PieceId>,
+}
+
+impl CastlingRule {
+    /// Returns `true` if the castling piece and its partner are on the same file
+    pub fn is_vertical(&self) -> bool {
+        self.direction.0 == 0
+    }
+}
+
+
+/// Information for the frontends about how to show a piece. It's not used by the engine.
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PieceDisplay {
+    /// Long name of the piece (e.g. `"Amazon"`)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Unicode character of the white and the black piece (e.g. `"♘"` and `"♞"`)
+    #[serde(default)]
+    pub glyphs: [Option<String>; 2],
+    /// Name of the image used to draw the piece (e.g. `"knight"`), which the frontend maps to its own SVG files
+    #[serde(default)]
+    pub svg_hint: Option<String>,
+}
+
+
 /// External representation of a piece
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 #[must_use]
 pub struct PieceDefinition {
     // The id of this piece for white and black. None if this piece is not available for that color.
     pub ids: [Option<PieceId>; 2],
     // Override the id of the piece when printing the algebraic notation
     pub notation_prefix: [Option<String>; 2],
+    // Name and images of the piece, for the frontends
+    #[serde(default)]
+    pub display: Option<PieceDisplay>,
     
     pub is_leader: bool,
     // Either None (no castle) or (queenside, kingside) (files where this piece moves when castling)
     pub castle_files: Option<(BCoord, BCoord)>,
     // True if this piece works as a rook for castling purposes
     pub is_castle_rook: bool,
+    // Other ways in which this piece can castle, in addition to castle_files (e.g. vertical castling
+    // or castling with a specific partner)
+    #[serde(default)]
+    pub castling_rules: Vec<CastlingRule>,
     pub explode_on_capture: bool,
     pub explosion_deltas: Vec<(i8, i8)>,
     pub immune_to_explosion: bool,
+    // How the piece captures (by default, replacing the captured piece)
+    #[serde(default)]
+    pub capture_mode: CaptureMode,
+    // Moving this piece resets the halfmove clock, like pawns in standard chess. If not set, it's
+    // `true` for pieces that can promote or only move forward (see `resets_halfmove_clock()`).
+    #[serde(default)]
+    pub resets_halfmove_clock: Option<bool>,
+    // If set, the piece becomes immobile (cannot move or capture) after moving this many times
+    #[serde(default)]
+    pub max_moves: Option<u32>,
     
     // Places where this piece can promote, as well as PieceId for the promotion pieces on each side
     pub promotion_squares: Vec<(BCoord, BCoord)>,
     pub promo_vals: [Vec<PieceId>; 2],
+    // If true, the piece can also move to a promotion square without promoting, and it can promote when
+    // moving away from a promotion square (like in shogi)
+    #[serde(default)]
+    pub promotion_is_optional: bool,
+    // When this piece is captured and goes to the hand of the capturing player, it's replaced by this
+    // piece (like promoted pieces in shogi). The id can belong to either player.
+    #[serde(default)]
+    pub demotes_to: Option<PieceId>,
     
     // Places where this piece can double move
     pub double_jump_squares: Vec<(BCoord, BCoord)>,
+    // If set, the double move can repeat the first jump up to this many times (e.g. 3 squares forward for pawns
+    // on a big board), and all the squares passed over can be captured en passant. By default, it has 2 steps.
+    #[serde(default)]
+    pub double_jump_steps: Option<u8>,
 
     // Ways the piece can capture (but not move without capturing)
     pub attack_sliding_deltas: Vec<Vec<(i8, i8)>>,
@@ -40,6 +168,9 @@ pub struct PieceDefinition {
     pub attack_northwest: bool,
     pub attack_southeast: bool,
     pub attack_southwest: bool,
+    // Captures that jump over a screen piece
+    #[serde(default)]
+    pub attack_hops: Vec<HopMove>,
 
     //Ways the piece can move (but not capture)
     pub translate_jump_deltas: Vec<(i8, i8)>,
@@ -52,6 +183,9 @@ pub struct PieceDefinition {
     pub translate_northwest: bool,
     pub translate_southeast: bool,
     pub translate_southwest: bool,
+    // Moves that jump over a screen piece
+    #[serde(default)]
+    pub translate_hops: Vec<HopMove>,
     
     // Successfully moving the piece to a win square is an instant win
     pub win_squares: Vec<(BCoord, BCoord)>,
@@ -103,6 +237,9 @@ impl PieceDefinition {
     pub fn can_jump(&self) -> bool {
         !self.translate_jump_deltas.is_empty() || !self.attack_jump_deltas.is_empty()
     }
+    pub fn can_hop(&self) -> bool {
+        !self.translate_hops.is_empty() || !self.attack_hops.is_empty()
+    }
     pub fn can_double_jump(&self) -> bool {
         !self.double_jump_squares.is_empty()
     }
@@ -110,20 +247,56 @@ impl PieceDefinition {
         !self.translate_sliding_deltas.is_empty() || !self.attack_sliding_deltas.is_empty()
     }
     pub fn can_castle(&self) -> bool {
-        self.castle_files.is_some()
+        self.castle_files.is_some() || !self.castling_rules.is_empty()
+    }
+    /// Returns all the ways in which this piece can castle: the ones in `castling_rules`, and the standard
+    /// rules implied by `castle_files` (the partner ends next to the castling piece, on the inner side)
+    pub fn get_castling_rules(&self) -> Vec<CastlingRule> {
+        let mut rules = Vec::new();
+        if let Some((queenside, kingside)) = self.castle_files {
+            rules.push(CastlingRule { direction: (1, 0), destination: kingside, partner_destination: kingside.wrapping_sub(1), partners: vec![] });
+            rules.push(CastlingRule { direction: (-1, 0), destination: queenside, partner_destination: queenside + 1, partners: vec![] });
+        }
+        rules.extend(self.castling_rules.iter().cloned());
+        rules
+    }
+    /// Returns `true` if moving this piece resets the halfmove clock: the value of `resets_halfmove_clock`, or
+    /// if it's not set, whether the piece can promote or only moves forward (like pawns)
+    pub fn resets_halfmove_clock(&self) -> bool {
+        self.resets_halfmove_clock.unwrap_or_else(|| self.can_promote() || self.forward_direction().is_some())
+    }
+    /// If this piece can only move forward by jumping (like a pawn), returns the forward direction
+    /// (`1` for north, `-1` for south). Otherwise returns `None`.
+    pub fn forward_direction(&self) -> Option<i8> {
+        if self.can_slide_main_direction() || self.can_slide_north_indirectly() || self.can_slide_south_indirectly()
+        || self.has_sliding_deltas() || self.translate_jump_deltas.is_empty() {
+            return None;
+        }
+        let direction = self.translate_jump_deltas[0].1.signum();
+        let all_forward = self.translate_jump_deltas.iter().chain(&self.attack_jump_deltas)
+            .all(|(_dx, dy)| dy.signum() == direction);
+        if direction != 0 && all_forward { Some(direction) } else { None }
     }
     
     pub fn eq_ignore_order(&self, other: &PieceDefinition) -> bool {
         self.ids == other.ids &&
+        self.display == other.display &&
         self.is_leader == other.is_leader &&
         self.castle_files == other.castle_files &&
         self.is_castle_rook == other.is_castle_rook &&
+        eq_anyorder(&self.castling_rules, &other.castling_rules) &&
         self.explode_on_capture == other.explode_on_capture &&
         eq_anyorder(&self.explosion_deltas, &other.explosion_deltas) &&
         self.immune_to_explosion == other.immune_to_explosion &&
+        self.capture_mode == other.capture_mode &&
+        self.resets_halfmove_clock == other.resets_halfmove_clock &&
         eq_anyorder(&self.promotion_squares, &other.promotion_squares) &&
         eq_anyorder(&self.promo_vals, &other.promo_vals) &&
+        self.promotion_is_optional == other.promotion_is_optional &&
+        self.demotes_to == other.demotes_to &&
+        self.max_moves == other.max_moves &&
         eq_anyorder(&self.double_jump_squares, &other.double_jump_squares) &&
+        self.double_jump_steps == other.double_jump_steps &&
         eq_anyorder(&self.attack_sliding_deltas, &other.attack_sliding_deltas) &&
         eq_anyorder(&self.attack_jump_deltas, &other.attack_jump_deltas) &&
         self.attack_north == other.attack_north &&
@@ -134,6 +307,7 @@ impl PieceDefinition {
         self.attack_northwest == other.attack_northwest &&
         self.attack_southeast == other.attack_southeast &&
         self.attack_southwest == other.attack_southwest &&
+        eq_anyorder(&self.attack_hops, &other.attack_hops) &&
         eq_anyorder(&self.translate_jump_deltas, &other.translate_jump_deltas) &&
         eq_anyorder(&self.translate_sliding_deltas, &other.translate_sliding_deltas) &&
         self.translate_north == other.translate_north &&
@@ -144,6 +318,7 @@ impl PieceDefinition {
         self.translate_northwest == other.translate_northwest &&
         self.translate_southeast == other.translate_southeast &&
         self.translate_southwest == other.translate_southwest &&
+        eq_anyorder(&self.translate_hops, &other.translate_hops) &&
         eq_anyorder(&self.win_squares, &other.win_squares)
     }
 }
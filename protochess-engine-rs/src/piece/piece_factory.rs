@@ -1,4 +1,4 @@
-use super::PieceDefinition;
+use super::{PieceDefinition, PieceDisplay, CaptureMode};
 use crate::types::{GameMode, BCoord};
 
 pub struct PieceFactory {
@@ -52,15 +52,23 @@ impl PieceFactory {
         PieceDefinition {
             ids,
             notation_prefix: [Some("".to_string()), Some("".to_string())],
+            display: display("Pawn", Some(("♙", "♟"))),
             is_leader: false,
             castle_files: None,
             is_castle_rook: false,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: true,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: Some(true),
+            max_moves: None,
             promotion_squares,
             double_jump_squares,
+            double_jump_steps: None,
             promo_vals,
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![(-1, move_dir), (1, move_dir)],
             attack_north: false,
@@ -71,6 +79,7 @@ impl PieceFactory {
             attack_northwest: false,
             attack_southeast: false,
             attack_southwest: false,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![(0, move_dir)],
             translate_sliding_deltas: vec![],
             translate_north: false,
@@ -81,6 +90,7 @@ impl PieceFactory {
             translate_northwest: false,
             translate_southeast: false,
             translate_southwest: false,
+            translate_hops: Vec::new(),
             win_squares: vec![],
         }
     }
@@ -89,15 +99,23 @@ impl PieceFactory {
         PieceDefinition {
             ids: [Some('N'), Some('n')],
             notation_prefix: [Some("N".to_string()), Some("N".to_string())],
+            display: display("Knight", Some(("♘", "♞"))),
             is_leader: false,
             castle_files: None,
             is_castle_rook: false,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: false,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: None,
+            max_moves: None,
             promotion_squares: vec![],
             double_jump_squares: vec![],
+            double_jump_steps: None,
             promo_vals: [vec![], vec![]],
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![(1, 2), (1, -2), (-1, 2), (-1, -2), (2, 1), (2, -1), (-2, 1), (-2, -1)],
             attack_north: false,
@@ -108,6 +126,7 @@ impl PieceFactory {
             attack_northwest: false,
             attack_southeast: false,
             attack_southwest: false,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![(1, 2), (1, -2), (-1, 2), (-1, -2), (2, 1), (2, -1), (-2, 1), (-2, -1)],
             translate_sliding_deltas: vec![],
             translate_north: false,
@@ -118,6 +137,7 @@ impl PieceFactory {
             translate_northwest: false,
             translate_southeast: false,
             translate_southwest: false,
+            translate_hops: Vec::new(),
             win_squares: vec![],
         }
     }
@@ -126,15 +146,23 @@ impl PieceFactory {
         PieceDefinition {
             ids: [Some('B'), Some('b')],
             notation_prefix: [Some("B".to_string()), Some("B".to_string())],
+            display: display("Bishop", Some(("♗", "♝"))),
             is_leader: false,
             castle_files: None,
             is_castle_rook: false,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: false,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: None,
+            max_moves: None,
             promotion_squares: vec![],
             double_jump_squares: vec![],
+            double_jump_steps: None,
             promo_vals: [vec![], vec![]],
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![],
             attack_north: false,
@@ -145,6 +173,7 @@ impl PieceFactory {
             attack_northwest: true,
             attack_southeast: true,
             attack_southwest: true,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![],
             translate_sliding_deltas: vec![],
             translate_north: false,
@@ -155,6 +184,7 @@ impl PieceFactory {
             translate_northwest: true,
             translate_southeast: true,
             translate_southwest: true,
+            translate_hops: Vec::new(),
             win_squares: vec![],
         }
     }
@@ -163,15 +193,23 @@ impl PieceFactory {
         PieceDefinition {
             ids: [Some('R'), Some('r')],
             notation_prefix: [Some("R".to_string()), Some("R".to_string())],
+            display: display("Rook", Some(("♖", "♜"))),
             is_leader: false,
             castle_files: None,
             is_castle_rook: true,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: false,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: None,
+            max_moves: None,
             promotion_squares: vec![],
             double_jump_squares: vec![],
+            double_jump_steps: None,
             promo_vals: [vec![], vec![]],
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![],
             attack_north: true,
@@ -182,6 +220,7 @@ impl PieceFactory {
             attack_northwest: false,
             attack_southeast: false,
             attack_southwest: false,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![],
             translate_sliding_deltas: vec![],
             translate_north: true,
@@ -192,6 +231,7 @@ impl PieceFactory {
             translate_northwest: false,
             translate_southeast: false,
             translate_southwest: false,
+            translate_hops: Vec::new(),
             win_squares: vec![],
         }
     }
@@ -218,15 +258,24 @@ impl PieceFactory {
         PieceDefinition {
             ids,
             notation_prefix: [Some("K".to_string()), Some("K".to_string())],
-            is_leader: self.mode != GameMode::Antichess,
+            display: display("King", Some(("♔", "♚"))),
+            // In Dark Chess, the king is captured like any other piece (see `GlobalRules::for_mode()`)
+            is_leader: self.mode != GameMode::Antichess && self.mode != GameMode::DarkChess,
             castle_files: if can_castle { Some((2, 6)) } else { None },
             is_castle_rook: false,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: false,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: None,
+            max_moves: None,
             promotion_squares: vec![],
             double_jump_squares: vec![],
+            double_jump_steps: None,
             promo_vals: [vec![], vec![]],
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             attack_north: false,
@@ -237,6 +286,7 @@ impl PieceFactory {
             attack_northwest: false,
             attack_southeast: false,
             attack_southwest: false,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             translate_sliding_deltas: vec![],
             translate_north: false,
@@ -247,6 +297,7 @@ impl PieceFactory {
             translate_northwest: false,
             translate_southeast: false,
             translate_southwest: false,
+            translate_hops: Vec::new(),
             win_squares,
         }
     }
@@ -255,15 +306,23 @@ impl PieceFactory {
         PieceDefinition {
             ids: [Some('Q'), Some('q')],
             notation_prefix: [Some("Q".to_string()), Some("Q".to_string())],
+            display: display("Queen", Some(("♕", "♛"))),
             is_leader: false,
             castle_files: None,
             is_castle_rook: false,
+            castling_rules: Vec::new(),
             explode_on_capture: self.mode == GameMode::Atomic,
             explosion_deltas: vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)],
             immune_to_explosion: false,
+            capture_mode: CaptureMode::Replace,
+            resets_halfmove_clock: None,
+            max_moves: None,
             promotion_squares: vec![],
             double_jump_squares: vec![],
+            double_jump_steps: None,
             promo_vals: [vec![], vec![]],
+            promotion_is_optional: false,
+            demotes_to: None,
             attack_sliding_deltas: vec![],
             attack_jump_deltas: vec![],
             attack_north: true,
@@ -274,6 +333,7 @@ impl PieceFactory {
             attack_northwest: true,
             attack_southeast: true,
             attack_southwest: true,
+            attack_hops: Vec::new(),
             translate_jump_deltas: vec![],
             translate_sliding_deltas: vec![],
             translate_north: true,
@@ -284,9 +344,37 @@ impl PieceFactory {
             translate_northwest: true,
             translate_southeast: true,
             translate_southwest: true,
+            translate_hops: Vec::new(),
             win_squares: vec![],
         }
     }
+    
+    /// Bishop + knight (the archbishop of Capablanca chess)
+    pub fn make_archbishop(&self) -> PieceDefinition {
+        let knight_deltas = vec![(1, 2), (1, -2), (-1, 2), (-1, -2), (2, 1), (2, -1), (-2, 1), (-2, -1)];
+        PieceDefinition {
+            ids: [Some('A'), Some('a')],
+            notation_prefix: [Some("A".to_string()), Some("A".to_string())],
+            display: display("Archbishop", None),
+            attack_jump_deltas: knight_deltas.clone(),
+            translate_jump_deltas: knight_deltas,
+            ..self.make_bishop()
+        }
+    }
+    
+    /// Rook + knight (the chancellor of Capablanca chess)
+    pub fn make_chancellor(&self) -> PieceDefinition {
+        let knight_deltas = vec![(1, 2), (1, -2), (-1, 2), (-1, -2), (2, 1), (2, -1), (-2, 1), (-2, -1)];
+        PieceDefinition {
+            ids: [Some('C'), Some('c')],
+            notation_prefix: [Some("C".to_string()), Some("C".to_string())],
+            display: display("Chancellor", None),
+            is_castle_rook: false,
+            attack_jump_deltas: knight_deltas.clone(),
+            translate_jump_deltas: knight_deltas,
+            ..self.make_rook()
+        }
+    }
 }
 
 impl Default for PieceFactory {
@@ -294,3 +382,12 @@ impl Default for PieceFactory {
         PieceFactory { mode: GameMode::Standard }
     }
 }
+
+// Display information of a built-in piece
+fn display(name: &str, glyphs: Option<(&str, &str)>) -> Option<PieceDisplay> {
+    Some(PieceDisplay {
+        name: Some(name.to_string()),
+        glyphs: glyphs.map_or([None, None], |(white, black)| [Some(white.to_string()), Some(black.to_string())]),
+        svg_hint: Some(name.to_lowercase()),
+    })
+}
@@ -1,4 +1,6 @@
-use crate::types::{Centipawns, BIndex, Bitboard, BCoord, BDimensions};
+use std::convert::TryFrom;
+
+use crate::types::{Centipawns, BIndex, Bitboard, BCoord, BDimensions, BOARD_SIZE, BOARD_WIDTH};
 use crate::utils::{from_index, to_index, distance_to_one};
 use crate::{MoveGen, PieceDefinition};
 
@@ -7,17 +9,17 @@ const EDGE_DIST_MULT: Centipawns = 5;
 const PROMOTION_DIST_MULT: Centipawns = 7;
 const WIN_DIST_MULT: Centipawns = 50;
 
-/// Returns Vec of size 256, each with an integer representing # of moves possible at that location
+/// Returns Vec of size BOARD_SIZE, each with an integer representing # of moves possible at that location
 pub fn compute_piece_square_table(piece: &PieceDefinition, dims: &BDimensions, endgame: bool) -> Vec<Centipawns> {
-    let mut return_vec = Vec::with_capacity(256);
+    let mut return_vec = Vec::with_capacity(BOARD_SIZE);
     let center_squares_bb = get_center_squares(dims.width, dims.height);
     // Keep promotion squares in bounds
-    let promotion_squares_bb = Bitboard::from_coord_list(&piece.promotion_squares) & &dims.bounds;
+    let promotion_squares_bb = Bitboard::from_coords(&piece.promotion_squares) & &dims.bounds;
     // Keep win squares in bounds
-    let win_squares_bb = Bitboard::from_coord_list(&piece.win_squares) & &dims.bounds;
+    let win_squares_bb = Bitboard::from_coords(&piece.win_squares) & &dims.bounds;
     
     
-    for index in 0..=BIndex::MAX {
+    for index in (0..BOARD_SIZE).map(|i| i as BIndex) {
         // Absolute score (always positive)
         let mut abs_score = 0;
         // Invertible score (is subtracted from leader if not in endgame)
@@ -49,13 +51,14 @@ pub fn compute_piece_square_table(piece: &PieceDefinition, dims: &BDimensions, e
         // Extra points for being close to a win square
         abs_score += points_for_distance_to_one(x, y, piece, dims, &win_squares_bb, avg_board, WIN_DIST_MULT);
         
-        // Extra points for castling a leader
-        if piece.is_leader && !endgame && piece.can_castle() && (y == 0 || y == dims.height - 1) {
-            let (queenside_x, kingside_x) = piece.castle_files.unwrap();
-            if x == queenside_x || x == kingside_x {
-                abs_score += 40;
-            } else if x < queenside_x || x > kingside_x {
-                abs_score += 20;
+        // Extra points for castling a leader (only for the standard castle files)
+        if piece.is_leader && !endgame && (y == 0 || y == dims.height - 1) {
+            if let Some((queenside_x, kingside_x)) = piece.castle_files {
+                if x == queenside_x || x == kingside_x {
+                    abs_score += 40;
+                } else if x < queenside_x || x > kingside_x {
+                    abs_score += 20;
+                }
             }
         }
         
@@ -75,10 +78,10 @@ fn get_center_squares(width: BCoord, height: BCoord) -> Bitboard {
     // For odd dimensions, the center is the 9 squares in the middle
     let x1 = width / 2 - 1;
     let x2 = width / 2;
-    let x3 = (width + 1) / 2;
+    let x3 = width.div_ceil(2);
     let y1 = height / 2 - 1;
     let y2 = height / 2;
-    let y3 = (height + 1) / 2;
+    let y3 = height.div_ceil(2);
     
     center_squares.set_bit_at(x1, y1);
     center_squares.set_bit_at(x1, y2);
@@ -92,28 +95,40 @@ fn get_center_squares(width: BCoord, height: BCoord) -> Bitboard {
     center_squares
 }
 
+/// Returns Vec of size BOARD_SIZE with the minimum number of moves that the piece needs to reach one of its win
+/// squares from each square, on an otherwise empty board. `None` if it can't reach them (or it has no win squares).
+pub fn compute_win_distances(piece: &PieceDefinition, dims: &BDimensions) -> Vec<Option<u8>> {
+    let win_squares_bb = Bitboard::from_coords(&piece.win_squares) & &dims.bounds;
+    (0..BOARD_SIZE).map(|i| {
+        let (x, y) = from_index(i as BIndex);
+        if !dims.in_bounds(x, y) {
+            return None;
+        }
+        u8::try_from(distance_to_squares(x, y, piece, dims, &win_squares_bb)).ok()
+    }).collect()
+}
+
 fn points_for_distance_to_one(x_start: BCoord, y_start: BCoord, piece: &PieceDefinition, dims: &BDimensions,
     board: &Bitboard, dist_threshold: isize, points: Centipawns) -> Centipawns
 {
     if board.is_zero() {
         return 0;
     }
-    let get_neighbors = |x: BCoord, y: BCoord| {
-        let mut neighbors = Vec::new();
-        let mut moves = get_moves_on_empty_board(piece, to_index(x, y), dims, false);
-        // Get the coordinates of all the 1s in the bitboard
-        while let Some(index) = moves.lowest_one() {
-            neighbors.push(from_index(index));
-            moves.clear_bit(index);
-        }
-        neighbors
-    };
-    let distance = distance_to_one(x_start, y_start, board, get_neighbors);
+    let distance = distance_to_squares(x_start, y_start, piece, dims, board);
     // Extend promotion bonus until distance = dist_threshold
     let promotion_points = std::cmp::max(0, dist_threshold - distance) as Centipawns;
     promotion_points * points
 }
 
+// Number of moves to reach the nearest square of the board (isize::MAX if it can't be reached)
+fn distance_to_squares(x_start: BCoord, y_start: BCoord, piece: &PieceDefinition, dims: &BDimensions, board: &Bitboard) -> isize {
+    if board.is_zero() {
+        return isize::MAX;
+    }
+    let get_neighbors = |x: BCoord, y: BCoord| get_moves_on_empty_board(piece, to_index(x, y), dims, false).to_coords();
+    distance_to_one(x_start, y_start, board, get_neighbors)
+}
+
 
 /// Returns the number of moves of a piecetype on an otherwise empty board
 fn get_moves_on_empty_board(mp: &PieceDefinition, index: BIndex, dims: &BDimensions, include_attacks: bool) -> Bitboard {
@@ -146,7 +161,7 @@ fn get_moves_on_empty_board(mp: &PieceDefinition, index: BIndex, dims: &BDimensi
     };
     for (dx, dy) in jumps {
         let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
-        if x2 < 0 || y2 < 0 || x2 > 15 || y2 > 15 {
+        if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
             continue;
         }
 
@@ -166,7 +181,7 @@ fn get_moves_on_empty_board(mp: &PieceDefinition, index: BIndex, dims: &BDimensi
     for run in sliding_delta_groups {
         for (dx, dy) in run {
             let (x2, y2) = (x as i8 + *dx, y as i8 + *dy);
-            if x2 < 0 || y2 < 0 || x2 > 15 || y2 > 15 {
+            if x2 < 0 || y2 < 0 || x2 >= BOARD_WIDTH as i8 || y2 >= BOARD_WIDTH as i8 {
                 break;
             }
             let to = to_index(x2 as BCoord, y2 as BCoord);
@@ -1,4 +1,4 @@
-use crate::types::{Bitboard, BDimensions, BCoord};
+use crate::types::{Bitboard, BDimensions, BCoord, BIndex, BOARD_SIZE};
 use crate::PieceDefinition;
 use crate::utils::from_index;
 
@@ -11,6 +11,8 @@ pub struct PrecomputedPieceDef {
     pub double_jump_squares: Bitboard,
     // Positions at which the game is won instantly
     pub instant_win_squares: Bitboard,
+    // Positions at which this piece can be dropped from the hand
+    pub drop_squares: Bitboard,
     
     // Jump bitboards for this piece
     pub jump_bitboards_translate: Vec<Bitboard>,
@@ -18,25 +20,51 @@ pub struct PrecomputedPieceDef {
     
     // Explosion bitboards for this piece
     pub explosion_bitboards: Vec<Bitboard>,
+    
+    // Result of definition.forward_direction()
+    pub forward_direction: Option<i8>,
+    
+    // Result of definition.resets_halfmove_clock()
+    pub resets_halfmove_clock: bool,
 }
 
 impl From<(&PieceDefinition, &BDimensions)> for PrecomputedPieceDef {
     fn from((definition, dims): (&PieceDefinition, &BDimensions)) -> Self {
+        let promotion_squares = Bitboard::from_coords(&definition.promotion_squares) & &dims.bounds;
         PrecomputedPieceDef { 
-            promotion_squares: Bitboard::from_coord_list(&definition.promotion_squares) & &dims.bounds,
-            double_jump_squares: Bitboard::from_coord_list(&definition.double_jump_squares) & &dims.bounds,
-            instant_win_squares: Bitboard::from_coord_list(&definition.win_squares) & &dims.bounds,
+            drop_squares: Self::precompute_drop_squares(definition, dims, &promotion_squares),
+            promotion_squares,
+            double_jump_squares: Bitboard::from_coords(&definition.double_jump_squares) & &dims.bounds,
+            instant_win_squares: Bitboard::from_coords(&definition.win_squares) & &dims.bounds,
             jump_bitboards_translate: Self::precompute_jumps(&definition.translate_jump_deltas, dims),
             jump_bitboards_capture: Self::precompute_jumps(&definition.attack_jump_deltas, dims),
             explosion_bitboards: Self::precompute_jumps(&definition.explosion_deltas, dims),
+            forward_direction: definition.forward_direction(),
+            resets_halfmove_clock: definition.resets_halfmove_clock(),
         }
     }
 }
 
 impl PrecomputedPieceDef {
+    // Pieces that must promote cannot be dropped on a promotion square, nor on the first rank if they only move forward
+    // (like pawns in crazyhouse). Pieces whose promotion is optional can be dropped anywhere.
+    fn precompute_drop_squares(definition: &PieceDefinition, dims: &BDimensions, promotion_squares: &Bitboard) -> Bitboard {
+        if promotion_squares.is_zero() || definition.promotion_is_optional {
+            return dims.bounds.clone();
+        }
+        let mut drop_squares = &dims.bounds & !promotion_squares;
+        if let Some(direction) = definition.forward_direction() {
+            let first_rank = if direction > 0 { 0 } else { dims.height - 1 };
+            for x in 0..dims.width {
+                drop_squares.clear_bit_at(x, first_rank);
+            }
+        }
+        drop_squares
+    }
+    
     fn precompute_jumps(deltas: &Vec<(i8, i8)>, dims: &BDimensions) -> Vec<Bitboard> {
-        let mut jumps = Vec::with_capacity(256);
-        for index in 0..=255 {
+        let mut jumps = Vec::with_capacity(BOARD_SIZE);
+        for index in (0..BOARD_SIZE).map(|i| i as BIndex) {
             let mut jump = Bitboard::zero();
             let (x, y) = from_index(index);
             for (dx, dy) in deltas {
@@ -0,0 +1,149 @@
+// Checks that a piece definition is consistent before registering it
+
+use std::fmt;
+
+use crate::types::{BCoord, BDimensions, Player};
+
+use super::PieceDefinition;
+
+/// Problem found in a `PieceDefinition` by `PieceDefinition::validate()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The piece has promotion squares but no promotion pieces for the player, or vice versa
+    PromotionMismatch { player: Player },
+    /// The piece only moves forward, and all the promotion squares are on the rank where it can never arrive
+    UnreachablePromotion,
+    /// One of the squares of the definition is outside the board. `field` is the name of the field
+    SquareOutOfBounds { field: &'static str, square: (BCoord, BCoord) },
+    /// A castling destination (of the piece or its partner) is outside the board
+    CastlingOutOfBounds { destination: BCoord },
+    /// A castling direction is not one of the 4 orthogonal directions
+    InvalidCastlingDirection { direction: (i8, i8) },
+    /// There is more than one castling rule in the same direction
+    DuplicateCastlingDirection { direction: (i8, i8) },
+    /// Both pieces of a castling rule end on the same square
+    CastlingSameSquare { direction: (i8, i8) },
+    /// A hop direction is not one of the 8 main directions
+    InvalidHopDirection { direction: (i8, i8) },
+    /// The explosion deltas include `(0, 0)`, but the exploding piece is always removed
+    ExplosionOnOwnSquare,
+    /// `max_moves` is 0, frozen pieces should be used instead
+    ZeroMaxMoves,
+    /// The number of double jump steps is not between 2 and 64
+    InvalidDoubleJumpSteps { steps: u8 },
+    /// The piece cannot move, capture or castle
+    NoMovement,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::PromotionMismatch { player } => {
+                let player = if *player == 0 { "white" } else { "black" };
+                write!(f, "promotion squares and pieces must be specified together (for {player})")
+            },
+            ValidationError::UnreachablePromotion => write!(f, "the piece only moves forward, so it can never reach its promotion squares"),
+            ValidationError::SquareOutOfBounds { field, square: (x, y) } => write!(f, "the square ({x}, {y}) of {field} is out of bounds"),
+            ValidationError::CastlingOutOfBounds { destination } => write!(f, "the castling destination {destination} is out of bounds"),
+            ValidationError::InvalidCastlingDirection { direction: (dx, dy) } =>
+                write!(f, "invalid castling direction ({dx}, {dy}), it must be one of the 4 orthogonal directions"),
+            ValidationError::DuplicateCastlingDirection { direction: (dx, dy) } =>
+                write!(f, "more than one castling rule in the direction ({dx}, {dy})"),
+            ValidationError::CastlingSameSquare { direction: (dx, dy) } =>
+                write!(f, "both pieces end on the same square when castling in the direction ({dx}, {dy})"),
+            ValidationError::InvalidHopDirection { direction: (dx, dy) } =>
+                write!(f, "invalid hop direction ({dx}, {dy}), it must be one of the 8 main directions"),
+            ValidationError::ExplosionOnOwnSquare => write!(f, "the explosion deltas cannot contain (0, 0)"),
+            ValidationError::ZeroMaxMoves => write!(f, "max_moves is 0, use frozen pieces instead"),
+            ValidationError::InvalidDoubleJumpSteps { steps } =>
+                write!(f, "invalid number of double jump steps ({steps}), it must be between 2 and 64"),
+            ValidationError::NoMovement => write!(f, "the piece cannot move, capture or castle"),
+        }
+    }
+}
+
+impl PieceDefinition {
+    /// Finds the contradictory or impossible settings of the definition, on a board of the given dimensions.
+    /// Returns all the problems found, or `Ok` if there are none.
+    pub fn validate(&self, dims: &BDimensions) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+        self.validate_promotions(dims, &mut errors);
+        self.validate_castling(dims, &mut errors);
+
+        let on_board = |&(x, y): &(BCoord, BCoord)| x < dims.width && y < dims.height;
+        for (field, squares) in [("double_jump_squares", &self.double_jump_squares), ("win_squares", &self.win_squares)] {
+            for square in squares.iter().filter(|sq| !on_board(sq)) {
+                errors.push(ValidationError::SquareOutOfBounds { field, square: *square });
+            }
+        }
+        for hop in self.translate_hops.iter().chain(&self.attack_hops) {
+            let (dx, dy) = hop.direction;
+            if dx.abs() > 1 || dy.abs() > 1 || (dx, dy) == (0, 0) {
+                errors.push(ValidationError::InvalidHopDirection { direction: hop.direction });
+            }
+        }
+        if self.explosion_deltas.contains(&(0, 0)) {
+            errors.push(ValidationError::ExplosionOnOwnSquare);
+        }
+        if self.max_moves == Some(0) {
+            errors.push(ValidationError::ZeroMaxMoves);
+        }
+        if let Some(steps) = self.double_jump_steps {
+            if !(2..=64).contains(&steps) {
+                errors.push(ValidationError::InvalidDoubleJumpSteps { steps });
+            }
+        }
+        let can_attack = self.attack_north || self.attack_south || self.attack_east || self.attack_west ||
+            self.attack_northeast || self.attack_northwest || self.attack_southeast || self.attack_southwest ||
+            !self.attack_jump_deltas.is_empty() || !self.attack_sliding_deltas.is_empty();
+        if !can_attack && !self.can_slide_main_direction() && !self.can_slide_north_indirectly() &&
+            !self.can_slide_south_indirectly() && !self.can_jump() && !self.can_hop() && !self.has_sliding_deltas() && !self.can_castle()
+        {
+            errors.push(ValidationError::NoMovement);
+        }
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+
+    fn validate_promotions(&self, dims: &BDimensions, errors: &mut Vec<ValidationError>) {
+        for (player, id) in self.ids.iter().enumerate() {
+            if id.is_some() && self.promotion_squares.is_empty() != self.promo_vals[player].is_empty() {
+                errors.push(ValidationError::PromotionMismatch { player: player as Player });
+            }
+        }
+        for &(x, y) in &self.promotion_squares {
+            if x >= dims.width || y >= dims.height {
+                errors.push(ValidationError::SquareOutOfBounds { field: "promotion_squares", square: (x, y) });
+            }
+        }
+        // A piece that only moves forward can never go back to the first rank (in its direction)
+        if let Some(direction) = self.forward_direction() {
+            let first_rank = if direction > 0 { 0 } else { dims.height - 1 };
+            if !self.promotion_squares.is_empty() && self.promotion_squares.iter().all(|&(_, y)| y == first_rank) {
+                errors.push(ValidationError::UnreachablePromotion);
+            }
+        }
+    }
+
+    fn validate_castling(&self, dims: &BDimensions, errors: &mut Vec<ValidationError>) {
+        let castling_rules = self.get_castling_rules();
+        for (i, rule) in castling_rules.iter().enumerate() {
+            let direction = rule.direction;
+            if direction.0.abs() + direction.1.abs() != 1 {
+                errors.push(ValidationError::InvalidCastlingDirection { direction });
+                continue;
+            }
+            if castling_rules[..i].iter().any(|other| other.direction == direction) {
+                errors.push(ValidationError::DuplicateCastlingDirection { direction });
+            }
+            if rule.destination == rule.partner_destination {
+                errors.push(ValidationError::CastlingSameSquare { direction });
+            }
+            let size = if rule.is_vertical() { dims.height } else { dims.width };
+            for destination in [rule.destination, rule.partner_destination] {
+                if destination >= size {
+                    errors.push(ValidationError::CastlingOutOfBounds { destination });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,81 @@
+// Recomputes the incrementally updated state of a position from scratch, to find bugs in make_move()
+// and unmake_move()
+
+use crate::{wrap_res, err_assert};
+use crate::types::{BIndex, Player, ZobKey, BOARD_SIZE};
+use crate::utils::to_index;
+
+use super::Position;
+use super::make_move::{turn_zobrist, immobile_zobrist, wall_zobrist};
+
+impl Position {
+    /// Computes the zobrist key of the position from scratch. It must always be equal to `get_zobrist()`.
+    pub fn compute_zobrist(&self) -> ZobKey {
+        let props = self.get_properties();
+        let mut zob = 0;
+        for ps in &self.pieces {
+            for piece in ps.iter() {
+                for index in piece.get_bitboard() {
+                    zob ^= piece.get_zobrist(index);
+                    if piece.has_not_moved(index) {
+                        zob ^= piece.get_castle_zobrist(index);
+                    }
+                }
+                zob ^= piece.get_hand_zobrist(piece.get_num_in_hand());
+            }
+        }
+        for x in 0..self.dimensions.width {
+            for y in 0..self.dimensions.height {
+                if !self.dimensions.in_bounds(x, y) {
+                    zob ^= wall_zobrist(to_index(x, y));
+                }
+            }
+        }
+        for index in &self.immobile {
+            zob ^= immobile_zobrist(index);
+        }
+        for player in 0..2 {
+            // Each check toggles the bits of the new number of checks (see `increment_num_checks()`)
+            for checks in 1..=props.times_in_check[player] {
+                zob ^= (checks as ZobKey) << (8 + 2 * player);
+            }
+            zob ^= (props.pieces_captured[player] as ZobKey) << (16 + 8 * player);
+        }
+        zob ^= props.ep_zobrist();
+        zob ^= turn_zobrist(props.turn_move, self.global_rules.turn_length(props.turn_number));
+        zob ^= self.whos_turn as ZobKey;
+        zob
+    }
+
+    // Replaces the zobrist key of the current position with the one computed from scratch
+    pub(super) fn reset_zobrist(&mut self) {
+        let zobrist = self.compute_zobrist();
+        self.properties_stack.last_mut().unwrap().zobrist_key = zobrist;
+    }
+
+    /// Checks that the incrementally updated zobrist key, occupied bitboards, material counters and move counts
+    /// are equal to the values computed from scratch. Returns an error describing the first difference.
+    pub fn check_consistency(&self) -> wrap_res!() {
+        let zobrist = self.compute_zobrist();
+        err_assert!(self.get_zobrist() == zobrist, "Zobrist key is {:x}, but it should be {:x}", self.get_zobrist(), zobrist);
+
+        let mut occupied = !&self.dimensions.bounds;
+        for ps in &self.pieces {
+            ps.check_consistency()?;
+            occupied |= ps.get_occupied();
+        }
+        err_assert!(self.occ_or_out_bounds == occupied, "The occupied bitboard doesn't match the pieces on the board");
+        err_assert!((self.pieces[0].get_occupied() & self.pieces[1].get_occupied()).is_zero(), "Both players have a piece on the same square");
+        for index in 0..BOARD_SIZE {
+            let owner = self.pieces.iter().position(|ps| ps.index_has_piece(index as BIndex)).map(|owner| owner as Player);
+            err_assert!(self.owner_at(index as BIndex) == owner, "The owner of square {} is {:?}, but it should be {:?}", index, self.owner_at(index as BIndex), owner);
+        }
+
+        let empty = !&occupied;
+        err_assert!((&self.immobile & &empty).is_zero(), "An empty square is marked as immobile");
+        for (index, &count) in self.move_counts.iter().enumerate() {
+            err_assert!(count == 0 || !empty.get_bit(index as BIndex), "The empty square {} has a move count of {}", index, count);
+        }
+        Ok(())
+    }
+}
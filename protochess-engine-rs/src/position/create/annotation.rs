@@ -0,0 +1,90 @@
+// Analysis of the moves of a game, to find the mistakes of each player
+
+use crate::{MoveGen, MoveInfo, MakeMoveResultFlag, wrap_res, err, err_assert};
+use crate::searcher::{Searcher, SearchHandle};
+use crate::types::{Centipawns, Depth, MoveAnnotation, MoveClassification};
+
+use super::game_state::GameState;
+use super::position_factory::PositionFactory;
+
+// Minimum number of centipawns lost (compared to the best move) by each classification
+const INACCURACY_LOSS: Centipawns = 50;
+const MISTAKE_LOSS: Centipawns = 100;
+const BLUNDER_LOSS: Centipawns = 300;
+
+impl PositionFactory {
+    /// Searches each position of the current game (up to the current position) with the given depth, and compares
+    /// the played move with the best move. Returns one annotation for each move of the history.
+    /// Each move requires up to 2 searches, so this is slow.
+    pub fn annotate_history(&self, depth: Depth) -> wrap_res!(Vec<MoveAnnotation>) {
+        self.annotate_history_with(depth, 1, &SearchHandle::default())
+    }
+
+    /// Same as `annotate_history()`, using `num_threads` threads for the searches. If the search is stopped with
+    /// the handle, only the moves that have been analyzed are returned.
+    pub fn annotate_history_with(&self, depth: Depth, num_threads: u32, handle: &SearchHandle) -> wrap_res!(Vec<MoveAnnotation>) {
+        err_assert!(depth != 0, "Depth must be greater than 0");
+        let state = self.get_state();
+        // Replay the game from the initial position
+        let initial = GameState { move_history: Vec::new(), ..state.clone() };
+        let mut position = match PositionFactory::default().set_state(initial, None)? {
+            Some(position) => position,
+            None => err!("Could not create the initial position of the game"),
+        };
+        let mut annotations = Vec::with_capacity(state.move_history.len());
+        for (mv, notation) in state.move_history.iter().zip(self.get_notation()) {
+            if handle.stop_requested() {
+                break;
+            }
+            let played = match MoveGen::get_legal_moves(&mut position).into_iter().find(|m| mv == m) {
+                Some(played) => played,
+                None => err!("Invalid move: {}", mv),
+            };
+            let (best_line, best_score, score, refutation) = Searcher::compare_move(&position, played, depth, num_threads, handle);
+            // The search was interrupted, the scores are not reliable
+            if handle.stop_requested() {
+                break;
+            }
+            let best_move = match best_line.first() {
+                Some(best_move) => MoveInfo::from(*best_move),
+                None => err!("No moves found"),
+            };
+            // Play the best move to get its full notation (including check and checkmate indicators)
+            let result = position.pub_make_move(&best_move);
+            position.unmake_move();
+            let best_notation = result.move_notation.unwrap_or_default();
+
+            let classification = if played == best_line[0] {
+                MoveClassification::Best
+            } else {
+                classify(best_score.saturating_sub(score))
+            };
+            annotations.push(MoveAnnotation {
+                mv: *mv,
+                notation: notation.clone(),
+                best_score,
+                score,
+                best_line: best_line.into_iter().map(Into::into).collect(),
+                best_notation,
+                refutation: refutation.into_iter().map(Into::into).collect(),
+                classification,
+            });
+            let result = position.pub_make_move(mv);
+            err_assert!(result.flag != MakeMoveResultFlag::IllegalMove, "Invalid move: {}", mv);
+        }
+        Ok(annotations)
+    }
+}
+
+// Classification of a move that is not the best one, from the centipawns that it loses
+fn classify(loss: Centipawns) -> MoveClassification {
+    if loss >= BLUNDER_LOSS {
+        MoveClassification::Blunder
+    } else if loss >= MISTAKE_LOSS {
+        MoveClassification::Mistake
+    } else if loss >= INACCURACY_LOSS {
+        MoveClassification::Inaccuracy
+    } else {
+        MoveClassification::Good
+    }
+}
@@ -0,0 +1,210 @@
+// Compact binary format of a game state, see `GameState::to_bytes()`
+
+use std::convert::TryFrom;
+
+use crate::{GameState, GameTermination, InitialState, MoveInfo, VariantRegistry, wrap_res, err, err_assert};
+
+// Version of the format, stored in the first byte
+const BINARY_VERSION: u8 = 1;
+// Flags stored in the second byte
+const BUILTIN_VARIANT: u8 = 1;
+const HAS_INITIAL_FEN: u8 = 2;
+const HAS_PRIOR_FENS: u8 = 4;
+const HAS_TERMINATION: u8 = 8;
+const KNOWN_FLAGS: u8 = BUILTIN_VARIANT | HAS_INITIAL_FEN | HAS_PRIOR_FENS | HAS_TERMINATION;
+
+impl GameState {
+    /// Converts the game state to a compact binary format, to be sent over the network or stored in a URL (after
+    /// encoding it in base64). Built-in variants are stored by name, and each move takes 3 to 6 bytes.
+    /// To send a `Position`, send the `GameState` that leads to it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.encode(true)
+    }
+    
+    // If `builtin_by_name` is false, built-in variants are stored like any other variant
+    fn encode(&self, builtin_by_name: bool) -> Vec<u8> {
+        let builtin = if builtin_by_name { VariantRegistry::builtin_name_of(&self.initial_state) } else { None };
+        let mut flags = 0;
+        if builtin.is_some() { flags |= BUILTIN_VARIANT; }
+        if self.initial_fen.is_some() { flags |= HAS_INITIAL_FEN; }
+        if !self.prior_fens.is_empty() { flags |= HAS_PRIOR_FENS; }
+        if self.termination.is_some() { flags |= HAS_TERMINATION; }
+        let mut bytes = vec![BINARY_VERSION, flags];
+        match builtin {
+            Some(name) => write_bytes(&mut bytes, name.as_bytes()),
+            None => write_bytes(&mut bytes, &serde_json::to_vec(&self.initial_state).unwrap()),
+        }
+        if let Some(fen) = &self.initial_fen {
+            write_bytes(&mut bytes, fen.as_bytes());
+        }
+        if !self.prior_fens.is_empty() {
+            write_varint(&mut bytes, self.prior_fens.len() as u32);
+            for fen in &self.prior_fens {
+                write_bytes(&mut bytes, fen.as_bytes());
+            }
+        }
+        if let Some(termination) = self.termination {
+            bytes.extend_from_slice(&termination_code(termination));
+        }
+        write_varint(&mut bytes, self.move_history.len() as u32);
+        for mv in &self.move_history {
+            write_varint(&mut bytes, square_code(mv.from));
+            write_varint(&mut bytes, square_code(mv.to));
+            write_varint(&mut bytes, mv.promotion.map_or(0, |id| id as u32 + 1));
+        }
+        bytes
+    }
+    
+    /// Loads a game state written by `to_bytes()`
+    pub fn from_bytes(bytes: &[u8]) -> wrap_res!(Self) {
+        let mut reader = Reader { bytes, pos: 0 };
+        let version = reader.byte()?;
+        err_assert!(version == BINARY_VERSION, "Unsupported version of the binary game state: {} (expected {})", version, BINARY_VERSION);
+        let flags = reader.byte()?;
+        err_assert!(flags & !KNOWN_FLAGS == 0, "Unknown flags in the binary game state: {:#04x}", flags & !KNOWN_FLAGS);
+        let variant = reader.bytes()?;
+        let initial_state = if flags & BUILTIN_VARIANT != 0 {
+            let name = String::from_utf8_lossy(variant);
+            let state = VariantRegistry::get(&name)?;
+            err_assert!(VariantRegistry::builtin_name_of(&state).is_some(), "'{}' is not a built-in variant", name);
+            state
+        } else {
+            match serde_json::from_slice::<InitialState>(variant) {
+                Ok(state) => state,
+                Err(e) => err!("Invalid variant in the binary game state: {}", e),
+            }
+        };
+        let initial_fen = if flags & HAS_INITIAL_FEN != 0 {
+            Some(reader.string("Invalid FEN in the binary game state")?)
+        } else {
+            None
+        };
+        let mut prior_fens = Vec::new();
+        if flags & HAS_PRIOR_FENS != 0 {
+            for _ in 0..reader.varint()? {
+                prior_fens.push(reader.string("Invalid prior FEN in the binary game state")?);
+            }
+        }
+        let termination = if flags & HAS_TERMINATION != 0 {
+            Some(termination_from_code([reader.byte()?, reader.byte()?])?)
+        } else {
+            None
+        };
+        let num_moves = reader.varint()?;
+        let mut move_history = Vec::new();
+        for _ in 0..num_moves {
+            let from = square_from_code(reader.varint()?)?;
+            let to = square_from_code(reader.varint()?)?;
+            let promotion = match reader.varint()? {
+                0 => None,
+                code => match char::from_u32(code - 1) {
+                    Some(id) => Some(id),
+                    None => err!("Invalid piece id in the binary game state"),
+                },
+            };
+            move_history.push(MoveInfo { from, to, promotion });
+        }
+        err_assert!(reader.pos == bytes.len(), "Unexpected data at the end of the binary game state");
+        Ok(GameState { initial_state, initial_fen, move_history, prior_fens, termination })
+    }
+    
+    /// Returns a hash of the content of the game state, which is the same in all platforms. Used to find duplicated
+    /// variants or games. Built-in variants are hashed by their rules, not by their name, so a custom variant with
+    /// the same rules as a built-in one has the same hash. The hash can change between versions of the engine.
+    pub fn content_hash(&self) -> u64 {
+        // 64-bit FNV-1a
+        self.encode(false).iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+    }
+}
+
+// Squares are stored as a single number, so that the squares of the first ranks take 1 byte
+fn square_code((x, y): (u8, u8)) -> u32 {
+    x as u32 + 32 * y as u32
+}
+
+fn square_from_code(code: u32) -> wrap_res!((u8, u8)) {
+    let (x, y) = (code % 32, code / 32);
+    match u8::try_from(y) {
+        Ok(y) => Ok((x as u8, y)),
+        Err(_) => err!("Invalid square in the binary game state"),
+    }
+}
+
+// The termination is stored as 2 bytes: its type and the player (2 if there is no player)
+fn termination_code(termination: GameTermination) -> [u8; 2] {
+    match termination {
+        GameTermination::Resignation { player } => [0, player],
+        GameTermination::DrawAgreement => [1, 2],
+        GameTermination::Adjudication { winner } => [2, winner.unwrap_or(2)],
+    }
+}
+
+fn termination_from_code(code: [u8; 2]) -> wrap_res!(GameTermination) {
+    match code {
+        [0, player @ 0..=1] => Ok(GameTermination::Resignation { player }),
+        [1, 2] => Ok(GameTermination::DrawAgreement),
+        [2, winner @ 0..=1] => Ok(GameTermination::Adjudication { winner: Some(winner) }),
+        [2, 2] => Ok(GameTermination::Adjudication { winner: None }),
+        _ => err!("Invalid termination in the binary game state"),
+    }
+}
+
+// Unsigned LEB128: 7 bits per byte, the highest bit is set in all the bytes except the last one
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+// Writes the length of the data, followed by the data
+fn write_bytes(out: &mut Vec<u8>, data: &[u8]) {
+    write_varint(out, data.len() as u32);
+    out.extend_from_slice(data);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn byte(&mut self) -> wrap_res!(u8) {
+        match self.bytes.get(self.pos) {
+            Some(&byte) => {
+                self.pos += 1;
+                Ok(byte)
+            },
+            None => err!("Unexpected end of the binary game state"),
+        }
+    }
+    
+    fn varint(&mut self) -> wrap_res!(u32) {
+        let mut value = 0u32;
+        for shift in (0..32).step_by(7) {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        err!("Invalid number in the binary game state")
+    }
+    
+    fn bytes(&mut self) -> wrap_res!(&'a [u8]) {
+        let len = self.varint()? as usize;
+        err_assert!(self.bytes.len() - self.pos >= len, "Unexpected end of the binary game state");
+        let data = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(data)
+    }
+    
+    // Reads a UTF-8 string written with write_bytes(), returns `error` if it's invalid
+    fn string(&mut self, error: &str) -> wrap_res!(String) {
+        match String::from_utf8(self.bytes()?.to_vec()) {
+            Ok(s) => Ok(s),
+            Err(_) => err!("{}", error),
+        }
+    }
+}
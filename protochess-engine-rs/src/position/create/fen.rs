@@ -4,14 +4,18 @@ use scan_fmt::scan_fmt;
 use crate::utils::{from_index, to_index};
 use crate::utils::notation::tuple_to_rank_file;
 use crate::{wrap_res, err_assert, err, PieceId, Position};
-use crate::types::{BCoord, Player};
+use crate::types::{BCoord, Player, MAX_BOARD_WIDTH, MAX_BOARD_HEIGHT};
 
+// List of squares (x, y)
+type SquareList = Vec<(BCoord, BCoord)>;
 
 #[derive(Debug, Clone)]
 pub struct PiecePlacement {
     pub x: BCoord,
     pub y: BCoord,
     pub piece_id: PieceId,
+    /// If `true`, the piece cannot move for the rest of the game. Written as `!` after the piece id.
+    pub frozen: bool,
 }
 
 /// Summary of the data encoded in a FEN string. Used as an intermediate step when converting between FEN and Position.
@@ -34,19 +38,43 @@ pub struct FenData {
     /// List of squares that have not been moved. `None` means that the castling has not been specified
     /// in the string. Assume that no pieces have moved (everyone can castle).
     pub castling_availability: Option<Vec<(BCoord, BCoord)>>, 
-    /// The EP square, and the square of the EP victim (piece that just did a double move and will be captured)
+    /// The EP squares (more than one if the last double move passed over multiple squares), and the square of the
+    /// EP victim (piece that just did a double move and will be captured).
     /// `None` means that EP is not available in this position
-    pub ep_square_and_victim: Option<((BCoord, BCoord), (BCoord, BCoord))>,
+    pub ep_squares_and_victim: Option<(SquareList, (BCoord, BCoord))>,
     /// Number of times each player has been in check. `None` means that this information is not available
     /// in the FEN string (not aplicable to this variant): assume that no player has been in check (`[0,0]`).
     pub times_in_check: Option<[u8; 2]>,
-    
-    // Fullmove and halfmove clocks are not used
+    /// Number of halfmoves since the last capture or move of a piece that resets the clock.
+    /// Defaults to 0 if not specified.
+    pub halfmove_clock: u16,
+    /// Number of the current full move, incremented after each turn of Black. It doesn't affect the game.
+    /// Defaults to 1 if not specified.
+    pub fullmove_number: u16,
+    /// Pieces in the hand of the players (crazyhouse), written like `[QRb]` after the piece placement.
+    /// `None` means that the FEN string doesn't specify a pocket: assume that both hands are empty.
+    pub pocket: Option<Vec<PieceId>>,
 }
 
 
 
 impl FenData {
+    /// Same as `parse_fen()`, but the pieces in hand can also be written as an extra rank after the last one
+    /// (like `.../RNBQKBNR/QRb`), which requires knowing the height of the board.
+    pub fn parse_fen_with_height(fen: &str, height: BCoord) -> wrap_res!(Self) {
+        let fen = fen.trim_start();
+        let placement = fen.split_whitespace().next().unwrap_or("");
+        let ranks = split_ranks(placement);
+        if placement.contains('[') || ranks.len() != height as usize + 1 {
+            return Self::parse_fen(fen);
+        }
+        // Convert the extra rank to the bracket syntax
+        let pocket = ranks[ranks.len() - 1];
+        let board = &placement[..placement.len() - pocket.len() - 1];
+        let rest = &fen[placement.len()..];
+        Self::parse_fen(&format!("{board}[{pocket}]{rest}"))
+    }
+    
     pub fn parse_fen(fen: &str) -> wrap_res!(Self) {
         // Split FEN string into parts
         let fen_parts: Vec<&str> = fen.split_whitespace().collect();
@@ -54,39 +82,52 @@ impl FenData {
             err!("Invalid FEN string, it must have at least 1 part");
         }
         
+        // Pieces in hand, at the end of the piece placement
+        let (placement, pocket) = match fen_parts[0].split_once('[') {
+            None => (fen_parts[0], None),
+            Some((placement, pocket)) => (placement, Some(parse_pocket(pocket)?)),
+        };
+        
         // Count the number of ranks
-        let board_height = fen_parts[0].chars().filter(|c| *c == '/').count() + 1;
-        err_assert!(board_height <= 16, "The FEN string has {board_height} ranks, but the limit is 16");
+        let ranks = split_ranks(placement);
+        let board_height = ranks.len();
+        err_assert!(board_height <= MAX_BOARD_HEIGHT as usize, "The FEN string has {board_height} ranks, but the limit is {MAX_BOARD_HEIGHT}");
         
         // Piece placement
-        let mut piece_placements = Vec::new();
+        let mut piece_placements: Vec<PiecePlacement> = Vec::new();
         let mut walls = Vec::new();
-        let mut x = 0;
-        let mut y = board_height as BCoord - 1;
-        let mut skip_x = 0;
         let mut board_width = 0;
-        for c in fen_parts[0].chars() {
-            if c == '/' {
-                board_width = std::cmp::max(board_width, x + skip_x);
-                x = 0;
-                y -= 1;
+        for (i, rank) in ranks.iter().enumerate() {
+            let y = (board_height - 1 - i) as BCoord;
+            let mut x = 0;
+            let mut skip_x = 0;
+            let mut chars = rank.chars();
+            while let Some(c) = chars.next() {
+                if c.is_ascii_digit() {
+                    skip_x = 10 * skip_x + c.to_digit(10).unwrap();
+                    err_assert!(skip_x <= MAX_BOARD_WIDTH as u32, "The FEN string has too many files ({skip_x} > {MAX_BOARD_WIDTH})");
+                    continue;
+                } else if c == '!' {
+                    // Frozen piece, the '!' must come right after the piece id
+                    match piece_placements.last_mut() {
+                        Some(p) if skip_x == 0 && p.x as u32 + 1 == x && p.y == y && !p.frozen => p.frozen = true,
+                        _ => err!("Invalid FEN string, '!' must come right after a piece"),
+                    }
+                    continue;
+                }
+                x += skip_x;
                 skip_x = 0;
-                continue;
-            } else if c.is_ascii_digit() {
-                skip_x = 10 * skip_x + c.to_digit(10).unwrap();
-                continue;
-            }
-            x += skip_x;
-            skip_x = 0;
-            if c == '*' {
-                walls.push((x as BCoord, y));
-            } else {
-                piece_placements.push(PiecePlacement { x: x as BCoord, y, piece_id: c });
+                if c == '*' {
+                    walls.push((x as BCoord, y));
+                } else {
+                    let piece_id = read_piece_id(c, &mut chars)?;
+                    piece_placements.push(PiecePlacement { x: x as BCoord, y, piece_id, frozen: false });
+                }
+                x += 1;
             }
-            x += 1;
+            board_width = std::cmp::max(board_width, x + skip_x);
         }
-        board_width = std::cmp::max(board_width, x + skip_x);
-        err_assert!(board_width <= 16, "The FEN string has too many files ({board_width} > 16)");
+        err_assert!(board_width <= MAX_BOARD_WIDTH as u32, "The FEN string has too many files ({board_width} > {MAX_BOARD_WIDTH})");
         
         let board_width = board_width as BCoord;
         let board_height = board_height as BCoord;
@@ -94,8 +135,8 @@ impl FenData {
         // Player to move
         let player_to_move = {
             // By default, white moves first
-            if fen_parts.len() <= 1 || fen_parts[1].to_ascii_lowercase() == "w" { 0 }
-            else if fen_parts[1].to_ascii_lowercase() == "b" { 1 }
+            if fen_parts.len() <= 1 || fen_parts[1].eq_ignore_ascii_case("w") { 0 }
+            else if fen_parts[1].eq_ignore_ascii_case("b") { 1 }
             else { err!("The player to move must be 'w' or 'b'") }
         };
         
@@ -109,54 +150,69 @@ impl FenData {
             else { Some(parse_castling(fen_parts[2], board_height, board_width)?) }
         };
         
-        let ep_square_and_victim = {
+        let ep_squares_and_victim = {
             if fen_parts.len() <= 3 || fen_parts[3] == "-" {
                 None
             } else {
-                // Expected formats: a1, a1(b2)
-                const EXPECTED_REGEX: &str = r"^[a-p][0-9]+(\([a-p][0-9]+\))?$";
+                // Expected formats: a1, a1(b2), a1,a2,a3(a4) (for double jumps that pass over multiple squares)
+                const EXPECTED_REGEX: &str = r"^[a-z][0-9]+(,[a-z][0-9]+)*(\([a-z][0-9]+\))?$";
                 err_assert!(Regex::new(EXPECTED_REGEX).unwrap().is_match(fen_parts[3]), "Invalid en passant square in FEN string");
-                let (ep_x, ep_y) = match scan_fmt!(fen_parts[3], "{[a-p]}{d}", char, isize) {
-                    Ok(parts) => parts,
-                    Err(_) => err!("Invalid en passant square in FEN string")
+                let (squares, victim) = match fen_parts[3].split_once('(') {
+                    Some((squares, victim)) => (squares, Some(&victim[..victim.len() - 1])),
+                    None => (fen_parts[3], None),
                 };
-                let (vic_x, vic_y) = match scan_fmt!(fen_parts[3], "{*[a-p]}{*d}({[a-p]}{d})", char, isize) {
-                    Ok(parts) => parts,
-                    Err(_) => {
+                let mut ep_squares = Vec::new();
+                for square in squares.split(',') {
+                    ep_squares.push(parse_ep_square(square, board_width, board_height, "Invalid en passant square in FEN string")?);
+                }
+                let victim = match victim {
+                    Some(victim) => parse_ep_square(victim, board_width, board_height, "Invalid en passant victim in FEN string")?,
+                    None => {
                         // If it's white's turn, the victim is the black pawn that just did a double move
-                        // (victim is 1 square below the EP square).
-                        if player_to_move == 0 { (ep_x, ep_y - 1) }
-                        else { (ep_x, ep_y + 1) }
+                        // (victim is 1 square below the EP squares).
+                        let (x, y) = if player_to_move == 0 { *ep_squares.iter().min_by_key(|sq| sq.1).unwrap() }
+                            else { *ep_squares.iter().max_by_key(|sq| sq.1).unwrap() };
+                        let y = if player_to_move == 0 { y as isize - 1 } else { y as isize + 1 };
+                        err_assert!(y >= 0 && y < board_height as isize, "Invalid en passant victim in FEN string");
+                        (x, y as BCoord)
                     }
                 };
-                // ep_x and ev_x are guaranteed to be a valid character between 'a' and 'p'
-                let ep_x = ep_x.to_digit(36).unwrap() as BCoord - 10;
-                let ep_y = ep_y - 1;
-                let vic_x = vic_x.to_digit(36).unwrap() as BCoord - 10;
-                let vic_y = vic_y - 1;
-                // Only need to check the y coordinates, since the x coordinates are already guaranteed to be valid
-                err_assert!(ep_y >= 0 && ep_y < board_height as isize, "Invalid en passant square in FEN string");
-                err_assert!(vic_y >= 0 && vic_y < board_height as isize, "Invalid en passant victim in FEN string");
-                
-                Some(((ep_x, ep_y as BCoord), (vic_x, vic_y as BCoord)))
+                Some((ep_squares, victim))
             }
         };
         
+        // Move clocks: the first 2 numbers after the EP square (the check count can come before them)
+        let mut clocks = fen_parts.iter().skip(4).filter(|part| part.chars().all(|c| c.is_ascii_digit()));
+        let halfmove_clock = match clocks.next() {
+            None => 0,
+            Some(part) => match part.parse::<u16>() {
+                Ok(clock) => clock,
+                Err(_) => err!("Invalid halfmove clock in FEN string, it must be between 0 and {}", u16::MAX),
+            },
+        };
+        let fullmove_number = match clocks.next() {
+            None => 1,
+            Some(part) => match part.parse::<u16>() {
+                Ok(number) => number,
+                Err(_) => err!("Invalid fullmove number in FEN string, it must be between 0 and {}", u16::MAX),
+            },
+        };
+        
         // Times in check: search all remaining parts for a +W+B format
         let mut times_in_check = None;
+        let times_in_check_regex = Regex::new(r"^\+([0-9]+)\+([0-9]+)$").unwrap();
+        let wrong_format_regex = Regex::new(r"^([0-9]+)\+([0-9]+)$").unwrap();
         for part in fen_parts.iter().skip(4) {
-            const TIMES_IN_CHECK_REGEX: &str = r"^\+([0-9]+)\+([0-9]+)$";
-            const WRONG_FORMAT_REGEX: &str = r"^([0-9]+)\+([0-9]+)$";
-            if !Regex::new(TIMES_IN_CHECK_REGEX).unwrap().is_match(part) {
+            if !times_in_check_regex.is_match(part) {
                 // Check if this is an alternative check count format
-                if Regex::new(WRONG_FORMAT_REGEX).unwrap().is_match(part) {
+                if wrong_format_regex.is_match(part) {
                     err!("Invalid check count format, use +W+B, where W is the number of times White put Black in check.
                     In 3-Check, '3+1' is equivalent to '+0+2'");
                 }
                 continue;
             }
             // Some of the parts match the check count format
-            let (white_checks, black_checks) = match scan_fmt!(fen_parts[6], "+{d}+{d}", u8, u8) {
+            let (white_checks, black_checks) = match scan_fmt!(part, "+{d}+{d}", u8, u8) {
                 Ok(parts) => parts,
                 Err(_) => err!("Invalid check format, make sure it's between +0+0 and +255+255"),
             };
@@ -170,21 +226,95 @@ impl FenData {
             piece_placements,
             walls,
             times_in_check,
+            halfmove_clock,
+            fullmove_number,
             player_to_move,
             castling_availability,
-            ep_square_and_victim,
+            ep_squares_and_victim,
+            pocket,
         })
     }
+
+    /// Returns the same position with the colors swapped: the board is mirrored vertically, the side to move
+    /// and the check counts are swapped, and each piece id is replaced by `flip_id(id)` (the id of the same piece
+    /// for the other player, usually the id with the opposite case).
+    pub fn color_flipped(&self, flip_id: impl Fn(PieceId) -> PieceId) -> Self {
+        let flip = |(x, y): (BCoord, BCoord)| (x, self.height - 1 - y);
+        Self {
+            width: self.width,
+            height: self.height,
+            piece_placements: self.piece_placements.iter()
+                .map(|p| PiecePlacement { x: p.x, y: self.height - 1 - p.y, piece_id: flip_id(p.piece_id), frozen: p.frozen })
+                .collect(),
+            walls: self.walls.iter().copied().map(flip).collect(),
+            times_in_check: self.times_in_check.map(|[white, black]| [black, white]),
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            player_to_move: 1 - self.player_to_move,
+            castling_availability: self.castling_availability.as_ref().map(|squares| squares.iter().copied().map(flip).collect()),
+            ep_squares_and_victim: self.ep_squares_and_victim.as_ref()
+                .map(|(squares, victim)| (squares.iter().copied().map(flip).collect(), flip(*victim))),
+            pocket: self.pocket.as_ref().map(|pocket| pocket.iter().copied().map(&flip_id).collect()),
+        }
+    }
 }
 
 
-/// Returns a list of the squares that have not moved
+/// Returns the list of pieces in hand. The format is `QRb]` (the opening bracket has already been removed)
+fn parse_pocket(pocket: &str) -> wrap_res!(Vec<PieceId>) {
+    let pocket = match pocket.strip_suffix(']') {
+        Some(pocket) => pocket,
+        None => err!("Invalid pocket in FEN string, it must be enclosed in brackets: '[{pocket}'"),
+    };
+    // Some programs use "-" for an empty pocket
+    if pocket == "-" {
+        return Ok(vec![]);
+    }
+    let mut pieces = Vec::new();
+    let mut chars = pocket.chars();
+    while let Some(c) = chars.next() {
+        err_assert!(!c.is_ascii_digit() && c != '/' && c != '*' && c != '!' && c != '[' && c != ']',
+            "Invalid pocket in FEN string: '[{pocket}]'");
+        pieces.push(read_piece_id(c, &mut chars)?);
+    }
+    Ok(pieces)
+}
+
+
+// Parses a square of the EP field of a FEN string, like "e3"
+fn parse_ep_square(square: &str, board_width: BCoord, board_height: BCoord, error: &str) -> wrap_res!((BCoord, BCoord)) {
+    let (x, y) = match scan_fmt!(square, "{[a-z]}{d}", char, isize) {
+        Ok(parts) => parts,
+        Err(_) => err!("{error}")
+    };
+    // x is guaranteed to be a valid character between 'a' and 'z'
+    let x = x.to_digit(36).unwrap() as BCoord - 10;
+    let y = y - 1;
+    err_assert!(x < board_width && y >= 0 && y < board_height as isize, "{error}");
+    Ok((x, y as BCoord))
+}
+
+/// Returns a list of the squares that have not moved.
+/// Both formats can be combined, like `KQ(e1,a16)kq` (useful for vertical castling, since
+/// the traditional format only describes castling along the back rank).
 fn parse_castling(castling: &str, board_height: BCoord, board_width: BCoord) -> wrap_res!(Vec<(BCoord, BCoord)>) {
-    if castling.starts_with('(') {
-        parse_custom_castling(castling)
-    } else {
-        parse_traditional_castling(castling, board_height, board_width)
+    let mut result = vec![];
+    let mut rest = castling;
+    while !rest.is_empty() {
+        if rest.starts_with('(') {
+            let end = match rest.find(')') {
+                Some(end) => end,
+                None => err!("Unclosed parenthesis in castling rights: '{castling}'"),
+            };
+            result.extend(parse_custom_castling(&rest[..=end])?);
+            rest = &rest[end + 1..];
+        } else {
+            let end = rest.find('(').unwrap_or(rest.len());
+            result.extend(parse_traditional_castling(&rest[..end], board_height, board_width)?);
+            rest = &rest[end..];
+        }
     }
+    Ok(result)
 }
 
 /// Converts a custom castling string to a list of squares that have not moved
@@ -195,12 +325,13 @@ fn parse_custom_castling(castling: &str) -> wrap_res!(Vec<(BCoord, BCoord)>) {
     let castling = &castling[1..castling.len() - 1];
     let squares = castling.split(',');
     for square in squares {
-        let (x, y) = match scan_fmt!(square, "{[a-p]}{d}", char, BCoord) {
+        let (x, y) = match scan_fmt!(square, "{[a-z]}{d}", char, BCoord) {
             Ok(parts) => parts,
             Err(_) => err!("Invalid castling square in FEN string")
         };
-        // x is guaranteed to be a valid character between 'a' and 'p'
+        // x is guaranteed to be a valid character between 'a' and 'z'
         let x = x.to_digit(36).unwrap() as BCoord - 10;
+        err_assert!(x < MAX_BOARD_WIDTH, "Invalid castling square in FEN string");
         let y = y - 1;
         result.push((x, y));
     }
@@ -238,6 +369,64 @@ fn parse_traditional_castling(castling: &str, height: BCoord, width: BCoord) ->
 }
 
 
+/// Splits the piece placement into ranks, ignoring the slashes inside escaped piece ids
+fn split_ranks(placement: &str) -> Vec<&str> {
+    let mut ranks = Vec::new();
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in placement.char_indices() {
+        match c {
+            '(' => escaped = true,
+            ')' => escaped = false,
+            '/' if !escaped => {
+                ranks.push(&placement[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    ranks.push(&placement[start..]);
+    ranks
+}
+
+/// Reads a piece id that starts with `c`. Ids that would have a special meaning in the FEN are
+/// escaped in parentheses, like `(!)`, or written as a code point, like `(U+005B)`.
+fn read_piece_id(c: char, chars: &mut std::str::Chars) -> wrap_res!(PieceId) {
+    if c != '(' {
+        err_assert!(c != ')', "Invalid FEN string, unexpected ')'");
+        return Ok(c);
+    }
+    let mut escaped = String::new();
+    loop {
+        match chars.next() {
+            Some(')') => break,
+            Some(c) => escaped.push(c),
+            None => err!("Invalid FEN string, missing ')' after '({escaped}'"),
+        }
+    }
+    let mut escaped_chars = escaped.chars();
+    if let (Some(c), None) = (escaped_chars.next(), escaped_chars.next()) {
+        return Ok(c);
+    }
+    let code_point = escaped.strip_prefix("U+").and_then(|hex| u32::from_str_radix(hex, 16).ok());
+    match code_point.and_then(char::from_u32) {
+        Some(c) => Ok(c),
+        None => err!("Invalid piece id in FEN string: '({escaped})'"),
+    }
+}
+
+/// Writes a piece id, escaping it if it would have a special meaning in the FEN (see `read_piece_id()`):
+/// digits (empty squares), `/` (end of a rank), `*` (wall), `!` (frozen piece) and `-` (empty pocket)
+fn write_piece_id(f: &mut std::fmt::Formatter<'_>, id: PieceId) -> std::fmt::Result {
+    if id.is_whitespace() || id.is_control() || matches!(id, '(' | ')' | '[' | ']') {
+        write!(f, "(U+{:04X})", id as u32)
+    } else if id.is_ascii_digit() || matches!(id, '!' | '/' | '*' | '-') {
+        write!(f, "({id})")
+    } else {
+        write!(f, "{id}")
+    }
+}
+
 /// Extracts the FEN data from a Position, so that it can be converted to a string
 impl From<&Position> for FenData {
     fn from(pos: &Position) -> Self {
@@ -251,9 +440,11 @@ impl From<&Position> for FenData {
                 let index = to_index(x, y);
                 if let Some(piece) = pos.piece_at(index) {
                     // Piece found, add it to the list
-                    piece_placements.push(PiecePlacement { x, y, piece_id: piece.get_piece_id() });
+                    // Pieces that have reached max_moves are also stored as frozen
+                    let frozen = pos.is_immobile(index);
+                    piece_placements.push(PiecePlacement { x, y, piece_id: piece.get_piece_id(), frozen });
                     // If this square can be used for castling, add it to the list
-                    if piece.has_not_moved(index) && piece.used_in_castling() {
+                    if piece.has_not_moved(index) {
                         castling.push((x, y));
                     }
                 }
@@ -262,11 +453,24 @@ impl From<&Position> for FenData {
                 }
             }
         }        
+        // Extract the pieces in hand, only if the variant uses them
+        let pocket = {
+            if pos.global_rules.captured_pieces_to_hand || pos.global_rules.gating || pos.pieces.iter().any(|ps| !ps.hand_is_empty()) {
+                let pieces = pos.pieces.iter()
+                    .flat_map(|ps| ps.iter_hand())
+                    .flat_map(|(piece, count)| std::iter::repeat_n(piece.get_piece_id(), count as usize))
+                    .collect();
+                Some(pieces)
+            } else {
+                None
+            }
+        };
+        
         // Extract EP square
-        let ep_square_and_victim = {
-            if let Some(ep_square) = pos.get_ep_square() {
+        let ep_squares_and_victim = {
+            if pos.has_ep_squares() {
                 let ep_victim = pos.get_ep_victim();
-                Some((from_index(ep_square), from_index(ep_victim)))
+                Some((pos.get_ep_squares().map(from_index).collect(), from_index(ep_victim)))
             } else {
                 None
             }
@@ -278,9 +482,12 @@ impl From<&Position> for FenData {
             piece_placements,
             walls,
             times_in_check: pos.get_times_checked().copied(),
+            halfmove_clock: pos.get_halfmove_clock(),
+            fullmove_number: pos.get_fullmove_number(),
             player_to_move: pos.whos_turn,
             castling_availability: Some(castling),
-            ep_square_and_victim,
+            ep_squares_and_victim,
+            pocket,
         }
     }
 }
@@ -312,7 +519,10 @@ impl std::fmt::Display for FenData {
                             write!(f, "{empty_count}")?;
                             empty_count = 0;
                         }
-                        write!(f, "{}", piece.piece_id)?;
+                        write_piece_id(f, piece.piece_id)?;
+                        if piece.frozen {
+                            write!(f, "!")?;
+                        }
                         found = true;
                         break;
                     }
@@ -331,6 +541,14 @@ impl std::fmt::Display for FenData {
                 write!(f, "/")?;
             }
         }
+        // Pieces in hand
+        if let Some(pocket) = &self.pocket {
+            write!(f, "[")?;
+            for &id in pocket {
+                write_piece_id(f, id)?;
+            }
+            write!(f, "]")?;
+        }
         
         // STEP 2: Player to move
         let player_char = if self.player_to_move == 0 { 'w' } else { 'b' };
@@ -354,13 +572,17 @@ impl std::fmt::Display for FenData {
         }
         
         // STEP 4: EP square
-        if let Some((ep_square, ep_victim)) = self.ep_square_and_victim {
-            write!(f, " {}({})", tuple_to_rank_file(ep_square), tuple_to_rank_file(ep_victim))?;
+        if let Some((ep_squares, ep_victim)) = &self.ep_squares_and_victim {
+            let ep_squares: Vec<String> = ep_squares.iter().copied().map(tuple_to_rank_file).collect();
+            write!(f, " {}({})", ep_squares.join(","), tuple_to_rank_file(*ep_victim))?;
         } else {
             write!(f, " -")?;
         }
         
-        // STEP 5: Times in check
+        // STEP 5: Halfmove clock and fullmove number
+        write!(f, " {} {}", self.halfmove_clock, self.fullmove_number)?;
+        
+        // STEP 6: Times in check
         // Important: Note that the order is reversed
         if let Some(times_in_check) = self.times_in_check {
             write!(f, " +{}+{}", times_in_check[1], times_in_check[0])?;
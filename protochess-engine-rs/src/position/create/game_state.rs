@@ -1,25 +1,39 @@
+use std::convert::TryFrom;
+
 use crate::utils::debug::split_debug_fen;
-use crate::{PieceDefinition, MoveInfo, GlobalRules, Position, MoveGen};
-use crate::types::{Player, BCoord, GameMode};
+use crate::{PieceDefinition, MoveInfo, GlobalRules, Position, PieceSquares, MoveGen, GameResult, MakeMoveResultFlag, wrap_res, err};
+use crate::piece::ValidationError;
+use crate::types::{Player, BCoord, GameMode, BDimensions, Bitboard};
 
 use super::fen::FenData;
 use super::variant_factory::VariantFactory;
+use super::variant_registry::VariantRegistry;
 
 
 /// Full state of the game, including:
 /// - **Initial state:** Defines the rules of the game and starting position.
 /// - **Initial fen (optional):** User-provided fen that further defines the starting position, overriding 
-/// the initial state. It's applied to the initial state before playing the moves in `move_history`.
-/// See [this document](https://github.com/pol-rivero/protochess-engine/tree/master/docs/FEN.md) for the custom FEN format.
+///   the initial state. It's applied to the initial state before playing the moves in `move_history`.
+///   See [this document](https://github.com/pol-rivero/protochess-engine/tree/master/docs/FEN.md) for the custom FEN format.
 /// - **Move history:** Defines the current position and allows enforcing the repetition rules.
+/// - **Termination (optional):** The game was ended by the players or by an arbiter (resignation, draw agreement
+///   or adjudication), instead of by the rules.
 /// 
 /// All games of a given chess variant have the same initial state, but can have different initial FENs.
 #[must_use]
 #[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GameState {
     pub initial_state: InitialState,
     pub initial_fen: Option<String>,
     pub move_history: Vec<MoveInfo>,
+    /// Positions of the game before `initial_fen` (oldest first), in FEN format. Used to detect repetitions
+    /// after resuming a game from a snapshot of its position.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prior_fens: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination: Option<GameTermination>,
 }
 // The default game state is the standard chess starting position
 // without any user-provided FEN and no moves played.
@@ -31,7 +45,102 @@ impl GameState {
         let (fen, variant) = split_debug_fen(fen);
         let initial_state = VariantFactory::new(variant).make_initial_state();
         let initial_fen = Some(fen);
-        GameState { initial_state, initial_fen, move_history: vec![] }
+        GameState { initial_state, initial_fen, move_history: vec![], prior_fens: vec![], termination: None }
+    }
+    
+    /// Creates a game state with the starting position of a named variant, see `VariantRegistry`
+    pub fn from_variant_name(name: &str) -> wrap_res!(Self) {
+        let initial_state = VariantRegistry::get(name)?;
+        Ok(GameState { initial_state, initial_fen: None, move_history: vec![], prior_fens: vec![], termination: None })
+    }
+    
+    /// Version of the JSON format written by `to_json()`
+    pub const JSON_VERSION: u32 = 2;
+    
+    /// Converts the game state to JSON, in a format that is stable between versions of the engine:
+    /// `{"version": 2, "initialState": {...}, "initialFen": "...", "moveHistory": ["e2e4", "e7e5"]}`.
+    /// The moves are written like `MoveInfo::to_string()`, and `initialFen` can be `null`.
+    /// The `priorFens` field (a list of strings) is only written if there are prior positions, and the
+    /// `termination` field (see `GameTermination`) only if the game has been terminated.
+    pub fn to_json(&self) -> String {
+        let json = GameStateJson {
+            version: Self::JSON_VERSION,
+            initial_state: self.initial_state.clone(),
+            initial_fen: self.initial_fen.clone(),
+            move_history: self.move_history.iter().map(MoveInfo::to_string).collect(),
+            prior_fens: self.prior_fens.clone(),
+            termination: self.termination,
+        };
+        serde_json::to_string(&json).unwrap()
+    }
+    
+    /// Loads a game state written by `to_json()`. JSON without a `version` field is read in the format of
+    /// version 1 (the serde representation of `GameState`, where each move is a `MoveInfo` object).
+    pub fn from_json(json: &str) -> wrap_res!(Self) {
+        let value: serde_json::Value = match serde_json::from_str(json) {
+            Ok(value) => value,
+            Err(e) => err!("Invalid JSON: {}", e),
+        };
+        let version = match value.get("version") {
+            None => 1,
+            Some(version) => match version.as_u64() {
+                Some(version) => version,
+                None => err!("Invalid version of the game state: {}", version),
+            },
+        };
+        match version {
+            1 => serde_json::from_value::<GameState>(value).map_err(|e| format!("Invalid game state (version 1): {e}")),
+            2 => {
+                let json = match serde_json::from_value::<GameStateJson>(value) {
+                    Ok(json) => json,
+                    Err(e) => err!("Invalid game state (version 2): {}", e),
+                };
+                let move_history = json.move_history.iter().map(|mv| MoveInfo::try_from(&mv[..])).collect::<wrap_res!(_)>()?;
+                Ok(GameState { initial_state: json.initial_state, initial_fen: json.initial_fen, move_history, prior_fens: json.prior_fens, termination: json.termination })
+            },
+            _ => err!("Unsupported version of the game state: {} (the latest version is {})", version, Self::JSON_VERSION),
+        }
+    }
+}
+
+// Format of `GameState::to_json()`, version 2
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameStateJson {
+    version: u32,
+    initial_state: InitialState,
+    initial_fen: Option<String>,
+    move_history: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    prior_fens: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    termination: Option<GameTermination>,
+}
+
+/// Ending of a game that is not caused by the rules, see `Engine::resign()`, `Engine::accept_draw()` and
+/// `Engine::adjudicate()`. Serialized as `{"type": "resignation", "player": 0}`, `{"type": "drawAgreement"}`
+/// or `{"type": "adjudication", "winner": 1}` (the winner is `null` for a draw).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum GameTermination {
+    /// The player resigned, the opponent wins
+    Resignation { player: Player },
+    /// The players agreed to a draw
+    DrawAgreement,
+    /// The game was ended by an arbiter (e.g. when a player disconnects), `None` for a draw
+    Adjudication { winner: Option<Player> },
+}
+
+impl GameTermination {
+    /// Returns the result of the game
+    pub fn result(&self) -> GameResult {
+        let (flag, winner) = match *self {
+            GameTermination::Resignation { player } => (MakeMoveResultFlag::Resignation, Some(1 - player)),
+            GameTermination::DrawAgreement => (MakeMoveResultFlag::DrawAgreement, None),
+            GameTermination::Adjudication { winner } => (MakeMoveResultFlag::Adjudication, winner),
+        };
+        GameResult { flag, winner: winner.into() }
     }
 }
 
@@ -39,6 +148,8 @@ impl GameState {
 /// Defines a chess variant. Includes the rules of the game and the starting position.
 #[must_use]
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct InitialState {
     /// Initial position in FEN format. It includes the walls as `'*'`, which cannot 
     /// be overridden by the user when loading another FEN.
@@ -62,6 +173,20 @@ impl Default for InitialState {
     }
 }
 
+impl InitialState {
+    /// Validates each piece definition (see `PieceDefinition::validate()`). Returns all the errors found,
+    /// along with the index of the piece in `piece_types`. Pieces without ids are not used, so they are skipped.
+    pub fn validate_pieces(&self) -> Result<(), Vec<(usize, ValidationError)>> {
+        // Only the size of the board is used
+        let dims = BDimensions { width: self.board_width, height: self.board_height, bounds: Bitboard::zero() };
+        let errors: Vec<_> = self.piece_types.iter().enumerate()
+            .filter(|(_, piece)| piece.ids.iter().any(Option::is_some))
+            .flat_map(|(i, piece)| piece.validate(&dims).err().unwrap_or_default().into_iter().map(move |e| (i, e)))
+            .collect();
+        if errors.is_empty() { Ok(()) } else { Err(errors) }
+    }
+}
+
 /// Contains data that changes with each move.
 #[must_use]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -70,16 +195,47 @@ pub struct StateDiff {
     pub fen: String,
     pub in_check: bool,
     pub player_to_move: Player,
+    /// Coordinates of the walls, which can change during the game (see `Engine::add_wall()`)
+    pub walls: Vec<(BCoord, BCoord)>,
+    /// Coordinates of the pieces of each type, see `Engine::get_piece_bitboards()`
+    pub pieces: Vec<PieceSquares>,
 }
 
 impl From<&mut Position> for StateDiff {
     fn from(pos: &mut Position) -> Self {
-        let fen = FenData::from(&*pos).to_string();
+        let fen_data = FenData::from(&*pos);
+        let walls = fen_data.walls.clone();
+        let fen = fen_data.to_string();
         let in_check = {
             if pos.leader_is_captured() { false }
             else { MoveGen::in_check(pos) }
         };
         let player_to_move = pos.whos_turn;
-        StateDiff { fen, in_check, player_to_move }
+        let pieces = pos.get_piece_bitboards();
+        StateDiff { fen, in_check, player_to_move, walls, pieces }
+    }
+}
+
+impl StateDiff {
+    /// Same as `StateDiff::from()`, but the FEN hides the enemy pieces that the player cannot see
+    /// (see `MoveGen::visible_squares()`), together with their castling rights and en passant squares
+    pub fn for_player(pos: &mut Position, player: Player) -> Self {
+        let visible = MoveGen::visible_squares(pos, player);
+        let is_visible = |&(x, y): &(BCoord, BCoord)| visible.get_bit_at(x, y);
+        let mut fen_data = FenData::from(&*pos);
+        fen_data.piece_placements.retain(|p| is_visible(&(p.x, p.y)));
+        if let Some(squares) = &mut fen_data.castling_availability {
+            squares.retain(is_visible);
+        }
+        if fen_data.ep_squares_and_victim.as_ref().is_some_and(|(_, victim)| !is_visible(victim)) {
+            fen_data.ep_squares_and_victim = None;
+        }
+        let mut diff = StateDiff::from(pos);
+        diff.fen = fen_data.to_string();
+        for (_, _, squares) in &mut diff.pieces {
+            squares.retain(is_visible);
+        }
+        diff.pieces.retain(|(_, _, squares)| !squares.is_empty());
+        diff
     }
 }
@@ -0,0 +1,138 @@
+use crate::MoveInfo;
+
+/// Line of moves of a `GameTree`. Each variation branches from another line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameLine {
+    /// Index of the line that this variation branches from, or `None` for the mainline
+    pub parent: Option<usize>,
+    /// Number of moves of the parent line that are played before the first move of this line
+    pub start_ply: usize,
+    /// Moves of this line after the branching point
+    pub moves: Vec<MoveInfo>,
+    /// Algebraic notation of `moves`
+    pub notation: Vec<String>,
+}
+
+/// Mainline of a game (the line with index 0) and its variations. Lines are never removed or shortened,
+/// only extended, so the indexes and the branching points stay valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameTree {
+    lines: Vec<GameLine>,
+    current_line: usize,
+    // All the moves of the current line, from the initial position
+    current_moves: Vec<MoveInfo>,
+}
+
+impl Default for GameTree {
+    fn default() -> Self {
+        GameTree::new(Vec::new(), Vec::new())
+    }
+}
+
+impl GameTree {
+    pub fn new(moves: Vec<MoveInfo>, notation: Vec<String>) -> GameTree {
+        let mainline = GameLine { parent: None, start_ply: 0, moves, notation };
+        let current_moves = mainline.moves.clone();
+        GameTree { lines: vec![mainline], current_line: 0, current_moves }
+    }
+
+    /// Returns all the lines, the mainline is the first one
+    pub fn get_lines(&self) -> &[GameLine] {
+        &self.lines
+    }
+
+    /// Returns the index of the selected line
+    pub fn get_current_line(&self) -> usize {
+        self.current_line
+    }
+
+    /// Returns all the moves of the selected line, from the initial position
+    pub fn get_current_moves(&self) -> &[MoveInfo] {
+        &self.current_moves
+    }
+
+    /// Returns all the moves of a line (including the ones of its parents), from the initial position
+    pub fn line_moves(&self, index: usize) -> Vec<MoveInfo> {
+        let line = &self.lines[index];
+        let mut moves = match line.parent {
+            Some(parent) => self.line_moves(parent)[..line.start_ply].to_vec(),
+            None => Vec::new(),
+        };
+        moves.extend_from_slice(&line.moves);
+        moves
+    }
+
+    /// Returns the algebraic notation of all the moves of a line, from the initial position
+    pub fn line_notation(&self, index: usize) -> Vec<String> {
+        let line = &self.lines[index];
+        let mut notation = match line.parent {
+            Some(parent) => self.line_notation(parent)[..line.start_ply].to_vec(),
+            None => Vec::new(),
+        };
+        notation.extend_from_slice(&line.notation);
+        notation
+    }
+
+    /// Changes the selected line. Panics if the index is out of bounds.
+    pub fn select_line(&mut self, index: usize) {
+        self.current_moves = self.line_moves(index);
+        self.current_line = index;
+    }
+
+    /// Adds a variation that branches from the line `parent` after `start_ply` moves, and returns its index.
+    /// The moves must not be empty, and `start_ply` must not exceed the length of the parent line.
+    pub fn add_variation(&mut self, parent: usize, start_ply: usize, moves: Vec<MoveInfo>, notation: Vec<String>) -> usize {
+        assert!(!moves.is_empty() && start_ply <= self.line_moves(parent).len(), "Invalid variation");
+        self.lines.push(GameLine { parent: Some(parent), start_ply, moves, notation });
+        self.lines.len() - 1
+    }
+
+    /// Called after playing `mv` when `ply` moves of the current line had been played. In this order:
+    /// - If `mv` is the next move of the current line, stays in the current line.
+    /// - If another line shares the first `ply` moves and continues with `mv`, selects that line.
+    /// - If the current line ends at `ply`, the move is added to its end.
+    /// - Otherwise, selects a new variation that branches from the current line.
+    pub fn play(&mut self, ply: usize, mv: &MoveInfo, notation: String) {
+        debug_assert!(ply <= self.current_moves.len());
+        if self.current_moves.get(ply) == Some(mv) {
+            return;
+        }
+        let prefix = &self.current_moves[..ply];
+        let existing = (0..self.lines.len()).find(|&i| {
+            let moves = self.line_moves(i);
+            moves.len() > ply && moves[..ply] == *prefix && moves[ply] == *mv
+        });
+        if let Some(index) = existing {
+            self.select_line(index);
+        } else if ply == self.current_moves.len() {
+            let line = &mut self.lines[self.current_line];
+            line.moves.push(*mv);
+            line.notation.push(notation);
+            self.current_moves.push(*mv);
+        } else {
+            let index = self.add_variation(self.current_line, ply, vec![*mv], vec![notation]);
+            self.select_line(index);
+        }
+    }
+
+    /// Returns the lines that leave the current line at `ply` with a different move (one line per
+    /// move, the first one that was added), sorted by index. Includes the current line if it has a move at `ply`.
+    pub fn lines_at(&self, ply: usize) -> Vec<usize> {
+        let prefix = &self.current_moves[..ply.min(self.current_moves.len())];
+        let mut found_moves = Vec::new();
+        let mut result = Vec::new();
+        for i in 0..self.lines.len() {
+            let moves = if i == self.current_line { self.current_moves.clone() } else { self.line_moves(i) };
+            if moves.len() <= ply || moves[..ply] != *prefix || found_moves.contains(&moves[ply]) {
+                continue;
+            }
+            // Prefer the current line for its own move
+            if i != self.current_line && self.current_moves.get(ply) == Some(&moves[ply]) {
+                continue;
+            }
+            found_moves.push(moves[ply]);
+            result.push(i);
+        }
+        result
+    }
+}
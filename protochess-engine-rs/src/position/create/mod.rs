@@ -1,6 +1,12 @@
 // This directory contains the code for creating new Position objects, as well as exporting them to external formats.
 
 pub mod fen;
-pub mod game_state;
-pub mod variant_factory;
+pub(crate) mod game_state;
+pub(crate) mod game_tree;
+pub(crate) mod variant_factory;
+pub(crate) mod variant_registry;
+pub(crate) mod variant_gen;
 pub mod position_factory;
+pub(crate) mod pgn;
+mod binary_format;
+mod annotation;
@@ -0,0 +1,283 @@
+// Import and export of games in Portable Game Notation (PGN).
+// Only the variants in `GameMode` can be represented, using the `[Variant "..."]` tag.
+
+use std::convert::TryFrom;
+
+use crate::{MoveInfo, MakeMoveResultFlag, MakeMoveResultWinner, wrap_res, err};
+use crate::types::{GameMode, MoveAnnotation};
+use crate::utils::notation::EN_PASSANT_SUFFIX;
+
+use super::fen::FenData;
+use super::game_state::GameState;
+use super::position_factory::PositionFactory;
+use super::variant_factory::VariantFactory;
+
+/// Tag pairs of a PGN file, in the order in which they appear
+pub type PgnHeaders = Vec<(String, String)>;
+
+// Same order as the Seven Tag Roster, "Result" is written separately
+const ROSTER_TAGS: [(&str, &str); 6] = [
+    ("Event", "?"), ("Site", "?"), ("Date", "????.??.??"), ("Round", "?"), ("White", "?"), ("Black", "?")
+];
+// Tags that are generated from the game state, the values provided by the user are ignored
+const GENERATED_TAGS: [&str; 4] = ["Result", "Variant", "SetUp", "FEN"];
+const KNOWN_VARIANTS: [GameMode; 10] = [
+    GameMode::Standard, GameMode::Atomic, GameMode::Horde, GameMode::Antichess,
+    GameMode::KingOfTheHill, GameMode::RacingKings, GameMode::ThreeCheck, GameMode::FiveCheck,
+    GameMode::Crazyhouse, GameMode::DarkChess,
+];
+const RESULT_MARKERS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+// Maximum length of a line in the movetext
+const MAX_LINE_LENGTH: usize = 80;
+
+impl GameState {
+    /// Loads a game in PGN format. The variant is taken from the `[Variant]` tag (standard chess if
+    /// missing), and the starting position from the `[FEN]` tag. Moves must be in algebraic notation.
+    /// Comments, variations and annotations are ignored.
+    pub fn from_pgn(pgn: &str) -> wrap_res!(Self) {
+        let (state, _headers) = Self::from_pgn_with_headers(pgn)?;
+        Ok(state)
+    }
+
+    /// Same as `from_pgn()`, but also returns all the tag pairs of the PGN
+    pub fn from_pgn_with_headers(pgn: &str) -> wrap_res!(Self, PgnHeaders) {
+        let mut headers = PgnHeaders::new();
+        let mut movetext = String::new();
+        for line in pgn.lines() {
+            let line = line.trim();
+            // Lines starting with '%' are escaped
+            if line.starts_with('%') {
+                continue;
+            }
+            if movetext.trim().is_empty() && line.starts_with('[') {
+                headers.push(parse_tag_pair(line)?);
+            } else {
+                movetext.push_str(line);
+                movetext.push('\n');
+            }
+        }
+
+        let get_header = |key: &str| headers.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+        let variant = match get_header("Variant") {
+            Some(variant) => GameMode::try_from(variant)?,
+            None => GameMode::Standard,
+        };
+        let mut state = GameState {
+            initial_state: VariantFactory::new(variant).make_initial_state(),
+            initial_fen: get_header("FEN").map(str::to_string),
+            move_history: vec![],
+            prior_fens: vec![],
+            termination: None,
+        };
+
+        // Convert the moves to MoveInfo, playing them on a temporary position
+        let mut pos = PositionFactory::default().set_state(state.clone(), None)?.unwrap();
+        for token in movetext_tokens(&movetext)? {
+            if RESULT_MARKERS.contains(&token.as_str()) {
+                break;
+            }
+            let mv = MoveInfo::from_san(&token, &mut pos)?;
+            let _result = pos.pub_make_move(&mv);
+            state.move_history.push(mv);
+        }
+        Ok((state, headers))
+    }
+}
+
+impl PositionFactory {
+    /// Exports the current game in PGN format. The movetext uses the same algebraic notation as
+    /// `get_notation()`, but pawn captures include the file of the pawn (`exd5` instead of `xd5`).
+    /// Custom variants can't be described in PGN, so the `[Variant]` tag is omitted for them.
+    pub fn to_pgn(&self) -> String {
+        self.to_pgn_with_headers(&PgnHeaders::new())
+    }
+
+    /// Same as `to_pgn()`, but includes the given tag pairs (for example, the ones returned by
+    /// `GameState::from_pgn_with_headers()`). The `Result`, `Variant`, `SetUp` and `FEN` tags are always
+    /// generated from the current game.
+    pub fn to_pgn_with_headers(&self, headers: &PgnHeaders) -> String {
+        self.to_pgn_impl(headers, &[])
+    }
+
+    /// Same as `to_pgn_with_headers()`, but adds the annotations returned by `annotate_history()`: the NAG of
+    /// the classification (`$6`, `$2` or `$4`) and a comment with the best move, after each move that is not good.
+    pub fn to_annotated_pgn(&self, headers: &PgnHeaders, annotations: &[MoveAnnotation]) -> String {
+        self.to_pgn_impl(headers, annotations)
+    }
+
+    // The annotations are optional, and correspond to the first moves of the history
+    fn to_pgn_impl(&self, headers: &PgnHeaders, annotations: &[MoveAnnotation]) -> String {
+        let state = self.get_state();
+        let result = self.result_marker();
+        let get_header = |key: &str| headers.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+
+        let mut pgn = String::new();
+        for (key, default) in ROSTER_TAGS {
+            push_tag_pair(&mut pgn, key, get_header(key).unwrap_or(default));
+        }
+        push_tag_pair(&mut pgn, "Result", result);
+        let variant = KNOWN_VARIANTS.iter()
+            .find(|mode| VariantFactory::new(**mode).make_initial_state() == state.initial_state);
+        if let Some(variant) = variant {
+            if *variant != GameMode::Standard {
+                push_tag_pair(&mut pgn, "Variant", &variant.to_string());
+            }
+        }
+        if let Some(fen) = &state.initial_fen {
+            push_tag_pair(&mut pgn, "SetUp", "1");
+            push_tag_pair(&mut pgn, "FEN", fen);
+        }
+        for (key, value) in headers {
+            if !GENERATED_TAGS.contains(&key.as_str()) && !ROSTER_TAGS.iter().any(|(k, _)| k == key) {
+                push_tag_pair(&mut pgn, key, value);
+            }
+        }
+        pgn.push('\n');
+
+        // Movetext
+        let first_player = match &state.initial_fen {
+            Some(fen) => FenData::parse_fen_with_height(fen, state.initial_state.board_height).map_or(0, |fen| fen.player_to_move),
+            None => state.initial_state.player_to_move,
+        };
+        let mut tokens = Vec::with_capacity(self.get_notation().len() * 3 / 2 + 1);
+        // After a comment, the move number is repeated before black's move
+        let mut commented = false;
+        for (i, (notation, mv)) in self.get_notation().iter().zip(&state.move_history).enumerate() {
+            let ply = i + first_player as usize;
+            if ply.is_multiple_of(2) {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            } else if i == 0 || commented {
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(pgn_move_notation(notation, mv));
+            commented = false;
+            let nag = annotations.get(i).and_then(|a| a.classification.nag().map(|nag| (a, nag)));
+            if let Some((annotation, nag)) = nag {
+                tokens.push(format!("${nag}"));
+                if let Some(best_move) = annotation.best_line.first() {
+                    tokens.push(format!("{{Best: {}}}", pgn_move_notation(&annotation.best_notation, best_move)));
+                    commented = true;
+                }
+            }
+        }
+        tokens.push(result.to_string());
+
+        let mut line_length = 0;
+        for token in tokens {
+            if line_length > 0 && line_length + 1 + token.len() > MAX_LINE_LENGTH {
+                pgn.push('\n');
+                line_length = 0;
+            } else if line_length > 0 {
+                pgn.push(' ');
+                line_length += 1;
+            }
+            line_length += token.len();
+            pgn.push_str(&token);
+        }
+        pgn.push('\n');
+        pgn
+    }
+
+    // Returns the PGN result of the game: "1-0", "0-1", "1/2-1/2" or "*" if the game has not ended
+    fn result_marker(&self) -> &'static str {
+        let last_result = self.get_last_result();
+        match (last_result.flag, last_result.winner) {
+            (MakeMoveResultFlag::Ok | MakeMoveResultFlag::IllegalMove, _) => "*",
+            (_, MakeMoveResultWinner::White) => "1-0",
+            (_, MakeMoveResultWinner::Black) => "0-1",
+            (_, MakeMoveResultWinner::None) => "1/2-1/2",
+        }
+    }
+}
+
+// Parses a line like `[Event "Casual game"]`
+fn parse_tag_pair(line: &str) -> wrap_res!(String, String) {
+    let inner = line.strip_prefix('[').and_then(|l| l.strip_suffix(']'));
+    let (key, value) = match inner.and_then(|l| l.split_once(char::is_whitespace)) {
+        Some(parts) => parts,
+        None => err!("Invalid PGN tag pair: '{line}'"),
+    };
+    let value = value.trim();
+    let value = match value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        Some(value) => value.replace("\\\"", "\"").replace("\\\\", "\\"),
+        None => err!("Invalid PGN tag pair: '{line}' (the value must be quoted)"),
+    };
+    Ok((key.to_string(), value))
+}
+
+fn push_tag_pair(pgn: &mut String, key: &str, value: &str) {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    pgn.push_str(&format!("[{key} \"{value}\"]\n"));
+}
+
+// Splits the movetext into moves and result markers, skipping comments, variations,
+// move numbers and Numeric Annotation Glyphs
+fn movetext_tokens(movetext: &str) -> wrap_res!(Vec<String>) {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = movetext.chars();
+    let mut variation_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                // Comments can't be nested
+                if !chars.by_ref().any(|c| c == '}') {
+                    err!("Unterminated comment in PGN");
+                }
+            },
+            ';' => {
+                // Comment until the end of the line
+                chars.by_ref().find(|c| *c == '\n');
+            },
+            '(' => variation_depth += 1,
+            ')' => {
+                if variation_depth == 0 {
+                    err!("Unexpected ')' in PGN");
+                }
+                variation_depth -= 1;
+            },
+            _ if variation_depth > 0 => {},
+            c if c.is_whitespace() => {
+                push_token(&mut tokens, std::mem::take(&mut current));
+                continue;
+            },
+            c => {
+                current.push(c);
+                continue;
+            },
+        }
+        // Comments and variations also end the current token
+        push_token(&mut tokens, std::mem::take(&mut current));
+    }
+    if variation_depth > 0 {
+        err!("Unterminated variation in PGN");
+    }
+    push_token(&mut tokens, current);
+    Ok(tokens)
+}
+
+fn push_token(tokens: &mut Vec<String>, token: String) {
+    // Numeric Annotation Glyph, or en passant suffix separated by a space
+    if token.is_empty() || token.starts_with('$') || token == "e.p." {
+        return;
+    }
+    // Remove the move number ("12." or "12...") if present
+    let token = match token.rfind('.') {
+        Some(i) if token[..i+1].chars().all(|c| c.is_ascii_digit() || c == '.') => token[i+1..].to_string(),
+        _ => token,
+    };
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+}
+
+// Converts the notation from get_notation() to the notation expected by other PGN readers
+fn pgn_move_notation(notation: &str, mv: &MoveInfo) -> String {
+    // Remove the " e.p." suffix (it's not used in PGN, and the space would split the move)
+    let mut notation = notation.replace(EN_PASSANT_SUFFIX, "");
+    // Pawn captures must include the file of the pawn
+    if notation.starts_with('x') {
+        notation.insert(0, (b'a' + mv.from.0) as char);
+    }
+    notation
+}
@@ -1,10 +1,15 @@
 use crate::position::position_properties::PositionProperties;
-use crate::{InitialState, Position, wrap_res, err_assert, MakeMoveResultFlag, MoveInfo, MakeMoveResult};
+use crate::position::NotationCache;
+use crate::{InitialState, Position, wrap_res, err_assert, err, MakeMoveResultFlag, MoveInfo, MakeMoveResult};
 use crate::utils::to_index;
-use crate::types::BDimensions;
+use crate::types::{BDimensions, BIndex, ZobKey};
 
 use super::fen::FenData;
-use super::game_state::GameState;
+use super::game_state::{GameState, GameTermination};
+use super::game_tree::GameTree;
+
+// Maximum number of entries in the notation cache, it's cleared when full
+const NOTATION_CACHE_SIZE: usize = 10_000;
 
 /// This struct is responsible for creating Position objects from `GameState` objects.
 /// Do not create Position objects directly, use this factory instead.
@@ -15,6 +20,12 @@ pub struct PositionFactory {
     current_state: Option<GameState>,
     last_result: Option<MakeMoveResult>,
     move_notation: Vec<String>,
+    // Mainline and variations of the game. The move history of current_state is always a prefix
+    // of the selected line.
+    tree: GameTree,
+    // Speeds up replaying the move history when a new position needs to be created.
+    // Only valid for the variant of current_state.
+    notation_cache: NotationCache,
 }
 
 impl PositionFactory {    
@@ -23,7 +34,7 @@ impl PositionFactory {
     /// Returns:
     /// - `Err` If the state is invalid
     /// - `Ok(None)` If the current position can be reused. In this case, `reused_position` parameter
-    /// will be updated.
+    ///   will be updated.
     /// - `Ok(Some(position))` If a new position was created.
     pub fn set_state(&mut self, new_state: GameState, reused_position: Option<&mut Position>) -> wrap_res!(Option<Position>) {
         // No current state, just create a new position
@@ -36,7 +47,8 @@ impl PositionFactory {
         let current_state = self.current_state.as_ref().unwrap();
         let reused_position = reused_position.unwrap();
         if current_state.initial_state != new_state.initial_state
-        || current_state.initial_fen != new_state.initial_fen {
+        || current_state.initial_fen != new_state.initial_fen
+        || current_state.prior_fens != new_state.prior_fens {
             let position = self.set_state_impl(new_state)?;
             return Ok(Some(position));
         }
@@ -57,7 +69,7 @@ impl PositionFactory {
         
         // Apply the new moves
         for (i, mv) in new_state.move_history[reuse_count..].iter().enumerate() {
-            let result = reused_position.pub_make_move(mv);
+            let result = reused_position.pub_make_move_with_cache(mv, &mut self.notation_cache);
             
             // If an illegal move is encountered, roll back and return error
             // In order to roll back, we need to make the moves that were undone earlier
@@ -69,7 +81,7 @@ impl PositionFactory {
                 }
                 // Redo the moves that were undone earlier
                 for mv2 in &current_state.move_history[reuse_count..] {
-                    let result2 = reused_position.pub_make_move(mv2);
+                    let result2 = reused_position.pub_make_move_with_cache(mv2, &mut self.notation_cache);
                     err_assert!(result2.flag != MakeMoveResultFlag::IllegalMove, 
                         "Invalid move when attempting to rollback: {}", mv2);
                     self.move_notation.push(result2.move_notation.clone().unwrap());
@@ -81,6 +93,7 @@ impl PositionFactory {
             self.last_result = Some(result);
         }
         // Incremental update was successful, store the new state
+        self.tree = GameTree::new(new_state.move_history.clone(), self.move_notation.clone());
         self.current_state = Some(new_state);
         Ok(None)
     }
@@ -94,37 +107,76 @@ impl PositionFactory {
         let mut new_state = self.current_state.as_ref().unwrap().clone();
         new_state.initial_fen = Some(fen.to_string());
         new_state.move_history.clear();
+        new_state.prior_fens.clear();
+        new_state.termination = None;
         // When loading a FEN, always create a new position instead of reusing the current one
         self.set_state_impl(new_state)
     }
     
     fn set_state_impl(&mut self, state: GameState) -> wrap_res!(Position) {
-        // Parse the variant's default starting position
-        let mut fen_data = FenData::parse_fen(&state.initial_state.fen)?;
-        fen_data.player_to_move = state.initial_state.player_to_move;
-        // Apply the user-proveded initial fen, if any
-        if let Some(initial_fen) = &state.initial_fen {
-            let old_fen = fen_data;
-            fen_data = FenData::parse_fen(initial_fen)?;
-            // Don't allow the user to override the walls
-            fen_data.walls = old_fen.walls;
-        }
+        let fen_data = Self::parse_fen(&state.initial_state, state.initial_fen.as_deref())?;
         let mut pos = Self::create_new_position(&state.initial_state, fen_data)?;
+        let prior_keys = Self::prior_keys(&state, &pos)?;
+        pos.set_prior_keys(prior_keys);
+        
+        // The cached moves are only valid for the same variant
+        let same_variant = self.current_state.as_ref()
+            .is_some_and(|current| current.initial_state == state.initial_state);
+        if !same_variant || self.notation_cache.len() > NOTATION_CACHE_SIZE {
+            self.notation_cache.clear();
+        }
         
         // Apply the move history
         self.move_notation.clear();
+        self.last_result = None;
         for m in &state.move_history {
-            let result = pos.pub_make_move(m);
-            err_assert!(result.flag != MakeMoveResultFlag::IllegalMove, "Invalid move: {}", m);
+            let result = pos.pub_make_move_with_cache(m, &mut self.notation_cache);
+            if result.flag == MakeMoveResultFlag::IllegalMove {
+                // The cache could now contain moves from a different variant than current_state
+                self.notation_cache.clear();
+                err!("Invalid move: {}", m);
+            }
             self.move_notation.push(result.move_notation.clone().unwrap());
             self.last_result = Some(result);
         }
         // Everything went well, store the new state
+        self.tree = GameTree::new(state.move_history.clone(), self.move_notation.clone());
         self.current_state = Some(state);
         Ok(pos)
     }
     
     
+    // Parses the variant's default starting position, or the given FEN (keeping the walls of the variant)
+    fn parse_fen(initial_state: &InitialState, fen: Option<&str>) -> wrap_res!(FenData) {
+        let height = initial_state.board_height;
+        let mut fen_data = FenData::parse_fen_with_height(&initial_state.fen, height)?;
+        fen_data.player_to_move = initial_state.player_to_move;
+        // Apply the user-proveded fen, if any
+        if let Some(fen) = fen {
+            let old_fen = fen_data;
+            fen_data = FenData::parse_fen_with_height(fen, height)?;
+            // Don't allow the user to override the walls
+            fen_data.walls = old_fen.walls;
+        }
+        Ok(fen_data)
+    }
+    
+    // Zobrist keys of the `prior_fens` of the state (newest first), until the number of pieces on the board
+    // changes: only the positions since the last capture can be repeated
+    fn prior_keys(state: &GameState, initial_pos: &Position) -> wrap_res!(Vec<ZobKey>) {
+        let mut keys = Vec::new();
+        let num_pieces = initial_pos.occ_or_out_bounds.count_ones();
+        for fen in state.prior_fens.iter().rev() {
+            let fen_data = Self::parse_fen(&state.initial_state, Some(fen)).map_err(|e| format!("Invalid prior FEN '{fen}': {e}"))?;
+            let pos = Self::create_new_position(&state.initial_state, fen_data)?;
+            if pos.occ_or_out_bounds.count_ones() != num_pieces {
+                break;
+            }
+            keys.push(pos.get_zobrist());
+        }
+        Ok(keys)
+    }
+    
     /// Returns the current `GameState`
     pub fn get_state(&self) -> &GameState {
         if let Some(state) = &self.current_state {
@@ -139,9 +191,34 @@ impl PositionFactory {
         &self.move_notation
     }
     
+    /// Returns the algebraic notation of the `ply`-th move of the move history (starting at 1), the one that
+    /// leads to the position of `fen_at_ply(ply)`
+    pub fn notation_at_ply(&self, ply: usize) -> wrap_res!(&str) {
+        let num_plies = self.move_notation.len();
+        err_assert!(ply >= 1 && ply <= num_plies, "Invalid ply {}, the move history has {} plies", ply, num_plies);
+        Ok(&self.move_notation[ply - 1])
+    }
+    
+    /// Returns the FEN of the position after the first `ply` moves of the move history (0 for the initial position).
+    /// `position` must be the current position, it's not modified.
+    pub fn fen_at_ply(&self, ply: usize, position: &Position) -> wrap_res!(String) {
+        let num_plies = self.get_state().move_history.len();
+        err_assert!(ply <= num_plies, "Invalid ply {}, the move history has {} plies", ply, num_plies);
+        // Undo the later moves on a copy of the position, instead of replaying the game from the start
+        let mut pos = position.clone();
+        for _ in ply..num_plies {
+            pos.unmake_move();
+        }
+        Ok(FenData::from(&pos).to_string())
+    }
+    
     /// Returns the result of the last move in `state.move_history`, or `Ok` if
-    /// this information is not known
+    /// this information is not known. If the game has been terminated, returns the result of the termination.
     pub fn get_last_result(&self) -> MakeMoveResult {
+        if let Some(termination) = self.get_termination() {
+            let notation = self.move_notation.last().cloned().unwrap_or_default();
+            return MakeMoveResult::game_over(termination.result(), Vec::new(), notation);
+        }
         if let Some(result) = &self.last_result {
             result.clone()
         } else {
@@ -149,10 +226,38 @@ impl PositionFactory {
         }
     }
     
+    /// Returns how the game was terminated by the players or an arbiter, if it was
+    pub fn get_termination(&self) -> Option<GameTermination> {
+        self.current_state.as_ref().and_then(|state| state.termination)
+    }
+    
+    /// Marks the game as terminated (or not) in the current `GameState`
+    pub fn set_termination(&mut self, termination: Option<GameTermination>) {
+        if let Some(state) = &mut self.current_state {
+            state.termination = termination;
+        } else {
+            panic!("No current state, call make_position() first");
+        }
+    }
+    
+    /// Returns the moves of the selected line of the game tree, including the ones that have been undone
+    /// with `remove_last_move()`
+    pub fn get_full_history(&self) -> &[MoveInfo] {
+        self.tree.get_current_moves()
+    }
+    
+    /// Returns the mainline and the variations of the game
+    pub fn get_game_tree(&self) -> &GameTree {
+        &self.tree
+    }
+    
     /// Adds a new move to the move history of the current `GameState`
-    /// Call this whenever a move is made to keep the stored `GameState` in sync
+    /// Call this whenever a move is made to keep the stored `GameState` in sync.
+    /// If the move is not the next one in the selected line, another line is selected (see `GameTree::play()`).
     pub fn add_move(&mut self, m: &MoveInfo, result: &MakeMoveResult) {
         if let Some(state) = &mut self.current_state {
+            let notation = result.move_notation.clone().unwrap_or_default();
+            self.tree.play(state.move_history.len(), m, notation);
             state.move_history.push(*m);
         } else {
             panic!("No current state, call make_position() first");
@@ -164,25 +269,80 @@ impl PositionFactory {
     }
     
     /// Removes the last move from the move history of the current `GameState`
-    /// Call this whenever a move is undone to keep the stored `GameState` in sync
+    /// Call this whenever a move is undone to keep the stored `GameState` in sync.
+    /// The move is kept in the full history, so that it can be made again.
     pub fn remove_last_move(&mut self) {
         if let Some(state) = &mut self.current_state {
             state.move_history.pop();
         } else {
             panic!("No current state, call make_position() first");
         }
+        self.move_notation.pop();
         self.last_result = None;
     }
     
+    /// Adds a variation that branches from the selected line after `at_ply` moves, and returns its index.
+    /// `position` must be the current position, it's not modified. Returns an error if a move is illegal.
+    pub fn add_variation(&mut self, at_ply: usize, moves: &[MoveInfo], position: &Position) -> wrap_res!(usize) {
+        let line_moves = self.tree.get_current_moves().to_vec();
+        err_assert!(at_ply <= line_moves.len(), "Cannot add a variation at ply {}, the line only has {} plies", at_ply, line_moves.len());
+        err_assert!(!moves.is_empty(), "The variation must contain at least one move");
+        // Play the variation on a copy of the position, starting from the branching point
+        let mut pos = position.clone();
+        let mut ply = self.get_state().move_history.len();
+        while ply > at_ply {
+            pos.unmake_move();
+            ply -= 1;
+        }
+        for mv in &line_moves[ply..at_ply] {
+            let _result = pos.pub_make_move_with_cache(mv, &mut self.notation_cache);
+        }
+        let mut notation = Vec::with_capacity(moves.len());
+        for mv in moves {
+            let result = pos.pub_make_move_with_cache(mv, &mut self.notation_cache);
+            if result.flag == MakeMoveResultFlag::IllegalMove {
+                err!("Invalid move: {}", mv);
+            }
+            notation.push(result.move_notation.unwrap());
+        }
+        Ok(self.tree.add_variation(self.tree.get_current_line(), at_ply, moves.to_vec(), notation))
+    }
+    
+    /// Selects another line of the game tree, and updates `position` incrementally.
+    /// If the current position is part of the new line, it doesn't change. Otherwise, goes to the first move
+    /// of the new line that is not in the old one.
+    pub fn select_line(&mut self, index: usize, position: &mut Position) -> wrap_res!() {
+        let num_lines = self.tree.get_lines().len();
+        err_assert!(index < num_lines, "Invalid line {}, there are {} lines", index, num_lines);
+        let new_moves = self.tree.line_moves(index);
+        let common = new_moves.iter().zip(self.tree.get_current_moves()).take_while(|(m1, m2)| m1 == m2).count();
+        let current_ply = self.get_state().move_history.len();
+        let ply = if current_ply <= common { current_ply } else { new_moves.len().min(common + 1) };
+        
+        let mut new_state = self.get_state().clone();
+        new_state.move_history = new_moves[..ply].to_vec();
+        // set_state() replaces the tree, keep the current one
+        let tree = std::mem::take(&mut self.tree);
+        let result = self.set_state(new_state, Some(position));
+        self.tree = tree;
+        if let Some(new_position) = result? {
+            *position = new_position;
+        }
+        self.tree.select_line(index);
+        Ok(())
+    }
+    
     /// Creates a new position from scratch, using the following data:
     /// - **Board height and width:** From `InitialState`
     /// - **Piece definitions:** From `InitialState`
     /// - **Global rules:** From `InitialState`
-    /// - **Piece placements and Walls:** From `FenData`
+    /// - **Piece placements, frozen pieces and Walls:** From `FenData`
     /// - **Player to move:** From `FenData`
     /// - **Castling availability:** From `FenData`
     /// - **EP square and victim:** From `FenData`
     /// - **Times in check:** From `FenData`
+    /// - **Halfmove clock:** From `FenData`
+    /// - **Pieces in hand:** From `FenData`
     fn create_new_position(state: &InitialState, fen: FenData) -> wrap_res!(Position) {
         
         let dims = BDimensions::from_walls(state.board_width, state.board_height, &fen.walls)?;
@@ -194,16 +354,26 @@ impl PositionFactory {
         
         // Update props
         let mut props = PositionProperties::default();
-        if let Some(((sx,sy),(vx,vy))) = fen.ep_square_and_victim {
-            err_assert!(dims.in_bounds(sx, sy), "Invalid EP square: ({sx}, {sy})");
-            err_assert!(dims.in_bounds(vx, vy), "Invalid EP victim: ({vx}, {vy})");
-            props.set_ep_square(to_index(sx, sy), to_index(vx, vy));
+        if let Some((ep_squares, (vx,vy))) = &fen.ep_squares_and_victim {
+            for &(sx, sy) in ep_squares {
+                err_assert!(dims.in_bounds(sx, sy), "Invalid EP square: ({sx}, {sy})");
+            }
+            err_assert!(dims.in_bounds(*vx, *vy), "Invalid EP victim: ({vx}, {vy})");
+            // The EP squares must be evenly spaced on a line, like the squares passed over by a double jump
+            let mut indexes: Vec<BIndex> = ep_squares.iter().map(|&(sx, sy)| to_index(sx, sy)).collect();
+            indexes.sort_unstable();
+            indexes.dedup();
+            let evenly_spaced = indexes.windows(3).all(|w| w[1] - w[0] == w[2] - w[1]);
+            err_assert!(evenly_spaced && indexes.len() <= 64, "The EP squares must be evenly spaced on a line");
+            props.set_ep_squares(indexes[0], *indexes.last().unwrap(), indexes.len() as u32, to_index(*vx, *vy));
         }
         if fen.player_to_move == 1 {
             // Use the lowest bit as player zobrist key
             props.zobrist_key ^= 1;
         }
         props.times_in_check = fen.times_in_check.unwrap_or([0,0]);
+        props.halfmove_clock = fen.halfmove_clock;
+        props.fullmove_number = fen.fullmove_number;
 
         // Instantiate position and register piecetypes
         let mut pos = Position::new(dims, fen.player_to_move, props, state.global_rules.clone());
@@ -211,15 +381,26 @@ impl PositionFactory {
             pos.register_piecetype(definition)?;
         }
         pos.assert_promotion_consistency()?;
+        pos.assert_win_conditions()?;
+        pos.assert_square_effects()?;
         
         // Add pieces
         for p in fen.piece_placements {
-            let can_castle = {
-                if fen.castling_availability.is_none() { true }
-                else { fen.castling_availability.as_ref().unwrap().contains(&(p.x, p.y)) }
+            let can_castle = match &fen.castling_availability {
+                None => true,
+                Some(castling) => castling.contains(&(p.x, p.y)),
             };
-            pos.public_add_piece(p.piece_id, to_index(p.x, p.y), can_castle)?;
+            // Frozen pieces cannot castle
+            pos.public_add_piece(p.piece_id, to_index(p.x, p.y), can_castle && !p.frozen)?;
+            if p.frozen {
+                pos.public_freeze_piece(to_index(p.x, p.y))?;
+            }
+        }
+        for piece_id in fen.pocket.unwrap_or_default() {
+            pos.public_add_to_hand(piece_id)?;
         }
+        // The number of checks and the turn progress are not included in the key of the initial properties
+        pos.reset_zobrist();
         Ok(pos)
     }
 }
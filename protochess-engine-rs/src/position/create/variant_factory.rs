@@ -43,4 +43,66 @@ impl VariantFactory {
             global_rules,
         }
     }
+    
+    /// Names of the built-in variants that are not a `GameMode`, see `make_fairy_variant()`
+    pub const FAIRY_VARIANTS: [&'static str; 4] = ["capablanca", "gothic", "crazyhouse-10x8", "los-alamos"];
+    
+    /// Creates the `InitialState` of a built-in variant that is not a `GameMode`, or `None` if the name
+    /// is not one of `FAIRY_VARIANTS`
+    pub fn make_fairy_variant(name: &str) -> Option<InitialState> {
+        match name {
+            "capablanca" => Some(Self::make_10x8_variant("rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR", GameMode::Standard)),
+            "gothic" => Some(Self::make_10x8_variant("rnbqckabnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNBQCKABNR", GameMode::Standard)),
+            "crazyhouse-10x8" => Some(Self::make_10x8_variant("rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR", GameMode::Crazyhouse)),
+            "los-alamos" => Some(Self::make_los_alamos()),
+            _ => None,
+        }
+    }
+    
+    // Capablanca-like variants: 10x8 board with an archbishop and a chancellor, the king starts on the f-file
+    fn make_10x8_variant(fen: &str, mode: GameMode) -> InitialState {
+        let factory = PieceFactory::new(mode);
+        let mut piece_types = factory.make_piece_set(10, 8);
+        for piece in &mut piece_types {
+            if piece.is_leader {
+                piece.castle_files = Some((2, 8));
+            }
+            if !piece.promotion_squares.is_empty() {
+                piece.promo_vals[0].extend(['A', 'C']);
+                piece.promo_vals[1].extend(['a', 'c']);
+            }
+        }
+        piece_types.push(factory.make_archbishop());
+        piece_types.push(factory.make_chancellor());
+        InitialState {
+            fen: fen.to_string(),
+            player_to_move: 0,
+            piece_types,
+            board_width: 10,
+            board_height: 8,
+            global_rules: GlobalRules::for_mode(mode),
+        }
+    }
+    
+    // 6x6 board without bishops, castling or pawn double moves
+    fn make_los_alamos() -> InitialState {
+        let factory = PieceFactory::new(GameMode::Standard);
+        let mut piece_types = factory.make_piece_set(6, 6);
+        piece_types.retain(|piece| piece.ids != [Some('B'), Some('b')]);
+        for piece in &mut piece_types {
+            piece.castle_files = None;
+            piece.double_jump_squares.clear();
+            for promo_vals in &mut piece.promo_vals {
+                promo_vals.retain(|id| !id.eq_ignore_ascii_case(&'b'));
+            }
+        }
+        InitialState {
+            fen: "rnqknr/pppppp/6/6/PPPPPP/RNQKNR".to_string(),
+            player_to_move: 0,
+            piece_types,
+            board_width: 6,
+            board_height: 6,
+            global_rules: GlobalRules::for_mode(GameMode::Standard),
+        }
+    }
 }
@@ -0,0 +1,199 @@
+// Generation of random variants ("random armies"), also useful to test the engine with unusual rules
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{InitialState, GameState, GlobalRules, PieceDefinition, MoveGen, wrap_res, err_assert};
+use crate::piece::{PieceFactory, compute_material_score_with, MATERIAL_WEIGHTS};
+use crate::types::{BCoord, BDimensions, Centipawns, GameMode, MAX_BOARD_WIDTH, MAX_BOARD_HEIGHT};
+
+use super::position_factory::PositionFactory;
+
+/// Constraints of the variants created by `VariantGenerator`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantConstraints {
+    /// Range of the board width (inclusive)
+    pub width: (BCoord, BCoord),
+    /// Range of the board height (inclusive). There must be at least 2 empty ranks between the armies, so the
+    /// minimum height is 4 (6 with pawns).
+    pub height: (BCoord, BCoord),
+    /// Maximum material score of the pieces of each player, excluding the leader and the pawns.
+    /// The scores are computed with `compute_material_score_with()` for the size of the generated board.
+    pub material_budget: Centipawns,
+    /// If true, each player has a leader (a king). Otherwise, each variant has a 50% chance of having leaders,
+    /// and a player without a leader loses when all their pieces are captured.
+    pub require_leader: bool,
+    /// If true, the black army is a mirror of the white army. Otherwise, each army is generated separately
+    /// (with the same material budget).
+    pub symmetric: bool,
+    /// If true, the second rank of each player is filled with pawns, which can promote to the pieces of `piece_pool`
+    pub pawns: bool,
+    /// Pieces that can be placed on the back rank, they cannot be leaders. Each piece must have an id for each player.
+    pub piece_pool: Vec<PieceDefinition>,
+}
+
+impl Default for VariantConstraints {
+    fn default() -> Self {
+        let factory = PieceFactory::default();
+        VariantConstraints {
+            width: (6, 10),
+            height: (6, 10),
+            material_budget: 4000,
+            require_leader: true,
+            symmetric: true,
+            pawns: true,
+            piece_pool: vec![
+                factory.make_queen(),
+                factory.make_rook(),
+                factory.make_bishop(),
+                factory.make_knight(),
+                factory.make_archbishop(),
+                factory.make_chancellor(),
+            ],
+        }
+    }
+}
+
+/// Creates random playable variants under some constraints (see `VariantConstraints`). The leader and the
+/// pieces are placed on the back rank of each player, and the pawns (if any) on the next rank.
+/// The sequence of variants only depends on the constraints and the seed.
+#[derive(Debug, Clone)]
+pub struct VariantGenerator {
+    constraints: VariantConstraints,
+    rng: StdRng,
+}
+
+impl VariantGenerator {
+    /// Returns an error if the constraints are not valid
+    pub fn new(constraints: VariantConstraints, seed: u64) -> wrap_res!(Self) {
+        let (min_width, max_width) = constraints.width;
+        let (min_height, max_height) = constraints.height;
+        err_assert!(min_width >= 1 && min_width <= max_width && max_width <= MAX_BOARD_WIDTH,
+            "Invalid range of board widths: {}..={} (the maximum width is {})", min_width, max_width, MAX_BOARD_WIDTH);
+        let army_ranks = if constraints.pawns { 2 } else { 1 };
+        err_assert!(min_height >= 2 * army_ranks + 2 && min_height <= max_height && max_height <= MAX_BOARD_HEIGHT,
+            "Invalid range of board heights: {}..={} (it must be between {} and {})", min_height, max_height, 2 * army_ranks + 2, MAX_BOARD_HEIGHT);
+        err_assert!(constraints.material_budget >= 0, "The material budget cannot be negative");
+        for piece in &constraints.piece_pool {
+            err_assert!(!piece.is_leader, "The piece pool cannot contain leaders");
+            err_assert!(piece.ids.iter().all(Option::is_some), "Each piece of the pool must have an id for each player");
+            err_assert!(piece.ids.iter().flatten().all(|id| !"KkPp".contains(*id)), "The ids 'K' and 'P' are reserved for the leader and the pawns");
+        }
+        err_assert!(!constraints.pawns || !constraints.piece_pool.is_empty(), "The pawns need a piece to promote to");
+        Ok(VariantGenerator { constraints, rng: StdRng::seed_from_u64(seed) })
+    }
+
+    /// Creates the next variant. Returns an error if it's not playable (e.g. the first player has no legal
+    /// moves), which can only happen with unusual piece pools.
+    pub fn generate(&mut self) -> wrap_res!(GameState) {
+        let c = &self.constraints;
+        let width = self.rng.gen_range(c.width.0, c.width.1 + 1);
+        let height = self.rng.gen_range(c.height.0, c.height.1 + 1);
+        let dims = BDimensions::new_without_walls(width, height)?;
+        let scores: Vec<Centipawns> = c.piece_pool.iter()
+            .map(|piece| compute_material_score_with(piece, &dims, &MATERIAL_WEIGHTS))
+            .collect();
+        let has_leader = c.require_leader || self.rng.gen_bool(0.5);
+
+        let white_rank = self.random_rank(width, &scores, has_leader);
+        let black_rank = if self.constraints.symmetric { white_rank.clone() } else { self.random_rank(width, &scores, has_leader) };
+        let initial_state = self.make_initial_state(&dims, &white_rank, &black_rank, has_leader);
+
+        let state = GameState { initial_state, initial_fen: None, move_history: vec![], prior_fens: vec![], termination: None };
+        let mut position = PositionFactory::default().set_state(state.clone(), None)?.unwrap();
+        err_assert!(!MoveGen::get_legal_moves(&mut position).is_empty(), "The generated variant has no legal moves");
+        Ok(state)
+    }
+
+    // Back rank of a player: None for empty squares, Some(None) for the leader, Some(Some(i)) for piece_pool[i]
+    fn random_rank(&mut self, width: BCoord, scores: &[Centipawns], has_leader: bool) -> Vec<Option<Option<usize>>> {
+        let mut rank = vec![None; width as usize];
+        let mut free_squares: Vec<usize> = (0..width as usize).collect();
+        if has_leader {
+            let square = free_squares.remove(self.rng.gen_range(0, free_squares.len()));
+            rank[square] = Some(None);
+        }
+        // Place random pieces that fit in the remaining budget, until the rank is full
+        let mut budget = self.constraints.material_budget;
+        while !free_squares.is_empty() {
+            let affordable: Vec<usize> = (0..scores.len()).filter(|&i| scores[i] <= budget).collect();
+            if affordable.is_empty() {
+                break;
+            }
+            let piece = affordable[self.rng.gen_range(0, affordable.len())];
+            let square = free_squares.remove(self.rng.gen_range(0, free_squares.len()));
+            rank[square] = Some(Some(piece));
+            budget -= scores[piece];
+        }
+        rank
+    }
+
+    fn make_initial_state(&self, dims: &BDimensions, white_rank: &[Option<Option<usize>>], black_rank: &[Option<Option<usize>>], has_leader: bool) -> InitialState {
+        let c = &self.constraints;
+        let (width, height) = (dims.width, dims.height);
+        let factory = PieceFactory::default();
+        let mut piece_types = c.piece_pool.clone();
+        if has_leader {
+            let mut king = factory.make_king(width, height);
+            // The rooks can be anywhere, so castling is not possible
+            king.castle_files = None;
+            piece_types.push(king);
+        }
+        if c.pawns {
+            for is_white in [true, false] {
+                let mut pawn = factory.make_pawn(is_white, width, height);
+                let player = if is_white { 0 } else { 1 };
+                pawn.promo_vals[player] = c.piece_pool.iter().filter_map(|piece| piece.ids[player]).collect();
+                piece_types.push(pawn);
+            }
+        }
+
+        let id_of = |square: &Option<Option<usize>>, player: usize| match square {
+            None => None,
+            Some(None) => Some(if player == 0 { 'K' } else { 'k' }),
+            Some(Some(i)) => c.piece_pool[*i].ids[player],
+        };
+        let mut ranks = vec![vec![None; width as usize]; height as usize];
+        for x in 0..width as usize {
+            ranks[0][x] = id_of(&white_rank[x], 0);
+            ranks[height as usize - 1][x] = id_of(&black_rank[x], 1);
+            if c.pawns {
+                ranks[1][x] = Some('P');
+                ranks[height as usize - 2][x] = Some('p');
+            }
+        }
+        // FEN ranks go from the top to the bottom
+        let fen = ranks.iter().rev().map(|rank| fen_rank(rank)).collect::<Vec<_>>().join("/");
+
+        InitialState {
+            fen,
+            player_to_move: 0,
+            piece_types,
+            board_width: width,
+            board_height: height,
+            global_rules: GlobalRules::for_mode(GameMode::Standard),
+        }
+    }
+}
+
+// Writes a rank in FEN format, with the number of consecutive empty squares
+fn fen_rank(rank: &[Option<char>]) -> String {
+    let mut fen = String::new();
+    let mut empty = 0;
+    for square in rank {
+        match square {
+            Some(id) => {
+                if empty > 0 {
+                    fen.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                fen.push(*id);
+            },
+            None => empty += 1,
+        }
+    }
+    if empty > 0 {
+        fen.push_str(&empty.to_string());
+    }
+    fen
+}
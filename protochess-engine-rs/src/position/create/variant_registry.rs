@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::sync::{PoisonError, RwLock};
+
+use crate::{InitialState, GameState, wrap_res, err, err_assert};
+use crate::types::GameMode;
+
+use super::position_factory::PositionFactory;
+use super::variant_factory::VariantFactory;
+
+lazy_static! {
+    // Variants registered at runtime, by lowercase name
+    static ref CUSTOM_VARIANTS: RwLock<HashMap<String, InitialState>> = RwLock::new(HashMap::new());
+}
+
+/// Global registry of named variants, used by `GameState::from_variant_name()`.
+/// It contains the built-in variants (each `GameMode` and `VariantFactory::FAIRY_VARIANTS`), and the
+/// variants registered at runtime with `register()`. Names are case-insensitive.
+pub struct VariantRegistry;
+
+impl VariantRegistry {
+    /// Registers a variant, replacing the variant previously registered with the same name.
+    /// Returns an error if the name is empty, it's the name of a built-in variant, or the initial state is invalid.
+    pub fn register(name: &str, variant: InitialState) -> wrap_res!() {
+        let name = normalize(name);
+        err_assert!(!name.is_empty(), "The name of the variant cannot be empty");
+        err_assert!(builtin_variant(&name).is_none(), "'{}' is the name of a built-in variant", name);
+        let state = GameState { initial_state: variant.clone(), initial_fen: None, move_history: Vec::new(), prior_fens: Vec::new(), termination: None };
+        if let Err(e) = PositionFactory::default().set_state(state, None) {
+            err!("Invalid variant '{}': {}", name, e);
+        }
+        let mut variants = CUSTOM_VARIANTS.write().unwrap_or_else(PoisonError::into_inner);
+        variants.insert(name, variant);
+        Ok(())
+    }
+
+    /// Removes a variant registered with `register()`. Returns `false` if there was no such variant.
+    pub fn unregister(name: &str) -> bool {
+        let mut variants = CUSTOM_VARIANTS.write().unwrap_or_else(PoisonError::into_inner);
+        variants.remove(&normalize(name)).is_some()
+    }
+
+    /// Returns the initial state of the variant with the given name
+    pub fn get(name: &str) -> wrap_res!(InitialState) {
+        let name = normalize(name);
+        if let Some(variant) = builtin_variant(&name) {
+            return Ok(variant);
+        }
+        let variants = CUSTOM_VARIANTS.read().unwrap_or_else(PoisonError::into_inner);
+        match variants.get(&name) {
+            Some(variant) => Ok(variant.clone()),
+            None => err!("Unknown variant '{}'", name),
+        }
+    }
+
+    /// Returns the name of the built-in variant with the given initial state, if there is one
+    pub fn builtin_name_of(variant: &InitialState) -> Option<String> {
+        let mut names = GameMode::ALL.iter().map(|mode| normalize(&mode.to_string()))
+            .chain(VariantFactory::FAIRY_VARIANTS.iter().map(|name| name.to_string()));
+        names.find(|name| builtin_variant(name).as_ref() == Some(variant))
+    }
+
+    /// Returns the names of all the variants: first the built-in ones, then the registered ones in alphabetical order
+    pub fn names() -> Vec<String> {
+        let mut names: Vec<String> = GameMode::ALL.iter().map(|mode| normalize(&mode.to_string())).collect();
+        names.extend(VariantFactory::FAIRY_VARIANTS.iter().map(|name| name.to_string()));
+        let variants = CUSTOM_VARIANTS.read().unwrap_or_else(PoisonError::into_inner);
+        let mut custom: Vec<String> = variants.keys().cloned().collect();
+        custom.sort();
+        names.extend(custom);
+        names
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+fn builtin_variant(name: &str) -> Option<InitialState> {
+    match GameMode::try_from(name) {
+        Ok(mode) => Some(VariantFactory::new(mode).make_initial_state()),
+        Err(_) => VariantFactory::make_fairy_variant(name),
+    }
+}
@@ -0,0 +1,127 @@
+// Encoding of positions as stacks of planes, the usual input of neural network evaluators
+
+use crate::{PieceId, wrap_res, err, err_assert};
+use crate::types::{BCoord, Player};
+use crate::utils::to_index;
+
+use super::Position;
+
+/// Number of planes after the piece planes, see `FeaturePlanes`
+pub const NUM_AUX_PLANES: usize = 7;
+
+/// Positions encoded as planes of `height x width` values, returned by `Position::to_feature_planes()`.
+/// The planes of each position are, in order:
+/// - One plane for each piece type in `piece_types`: 1 on the squares occupied by that piece, 0 elsewhere.
+///   The white pieces come first, then the black pieces.
+/// - Castling: 1 on the squares of the white pieces that can still castle, then the same for black.
+/// - En passant: 1 on the squares where an en passant capture is possible.
+/// - Side to move: all 1 if black is to move, all 0 if white is to move.
+/// - Checks: the number of times that white has been in check (in all the squares), then the same for black.
+///   Always 0 if the variant doesn't limit the number of checks.
+/// - Walls: 1 on the squares that are out of bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeaturePlanes {
+    /// Number of positions
+    pub batch_size: usize,
+    /// Number of planes of each position, `piece_types.len() + NUM_AUX_PLANES`
+    pub num_planes: usize,
+    pub height: usize,
+    pub width: usize,
+    /// Player and id of the piece type of each piece plane
+    pub piece_types: Vec<(Player, PieceId)>,
+    /// Values of the planes, the value of square (x, y) of plane `p` of position `b` is at
+    /// `((b * num_planes + p) * height + y) * width + x`
+    pub data: Vec<f32>,
+}
+
+impl Position {
+    /// Encodes the position as planes, see `FeaturePlanes`
+    pub fn to_feature_planes(&self) -> FeaturePlanes {
+        let mut planes = self.empty_planes(1);
+        self.write_planes(&mut planes.data);
+        planes
+    }
+
+    /// Encodes a batch of positions as planes, see `FeaturePlanes`. Returns an error if the list is empty, or if the
+    /// positions don't have the same board size and piece types (the layout of the planes would be different).
+    pub fn batch_feature_planes(positions: &[Position]) -> wrap_res!(FeaturePlanes) {
+        let first = match positions.first() {
+            Some(first) => first,
+            None => err!("The batch must contain at least 1 position"),
+        };
+        let mut planes = first.empty_planes(positions.len());
+        let position_size = planes.num_planes * planes.height * planes.width;
+        for (position, data) in positions.iter().zip(planes.data.chunks_mut(position_size)) {
+            err_assert!(position.dimensions.width == first.dimensions.width && position.dimensions.height == first.dimensions.height,
+                "All the positions of the batch must have the same board size");
+            err_assert!(position.piece_types() == planes.piece_types, "All the positions of the batch must have the same piece types");
+            position.write_planes(data);
+        }
+        Ok(planes)
+    }
+
+    fn piece_types(&self) -> Vec<(Player, PieceId)> {
+        self.pieces.iter()
+            .flat_map(|ps| ps.iter().map(|piece| (piece.get_player(), piece.get_piece_id())))
+            .collect()
+    }
+
+    fn empty_planes(&self, batch_size: usize) -> FeaturePlanes {
+        let piece_types = self.piece_types();
+        let num_planes = piece_types.len() + NUM_AUX_PLANES;
+        let (width, height) = (self.dimensions.width as usize, self.dimensions.height as usize);
+        FeaturePlanes {
+            batch_size,
+            num_planes,
+            height,
+            width,
+            piece_types,
+            data: vec![0.0; batch_size * num_planes * height * width],
+        }
+    }
+
+    // Writes the planes of this position to `data`
+    fn write_planes(&self, data: &mut [f32]) {
+        let (width, height) = (self.dimensions.width, self.dimensions.height);
+        let plane_size = width as usize * height as usize;
+        let mut plane = 0;
+        let mut fill = |plane: usize, value: &dyn Fn(BCoord, BCoord) -> f32| {
+            let plane_data = &mut data[plane * plane_size..(plane + 1) * plane_size];
+            for y in 0..height {
+                for x in 0..width {
+                    plane_data[y as usize * width as usize + x as usize] = value(x, y);
+                }
+            }
+        };
+        // Piece planes
+        for piece in self.pieces.iter().flat_map(|ps| ps.iter()) {
+            fill(plane, &|x, y| if piece.is_at_index(to_index(x, y)) { 1.0 } else { 0.0 });
+            plane += 1;
+        }
+        // Castling
+        for ps in &self.pieces {
+            fill(plane, &|x, y| {
+                let index = to_index(x, y);
+                let can_castle = ps.piece_at(index).is_some_and(|piece| piece.used_in_castling() && piece.has_not_moved(index));
+                if can_castle { 1.0 } else { 0.0 }
+            });
+            plane += 1;
+        }
+        // En passant
+        let ep_squares: Vec<_> = self.get_ep_squares().collect();
+        fill(plane, &|x, y| if ep_squares.contains(&to_index(x, y)) { 1.0 } else { 0.0 });
+        plane += 1;
+        // Side to move
+        let side = self.whos_turn as f32;
+        fill(plane, &|_, _| side);
+        plane += 1;
+        // Checks
+        let checks = self.get_times_checked().copied().unwrap_or_default();
+        for times_checked in checks {
+            fill(plane, &|_, _| times_checked as f32);
+            plane += 1;
+        }
+        // Walls
+        fill(plane, &|x, y| if self.dimensions.in_bounds(x, y) { 0.0 } else { 1.0 });
+    }
+}
@@ -1,14 +1,97 @@
-use crate::types::GameMode;
+use crate::types::{BCoord, GameMode};
+use crate::piece::PieceId;
 
+/// Decides when a player loses because their leader pieces have been captured
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum LeaderCaptureRule {
+    /// Lose when all the leaders have been captured. The player is only in check when a single leader is left.
+    #[default]
+    AllLeaders,
+    /// Lose as soon as any leader is captured (e.g. Two-Kings variants)
+    AnyLeader,
+    /// Lose when all the pieces of any leader type have been captured (e.g. Extinction chess, where all the
+    /// piece types are leaders). Leader types that are not on the board in the initial position are ignored.
+    AnyLeaderType,
+}
+
+/// Result of the game when the player to move has no legal moves but is not in check, see `GlobalRules::stalemate_rule`.
+/// Like the other ways of winning, the winner is swapped if `GlobalRules::invert_win_conditions` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum StalemateRule {
+    /// The game is a draw (standard chess)
+    #[default]
+    Draw,
+    /// The stalemated player loses
+    StalematedLoses,
+    /// The stalemated player wins
+    StalematedWins,
+    /// The player with more material wins, or it is a draw if both have the same (like when `move_limit` is reached)
+    MaterialAdjudication,
+}
+
+impl StalemateRule {
+    // Also accepts the `stalematedPlayerLoses` boolean of the game states saved by older versions
+    fn deserialize_compat<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Compat {
+            Rule(StalemateRule),
+            StalematedPlayerLoses(bool),
+        }
+        Ok(match serde::Deserialize::deserialize(deserializer)? {
+            Compat::Rule(rule) => rule,
+            Compat::StalematedPlayerLoses(true) => StalemateRule::StalematedLoses,
+            Compat::StalematedPlayerLoses(false) => StalemateRule::Draw,
+        })
+    }
+}
+
+/// Additional way of winning the game, see `GlobalRules::win_conditions`
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WinCondition {
+    /// Win when any piece of the player stands on one of the squares (e.g. King of the Hill for all pieces)
+    ReachRegion { squares: Vec<(BCoord, BCoord)> },
+    /// Win when a piece with the given id is captured (capture the flag)
+    CapturePiece { piece: PieceId },
+    /// Win when a piece with the given id stands on one of the squares. Unlike the `win_squares` of the piece
+    /// definition, the piece doesn't need to belong to the player (e.g. drive the enemy king to a corner).
+    PieceReachesSquare { piece: PieceId, squares: Vec<(BCoord, BCoord)> },
+}
 
+/// Special behavior of some squares of the board, see `GlobalRules::square_effects`.
+/// Promotions, double jumps, castling and drops are not affected by portals and ice.
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SquareEffect {
+    /// A piece that ends a non-capturing move on one of the squares is teleported to the other one, if it's empty
+    Portal { squares: [(BCoord, BCoord); 2] },
+    /// A piece that ends a non-capturing move on one of the squares keeps moving in the same direction (one step
+    /// at a time, or repeating the jump if it's not a straight line) until it reaches a square without ice, or
+    /// until the next square is occupied, out of bounds or a promotion square of the piece
+    Ice { squares: Vec<(BCoord, BCoord)> },
+    /// Pieces can only move to or capture on the square when moving towards `direction`: the displacement of the
+    /// move must have a positive dot product with it. For example, `(0, 1)` only allows entering from the south side.
+    OneWay { square: (BCoord, BCoord), direction: (i8, i8) },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct GlobalRules {
     // If true, a player must capture if they can
     pub capturing_is_forced: bool,
     // If true, a player cannot put the opponent in check
     pub check_is_forbidden: bool,
-    // If true, a player who is stalemated loses. If false, the game is a draw
-    pub stalemated_player_loses: bool,
+    // Result of the game when a player is stalemated, see `StalemateRule`
+    #[serde(default, alias = "stalematedPlayerLoses", deserialize_with = "StalemateRule::deserialize_compat")]
+    pub stalemate_rule: StalemateRule,
     // If true, what would be a win for white is a win for black, and vice versa
     pub invert_win_conditions: bool,
     // Number of times that the same position is reached to draw by repetition
@@ -17,6 +100,58 @@ pub struct GlobalRules {
     // Number of times that a player is put in check to lose instantly
     // 0 means checks are not counted
     pub checks_to_lose: u8,
+    // Number of moves (by each player) without captures or moves of pieces that reset the halfmove clock
+    // (pawns in standard chess) to draw. 0 means no draw. In standard chess, this is the 50-move rule.
+    // States saved before this rule existed get the 50-move rule, like all the built-in variants
+    #[serde(default = "default_moves_without_progress_draw")]
+    pub moves_without_progress_draw: u8,
+    // If true, captured pieces go to the hand of the capturing player, and can be dropped on an empty square
+    // instead of making a move (crazyhouse)
+    #[serde(default)]
+    pub captured_pieces_to_hand: bool,
+    // If true, pieces in hand cannot be dropped. Instead, when a piece on the back rank moves for the first time,
+    // a piece from the hand can be placed on the square it leaves (gating, like in Seirawan chess)
+    #[serde(default)]
+    pub gating: bool,
+    // When a player loses because their leaders have been captured, see `LeaderCaptureRule`.
+    // If the player has no leader pieces, they lose when all their pieces are captured.
+    #[serde(default)]
+    pub leader_capture_rule: LeaderCaptureRule,
+    // If true, a move is illegal if it leaves two leaders of different players on the same rank, file or
+    // diagonal with no pieces between them (like the flying general rule in Xiangqi)
+    #[serde(default)]
+    pub leaders_cannot_face: bool,
+    // Number of consecutive moves that each player makes in a turn (2 in Marseillais chess). The first turn of
+    // the game only has 1 move. If a move gives check, the turn ends immediately. 0 is the same as 1.
+    #[serde(default)]
+    pub moves_per_turn: u8,
+    // If true, the number of moves per turn increases by 1 with each turn: 1 for White, 2 for Black, 3 for White...
+    // (progressive chess), and `moves_per_turn` is ignored. If a move gives check, the turn ends immediately.
+    #[serde(default)]
+    pub progressive: bool,
+    // If true, a player who is not in check can pass instead of making a move
+    #[serde(default)]
+    pub passing_allowed: bool,
+    // Additional win conditions for each player (index 0 = white, 1 = black), see `WinCondition`.
+    // The game ends as soon as a condition is met, even in the middle of a turn.
+    #[serde(default)]
+    pub win_conditions: [Vec<WinCondition>; 2],
+    // Number of full moves (a turn of each player) after which the game ends. The player with more material
+    // (pieces on the board and in hand, using their material scores) wins, or it is a draw if both have the same.
+    // 0 means no limit.
+    #[serde(default)]
+    pub move_limit: u16,
+    // Number of enemy pieces that a player must capture to win instantly. 0 means captures are not counted
+    #[serde(default)]
+    pub captures_to_win: u8,
+    // Squares with special behavior: portals, ice and one-way squares (see `SquareEffect`)
+    #[serde(default)]
+    pub square_effects: Vec<SquareEffect>,
+}
+
+// Same value as in `GlobalRules::for_mode()`
+fn default_moves_without_progress_draw() -> u8 {
+    50
 }
 
 
@@ -27,39 +162,95 @@ impl GlobalRules {
             GameMode::FiveCheck => 5,
             _ => 0,
         };
+        // The kings are not leaders, so there is no check
+        let win_conditions = match mode {
+            GameMode::DarkChess => [
+                vec![WinCondition::CapturePiece { piece: 'k' }],
+                vec![WinCondition::CapturePiece { piece: 'K' }],
+            ],
+            _ => Default::default(),
+        };
         match mode {
             GameMode::Antichess => {
                 GlobalRules {
                     capturing_is_forced: true,
                     check_is_forbidden: false,
-                    stalemated_player_loses: true,
+                    stalemate_rule: StalemateRule::StalematedLoses,
                     invert_win_conditions: true,
                     repetitions_draw: 3,
                     checks_to_lose: 0,
+                    moves_without_progress_draw: 50,
+                    captured_pieces_to_hand: false,
+                    gating: false,
+                    leader_capture_rule: LeaderCaptureRule::AllLeaders,
+                    leaders_cannot_face: false,
+                    moves_per_turn: 1,
+                    progressive: false,
+                    passing_allowed: false,
+                    win_conditions: Default::default(),
+                    move_limit: 0,
+                    captures_to_win: 0,
+                    square_effects: Vec::new(),
                 }
             },
             GameMode::RacingKings => {
                 GlobalRules {
                     capturing_is_forced: false,
                     check_is_forbidden: true,
-                    stalemated_player_loses: false,
+                    stalemate_rule: StalemateRule::Draw,
                     invert_win_conditions: false,
                     repetitions_draw: 3,
                     checks_to_lose: 0,
+                    moves_without_progress_draw: 50,
+                    captured_pieces_to_hand: false,
+                    gating: false,
+                    leader_capture_rule: LeaderCaptureRule::AllLeaders,
+                    leaders_cannot_face: false,
+                    moves_per_turn: 1,
+                    progressive: false,
+                    passing_allowed: false,
+                    win_conditions: Default::default(),
+                    move_limit: 0,
+                    captures_to_win: 0,
+                    square_effects: Vec::new(),
                 }
             },
             _ => {
                 GlobalRules {
                     capturing_is_forced: false,
                     check_is_forbidden: false,
-                    stalemated_player_loses: false,
+                    stalemate_rule: StalemateRule::Draw,
                     invert_win_conditions: false,
                     repetitions_draw: 3,
                     checks_to_lose,
+                    moves_without_progress_draw: 50,
+                    captured_pieces_to_hand: mode == GameMode::Crazyhouse,
+                    gating: false,
+                    leader_capture_rule: LeaderCaptureRule::AllLeaders,
+                    leaders_cannot_face: false,
+                    moves_per_turn: 1,
+                    progressive: false,
+                    passing_allowed: false,
+                    win_conditions,
+                    move_limit: 0,
+                    captures_to_win: 0,
+                    square_effects: Vec::new(),
                 }
             },
         }
     }
+    
+    /// Number of moves that a player makes in the given turn (the first turn of the game is 0),
+    /// see `moves_per_turn` and `progressive`
+    pub fn turn_length(&self, turn_number: u32) -> u32 {
+        if self.progressive {
+            turn_number.saturating_add(1)
+        } else if turn_number == 0 {
+            1
+        } else {
+            std::cmp::max(self.moves_per_turn, 1) as u32
+        }
+    }
 }
 
 impl Default for GlobalRules {
@@ -0,0 +1,127 @@
+use crate::PieceDefinition;
+use crate::piece::PieceFactory;
+use crate::types::{BIndex, GameMode};
+use crate::utils::from_index;
+
+use super::Position;
+use super::global_rules::StalemateRule;
+
+lazy_static! {
+    // Standard pieces whose dead positions are known: the castling rights of the king are ignored
+    static ref KING: PieceDefinition = PieceFactory::new(GameMode::Standard).make_king(8, 8);
+    static ref KNIGHT: PieceDefinition = PieceFactory::new(GameMode::Standard).make_knight();
+    static ref BISHOP: PieceDefinition = PieceFactory::new(GameMode::Standard).make_bishop();
+}
+
+impl Position {
+    /// Returns `true` if neither player can checkmate the opponent, no matter how the game continues.
+    /// Detects K vs K, K+B vs K, K+N vs K and K+B(s) vs K+B(s) with all bishops on the same color.
+    /// Only the pieces that move exactly like the standard king, knight and bishop are considered: custom pieces
+    /// that are similar (like a wazir, which can only attack squares of the other color) may still be able to mate.
+    pub fn insufficient_material(&self) -> bool {
+        // Only applies to variants where the only way of winning is checkmate
+        let rules = &self.global_rules;
+        if rules.invert_win_conditions || rules.stalemate_rule != StalemateRule::Draw || rules.checks_to_lose != 0 {
+            return false;
+        }
+        // Pieces in hand can always be dropped to continue the attack
+        if self.pieces.iter().any(|ps| !ps.hand_is_empty()) {
+            return false;
+        }
+        // Non-leader pieces of each player
+        let mut pieces: [Vec<(Option<StandardMovement>, BIndex)>; 2] = [Vec::new(), Vec::new()];
+        let mut leaders = Vec::with_capacity(2);
+        for (player, piece_set) in self.pieces.iter().enumerate() {
+            // Each player needs exactly 1 leader to be checkmated
+            if piece_set.get_single_leader().is_none() {
+                return false;
+            }
+            for piece in piece_set.iter() {
+                if piece.get_num_pieces() == 0 {
+                    continue;
+                }
+                let definition = piece.get_movement();
+                // Promotions or win squares could allow winning the game
+                if definition.can_promote() || !definition.win_squares.is_empty() {
+                    return false;
+                }
+                if piece.is_leader() {
+                    leaders.push(piece.standard_movement());
+                    continue;
+                }
+                for index in piece.get_indexes() {
+                    pieces[player].push((piece.standard_movement(), index));
+                }
+                // There are too many pieces on the board, exit early
+                if pieces[player].len() > 2 {
+                    return false;
+                }
+            }
+        }
+
+        // A leader that moves differently from a king could help to checkmate
+        if !leaders.iter().all(|&movement| movement == Some(StandardMovement::King)) {
+            return false;
+        }
+
+        // K vs K, or K + (knight or bishop) vs K
+        let num_pieces = pieces[0].len() + pieces[1].len();
+        let minor_piece = |movement: Option<StandardMovement>| {
+            matches!(movement, Some(StandardMovement::Knight | StandardMovement::Bishop))
+        };
+        if num_pieces == 0 || (num_pieces == 1 && pieces.iter().flatten().all(|&(movement, _)| minor_piece(movement))) {
+            return true;
+        }
+
+        // All the pieces are bishops, and they are on squares of the same color
+        let square_color = |index: BIndex| {
+            let (x, y) = from_index(index);
+            (x + y) % 2
+        };
+        let first_color = square_color(pieces.iter().flatten().next().unwrap().1);
+        pieces.iter().flatten().all(|&(movement, index)| {
+            movement == Some(StandardMovement::Bishop) && square_color(index) == first_color
+        })
+    }
+}
+
+/// Standard piece that moves exactly like a piece type, used to detect dead positions. It's computed once for each
+/// piece type (see `Piece::standard_movement()`), since comparing the movement of the pieces is slow.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum StandardMovement {
+    King,
+    Knight,
+    Bishop,
+}
+
+impl StandardMovement {
+    /// Returns the standard piece that moves like `definition`, if any
+    pub(crate) fn of(definition: &PieceDefinition) -> Option<StandardMovement> {
+        if moves_like(definition, &KING) {
+            Some(StandardMovement::King)
+        } else if moves_like(definition, &KNIGHT) {
+            Some(StandardMovement::Knight)
+        } else if moves_like(definition, &BISHOP) {
+            Some(StandardMovement::Bishop)
+        } else {
+            None
+        }
+    }
+}
+
+// Returns true if the piece moves and captures exactly like `reference`. Its id, name, castling rights and the
+// properties that don't affect the checkmates (halfmove clock, explosions if it doesn't explode) are ignored.
+fn moves_like(definition: &PieceDefinition, reference: &PieceDefinition) -> bool {
+    let mut definition = definition.clone();
+    definition.ids = reference.ids;
+    definition.display = reference.display.clone();
+    definition.castle_files = reference.castle_files;
+    definition.is_castle_rook = reference.is_castle_rook;
+    definition.castling_rules = reference.castling_rules.clone();
+    definition.resets_halfmove_clock = reference.resets_halfmove_clock;
+    if !definition.explode_on_capture {
+        definition.explosion_deltas = reference.explosion_deltas.clone();
+        definition.immune_to_explosion = reference.immune_to_explosion;
+    }
+    definition.eq_ignore_order(reference)
+}
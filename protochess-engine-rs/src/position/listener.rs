@@ -0,0 +1,77 @@
+// Notifications of the changes of the pieces on the board, to update the state of an evaluator incrementally
+
+use std::any::Any;
+use std::fmt;
+
+use crate::PieceId;
+use crate::types::{BIndex, Player};
+
+use super::Position;
+
+/// Change of a piece on the board, emitted by `make_move()`, `unmake_move()` and the public functions that
+/// modify the position. Unmaking a move emits the inverse changes of making it.
+/// Pieces in the hand are not on the board, so dropping a piece is an `Add` and capturing it is a `Remove`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PieceChange {
+    /// A piece was placed on `index` (drops, promotions, gating, castling and undone captures)
+    Add { piece_id: PieceId, player: Player, index: BIndex },
+    /// A piece was removed from `index` (captures, explosions, promotions and castling)
+    Remove { piece_id: PieceId, player: Player, index: BIndex },
+    /// A piece moved from `from` to `to`, which was empty
+    Move { piece_id: PieceId, player: Player, from: BIndex, to: BIndex },
+}
+
+/// Receives the changes of the pieces of a position, registered with `Position::set_listener()`.
+/// Allows an evaluator (e.g. NNUE) to update its accumulator in O(changes) instead of extracting the features
+/// of the position at each node: the evaluator can retrieve the listener with `Position::get_listener()`.
+/// Each clone of the position (e.g. for each search thread) has its own clone of the listener.
+pub trait PositionListener: Any + Send + Sync {
+    /// Rebuilds the state of the listener from scratch. Called when the listener is registered.
+    fn reset(&mut self, position: &Position);
+    /// Called after each change of a piece on the board
+    fn piece_changed(&mut self, change: PieceChange);
+    /// Returns a copy of the listener, it's usually `Box::new(self.clone())`
+    fn clone_box(&self) -> Box<dyn PositionListener>;
+}
+
+impl Clone for Box<dyn PositionListener> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+impl fmt::Debug for dyn PositionListener {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("PositionListener")
+    }
+}
+
+impl Position {
+    /// Registers a listener that receives the changes of the pieces on the board, replacing the previous one.
+    /// Calls `reset()` on the new listener.
+    pub fn set_listener(&mut self, listener: Option<Box<dyn PositionListener>>) {
+        self.listener = listener.map(|mut listener| {
+            listener.reset(self);
+            listener
+        });
+    }
+
+    /// Removes the listener of the position and returns it
+    pub fn take_listener(&mut self) -> Option<Box<dyn PositionListener>> {
+        self.listener.take()
+    }
+
+    /// Returns the listener of the position, if it has type `T`
+    pub fn get_listener<T: PositionListener>(&self) -> Option<&T> {
+        let listener: &dyn Any = self.listener.as_deref()?;
+        listener.downcast_ref()
+    }
+
+    // Sends a change to the listener, if there is one
+    #[inline]
+    pub(super) fn notify(&mut self, change: PieceChange) {
+        if let Some(listener) = &mut self.listener {
+            listener.piece_changed(change);
+        }
+    }
+}
@@ -1,76 +1,189 @@
-use crate::{Position, MoveInfo, MoveGen, MakeMoveResult};
-use crate::types::{Move, MoveType};
+use std::collections::HashMap;
+
+use crate::{Position, MoveInfo, MoveGen, MakeMoveResult, MakeMoveResultFlag, MakeMoveResultWinner, GameResult, BoardDelta, SquareChange};
+use crate::types::{BCoord, BIndex, Move, MoveType, Player, ZobKey};
+use crate::piece::{Piece, PieceId};
 use crate::utils::notation::{get_algebraic_notation, add_suffix};
+use crate::utils::{from_index, to_index};
 
 use super::position_properties::PositionProperties;
+use super::FROZEN_PIECE;
+use super::global_rules::{LeaderCaptureRule, StalemateRule};
+use super::listener::PieceChange;
+
+/// Maps a position (its zobrist key) and a move played in that position to the internal move
+/// and its algebraic notation (without check or checkmate indicators).
+/// Avoids generating the moves and the notation again when replaying a game.
+pub type NotationCache = HashMap<(ZobKey, MoveInfo), (Move, String)>;
 
 impl Position {
     
     /// Public interface for making a move. Checks if the move is legal, and if so, makes it.
     pub fn pub_make_move(&mut self, target_move: &MoveInfo) -> MakeMoveResult {
-        let moves = MoveGen::get_pseudo_moves(self, true);
-        for mv in &moves {
-            if target_move != mv {
-                continue;
-            }
-            // Found the move, try to play it
-            let exploded = mv.get_potential_explosion(self);
-            let mut move_notation = get_algebraic_notation(self, *mv, &moves);
-            if !MoveGen::make_move_if_legal(*mv, self) {
-                continue;
-            }
-            
-            let winner = {
-                if self.global_rules.invert_win_conditions {
-                    self.whos_turn
-                } else {
-                    1 - self.whos_turn
+        self.pub_make_move_impl(target_move, None)
+    }
+    
+    /// Same as `pub_make_move()`, but looks up the move in the cache before generating the moves
+    /// and the notation, and stores it in the cache afterwards.
+    pub fn pub_make_move_with_cache(&mut self, target_move: &MoveInfo, cache: &mut NotationCache) -> MakeMoveResult {
+        self.pub_make_move_impl(target_move, Some(cache))
+    }
+    
+    fn pub_make_move_impl(&mut self, target_move: &MoveInfo, cache: Option<&mut NotationCache>) -> MakeMoveResult {
+        let key = (self.get_zobrist(), *target_move);
+        let cached = cache.as_ref().and_then(|c| c.get(&key)).cloned();
+        let (mv, mut move_notation) = match cached {
+            // Check the legality anyway, in case of a zobrist collision
+            Some((mv, notation)) if MoveGen::is_move_legal(mv, self) => (mv, notation),
+            _ => {
+                let found = self.find_legal_move(target_move);
+                if found.is_none() {
+                    return MakeMoveResult::illegal_move();
                 }
-            };
-            
-            // Leader captured (atomic chess, or playing without a king)
-            if self.leader_is_captured() {
-                move_notation = add_suffix(move_notation, "#");
-                if self.pieces[self.whos_turn as usize].get_leader().is_none() {
-                    return MakeMoveResult::all_pieces_captured(winner, exploded, move_notation);
+                let found = found.unwrap();
+                if let Some(cache) = cache {
+                    cache.insert(key, found.clone());
                 }
-                return MakeMoveResult::leader_captured(winner, exploded, move_notation);
-            }
-            // Piece moved to winning square (king of the hill, racing kings)
-            if self.piece_is_on_winning_square() {
-                move_notation = add_suffix(move_notation, "#");
-                return MakeMoveResult::piece_in_win_square(winner, exploded, move_notation);
-            }
-            let in_check = MoveGen::in_check(self);
-            // No legal moves, check if it's checkmate or stalemate
-            if MoveGen::get_legal_moves(self).is_empty() {
-                if in_check {
+                found
+            },
+        };
+        
+        let exploded = mv.get_potential_explosion(self);
+        let mover = self.whos_turn;
+        let board_before = self.occupied_squares();
+        self.make_move(mv);
+        let delta = board_delta(&board_before, &self.occupied_squares(), mv, mover);
+        let mut result = match self.game_result_impl(true) {
+            Some(result) => {
+                // Don't add "#" if it's a draw
+                if result.winner != MakeMoveResultWinner::None {
                     move_notation = add_suffix(move_notation, "#");
-                    return MakeMoveResult::checkmate(winner, exploded, move_notation);
                 }
-                if self.global_rules.stalemated_player_loses {
-                    move_notation = add_suffix(move_notation, "#");
-                    return MakeMoveResult::stalemate(Some(winner), exploded, move_notation);
+                MakeMoveResult::game_over(result, exploded, move_notation)
+            },
+            None => {
+                if MoveGen::in_check(self) {
+                    move_notation = add_suffix(move_notation, "+");
                 }
-                // Don't add "#" since it's a draw
-                return MakeMoveResult::stalemate(None, exploded, move_notation);
-            }
-            // Checked N times (N=3 in 3-check)
-            if in_check && self.increment_num_checks() {
-                move_notation = add_suffix(move_notation, "#");
-                return MakeMoveResult::check_limit(winner, exploded, move_notation);
+                MakeMoveResult::ok(exploded, move_notation)
+            },
+        };
+        result.delta = delta;
+        result
+    }
+    
+    /// Returns the result of the game if it's over in the current position, or `None` if the game continues.
+    /// Unlike `pub_make_move()`, it doesn't count the current check: the position must have been reached
+    /// with `pub_make_move()` or loaded with the number of checks of each player.
+    pub fn game_result(&mut self) -> Option<GameResult> {
+        self.game_result_impl(false)
+    }
+    
+    // If `count_check` is true, the last move has just been made and it hasn't been counted towards the check limit
+    fn game_result_impl(&mut self, count_check: bool) -> Option<GameResult> {
+        let result = |flag, winner: Option<Player>| Some(GameResult { flag, winner: winner.into() });
+        let winner = {
+            if self.global_rules.invert_win_conditions {
+                self.whos_turn
+            } else {
+                1 - self.whos_turn
             }
-            // Threefold Repetition
-            if self.draw_by_repetition() {
-                return MakeMoveResult::repetition(move_notation);
+        };
+        
+        // Leader captured (atomic chess, or playing without a king)
+        if self.leader_is_captured() {
+            if !self.pieces[self.whos_turn as usize].has_leader() {
+                return result(MakeMoveResultFlag::AllPiecesCaptured, Some(winner));
             }
-            
+            return result(MakeMoveResultFlag::LeaderCaptured, Some(winner));
+        }
+        // Piece moved to winning square (king of the hill, racing kings)
+        if self.piece_is_on_winning_square() {
+            return result(MakeMoveResultFlag::PieceInWinSquare, Some(winner));
+        }
+        // Win condition of the global rules (reach a region, capture the flag...)
+        if let Some(player) = self.win_condition_met() {
+            let winner = if self.global_rules.invert_win_conditions { 1 - player } else { player };
+            return result(MakeMoveResultFlag::WinConditionMet, Some(winner));
+        }
+        // Captured enough enemy pieces
+        if let Some(player) = self.capture_limit_reached() {
+            let winner = if self.global_rules.invert_win_conditions { 1 - player } else { player };
+            return result(MakeMoveResultFlag::CaptureLimit, Some(winner));
+        }
+        let in_check = MoveGen::in_check(self);
+        // No legal moves, check if it's checkmate or stalemate
+        if MoveGen::get_legal_moves(self).is_empty() {
             if in_check {
-                move_notation = add_suffix(move_notation, "+");
+                return result(MakeMoveResultFlag::Checkmate, Some(winner));
             }
-            return MakeMoveResult::ok(exploded, move_notation);
+            let winner = match self.global_rules.stalemate_rule {
+                StalemateRule::Draw => None,
+                StalemateRule::StalematedLoses => Some(winner),
+                StalemateRule::StalematedWins => Some(1 - winner),
+                StalemateRule::MaterialAdjudication => self.adjudicated_winner(),
+            };
+            return result(MakeMoveResultFlag::Stalemate, winner);
+        }
+        // Checked N times (N=3 in 3-check)
+        let check_limit_reached = if count_check {
+            in_check && self.increment_num_checks()
+        } else {
+            let checks_to_lose = self.global_rules.checks_to_lose;
+            checks_to_lose != 0 && self.get_properties().times_in_check[self.whos_turn as usize] >= checks_to_lose
+        };
+        if check_limit_reached {
+            return result(MakeMoveResultFlag::CheckLimit, Some(winner));
+        }
+        // Move limit reached, the player with more material wins
+        if self.move_limit_reached() {
+            return result(MakeMoveResultFlag::MoveLimit, self.adjudicated_winner());
+        }
+        // Threefold Repetition
+        if self.draw_by_repetition() {
+            return result(MakeMoveResultFlag::Repetition, None);
+        }
+        // 50-move rule
+        if self.draw_by_moves_without_progress() {
+            return result(MakeMoveResultFlag::NoProgress, None);
         }
-        MakeMoveResult::illegal_move()
+        // Dead position (e.g. king vs king)
+        if self.insufficient_material() {
+            return result(MakeMoveResultFlag::InsufficientMaterial, None);
+        }
+        None
+    }
+    
+    // Winner of a game decided by material (the player with more material, or the one with less if the win
+    // conditions are inverted), or `None` if both players have the same material
+    fn adjudicated_winner(&self) -> Option<Player> {
+        self.material_leader().map(|player| {
+            if self.global_rules.invert_win_conditions { 1 - player } else { player }
+        })
+    }
+    
+    // Returns the index, id and player of each piece on the board, sorted by index
+    fn occupied_squares(&self) -> Vec<(BIndex, PieceId, Player)> {
+        let mut squares: Vec<_> = self.pieces.iter()
+            .flat_map(|ps| ps.iter())
+            .flat_map(|piece| piece.get_indexes().into_iter().map(move |index| (index, piece.get_piece_id(), piece.get_player())))
+            .collect();
+        squares.sort_unstable();
+        squares
+    }
+    
+    // Returns the legal move that matches the target move, along with its algebraic notation
+    fn find_legal_move(&mut self, target_move: &MoveInfo) -> Option<(Move, String)> {
+        let moves = MoveGen::get_pseudo_moves(self, true);
+        for mv in &moves {
+            if target_move != mv || !MoveGen::is_move_legal(*mv, self) {
+                continue;
+            }
+            // Only compute the notation for the legal move that will be played
+            let notation = get_algebraic_notation(self, *mv, &moves);
+            return Some((*mv, notation));
+        }
+        None
     }
     
     
@@ -79,64 +192,109 @@ impl Position {
         let my_player_num = self.whos_turn;
         let mut new_props = *self.get_properties(); // Copy the current properties
         new_props.num_captures = 0;
+        new_props.captured_to_hand = None;
         let move_type = mv.get_move_type();
-        
-        // Update the player
-        self.whos_turn = 1 - self.whos_turn;
-        // Update the player zobrist key
-        // For simplicity, use the lowest bit to represent the player
-        new_props.zobrist_key ^= 1;
+        let rule = self.global_rules.leader_capture_rule;
+        let enemy_num = 1 - my_player_num;
         
         // In the special case of the null move, don't do anything except update whos_turn
         // And update props
-        if move_type == MoveType::Null {
+        if move_type == MoveType::Null || move_type == MoveType::Pass {
             // Update props
-            // Since we're passing, there cannot be an ep square
-            new_props.clear_ep_square();
+            // Since we're passing, there cannot be ep squares
+            new_props.clear_ep_squares();
             new_props.move_played = mv;
+            // Passing in a game counts as a move without progress, the null move of the search doesn't
+            if move_type == MoveType::Pass {
+                new_props.halfmove_clock = new_props.halfmove_clock.saturating_add(1);
+            }
             self.properties_stack.push(new_props);
+            // Passing ends the turn, even if the player had more moves left
+            self.update_turn(my_player_num, true);
+            #[cfg(all(feature = "consistency-checks", debug_assertions))]
+            self.assert_consistency("making", mv);
             return;
         }
 
+        // Rifle captures don't move the capturing piece
+        let piece_stays = self.player_piece_at(my_player_num, mv.get_from()).is_some_and(|piece| piece.stays_when_making(mv));
+        
         // If this move is a capture, remove the captured piece before moving
         if move_type == MoveType::Capture || move_type == MoveType::PromotionCapture {
             let capt_index = mv.get_target();
-            let captured_piece = self.player_piece_at(self.whos_turn, capt_index).unwrap();
+            let captured_piece = self.player_piece_at(enemy_num, capt_index).unwrap();
             let piece_id = captured_piece.get_piece_id();
             let capt_player = captured_piece.get_player();
+            new_props.leader_captured[capt_player as usize] |= is_losing_capture(captured_piece, rule);
             let castling_zob = captured_piece.get_castle_zobrist(capt_index);
             new_props.zobrist_key ^= captured_piece.get_zobrist(capt_index);
+            let hand_id = {
+                if self.global_rules.captured_pieces_to_hand { self.hand_piece_id(captured_piece, my_player_num) }
+                else { None }
+            };
+            let capt_count = self.take_move_count(capt_index, &mut new_props.zobrist_key);
     
-            let could_castle = self.pieces[capt_player as usize].remove_piece(capt_index);
+            let could_castle = self.remove_piece_of(capt_player, capt_index);
             if could_castle {
                 new_props.zobrist_key ^= castling_zob;
             }
-            self.captures_stack.push((piece_id, capt_player, could_castle, capt_index));
+            // Crazyhouse: the captured piece goes to the hand of the capturing player
+            if let Some(id) = hand_id {
+                new_props.zobrist_key ^= self.pieces[my_player_num as usize].add_to_hand(id);
+                new_props.captured_to_hand = Some(id);
+            }
+            self.captures_stack.push((piece_id, capt_player, could_castle, capt_index, capt_count));
             new_props.num_captures += 1;
+            self.notify(PieceChange::Remove { piece_id, player: capt_player, index: capt_index });
     
             // Check if the capturing piece explodes
             self.explode_piece(mv, my_player_num, &mut new_props);
+            if self.global_rules.captures_to_win != 0 {
+                self.count_captures(my_player_num, &mut new_props);
+            }
         }
         
         // If this move is a castle, first remove the rook (in chess960 the king could move to the rook's square
         // and the rook would be overwritten)
         let mut rook_id = None;
+        let mut rook_count = 0;
+        let mut rook_to = 0;
         if move_type == MoveType::KingsideCastle || move_type == MoveType::QueensideCastle {
             let rook_from = mv.get_target();
+            rook_to = self.player_piece_at(my_player_num, mv.get_from()).unwrap().get_castling_partner_to(mv);
             let rook_piece = self.player_piece_at(my_player_num, rook_from).unwrap();
             new_props.zobrist_key ^= rook_piece.get_zobrist(rook_from);
             new_props.zobrist_key ^= rook_piece.get_castle_zobrist(rook_from);
             rook_id = Some(rook_piece.get_piece_id());
-            self.pieces[my_player_num as usize].remove_piece(rook_from);
+            self.remove_piece_of(my_player_num, rook_from);
+            rook_count = self.take_move_count(rook_from, &mut new_props.zobrist_key);
+            self.notify(PieceChange::Remove { piece_id: rook_id.unwrap(), player: my_player_num, index: rook_from });
         }
 
         let from = mv.get_from();
         let to = mv.get_to();
+        // Captures reset the halfmove clock
+        let mut resets_clock = new_props.num_captures > 0;
+        if let Some(id) = mv.get_drop_piece() {
+            // Place a piece from the hand on the board
+            new_props.zobrist_key ^= self.pieces[my_player_num as usize].remove_from_hand(id);
+            self.add_piece_of(my_player_num, id, to, false);
+            let dropped_piece = self.player_piece_at(my_player_num, to).unwrap();
+            new_props.zobrist_key ^= dropped_piece.get_zobrist(to);
+            self.notify(PieceChange::Add { piece_id: id, player: my_player_num, index: to });
+        }
         // Move the piece (only if it hasn't exploded)
-        if self.pieces[my_player_num as usize].index_has_piece(from) {
+        else if !piece_stays && self.pieces[my_player_num as usize].index_has_piece(from) {
             // Move piece to location
-            new_props.moved_piece_castle = self.pieces[my_player_num as usize].move_piece(from, to, false);
+            let move_count = self.take_move_count(from, &mut new_props.zobrist_key);
+            new_props.moved_piece_count = move_count;
+            new_props.moved_piece_castle = self.move_piece_of(my_player_num, from, to, false);
+            let moved_id = self.player_piece_at(my_player_num, to).unwrap().get_piece_id();
+            self.notify(PieceChange::Move { piece_id: moved_id, player: my_player_num, from, to });
             let moved_piece = self.player_piece_at(my_player_num, to).unwrap();
+            resets_clock |= moved_piece.resets_halfmove_clock();
+            // Only count the moves of the pieces that have a limit
+            let counts_moves = moved_piece.get_movement().max_moves.is_some();
             new_props.zobrist_key ^= moved_piece.get_zobrist(from);
             new_props.zobrist_key ^= moved_piece.get_zobrist(to);
             if new_props.moved_piece_castle {
@@ -151,31 +309,49 @@ impl Position {
                 new_props.zobrist_key ^= moved_piece.get_zobrist(to);
                 new_props.promote_from = moved_piece.get_piece_id();
                 // Remove old piece
-                self.pieces[my_player_num as usize].remove_piece(to);
+                self.remove_piece_of(my_player_num, to);
                 // Add new piece
-                self.pieces[my_player_num as usize].add_piece(promo, to, false);
+                self.add_piece_of(my_player_num, promo, to, false);
                 let piece = self.player_piece_at(my_player_num, to).unwrap();
                 new_props.zobrist_key ^= piece.get_zobrist(to);
+                self.notify(PieceChange::Remove { piece_id: moved_id, player: my_player_num, index: to });
+                self.notify(PieceChange::Add { piece_id: promo, player: my_player_num, index: to });
+            }
+            let new_count = if counts_moves { move_count + 1 } else { move_count };
+            self.put_move_count(to, new_count, &mut new_props.zobrist_key);
+            
+            // Gating: place a piece from the hand on the square that the piece has left
+            if let Some(id) = mv.get_gate_piece() {
+                new_props.zobrist_key ^= self.pieces[my_player_num as usize].remove_from_hand(id);
+                self.add_piece_of(my_player_num, id, from, false);
+                let gated_piece = self.player_piece_at(my_player_num, from).unwrap();
+                new_props.zobrist_key ^= gated_piece.get_zobrist(from);
+                self.notify(PieceChange::Add { piece_id: id, player: my_player_num, index: from });
             }
         }
         
         // If this move is a castle, add the rook back
         if move_type == MoveType::KingsideCastle || move_type == MoveType::QueensideCastle {
-            let rook_to = {
-                if move_type == MoveType::KingsideCastle { to - 1 }
-                else { to + 1 }
-            };
-            self.pieces[my_player_num as usize].add_piece(rook_id.unwrap(), rook_to, false);
+            self.add_piece_of(my_player_num, rook_id.unwrap(), rook_to, false);
             let rook_piece = self.player_piece_at(my_player_num, rook_to).unwrap();
             new_props.zobrist_key ^= rook_piece.get_zobrist(rook_to);
+            self.put_move_count(rook_to, rook_count, &mut new_props.zobrist_key);
+            self.notify(PieceChange::Add { piece_id: rook_id.unwrap(), player: my_player_num, index: rook_to });
         }
 
         // Pawn en-passant
         // Check for a pawn double push to set ep square
         if move_type == MoveType::DoubleJump {
-            new_props.set_ep_square(mv.get_target(), mv.get_to());
+            let (last, count) = double_jump_trail(mv);
+            new_props.set_ep_squares(mv.get_target(), last, count, mv.get_to());
         } else {
-            new_props.clear_ep_square();
+            new_props.clear_ep_squares();
+        }
+        
+        if resets_clock {
+            new_props.halfmove_clock = 0;
+        } else {
+            new_props.halfmove_clock = new_props.halfmove_clock.saturating_add(1);
         }
         
         // Update props
@@ -184,31 +360,155 @@ impl Position {
         
         // Update occupied bbs for future calculations
         self.update_occupied();
+        
+        // Update the player
+        self.update_turn(my_player_num, false);
+        #[cfg(all(feature = "consistency-checks", debug_assertions))]
+        self.assert_consistency("making", mv);
+    }
+    
+    /// Same as `make_move()`, but leaves the position unchanged and returns a copy with the move made.
+    /// Slower than making and unmaking the move, it's used to test and benchmark `make_move()` and `unmake_move()`.
+    pub fn clone_and_make(&self, mv: Move) -> Position {
+        let mut position = self.clone();
+        position.make_move(mv);
+        position
+    }
+    
+    // With the `consistency-checks` feature, the position is rebuilt from scratch after every make and unmake
+    // in debug builds (see `check_consistency()`)
+    #[cfg(all(feature = "consistency-checks", debug_assertions))]
+    fn assert_consistency(&self, action: &str, mv: Move) {
+        if let Err(msg) = self.check_consistency() {
+            panic!("Inconsistent position after {} the move {}: {}\n{}", action, mv, msg, self);
+        }
+    }
+    
+    // Switches the player to move after `player` has made a move, unless the player has more moves left in the
+    // current turn (see `GlobalRules::moves_per_turn`). The turn always ends after a null move, a check or a move
+    // that ends the game. Must be called after pushing the properties of the move.
+    #[inline]
+    fn update_turn(&mut self, player: Player, is_null: bool) {
+        let enemy = 1 - player;
+        let props = self.get_properties();
+        let (turn_number, turn_move) = (props.turn_number, props.turn_move);
+        let turn_length = self.global_rules.turn_length(turn_number);
+        self.whos_turn = enemy;
+        let continues = !is_null && turn_move + 1 < turn_length &&
+            !MoveGen::in_check(self) &&
+            !self.leaders_are_captured(player) && !self.leaders_are_captured(enemy) &&
+            !self.piece_is_on_winning_square() &&
+            !self.meets_win_condition(player) && !self.meets_win_condition(enemy) &&
+            self.capture_limit_reached().is_none();
+        
+        let props = self.properties_stack.last_mut().unwrap();
+        props.zobrist_key ^= turn_zobrist(turn_move, turn_length);
+        if continues {
+            self.whos_turn = player;
+            // Only the enemy can capture en passant, and only after the last move of the turn
+            props.clear_ep_squares();
+            props.turn_move = turn_move + 1;
+            props.zobrist_key ^= turn_zobrist(turn_move + 1, turn_length);
+        } else {
+            props.turn_number = turn_number.saturating_add(1);
+            props.turn_move = 0;
+            if player == 1 {
+                props.fullmove_number = props.fullmove_number.saturating_add(1);
+            }
+            // Update the player zobrist key
+            // For simplicity, use the lowest bit to represent the player
+            props.zobrist_key ^= 1;
+            props.zobrist_key ^= turn_zobrist(0, self.global_rules.turn_length(props.turn_number));
+        }
+    }
+
+    // Adds the enemy pieces captured in this move to the capture count of `player`
+    fn count_captures(&self, player: Player, new_props: &mut PositionProperties) {
+        let last_captures = &self.captures_stack[self.captures_stack.len() - new_props.num_captures as usize..];
+        let enemy_captures = last_captures.iter().filter(|(_, owner, ..)| *owner != player).count();
+        let old_count = new_props.pieces_captured[player as usize];
+        let new_count = old_count.saturating_add(enemy_captures as u8);
+        new_props.pieces_captured[player as usize] = new_count;
+        // Update the zobrist key (use bits 16-23 for white, 24-31 for black)
+        let shift = 16 + 8 * player as ZobKey;
+        new_props.zobrist_key ^= ((old_count as ZobKey) << shift) ^ ((new_count as ZobKey) << shift);
+    }
+
+    // Returns the id of the piece that `player` gets in hand after capturing `captured`, or None if
+    // the player cannot have that piece
+    fn hand_piece_id(&self, captured: &Piece, player: Player) -> Option<PieceId> {
+        // Promoted pieces go to the hand as their unpromoted type
+        let captured = match captured.get_movement().demotes_to {
+            Some(id) => self.lookup_piece(id)?,
+            None => captured,
+        };
+        let own_pieces = &self.pieces[player as usize];
+        if let Some(id) = captured.get_movement().ids[player as usize] {
+            if own_pieces.contains_piece(id) {
+                return Some(id);
+            }
+        }
+        // Some pieces (like pawns) use a different definition for each player, try the id of the other color
+        let id = captured.get_piece_id();
+        let swapped = if id.is_lowercase() { id.to_ascii_uppercase() } else { id.to_ascii_lowercase() };
+        if swapped != id && own_pieces.contains_piece(swapped) {
+            Some(swapped)
+        } else {
+            None
+        }
+    }
+
+    // Resets the move count of a square whose piece is moved or removed, and returns the old count
+    #[inline]
+    pub(super) fn take_move_count(&mut self, index: BIndex, zobrist_key: &mut ZobKey) -> u32 {
+        if self.immobile.get_bit(index) {
+            self.immobile.clear_bit(index);
+            *zobrist_key ^= immobile_zobrist(index);
+        }
+        std::mem::take(&mut self.move_counts[index as usize])
+    }
+    
+    // Sets the move count of the piece at the given index (the square must have been reset before),
+    // and marks it as immobile if it cannot move anymore
+    #[inline]
+    pub(super) fn put_move_count(&mut self, index: BIndex, count: u32, zobrist_key: &mut ZobKey) {
+        if count == 0 {
+            return;
+        }
+        self.move_counts[index as usize] = count;
+        let max_moves = self.piece_at(index).and_then(|piece| piece.get_movement().max_moves);
+        if count == FROZEN_PIECE || max_moves.is_some_and(|max| count >= max) {
+            self.immobile.set_bit(index);
+            *zobrist_key ^= immobile_zobrist(index);
+        }
     }
 
     #[inline]
     fn explode_piece(&mut self, mv: Move, my_player_num: u8, new_props: &mut PositionProperties) {
+        let rule = self.global_rules.leader_capture_rule;
         let from = mv.get_from();
         let moved_piece = self.pieces[my_player_num as usize].piece_at_mut(from).unwrap();
         if !moved_piece.explodes_on_capture() {
             return;
         }
         // Clone explosion radius bitboard
-        let mut explosion = moved_piece.get_explosion(mv.get_to()).clone();
+        let explosion = moved_piece.get_explosion(moved_piece.get_capture_square(mv)).clone();
         // Update zobrist key
         new_props.zobrist_key ^= moved_piece.get_zobrist(from);
         let moved_piece_castle_zob = moved_piece.get_castle_zobrist(from);
         let moved_piece_id = moved_piece.get_piece_id();
+        new_props.leader_captured[my_player_num as usize] |= is_losing_capture(moved_piece, rule);
         // Remove the capturing piece
-        let capturing_could_castle = self.pieces[my_player_num as usize].remove_piece(from);
+        let capturing_could_castle = self.remove_piece_of(my_player_num, from);
         if capturing_could_castle {
             new_props.zobrist_key ^= moved_piece_castle_zob;
         }
-        self.captures_stack.push((moved_piece_id, my_player_num, capturing_could_castle, from));
+        let moved_count = self.take_move_count(from, &mut new_props.zobrist_key);
+        self.captures_stack.push((moved_piece_id, my_player_num, capturing_could_castle, from, moved_count));
         new_props.num_captures += 1;
+        self.notify(PieceChange::Remove { piece_id: moved_piece_id, player: my_player_num, index: from });
         // Remove all pieces in the explosion radius
-        while let Some(nindex) = explosion.lowest_one() {
-            explosion.clear_bit(nindex);
+        for nindex in &explosion {
             if let Some(exploded_piece) = self.piece_at_mut(nindex) {
                 if exploded_piece.immune_to_explosion() {
                     continue;
@@ -216,13 +516,16 @@ impl Position {
                 new_props.zobrist_key ^= exploded_piece.get_zobrist(nindex);
                 let exploded_id = exploded_piece.get_piece_id();
                 let exploded_player = exploded_piece.get_player();
+                new_props.leader_captured[exploded_player as usize] |= is_losing_capture(exploded_piece, rule);
                 let exploded_castle_zob = exploded_piece.get_castle_zobrist(nindex);
-                let could_castle = self.pieces[exploded_player as usize].remove_piece(nindex);
+                let could_castle = self.remove_piece_of(exploded_player, nindex);
                 if could_castle {
                     new_props.zobrist_key ^= exploded_castle_zob;
                 }
-                self.captures_stack.push((exploded_id, exploded_player, could_castle, nindex));
+                let exploded_count = self.take_move_count(nindex, &mut new_props.zobrist_key);
+                self.captures_stack.push((exploded_id, exploded_player, could_castle, nindex, exploded_count));
                 new_props.num_captures += 1;
+                self.notify(PieceChange::Remove { piece_id: exploded_id, player: exploded_player, index: nindex });
             }
         }
     }
@@ -241,64 +544,191 @@ impl Position {
         // Consume prev props; never to return again
         let props = self.properties_stack.pop().expect("No move to undo");
         
-        // Update player turn
-        self.whos_turn = 1 - self.whos_turn;
+        // Update player turn (unless the move was not the last one of the turn)
+        if props.turn_move == 0 {
+            self.whos_turn = 1 - self.whos_turn;
+        }
 
         let my_player_num = self.whos_turn;
         let mv = props.move_played;
         let move_type = mv.get_move_type();
         
-        // Undo null moves
-        if move_type == MoveType::Null {
+        // Undo null moves and passes
+        if move_type == MoveType::Null || move_type == MoveType::Pass {
+            #[cfg(all(feature = "consistency-checks", debug_assertions))]
+            self.assert_consistency("unmaking", mv);
             return;
         }
         let from = mv.get_from();
         let to = mv.get_to();
         
+        // The zobrist key is restored from the properties stack, ignore the updates
+        let mut zob_ignored = 0;
+        
         // If this move is a castle, remove the rook
         let mut rook_id = None;
+        let mut rook_count = 0;
         if move_type == MoveType::KingsideCastle || move_type == MoveType::QueensideCastle {
-            let rook_to = {
-                if move_type == MoveType::KingsideCastle { to - 1 }
-                else { to + 1 }
-            };
+            // The castling piece is still on its destination
+            let rook_to = self.player_piece_at(my_player_num, to).unwrap().get_castling_partner_to(mv);
             let rook_piece = self.pieces[my_player_num as usize].piece_at_mut(rook_to).unwrap();
             rook_id = Some(rook_piece.get_piece_id());
-            self.pieces[my_player_num as usize].remove_piece(rook_to);
+            self.remove_piece_of(my_player_num, rook_to);
+            rook_count = self.take_move_count(rook_to, &mut zob_ignored);
+            self.notify(PieceChange::Remove { piece_id: rook_id.unwrap(), player: my_player_num, index: rook_to });
         }
 
-        // Undo move piece to location
-        if self.pieces[my_player_num as usize].index_has_piece(to) {
-            self.pieces[my_player_num as usize].move_piece(to, from, props.moved_piece_castle);
+        // Undo drop, return the piece to the hand
+        if let Some(id) = mv.get_drop_piece() {
+            self.remove_piece_of(my_player_num, to);
+            self.pieces[my_player_num as usize].add_to_hand(id);
+            self.take_move_count(to, &mut zob_ignored);
+            self.notify(PieceChange::Remove { piece_id: id, player: my_player_num, index: to });
+        }
+        // Undo move piece to location (exploded pieces and rifle captures leave the destination empty)
+        else if self.pieces[my_player_num as usize].index_has_piece(to) {
+            // Undo gating, return the piece to the hand
+            if let Some(id) = mv.get_gate_piece() {
+                self.remove_piece_of(my_player_num, from);
+                self.pieces[my_player_num as usize].add_to_hand(id);
+                self.notify(PieceChange::Remove { piece_id: id, player: my_player_num, index: from });
+            }
+            self.take_move_count(to, &mut zob_ignored);
+            let moved_id = self.player_piece_at(my_player_num, to).unwrap().get_piece_id();
+            self.move_piece_of(my_player_num, to, from, props.moved_piece_castle);
+            self.notify(PieceChange::Move { piece_id: moved_id, player: my_player_num, from: to, to: from });
             
             // Undo Promotion
             if move_type == MoveType::Promotion || move_type == MoveType::PromotionCapture {
                 // Remove old piece
-                self.pieces[my_player_num as usize].remove_piece(from);
+                self.remove_piece_of(my_player_num, from);
                 let promoted_from = props.promote_from;
                 // Assume that the piece that promoted must have moved, so it can't castle
-                self.pieces[my_player_num as usize].add_piece(promoted_from, from, false);
+                self.add_piece_of(my_player_num, promoted_from, from, false);
+                self.notify(PieceChange::Remove { piece_id: moved_id, player: my_player_num, index: from });
+                self.notify(PieceChange::Add { piece_id: promoted_from, player: my_player_num, index: from });
             }
+            self.put_move_count(from, props.moved_piece_count, &mut zob_ignored);
         }
 
         // Undo special moves
         // Special moves
         match move_type {
             MoveType::Capture | MoveType::PromotionCapture => {
+                if let Some(id) = props.captured_to_hand {
+                    self.pieces[my_player_num as usize].remove_from_hand(id);
+                }
                 for _ in 0..props.num_captures {
-                    let (piece_id, owner, captured_can_castle, capt_index) = self.captures_stack.pop().unwrap();
-                    self.pieces[owner as usize].add_piece(piece_id, capt_index, captured_can_castle);
+                    let (piece_id, owner, captured_can_castle, capt_index, move_count) = self.captures_stack.pop().unwrap();
+                    self.add_piece_of(owner, piece_id, capt_index, captured_can_castle);
+                    self.put_move_count(capt_index, move_count, &mut zob_ignored);
+                    self.notify(PieceChange::Add { piece_id, player: owner, index: capt_index });
                 }
             },
             MoveType::KingsideCastle | MoveType::QueensideCastle => {
                 // Add back the rook
                 let rook_from = mv.get_target();
-                self.pieces[my_player_num as usize].add_piece(rook_id.unwrap(), rook_from, true);
+                self.add_piece_of(my_player_num, rook_id.unwrap(), rook_from, true);
+                self.put_move_count(rook_from, rook_count, &mut zob_ignored);
+                self.notify(PieceChange::Add { piece_id: rook_id.unwrap(), player: my_player_num, index: rook_from });
             }
             _ => {}
         }
         
         // Update occupied bbs for future calculations
         self.update_occupied();
+        #[cfg(all(feature = "consistency-checks", debug_assertions))]
+        self.assert_consistency("unmaking", mv);
+    }
+}
+
+// Returns true if capturing this piece (before removing it from the board) makes its owner lose, when the
+// rule is `AnyLeader` or `AnyLeaderType`
+#[inline]
+fn is_losing_capture(piece: &Piece, rule: LeaderCaptureRule) -> bool {
+    piece.is_leader() && (rule == LeaderCaptureRule::AnyLeader || piece.get_num_pieces() == 1)
+}
+
+// Zobrist key for the progress of the current turn, when players can make several moves per turn.
+// Turns of a single move don't change the key.
+#[inline]
+pub(super) fn turn_zobrist(turn_move: u32, turn_length: u32) -> ZobKey {
+    if turn_length == 1 {
+        return 0;
+    }
+    ((turn_move as ZobKey) << 32 | turn_length as ZobKey).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+}
+
+// Zobrist key for having an immobile piece at the given index
+#[inline]
+pub(super) fn immobile_zobrist(index: BIndex) -> ZobKey {
+    // Spread the bits of the index, so that the keys don't collide with the player and EP keys
+    (index as ZobKey + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+}
+
+// Zobrist key for having a wall at the given index (a square inside the board that is out of bounds).
+// The lowest bit is cleared, since it's the key of the player to move.
+#[inline]
+pub(super) fn wall_zobrist(index: BIndex) -> ZobKey {
+    (index as ZobKey + 1).wrapping_mul(0xD6E8_FEB8_6659_FD93) & !1
+}
+
+// Compares the pieces of the board (from occupied_squares()) before and after the move `mv` of `mover`
+fn board_delta(before: &[(BIndex, PieceId, Player)], after: &[(BIndex, PieceId, Player)], mv: Move, mover: Player) -> BoardDelta {
+    let mut removed: Vec<_> = before.iter().filter(|square| !after.contains(square)).copied().collect();
+    let mut added: Vec<_> = after.iter().filter(|square| !before.contains(square)).copied().collect();
+    let mut changes = Vec::new();
+    // The piece that made the move, which can be promoted. When castling, the king doesn't end on `mv.get_to()`.
+    let moved_from = removed.iter().position(|&(index, _, player)| index == mv.get_from() && player == mover);
+    let moved_to = moved_from.and_then(|i| {
+        added.iter().position(|&(index, _, player)| index == mv.get_to() && player == mover)
+            .or_else(|| added.iter().position(|&(_, id, player)| id == removed[i].1 && player == mover))
+    });
+    if let (Some(i), Some(j)) = (moved_from, moved_to) {
+        let (from, id, player) = removed.remove(i);
+        let (to, new_id, _) = added.remove(j);
+        changes.push(SquareChange {
+            from: Some(from_index(from)),
+            to: Some(from_index(to)),
+            piece_id: id,
+            promotion: if new_id != id { Some(new_id) } else { None },
+            player,
+        });
+    }
+    // Other pieces that have moved (the rook when castling)
+    let mut i = 0;
+    while i < removed.len() {
+        let (from, id, player) = removed[i];
+        match added.iter().position(|&(_, other_id, other_player)| other_id == id && other_player == player) {
+            Some(j) => {
+                let (to, _, _) = added.remove(j);
+                removed.remove(i);
+                changes.push(SquareChange { from: Some(from_index(from)), to: Some(from_index(to)), piece_id: id, promotion: None, player });
+            },
+            None => i += 1,
+        }
+    }
+    for (index, piece_id, player) in removed {
+        changes.push(SquareChange { from: Some(from_index(index)), to: None, piece_id, promotion: None, player });
+    }
+    for (index, piece_id, player) in added {
+        changes.push(SquareChange { from: None, to: Some(from_index(index)), piece_id, promotion: None, player });
+    }
+    BoardDelta { changes }
+}
+
+// Returns the last square and the number of squares that a double jump has passed over. The first one is the
+// target of the move, and longer double jumps repeat the first jump (see `PieceDefinition::double_jump_steps`).
+fn double_jump_trail(mv: Move) -> (BIndex, u32) {
+    let (from_x, from_y) = from_index(mv.get_from());
+    let (first_x, first_y) = from_index(mv.get_target());
+    let (to_x, to_y) = from_index(mv.get_to());
+    let (dx, dy) = (first_x as i32 - from_x as i32, first_y as i32 - from_y as i32);
+    let steps = if dx != 0 { (to_x as i32 - from_x as i32) / dx } else { (to_y as i32 - from_y as i32) / dy };
+    let repeats_jump = steps > 2 && from_x as i32 + steps * dx == to_x as i32 && from_y as i32 + steps * dy == to_y as i32;
+    if !repeats_jump {
+        return (mv.get_target(), 1);
     }
+    let last = to_index((from_x as i32 + (steps - 1) * dx) as BCoord, (from_y as i32 + (steps - 1) * dy) as BCoord);
+    (last, steps as u32 - 1)
 }
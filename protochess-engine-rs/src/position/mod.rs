@@ -2,17 +2,57 @@ use std::fmt;
 
 use crate::{types::*, PieceDefinition, err_assert, wrap_res, err};
 use crate::utils::to_index;
-use crate::piece::{Piece, PieceId};
+use crate::piece::{Piece, PieceId, ValidationError, MaterialWeights};
 
 mod position_properties;
 mod make_move;
-pub mod global_rules;
+mod insufficient_material;
+mod consistency;
+pub(crate) mod global_rules;
 pub mod create;
-pub mod piece_set;
+pub(crate) mod piece_set;
+pub mod feature_planes;
+pub(crate) mod listener;
 
-use global_rules::GlobalRules;
+use global_rules::{GlobalRules, LeaderCaptureRule, SquareEffect, WinCondition};
+use crate::move_generator::square_effects::SquareEffects;
 use position_properties::PositionProperties;
 use piece_set::PieceSet;
+use listener::{PieceChange, PositionListener};
+pub use make_move::NotationCache;
+pub(crate) use insufficient_material::StandardMovement;
+
+/// Id, owner and coordinates of all the pieces of a piece type, see `Position::get_piece_bitboards()`
+pub type PieceSquares = (PieceId, Player, Vec<(BCoord, BCoord)>);
+
+/// Move count of the pieces that cannot move at all
+const FROZEN_PIECE: u32 = u32::MAX;
+
+// Win condition of the global rules, with the squares converted to a bitboard
+#[derive(Clone, Debug)]
+enum WinGoal {
+    // Any piece of the player (if None) or the piece with the given id stands on one of the squares
+    Region(Option<PieceId>, Bitboard),
+    // The piece with the given id was captured in the last move
+    Capture(PieceId),
+}
+
+impl WinGoal {
+    fn new(condition: &WinCondition, dimensions: &BDimensions) -> WinGoal {
+        let region = |squares: &[(BCoord, BCoord)]| {
+            let mut bitboard = Bitboard::zero();
+            for &(x, y) in squares.iter().filter(|&&(x, y)| dimensions.in_bounds(x, y)) {
+                bitboard.set_bit_at(x, y);
+            }
+            bitboard
+        };
+        match condition {
+            WinCondition::ReachRegion { squares } => WinGoal::Region(None, region(squares)),
+            WinCondition::CapturePiece { piece } => WinGoal::Capture(*piece),
+            WinCondition::PieceReachesSquare { piece, squares } => WinGoal::Region(Some(*piece), region(squares)),
+        }
+    }
+}
 
 /// Represents a single position in chess
 #[derive(Clone, Debug)]
@@ -27,11 +67,27 @@ pub struct Position {
     // Similar to state in stockfish
     properties_stack: Vec<PositionProperties>,
     // Full id (piece type + player num) of the captured pieces, if any.
-    // Also store whether the captured piece could castle and the index where it was captured.
+    // Also store whether the captured piece could castle, the index where it was captured and its move count.
     // In regular chess, this will be a maximum of 1 piece. In atomic chess, there can be up to 9.
-    captures_stack: Vec<(PieceId, Player, bool, BIndex)>,
+    captures_stack: Vec<(PieceId, Player, bool, BIndex, u32)>,
+    // Number of times that the piece on each square has moved (only for pieces with `max_moves`),
+    // or `FROZEN_PIECE` if the piece was frozen in the initial position
+    move_counts: Vec<u32>,
+    // Squares of the pieces that cannot move, because they are frozen or have reached `max_moves`
+    immobile: Bitboard,
     // Global rules of the game
     pub global_rules: GlobalRules,
+    // Precomputed `win_conditions` of the global rules, for each player
+    win_goals: [Vec<WinGoal>; 2],
+    // Precomputed `square_effects` of the global rules
+    square_effects: SquareEffects,
+    // Zobrist keys of the positions before the initial one that can be repeated, see `GameState::prior_fens`
+    prior_keys: Vec<ZobKey>,
+    // Owner of the piece on each square (None if empty), so that `piece_at()` only looks up one piece set.
+    // Updated by `add_piece_of()`, `remove_piece_of()` and `move_piece_of()`.
+    owner_at_index: [Option<Player>; BOARD_SIZE],
+    // Receives the changes of the pieces on the board, see `set_listener()`
+    listener: Option<Box<dyn PositionListener>>,
 }
 
 impl Position {
@@ -39,6 +95,10 @@ impl Position {
         let mut properties_stack = Vec::with_capacity(128);
         properties_stack.push(props);
         let occ_or_out_bounds = !&dimensions.bounds;
+        let win_goals = [0, 1].map(|player| {
+            rules.win_conditions[player].iter().map(|condition| WinGoal::new(condition, &dimensions)).collect()
+        });
+        let square_effects = SquareEffects::new(&rules.square_effects, &dimensions);
         
         Position {
             dimensions,
@@ -47,26 +107,35 @@ impl Position {
             occ_or_out_bounds,
             properties_stack,
             captures_stack: Vec::with_capacity(128),
+            move_counts: vec![0; BOARD_SIZE],
+            immobile: Bitboard::zero(),
             global_rules: rules,
+            win_goals,
+            square_effects,
+            prior_keys: Vec::new(),
+            owner_at_index: [None; BOARD_SIZE],
+            listener: None,
         }
     }
 
     /// Registers a new piece type for a given player in this position
     pub fn register_piecetype(&mut self, definition: &PieceDefinition) -> wrap_res!() {
+        // Pieces without ids are not used, so they are not validated
+        let first_id = definition.ids.iter().flatten().next();
+        if let (Some(id), Err(errors)) = (first_id, definition.validate(&self.dimensions)) {
+            let errors: Vec<String> = errors.iter().map(ValidationError::to_string).collect();
+            err!("Invalid piece '{}': {}", id, errors.join("; "));
+        }
         // Insert piece for all players specified in the definition
         for (player, id) in definition.ids.iter().enumerate() {
             if id.is_none() { continue; }
             let id = id.unwrap();
             
-            // Make sure that the promotion squares and pieces are specified together
-            err_assert!(definition.promotion_squares.is_empty() == definition.promo_vals[player].is_empty(), 
-                "Promotion squares and pieces must be specified together");
-                
             // Make sure that the piece is uniquely identifiable for this player
             for set in &self.pieces {
                 err_assert!(!set.contains_piece(id), "Piece id {id} already exists");
             }
-            self.pieces[player].register_piecetype(definition, &self.dimensions)?;
+            self.pieces[player].register_piecetype(definition, &self.dimensions, self.global_rules.gating)?;
         }
         Ok(())
     }
@@ -74,6 +143,48 @@ impl Position {
         for player in 0..self.pieces.len() {
             self.pieces[player].assert_promotion_consistency()?;
         }
+        // The demoted piece can belong to either player
+        for piece in self.pieces.iter().flat_map(|ps| ps.iter()) {
+            if let Some(demoted) = piece.get_movement().demotes_to {
+                let id = piece.get_piece_id();
+                err_assert!(self.lookup_piece(demoted).is_some(), "Piece '{id}' demotes to '{demoted}', which does not exist");
+            }
+        }
+        Ok(())
+    }
+    pub fn assert_win_conditions(&self) -> wrap_res!() {
+        for condition in self.global_rules.win_conditions.iter().flatten() {
+            let (piece, squares) = match condition {
+                WinCondition::ReachRegion { squares } => (None, &squares[..]),
+                WinCondition::CapturePiece { piece } => (Some(*piece), &[][..]),
+                WinCondition::PieceReachesSquare { piece, squares } => (Some(*piece), &squares[..]),
+            };
+            if let Some(id) = piece {
+                err_assert!(self.lookup_piece(id).is_some(), "Win condition uses piece '{id}', which does not exist");
+            }
+            for &(x, y) in squares {
+                err_assert!(self.in_bounds(x, y), "Win condition square ({x}, {y}) is out of bounds");
+            }
+        }
+        Ok(())
+    }
+    pub fn assert_square_effects(&self) -> wrap_res!() {
+        for effect in &self.global_rules.square_effects {
+            let squares = match effect {
+                SquareEffect::Portal { squares } => {
+                    err_assert!(squares[0] != squares[1], "Both ends of a portal are on the same square");
+                    &squares[..]
+                },
+                SquareEffect::Ice { squares } => &squares[..],
+                SquareEffect::OneWay { square, direction } => {
+                    err_assert!(*direction != (0, 0), "One-way square {square:?} has no direction");
+                    std::slice::from_ref(square)
+                },
+            };
+            for &(x, y) in squares {
+                err_assert!(self.in_bounds(x, y), "Square effect on ({x}, {y}), which is out of bounds");
+            }
+        }
         Ok(())
     }
 
@@ -84,9 +195,13 @@ impl Position {
     
     #[inline]
     pub fn draw_by_repetition(&self) -> bool {
-        if self.global_rules.repetitions_draw == 0 {
-            return false;
-        }
+        let repetitions = self.global_rules.repetitions_draw;
+        repetitions != 0 && self.repetition_count() >= repetitions as u32
+    }
+    
+    /// Returns the number of times that the current position has occurred since the last capture, including
+    /// the current one and the positions before the initial position (see `set_prior_keys()`)
+    pub fn repetition_count(&self) -> u32 {
         let mut num_reps = 1;
         let my_zob = self.get_zobrist();
         // Skip the last element, since it's the current position
@@ -99,16 +214,42 @@ impl Position {
             // A capture breaks the repetition
             // We could also break on pawn moves, but the concept of "pawn" doesn't exist in a custom game
             if p.num_captures > 0 {
-                break;
+                return num_reps;
             }
             i -= 1;
         }
-        num_reps >= self.global_rules.repetitions_draw
+        num_reps + self.prior_keys.iter().filter(|&&key| key == my_zob).count() as u32
+    }
+    
+    /// Sets the zobrist keys of the positions that occurred before the initial position, since the last capture.
+    /// They are taken into account when detecting repetitions.
+    pub fn set_prior_keys(&mut self, keys: Vec<ZobKey>) {
+        self.prior_keys = keys;
     }
     
     #[inline]
-    pub fn get_ep_square(&self) -> Option<BIndex> {
-        self.get_properties().get_ep_square()
+    pub fn draw_by_moves_without_progress(&self) -> bool {
+        let max_moves = self.global_rules.moves_without_progress_draw;
+        max_moves != 0 && self.get_halfmove_clock() >= 2 * max_moves as u16
+    }
+    
+    #[inline]
+    pub fn get_halfmove_clock(&self) -> u16 {
+        self.get_properties().halfmove_clock
+    }
+    #[inline]
+    pub fn get_fullmove_number(&self) -> u16 {
+        self.get_properties().fullmove_number
+    }
+    
+    /// Returns the squares that the last move has passed over with a double jump (they can be captured en passant)
+    #[inline]
+    pub fn get_ep_squares(&self) -> impl Iterator<Item=BIndex> {
+        self.get_properties().get_ep_squares()
+    }
+    #[inline]
+    pub fn has_ep_squares(&self) -> bool {
+        self.get_properties().has_ep_squares()
     }
     #[inline]
     pub fn get_ep_victim(&self) -> BIndex {
@@ -124,6 +265,52 @@ impl Position {
         }
     }
     
+    /// Returns the player that has captured `GlobalRules::captures_to_win` enemy pieces, if any
+    #[inline]
+    pub fn capture_limit_reached(&self) -> Option<Player> {
+        let captures_to_win = self.global_rules.captures_to_win;
+        if captures_to_win == 0 {
+            return None;
+        }
+        let pieces_captured = self.get_properties().pieces_captured;
+        (0..2).find(|&player| pieces_captured[player as usize] >= captures_to_win)
+    }
+    
+    /// Returns the number of turns left until the game ends because of `GlobalRules::move_limit`, if there is a limit
+    #[inline]
+    pub fn turns_until_move_limit(&self) -> Option<u32> {
+        let move_limit = self.global_rules.move_limit;
+        if move_limit == 0 {
+            return None;
+        }
+        Some((2 * move_limit as u32).saturating_sub(self.get_properties().turn_number))
+    }
+    
+    /// Returns true if the game has ended because of `GlobalRules::move_limit`
+    #[inline]
+    pub fn move_limit_reached(&self) -> bool {
+        self.turns_until_move_limit() == Some(0)
+    }
+    
+    /// Returns the player with more material (pieces on the board and in hand), or None if both have the same
+    pub fn material_leader(&self) -> Option<Player> {
+        let white = self.pieces[0].get_material_score().0;
+        let black = self.pieces[1].get_material_score().0;
+        match white.cmp(&black) {
+            std::cmp::Ordering::Greater => Some(0),
+            std::cmp::Ordering::Less => Some(1),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
+    
+    /// Recomputes the material scores of all the pieces with custom weights (see `Engine::set_eval_weights()`).
+    /// Adding or removing a wall goes back to the default weights.
+    pub fn set_material_weights(&mut self, weights: &MaterialWeights) {
+        for ps in &mut self.pieces {
+            ps.set_material_weights(weights, &self.dimensions);
+        }
+    }
+    
     #[inline]
     pub fn increment_num_checks(&mut self) -> bool {
         if self.global_rules.checks_to_lose == 0 {
@@ -134,6 +321,10 @@ impl Position {
         let old_checks = self.properties_stack[i-1].times_in_check[checked_player];
         let new_checks = old_checks + 1;
         
+        // The same node can be searched more than once (re-searches), only count the check the first time
+        if self.properties_stack[i].times_in_check[checked_player] == new_checks {
+            return new_checks >= self.global_rules.checks_to_lose;
+        }
         self.properties_stack[i].times_in_check[checked_player] = new_checks;
         // Update the zobrist key (use bits 8-9 for white, 10-11 for black)
         self.properties_stack[i].zobrist_key ^= (new_checks as ZobKey) << (8 + 2 * checked_player);
@@ -143,40 +334,75 @@ impl Position {
     
     #[inline]
     pub fn leader_is_captured(&self) -> bool {
-        self.get_num_leader_pieces(self.whos_turn) == 0
+        self.leaders_are_captured(self.whos_turn)
     }
     #[inline]
     pub fn enemy_leader_is_captured(&self) -> bool {
-        self.get_num_leader_pieces(1 - self.whos_turn) == 0
+        self.leaders_are_captured(1 - self.whos_turn)
     }
+    // Returns true if the player has lost because their leaders have been captured (see `LeaderCaptureRule`)
     #[inline]
-    fn get_num_leader_pieces(&self, player: Player) -> u32 {
-        if let Some(leader) = self.pieces[player as usize].get_leader() {
-            leader.get_num_pieces()
-        } else {
-            self.pieces[player as usize].get_occupied().count_ones()
+    fn leaders_are_captured(&self, player: Player) -> bool {
+        let pieces = &self.pieces[player as usize];
+        if !pieces.has_leader() {
+            // If the player has no leaders, they only lose when all pieces are captured
+            return pieces.get_occupied().is_zero();
+        }
+        match self.global_rules.leader_capture_rule {
+            LeaderCaptureRule::AllLeaders => pieces.get_leaders().all(|leader| leader.get_num_pieces() == 0),
+            // Leader types that are missing from the initial position don't count
+            LeaderCaptureRule::AnyLeader | LeaderCaptureRule::AnyLeaderType => {
+                self.get_properties().leader_captured[player as usize] ||
+                pieces.get_leaders().all(|leader| leader.get_num_pieces() == 0)
+            },
         }
     }
 
+    #[inline]
     pub fn piece_at(&self, index: BIndex) -> Option<&Piece> {
-        for ps in &self.pieces {
-            if let Some(piece) = ps.piece_at(index) {
-                return Some(piece);
-            }
-        }
-        None
+        let owner = self.owner_at(index)?;
+        self.pieces[owner as usize].piece_at(index)
     }
+    #[inline]
     pub fn piece_at_mut(&mut self, index: BIndex) -> Option<&mut Piece> {
-        for ps in &mut self.pieces {
-            if let Some(piece) = ps.piece_at_mut(index) {
-                return Some(piece);
-            }
-        }
-        None
+        let owner = self.owner_at(index)?;
+        self.pieces[owner as usize].piece_at_mut(index)
+    }
+    /// Returns the player that owns the piece at the given index, or `None` if the square is empty
+    #[inline]
+    pub fn owner_at(&self, index: BIndex) -> Option<Player> {
+        self.owner_at_index[index as usize]
+    }
+    
+    // Same as `PieceSet::add_piece()`, `remove_piece()` and `move_piece()`, but they also update the owner of
+    // the squares. Always use these functions to modify the pieces of the position.
+    pub(crate) fn add_piece_of(&mut self, player: Player, piece_id: PieceId, index: BIndex, set_can_castle: bool) {
+        self.pieces[player as usize].add_piece(piece_id, index, set_can_castle);
+        self.owner_at_index[index as usize] = Some(player);
+    }
+    pub(crate) fn remove_piece_of(&mut self, player: Player, index: BIndex) -> bool {
+        self.owner_at_index[index as usize] = None;
+        self.pieces[player as usize].remove_piece(index)
+    }
+    pub(crate) fn move_piece_of(&mut self, player: Player, from: BIndex, to: BIndex, set_can_castle: bool) -> bool {
+        self.owner_at_index[from as usize] = None;
+        self.owner_at_index[to as usize] = Some(player);
+        self.pieces[player as usize].move_piece(from, to, set_can_castle)
     }
     pub fn player_piece_at(&self, player: Player, index: BIndex) -> Option<&Piece> {
         self.pieces[player as usize].piece_at(index)
     }
+    /// Returns the squares of the pieces of each type, see `PieceSquares`.
+    /// The piece types that have no pieces on the board are skipped.
+    pub fn get_piece_bitboards(&self) -> Vec<PieceSquares> {
+        let mut result = Vec::new();
+        for (player, ps) in self.pieces.iter().enumerate() {
+            for piece in ps.iter().filter(|piece| !piece.get_bitboard().is_zero()) {
+                result.push((piece.get_piece_id(), player as Player, piece.get_bitboard().to_coords()));
+            }
+        }
+        result
+    }
     pub fn lookup_piece(&self, piece_id: PieceId) -> Option<&Piece> {
         for ps in &self.pieces {
             if let Some(piece) = ps.lookup_piece(piece_id) {
@@ -206,42 +432,124 @@ impl Position {
         let owner = owner.unwrap();
         
         let mut zob = self.get_zobrist();
-        self.pieces[owner as usize].add_piece(piece_id, index, can_castle);
+        self.add_piece_of(owner, piece_id, index, can_castle);
         let piece = self.player_piece_at(owner, index).unwrap();
         // Update the zobrist key
         zob ^= piece.get_zobrist(index);
-        if can_castle && piece.used_in_castling() {
+        if piece.has_not_moved(index) {
             zob ^= piece.get_castle_zobrist(index);
         }
         self.update_occupied();
         let stack_len = self.properties_stack.len();
         self.properties_stack[stack_len - 1].zobrist_key = zob;
+        self.notify(PieceChange::Add { piece_id, player: owner, index });
+        Ok(())
+    }
+
+    /// Adds a piece to the hand of its owner, so that it can be dropped on the board later
+    pub fn public_add_to_hand(&mut self, piece_id: PieceId) -> wrap_res!() {
+        let owner = self.pieces.iter().position(|ps| ps.contains_piece(piece_id));
+        err_assert!(owner.is_some(), "Attempted to add piece with ID={piece_id} to the hand, but it doesn't exist");
+        let zob_update = self.pieces[owner.unwrap()].add_to_hand(piece_id);
+        let stack_len = self.properties_stack.len();
+        self.properties_stack[stack_len - 1].zobrist_key ^= zob_update;
+        Ok(())
+    }
+
+    /// Freezes the piece at the given index, so that it cannot move for the rest of the game
+    pub fn public_freeze_piece(&mut self, index: BIndex) -> wrap_res!() {
+        err_assert!(self.piece_at(index).is_some(), "Attempted to freeze piece on square that was empty");
+        let mut zob = self.get_zobrist();
+        self.take_move_count(index, &mut zob);
+        self.put_move_count(index, FROZEN_PIECE, &mut zob);
+        let stack_len = self.properties_stack.len();
+        self.properties_stack[stack_len - 1].zobrist_key = zob;
         Ok(())
     }
 
     /// Removes a piece from the position, assuming the piece is there
     pub fn public_remove_piece(&mut self, index: BIndex) -> wrap_res!() {
-        let owner = {
-            if self.pieces[0].index_has_piece(index) { 0 }
-            else if self.pieces[1].index_has_piece(index) { 1 }
-            else { err!("Attempted to remove piece from square that was empty") }
+        let owner = match self.owner_at(index) {
+            Some(owner) => owner,
+            None => err!("Attempted to remove piece from square that was empty"),
         };
         let mut zob = self.get_zobrist();
+        self.take_move_count(index, &mut zob);
         let piece = self.piece_at_mut(index).unwrap();
         // Update the zobrist key
         zob ^= piece.get_zobrist(index);
-        let used_in_castling = piece.used_in_castling();
         let castle_zob = piece.get_castle_zobrist(index);
-        let could_casle = self.pieces[owner].remove_piece(index);
-        if could_casle && used_in_castling {
+        let piece_id = piece.get_piece_id();
+        let could_castle = self.remove_piece_of(owner, index);
+        if could_castle {
             zob ^= castle_zob;
         }
         self.update_occupied();
         let stack_len = self.properties_stack.len();
         self.properties_stack[stack_len - 1].zobrist_key = zob;
+        self.notify(PieceChange::Remove { piece_id, player: owner, index });
+        Ok(())
+    }
+    
+    /// Turns an empty square of the board into a wall (an out of bounds square that no piece can enter or slide through)
+    pub fn public_add_wall(&mut self, x: BCoord, y: BCoord) -> wrap_res!() {
+        err_assert!(x < self.dimensions.width && y < self.dimensions.height, "Coordinates ({x}, {y}) are outside of the board");
+        err_assert!(self.in_bounds(x, y), "There is already a wall at ({x}, {y})");
+        let index = to_index(x, y);
+        err_assert!(self.piece_at(index).is_none(), "Cannot add a wall at ({x}, {y}), the square is occupied");
+        err_assert!(!self.get_ep_squares().any(|ep| ep == index), "Cannot add a wall at ({x}, {y}), it's an en passant square");
+        self.dimensions.bounds.clear_bit_at(x, y);
+        self.update_dimensions(index);
         Ok(())
     }
     
+    /// Turns a wall back into an empty square of the board
+    pub fn public_remove_wall(&mut self, x: BCoord, y: BCoord) -> wrap_res!() {
+        err_assert!(x < self.dimensions.width && y < self.dimensions.height, "Coordinates ({x}, {y}) are outside of the board");
+        err_assert!(!self.in_bounds(x, y), "There is no wall at ({x}, {y})");
+        self.dimensions.bounds.set_bit_at(x, y);
+        self.update_dimensions(to_index(x, y));
+        Ok(())
+    }
+    
+    // Recomputes everything that depends on the bounds of the board, after toggling the wall at `index`
+    fn update_dimensions(&mut self, index: BIndex) {
+        for ps in &mut self.pieces {
+            ps.set_dimensions(&self.dimensions);
+        }
+        let rules = &self.global_rules;
+        let dimensions = &self.dimensions;
+        self.win_goals = [0, 1].map(|player| {
+            rules.win_conditions[player].iter().map(|condition| WinGoal::new(condition, dimensions)).collect()
+        });
+        self.square_effects = SquareEffects::new(&rules.square_effects, dimensions);
+        self.update_occupied();
+        // The wall stays when moves are unmade, so it's part of the key of all the previous positions
+        // (otherwise they would not be repetitions of the next ones)
+        let wall_key = make_move::wall_zobrist(index);
+        for props in &mut self.properties_stack {
+            props.zobrist_key ^= wall_key;
+        }
+        for key in &mut self.prior_keys {
+            *key ^= wall_key;
+        }
+    }
+    
+    /// Square effects of the global rules, applied by the move generator
+    pub fn get_square_effects(&self) -> &SquareEffects {
+        &self.square_effects
+    }
+    
+    /// Returns `true` if the piece at the given index cannot move, because it's frozen or it has
+    /// already moved `max_moves` times
+    pub fn is_immobile(&self, index: BIndex) -> bool {
+        self.immobile.get_bit(index)
+    }
+    /// Squares of all the pieces that cannot move (see `is_immobile()`)
+    pub fn get_immobile_squares(&self) -> &Bitboard {
+        &self.immobile
+    }
+    
     /// Returns true if any of the pieces on the board is on a winning square
     pub fn piece_is_on_winning_square(&self) -> bool {
         for piece_set in &self.pieces {
@@ -254,6 +562,27 @@ impl Position {
         false
     }
     
+    /// Returns the player that has met one of the `win_conditions` of the global rules, if any.
+    /// If both players have met a condition, the player who made the last move wins.
+    pub fn win_condition_met(&self) -> Option<Player> {
+        let last_player = if self.get_properties().turn_move > 0 { self.whos_turn } else { 1 - self.whos_turn };
+        [last_player, 1 - last_player].iter().copied().find(|&player| self.meets_win_condition(player))
+    }
+    // Returns true if the player has met one of their win conditions
+    fn meets_win_condition(&self, player: Player) -> bool {
+        self.win_goals[player as usize].iter().any(|goal| match goal {
+            WinGoal::Region(None, squares) => !(self.pieces[player as usize].get_occupied() & squares).is_zero(),
+            WinGoal::Region(Some(id), squares) => {
+                self.lookup_piece(*id).is_some_and(|piece| !(piece.get_bitboard() & squares).is_zero())
+            },
+            WinGoal::Capture(id) => {
+                let num_captures = self.get_properties().num_captures as usize;
+                let last_captures = &self.captures_stack[self.captures_stack.len() - num_captures..];
+                last_captures.iter().any(|(captured, ..)| captured == id)
+            },
+        })
+    }
+    
     
     
     /// Get the top of the properties stack
@@ -266,7 +595,7 @@ impl Position {
     /// Must be called after every position update/modification
     fn update_occupied(&mut self) {
         self.occ_or_out_bounds = !&self.dimensions.bounds;
-        for (_i, ps) in self.pieces.iter_mut().enumerate() {
+        for ps in &mut self.pieces {
             ps.update_occupied();
             self.occ_or_out_bounds |= ps.get_occupied();
         }
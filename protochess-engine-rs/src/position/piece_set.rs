@@ -3,8 +3,9 @@ use std::slice::{Iter, IterMut};
 use crate::utils::debug::eq_anyorder;
 use crate::{PieceDefinition, wrap_res, err_assert};
 //Pieces that a player has
-use crate::types::{Bitboard, BIndex, Player, BDimensions, Centipawns, BCoord};
-use crate::piece::{Piece, PieceId};
+use crate::types::{Bitboard, BIndex, Player, BDimensions, Centipawns, BCoord, ZobKey, BOARD_SIZE};
+use crate::piece::{Piece, PieceId, MaterialWeights};
+use super::global_rules::LeaderCaptureRule;
 use crate::utils::from_index;
 
 /// Represents a set of pieces for a player
@@ -12,55 +13,90 @@ use crate::utils::from_index;
 #[derive(Clone, Debug)]
 pub struct PieceSet {
     pieces: Vec<Piece>,
-    leader_piece_index: isize,
+    // Indexes (in `pieces`) of the leader piece types
+    leader_piece_indexes: Vec<usize>,
+    // Indexes (in `pieces`) of the piece types that capture by withdrawing or advancing
+    displacement_piece_indexes: Vec<usize>,
+    // Indexes (in `pieces`) of the piece types that can capture by hopping over a screen piece
+    hopper_piece_indexes: Vec<usize>,
     occupied: Bitboard,
     player_num: Player,
     // Inverse attack pattern of all the pieces in the set
     inverse_attack: PieceDefinition,
     inverse_attack_jumps: Vec<Bitboard>,
     
-    piece_at_index: [isize; 256],
+    piece_at_index: [isize; BOARD_SIZE],
 }
 
 impl PieceSet {
     pub fn new(player_num: Player) -> PieceSet {
-        let mut inverse_attack_jumps = Vec::with_capacity(256);
-        for _ in 0..256 {
+        let mut inverse_attack_jumps = Vec::with_capacity(BOARD_SIZE);
+        for _ in 0..BOARD_SIZE {
             inverse_attack_jumps.push(Bitboard::zero());
         }
         PieceSet {
             occupied: Bitboard::zero(),
             pieces: Vec::new(),
-            leader_piece_index: -1,
+            leader_piece_indexes: Vec::new(),
+            displacement_piece_indexes: Vec::new(),
+            hopper_piece_indexes: Vec::new(),
             player_num,
             inverse_attack: PieceDefinition::default(),
             inverse_attack_jumps,
-            piece_at_index: [-1; 256],
+            piece_at_index: [-1; BOARD_SIZE],
         }
     }
     
     /// Add a new piece definition to the set. The following conditions must have been checked before calling this function:
     /// - Both ids (white and black) are unique among all pieces in all sets (so that it is possible to uniquely identify a piece)
     /// - This piece is available for the player (i.e. `ids[player_num]` is not `None`)
-    pub fn register_piecetype(&mut self, definition: &PieceDefinition, dims: &BDimensions) -> wrap_res!() {
+    /// 
+    /// If `gating` is `true`, the piece keeps track of whether it has moved from the back rank (see `GlobalRules::gating`).
+    pub fn register_piecetype(&mut self, definition: &PieceDefinition, dims: &BDimensions, gating: bool) -> wrap_res!() {
         if definition.is_leader {
-            let player = if self.player_num == 0 { "White" } else { "Black" };
-            err_assert!(self.leader_piece_index == -1, "{player} has more than 1 leader piece");
-            self.leader_piece_index = self.pieces.len() as isize;
+            self.leader_piece_indexes.push(self.pieces.len());
+        }
+        if definition.capture_mode.captures_by_displacement() {
+            self.displacement_piece_indexes.push(self.pieces.len());
+        } else if !definition.attack_hops.is_empty() {
+            self.hopper_piece_indexes.push(self.pieces.len());
         }
         
-        let piece = Piece::new(definition.clone(), self.player_num, dims);
+        let mut piece = Piece::new(definition.clone(), self.player_num, dims);
+        if gating {
+            piece.enable_gating(dims);
+        }
         
-        let reserved_id_regex = regex::Regex::new(r"[\s/0-9*]").unwrap();
+        let reserved_id_regex = regex::Regex::new(r"[\s/0-9*_]").unwrap();
         err_assert!(!reserved_id_regex.is_match(&piece.get_piece_id().to_string()),
-            "A piece cannot have a space, slash, number, or asterisk as its id");
+            "A piece cannot have a space, slash, number, asterisk or underscore as its id");
         
         self.pieces.push(piece);
-        // Update the inverse movement pattern
-        self.update_inverse_attack(definition, dims);
+        // Update the inverse movement pattern. Pieces that capture by displacement don't use their attack pattern.
+        if !definition.capture_mode.captures_by_displacement() {
+            self.update_inverse_attack(definition, dims);
+        }
         
         Ok(())
     }
+    /// Recomputes the move tables of all the pieces and the inverse attack pattern, after a wall has been added or removed
+    pub fn set_dimensions(&mut self, dims: &BDimensions) {
+        self.inverse_attack = PieceDefinition::default();
+        self.inverse_attack_jumps.iter_mut().for_each(|jumps| *jumps = Bitboard::zero());
+        for i in 0..self.pieces.len() {
+            self.pieces[i].set_dimensions(dims);
+            let definition = self.pieces[i].get_movement().clone();
+            if !definition.capture_mode.captures_by_displacement() {
+                self.update_inverse_attack(&definition, dims);
+            }
+        }
+    }
+    /// Recomputes the material scores of all the pieces with custom weights
+    pub fn set_material_weights(&mut self, weights: &MaterialWeights, dims: &BDimensions) {
+        for piece in &mut self.pieces {
+            piece.set_material_weights(weights, dims);
+        }
+    }
     pub fn assert_promotion_consistency(&self) -> wrap_res!() {
         for piece in &self.pieces {
             for promotion in &piece.get_movement().promo_vals[self.player_num as usize] {
@@ -79,10 +115,10 @@ impl PieceSet {
         self.lookup_piece(piece_id).is_some()
     }
     
-    pub fn iter(&self) -> Iter<Piece> {
+    pub fn iter(&self) -> Iter<'_, Piece> {
         self.pieces.iter()
     }
-    pub fn iter_mut(&mut self) -> IterMut<Piece> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, Piece> {
         self.pieces.iter_mut()
     }
     
@@ -115,11 +151,75 @@ impl PieceSet {
         }
     }
     
-    pub fn get_leader(&self) -> Option<&Piece> {
-        if self.leader_piece_index == -1 {
-            return None;
+    /// Returns `true` if the player has at least one leader piece type (even if all of them have been captured)
+    pub fn has_leader(&self) -> bool {
+        !self.leader_piece_indexes.is_empty()
+    }
+    
+    /// Returns the leader piece types of the player
+    pub fn get_leaders(&self) -> impl Iterator<Item=&Piece> {
+        self.leader_piece_indexes.iter().map(move |&i| &self.pieces[i])
+    }
+    
+    /// Returns the piece types of the player that capture by withdrawing or advancing (see `CaptureMode`)
+    pub fn get_displacement_capturers(&self) -> impl Iterator<Item=&Piece> {
+        self.displacement_piece_indexes.iter().map(move |&i| &self.pieces[i])
+    }
+    
+    /// Returns the piece types of the player that can capture by hopping (see `PieceDefinition::attack_hops`)
+    pub fn get_hoppers(&self) -> impl Iterator<Item=&Piece> {
+        self.hopper_piece_indexes.iter().map(move |&i| &self.pieces[i])
+    }
+    
+    /// Returns the index of the leader, if there is exactly one leader piece on the board
+    pub fn get_single_leader(&self) -> Option<BIndex> {
+        let mut leaders = self.get_leaders().filter(|leader| leader.get_num_pieces() > 0);
+        match (leaders.next(), leaders.next()) {
+            (Some(leader), None) if leader.get_num_pieces() == 1 => leader.get_bitboard().lowest_one(),
+            _ => None,
+        }
+    }
+    
+    /// Returns the squares of the leaders whose capture would make the player lose immediately
+    pub fn get_royal_squares(&self, rule: LeaderCaptureRule) -> Bitboard {
+        let mut royal = Bitboard::zero();
+        match rule {
+            LeaderCaptureRule::AllLeaders => {
+                if let Some(index) = self.get_single_leader() {
+                    royal.set_bit(index);
+                }
+            },
+            LeaderCaptureRule::AnyLeader => {
+                for leader in self.get_leaders() {
+                    royal |= leader.get_bitboard();
+                }
+            },
+            LeaderCaptureRule::AnyLeaderType => {
+                for leader in self.get_leaders().filter(|leader| leader.get_num_pieces() == 1) {
+                    royal |= leader.get_bitboard();
+                }
+            },
+        }
+        royal
+    }
+    
+    /// Returns `true` if the player would lose after removing the pieces in `removed` from the board.
+    /// Leaders that have been captured before are not taken into account.
+    pub fn loses_without(&self, rule: LeaderCaptureRule, removed: &Bitboard) -> bool {
+        if !self.has_leader() {
+            // If the player has no leaders, they only lose when all pieces are captured
+            return (self.get_occupied() & !removed).is_zero();
+        }
+        match rule {
+            LeaderCaptureRule::AllLeaders => self.get_leaders().all(|leader| (leader.get_bitboard() & !removed).is_zero()),
+            LeaderCaptureRule::AnyLeader => {
+                self.get_leaders().any(|leader| !(leader.get_bitboard() & removed).is_zero()) ||
+                self.get_leaders().all(|leader| leader.get_num_pieces() == 0)
+            },
+            LeaderCaptureRule::AnyLeaderType => {
+                self.get_leaders().any(|leader| leader.get_num_pieces() > 0 && (leader.get_bitboard() & !removed).is_zero())
+            },
         }
-        self.pieces.get(self.leader_piece_index as usize)
     }
     
     pub fn get_inverse_attack(&self, index: BIndex) -> (&PieceDefinition, &Bitboard) {
@@ -167,13 +267,66 @@ impl PieceSet {
         could_castle
     }
     
+    /// Checks that the occupied bitboard, the piece at each index and the material counters of the pieces are
+    /// equal to the values computed from their bitboards (see `Position::check_consistency()`)
+    pub fn check_consistency(&self) -> wrap_res!() {
+        let mut occupied = Bitboard::zero();
+        for (i, piece) in self.pieces.iter().enumerate() {
+            let bb = piece.get_bitboard();
+            let id = piece.get_piece_id();
+            err_assert!((&occupied & bb).is_zero(), "Piece {} overlaps with another piece of player {}", id, self.player_num);
+            occupied |= bb;
+            let num_pieces = bb.count_ones();
+            err_assert!(piece.get_num_pieces() == num_pieces, "Piece {} has a count of {}, but there are {} on the board", id, piece.get_num_pieces(), num_pieces);
+            let material = piece.get_material_score() * num_pieces as Centipawns;
+            err_assert!(piece.get_material_score_all() == material, "Piece {} has a material score of {}, but it should be {}", id, piece.get_material_score_all(), material);
+            for index in bb {
+                err_assert!(self.piece_at_index[index as usize] == i as isize, "The piece at index {} is not {}", index, id);
+            }
+        }
+        err_assert!(self.occupied == occupied, "The occupied bitboard of player {} doesn't match its pieces", self.player_num);
+        let num_indexes = self.piece_at_index.iter().filter(|&&i| i != -1).count();
+        err_assert!(num_indexes == occupied.count_ones() as usize, "Player {} has pieces at indexes that are not occupied", self.player_num);
+        Ok(())
+    }
+    
+    /// Add a piece of a given type (assuming it exists) to the hand of the player.
+    /// Returns the zobrist update for the new number of pieces in hand.
+    pub fn add_to_hand(&mut self, piece_id: PieceId) -> ZobKey {
+        let piece = self.pieces.iter_mut().find(|p| p.get_piece_id() == piece_id).unwrap();
+        let old_count = piece.get_num_in_hand();
+        piece.add_to_hand_();
+        piece.get_hand_zobrist(old_count) ^ piece.get_hand_zobrist(old_count + 1)
+    }
+    
+    /// Remove a piece of a given type from the hand of the player (assuming there is one).
+    /// Returns the zobrist update for the new number of pieces in hand.
+    pub fn remove_from_hand(&mut self, piece_id: PieceId) -> ZobKey {
+        let piece = self.pieces.iter_mut().find(|p| p.get_piece_id() == piece_id).unwrap();
+        let old_count = piece.get_num_in_hand();
+        piece.remove_from_hand_();
+        piece.get_hand_zobrist(old_count) ^ piece.get_hand_zobrist(old_count - 1)
+    }
+    
+    /// Returns the pieces that the player has in hand, along with their count
+    pub fn iter_hand(&self) -> impl Iterator<Item=(&Piece, u32)> {
+        self.pieces.iter()
+            .map(|p| (p, p.get_num_in_hand()))
+            .filter(|(_, count)| *count > 0)
+    }
+    
+    /// Returns true if the player has no pieces in hand
+    pub fn hand_is_empty(&self) -> bool {
+        self.pieces.iter().all(|p| p.get_num_in_hand() == 0)
+    }
+    
     /// Returns the material score of all pieces in the set, and of only the leader pieces
     pub fn get_material_score(&self) -> (Centipawns, Centipawns) {
         let mut score = 0;
         let mut leader_score = 0;
         for piece in &self.pieces {
             let piece_total_score = piece.get_material_score_all();
-            score += piece_total_score;
+            score += piece_total_score + piece.get_material_score_hand();
             if piece.is_leader() {
                 leader_score += piece_total_score;
             }
@@ -204,7 +357,7 @@ impl PieceSet {
         for delta in &other.attack_jump_deltas {
             self.inverse_attack.attack_jump_deltas.push((-delta.0, -delta.1));
             
-            for i in 0..=255 {
+            for i in (0..BOARD_SIZE).map(|i| i as BIndex) {
                 let (x, y) = from_index(i);
                 let nx = x as i8 - delta.0;
                 let ny = y as i8 - delta.1;
@@ -232,7 +385,7 @@ impl PartialEq for PieceSet {
         self.player_num == other.player_num &&
         self.inverse_attack.eq_ignore_order(&other.inverse_attack) &&
         self.inverse_attack_jumps == other.inverse_attack_jumps && {
-            for pos in 0..256 {
+            for pos in 0..BOARD_SIZE {
                 let i1 = self.piece_at_index[pos];
                 let i2 = other.piece_at_index[pos];
                 if i1 == -1 && i2 == -1 { continue; }
@@ -242,12 +395,7 @@ impl PartialEq for PieceSet {
                 }
             }
             true
-        } && {
-            let i1 = self.leader_piece_index;
-            let i2 = other.leader_piece_index;
-            if i1 == -1 && i2 == -1 { true }
-            else if i1 == -1 || i2 == -1 { false }
-            else { self.pieces[i1 as usize] == other.pieces[i2 as usize] }
-        }
+        } &&
+        eq_anyorder(&self.get_leaders().collect::<Vec<_>>(), &other.get_leaders().collect::<Vec<_>>())
     }
 }
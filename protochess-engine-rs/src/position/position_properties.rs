@@ -9,37 +9,79 @@ pub struct PositionProperties {
     pub move_played: Move,
     //If the last move was a promotion, promote_from is the previous piecetype
     pub promote_from: PieceId,
-    //EP square (square behind a double pawn push)
-    ep_square: Option<BIndex>,
-    ep_victim: BIndex, // Only valid if ep_square is Some
+    //EP squares (squares that the last double jump has passed over, like the square behind a double pawn push).
+    //Small bitboard along the line of the jump: bit i of ep_mask is set if ep_first + i * ep_step is an EP square.
+    ep_first: BIndex,
+    ep_step: BIndex,
+    ep_mask: u64,
+    ep_victim: BIndex, // Only valid if ep_mask is not 0
     // true if the piece that moved could castle
     pub moved_piece_castle: bool,
+    // Number of times that the piece that moved had moved before this move
+    pub moved_piece_count: u32,
     pub num_captures: u8,
+    // If the last move was a capture and the captured piece went to the hand of the player who moved, its new id
+    pub captured_to_hand: Option<PieceId>,
+    // True if each player has lost a leader whose capture loses the game (used by `LeaderCaptureRule::AnyLeader`
+    // and `LeaderCaptureRule::AnyLeaderType`)
+    pub leader_captured: [bool; 2],
     // Number of times that each player has been in check
     pub times_in_check: [u8; 2],
+    // Number of enemy pieces that each player has captured (only counted if `GlobalRules::captures_to_win` is set)
+    pub pieces_captured: [u8; 2],
+    // Number of halfmoves since the last capture or move of a piece that resets the clock
+    pub halfmove_clock: u16,
+    // Fullmove number of the FEN, incremented after each turn of Black
+    pub fullmove_number: u16,
+    // Number of turns that have been completed since the initial position
+    pub turn_number: u32,
+    // Number of moves that the player to move has already made in the current turn (see `GlobalRules::moves_per_turn`).
+    // It is 0 if the last move switched the player to move.
+    pub turn_move: u32,
 }
 
 impl PositionProperties {
-    // Access EP square
-    pub fn set_ep_square(&mut self, ep_square: BIndex, ep_victim: BIndex) {
-        self.clear_ep_square();
-        self.ep_square = Some(ep_square);
+    // Access EP squares
+    /// Sets `count` evenly spaced EP squares, from `first` to `last` (both included). `count` must be between 1 and 64.
+    pub fn set_ep_squares(&mut self, first: BIndex, last: BIndex, count: u32, ep_victim: BIndex) {
+        assert!((1..=64).contains(&count), "Invalid number of EP squares: {}", count);
+        self.clear_ep_squares();
+        self.ep_first = first.min(last);
+        self.ep_step = if count > 1 { (first.max(last) - self.ep_first) / (count as BIndex - 1) } else { 1 };
+        self.ep_mask = u64::MAX >> (64 - count);
         self.ep_victim = ep_victim;
-        // Update zobrist. For simplicity, use the ep index as the zobrist key
-        self.zobrist_key ^= ep_square as ZobKey;
+        self.zobrist_key ^= self.ep_zobrist();
     }
-    pub fn clear_ep_square(&mut self) {
-        if let Some(sq) = self.ep_square {
-            // If the last prop had some ep square then we want to clear zob by xoring again
-            self.zobrist_key ^= sq as ZobKey;
+    pub fn clear_ep_squares(&mut self) {
+        // If the last prop had some ep square then we want to clear zob by xoring again
+        self.zobrist_key ^= self.ep_zobrist();
+        self.ep_mask = 0;
+    }
+    pub fn has_ep_squares(&self) -> bool {
+        self.ep_mask != 0
+    }
+    pub fn is_ep_square(&self, index: BIndex) -> bool {
+        if self.ep_mask == 0 || index < self.ep_first || !(index - self.ep_first).is_multiple_of(self.ep_step) {
+            return false;
         }
-        self.ep_square = None;
+        let bit = (index - self.ep_first) / self.ep_step;
+        bit < 64 && (self.ep_mask >> bit) & 1 != 0
     }
-    pub fn get_ep_square(&self) -> Option<BIndex> {
-        self.ep_square
+    pub fn get_ep_squares(&self) -> impl Iterator<Item=BIndex> {
+        let (first, step, mask) = (self.ep_first, self.ep_step, self.ep_mask);
+        (0..64).filter(move |bit| (mask >> bit) & 1 != 0).map(move |bit| first + bit as BIndex * step)
     }
     pub fn get_ep_victim(&self) -> BIndex {
-        assert!(self.ep_square.is_some(), "Attempted to get ep victim when ep square is None");
+        assert!(self.has_ep_squares(), "Attempted to get ep victim when there are no ep squares");
         self.ep_victim
     }
+    
+    // For simplicity, a single ep square uses its index as the zobrist key
+    pub fn ep_zobrist(&self) -> ZobKey {
+        match self.ep_mask {
+            0 => 0,
+            1 => self.ep_first as ZobKey,
+            mask => self.ep_first as ZobKey ^ (mask ^ (self.ep_step as ZobKey) << 48).wrapping_mul(0x9E37_79B9_7F4A_7C15),
+        }
+    }
 }
@@ -0,0 +1,12 @@
+//! Stable public interface of the engine.
+//!
+//! Everything needed to set up a game, play moves and search for the best move is re-exported here.
+//! Fallible functions return `Result<T, String>`, where the error is a human-readable message.
+
+pub use crate::Engine;
+pub use crate::{GameState, GameTermination, InitialState, StateDiff, PgnHeaders, GameTree, GameLine, VariantRegistry, VariantGenerator, VariantConstraints, RenderOptions, FeaturePlanes, PositionListener, PieceChange};
+pub use crate::{GlobalRules, LeaderCaptureRule, StalemateRule, WinCondition, SquareEffect, PieceDefinition, PieceDisplay, CaptureMode, HopMove, HopLanding, CastlingRule, PieceId, PieceSquares, ValidationError};
+pub use crate::{MoveInfo, MoveList, MoveStr};
+pub use crate::{MakeMoveResult, MakeMoveResultFlag, MakeMoveResultWinner, GameResult, BoardDelta, SquareChange};
+pub use crate::{Algorithm, ScoreType, MATE_SCORE, SearchOutcome, SearchResult, TimeControl, SearchHandle, MAX_SKILL_LEVEL, Evaluator, HandCraftedEvaluator, EvalWeights, MaterialWeights, TuningParams, SearchStats, SearchTreeNode, PvLine, BestMoveExplanation, AlternativeMove, PuzzleCandidate, MoveAnnotation, MoveClassification, EvalBreakdown, PieceEval};
+pub use crate::types::{BCoord, Centipawns, Depth, Player, GameMode, CUTOFF_MOVE_INDEXES};
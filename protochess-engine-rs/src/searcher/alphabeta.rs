@@ -1,16 +1,35 @@
-use instant::Instant;
-
 #[cfg(feature = "parallel")]
 use std::sync::atomic::Ordering;
 
-use crate::MoveGen;
-use crate::types::{Move, Depth, Centipawns, SearchTimeout, ZobKey};
+use crate::{MoveGen, StalemateRule};
+use crate::move_generator::LegalityInfo;
+use crate::types::{Move, Depth, Centipawns, Player, SearchTimeout, ZobKey, SearchTreeNode, CUTOFF_MOVE_INDEXES, MATE_SCORE};
 
 use super::Searcher;
 use super::eval;
+use super::move_picker::MovePicker;
 use super::transposition_table::{Entry, EntryFlag};
 
-pub const GAME_OVER_SCORE: Centipawns = -1_000_000;
+pub const GAME_OVER_SCORE: Centipawns = -MATE_SCORE;
+
+// Minimum remaining depth for trying a singular extension
+const SINGULAR_MIN_DEPTH: Depth = 6;
+// Maximum number of singular extensions in a single search path
+const MAX_SINGULAR_EXTENSIONS: Depth = 3;
+// Margin (per ply of depth) by which the other moves must fail low for the move to be singular
+const SINGULAR_MARGIN: Centipawns = 4;
+
+/// Number of plies that the current search path has been extended by
+#[derive(Clone, Copy, Default)]
+struct Extensions {
+    check: Depth,
+    singular: Depth,
+}
+impl Extensions {
+    fn total(self) -> Depth {
+        self.check + self.singular
+    }
+}
 
 impl Searcher {
     /// Search for the best move to play at the current position.
@@ -20,16 +39,53 @@ impl Searcher {
     /// Returns `Err(SearchTimeout)` if the search timed out.
     pub fn start_alphabeta(&mut self, depth: Depth, hint: &Vec<Move>) -> Result<Centipawns, SearchTimeout> {
         // Use -MAX instead of MIN to avoid overflow when negating
-        self.alphabeta::<true,true>(depth, depth, 0, -Centipawns::MAX, Centipawns::MAX, true, Some(hint))
+        self.start_alphabeta_window(depth, hint, -Centipawns::MAX, Centipawns::MAX)
+    }
+    
+    /// Same as `start_alphabeta()`, but only searches the scores between `alpha` and `beta`.
+    /// If the returned score is `alpha` or `beta`, the real score is outside the window and the PV is not valid.
+    pub fn start_alphabeta_window(&mut self, depth: Depth, hint: &Vec<Move>, alpha: Centipawns, beta: Centipawns) -> Result<Centipawns, SearchTimeout> {
+        self.alphabeta::<true,true>(depth, Extensions::default(), 0, alpha, beta, true, Some(hint))
+    }
+    
+    // Same as alphabeta_impl(), but records the node in the search tree if it's in the first plies
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    fn alphabeta<const IS_PV: bool, const IS_ROOT: bool>(&mut self,
+            depth: Depth,
+            extensions: Extensions,
+            pv_index: usize,
+            alpha: Centipawns,
+            beta: Centipawns,
+            do_null: bool,
+            hint: Option<&Vec<Move>>
+        ) -> Result<Centipawns, SearchTimeout>
+    {
+        if pv_index >= self.limits.tree_plies as usize {
+            return self.alphabeta_impl::<IS_PV, IS_ROOT>(depth, extensions, pv_index, alpha, beta, do_null, hint);
+        }
+        let last_move = self.pos.get_properties().move_played;
+        let node_index = self.tree.len();
+        self.tree.push(SearchTreeNode {
+            ply: pv_index as u8,
+            mv: if IS_ROOT || last_move.is_null() { None } else { Some(last_move.into()) },
+            depth,
+            alpha,
+            beta,
+            score: None,
+        });
+        let score = self.alphabeta_impl::<IS_PV, IS_ROOT>(depth, extensions, pv_index, alpha, beta, do_null, hint)?;
+        self.tree[node_index].score = Some(score);
+        Ok(score)
     }
     
     // alpha is the best score that I can currently guarantee at this level or above.
     // beta is the worst score for me that the opponent can currently guarantee at this level or above.
     #[allow(clippy::too_many_lines)]
     #[allow(clippy::too_many_arguments)]
-    fn alphabeta<const IS_PV: bool, const IS_ROOT: bool>(&mut self,
+    fn alphabeta_impl<const IS_PV: bool, const IS_ROOT: bool>(&mut self,
             mut depth: Depth,
-            mut search_depth: Depth,
+            mut extensions: Extensions,
             pv_index: usize,
             mut alpha: Centipawns,
             mut beta: Centipawns,
@@ -37,14 +93,13 @@ impl Searcher {
             hint: Option<&Vec<Move>>
         ) -> Result<Centipawns, SearchTimeout>
     {
-        let mut known_check = false;
-        if IS_PV {
-            // If in check, extend search by 1 ply. Limit the extension to 2x the original depth.
-            known_check = self.known_checks.contains(&self.zobrist());
-            if known_check && search_depth < self.max_searching_depth {
-                depth += 1;
-                search_depth += 1;
-            }
+        // Move excluded in the singular extension verification search of this node
+        let excluded_move = self.excluded_moves[pv_index];
+        // PV nodes in check are extended by 1 ply (before probing the transposition table)
+        let pv_in_check = IS_PV && MoveGen::in_check(&mut self.pos);
+        if pv_in_check && self.can_extend(extensions) {
+            depth += 1;
+            extensions.check += 1;
         }
         
         // Skip position if a mating move at this depth is already available
@@ -57,8 +112,17 @@ impl Searcher {
             }
         }
 
+        // In multi-PV mode, the root entry doesn't take the excluded moves into account
+        // The verification search of singular extensions doesn't search all the moves either
+        let use_table = (!IS_ROOT || self.excluded_root_moves.is_empty()) && excluded_move.is_null();
+        
         // Probe transposition table
-        if let Some(entry) = self.transposition_table.retrieve(self.zobrist()) {
+        let tt_entry = self.transposition_table.retrieve(self.zobrist()).filter(|_| use_table);
+        if use_table {
+            self.stats.tt_probes += 1;
+            self.stats.tt_hits += tt_entry.is_some() as u64;
+        }
+        if let Some(entry) = tt_entry {
             if entry.depth >= depth {
                 match entry.flag {
                     EntryFlag::Exact => {
@@ -112,14 +176,16 @@ impl Searcher {
         // Null move pruning
         if  !IS_PV && depth > 3 && // Don't skip a turn in PV nodes or close to the leaves
             do_null && // Don't do 2 null moves in a row
+            excluded_move.is_null() && // Don't skip a turn in the verification search
             !self.pos.global_rules.capturing_is_forced && // Don't skip a turn if capturing is forced
             eval::can_do_null_move(&self.pos) && // Don't skip a turn in endgame
             !MoveGen::in_check(&mut self.pos) // Don't skip a turn in check
         {
             self.pos.make_move(Move::null());
-            let nscore = -self.alphabeta::<false,false>(depth-3, search_depth, pv_index+1, -beta, -beta+1, false, None)?;
+            let nscore = -self.alphabeta::<false,false>(depth-3, extensions, pv_index+1, -beta, -beta+1, false, None)?;
             self.pos.unmake_move();
             if nscore >= beta {
+                self.stats.null_move_prunes += 1;
                 return Ok(beta);
             }
         }
@@ -128,61 +194,101 @@ impl Searcher {
         let mut num_legal_moves = 0;
         let old_alpha = alpha;
         let mut best_score = -Centipawns::MAX; // Use -MAX instead of MIN to avoid overflow when negating
-        let in_check = known_check || MoveGen::in_check(&mut self.pos);
-        if !IS_ROOT && in_check && self.pos.increment_num_checks() {
+        let in_check = if IS_PV { pv_in_check } else { MoveGen::in_check(&mut self.pos) };
+        let mover = self.pos.whos_turn;
+        // Don't increment the check counter in the root node, or twice for the same node
+        if !IS_ROOT && excluded_move.is_null() && in_check && self.pos.increment_num_checks() {
             // If the player has been checked N times, the game is over.
-            // Don't increment the check counter in the root node
             self.end_pv::<IS_PV>(pv_index);
             return Ok(self.checkmate_score(pv_index));
         }
-        if IS_PV && in_check && !known_check && search_depth < self.max_searching_depth {
-            // If in check, extend search by 1 ply. Limit the extension to 2x the original depth.
-            depth += 1;
-            search_depth += 1;
-            self.known_checks.insert(self.zobrist());
-        }
+        
+        // If the move from the transposition table is much better than all the others, extend it by 1 ply
+        let singular_move = {
+            if IS_ROOT { Move::null() }
+            else { self.singular_move(depth, extensions, pv_index)? }
+        };
+        let child_extensions = |mv: Move| {
+            if mv == singular_move {
+                (depth, Extensions { singular: extensions.singular + 1, ..extensions })
+            } else {
+                (depth - 1, extensions)
+            }
+        };
         
         // If a hint is available, try the hinted move as the leftmost child
+        let mut hinted_move = Move::null();
         if let Some(pv_hint) = hint {
             if IS_PV && pv_hint.len() > pv_index {
                 let mv = pv_hint[pv_index];
+                hinted_move = mv;
+                let nodes_before = self.nodes_searched;
+                if IS_ROOT {
+                    self.report_root_move(mv, 1);
+                }
                 // Since this move was in the PV, it must be legal
                 self.pos.make_move(mv);
                 num_legal_moves += 1;
                 let score: Centipawns;
                 if let Some(end_score) = self.is_game_over(mv, pv_index+1) {
                     self.end_pv::<IS_PV>(pv_index);
-                    score = -end_score;
+                    score = self.score_for(mover, end_score);
                 } else {
-                    score = -self.alphabeta::<IS_PV,false>(depth-1, search_depth, pv_index+1, -beta, -alpha, true, hint)?;
+                    let (child_depth, child_extensions) = child_extensions(mv);
+                    score = self.search_child::<IS_PV>(child_depth, child_extensions, pv_index+1, alpha, beta, hint, mover)?;
                 }
                 self.pos.unmake_move();
-                // This is the leftmost branch, we know that best_score = -INF && alpha = -INF
-                assert!(score > best_score);
-                assert!(score > alpha);
+                if IS_ROOT {
+                    self.update_root_move(mv, (score > alpha).then_some(score), self.nodes_searched - nodes_before);
+                }
+                // This is the leftmost branch, we know that best_score = -INF
                 best_score = score;
                 best_move = mv;
-                alpha = score;
-                self.update_history_heuristic(depth, mv);
+                // The window is only narrower than (-INF, INF) when using aspiration windows
+                if score >= beta {
+                    self.stats.cutoffs_by_move_index[0] += 1;
+                    self.end_pv::<IS_PV>(pv_index);
+                    return Ok(beta);
+                }
+                if score > alpha {
+                    alpha = score;
+                    self.update_history_heuristic(depth, mv);
+                }
             }
         }
         
-        // Get potential moves, sorted by move ordering heuristics (try the most promising moves first)
-        let moves = MoveGen::get_pseudo_moves(&mut self.pos, true);
-        for (_move_score, mv) in self.sort_moves_by_score(moves, depth) {
+        // Generate the potential moves in stages, trying the most promising moves first
+        let legality = LegalityInfo::with_check(&self.pos, in_check);
+        // At the root, search the moves in the order of the previous iteration
+        let mut move_picker = {
+            if IS_ROOT { MovePicker::with_moves(self.sorted_root_moves(depth)) }
+            else { MovePicker::new(self, depth) }
+        };
+        while let Some(mv) = move_picker.next(self) {
             
-            if !MoveGen::make_move_if_legal(mv, &mut self.pos) {
+            if IS_ROOT && (mv == hinted_move || self.excluded_root_moves.contains(&mv)) {
+                continue;
+            }
+            if mv == excluded_move {
+                continue;
+            }
+            let nodes_before = self.nodes_searched;
+            if !MoveGen::make_move_if_legal_with(mv, &mut self.pos, &legality) {
                 continue;
             }
 
             num_legal_moves += 1;
+            if IS_ROOT {
+                self.report_root_move(mv, num_legal_moves as u32);
+            }
+            let (child_depth, child_extensions) = child_extensions(mv);
             let mut score: Centipawns;
             if let Some(end_score) = self.is_game_over(mv, pv_index+1) {
                 self.end_pv::<IS_PV>(pv_index);
-                score = -end_score;
+                score = self.score_for(mover, end_score);
             } else if num_legal_moves == 1 {
                 // Leftmost child when the hint is not available
-                score = -self.alphabeta::<IS_PV,false>(depth-1, search_depth, pv_index+1, -beta, -alpha, true, None)?;
+                score = self.search_child::<IS_PV>(child_depth, child_extensions, pv_index+1, alpha, beta, None, mover)?;
             } else {
                 // Try late move reduction
                 if !IS_PV && num_legal_moves > 4 && mv.is_quiet() && depth >= 5 && !in_check {
@@ -191,7 +297,9 @@ impl Searcher {
                         if num_legal_moves > 10 { depth - 4 }
                         else { depth - 3 }
                     };
-                    score = -self.alphabeta::<false,false>(reduced_depth, search_depth, pv_index+1, -alpha-1, -alpha, true, None)?;
+                    score = self.search_child::<false>(reduced_depth, extensions, pv_index+1, alpha, alpha+1, None, mover)?;
+                    self.stats.lmr_searches += 1;
+                    self.stats.lmr_researches += (score > alpha) as u64;
                 } else {
                     // Cannot reduce, proceed with standard PVS
                     score = alpha + 1;
@@ -200,15 +308,18 @@ impl Searcher {
                 if IS_PV || score > alpha {
                     // PVS
                     // Null window search
-                    score = -self.alphabeta::<false,false>(depth-1, search_depth, pv_index+1, -alpha-1, -alpha, true, None)?;
+                    score = self.search_child::<false>(child_depth, child_extensions, pv_index+1, alpha, alpha+1, None, mover)?;
                     // Re-search if necessary
                     if score > alpha && score < beta {
-                        score = -self.alphabeta::<IS_PV,false>(depth-1, search_depth, pv_index+1, -beta, -alpha, true, None)?;
+                        score = self.search_child::<IS_PV>(child_depth, child_extensions, pv_index+1, alpha, beta, None, mover)?;
                     }
                 }
             }
 
             self.pos.unmake_move();
+            if IS_ROOT {
+                self.update_root_move(mv, (score > alpha).then_some(score), self.nodes_searched - nodes_before);
+            }
 
             if score > best_score {
                 best_score = score;
@@ -216,16 +327,20 @@ impl Searcher {
 
                 if score > alpha {
                     if score >= beta {
+                        let move_index = std::cmp::min(num_legal_moves - 1, CUTOFF_MOVE_INDEXES - 1);
+                        self.stats.cutoffs_by_move_index[move_index] += 1;
                         // Record new killer moves
                         self.update_killers(depth, mv);
                         // Beta cutoff, store in transpositon table
-                        self.transposition_table.insert(Entry::new(
-                            self.zobrist(),
-                            EntryFlag::Beta,
-                            beta,
-                            mv,
-                            depth,
-                        ));
+                        if use_table {
+                            self.transposition_table.insert(Entry::new(
+                                self.zobrist(),
+                                EntryFlag::Beta,
+                                beta,
+                                mv,
+                                depth,
+                            ));
+                        }
                         self.end_pv::<IS_PV>(pv_index);
                         return Ok(beta);
                     }
@@ -238,29 +353,35 @@ impl Searcher {
         }
 
         if num_legal_moves == 0 {
-            return if in_check || self.pos.global_rules.stalemated_player_loses {
+            if !excluded_move.is_null() {
+                // The excluded move is the only legal move, so it's singular
+                return Ok(alpha);
+            }
+            return if in_check {
                 // No legal moves and in check: Checkmate
                 self.end_pv::<IS_PV>(pv_index);
                 Ok(self.checkmate_score(pv_index))
             } else {
                 // No legal moves but also not in check: Stalemate
                 self.end_pv::<IS_PV>(pv_index);
-                Ok(0)
+                Ok(self.stalemate_score(pv_index))
             };
         }
 
         if IS_PV && alpha != old_alpha {
             //Alpha improvement, record PV
-            self.transposition_table.insert(Entry::new(
-                self.zobrist(),
-                EntryFlag::Exact,
-                best_score,
-                best_move,
-                depth,
-            ));
+            if use_table {
+                self.transposition_table.insert(Entry::new(
+                    self.zobrist(),
+                    EntryFlag::Exact,
+                    best_score,
+                    best_move,
+                    depth,
+                ));
+            }
             self.principal_variation[pv_index] = best_move;
             
-        } else {
+        } else if use_table {
             self.transposition_table.insert(Entry::new(
                 self.zobrist(),
                 EntryFlag::Alpha,
@@ -280,25 +401,46 @@ impl Searcher {
         if self.pos.leader_is_captured() {
             return Ok(self.checkmate_score(pv_index));
         }
+        if let Some(score) = self.rules_game_over_score(pv_index) {
+            return Ok(score);
+        }
         self.increment_num_nodes()?;
+        self.stats.qnodes += 1;
 
-        let score = eval::evaluate(&self.pos);
+        let (stand_pat, eval_hit) = self.eval_cache.evaluate(&self.pos);
+        self.stats.eval_probes += 1;
+        self.stats.eval_hits += eval_hit as u64;
         
-        if score >= beta {
-            return Ok(beta);
-        }
-        if score > alpha {
-            alpha = score;
+        // When capturing is forced, the player cannot stand pat: the chain of captures is searched until the end
+        let must_capture = self.pos.global_rules.capturing_is_forced && MoveGen::has_captures(&self.pos);
+        if !must_capture {
+            if stand_pat >= beta {
+                return Ok(beta);
+            }
+            if stand_pat > alpha {
+                alpha = stand_pat;
+            }
         }
 
         // Get only captures, sorted by move ordering heuristics (try the most promising moves first)
         let moves = MoveGen::get_pseudo_moves(&mut self.pos, false);
+        let mover = self.pos.whos_turn;
+        let can_prune = eval::can_use_see(&self.pos);
+        let legality = LegalityInfo::new(&mut self.pos);
+        let mut num_legal_moves = 0;
         for (_move_score, mv) in self.sort_moves_by_score(moves, 0) {
+            if can_prune && !mv.is_promotion() && self.prune_capture(mv, stand_pat, alpha) {
+                continue;
+            }
             // This is a capture move, so there is no need to check for repetition
-            if !MoveGen::make_move_if_legal(mv, &mut self.pos) {
+            if !MoveGen::make_move_if_legal_with(mv, &mut self.pos, &legality) {
                 continue;
             }
-            let score = -self.quiesce(-beta, -alpha, pv_index+1)?;
+            num_legal_moves += 1;
+            let score = {
+                if self.pos.whos_turn == mover { self.quiesce(alpha, beta, pv_index+1)? }
+                else { -self.quiesce(-beta, -alpha, pv_index+1)? }
+            };
             self.pos.unmake_move();
 
             if score >= beta {
@@ -308,26 +450,127 @@ impl Searcher {
                 alpha = score;
             }
         }
+        // If all the captures are illegal, the player can make a quiet move instead
+        if must_capture && num_legal_moves == 0 {
+            return Ok(stand_pat.clamp(alpha, beta));
+        }
         Ok(alpha)
     }
     
+    // Searches the position after a move of `mover`, and returns the score from the point of view of `mover`.
+    // The window is also given from the point of view of `mover`. When players can make several moves per turn,
+    // the same player may be to move again, in which case the score is not negated.
+    #[inline]
+    #[allow(clippy::too_many_arguments)]
+    fn search_child<const IS_PV: bool>(&mut self,
+            depth: Depth,
+            extensions: Extensions,
+            pv_index: usize,
+            alpha: Centipawns,
+            beta: Centipawns,
+            hint: Option<&Vec<Move>>,
+            mover: Player
+        ) -> Result<Centipawns, SearchTimeout>
+    {
+        if self.pos.whos_turn == mover {
+            self.alphabeta::<IS_PV,false>(depth, extensions, pv_index, alpha, beta, true, hint)
+        } else {
+            Ok(-self.alphabeta::<IS_PV,false>(depth, extensions, pv_index, -beta, -alpha, true, hint)?)
+        }
+    }
+    
+    /// Converts a score for the player to move to the point of view of `player`
+    #[inline]
+    pub fn score_for(&self, player: Player, score: Centipawns) -> Centipawns {
+        if self.pos.whos_turn == player { score } else { -score }
+    }
+    
+    /// Returns true if the search path can be extended by 1 more ply.
+    /// The total extension is limited to the original depth of the search.
+    #[inline]
+    fn can_extend(&self, extensions: Extensions) -> bool {
+        extensions.total() < self.max_searching_depth / 2
+    }
+    
+    /// Returns the move from the transposition table if it's singular: a search at reduced depth that excludes it
+    /// fails low by a margin. Otherwise (or if the conditions for singular extensions are not met), returns a null move.
+    fn singular_move(&mut self, depth: Depth, extensions: Extensions, pv_index: usize) -> Result<Move, SearchTimeout> {
+        if depth < SINGULAR_MIN_DEPTH || extensions.singular >= MAX_SINGULAR_EXTENSIONS || !self.can_extend(extensions) {
+            return Ok(Move::null());
+        }
+        // Don't start a verification search inside another verification search of the same node
+        if !self.excluded_moves[pv_index].is_null() {
+            return Ok(Move::null());
+        }
+        let entry = match self.transposition_table.retrieve(self.zobrist()) {
+            Some(entry) => *entry,
+            None => return Ok(Move::null()),
+        };
+        // The entry must be a lower bound (or exact) from a search that was not much shallower
+        let is_lower_bound = matches!(entry.flag, EntryFlag::Beta | EntryFlag::Exact);
+        let is_mate_score = entry.value.abs() > -GAME_OVER_SCORE / 2;
+        if entry.mv.is_null() || !is_lower_bound || is_mate_score || entry.depth + 3 < depth {
+            return Ok(Move::null());
+        }
+        
+        let singular_beta = entry.value - SINGULAR_MARGIN * depth as Centipawns;
+        self.stats.singular_searches += 1;
+        self.excluded_moves[pv_index] = entry.mv;
+        // The verification search is not part of the search tree
+        let tree_plies = std::mem::take(&mut self.limits.tree_plies);
+        let score = self.alphabeta::<false,false>(depth / 2, extensions, pv_index, singular_beta-1, singular_beta, false, None);
+        self.limits.tree_plies = tree_plies;
+        self.excluded_moves[pv_index] = Move::null();
+        
+        if score? < singular_beta {
+            self.stats.singular_extensions += 1;
+            Ok(entry.mv)
+        } else {
+            Ok(Move::null())
+        }
+    }
+    
+    /// Returns true if a capture can be skipped in the quiescence search: either it can't raise alpha
+    /// even if the captured piece was free (delta pruning), or it loses material (SEE < 0)
+    fn prune_capture(&self, mv: Move, stand_pat: Centipawns, alpha: Centipawns) -> bool {
+        // Safety margin for positional gains, roughly 2 pawns
+        const DELTA_MARGIN: Centipawns = 200;
+        let victim_score = match self.pos.player_piece_at(1 - self.pos.whos_turn, mv.get_target()) {
+            // Never skip capturing a leader
+            Some(piece) if piece.is_leader() => return false,
+            Some(piece) => piece.get_material_score(),
+            None => 0,
+        };
+        if stand_pat + victim_score + DELTA_MARGIN < alpha {
+            return true;
+        }
+        MoveGen::see(&self.pos, mv) < 0
+    }
+    
     #[inline]
-    fn zobrist(&self) -> ZobKey {
+    pub(super) fn zobrist(&self) -> ZobKey {
         self.pos.get_zobrist()
     } 
     
     #[inline]
     fn increment_num_nodes(&mut self) -> Result<(), SearchTimeout> {
         self.nodes_searched += 1;
-        // Check for timeout periodically (every 2^19 nodes)
+        // The node limit is checked on every node, so that the search is reproducible
+        // If this is the first search (depth 1, max_searching_depth 2), don't stop
+        if self.node_limit_reached() && self.max_searching_depth > 2 {
+            #[cfg(feature = "parallel")]
+            self.stop_flag.store(true, Ordering::Relaxed);
+            return Err(SearchTimeout);
+        }
+        // Check for timeout periodically (every 2^14 nodes, often enough for the short thinking times of a clock)
         #[allow(clippy::collapsible_if)]
-        if self.nodes_searched.trailing_zeros() >= 19 {
+        if self.nodes_searched.trailing_zeros() >= 14 {
             #[cfg(feature = "parallel")]
             if self.stop_flag.load(Ordering::Relaxed) {
                 return Err(SearchTimeout);
             }
             // If this is the first search (depth 1, max_searching_depth 2), don't time out
-            if Instant::now() >= self.end_time && self.max_searching_depth > 2 {
+            if (self.time_is_up() || self.is_cancelled()) && self.max_searching_depth > 2 {
                 // Signal other threads to stop
                 #[cfg(feature = "parallel")]
                 self.stop_flag.store(true, Ordering::Relaxed);
@@ -339,15 +582,25 @@ impl Searcher {
     
     #[inline]
     // Check for instant game over conditions (does not check for checkmate or stalemate)
-    fn is_game_over(&mut self, mv: Move, pv_index: usize) -> Option<Centipawns> {
+    pub fn is_game_over(&mut self, mv: Move, pv_index: usize) -> Option<Centipawns> {
         // There is repetition, the result is always a draw
         if self.pos.draw_by_repetition() {
-            return Some(0);
+            return Some(self.draw_score());
         }
         // The leader is captured
         if self.pos.leader_is_captured() {
             return Some(self.checkmate_score(pv_index));
         }
+        // Only captures can leave the players without enough material to checkmate
+        if mv.is_capture() && self.pos.insufficient_material() {
+            return Some(self.draw_score());
+        }
+        // Too many moves without progress, the result is a draw unless the move is checkmate
+        if self.pos.draw_by_moves_without_progress() &&
+            !(MoveGen::in_check(&mut self.pos) && MoveGen::get_legal_moves(&mut self.pos).is_empty())
+        {
+            return Some(self.draw_score());
+        }
         // The opponent has moved the leader to a winning position
         let opponent = 1 - self.pos.whos_turn;
         let to = mv.get_to();
@@ -357,7 +610,30 @@ impl Searcher {
                 return Some(self.checkmate_score(pv_index));
             }
         }
-        None
+        // The game has ended because of the win conditions, the capture limit or the move limit
+        self.rules_game_over_score(pv_index)
+    }
+    
+    // If the game has ended because of the win conditions, the capture limit or the move limit of the global rules,
+    // returns the score of the game for the player to move
+    fn rules_game_over_score(&self, pv_index: usize) -> Option<Centipawns> {
+        let winner = {
+            if let Some(player) = self.pos.win_condition_met() {
+                player
+            } else if let Some(player) = self.pos.capture_limit_reached() {
+                player
+            } else if self.pos.move_limit_reached() {
+                // The player with more material wins, or it is a draw
+                match self.pos.material_leader() {
+                    Some(player) => player,
+                    None => return Some(self.draw_score()),
+                }
+            } else {
+                return None;
+            }
+        };
+        let score = self.checkmate_score(pv_index);
+        Some(if winner == self.pos.whos_turn { -score } else { score })
     }
 
     #[inline]
@@ -378,7 +654,7 @@ impl Searcher {
     }
 
     #[inline]
-    fn sort_moves_by_score(&self, moves: Vec<Move>, depth: Depth) -> Vec<(Centipawns, Move)> {
+    pub(super) fn sort_moves_by_score(&self, moves: Vec<Move>, depth: Depth) -> Vec<(Centipawns, Move)> {
         // Limit depth to the size of the killer moves array
         let depth = std::cmp::min(self.killer_moves.len() - 1, depth as usize);
         let mut moves_and_score = Vec::with_capacity(moves.len());
@@ -397,24 +673,41 @@ impl Searcher {
             }
         }
         
-        // Sort moves by decreasing score
-        moves_and_score.sort_unstable_by(|a, b| b.0.cmp(&a.0));
-        
-        if self.pos.global_rules.invert_win_conditions {
-            moves_and_score.reverse();
-        }
-        
+        // Sort moves by decreasing score. Use a stable sort, so that ties are always broken in the same way
+        // (by the order of move generation) and the search is reproducible.
+        moves_and_score.sort_by_key(|a| std::cmp::Reverse(a.0));
         moves_and_score
     }
     
     #[inline]
-    fn checkmate_score(&self, pv_index: usize) -> Centipawns {
+    pub fn checkmate_score(&self, pv_index: usize) -> Centipawns {
         // A checkmate is effectively -inf, but if we are losing we prefer the longest sequence
         // Add 1 centipawn per ply to the score to prefer shorter checkmates (or longer when losing)
         let score = GAME_OVER_SCORE + pv_index as Centipawns;
         if self.pos.global_rules.invert_win_conditions { -score } else { score }
     }
     
+    // Score of a stalemate for the player to move, see `StalemateRule`
+    fn stalemate_score(&self, pv_index: usize) -> Centipawns {
+        let loss = self.checkmate_score(pv_index);
+        match self.pos.global_rules.stalemate_rule {
+            StalemateRule::Draw => self.draw_score(),
+            StalemateRule::StalematedLoses => loss,
+            StalemateRule::StalematedWins => -loss,
+            StalemateRule::MaterialAdjudication => match self.pos.material_leader() {
+                Some(player) if player == self.pos.whos_turn => -loss,
+                Some(_) => loss,
+                None => self.draw_score(),
+            },
+        }
+    }
+    
+    #[inline]
+    pub fn draw_score(&self) -> Centipawns {
+        // With contempt, the player to move at the root avoids draws (or seeks them if the contempt is negative)
+        if self.pos.whos_turn == self.root_player { -self.contempt } else { self.contempt }
+    }
+    
     #[inline]
     fn end_pv<const IS_PV: bool>(&mut self, index: usize) {
         if IS_PV {
@@ -1,70 +1,672 @@
+use std::fmt;
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::MoveGen;
 use crate::position::Position;
-use crate::types::{Move, Centipawns};
+use crate::position::piece_set::PieceSet;
+use crate::piece::Piece;
+use crate::types::{Move, Centipawns, EvalBreakdown, PieceEval, BCoord, BIndex, Bitboard, Player, ZobKey, BOARD_SIZE, BOARD_WIDTH};
+use crate::utils::from_index;
 
 use super::Searcher;
 
+// Material score (without leaders) of both players combined, below which the game is considered to be in the endgame
+// Arbitrary threshold of roughly 2 queens and 2 rooks, feel free to experiment
+const ENDGAME_THRESHOLD: Centipawns = 3000;
+// Penalty for each time that a player has been checked (only in N-check variants)
+const CHECK_PENALTY: Centipawns = 512;
+// Number of turns before the move limit in which only the material is evaluated (see `GlobalRules::move_limit`)
+const MOVE_LIMIT_HORIZON: u32 = 4;
+// Bonus for each pawn of the leaderless player (Horde) that has a friendly pawn next to it or defending it
+const HORDE_COHESION: Centipawns = 5;
+// Bonus for the pawns of the leaderless player (Horde) that approach the enemy back rank, scaled quadratically
+// with the number of ranks that they have advanced beyond the middle of the board
+const HORDE_BREAKTHROUGH: Centipawns = 8;
+// Bonus for each piece that the opponent can capture, minus the pieces that the player can capture (Antichess)
+const ANTICHESS_CAPTURE: Centipawns = 40;
+// Bonus for each capture that would explode an enemy leader (only if some pieces explode, like in Atomic)
+const EXPLOSION_THREAT: Centipawns = 150;
+// Penalty for each piece whose capture would explode a friendly leader (only if some pieces explode)
+const EXPLOSION_CLUSTERING: Centipawns = 20;
+// Bonus for each move that the closest piece of a player is from reaching a win square (King of the Hill, Racing Kings),
+// counted from the size of the board. Interpolated between these values with the material on the board.
+const GOAL_DISTANCE_MIDDLEGAME: Centipawns = 10;
+const GOAL_DISTANCE_ENDGAME: Centipawns = 30;
+// Material score (without leaders) of both players combined, above which the middlegame weight is used
+const GOAL_DISTANCE_OPENING_MATERIAL: Centipawns = 2 * ENDGAME_THRESHOLD;
+
+/// Weights of the pawn structure and king safety terms of the evaluation.
+/// Pawns are generalized to any piece that can only move forward (see `PieceDefinition::forward_direction()`).
+/// Setting a weight to 0 disables the corresponding term.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalWeights {
+    /// Penalty for each additional pawn on the same file
+    pub doubled_pawn: Centipawns,
+    /// Penalty for each pawn without friendly pawns on the adjacent files
+    pub isolated_pawn: Centipawns,
+    /// Bonus for a passed pawn (no enemy pawns in front of it, on the same or adjacent files) on the last rank.
+    /// Scaled linearly with the number of ranks that the pawn has advanced.
+    pub passed_pawn: Centipawns,
+    /// Penalty for each enemy piece (other than pawns and leaders) at a distance of at most 2 squares from
+    /// the leader. Only in the middlegame.
+    pub king_attacker: Centipawns,
+    /// Bonus for each friendly pawn in the 2 ranks in front of the leader. Only in the middlegame.
+    pub pawn_shield: Centipawns,
+    /// Bonus for a piece (other than pawns and leaders) that can move to as many squares as it could from
+    /// the center of an empty board. Scaled linearly with the number of squares it can move to.
+    pub mobility: Centipawns,
+}
+
+/// Default weights, used by `evaluate()`. See `evaluate_with_weights()` and `Engine::set_eval_weights()`.
+pub const EVAL_WEIGHTS: EvalWeights = EvalWeights {
+    doubled_pawn: 15,
+    isolated_pawn: 10,
+    passed_pawn: 80,
+    king_attacker: 12,
+    pawn_shield: 8,
+    mobility: 30,
+};
+
+/// Variant-specific terms of the evaluation. The position doesn't know its `GameMode`,
+/// so the profile is detected from the rules and the pieces (see `EvalProfile::for_position()`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvalProfile {
+    /// Only the standard terms
+    Standard,
+    /// One of the players has no leaders (Horde). Their pawns are not penalized for being doubled or isolated,
+    /// and get a bonus for staying together (cohesion) and for approaching the enemy back rank (breakthrough).
+    Horde { leaderless: Player },
+    /// The goal is to lose all the pieces and capturing is forced (Antichess). Only the material (which is bad)
+    /// and the available captures (which are forced) are evaluated.
+    Antichess,
+}
+
+impl EvalProfile {
+    /// Returns the profile that matches the rules and pieces of the position
+    pub fn for_position(position: &Position) -> EvalProfile {
+        let rules = &position.global_rules;
+        if rules.invert_win_conditions {
+            if rules.capturing_is_forced {
+                return EvalProfile::Antichess;
+            }
+            return EvalProfile::Standard;
+        }
+        match (position.pieces[0].has_leader(), position.pieces[1].has_leader()) {
+            (false, true) => EvalProfile::Horde { leaderless: 0 },
+            (true, false) => EvalProfile::Horde { leaderless: 1 },
+            _ => EvalProfile::Standard,
+        }
+    }
+}
+
+// Number of entries in the mobility cache, must be a power of 2
+const MOBILITY_CACHE_SIZE: usize = 1 << 14;
+
+/// Stores the mobility scores of recently evaluated positions, indexed by zobrist key.
+/// Computing the mobility is the slowest part of the evaluation, and the same positions are
+/// evaluated many times during a search.
+#[derive(Debug, Clone)]
+pub struct MobilityCache {
+    entries: Vec<(ZobKey, [Centipawns; 2])>,
+}
+
+impl MobilityCache {
+    pub fn new() -> MobilityCache {
+        MobilityCache { entries: vec![(0, [0, 0]); MOBILITY_CACHE_SIZE] }
+    }
+    
+    // Returns the mobility score of each player
+    fn get_or_compute(&mut self, position: &Position, weights: &EvalWeights) -> [Centipawns; 2] {
+        // The mobility doesn't depend on the player to move, which is stored in the lowest bit
+        let key = position.get_zobrist() | 1;
+        let entry = &mut self.entries[key as usize & (MOBILITY_CACHE_SIZE - 1)];
+        if entry.0 != key {
+            *entry = (key, [mobility_score(position, 0, weights), mobility_score(position, 1, weights)]);
+        }
+        entry.1
+    }
+}
+
+impl Default for MobilityCache {
+    fn default() -> Self {
+        MobilityCache::new()
+    }
+}
+
+/// Static evaluation used by the search, e.g. to replace the built-in evaluation with a neural network.
+/// See `SearchHandle::with_evaluator()` and `Engine::set_evaluator()`.
+pub trait Evaluator {
+    /// Returns the score of the position for the player to move (`position.whos_turn`), in centipawns.
+    /// The result must only depend on the position (it's cached by zobrist key).
+    fn evaluate(&self, position: &Position) -> Centipawns;
+}
+
+impl fmt::Debug for dyn Evaluator + Send + Sync {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Evaluator")
+    }
+}
+
+/// The built-in hand-crafted evaluation, `evaluate()`. Used when no other evaluator is given.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HandCraftedEvaluator;
+
+impl Evaluator for HandCraftedEvaluator {
+    fn evaluate(&self, position: &Position) -> Centipawns {
+        evaluate(position)
+    }
+}
+
+// Number of entries in the evaluation cache, must be a power of 2
+const EVAL_CACHE_SIZE: usize = 1 << 15;
+
+/// Stores the evaluation of recently evaluated positions, indexed by zobrist key, together with a `MobilityCache`
+/// for the positions that are not found. The zobrist key includes everything that the evaluation depends on
+/// (including the number of checks), except for the distance to the move limit: positions close to it are not cached.
+#[derive(Debug, Clone)]
+pub struct EvalCache {
+    entries: Vec<Option<(ZobKey, Centipawns)>>,
+    mobility_cache: MobilityCache,
+    // Replaces the built-in evaluation if set (then the mobility cache and the weights are unused)
+    evaluator: Option<Arc<dyn Evaluator + Send + Sync>>,
+    weights: EvalWeights,
+}
+
+impl EvalCache {
+    pub fn new() -> EvalCache {
+        EvalCache::with_evaluator(None, EVAL_WEIGHTS)
+    }
+    
+    /// Same as `new()`, but the positions are evaluated with `evaluator` if it's not `None`,
+    /// or else with `evaluate_with_weights()`
+    pub fn with_evaluator(evaluator: Option<Arc<dyn Evaluator + Send + Sync>>, weights: EvalWeights) -> EvalCache {
+        EvalCache {
+            entries: vec![None; EVAL_CACHE_SIZE],
+            mobility_cache: MobilityCache::new(),
+            evaluator,
+            weights,
+        }
+    }
+    
+    /// Same as `evaluate()`, but the result is retrieved from the cache if possible.
+    /// Also returns `true` if the position was found in the cache.
+    pub fn evaluate(&mut self, position: &Position) -> (Centipawns, bool) {
+        if near_move_limit(position) {
+            return (self.evaluate_uncached(position), false);
+        }
+        let key = position.get_zobrist();
+        let index = key as usize & (EVAL_CACHE_SIZE - 1);
+        match self.entries[index] {
+            Some((entry_key, score)) if entry_key == key => (score, true),
+            _ => {
+                let score = self.evaluate_uncached(position);
+                self.entries[index] = Some((key, score));
+                (score, false)
+            },
+        }
+    }
+    
+    fn evaluate_uncached(&mut self, position: &Position) -> Centipawns {
+        match &self.evaluator {
+            Some(evaluator) => evaluator.evaluate(position),
+            None => evaluate_impl(position, &self.weights, Some(&mut self.mobility_cache)),
+        }
+    }
+}
+
+impl Default for EvalCache {
+    fn default() -> Self {
+        EvalCache::new()
+    }
+}
+
+impl Default for EvalWeights {
+    fn default() -> Self {
+        EVAL_WEIGHTS
+    }
+}
+
 /// Retrieves the score for the player to move (`position.whos_turn`)
 pub fn evaluate(position: &Position) -> Centipawns {
-    // Material score (without leaders) of both players combined, below which the game is considered to be in the endgame
-    // Arbitrary threshold of roughly 2 queens and 2 rooks, feel free to experiment
-    const ENDGAME_THRESHOLD: Centipawns = 3000;
-    
+    evaluate_impl(position, &EVAL_WEIGHTS, None)
+}
+
+/// Same as `evaluate()`, but with custom weights for the pawn structure, king safety and mobility terms
+pub fn evaluate_with_weights(position: &Position, weights: &EvalWeights) -> Centipawns {
+    evaluate_impl(position, weights, None)
+}
+
+fn evaluate_impl(position: &Position, weights: &EvalWeights, cache: Option<&mut MobilityCache>) -> Centipawns {
+    evaluate_terms(position, weights, cache).total()
+}
+
+// Terms of the evaluation for the player to move, shared by `evaluate()` and `evaluate_breakdown()`.
+// Everything except the variant terms and the checks is inverted when trying to lose.
+struct EvalTerms {
+    material: Centipawns,
+    positional: Centipawns,
+    mobility: Centipawns,
+    pawn_structure: Centipawns,
+    king_safety: Centipawns,
+    checks: Centipawns,
+    variant: Centipawns,
+    is_endgame: bool,
+}
+
+impl EvalTerms {
+    fn total(&self) -> Centipawns {
+        self.material + self.positional + self.mobility + self.pawn_structure + self.king_safety + self.checks + self.variant
+    }
+}
+
+fn evaluate_terms(position: &Position, weights: &EvalWeights, cache: Option<&mut MobilityCache>) -> EvalTerms {
     let player_num = position.whos_turn;
-    // Material score (opponent pieces are negative)
-    let mut score = 0;
+    let profile = EvalProfile::for_position(position);
+    // Opponent pieces are negative
+    let sign = |ps: &PieceSet| if ps.get_player_num() == player_num { 1 } else { -1 };
+    let mut terms = EvalTerms {
+        material: 0, positional: 0, mobility: 0, pawn_structure: 0, king_safety: 0, checks: 0, variant: 0, is_endgame: false,
+    };
     //Material score of both players (opponent pieces are positive), without the leaders
     let mut total_leaderless_score = 0;
     
     for ps in &position.pieces {
         let (material_score, leaders_score) = ps.get_material_score();
-        
-        if ps.get_player_num() == player_num {
-            score += material_score;
-        } else {
-            score -= material_score;
-        }
-        
+        terms.material += sign(ps) * material_score;
         total_leaderless_score += material_score - leaders_score;
     }
 
-    // Positional score
-    let is_endgame = total_leaderless_score < ENDGAME_THRESHOLD;
-    for ps in &position.pieces {
-        let ps_score = {
-            if is_endgame {
-                ps.get_positional_score::<true>()
-            } else {
-                ps.get_positional_score::<false>()
-            }
-        };
-        if ps.get_player_num() == player_num {
-            score += ps_score;
-        } else {
-            score -= ps_score;
+    terms.is_endgame = total_leaderless_score < ENDGAME_THRESHOLD;
+    // Close to the move limit, only the material decides the result of the game.
+    // In antichess, the standard terms assume that the goal is to keep the pieces.
+    if !near_move_limit(position) && profile != EvalProfile::Antichess {
+        for ps in &position.pieces {
+            let ps_score = {
+                if terms.is_endgame {
+                    ps.get_positional_score::<true>()
+                } else {
+                    ps.get_positional_score::<false>()
+                }
+            };
+            terms.positional += sign(ps) * ps_score;
+        }
+
+        (terms.pawn_structure, terms.king_safety) = structure_scores(position, terms.is_endgame, weights, profile);
+    
+        if weights.mobility != 0 {
+            let mobility = match cache {
+                Some(cache) => cache.get_or_compute(position, weights),
+                None => [mobility_score(position, 0, weights), mobility_score(position, 1, weights)],
+            };
+            terms.mobility = mobility[player_num as usize] - mobility[1 - player_num as usize];
         }
     }
 
     // When trying to lose, minimize own score
     if position.global_rules.invert_win_conditions {
-        score = -score;
+        terms.material = -terms.material;
+        terms.positional = -terms.positional;
+        terms.mobility = -terms.mobility;
+        terms.pawn_structure = -terms.pawn_structure;
+        terms.king_safety = -terms.king_safety;
+    }
+    
+    if !near_move_limit(position) {
+        terms.variant = variant_score(position, profile, total_leaderless_score);
     }
     
     if let Some(times_checked) = position.get_times_checked() {
-        const CHECK_PENALTY: Centipawns = 512;
-        score -= CHECK_PENALTY * times_checked[player_num as usize] as Centipawns;
-        score += CHECK_PENALTY * times_checked[1-player_num as usize] as Centipawns;
+        terms.checks = CHECK_PENALTY * (times_checked[1-player_num as usize] as Centipawns
+            - times_checked[player_num as usize] as Centipawns);
+    }
+    
+    terms
+}
+
+
+// Returns true if the game is about to end because of the move limit
+fn near_move_limit(position: &Position) -> bool {
+    position.turns_until_move_limit().is_some_and(|turns| turns <= MOVE_LIMIT_HORIZON)
+}
+
+/// Same as `evaluate()`, but returns the contribution of each piece and each term of the evaluation.
+/// This is slower, don't use it during the search.
+pub fn evaluate_breakdown(position: &Position) -> EvalBreakdown {
+    evaluate_breakdown_with_weights(position, &EVAL_WEIGHTS)
+}
+
+/// Same as `evaluate_breakdown()`, but with custom weights for the pawn structure, king safety and mobility terms
+pub fn evaluate_breakdown_with_weights(position: &Position, weights: &EvalWeights) -> EvalBreakdown {
+    let terms = evaluate_terms(position, weights, None);
+    let player_num = position.whos_turn;
+    // Everything is inverted when trying to lose
+    let invert_sign = if position.global_rules.invert_win_conditions { -1 } else { 1 };
+    // The positional and mobility scores are 0 when they are not evaluated
+    let standard_factor = if near_move_limit(position) || EvalProfile::for_position(position) == EvalProfile::Antichess { 0 } else { 1 };
+    
+    // Contribution of each piece on the board, which adds up to the material (without the pieces in hand),
+    // positional and mobility terms
+    let mut pieces = Vec::new();
+    for ps in &position.pieces {
+        // Opponent pieces are negative
+        let sign = if ps.get_player_num() == player_num { invert_sign } else { -invert_sign };
+        let enemies = position.pieces[1 - ps.get_player_num() as usize].get_occupied();
+        for piece in ps.iter() {
+            for index in piece.get_indexes() {
+                let positional = {
+                    if terms.is_endgame { piece.get_positional_score::<true>(index) }
+                    else { piece.get_positional_score::<false>(index) }
+                };
+                pieces.push(PieceEval {
+                    piece_id: piece.get_piece_id(),
+                    position: from_index(index),
+                    material: sign * piece.get_material_score(),
+                    positional: standard_factor * sign * positional,
+                    mobility: standard_factor * sign * piece_mobility_score(position, piece, index, enemies, weights),
+                });
+            }
+        }
     }
     
+    EvalBreakdown {
+        total: terms.total(),
+        material: terms.material,
+        positional: terms.positional,
+        mobility: terms.mobility,
+        pawn_structure: terms.pawn_structure,
+        king_safety: terms.king_safety,
+        checks: terms.checks,
+        variant: terms.variant,
+        is_endgame: terms.is_endgame,
+        pieces,
+    }
+}
+
+
+// Position of a pawn-like piece: (x, y, forward direction)
+type PawnInfo = (i8, i8, i8);
+
+// Pawn-like pieces of a player. The evaluation runs at every leaf of the search, so they are stored
+// in a fixed-size buffer instead of being allocated.
+struct Pawns {
+    pawns: [PawnInfo; BOARD_SIZE],
+    len: usize,
+}
+
+impl Pawns {
+    fn of(piece_set: &PieceSet) -> Pawns {
+        let mut pawns = Pawns { pawns: [(0, 0, 0); BOARD_SIZE], len: 0 };
+        for piece in piece_set.iter() {
+            if let Some(direction) = piece.get_forward_direction() {
+                for index in piece.get_bitboard() {
+                    let (x, y) = from_index(index);
+                    pawns.pawns[pawns.len] = (x as i8, y as i8, direction);
+                    pawns.len += 1;
+                }
+            }
+        }
+        pawns
+    }
+}
+
+impl Deref for Pawns {
+    type Target = [PawnInfo];
+    fn deref(&self) -> &[PawnInfo] {
+        &self.pawns[..self.len]
+    }
+}
+
+// Returns the pawn structure and king safety scores for the player to move
+fn structure_scores(position: &Position, is_endgame: bool, weights: &EvalWeights, profile: EvalProfile) -> (Centipawns, Centipawns) {
+    let pawns = [Pawns::of(&position.pieces[0]), Pawns::of(&position.pieces[1])];
+    let height = position.dimensions.height;
+    let mut pawn_structure = [0; 2];
+    let mut king_safety = [0; 2];
+    for player in 0..2 {
+        let enemy = 1 - player;
+        let player_weights = match profile {
+            // The pawns of the horde are always doubled, the cohesion term is used instead
+            EvalProfile::Horde { leaderless } if leaderless as usize == player => {
+                EvalWeights { doubled_pawn: 0, isolated_pawn: 0, ..*weights }
+            },
+            _ => *weights,
+        };
+        pawn_structure[player] = pawn_structure_score(&pawns[player], &pawns[enemy], height, &player_weights);
+        if !is_endgame {
+            king_safety[player] = king_safety_score(position, player as Player, &pawns[player], weights);
+        }
+    }
+    let me = position.whos_turn as usize;
+    let enemy = 1 - me;
+    (pawn_structure[me] - pawn_structure[enemy], king_safety[me] - king_safety[enemy])
+}
+
+fn pawn_structure_score(pawns: &[PawnInfo], enemy_pawns: &[PawnInfo], height: BCoord, weights: &EvalWeights) -> Centipawns {
+    let mut score = 0;
+    let mut pawns_per_file = [0; BOARD_WIDTH as usize];
+    for (x, _, _) in pawns {
+        pawns_per_file[*x as usize] += 1;
+    }
+    for count in pawns_per_file {
+        if count > 1 {
+            score -= weights.doubled_pawn * (count - 1);
+        }
+    }
+    for &(x, y, direction) in pawns {
+        let has_neighbor = |file: i8| (0..BOARD_WIDTH as i8).contains(&file) && pawns_per_file[file as usize] > 0;
+        if !has_neighbor(x - 1) && !has_neighbor(x + 1) {
+            score -= weights.isolated_pawn;
+        }
+        let is_blocked = |&(ex, ey, _): &PawnInfo| (ex - x).abs() <= 1 && (ey - y) * direction > 0;
+        if !enemy_pawns.iter().any(is_blocked) {
+            let last_rank = height as i8 - 1;
+            let advanced = if direction > 0 { y } else { last_rank - y };
+            score += weights.passed_pawn * advanced as Centipawns / last_rank.max(1) as Centipawns;
+        }
+    }
+    score
+}
+
+fn king_safety_score(position: &Position, player: Player, pawns: &[PawnInfo], weights: &EvalWeights) -> Centipawns {
+    // Only applies if the player has a single leader
+    let leader = match position.pieces[player as usize].get_single_leader() {
+        Some(leader) => leader,
+        None => return 0,
+    };
+    let (lx, ly) = from_index(leader);
+    let (lx, ly) = (lx as i8, ly as i8);
+    
+    let mut score = 0;
+    for &(x, y, direction) in pawns {
+        let ranks_ahead = (y - ly) * direction;
+        if (x - lx).abs() <= 1 && (1..=2).contains(&ranks_ahead) {
+            score += weights.pawn_shield;
+        }
+    }
+    for piece in position.pieces[1 - player as usize].iter() {
+        if piece.is_leader() || piece.get_forward_direction().is_some() {
+            continue;
+        }
+        for index in piece.get_bitboard() {
+            let (x, y) = from_index(index);
+            if (x as i8 - lx).abs() <= 2 && (y as i8 - ly).abs() <= 2 {
+                score -= weights.king_attacker;
+            }
+        }
+    }
+    score
+}
+
+
+// Returns the score of the variant-specific terms (see `EvalProfile`), the explosion terms and the distance to the
+// win squares for the player to move. Unlike the other terms, the score is not inverted when trying to lose.
+fn variant_score(position: &Position, profile: EvalProfile, total_leaderless_score: Centipawns) -> Centipawns {
+    let me = position.whos_turn;
+    let mut score = match profile {
+        EvalProfile::Standard => 0,
+        EvalProfile::Horde { leaderless } => {
+            let score = horde_score(&Pawns::of(&position.pieces[leaderless as usize]), position.dimensions.height);
+            if leaderless == me { score } else { -score }
+        },
+        EvalProfile::Antichess => {
+            // The pieces attacked by the opponent will be given away, but the player to move must capture first
+            let enemy_captures = capture_targets(position, 1 - me).count_ones() as Centipawns;
+            let own_captures = capture_targets(position, me).count_ones() as Centipawns;
+            ANTICHESS_CAPTURE * (enemy_captures - own_captures)
+        },
+    };
+    // The explosion terms assume that the goal is to keep the leaders
+    if !position.global_rules.invert_win_conditions && has_explosions(position) {
+        score += explosion_score(position, me) - explosion_score(position, 1 - me);
+    }
+    // Reaching a win square would lose the game
+    if !position.global_rules.invert_win_conditions {
+        // The leaders are safer in the endgame
+        let material = total_leaderless_score.clamp(0, GOAL_DISTANCE_OPENING_MATERIAL);
+        let weight = (GOAL_DISTANCE_ENDGAME * (GOAL_DISTANCE_OPENING_MATERIAL - material)
+            + GOAL_DISTANCE_MIDDLEGAME * material) / GOAL_DISTANCE_OPENING_MATERIAL;
+        score += goal_distance_score(position, me, weight) - goal_distance_score(position, 1 - me, weight);
+    }
+    score
+}
+
+// Bonus for the piece of a player that is closest to one of its win squares
+fn goal_distance_score(position: &Position, player: Player, weight: Centipawns) -> Centipawns {
+    let mut closest = None;
+    for piece in position.pieces[player as usize].iter().filter(|piece| piece.has_win_squares()) {
+        for index in piece.get_bitboard() {
+            if let Some(distance) = piece.get_win_distance(index) {
+                closest = Some(closest.map_or(distance, |closest: u8| closest.min(distance)));
+            }
+        }
+    }
+    let horizon = position.dimensions.width.max(position.dimensions.height) as Centipawns;
+    match closest {
+        Some(distance) => weight * (horizon - distance as Centipawns).max(0),
+        None => 0,
+    }
+}
+
+// Cohesion and breakthrough score of the pawns of the leaderless player in Horde
+fn horde_score(pawns: &[PawnInfo], height: BCoord) -> Centipawns {
+    let last_rank = height as i8 - 1;
+    let mut score = 0;
+    for &(x, y, direction) in pawns {
+        // A pawn next to another one can be defended by advancing, a pawn behind it defends it
+        let is_supported = |&(ox, oy, _): &PawnInfo| (ox - x).abs() == 1 && (oy == y || oy == y - direction);
+        if pawns.iter().any(is_supported) {
+            score += HORDE_COHESION;
+        }
+        let advanced = if direction > 0 { y } else { last_rank - y };
+        let beyond_middle = (advanced - last_rank / 2).max(0) as Centipawns;
+        score += HORDE_BREAKTHROUGH * beyond_middle * beyond_middle;
+    }
+    score
+}
+
+// Returns the enemy pieces that a player can capture (without checking if the captures are legal)
+fn capture_targets(position: &Position, player: Player) -> Bitboard {
+    let enemies = position.pieces[1 - player as usize].get_occupied();
+    let mut captures = Vec::new();
+    for piece in position.pieces[player as usize].iter() {
+        piece.output_captures(position, enemies, &position.occ_or_out_bounds, &mut captures);
+    }
+    let mut targets = Bitboard::zero();
+    for mv in captures {
+        targets.set_bit(mv.get_target());
+    }
+    // En passant squares are only meaningful for the player to move
+    targets & enemies
+}
+
+
+// Returns true if some piece type explodes when capturing
+fn has_explosions(position: &Position) -> bool {
+    position.pieces.iter().any(|ps| ps.iter().any(|piece| piece.explodes_on_capture()))
+}
+
+// Returns the squares of the leaders of a player that are destroyed if they are caught in an explosion
+fn explosive_royals(position: &Position, player: Player) -> Bitboard {
+    let ps = &position.pieces[player as usize];
+    let mut royals = ps.get_royal_squares(position.global_rules.leader_capture_rule);
+    for leader in ps.get_leaders().filter(|leader| leader.immune_to_explosion()) {
+        royals &= !leader.get_bitboard();
+    }
+    royals
+}
+
+// Explosion threats of a player on the enemy leaders, and penalty for the pieces that are next to their own leaders
+fn explosion_score(position: &Position, player: Player) -> Centipawns {
+    let enemy = 1 - player;
+    let mut score = 0;
+    
+    let enemy_royals = explosive_royals(position, enemy);
+    if !enemy_royals.is_zero() {
+        let enemies = position.pieces[enemy as usize].get_occupied();
+        let mut captures = Vec::new();
+        for piece in position.pieces[player as usize].iter() {
+            // The leaders would explode themselves
+            if piece.explodes_on_capture() && !piece.is_leader() {
+                piece.output_captures(position, enemies, &position.occ_or_out_bounds, &mut captures);
+                for mv in captures.drain(..) {
+                    if !(piece.get_explosion(piece.get_capture_square(mv)) & &enemy_royals).is_zero() {
+                        score += EXPLOSION_THREAT;
+                    }
+                }
+            }
+        }
+    }
+    
+    let own_royals = explosive_royals(position, player);
+    if !own_royals.is_zero() {
+        let exploding_enemies: Vec<&Piece> = position.pieces[enemy as usize].iter()
+            .filter(|piece| piece.explodes_on_capture())
+            .collect();
+        let pieces = position.pieces[player as usize].get_occupied() & !&own_royals;
+        for index in &pieces {
+            // Capturing this piece would also destroy a leader
+            if exploding_enemies.iter().any(|piece| !(piece.get_explosion(index) & &own_royals).is_zero()) {
+                score -= EXPLOSION_CLUSTERING;
+            }
+        }
+    }
+    score
+}
+
+
+// Returns the mobility score of a player
+fn mobility_score(position: &Position, player: Player, weights: &EvalWeights) -> Centipawns {
+    let enemies = position.pieces[1 - player as usize].get_occupied();
+    let mut score = 0;
+    for piece in position.pieces[player as usize].iter() {
+        for index in piece.get_bitboard() {
+            score += piece_mobility_score(position, piece, index, enemies, weights);
+        }
+    }
     score
 }
 
+fn piece_mobility_score(position: &Position, piece: &Piece, index: BIndex, enemies: &Bitboard, weights: &EvalWeights) -> Centipawns {
+    let center_mobility = piece.get_center_mobility();
+    if piece.is_leader() || piece.get_forward_direction().is_some() || center_mobility == 0 {
+        return 0;
+    }
+    let mobility = piece.get_mobility(index, enemies, &position.occ_or_out_bounds);
+    weights.mobility * mobility as Centipawns / center_mobility as Centipawns
+}
+
+
+/// Score of the killer moves in `score_move()`. Captures with a lower score lose material.
+pub const KILLERMOVE_SCORE: Centipawns = 9000;
 
 /// Scores a move on a position
 /// This is used for move ordering in order to search the moves with the most potential first
 pub fn score_move(search: &Searcher, depth: usize, mv: Move) -> Centipawns {
     const CAPTURE_BASE_SCORE: Centipawns = 10000;
-    const KILLERMOVE_SCORE: Centipawns = 9000;
     const PROMOTION_SCORE: Centipawns = 1000;
+    // Captures that lose material (according to SEE) are searched after the killer moves
+    const LOSING_CAPTURE_SCORE: Centipawns = 8000;
     let mut score = 0;
     if mv.is_capture() {
         let current_player = search.pos.whos_turn;
@@ -74,8 +676,27 @@ pub fn score_move(search: &Searcher, depth: usize, mv: Move) -> Centipawns {
         let attacker_score = attacker.get_material_score();
         let victim_score = victim.get_material_score();
 
-        score += CAPTURE_BASE_SCORE;
-        score += 8 * victim_score - attacker_score;
+        if search.pos.global_rules.invert_win_conditions {
+            // When trying to lose (antichess), SEE is meaningless: prefer the captures that take the least valuable
+            // piece with the most valuable one, which is likely to be captured back
+            score += CAPTURE_BASE_SCORE + 8 * attacker_score - victim_score;
+            if mv.is_promotion() {
+                score += PROMOTION_SCORE;
+            }
+            return score;
+        }
+        // Only captures of a less valuable piece can lose material
+        let see = if victim_score < attacker_score && can_use_see(&search.pos) {
+            MoveGen::see(&search.pos, mv)
+        } else {
+            0
+        };
+        if see < 0 {
+            score += LOSING_CAPTURE_SCORE + see / 16;
+        } else {
+            score += CAPTURE_BASE_SCORE;
+            score += 8 * victim_score - attacker_score;
+        }
     } else if mv == search.killer_moves[depth][0] || mv == search.killer_moves[depth][1] {
         score += KILLERMOVE_SCORE;
     } else {
@@ -84,12 +705,19 @@ pub fn score_move(search: &Searcher, depth: usize, mv: Move) -> Centipawns {
     if mv.is_promotion() {
         score += PROMOTION_SCORE;
     }
-    if search.pos.global_rules.invert_win_conditions {
-        score = -score;
-    }
     score
 }
 
+/// Determines whether or not the Static Exchange Evaluation (`MoveGen::see()`) is meaningful for a Position.
+/// SEE assumes that the goal is to win material, and it ignores explosions, forced captures and square effects.
+pub fn can_use_see(position: &Position) -> bool {
+    let rules = &position.global_rules;
+    if rules.invert_win_conditions || rules.capturing_is_forced || !position.get_square_effects().is_empty() {
+        return false;
+    }
+    !has_explosions(position)
+}
+
 /// Determines whether or not null move pruning can be performed for a Position
 pub fn can_do_null_move(position: &Position) -> bool {
     // Material score (without leaders) of current player, below which null move pruning is NOT performed
@@ -0,0 +1,115 @@
+#[cfg(feature = "parallel")]
+use std::sync::Arc;
+
+use crate::MoveGen;
+use crate::position::Position;
+use crate::types::{Move, Depth, Centipawns, BestMoveExplanation, AlternativeMove};
+
+use super::{Searcher, SearchHandle};
+use super::transposition_table::TranspositionTable;
+
+// Depth used to select the most plausible alternatives, before searching them at full depth
+const PRESELECTION_DEPTH: Depth = 2;
+
+impl Searcher {
+    /// Searches the best move, then searches the `num_alternatives` most promising alternatives
+    /// to find out the line that refutes each of them.
+    /// Returns `None` if no best move was found (the position has no legal moves, or the search was stopped
+    /// before completing the first iteration).
+    pub fn explain_best_move(position: &Position, depth: Depth, num_alternatives: usize, num_threads: u32, handle: &SearchHandle) -> Option<BestMoveExplanation> {
+        let (pv, score, _search_depth) = Searcher::get_best_move_with_callback(position, depth, num_threads, &handle.at_full_strength(), &mut |_| {});
+        let best_move = *pv.first()?;
+
+        // The refutations of the alternatives are searched on a single thread, sharing the same transposition table
+        let mut searcher = Searcher::refutation_searcher(position, handle);
+
+        // The reply is searched 1 ply shallower, so that the alternative lines have the same length as the PV
+        let reply_depth = std::cmp::max(depth - 1, 1);
+        let preselection_depth = std::cmp::min(reply_depth, PRESELECTION_DEPTH);
+
+        let mut alternatives: Vec<(Centipawns, Move, Vec<Move>)> = MoveGen::get_legal_moves(&mut searcher.pos)
+            .into_iter()
+            .filter(|&mv| mv != best_move)
+            .map(|mv| searcher.refute_root_move(mv, preselection_depth))
+            .collect();
+        alternatives.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+        alternatives.truncate(num_alternatives);
+
+        if preselection_depth < reply_depth {
+            // Search the selected alternatives again at full depth
+            for alternative in &mut alternatives {
+                *alternative = searcher.refute_root_move(alternative.1, reply_depth);
+            }
+            alternatives.sort_by_key(|(score, _, _)| std::cmp::Reverse(*score));
+        }
+
+        Some(BestMoveExplanation {
+            best_move: best_move.into(),
+            score,
+            pv: pv.into_iter().map(Into::into).collect(),
+            alternatives: alternatives.into_iter().map(|(score, mv, refutation)| {
+                AlternativeMove {
+                    move_info: mv.into(),
+                    score,
+                    refutation: refutation.into_iter().map(Into::into).collect(),
+                }
+            }).collect(),
+        })
+    }
+
+    /// Searches the best move, and the score of `mv` (a legal move of the position). The reply to `mv` is searched
+    /// 1 ply shallower, so that both lines have the same length.
+    /// Returns the best line, its score, the score of `mv` and the best line for the opponent after `mv`
+    /// (empty if `mv` ends the game). The scores are for the player to move.
+    pub fn compare_move(position: &Position, mv: Move, depth: Depth, num_threads: u32, handle: &SearchHandle) -> (Vec<Move>, Centipawns, Centipawns, Vec<Move>) {
+        let (pv, score, _search_depth) = Searcher::get_best_move_with_callback(position, depth, num_threads, &handle.at_full_strength(), &mut |_| {});
+        if pv.first() == Some(&mv) {
+            let refutation = pv[1..].to_vec();
+            return (pv, score, score, refutation);
+        }
+        let mut searcher = Searcher::refutation_searcher(position, handle);
+        let (move_score, _mv, refutation) = searcher.refute_root_move(mv, std::cmp::max(depth - 1, 1));
+        (pv, score, move_score, refutation)
+    }
+
+    // Single-threaded searcher for refute_root_move(), stopped by the handle
+    fn refutation_searcher(position: &Position, handle: &SearchHandle) -> Searcher {
+        #[cfg(not(feature = "parallel"))]
+        let table = TranspositionTable::default().into();
+        #[cfg(feature = "parallel")]
+        let table = Arc::new(TranspositionTable::default()).into();
+        let mut searcher = Searcher::new(position, table);
+        searcher.cancel_flag = handle.stop_flag();
+        searcher.eval_cache = handle.new_eval_cache();
+        handle.apply_material_weights(&mut searcher.pos);
+        searcher.contempt = handle.draw_contempt();
+        searcher
+    }
+
+    // Plays a legal root move and searches the best reply for the opponent.
+    // Returns the score (for the player to move at the root), the move and the refutation line.
+    fn refute_root_move(&mut self, mv: Move, depth: Depth) -> (Centipawns, Move, Vec<Move>) {
+        let mover = self.pos.whos_turn;
+        self.pos.make_move(mv);
+        let (score, refutation) = {
+            if let Some(end_score) = self.is_game_over(mv, 1) {
+                (self.score_for(mover, end_score), Vec::new())
+            } else if MoveGen::in_check(&mut self.pos) && self.pos.increment_num_checks() {
+                // The opponent has been checked too many times
+                (-self.checkmate_score(1), Vec::new())
+            } else {
+                // Reset the state shared with other threads, since the previous search has set the stop flag
+                #[cfg(feature = "parallel")] {
+                    self.stop_flag = Arc::default();
+                    self.current_searched_depth = Arc::default();
+                    self.extra_time_ms = Arc::default();
+                }
+                self.limits.max_depth = depth;
+                let (pv, score, _depth) = self.search(&mut |_| {}).swap_remove(0);
+                (self.score_for(mover, score), pv)
+            }
+        };
+        self.pos.unmake_move();
+        (score, mv, refutation)
+    }
+}
@@ -0,0 +1,278 @@
+// Monte Carlo tree search, an alternative to the alpha-beta search (see `Algorithm::Mcts`).
+// Each simulation descends the tree choosing the moves with the PUCT formula, expands the leaf and evaluates it
+// with the evaluator (instead of playing random moves until the end of the game), and then adds the value of the
+// leaf to the nodes along the path. The values are between -1 (lost) and 1 (won).
+
+use std::sync::atomic::Ordering;
+
+use instant::Instant;
+
+use crate::types::{Centipawns, Depth, MakeMoveResultWinner, Move, Player, SearchResult, SearchStats};
+use crate::{MoveGen, MoveInfo, Position};
+use crate::utils::notation::line_to_san;
+
+use super::alphabeta::GAME_OVER_SCORE;
+use super::eval::EvalCache;
+use super::{SearchHandle, SearchLimits, SearchRes};
+
+// Centipawns that correspond to a value of tanh(1) = 0.76. The values of big advantages must stay clearly
+// below the value of a win, otherwise the search doesn't prefer mating the opponent to keep winning material.
+const VALUE_SCALE: f64 = 1000.0;
+// Values closer to 1 are converted to this value, so that only the proven wins get a mate score
+const MAX_VALUE: f64 = 0.999;
+// Temperature (in centipawns) of the softmax that converts the scores of the moves into their prior probabilities
+const PRIOR_TEMPERATURE: f64 = 100.0;
+// Scores beyond this value (in centipawns) are truncated before computing the priors and estimates
+const MAX_PRIOR_SCORE: Centipawns = 3000;
+// Number of simulations between the checks of the time limit
+const TIME_CHECK_INTERVAL: u64 = 16;
+
+// Node of the search tree, reached by playing `mv` from the parent node
+struct Node {
+    mv: Move,
+    // Player that made the move, who is not always the opponent of the player to move (see `moves_per_turn`)
+    mover: Player,
+    // Indexes of the children in `Tree::nodes`, assigned when the node is expanded
+    children: Vec<usize>,
+    expanded: bool,
+    visits: u32,
+    // Sum of the values of the simulations that have gone through this node, for `mover`
+    value_sum: f64,
+    // Probability of the move being the best one, estimated from the score of the position after it
+    prior: f64,
+    // Value of the evaluation of the position after the move for `mover`, used until the node is visited
+    estimate: f64,
+    // Value of the move for `mover` if it ends the game
+    terminal: Option<f64>,
+}
+
+impl Node {
+    fn new(mv: Move, mover: Player, prior: f64, estimate: f64) -> Node {
+        Node { mv, mover, children: Vec::new(), expanded: false, visits: 0, value_sum: 0.0, prior, estimate, terminal: None }
+    }
+
+    // Average value of the simulations for `mover`
+    fn mean_value(&self) -> f64 {
+        if self.visits == 0 { self.estimate } else { self.value_sum / self.visits as f64 }
+    }
+}
+
+struct Tree {
+    pos: Position,
+    nodes: Vec<Node>,
+    c_puct: f64,
+    eval_cache: EvalCache,
+    // Deepest ply reached by a simulation
+    max_ply: Depth,
+    // Value of a draw for the player to move at the root, from the contempt
+    draw_value: f64,
+    root_player: Player,
+}
+
+/// Runs the simulations (until `simulations`, the time of `limits` or `limits.max_nodes` are exhausted, or
+/// `handle` is stopped) and returns the most visited line. `limits.max_depth` limits the depth of the tree.
+pub(super) fn search(position: &Position, limits: SearchLimits, simulations: u32, c_puct: f64, handle: &SearchHandle,
+    on_result: &mut dyn FnMut(SearchResult)) -> SearchRes
+{
+    let start_time = Instant::now();
+    let end_time = start_time + limits.time.optimum();
+    let cancel_flag = handle.stop_flag();
+    let mut tree = Tree {
+        pos: position.clone(),
+        nodes: vec![Node::new(Move::null(), 1 - position.whos_turn, 1.0, 0.0)],
+        c_puct,
+        eval_cache: handle.new_eval_cache(),
+        max_ply: 0,
+        draw_value: -(handle.draw_contempt() as f64 / VALUE_SCALE).tanh(),
+        root_player: position.whos_turn,
+    };
+    handle.apply_material_weights(&mut tree.pos);
+    let max_simulations = std::cmp::min(simulations as u64, limits.max_nodes);
+    let mut num_simulations = 0;
+    // The first two simulations (which expand the root and visit a move) are always completed,
+    // so that there is a move to return
+    while num_simulations < max_simulations || num_simulations < 2 {
+        if num_simulations >= 2 {
+            if cancel_flag.load(Ordering::Relaxed) {
+                break;
+            }
+            if num_simulations % TIME_CHECK_INTERVAL == 0 && Instant::now() >= end_time {
+                break;
+            }
+        }
+        tree.simulate(limits.max_depth);
+        num_simulations += 1;
+    }
+
+    let pv = tree.principal_variation();
+    let score = tree.root_score();
+    let depth = std::cmp::max(tree.max_ply, 1);
+    let pv_info: Vec<MoveInfo> = pv.iter().map(|&mv| mv.into()).collect();
+    on_result(SearchResult {
+        depth,
+        score,
+        score_type: score.into(),
+        pv_san: line_to_san(position, &pv_info),
+        pv: pv_info,
+        nodes: num_simulations,
+        total_nodes: num_simulations,
+        elapsed_ms: start_time.elapsed().as_millis() as u64,
+        nps: super::nodes_per_second(num_simulations, start_time.elapsed()),
+        branching_factor: 0.0,
+        stats: SearchStats { nodes: num_simulations, ..Default::default() },
+        tree: Vec::new(),
+    });
+    (pv, score, depth)
+}
+
+impl Tree {
+    // Plays one simulation from the root and updates the values of the nodes along the path
+    fn simulate(&mut self, max_depth: Depth) {
+        let mut path = vec![0];
+        let mut node = 0;
+        while self.nodes[node].expanded && self.nodes[node].terminal.is_none() && !self.nodes[node].children.is_empty() {
+            node = self.select_child(node);
+            self.make_move(self.nodes[node].mv);
+            path.push(node);
+        }
+        let ply = (path.len() - 1) as Depth;
+        self.max_ply = std::cmp::max(self.max_ply, ply);
+
+        let value = match self.nodes[node].terminal {
+            Some(value) => value,
+            None => {
+                let mover = self.nodes[node].mover;
+                match self.game_over_value(mover) {
+                    Some(value) if node != 0 => {
+                        self.nodes[node].terminal = Some(value);
+                        value
+                    },
+                    _ => {
+                        if ply < max_depth {
+                            self.expand(node);
+                        }
+                        self.evaluate(mover)
+                    },
+                }
+            },
+        };
+
+        let leaf_mover = self.nodes[node].mover;
+        for &index in path.iter().rev() {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.value_sum += if node.mover == leaf_mover { value } else { -value };
+        }
+        for _ in 1..path.len() {
+            self.pos.unmake_move();
+        }
+    }
+
+    // Returns the child with the highest PUCT score: the mean value, plus an exploration bonus that is higher
+    // for the moves with a high prior and few visits
+    fn select_child(&self, node: usize) -> usize {
+        let sqrt_visits = (self.nodes[node].visits as f64).sqrt();
+        let puct = |child: &Node| child.mean_value() + self.c_puct * child.prior * sqrt_visits / (1.0 + child.visits as f64);
+        let mut best = self.nodes[node].children[0];
+        for &child in &self.nodes[node].children[1..] {
+            if puct(&self.nodes[child]) > puct(&self.nodes[best]) {
+                best = child;
+            }
+        }
+        best
+    }
+
+    // Adds a child for each legal move, with priors and estimates from the scores of the positions after the moves
+    fn expand(&mut self, node: usize) {
+        let mover = self.pos.whos_turn;
+        let moves = MoveGen::get_legal_moves(&mut self.pos);
+        let scores: Vec<f64> = moves.iter().map(|&mv| {
+            self.make_move(mv);
+            let (score, _hit) = self.eval_cache.evaluate(&self.pos);
+            let score = if self.pos.whos_turn == mover { score } else { -score };
+            self.pos.unmake_move();
+            score.clamp(-MAX_PRIOR_SCORE, MAX_PRIOR_SCORE) as f64
+        }).collect();
+        let max_score = scores.iter().copied().fold(f64::MIN, f64::max);
+        let weights: Vec<f64> = scores.iter().map(|score| ((score - max_score) / PRIOR_TEMPERATURE).exp()).collect();
+        let total_weight: f64 = weights.iter().sum();
+        for ((&mv, score), weight) in moves.iter().zip(scores).zip(weights) {
+            let child = self.nodes.len();
+            self.nodes[node].children.push(child);
+            self.nodes.push(Node::new(mv, mover, weight / total_weight, (score / VALUE_SCALE).tanh()));
+        }
+        self.nodes[node].expanded = true;
+    }
+
+    // Value of the current position for `player`, from the evaluator
+    fn evaluate(&mut self, player: Player) -> f64 {
+        let (score, _hit) = self.eval_cache.evaluate(&self.pos);
+        let value = (score as f64 / VALUE_SCALE).tanh();
+        if self.pos.whos_turn == player { value } else { -value }
+    }
+
+    // If the game is over in the current position, returns its value for `player`
+    fn game_over_value(&mut self, player: Player) -> Option<f64> {
+        let result = self.pos.game_result()?;
+        Some(match (result.winner, player) {
+            (MakeMoveResultWinner::None, _) if player == self.root_player => self.draw_value,
+            (MakeMoveResultWinner::None, _) => -self.draw_value,
+            (MakeMoveResultWinner::White, 0) | (MakeMoveResultWinner::Black, 1) => 1.0,
+            _ => -1.0,
+        })
+    }
+
+    // Makes a move of the tree, counting the checks towards `checks_to_lose` like the alpha-beta search
+    fn make_move(&mut self, mv: Move) {
+        self.pos.make_move(mv);
+        if self.pos.global_rules.checks_to_lose != 0 && MoveGen::in_check(&mut self.pos) {
+            let _limit_reached = self.pos.increment_num_checks();
+        }
+    }
+
+    // Most visited child of a node (the one with the highest value in case of a tie), if any has been visited
+    fn most_visited_child(&self, node: usize) -> Option<usize> {
+        let mut best: Option<usize> = None;
+        for &child in &self.nodes[node].children {
+            let candidate = &self.nodes[child];
+            let is_better = match best {
+                None => candidate.visits > 0,
+                Some(best) => {
+                    let best = &self.nodes[best];
+                    candidate.visits > best.visits || (candidate.visits == best.visits && candidate.mean_value() > best.mean_value())
+                },
+            };
+            if is_better {
+                best = Some(child);
+            }
+        }
+        best
+    }
+
+    // Line of the most visited moves, starting from the root
+    fn principal_variation(&self) -> Vec<Move> {
+        let mut pv = Vec::new();
+        let mut node = 0;
+        while let Some(child) = self.most_visited_child(node) {
+            pv.push(self.nodes[child].mv);
+            node = child;
+        }
+        pv
+    }
+
+    // Score of the best move for the player to move at the root, converted back to centipawns
+    fn root_score(&self) -> Centipawns {
+        let best = match self.most_visited_child(0) {
+            Some(best) => &self.nodes[best],
+            None => return 0,
+        };
+        // Winning or losing immediately
+        if let Some(value) = best.terminal.filter(|value| value.abs() == 1.0) {
+            let mate_score = -GAME_OVER_SCORE - 1;
+            return if value > 0.0 { mate_score } else { -mate_score };
+        }
+        let value = best.mean_value().clamp(-MAX_VALUE, MAX_VALUE);
+        let score = (value.atanh() * VALUE_SCALE).round() as Centipawns;
+        if best.mover == self.pos.whos_turn { score } else { -score }
+    }
+}
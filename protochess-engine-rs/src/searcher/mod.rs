@@ -1,20 +1,79 @@
-use std::collections::BTreeSet;
 
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::sync::atomic::{Ordering, AtomicBool};
 #[cfg(feature = "parallel")]
-use std::sync::{Arc, Mutex};
-#[cfg(feature = "parallel")]
-use std::sync::atomic::{Ordering, AtomicBool, AtomicU8};
+use std::sync::atomic::{AtomicU8, AtomicU64};
 
 use instant::{Instant, Duration};
 
-use crate::types::{Move, Depth, Centipawns, SearchTimeout, ZobKey};
-use crate::Position;
+use crate::types::{Algorithm, Move, Depth, Centipawns, Player, ScoreType, SearchTimeout, SearchResult, SearchStats, SearchTreeNode, TimeControl, BOARD_SIZE};
+use crate::{Position, MoveGen, MoveInfo};
+use crate::utils::notation::line_to_san;
 
 mod alphabeta;
+mod mcts;
+mod skill;
+mod variety;
+mod explain;
+mod tactics;
+mod move_picker;
+mod root_moves;
+pub mod time_manager;
+mod search_handle;
 pub mod transposition_table;
-pub mod eval;
+pub(crate) mod eval;
+#[cfg(feature = "parallel")]
+mod pool;
+
+#[cfg(not(feature = "parallel"))]
+use transposition_table::TranspositionTable;
+use transposition_table::TranspositionHandle;
+use time_manager::{TimeManager, BestMoveTracker};
+pub use search_handle::SearchHandle;
+pub use skill::MAX_SKILL_LEVEL;
+#[cfg(feature = "parallel")]
+use pool::{SearcherPool, SharedHeuristics};
 
-use transposition_table::{TranspositionTable, TranspositionHandle};
+// Number of consecutive iterations with the same best move after which a recapture is considered an easy move
+const EASY_MOVE_ITERATIONS: u32 = 4;
+// Number of consecutive iterations with the same best move and mate score after which the search stops,
+// if the mate is shorter than the searched depth
+const MATE_STABLE_ITERATIONS: u32 = 2;
+// Half-width of the aspiration window used by the helper threads, a few centipawns are added for each thread
+#[cfg(feature = "parallel")]
+const ASPIRATION_WINDOW: Centipawns = 40;
+
+// Conditions for stopping the iterative deepening search
+#[derive(Debug, Clone, Copy)]
+struct SearchLimits {
+    max_depth: Depth,
+    time: TimeManager,
+    // Maximum number of nodes searched (by each thread)
+    max_nodes: u64,
+    // Stop as soon as a mate in this number of moves (or less) is found
+    mate_in: Option<Depth>,
+    // Number of lines to search in multi-PV mode (1 = only the best move)
+    multipv: u32,
+    // Number of plies of the search tree that are recorded in each SearchResult (0 = none)
+    tree_plies: u8,
+}
+impl Default for SearchLimits {
+    fn default() -> Self {
+        SearchLimits {
+            max_depth: Depth::MAX,
+            // Cannot use u64::MAX due to overflow, 1_000_000 seconds is 11.5 days
+            time: TimeManager::fixed(1_000_000_000),
+            max_nodes: u64::MAX,
+            mate_in: None,
+            multipv: 1,
+            tree_plies: 0,
+        }
+    }
+}
+
+// Score of each quiet move, indexed by [from][to]. Stored on the heap, since it can be very large with big boards
+type HistoryTable = Box<[[Centipawns; BOARD_SIZE]; BOARD_SIZE]>;
 
 #[derive(Debug, Clone)]
 pub struct Searcher {
@@ -23,15 +82,39 @@ pub struct Searcher {
     //We store two killer moves per ply,
     //indexed by killer_moves[depth][0/1]
     killer_moves: [[Move;2];256],
-    //Indexed by history_moves[side2move][from][to]
-    history_moves: [[Centipawns;256];256],
+    //Indexed by history_moves[from][to]
+    history_moves: HistoryTable,
     transposition_table: TranspositionHandle,
     // Stats
     nodes_searched: u64,
+    // Nodes searched in the previous iterations of iterative deepening
+    total_nodes_searched: u64,
     max_searching_depth: Depth,
     end_time: Instant,
+    // Extra time (in ms) granted after end_time because the best move was unstable
+    #[cfg(not(feature = "parallel"))]
+    extra_time_ms: u64,
     principal_variation: [Move; Depth::MAX as usize + 1],
-    known_checks: BTreeSet<ZobKey>,
+    // Move excluded at each ply during the singular extension verification search (null if none)
+    excluded_moves: [Move; Depth::MAX as usize + 1],
+    limits: SearchLimits,
+    // Moves that are skipped at the root: the avoided moves, and the moves of the previous lines in multi-PV mode
+    excluded_root_moves: Vec<Move>,
+    // Moves that are never searched at the root (see `get_best_move_among()`)
+    avoided_root_moves: Vec<Move>,
+    eval_cache: eval::EvalCache,
+    // Statistics and first plies of the search tree of the current iteration
+    stats: SearchStats,
+    tree: Vec<SearchTreeNode>,
+    // Set from outside the search to stop it early (see `SearchHandle`)
+    cancel_flag: Arc<AtomicBool>,
+    // Root moves of the last searched position, sorted by the previous iteration
+    root_moves: root_moves::RootMoves,
+    // The root move being searched is published here (see `SearchHandle::current_move()`)
+    current_move: search_handle::CurrentMove,
+    // Draws are scored as -contempt for the player to move at the root, and as +contempt for the opponent
+    contempt: Centipawns,
+    root_player: Player,
     
     // Attributes for parallel search
     #[cfg(feature = "parallel")]
@@ -40,6 +123,10 @@ pub struct Searcher {
     stop_flag: Arc<AtomicBool>,
     #[cfg(feature = "parallel")]
     current_searched_depth: Arc<AtomicU8>,
+    #[cfg(feature = "parallel")]
+    extra_time_ms: Arc<AtomicU64>,
+    #[cfg(feature = "parallel")]
+    shared_heuristics: Arc<SharedHeuristics>,
 }
 
 type SearchRes = (Vec<Move>, Centipawns, Depth);
@@ -49,13 +136,27 @@ impl Searcher {
         Searcher{
             pos: position.clone(),
             killer_moves: [[Move::null(); 2];256],
-            history_moves: [[0;256];256],
+            history_moves: vec![[0; BOARD_SIZE]; BOARD_SIZE].into_boxed_slice().try_into().unwrap(),
             transposition_table,
             nodes_searched: 0,
+            total_nodes_searched: 0,
             max_searching_depth: 0,
             end_time: Instant::now(),
+            #[cfg(not(feature = "parallel"))]
+            extra_time_ms: 0,
             principal_variation: [Move::null(); Depth::MAX as usize + 1],
-            known_checks: BTreeSet::new(),
+            excluded_moves: [Move::null(); Depth::MAX as usize + 1],
+            limits: SearchLimits::default(),
+            excluded_root_moves: Vec::new(),
+            avoided_root_moves: Vec::new(),
+            eval_cache: eval::EvalCache::new(),
+            stats: SearchStats::default(),
+            tree: Vec::new(),
+            cancel_flag: Arc::default(),
+            root_moves: root_moves::RootMoves::default(),
+            current_move: Arc::default(),
+            contempt: 0,
+            root_player: position.whos_turn,
             
             #[cfg(feature = "parallel")]
             thread_num: 0,
@@ -63,90 +164,185 @@ impl Searcher {
             stop_flag: Arc::default(),
             #[cfg(feature = "parallel")]
             current_searched_depth: Arc::default(),
+            #[cfg(feature = "parallel")]
+            extra_time_ms: Arc::default(),
+            #[cfg(feature = "parallel")]
+            shared_heuristics: Arc::default(),
         }
     }
     
     pub fn get_best_move(position: &Position, depth: Depth, num_threads: u32) -> SearchRes {
-        // Create a new copy of the heuristics for each search
-        // Cannot use u64::MAX due to overflow, 1_000_000 seconds is 11.5 days
-        Searcher::get_best_move_impl(position, depth, 1_000_000, num_threads)
+        Searcher::get_best_move_with_callback(position, depth, num_threads, &SearchHandle::default(), &mut |_| {})
     }
 
     pub fn get_best_move_timeout(position: &Position, time_sec: u64, num_threads: u32) -> SearchRes {
+        Searcher::get_best_move_timeout_with_callback(position, time_sec, num_threads, &SearchHandle::default(), &mut |_| {})
+    }
+    
+    /// Same as `get_best_move()`, but calls `on_result` each time a new depth has been searched
+    pub fn get_best_move_with_callback(position: &Position, depth: Depth, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
         // Create a new copy of the heuristics for each search
-        Searcher::get_best_move_impl(position, Depth::MAX, time_sec, num_threads)
+        let limits = SearchLimits { max_depth: depth, ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, on_result).swap_remove(0)
+    }
+    
+    /// Same as `get_best_move_with_callback()`, but each `SearchResult` also contains the first `tree_plies` plies
+    /// of the search tree. The tree grows exponentially with `tree_plies`, so it should be small (2 or 3).
+    pub fn get_best_move_with_tree_callback(position: &Position, depth: Depth, tree_plies: u8, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
+        let limits = SearchLimits { max_depth: depth, tree_plies, ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, on_result).swap_remove(0)
+    }
+    
+    /// Same as `get_best_move_with_callback()`, but the moves in `avoided_moves` are not searched at the root
+    /// (like the `searchmoves` option of UCI). They must be legal moves, and at least one legal move must be left.
+    pub fn get_best_move_avoiding(position: &Position, avoided_moves: &[Move], depth: Depth, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
+        let limits = SearchLimits { max_depth: depth, ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, avoided_moves, num_threads, handle, on_result).swap_remove(0)
+    }
+    
+    /// Same as `get_best_move_timeout()`, but calls `on_result` each time a new depth has been searched
+    pub fn get_best_move_timeout_with_callback(position: &Position, time_sec: u64, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
+        Searcher::get_best_move_timeout_ms_with_callback(position, time_sec.saturating_mul(1000), num_threads, handle, on_result)
+    }
+    
+    /// Same as `get_best_move_timeout_with_callback()`, but the time limit is given in milliseconds
+    pub fn get_best_move_timeout_ms_with_callback(position: &Position, time_ms: u64, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
+        // Create a new copy of the heuristics for each search
+        let limits = SearchLimits { time: TimeManager::fixed(time_ms), ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, on_result).swap_remove(0)
+    }
+    
+    /// Searches for the player to move, who has the given time left on the clock. The thinking time depends on
+    /// the clock and on how stable the best move is.
+    pub fn get_best_move_clock_with_callback(position: &Position, clock: &TimeControl, num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> SearchRes {
+        let limits = SearchLimits { time: TimeManager::from_clock(clock), ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, on_result).swap_remove(0)
+    }
+    
+    /// Searches until (approximately) `max_nodes` nodes have been searched, regardless of the time it takes.
+    /// When using multiple threads, the limit applies to each thread.
+    pub fn get_best_move_nodes(position: &Position, max_nodes: u64, num_threads: u32, handle: &SearchHandle) -> SearchRes {
+        let limits = SearchLimits { max_nodes, ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, &mut |_| {}).swap_remove(0)
+    }
+    
+    /// Searches until a mate in `mate_in` moves (or less) is found for the player to move,
+    /// or until all the lines of that length have been searched.
+    pub fn get_best_move_mate(position: &Position, mate_in: Depth, num_threads: u32, handle: &SearchHandle) -> SearchRes {
+        // A mate in N moves is played at ply 2N-1, but it's only detected at the next ply (when there are no legal moves)
+        let max_depth = mate_in.saturating_mul(2);
+        let limits = SearchLimits { max_depth, mate_in: Some(mate_in), ..Default::default() };
+        // The rest of the mating line must be the best moves, regardless of the skill level
+        let handle = &handle.at_full_strength();
+        let (mut pv, score, depth) = Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, &mut |_| {}).swap_remove(0);
+        if Searcher::is_mate_score(score, mate_in) {
+            // The PV can be truncated by transposition table hits, search the rest of the mating line
+            let mate_plies = (-alphabeta::GAME_OVER_SCORE - score) as usize;
+            let mut pos = position.clone();
+            for &mv in &pv {
+                pos.make_move(mv);
+            }
+            while pv.len() < mate_plies {
+                let remaining_plies = (mate_plies - pv.len()) as Depth;
+                let limits = SearchLimits { max_depth: remaining_plies + 1, ..Default::default() };
+                let (line, _, _) = Searcher::get_best_move_impl(&pos, limits, &[], num_threads, handle, &mut |_| {}).swap_remove(0);
+                if line.is_empty() { break; }
+                pos.make_move(line[0]);
+                pv.push(line[0]);
+            }
+        }
+        (pv, score, depth)
+    }
+    
+    /// Multi-PV search: returns the best `multipv` lines, sorted from best to worst.
+    /// Returns fewer lines if there are not enough legal moves.
+    pub fn get_best_moves(position: &Position, depth: Depth, multipv: u32, num_threads: u32, handle: &SearchHandle) -> Vec<SearchRes> {
+        let limits = SearchLimits { max_depth: depth, multipv, ..Default::default() };
+        Searcher::get_best_move_impl(position, limits, &[], num_threads, handle, &mut |_| {})
     }
     
-    // Run for some time, then return the PV, the position score, and the depth
-    fn get_best_move_impl(position: &Position, max_depth: Depth, time_sec: u64, num_threads: u32) -> SearchRes {
+    /// Returns true if `score` means that the player to move can force a mate in `mate_in` moves or less
+    pub fn is_mate_score(score: Centipawns, mate_in: Depth) -> bool {
+        // The score of a checkmate is reduced by 1 for each ply
+        let plies = 2 * mate_in as Centipawns - 1;
+        score >= -alphabeta::GAME_OVER_SCORE - plies
+    }
+    
+    // Run for some time, then return the PVs (best first), the position scores, and the depth
+    // `avoided_moves` are legal moves of the position that are not searched, there must be at least one other legal move
+    fn get_best_move_impl(position: &Position, mut limits: SearchLimits, avoided_moves: &[Move], num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> Vec<SearchRes> {
         // Limit the max depth to 127 to avoid overflow when doubling
-        let max_depth = std::cmp::min(max_depth, 127);
+        limits.max_depth = std::cmp::min(limits.max_depth, 127);
+        // Mate searches, multi-PV, search trees and avoided moves need the alpha-beta search. MCTS always uses a single thread.
+        if let Algorithm::Mcts { simulations, c_puct } = handle.algorithm() {
+            if limits.mate_in.is_none() && limits.multipv == 1 && limits.tree_plies == 0 && avoided_moves.is_empty() {
+                return vec![mcts::search(position, limits, simulations, c_puct, handle, on_result)];
+            }
+        }
+        let single_line = limits.mate_in.is_none() && limits.multipv == 1 && limits.tree_plies == 0;
+        // In the first turns, the opening variety chooses among the lines close to the best one
+        if let Some((turns, margin)) = handle.opening_variety() {
+            if single_line && variety::applies(position, turns) {
+                let mut variety_limits = limits;
+                variety::limit_search(&mut variety_limits);
+                let lines = Searcher::get_best_move_impl(position, variety_limits, avoided_moves, num_threads, handle, on_result);
+                return vec![variety::pick_line(position, lines, margin, handle.random_seed())];
+            }
+        }
+        // A low skill level chooses among the lines of a shallow multi-PV search
+        let skill_level = handle.skill_level();
+        if skill_level < MAX_SKILL_LEVEL && single_line {
+            let mut skill_limits = limits;
+            skill::limit_search(&mut skill_limits, skill_level);
+            let lines = Searcher::get_best_move_impl(position, skill_limits, avoided_moves, num_threads, handle, on_result);
+            return vec![skill::pick_line(position, lines, skill_level, handle.random_seed())];
+        }
         #[cfg(not(feature = "parallel"))] {
             assert!(num_threads == 1);
             let table = TranspositionTable::default();
-            Searcher::new(position, table.into()).search(max_depth, time_sec)
+            let mut searcher = Searcher::new(position, table.into());
+            searcher.limits = limits;
+            searcher.cancel_flag = handle.stop_flag();
+            searcher.current_move = handle.current_move_slot();
+            searcher.eval_cache = handle.new_eval_cache();
+            handle.apply_material_weights(&mut searcher.pos);
+            searcher.contempt = handle.draw_contempt();
+            searcher.avoided_root_moves = avoided_moves.to_vec();
+            searcher.search(on_result)
         }
         #[cfg(feature = "parallel")] {
-            Self::search_multi_thread(position, max_depth, time_sec, num_threads)
+            SearcherPool::search_global(position, limits, avoided_moves, num_threads, handle, on_result)
         }
     }
     
-    #[cfg(feature = "parallel")]
-    fn search_multi_thread(position: &Position, max_depth: Depth, time_sec: u64, num_threads: u32) -> SearchRes {
-        // Arc pointer to a vector of results
-        let res = vec![Default::default(); num_threads as usize];
-        let results_arc = Arc::new(Mutex::new(res));
-        // Arc pointers to global search state
-        let stop_arc = Arc::new(AtomicBool::new(false));
-        let depth_arc = Arc::new(AtomicU8::new(0));
-        // Global transposition table
-        let table = Arc::new(TranspositionTable::default());
-        rayon::scope(|scope| {
-            for thread_num in 0..num_threads {
-                // Clone the pointers on each iteration
-                let results_arc = results_arc.clone();
-                let stop_arc = stop_arc.clone();
-                let depth_arc = depth_arc.clone();
-                let table = table.clone();
-                // Spawn a new task in the thread pool, take ownership of the pointers
-                scope.spawn(move |_scope| {
-                    // Create a new searcher (with cloned position) for each thread
-                    let mut searcher = Searcher::new(position, table.into());
-                    searcher.thread_num = thread_num;
-                    searcher.stop_flag = stop_arc;
-                    searcher.current_searched_depth = depth_arc;
-                    let thread_result = searcher.search(max_depth, time_sec);
-                    // When the thread is done, store the result in the results vector
-                    let mut results_vec = results_arc.lock().unwrap();
-                    results_vec[thread_num as usize] = thread_result;
-                });
-            }
-        });
-        let mut best_pv = Vec::new();
-        let mut best_score = -Centipawns::MAX;
-        let mut best_depth = 0;
-        // Consume the results vector, return the best result (prefer higher depth, then higher score, then longer PV)
-        let results_mutex = Arc::try_unwrap(results_arc).expect("Arc still has owners");
-        let results_vec = results_mutex.into_inner().expect("Mutex is poisoned");
-        for (pv, score, depth) in results_vec {
-            if depth > best_depth ||
-                (depth == best_depth && score > best_score) ||
-                (depth == best_depth && score == best_score && pv.len() > best_pv.len())
-            {
-                best_score = score;
-                best_depth = depth;
-                best_pv = pv;
-            }
-        }
-        (best_pv, best_score, best_depth)
-    }
-    
-    fn search(&mut self, max_depth: Depth, time_sec: u64) -> SearchRes {
+    // Iterative deepening search. Returns the best line, followed by the secondary lines in multi-PV mode.
+    fn search(&mut self, on_result: &mut dyn FnMut(SearchResult)) -> Vec<SearchRes> {
+        let max_depth = self.limits.max_depth;
         let mut pv = Vec::with_capacity(max_depth as usize);
+        // Secondary lines in multi-PV mode, sorted from best to worst
+        let mut other_lines: Vec<SearchRes> = Vec::new();
+        self.excluded_root_moves.clone_from(&self.avoided_root_moves);
+        // There can't be more lines than searched moves
+        let num_moves = MoveGen::get_legal_moves(&mut self.pos).len() - self.avoided_root_moves.len();
+        let num_lines = {
+            if self.limits.multipv > 1 {
+                std::cmp::min(self.limits.multipv as usize, num_moves)
+            } else {
+                1
+            }
+        };
         let mut pv_score: Centipawns = 0;
+        let mut previous_pv_score: Centipawns;
         let mut pv_depth: Depth = 0;
-        self.known_checks.clear();
-        self.end_time = Instant::now() + Duration::from_secs(time_sec);
+        // Nodes of the last completed iteration, used to compute the effective branching factor
+        let mut previous_iteration_nodes: u64 = 0;
+        let mut branching_factor_estimate = 0.0;
+        self.total_nodes_searched = 0;
+        let start_time = Instant::now();
+        self.end_time = start_time + self.limits.time.optimum();
+        let mut best_move_tracker = BestMoveTracker::default();
+        // With a clock, don't waste time when there is only one legal move
+        let single_legal_move = self.limits.time.easy_move_time().is_some() && num_moves == 1;
         
         let mut search_depth;
         #[cfg(not(feature = "parallel"))] {
@@ -163,43 +359,43 @@ impl Searcher {
         
         // Iterative deepening search
         loop {
+            self.total_nodes_searched += self.nodes_searched;
             self.nodes_searched = 0;
+            self.stats = SearchStats::default();
+            self.tree.clear();
             self.max_searching_depth = 2 * search_depth;
-            match self.start_alphabeta(search_depth, &pv) {
+            match self.search_depth(search_depth, &pv, pv_score) {
                 Ok(score) => {
+                    #[cfg(feature = "parallel")]
+                    self.shared_heuristics.merge(&mut self.killer_moves, &mut self.history_moves);
                     // Update the current searched depth
                     #[cfg(feature = "parallel")] {
                         self.current_searched_depth.fetch_max(search_depth, Ordering::Relaxed);
                     }
-                    // If there have been transposition table hits, the new pv won't be complete.
-                    // It might be shorter than the previous pv, in which case we can keep the old pv
-                    // as long as it's consistent with the new pv.
-                    let mut new_pv_len = 0;
-                    let mut consistent = true;
-                    for mv in self.principal_variation {
-                        if mv.is_null() { break; }
-                        new_pv_len += 1;
-                        if pv.len() >= new_pv_len && pv[new_pv_len-1] != mv {
-                            consistent = false;
-                            break;
-                        }
-                    }
-                    if new_pv_len >= pv.len() || !consistent {
-                        // Copy the new pv into a vector
-                        pv.clear();
-                        for mv in self.principal_variation {
-                            if mv.is_null() { break; }
-                            pv.push(mv);
-                        }
-                    }
-                    // Clean up the temporary space for  the new pv
-                    for i in 0..self.max_searching_depth {
-                        self.principal_variation[i as usize] = Move::null();
-                    }
+                    self.update_pv(&mut pv);
+                    branching_factor_estimate = branching_factor(previous_iteration_nodes, self.nodes_searched, search_depth - pv_depth);
+                    previous_iteration_nodes = self.nodes_searched;
                     pv_depth = search_depth;
+                    previous_pv_score = pv_score;
                     pv_score = score;
-                    // Print PV info
-                    println!("{}", self.format_result(score, &pv, search_depth));
+                    // If the best move keeps changing, the search is unstable: give it more time
+                    // so that the timeout doesn't cut the search right after the PV flips
+                    best_move_tracker.update(&pv, score, search_depth);
+                    self.add_extra_time(self.limits.time.extra_time(best_move_tracker.instability()));
+                    self.report_result(&pv, score, search_depth, start_time, branching_factor_estimate, on_result);
+                    // Search the secondary lines at the same depth. Only the main thread searches them, the
+                    // helper threads only search the best line (and fill the transposition table).
+                    if num_lines > 1 && self.is_main_thread() {
+                        match self.search_other_lines(search_depth, &pv, &other_lines, num_lines) {
+                            Ok(lines) => other_lines = lines,
+                            Err(SearchTimeout) => {
+                                // Keep the lines of the previous depth, except the one that is now the best line
+                                other_lines.retain(|(line, _, _)| line.first() != pv.first());
+                                other_lines.truncate(num_lines - 1);
+                                break;
+                            },
+                        }
+                    }
                 },
                 Err(SearchTimeout) => {
                     // Thread timed out, return the best move found so far
@@ -213,9 +409,21 @@ impl Searcher {
                 break;
             }
             
-            if Instant::now() >= self.end_time || search_depth == max_depth {
-                // Set stop flag to stop other threads
-                #[cfg(feature = "parallel")] {
+            let mate_found = self.limits.mate_in.is_some_and(|mate_in| Searcher::is_mate_score(pv_score, mate_in));
+            // With a clock, play obvious moves quickly: the best move is a recapture that hasn't changed for a while
+            let easy_move = self.limits.time.easy_move_time().is_some_and(|easy_time| {
+                single_legal_move || (best_move_tracker.stable_iterations() >= EASY_MOVE_ITERATIONS && start_time.elapsed() >= easy_time &&
+                    pv.first().is_some_and(|&mv| self.is_recapture(mv)))
+            });
+            // Searching deeper doesn't change a forced mate that fits in the searched depth, once the best line is stable
+            let mate_is_stable = num_lines == 1 && best_move_tracker.stable_iterations() >= MATE_STABLE_ITERATIONS && pv_score == previous_pv_score &&
+                matches!(ScoreType::from(pv_score), ScoreType::Mate(plies) if plies.unsigned_abs() < search_depth as u32);
+            let must_stop = self.time_is_up() || self.node_limit_reached() || self.is_cancelled() || mate_found || mate_is_stable || easy_move;
+            if must_stop || search_depth == max_depth {
+                // Set stop flag to stop other threads. In multi-PV mode, a helper thread that reaches the max depth
+                // lets the main thread finish the secondary lines.
+                #[cfg(feature = "parallel")]
+                if must_stop || num_lines == 1 || self.is_main_thread() {
                     self.stop_flag.store(true, Ordering::Relaxed);
                 }
                 // Return the best move found so far
@@ -226,11 +434,178 @@ impl Searcher {
                 search_depth += 1;
             }
             #[cfg(feature = "parallel")] {
-                let next_depth = self.current_searched_depth.load(Ordering::Relaxed) + 1;
+                // Odd helper threads skip one depth, so that not all threads search the same depth
+                let skipped_depths = if self.thread_num % 2 == 1 { 1 } else { 0 };
+                let next_depth = self.current_searched_depth.load(Ordering::Relaxed) + 1 + skipped_depths;
                 search_depth = std::cmp::min(next_depth, max_depth);
             }
         }
-        (pv, pv_score, pv_depth)
+        // The time ran out while the best move was flipping, even with the extra time: play the deepest stable line
+        if num_lines == 1 && self.time_is_up() {
+            if let Some((stable_pv, stable_score, stable_depth)) = best_move_tracker.fallback_line() {
+                pv.clone_from(stable_pv);
+                pv_score = *stable_score;
+                pv_depth = *stable_depth;
+                // The last reported result must be the returned one
+                self.report_result(&pv, pv_score, pv_depth, start_time, branching_factor_estimate, on_result);
+            }
+        }
+        let mut lines = vec![(pv, pv_score, pv_depth)];
+        lines.append(&mut other_lines);
+        lines
+    }
+    
+    // Searches the best line at a given depth. The helper threads of a parallel search use an aspiration window
+    // around the previous score (with a different width for each thread), and re-search with a full window on failure.
+    fn search_depth(&mut self, depth: Depth, hint: &Vec<Move>, previous_score: Centipawns) -> Result<Centipawns, SearchTimeout> {
+        #[cfg(feature = "parallel")]
+        if self.thread_num > 0 && depth > 4 && !hint.is_empty() && previous_score.abs() < -alphabeta::GAME_OVER_SCORE / 2 {
+            let window = ASPIRATION_WINDOW + 5 * self.thread_num as Centipawns;
+            let (alpha, beta) = (previous_score - window, previous_score + window);
+            let score = self.start_alphabeta_window(depth, hint, alpha, beta)?;
+            if score > alpha && score < beta {
+                return Ok(score);
+            }
+        }
+        #[cfg(not(feature = "parallel"))]
+        let _ = previous_score;
+        self.start_alphabeta(depth, hint)
+    }
+    
+    // Searches the secondary lines of a multi-PV search: each line excludes the first move of the previous lines.
+    // The lines of the previous iteration are used as hints. Returns the lines, sorted from best to worst.
+    fn search_other_lines(&mut self, depth: Depth, best_pv: &[Move], previous_lines: &[SearchRes], num_lines: usize) -> Result<Vec<SearchRes>, SearchTimeout> {
+        self.excluded_root_moves.clone_from(&self.avoided_root_moves);
+        self.excluded_root_moves.push(best_pv[0]);
+        let mut lines = Vec::with_capacity(num_lines - 1);
+        let mut result = Ok(());
+        while lines.len() + 1 < num_lines {
+            // Use the line of the previous iteration that has the same rank as a hint, if it's not excluded
+            let mut line = previous_lines.get(lines.len())
+                .map(|(line, _, _)| line.clone())
+                .filter(|line| !line.is_empty() && !self.excluded_root_moves.contains(&line[0]))
+                .unwrap_or_default();
+            match self.start_alphabeta(depth, &line) {
+                Ok(score) => {
+                    self.update_pv(&mut line);
+                    if line.is_empty() {
+                        // The search ended at the root due to a transposition table hit, no move to exclude
+                        break;
+                    }
+                    self.excluded_root_moves.push(line[0]);
+                    lines.push((line, score, depth));
+                },
+                Err(timeout) => {
+                    result = Err(timeout);
+                    break;
+                },
+            }
+        }
+        self.excluded_root_moves.clone_from(&self.avoided_root_moves);
+        result?;
+        // The best line has been found first, but later lines can have a higher score due to search instability
+        lines.sort_by_key(|(_, score, _)| std::cmp::Reverse(*score));
+        Ok(lines)
+    }
+    
+    // Stores the PV found by the last call to start_alphabeta() in `pv`, and cleans up the temporary PV array.
+    // If there have been transposition table hits, the new pv won't be complete.
+    // It might be shorter than the previous pv, in which case we can keep the old pv
+    // as long as it's consistent with the new pv.
+    fn update_pv(&mut self, pv: &mut Vec<Move>) {
+        let mut new_pv_len = 0;
+        let mut consistent = true;
+        for mv in self.principal_variation {
+            if mv.is_null() { break; }
+            new_pv_len += 1;
+            if pv.len() >= new_pv_len && pv[new_pv_len-1] != mv {
+                consistent = false;
+                break;
+            }
+        }
+        if new_pv_len >= pv.len() || !consistent {
+            // Copy the new pv into a vector
+            pv.clear();
+            for mv in self.principal_variation {
+                if mv.is_null() { break; }
+                pv.push(mv);
+            }
+        }
+        // Clean up the temporary space for the new pv
+        for i in 0..self.max_searching_depth {
+            self.principal_variation[i as usize] = Move::null();
+        }
+    }
+    
+    // Returns true if the search has run out of time, including the extra time given for instability
+    #[inline]
+    fn time_is_up(&self) -> bool {
+        #[cfg(not(feature = "parallel"))]
+        let extra_time_ms = self.extra_time_ms;
+        #[cfg(feature = "parallel")]
+        let extra_time_ms = self.extra_time_ms.load(Ordering::Relaxed);
+        Instant::now() >= self.end_time + Duration::from_millis(extra_time_ms)
+    }
+    
+    // Returns true if the move captures the piece that has just captured on the last move
+    fn is_recapture(&self, mv: Move) -> bool {
+        let last_move = self.pos.get_properties().move_played;
+        mv.is_capture() && last_move.is_capture() && mv.get_target() == last_move.get_to()
+    }
+    
+    // The main thread is the only one that searches the secondary lines in multi-PV mode
+    fn is_main_thread(&self) -> bool {
+        #[cfg(feature = "parallel")] {
+            self.thread_num == 0
+        }
+        #[cfg(not(feature = "parallel"))] {
+            true
+        }
+    }
+    
+    // Returns true if the search has been stopped from outside (see `SearchHandle`)
+    #[inline]
+    fn is_cancelled(&self) -> bool {
+        self.cancel_flag.load(Ordering::Relaxed)
+    }
+    
+    // Returns true if the search has reached its maximum number of nodes
+    #[inline]
+    fn node_limit_reached(&self) -> bool {
+        self.total_nodes_searched + self.nodes_searched >= self.limits.max_nodes
+    }
+    
+    // Extend the deadline of the search. The extra time never decreases during a search.
+    fn add_extra_time(&mut self, extra_time: Duration) {
+        let extra_time_ms = extra_time.as_millis() as u64;
+        #[cfg(not(feature = "parallel"))] {
+            self.extra_time_ms = std::cmp::max(self.extra_time_ms, extra_time_ms);
+        }
+        #[cfg(feature = "parallel")] {
+            self.extra_time_ms.fetch_max(extra_time_ms, Ordering::Relaxed);
+        }
+    }
+    
+    // Print the result of an iteration and pass it to the callback
+    fn report_result(&mut self, pv: &Vec<Move>, score: Centipawns, depth: Depth, start_time: Instant, branching_factor: f64, on_result: &mut dyn FnMut(SearchResult)) {
+        println!("{}", self.format_result(score, pv, depth));
+        let pv_info: Vec<MoveInfo> = pv.iter().map(|&mv| mv.into()).collect();
+        let elapsed = start_time.elapsed();
+        let total_nodes = self.total_nodes_searched + self.nodes_searched;
+        on_result(SearchResult {
+            depth,
+            score,
+            score_type: score.into(),
+            pv_san: line_to_san(&self.pos, &pv_info),
+            pv: pv_info,
+            nodes: self.nodes_searched,
+            total_nodes,
+            elapsed_ms: elapsed.as_millis() as u64,
+            nps: nodes_per_second(total_nodes, elapsed),
+            branching_factor,
+            stats: SearchStats { nodes: self.nodes_searched, ..self.stats.clone() },
+            tree: std::mem::take(&mut self.tree),
+        });
     }
     
     // Format the result as a string in order to print it all at once. This prevents 2 threads from printing at the same time.
@@ -240,14 +615,12 @@ impl Searcher {
         #[cfg(not(feature = "parallel"))]
         let thread_str = String::new();
         
-        let diff = -(score.abs() + alphabeta::GAME_OVER_SCORE);
-        let score_str = {
-            if diff < 200 {
-                let sign = if score > 0 { "" } else { "-" };
-                format!("MATE {}{}", sign, (diff+1) / 2)
-            } else {
-                format!("cp {:<4}", score)
-            }
+        let score_str = match ScoreType::from_centipawns(score) {
+            ScoreType::Mate(plies) => {
+                let sign = if plies > 0 { "" } else { "-" };
+                format!("MATE {}{}", sign, (plies.abs() + 1) / 2)
+            },
+            ScoreType::Cp(score) => format!("cp {:<4}", score),
         };
         let mut pv_str = String::new();
         for m in pv {
@@ -258,3 +631,18 @@ impl Searcher {
     }
 }
 
+
+// Average number of nodes searched per second
+fn nodes_per_second(nodes: u64, elapsed: Duration) -> u64 {
+    let micros = std::cmp::max(elapsed.as_micros(), 1);
+    // Use u128 to avoid overflowing in very long searches
+    (nodes as u128 * 1_000_000 / micros).try_into().unwrap_or(u64::MAX)
+}
+
+// Effective branching factor between two completed iterations, `extra_depth` plies apart (0 if unknown)
+fn branching_factor(previous_nodes: u64, nodes: u64, extra_depth: Depth) -> f64 {
+    if previous_nodes == 0 || extra_depth == 0 {
+        return 0.0;
+    }
+    (nodes as f64 / previous_nodes as f64).powf(1.0 / extra_depth as f64)
+}
@@ -0,0 +1,165 @@
+// Staged move generation: the moves that are most likely to cause a cutoff are generated first,
+// so that the quiet moves are not generated at all when there is an early cutoff
+
+use crate::MoveGen;
+use crate::types::{Move, Depth, Centipawns};
+
+use super::Searcher;
+use super::eval;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Start,
+    TableMove,
+    GoodCaptures,
+    Killers,
+    BadCaptures,
+    Quiets,
+    // All the moves at once, sorted by score
+    AllMoves,
+    Done,
+}
+
+/// Yields the pseudo-legal moves of the current position of a `Searcher`, in this order: the move from the
+/// transposition table, the captures that don't lose material, the killer moves, the rest of the captures
+/// and the quiet moves (sorted by the history heuristic). Each move is only yielded once.
+/// The caller must check that the moves are legal.
+pub struct MovePicker {
+    // Stage of the moves in `moves`
+    stage: Stage,
+    all_at_once: bool,
+    depth: Depth,
+    table_move: Move,
+    // Killer moves that have been yielded
+    killers: [Move; 2],
+    // Moves of the current stage and index of the next move to yield
+    moves: Vec<(Centipawns, Move)>,
+    next_move: usize,
+    bad_captures: Vec<(Centipawns, Move)>,
+    captures_exist: bool,
+}
+
+impl MovePicker {
+    pub fn new(searcher: &Searcher, depth: Depth) -> MovePicker {
+        MovePicker {
+            stage: Stage::Start,
+            // With inverted win conditions the usual order doesn't apply, sort all the moves together
+            all_at_once: searcher.pos.global_rules.invert_win_conditions,
+            depth,
+            table_move: searcher.transposition_table.retrieve(searcher.zobrist()).map_or(Move::null(), |entry| entry.mv),
+            killers: [Move::null(); 2],
+            moves: Vec::new(),
+            next_move: 0,
+            bad_captures: Vec::new(),
+            captures_exist: false,
+        }
+    }
+
+    /// Yields the given moves, in the same order
+    pub fn with_moves(moves: Vec<Move>) -> MovePicker {
+        MovePicker {
+            stage: Stage::Done,
+            all_at_once: true,
+            depth: 0,
+            table_move: Move::null(),
+            killers: [Move::null(); 2],
+            moves: moves.into_iter().map(|mv| (0, mv)).collect(),
+            next_move: 0,
+            bad_captures: Vec::new(),
+            captures_exist: false,
+        }
+    }
+
+    /// Returns the next pseudo-legal move, or `None` if all the moves have been yielded
+    pub fn next(&mut self, searcher: &mut Searcher) -> Option<Move> {
+        loop {
+            while let Some(&(_, mv)) = self.moves.get(self.next_move) {
+                self.next_move += 1;
+                if !self.already_yielded(mv) {
+                    return Some(mv);
+                }
+            }
+            if self.stage == Stage::Done {
+                return None;
+            }
+            self.next_stage(searcher);
+        }
+    }
+
+    // Advances to the next stage and generates its moves
+    fn next_stage(&mut self, searcher: &mut Searcher) {
+        self.moves.clear();
+        self.next_move = 0;
+        self.stage = match self.stage {
+            Stage::Start if self.all_at_once => Stage::AllMoves,
+            Stage::Start => Stage::TableMove,
+            Stage::TableMove => Stage::GoodCaptures,
+            Stage::GoodCaptures => Stage::Killers,
+            Stage::Killers => Stage::BadCaptures,
+            Stage::BadCaptures => Stage::Quiets,
+            Stage::Quiets | Stage::AllMoves | Stage::Done => Stage::Done,
+        };
+        let killers_index = std::cmp::min(searcher.killer_moves.len() - 1, self.depth as usize);
+        match self.stage {
+            Stage::TableMove => {
+                if MoveGen::is_pseudo_legal(self.table_move, &mut searcher.pos) {
+                    self.moves.push((Centipawns::MAX, self.table_move));
+                } else {
+                    self.table_move = Move::null();
+                }
+            },
+            Stage::GoodCaptures => {
+                let captures = MoveGen::get_pseudo_moves(&mut searcher.pos, false);
+                self.captures_exist = !captures.is_empty();
+                for mv in captures {
+                    let score = eval::score_move(searcher, killers_index, mv);
+                    if score > eval::KILLERMOVE_SCORE {
+                        self.moves.push((score, mv));
+                    } else {
+                        self.bad_captures.push((score, mv));
+                    }
+                }
+                sort_by_score(&mut self.moves);
+            },
+            Stage::Killers => {
+                for (i, killer) in searcher.killer_moves[killers_index].iter().copied().enumerate() {
+                    if killer.is_capture() || killer == self.table_move || !MoveGen::is_pseudo_legal(killer, &mut searcher.pos) {
+                        continue;
+                    }
+                    self.moves.push((eval::KILLERMOVE_SCORE, killer));
+                    self.killers[i] = killer;
+                }
+            },
+            Stage::BadCaptures => {
+                self.moves = std::mem::take(&mut self.bad_captures);
+                sort_by_score(&mut self.moves);
+            },
+            Stage::Quiets => {
+                for mv in MoveGen::get_pseudo_quiets(&mut searcher.pos, self.captures_exist) {
+                    self.moves.push((eval::score_move(searcher, killers_index, mv), mv));
+                }
+                sort_by_score(&mut self.moves);
+            },
+            Stage::AllMoves => {
+                let moves = MoveGen::get_pseudo_moves(&mut searcher.pos, true);
+                self.moves = searcher.sort_moves_by_score(moves, self.depth);
+            },
+            Stage::Start | Stage::Done => {},
+        }
+    }
+
+    // Skip the moves that have been yielded in the previous stages
+    fn already_yielded(&self, mv: Move) -> bool {
+        match self.stage {
+            Stage::GoodCaptures | Stage::BadCaptures => mv == self.table_move,
+            Stage::Quiets => mv == self.table_move || self.killers.contains(&mv),
+            _ => false,
+        }
+    }
+}
+
+// Sort moves by decreasing score. Use a stable sort, so that ties are always broken in the same way
+// (by the order of move generation) and the search is reproducible.
+fn sort_by_score(moves: &mut [(Centipawns, Move)]) {
+    moves.sort_by_key(|a| std::cmp::Reverse(a.0));
+}
@@ -0,0 +1,267 @@
+// Persistent worker threads for the parallel (Lazy SMP) search
+
+use std::any::Any;
+use std::panic::{AssertUnwindSafe, catch_unwind, resume_unwind};
+use std::sync::{Arc, Mutex, PoisonError, mpsc};
+use std::sync::atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering};
+
+use crate::Position;
+use crate::types::{Move, Centipawns, SearchResult, BOARD_SIZE};
+
+use super::{Searcher, SearchLimits, SearchRes, SearchHandle, HistoryTable};
+use super::transposition_table::TranspositionTable;
+use super::search_handle::CurrentMove;
+
+lazy_static! {
+    // Shared by all the searches, so that the worker threads are only spawned once
+    static ref SEARCHER_POOL: SearcherPool = SearcherPool::default();
+}
+
+/// Killer moves and history heuristic, periodically merged between the threads of a search
+#[derive(Debug, Default)]
+pub struct SharedHeuristics {
+    killer_moves: Mutex<Vec<[Move; 2]>>,
+    history_moves: Mutex<Vec<[Centipawns; BOARD_SIZE]>>,
+}
+
+impl SharedHeuristics {
+    /// Merges the heuristics of a thread with the shared ones, and updates both with the result.
+    /// The history keeps the highest score of each move, and empty killer slots are filled with the shared moves.
+    pub fn merge(&self, killer_moves: &mut [[Move; 2]; 256], history_moves: &mut HistoryTable) {
+        {
+            let mut shared = self.killer_moves.lock().unwrap_or_else(PoisonError::into_inner);
+            shared.resize(killer_moves.len(), [Move::null(); 2]);
+            for (shared_killers, killers) in shared.iter_mut().zip(killer_moves.iter_mut()) {
+                for i in 0..2 {
+                    if killers[i].is_null() {
+                        killers[i] = shared_killers[i];
+                    }
+                }
+                *shared_killers = *killers;
+            }
+        }
+        let mut shared = self.history_moves.lock().unwrap_or_else(PoisonError::into_inner);
+        shared.resize(history_moves.len(), [0; BOARD_SIZE]);
+        for (shared_row, row) in shared.iter_mut().zip(history_moves.iter_mut()) {
+            for (shared_score, score) in shared_row.iter_mut().zip(row.iter_mut()) {
+                *shared_score = std::cmp::max(*shared_score, *score);
+                *score = *shared_score;
+            }
+        }
+    }
+}
+
+// State shared by all the threads of a search
+#[derive(Clone)]
+struct SharedState {
+    stop_flag: Arc<AtomicBool>,
+    current_searched_depth: Arc<AtomicU8>,
+    extra_time_ms: Arc<AtomicU64>,
+    table: Arc<TranspositionTable>,
+    heuristics: Arc<SharedHeuristics>,
+    // Set by the `SearchHandle` of the caller
+    cancel_flag: Arc<AtomicBool>,
+    current_move: CurrentMove,
+    // Evaluator and weights of the evaluation
+    handle: SearchHandle,
+    contempt: Centipawns,
+}
+
+struct Job {
+    thread_num: u32,
+    position: Position,
+    limits: SearchLimits,
+    avoided_moves: Vec<Move>,
+    shared: SharedState,
+    result_sender: mpsc::Sender<WorkerMessage>,
+}
+
+enum WorkerMessage {
+    // Result of a completed depth
+    Intermediate(Box<SearchResult>),
+    // Final result of the thread
+    Done(u32, Vec<SearchRes>),
+    // The thread panicked, the panic is propagated to the calling thread
+    Panicked(Box<dyn Any + Send>),
+}
+
+/// Set of worker threads that are kept alive between searches, instead of spawning new tasks for each search.
+/// The calling thread runs the main thread of the search, and each helper thread takes an idle worker (or spawns
+/// a new one). The workers are returned to the pool when the search ends, so that concurrent searches (of different
+/// engines, or started from a callback) don't wait for each other.
+#[derive(Default)]
+pub struct SearcherPool {
+    // Job channels of the workers that are not running a search. A worker exits when its channel is closed.
+    idle_workers: Mutex<Vec<mpsc::Sender<Job>>>,
+}
+
+impl SearcherPool {
+    /// Runs a search on the shared `SEARCHER_POOL` with `num_threads` threads (including the calling thread)
+    pub(super) fn search_global(position: &Position, limits: SearchLimits, avoided_moves: &[Move], num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> Vec<SearchRes> {
+        SEARCHER_POOL.search(position, limits, avoided_moves, num_threads, handle, on_result)
+    }
+
+    // Run a search on all the threads, return the best lines of the best thread (of the main thread in multi-PV mode)
+    fn search(&self, position: &Position, limits: SearchLimits, avoided_moves: &[Move], num_threads: u32, handle: &SearchHandle, on_result: &mut dyn FnMut(SearchResult)) -> Vec<SearchRes> {
+        let shared = SharedState {
+            stop_flag: Arc::new(AtomicBool::new(false)),
+            current_searched_depth: Arc::new(AtomicU8::new(0)),
+            extra_time_ms: Arc::new(AtomicU64::new(0)),
+            table: Arc::new(TranspositionTable::default()),
+            heuristics: Arc::default(),
+            cancel_flag: handle.stop_flag(),
+            current_move: handle.current_move_slot(),
+            handle: handle.clone(),
+            contempt: handle.draw_contempt(),
+        };
+        // Intermediate results are sent to the calling thread, so that the callback doesn't need to be Send
+        let (result_sender, result_receiver) = mpsc::channel::<WorkerMessage>();
+        let workers = self.take_workers(num_threads.saturating_sub(1) as usize);
+        for (i, worker) in workers.iter().enumerate() {
+            let job = Job {
+                thread_num: i as u32 + 1,
+                position: position.clone(),
+                limits,
+                avoided_moves: avoided_moves.to_vec(),
+                shared: shared.clone(),
+                result_sender: result_sender.clone(),
+            };
+            match worker {
+                // The workers only exit when their channel is closed, this cannot fail
+                Some(worker) => worker.send(job).unwrap(),
+                // The threads can't be created (in WASM, only `initThreadPool()` can create threads).
+                // Run the job as a task of the global pool, which ends with the search.
+                None => rayon::spawn(move || Self::run_job_or_report_panic(job)),
+            }
+        }
+        // Drop the original sender, so that the loop ends when all threads are done
+        drop(result_sender);
+
+        let mut results_vec: Vec<Vec<SearchRes>> = vec![Default::default(); workers.len() + 1];
+        // Only report each depth once (the first thread to finish it)
+        let mut reported_depth = 0;
+        let mut panic_payload = None;
+        let mut handle_message = |message, on_result: &mut dyn FnMut(SearchResult)| {
+            match message {
+                WorkerMessage::Intermediate(result) => {
+                    if result.depth > reported_depth {
+                        reported_depth = result.depth;
+                        on_result(*result);
+                    }
+                },
+                WorkerMessage::Done(thread_num, lines) => results_vec[thread_num as usize] = lines,
+                WorkerMessage::Panicked(payload) => panic_payload = Some(payload),
+            }
+        };
+        
+        // The main thread runs on the calling thread, the results of the helpers are reported between its depths
+        let mut searcher = Self::new_searcher(0, position, limits, avoided_moves.to_vec(), shared.clone());
+        let main_result = catch_unwind(AssertUnwindSafe(|| {
+            searcher.search(&mut |result| {
+                for message in result_receiver.try_iter() {
+                    handle_message(message, on_result);
+                }
+                handle_message(WorkerMessage::Intermediate(Box::new(result)), on_result);
+            })
+        }));
+        match main_result {
+            Ok(lines) => handle_message(WorkerMessage::Done(0, lines), on_result),
+            Err(payload) => {
+                // Stop the helpers before propagating the panic
+                shared.stop_flag.store(true, Ordering::Relaxed);
+                handle_message(WorkerMessage::Panicked(payload), on_result);
+            },
+        }
+        for message in result_receiver {
+            handle_message(message, on_result);
+        }
+        self.return_workers(workers);
+        if let Some(payload) = panic_payload {
+            resume_unwind(payload);
+        }
+
+        // Only the main thread has searched the secondary lines
+        if limits.multipv > 1 {
+            return results_vec.swap_remove(0);
+        }
+        let mut best_lines = vec![(Vec::new(), -Centipawns::MAX, 0)];
+        // Return the best result (prefer higher depth, then higher score, then longer PV)
+        for lines in results_vec {
+            let (pv, score, depth) = &lines[0];
+            let (best_pv, best_score, best_depth) = &best_lines[0];
+            if depth > best_depth ||
+                (depth == best_depth && score > best_score) ||
+                (depth == best_depth && score == best_score && pv.len() > best_pv.len())
+            {
+                best_lines = lines;
+            }
+        }
+        best_lines
+    }
+    
+    // Takes `count` idle workers, spawning new ones if needed. None if a worker thread can't be created.
+    fn take_workers(&self, count: usize) -> Vec<Option<mpsc::Sender<Job>>> {
+        let mut workers: Vec<_> = {
+            let mut idle_workers = self.idle_workers.lock().unwrap_or_else(PoisonError::into_inner);
+            let first_taken = idle_workers.len().saturating_sub(count);
+            idle_workers.drain(first_taken..).map(Some).collect()
+        };
+        workers.resize_with(count, Self::spawn_worker);
+        workers
+    }
+    
+    // Makes the workers available for the next searches, keeping at most one idle worker per core
+    fn return_workers(&self, workers: Vec<Option<mpsc::Sender<Job>>>) {
+        let mut idle_workers = self.idle_workers.lock().unwrap_or_else(PoisonError::into_inner);
+        idle_workers.extend(workers.into_iter().flatten());
+        idle_workers.truncate(rayon::current_num_threads());
+    }
+    
+    fn spawn_worker() -> Option<mpsc::Sender<Job>> {
+        let (job_sender, job_receiver) = mpsc::channel::<Job>();
+        let worker = std::thread::Builder::new().name("protochess-searcher".to_string()).spawn(move || {
+            // Wait for jobs until the channel is closed
+            for job in job_receiver {
+                Self::run_job_or_report_panic(job);
+            }
+        });
+        worker.ok().map(|_| job_sender)
+    }
+
+    // Runs a job, and sends the panic to the calling thread if the search panics (the worker is kept alive)
+    fn run_job_or_report_panic(job: Job) {
+        let result_sender = job.result_sender.clone();
+        if let Err(payload) = catch_unwind(AssertUnwindSafe(|| Self::run_job(job))) {
+            result_sender.send(WorkerMessage::Panicked(payload)).ok();
+        }
+    }
+
+    fn run_job(job: Job) {
+        let mut searcher = Self::new_searcher(job.thread_num, &job.position, job.limits, job.avoided_moves, job.shared);
+        let result_sender = job.result_sender;
+        let mut send_result = |result| {
+            // The receiver is alive until all threads finish, this cannot fail
+            result_sender.send(WorkerMessage::Intermediate(Box::new(result))).unwrap();
+        };
+        let thread_result = searcher.search(&mut send_result);
+        result_sender.send(WorkerMessage::Done(job.thread_num, thread_result)).unwrap();
+    }
+    
+    fn new_searcher(thread_num: u32, position: &Position, limits: SearchLimits, avoided_moves: Vec<Move>, shared: SharedState) -> Searcher {
+        // Create a new searcher (with cloned position) for each search
+        let mut searcher = Searcher::new(position, shared.table.into());
+        searcher.thread_num = thread_num;
+        searcher.stop_flag = shared.stop_flag;
+        searcher.current_searched_depth = shared.current_searched_depth;
+        searcher.extra_time_ms = shared.extra_time_ms;
+        searcher.shared_heuristics = shared.heuristics;
+        searcher.cancel_flag = shared.cancel_flag;
+        searcher.current_move = shared.current_move;
+        searcher.eval_cache = shared.handle.new_eval_cache();
+        shared.handle.apply_material_weights(&mut searcher.pos);
+        searcher.contempt = shared.contempt;
+        searcher.limits = limits;
+        searcher.avoided_root_moves = avoided_moves;
+        searcher
+    }
+}
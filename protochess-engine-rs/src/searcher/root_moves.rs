@@ -0,0 +1,62 @@
+// Statistics of the root moves, kept between the iterations of iterative deepening. Each iteration searches
+// the root moves in the order of the previous one: the moves that raised alpha first (best score first),
+// then the rest by decreasing size of their subtree.
+
+use crate::{MoveGen, MoveInfo};
+use crate::types::{Move, Depth, Centipawns, ZobKey};
+
+use super::Searcher;
+
+#[derive(Debug, Clone, Copy)]
+struct RootMove {
+    mv: Move,
+    // Score of the move, or -MAX if it didn't raise alpha (then the score is only an upper bound)
+    score: Centipawns,
+    // Number of nodes of the subtree of the move in the last search
+    nodes: u64,
+}
+
+/// Root moves of the last searched position, with their statistics
+#[derive(Debug, Clone, Default)]
+pub struct RootMoves {
+    key: ZobKey,
+    moves: Vec<RootMove>,
+}
+
+impl Searcher {
+    // Returns the legal moves of the current position, in the order in which they must be searched at the root.
+    // The first time that a position is searched, the moves are sorted by the move ordering heuristics.
+    pub(super) fn sorted_root_moves(&mut self, depth: Depth) -> Vec<Move> {
+        let key = self.zobrist();
+        if self.root_moves.moves.is_empty() || self.root_moves.key != key {
+            let moves = MoveGen::get_legal_moves(&mut self.pos);
+            self.root_moves.moves = self.sort_moves_by_score(moves, depth).into_iter()
+                .map(|(_, mv)| RootMove { mv, score: -Centipawns::MAX, nodes: 0 })
+                .collect();
+            self.root_moves.key = key;
+        }
+        // Stable sort: the moves with the same statistics keep the order of the previous iteration
+        self.root_moves.moves.sort_by_key(|root_move| std::cmp::Reverse((root_move.score, root_move.nodes)));
+        self.root_moves.moves.iter().map(|root_move| root_move.mv).collect()
+    }
+
+    // Stores the result of searching a root move: its score (if it raised alpha) and the size of its subtree
+    pub(super) fn update_root_move(&mut self, mv: Move, score: Option<Centipawns>, nodes: u64) {
+        if let Some(root_move) = self.root_moves.moves.iter_mut().find(|root_move| root_move.mv == mv) {
+            root_move.score = score.unwrap_or(-Centipawns::MAX);
+            root_move.nodes = nodes;
+        }
+    }
+
+    // Publishes the root move that is about to be searched, and its number in the search order (starting at 1)
+    pub(super) fn report_root_move(&self, mv: Move, number: u32) {
+        #[cfg(feature = "parallel")]
+        if self.thread_num != 0 {
+            // Only the main thread reports its progress
+            return;
+        }
+        if let Ok(mut current_move) = self.current_move.lock() {
+            *current_move = Some((MoveInfo::from(mv), number));
+        }
+    }
+}
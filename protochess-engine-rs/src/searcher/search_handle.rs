@@ -0,0 +1,212 @@
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::MoveInfo;
+use crate::types::{Algorithm, Centipawns};
+use crate::position::Position;
+use crate::utils::tuning::TuningParams;
+use super::eval::{EvalCache, Evaluator};
+use super::skill::MAX_SKILL_LEVEL;
+
+/// Allows stopping the searches of an `Engine` from another thread (e.g. when the user cancels the analysis
+/// in a GUI). Returned by `Engine::search_handle()`, all the clones of a handle control the same engine.
+/// The handle also holds the settings of the searches (evaluator and its weights, algorithm, contempt, skill level and randomization),
+/// see `with_evaluator()`.
+#[derive(Debug, Clone, Default)]
+pub struct SearchHandle {
+    stop: Arc<AtomicBool>,
+    running: Arc<AtomicBool>,
+    // Root move being searched and its number in the search order, see `current_move()`
+    current_move: CurrentMove,
+    // Replaces the built-in evaluation in the searches started with this handle
+    evaluator: Option<Arc<dyn Evaluator + Send + Sync>>,
+    // Weights of the built-in evaluation (see `Engine::set_eval_weights()`)
+    eval_weights: TuningParams,
+    algorithm: Algorithm,
+    // Score of a draw for the opponent of the player to move at the root (see `Engine::set_contempt()`)
+    contempt: Centipawns,
+    play_for_win: bool,
+    // Skill level below `MAX_SKILL_LEVEL`, or None for full strength (see `Engine::set_skill_level()`)
+    skill_level: Option<u8>,
+    // Seed of the random choices of the searches (see `Engine::set_random_seed()`)
+    random_seed: u64,
+    // Number of turns and margin (in centipawns) of `Engine::set_opening_variety()`, None if disabled
+    opening_variety: Option<(u32, Centipawns)>,
+}
+
+/// Root move that a search is currently searching, and its number in the search order (starting at 1)
+pub(crate) type CurrentMove = Arc<Mutex<Option<(MoveInfo, u32)>>>;
+
+/// Contempt used in the "play for a win" mode: draws are scored like losing about a queen
+pub(crate) const PLAY_FOR_WIN_CONTEMPT: Centipawns = 1000;
+
+impl SearchHandle {
+    /// Creates a handle whose searches evaluate the positions with `evaluator`, instead of the built-in
+    /// evaluation (`eval::HandCraftedEvaluator`)
+    pub fn with_evaluator(evaluator: Box<dyn Evaluator + Send + Sync>) -> SearchHandle {
+        SearchHandle { evaluator: Some(evaluator.into()), ..Default::default() }
+    }
+    
+    /// Stops the current search as soon as possible. The search returns the best move found so far
+    /// (the first iteration is always completed). Does nothing if the engine is not searching.
+    pub fn stop(&self) {
+        if self.is_running() {
+            self.stop.store(true, Ordering::Relaxed);
+        }
+    }
+    
+    /// Returns true if the engine is currently searching
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::Relaxed)
+    }
+    
+    /// Returns the root move that the current search is searching, and its number in the search order (starting
+    /// at 1), like the `currmove` and `currmovenumber` of the UCI protocol. Useful for displaying the progress of
+    /// long searches. After the search, returns the last root move that was searched.
+    /// Returns `None` if no root move has been searched yet.
+    pub fn current_move(&self) -> Option<(MoveInfo, u32)> {
+        self.current_move.lock().ok().and_then(|current_move| *current_move)
+    }
+    
+    /// Marks the engine as searching until the returned guard is dropped
+    pub(crate) fn start(&self) -> RunningSearch {
+        if let Ok(mut current_move) = self.current_move.lock() {
+            *current_move = None;
+        }
+        self.stop.store(false, Ordering::Relaxed);
+        self.running.store(true, Ordering::Relaxed);
+        RunningSearch(self.running.clone())
+    }
+    
+    /// Returns true if `stop()` has been called during the current (or last) search
+    pub(crate) fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    
+    /// Flag that is set when the search must stop
+    pub(crate) fn stop_flag(&self) -> Arc<AtomicBool> {
+        self.stop.clone()
+    }
+    
+    /// Slot where the search publishes the root move that it is searching
+    pub(crate) fn current_move_slot(&self) -> CurrentMove {
+        self.current_move.clone()
+    }
+    
+    /// Evaluator of the searches, `None` for the built-in evaluation
+    pub(crate) fn evaluator(&self) -> Option<Arc<dyn Evaluator + Send + Sync>> {
+        self.evaluator.clone()
+    }
+    
+    /// Sets the evaluator of the next searches, `None` for the built-in evaluation
+    pub(crate) fn set_evaluator(&mut self, evaluator: Option<Arc<dyn Evaluator + Send + Sync>>) {
+        self.evaluator = evaluator;
+    }
+    
+    /// Weights of the built-in evaluation of the searches
+    pub(crate) fn eval_weights(&self) -> TuningParams {
+        self.eval_weights
+    }
+    
+    /// Sets the weights of the built-in evaluation of the next searches
+    pub(crate) fn set_eval_weights(&mut self, weights: TuningParams) {
+        self.eval_weights = weights;
+    }
+    
+    /// Creates the evaluation cache of a searcher, which evaluates with the evaluator or the weights of the handle
+    pub(crate) fn new_eval_cache(&self) -> EvalCache {
+        EvalCache::with_evaluator(self.evaluator(), self.eval_weights.eval)
+    }
+    
+    /// Applies the material weights of the handle to the position of a searcher (a copy of the root position)
+    pub(crate) fn apply_material_weights(&self, position: &mut Position) {
+        if self.eval_weights.material != TuningParams::default().material {
+            position.set_material_weights(&self.eval_weights.material);
+        }
+    }
+    
+    /// Algorithm of the searches
+    pub(crate) fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+    
+    /// Sets the algorithm of the next searches
+    pub(crate) fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.algorithm = algorithm;
+    }
+    
+    /// Contempt set with `set_contempt()`, ignoring the "play for a win" mode
+    pub(crate) fn contempt(&self) -> Centipawns {
+        self.contempt
+    }
+    
+    /// Sets the contempt of the next searches
+    pub(crate) fn set_contempt(&mut self, contempt: Centipawns) {
+        self.contempt = contempt;
+    }
+    
+    /// Returns true if the searches avoid the draws, see `set_play_for_win()`
+    pub(crate) fn plays_for_win(&self) -> bool {
+        self.play_for_win
+    }
+    
+    /// Enables or disables the "play for a win" mode, which raises the contempt to at least `PLAY_FOR_WIN_CONTEMPT`
+    pub(crate) fn set_play_for_win(&mut self, play_for_win: bool) {
+        self.play_for_win = play_for_win;
+    }
+    
+    /// Contempt used by the searches: draws are scored as `-draw_contempt()` for the player to move at the root
+    pub(crate) fn draw_contempt(&self) -> Centipawns {
+        if self.play_for_win { std::cmp::max(self.contempt, PLAY_FOR_WIN_CONTEMPT) } else { self.contempt }
+    }
+    
+    /// Skill level of the searches, `MAX_SKILL_LEVEL` for full strength
+    pub(crate) fn skill_level(&self) -> u8 {
+        self.skill_level.unwrap_or(MAX_SKILL_LEVEL)
+    }
+    
+    /// Sets the skill level of the next searches
+    pub(crate) fn set_skill_level(&mut self, level: u8) {
+        self.skill_level = if level < MAX_SKILL_LEVEL { Some(level) } else { None };
+    }
+    
+    /// Seed of the random choices of the searches
+    pub(crate) fn random_seed(&self) -> u64 {
+        self.random_seed
+    }
+    
+    /// Sets the seed of the random choices of the next searches
+    pub(crate) fn set_random_seed(&mut self, seed: u64) {
+        self.random_seed = seed;
+    }
+    
+    /// Number of turns and margin of the opening variety, if enabled
+    pub(crate) fn opening_variety(&self) -> Option<(u32, Centipawns)> {
+        self.opening_variety
+    }
+    
+    /// Sets the opening variety of the next searches, None to disable it
+    pub(crate) fn set_opening_variety(&mut self, opening_variety: Option<(u32, Centipawns)>) {
+        self.opening_variety = opening_variety;
+    }
+    
+    /// Same handle (it's stopped with this one), but the searches play at full strength and always choose the best
+    /// move. Used by the analysis, which must find the best moves regardless of the skill level and the variety.
+    pub(crate) fn at_full_strength(&self) -> SearchHandle {
+        SearchHandle { skill_level: None, opening_variety: None, ..self.clone() }
+    }
+    
+    /// Creates a handle with the same settings, which controls the searches independently of this one
+    pub(crate) fn detached(&self) -> SearchHandle {
+        SearchHandle { stop: Arc::default(), running: Arc::default(), current_move: Arc::default(), ..self.clone() }
+    }
+}
+
+/// Clears the running flag of a `SearchHandle` when the search ends (even if it panics)
+pub(crate) struct RunningSearch(Arc<AtomicBool>);
+
+impl Drop for RunningSearch {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
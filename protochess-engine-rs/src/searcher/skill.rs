@@ -0,0 +1,46 @@
+// Strength limiting for human opponents (see `Engine::set_skill_level()`). The search depth is limited, and the move
+// is chosen among the best lines of a multi-PV search: each line gets a random bonus that grows with the weakness,
+// so the weaker levels often play moves that are slightly (or, at the lowest levels, clearly) worse than the best one.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::Position;
+use crate::types::Depth;
+
+use super::{SearchLimits, SearchRes};
+
+/// Skill level that plays at full strength
+pub const MAX_SKILL_LEVEL: u8 = 20;
+// Number of lines among which the move is chosen
+const SKILL_MULTIPV: u32 = 4;
+// Maximum random bonus (in centipawns) added to a line, scaled by the weakness
+const MAX_NOISE: i64 = 100;
+
+// Reduces the search of a single line to the depth and number of lines of the skill level
+pub(super) fn limit_search(limits: &mut SearchLimits, level: u8) {
+    limits.max_depth = std::cmp::min(limits.max_depth, level as Depth + 1);
+    limits.multipv = SKILL_MULTIPV;
+}
+
+// Chooses one of the lines (sorted from best to worst) of the search limited by `limit_search()`.
+// The choice only depends on the position, the lines and the seed, so the same search always plays the same move.
+pub(super) fn pick_line(position: &Position, mut lines: Vec<SearchRes>, level: u8, seed: u64) -> SearchRes {
+    let weakness = 120 - 2 * level as i64;
+    let top_score = lines[0].1 as i64;
+    let lowest_score = lines[lines.len() - 1].1 as i64;
+    let delta = std::cmp::min(top_score - lowest_score, MAX_NOISE);
+    let mut rng = StdRng::seed_from_u64(seed ^ position.get_zobrist() ^ level as u64);
+    let mut best_index = 0;
+    let mut best_value = i64::MIN;
+    for (index, (_pv, score, _depth)) in lines.iter().enumerate() {
+        let score = *score as i64;
+        // The bonus grows with the difference to the best line, so that the worse lines can also be chosen
+        let bonus = (weakness * (top_score - score) + delta * rng.gen_range(0, weakness)) / 128;
+        if score + bonus > best_value {
+            best_value = score + bonus;
+            best_index = index;
+        }
+    }
+    lines.swap_remove(best_index)
+}
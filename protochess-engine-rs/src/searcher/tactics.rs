@@ -0,0 +1,73 @@
+use crate::MoveGen;
+use crate::position::Position;
+use crate::types::{Move, Depth, Centipawns};
+
+use super::{alphabeta, Searcher, SearchHandle, SearchRes};
+
+impl Searcher {
+    /// Returns the number of moves in which the player to move can force a mate, if `score` is a winning mate score
+    pub fn mate_in(score: Centipawns) -> Option<Depth> {
+        if score <= -alphabeta::GAME_OVER_SCORE / 2 {
+            return None;
+        }
+        // The score of a checkmate is reduced by 1 for each ply
+        let plies = -alphabeta::GAME_OVER_SCORE - score;
+        Some(((plies + 1) / 2).min(Depth::MAX as Centipawns) as Depth)
+    }
+
+    /// Searches the 2 best moves of the position. If the best one is at least `threshold` centipawns better than the
+    /// second best, returns its line and score, and the difference with the second best move.
+    /// Returns `None` if the position has less than 2 legal moves.
+    pub fn find_tactic(position: &Position, depth: Depth, threshold: Centipawns, num_threads: u32, handle: &SearchHandle) -> Option<(SearchRes, Centipawns)> {
+        let mut lines = Searcher::get_best_moves(position, depth, 2, num_threads, handle);
+        if lines.len() < 2 || lines[0].0.is_empty() {
+            return None;
+        }
+        let gain = lines[0].1.saturating_sub(lines[1].1);
+        if gain < threshold {
+            return None;
+        }
+        Some((lines.swap_remove(0), gain))
+    }
+
+    /// Returns the themes of a puzzle whose solution starts with `pv[0]`, and whose score is `score`:
+    /// `"mate in N"`, `"fork"` (the moved piece attacks 2 or more enemy pieces that are not pawns),
+    /// `"capture"`, `"promotion"` and `"drop"`.
+    pub fn puzzle_themes(position: &Position, pv: &[Move], score: Centipawns) -> Vec<String> {
+        let mut themes = Vec::new();
+        if let Some(moves) = Searcher::mate_in(score) {
+            themes.push(format!("mate in {moves}"));
+        }
+        let mv = match pv.first() {
+            Some(mv) => *mv,
+            None => return themes,
+        };
+        let mut pos = position.clone();
+        let mover = pos.whos_turn;
+        pos.make_move(mv);
+        // The moved piece can be gone (e.g. if it exploded)
+        let to = mv.get_to();
+        if pos.piece_at(to).is_some_and(|piece| piece.get_player() == mover) {
+            let targets: Vec<_> = pos.pieces[1 - mover as usize].iter()
+                .filter(|enemy| enemy.get_forward_direction().is_none())
+                .flat_map(|enemy| enemy.get_indexes())
+                .collect();
+            let attacked = targets.into_iter()
+                .filter(|&index| MoveGen::attackers_of(index, &mut pos, mover).get_bit(to))
+                .count();
+            if attacked >= 2 {
+                themes.push("fork".to_string());
+            }
+        }
+        if mv.is_capture() {
+            themes.push("capture".to_string());
+        }
+        if mv.get_promotion_piece().is_some() {
+            themes.push("promotion".to_string());
+        }
+        if mv.get_drop_piece().is_some() {
+            themes.push("drop".to_string());
+        }
+        themes
+    }
+}
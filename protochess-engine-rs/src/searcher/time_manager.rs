@@ -0,0 +1,137 @@
+// Allocation of the thinking time of timed searches
+
+use instant::Duration;
+
+use crate::types::{Centipawns, Depth, Move, TimeControl};
+
+// Fraction of a fixed time budget that is reserved for the searches whose best move keeps changing.
+// The extra time is taken out of the budget, so that the search never takes longer than the budget.
+const FIXED_TIME_RESERVE: f64 = 0.3;
+// If the number of best move changes (see `BestMoveTracker::instability()`) is above this value when the time
+// runs out, the search falls back to the deepest line whose best move was confirmed by the next iteration.
+// It can only be exceeded if the best move has changed in the last iteration and in one of the previous ones.
+const UNSTABLE_FALLBACK_CHANGES: f64 = 1.0;
+// Time reserved on each move for the communication with the GUI or server, in milliseconds
+const MOVE_OVERHEAD_MS: u64 = 50;
+// Expected number of moves left in the game when the time control doesn't specify it (sudden death)
+const DEFAULT_MOVES_TO_GO: u64 = 30;
+// Maximum time of a move, as a multiple of the optimum time
+const MAX_TIME_FACTOR: u64 = 4;
+// A single move can use at most 1/N of the remaining time, unless it's the last move before the time control
+const MAX_REMAINING_DIVISOR: u64 = 3;
+// Fraction of the optimum time after which an easy move (e.g. an obvious recapture) is played
+const EASY_MOVE_FACTOR: f64 = 0.25;
+
+/// Decides how long a timed search can think. The search should stop after the optimum time, but it can
+/// use some extra time when the best move is unstable, and with a clock it can stop early on easy moves.
+#[derive(Debug, Clone, Copy)]
+pub struct TimeManager {
+    optimum: Duration,
+    // Extra time given when the best move keeps changing
+    max_extra: Duration,
+    // If true, the search doesn't need to use all the optimum time when the best move is obvious
+    stop_early: bool,
+}
+
+impl TimeManager {
+    /// Thinking time of a search with a fixed time budget. The optimum time and the extra time add up to the budget.
+    pub fn fixed(time_ms: u64) -> TimeManager {
+        let budget = Duration::from_millis(time_ms);
+        let optimum = budget.mul_f64(1.0 - FIXED_TIME_RESERVE);
+        TimeManager {
+            optimum,
+            max_extra: budget - optimum,
+            stop_early: false,
+        }
+    }
+    
+    /// Thinking time of a search that plays with a clock
+    pub fn from_clock(clock: &TimeControl) -> TimeManager {
+        let available = clock.remaining_ms.saturating_sub(MOVE_OVERHEAD_MS).max(1);
+        let moves_to_go = clock.moves_to_go.map_or(DEFAULT_MOVES_TO_GO, |moves| moves.max(1) as u64);
+        // Most of the increment can be spent, since it will be added back after the move
+        let optimum_ms = (available / moves_to_go + clock.increment_ms * 3 / 4).min(available);
+        let maximum_ms = {
+            if moves_to_go == 1 {
+                optimum_ms
+            } else {
+                (optimum_ms * MAX_TIME_FACTOR).min(available / MAX_REMAINING_DIVISOR).max(optimum_ms)
+            }
+        };
+        TimeManager {
+            optimum: Duration::from_millis(optimum_ms),
+            max_extra: Duration::from_millis(maximum_ms - optimum_ms),
+            stop_early: true,
+        }
+    }
+    
+    /// Time that the search should take
+    pub fn optimum(&self) -> Duration {
+        self.optimum
+    }
+    
+    /// Extra time given to the search, `instability` goes from 0 (stable best move) to 1 (the best move keeps changing)
+    pub fn extra_time(&self, instability: f64) -> Duration {
+        self.max_extra.mul_f64(instability.clamp(0.0, 1.0))
+    }
+    
+    /// Time after which an easy move can be played, or `None` if the search must always use the optimum time
+    pub fn easy_move_time(&self) -> Option<Duration> {
+        if self.stop_early {
+            Some(self.optimum.mul_f64(EASY_MOVE_FACTOR))
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks how often the best move of the root changes between the iterations of a search, and remembers the
+/// deepest line whose best move was the same as in the previous iteration (or the first line)
+#[derive(Debug, Clone, Default)]
+pub struct BestMoveTracker {
+    // Number of times the best move has changed between iterations, decays by half each iteration
+    changes: f64,
+    // Number of consecutive iterations that have returned the same best move
+    stable_iterations: u32,
+    // Best move of the last iteration
+    best_move: Option<Move>,
+    // Deepest stable line, with its score and depth
+    stable_line: Option<(Vec<Move>, Centipawns, Depth)>,
+}
+
+impl BestMoveTracker {
+    /// Records the best line of a completed iteration
+    pub fn update(&mut self, pv: &[Move], score: Centipawns, depth: Depth) {
+        let best_move = pv.first().copied();
+        self.changes /= 2.0;
+        if self.best_move.is_some() && self.best_move != best_move {
+            self.changes += 1.0;
+            self.stable_iterations = 0;
+        } else {
+            self.stable_iterations += 1;
+            self.stable_line = Some((pv.to_vec(), score, depth));
+        }
+        self.best_move = best_move;
+    }
+    
+    /// Number of times the best move has changed, where each change counts half as much as the next one.
+    /// Goes from 0 (the best move has never changed) to 2 (it changes on every iteration).
+    pub fn instability(&self) -> f64 {
+        self.changes
+    }
+    
+    /// Number of consecutive iterations (including the last one) that have returned the same best move
+    pub fn stable_iterations(&self) -> u32 {
+        self.stable_iterations
+    }
+    
+    /// Line that should be played instead of the last one when the search runs out of time, if the best move
+    /// is too unstable to trust the last iteration
+    pub fn fallback_line(&self) -> Option<&(Vec<Move>, Centipawns, Depth)> {
+        if self.changes > UNSTABLE_FALLBACK_CHANGES {
+            self.stable_line.as_ref()
+        } else {
+            None
+        }
+    }
+}
@@ -74,13 +74,18 @@ impl Entry {
     /// See <https://craftychess.com/hyatt/hashing.html>
     #[inline]
     fn get_hash_mask(&self) -> ZobKey {
-        // Unsafely cast the struct to a [u64; 3] to get the raw bytes
-        let data: &[u64; 3] = unsafe { &*(self as *const Entry).cast::<[u64; 3]>() };
+        // Unsafely cast the struct to a [u64; ENTRY_WORDS] to get the raw bytes
+        let data: &[u64; ENTRY_WORDS] = unsafe { &*(self as *const Entry).cast::<[u64; ENTRY_WORDS]>() };
         // XOR all the bytes
-        data[0] ^ data[1] ^ data[2]
+        data.iter().fold(0, |acc, word| acc ^ word)
     }
 }
 
+// Size of an entry in 64-bit words (3, or 4 with the `large-boards` feature since moves are larger)
+const ENTRY_WORDS: usize = std::mem::size_of::<Entry>() / 8;
+// The padding field must fill the struct completely
+const _: () = assert!(ENTRY_WORDS * 8 == std::mem::size_of::<Entry>());
+
 impl Default for Entry {
     fn default() -> Self {
         Entry::null()
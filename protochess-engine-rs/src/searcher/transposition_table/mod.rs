@@ -1,7 +1,7 @@
 use crate::types::Depth;
 use crate::types::ZobKey;
 
-pub mod entry;
+pub(crate) mod entry;
 pub use self::entry::Entry;
 pub use self::entry::EntryFlag;
 
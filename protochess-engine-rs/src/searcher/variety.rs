@@ -0,0 +1,32 @@
+// Opening variety (see `Engine::set_opening_variety()`): during the first turns of the game, the move is chosen at
+// random among the best lines of a multi-PV search whose score is close to the best one
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::Position;
+use crate::types::Centipawns;
+
+use super::{SearchLimits, SearchRes};
+
+// Maximum number of moves among which the move is chosen
+const VARIETY_MULTIPV: u32 = 8;
+
+// Returns true if the move of the position must be chosen at random
+pub(super) fn applies(position: &Position, turns: u32) -> bool {
+    position.get_properties().turn_number < turns
+}
+
+// Searches the lines among which the move is chosen
+pub(super) fn limit_search(limits: &mut SearchLimits) {
+    limits.multipv = VARIETY_MULTIPV;
+}
+
+// Chooses one of the lines (sorted from best to worst) whose score is at most `margin` centipawns below the best one.
+// The choice only depends on the position, the lines and the seed.
+pub(super) fn pick_line(position: &Position, mut lines: Vec<SearchRes>, margin: Centipawns, seed: u64) -> SearchRes {
+    let min_score = lines[0].1.saturating_sub(margin);
+    let num_candidates = lines.iter().take_while(|(_pv, score, _depth)| *score >= min_score).count();
+    let mut rng = StdRng::seed_from_u64(seed ^ position.get_zobrist());
+    lines.swap_remove(rng.gen_range(0, num_candidates))
+}
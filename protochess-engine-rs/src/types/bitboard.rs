@@ -1,11 +1,36 @@
 use std::ops;
 use impl_ops::*;
 
-use crate::{utils::to_index, wrap_res, err_assert};
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
 
+use crate::{utils::{to_index, from_index}, wrap_res, err_assert};
 
+
+#[cfg(not(feature = "large-boards"))]
 pub type BIndex = u8; // 256 positions in 16x16 board
-pub type BCoord = u8; // Coordinate the board: [0..15]
+#[cfg(feature = "large-boards")]
+pub type BIndex = u16; // 1024 positions in 32x32 board
+pub type BCoord = u8; // Coordinate the board: [0..BOARD_WIDTH-1]
+
+/// Number of columns (and rows) of the internal board representation. Each row is stored as
+/// a contiguous group of `BOARD_WIDTH` bits, so that moving one square north adds `BOARD_WIDTH` to the index.
+#[cfg(not(feature = "large-boards"))]
+pub const BOARD_WIDTH: BCoord = 16;
+#[cfg(feature = "large-boards")]
+pub const BOARD_WIDTH: BCoord = 32;
+/// Total number of squares (bits) of the internal board representation
+pub const BOARD_SIZE: usize = BOARD_WIDTH as usize * BOARD_WIDTH as usize;
+/// Maximum width of a playable board. Files are named with a single letter, so the width is limited to 26.
+pub const MAX_BOARD_WIDTH: BCoord = if BOARD_WIDTH < 26 { BOARD_WIDTH } else { 26 };
+/// Maximum height of a playable board
+pub const MAX_BOARD_HEIGHT: BCoord = BOARD_WIDTH;
+// Number of 64-bit words of a bitboard
+const BOARD_WORDS: usize = BOARD_SIZE / 64;
+
+#[cfg(not(feature = "large-boards"))]
+type BoardInternal = numext_fixed_uint::U256;
+#[cfg(feature = "large-boards")]
+type BoardInternal = numext_fixed_uint::U1024;
 
 // Store bounds of the board (bit set to 1 for valid positions) and dimensions (width and height)
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
@@ -19,7 +44,8 @@ pub struct BDimensions {
 impl BDimensions {
     // Create a BDimensions object of a given width and height, with all squares valid
     pub fn new_without_walls(width: BCoord, height: BCoord) -> wrap_res!(BDimensions) {
-        err_assert!(width <= 16 && height <= 16, "Board dimensions ({width}x{height}) must be <= 16x16");
+        err_assert!(width <= MAX_BOARD_WIDTH && height <= MAX_BOARD_HEIGHT,
+            "Board dimensions ({width}x{height}) must be <= {MAX_BOARD_WIDTH}x{MAX_BOARD_HEIGHT}");
         let mut bounds = Bitboard::zero();
         for x in 0..width {
             for y in 0..height {
@@ -30,7 +56,8 @@ impl BDimensions {
     }
     // Given a list of invalid squares (coordinates of walls), return a BDimensions object
     pub fn from_walls(width: BCoord, height: BCoord, walls: &[(BCoord, BCoord)]) -> wrap_res!(BDimensions) {
-        err_assert!(width <= 16 && height <= 16, "Board dimensions must be <= 16x16");
+        err_assert!(width <= MAX_BOARD_WIDTH && height <= MAX_BOARD_HEIGHT,
+            "Board dimensions must be <= {MAX_BOARD_WIDTH}x{MAX_BOARD_HEIGHT}");
         let mut bounds = Bitboard::zero();
         for x in 0..width {
             for y in 0..height {
@@ -50,26 +77,51 @@ impl BDimensions {
     }
 }
 
+lazy_static! {
+    // WEST_FILES[k] has the bits of the first k files (x < k) of all the rows
+    static ref WEST_FILES: Vec<Bitboard> = (0..=BOARD_WIDTH).map(|k| {
+        let mut files = Bitboard::zero();
+        for y in 0..BOARD_WIDTH {
+            for x in 0..k {
+                files.set_bit_at(x, y);
+            }
+        }
+        files
+    }).collect();
+}
+
+/// Set of squares of the board, with one bit per square (see `BOARD_WIDTH`).
+/// Iterating over `&Bitboard` yields the indexes of the set bits, from lowest to highest.
+/// Serialized as the list of `(x, y)` coordinates of the set bits.
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 #[must_use]
 pub struct Bitboard {
-    board_internal: numext_fixed_uint::U256,
+    board_internal: BoardInternal,
 }
 
 impl Bitboard {
     #[inline]
     pub fn zero() -> Bitboard {
-        Bitboard { board_internal: numext_fixed_uint::U256::zero() }
+        Bitboard { board_internal: BoardInternal::zero() }
     }
     #[inline]
     pub fn one() -> Bitboard {
-        Bitboard { board_internal: numext_fixed_uint::U256::one() }
+        Bitboard { board_internal: BoardInternal::one() }
     }
-    pub fn from_coord_list(squares: &[(BCoord, BCoord)]) -> Bitboard {
+    pub fn from_coords(squares: &[(BCoord, BCoord)]) -> Bitboard {
         let mut board = Bitboard::zero();
         squares.iter().for_each(|(x,y)| board.set_bit_at(*x, *y));
         board
     }
+    /// Returns the `(x, y)` coordinates of the set bits, from lowest to highest index
+    pub fn to_coords(&self) -> Vec<(BCoord, BCoord)> {
+        self.iter().map(from_index).collect()
+    }
+    /// Returns an iterator over the indexes of the set bits, from lowest to highest
+    #[inline]
+    pub fn iter(&self) -> BitboardIter {
+        BitboardIter { words: *self.get_inner(), word: 0 }
+    }
     #[inline]
     pub fn set_bit(&mut self, index: BIndex) {
         self.board_internal.set_bit(index as usize, true);
@@ -110,23 +162,47 @@ impl Bitboard {
     pub fn count_ones(&self) -> u32 {
         self.board_internal.count_ones()
     }
+    /// Moves all the bits `k` squares north (towards higher y). The bits that leave the board are lost.
+    #[inline]
+    pub fn shift_n(&self, k: BCoord) -> Bitboard {
+        if k >= BOARD_WIDTH { return Bitboard::zero(); }
+        Bitboard { board_internal: &self.board_internal << (k as u32 * BOARD_WIDTH as u32) }
+    }
+    /// Moves all the bits `k` squares south (towards lower y). The bits that leave the board are lost.
+    #[inline]
+    pub fn shift_s(&self, k: BCoord) -> Bitboard {
+        if k >= BOARD_WIDTH { return Bitboard::zero(); }
+        Bitboard { board_internal: &self.board_internal >> (k as u32 * BOARD_WIDTH as u32) }
+    }
+    /// Moves all the bits `k` squares east (towards higher x). The bits don't wrap around to the next row.
+    #[inline]
+    pub fn shift_e(&self, k: BCoord) -> Bitboard {
+        if k >= BOARD_WIDTH { return Bitboard::zero(); }
+        (self & &WEST_FILES[(BOARD_WIDTH - k) as usize]) << k
+    }
+    /// Moves all the bits `k` squares west (towards lower x). The bits don't wrap around to the previous row.
+    #[inline]
+    pub fn shift_w(&self, k: BCoord) -> Bitboard {
+        if k >= BOARD_WIDTH { return Bitboard::zero(); }
+        (self & !&WEST_FILES[k as usize]) >> k
+    }
     #[inline]
     pub fn overflowing_mul(self, rhs: &Bitboard) -> Bitboard {
         Bitboard { board_internal: self.board_internal.overflowing_mul(&rhs.board_internal).0 }
     }
     #[inline]
-    pub fn get_inner(&self) -> &[u64; 4] {
+    pub fn get_inner(&self) -> &[u64; BOARD_WORDS] {
         self.board_internal.get_inner()
     }
     #[inline]
-    pub fn get_inner_mut(&mut self) -> &mut [u64; 4] {
+    pub fn get_inner_mut(&mut self) -> &mut [u64; BOARD_WORDS] {
         self.board_internal.get_inner_mut()
     }
 }
 impl std::fmt::Display for Bitboard {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        for y in (0..16).rev() {
-            for x in 0..16 {
+        for y in (0..BOARD_WIDTH).rev() {
+            for x in 0..BOARD_WIDTH {
                 if self.get_bit_at(x, y) {
                     write!(f, "1")?;
                 } else {
@@ -150,6 +226,52 @@ impl Default for Bitboard {
     }
 }
 
+/// Iterator over the indexes of the set bits of a bitboard, see `Bitboard::iter()`
+#[derive(Debug, Clone)]
+pub struct BitboardIter {
+    // Remaining bits, and index of the lowest word that can have bits
+    words: [u64; BOARD_WORDS],
+    word: usize,
+}
+impl Iterator for BitboardIter {
+    type Item = BIndex;
+    #[inline]
+    fn next(&mut self) -> Option<BIndex> {
+        while self.word < BOARD_WORDS {
+            let bits = self.words[self.word];
+            if bits != 0 {
+                // Clear the lowest set bit
+                self.words[self.word] = bits & (bits - 1);
+                return Some((self.word * 64 + bits.trailing_zeros() as usize) as BIndex);
+            }
+            self.word += 1;
+        }
+        None
+    }
+}
+impl IntoIterator for &Bitboard {
+    type Item = BIndex;
+    type IntoIter = BitboardIter;
+    fn into_iter(self) -> BitboardIter {
+        self.iter()
+    }
+}
+
+impl Serialize for Bitboard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_coords().serialize(serializer)
+    }
+}
+impl<'de> Deserialize<'de> for Bitboard {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Bitboard, D::Error> {
+        let coords = Vec::<(BCoord, BCoord)>::deserialize(deserializer)?;
+        if let Some(&(x, y)) = coords.iter().find(|&&(x, y)| x >= BOARD_WIDTH || y >= BOARD_WIDTH) {
+            return Err(serde::de::Error::custom(format!("Square ({x}, {y}) is outside the board")));
+        }
+        Ok(Bitboard::from_coords(&coords))
+    }
+}
+
 impl_op_ex!(+ |a: &Bitboard, b: &Bitboard| -> Bitboard { Bitboard{board_internal: &a.board_internal + &b.board_internal} });
 impl_op_ex!(- |a: &Bitboard, b: &Bitboard| -> Bitboard { Bitboard{board_internal: &a.board_internal - &b.board_internal} });
 impl_op_ex!(& |a: &Bitboard, b: &Bitboard| -> Bitboard { Bitboard{board_internal: &a.board_internal & &b.board_internal} });
@@ -164,19 +286,19 @@ impl_op_ex!(-= |a: &mut Bitboard, b: &Bitboard| { a.board_internal -= &b.board_i
 impl_op_ex!(&= |a: &mut Bitboard, b: &Bitboard| { a.board_internal &= &b.board_internal });
 impl_op_ex!(|= |a: &mut Bitboard, b: &Bitboard| { a.board_internal |= &b.board_internal });
 impl_op_ex!(^= |a: &mut Bitboard, b: &Bitboard| { a.board_internal ^= &b.board_internal });
-impl_op_ex!(^= |a: &mut Bitboard, b: u16| { a.board_internal ^= numext_fixed_uint::U256::from(b) });
+impl_op_ex!(^= |a: &mut Bitboard, b: u16| { a.board_internal ^= BoardInternal::from(b) });
 impl_op_ex!(<<= |a: &mut Bitboard, b: BCoord| { a.board_internal <<= b });
 impl_op_ex!(>>= |a: &mut Bitboard, b: BCoord| { a.board_internal >>= b });
 
 trait GetBitboardInner {
-    fn get_inner(&self) -> &[u64; 4];
-    fn get_inner_mut(&mut self) -> &mut [u64; 4];
+    fn get_inner(&self) -> &[u64; BOARD_WORDS];
+    fn get_inner_mut(&mut self) -> &mut [u64; BOARD_WORDS];
 }
-impl GetBitboardInner for numext_fixed_uint::U256 {
-    fn get_inner(&self) -> &[u64; 4] {
+impl GetBitboardInner for BoardInternal {
+    fn get_inner(&self) -> &[u64; BOARD_WORDS] {
         &self.0
     }
-    fn get_inner_mut(&mut self) -> &mut [u64; 4] {
+    fn get_inner_mut(&mut self) -> &mut [u64; BOARD_WORDS] {
         &mut self.0
     }
 }
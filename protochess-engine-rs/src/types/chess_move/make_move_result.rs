@@ -1,3 +1,4 @@
+use crate::piece::PieceId;
 use crate::types::{Player, BCoord};
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -9,9 +10,18 @@ pub enum MakeMoveResultFlag {
     LeaderCaptured,
     AllPiecesCaptured,
     PieceInWinSquare,
+    WinConditionMet,
     CheckLimit,
+    CaptureLimit,
+    MoveLimit,
     Stalemate,
     Repetition,
+    NoProgress,
+    InsufficientMaterial,
+    // The game was terminated by the players or an arbiter, see `GameTermination`
+    Resignation,
+    DrawAgreement,
+    Adjudication,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -32,6 +42,40 @@ pub struct MakeMoveResult {
     pub exploded: Vec<(BCoord, BCoord)>,
     /// If `flag != IllegalMove`, contains the move in algebraic notation
     pub move_notation: Option<String>,
+    /// Pieces of the board that have been moved, removed or added by the move
+    pub delta: BoardDelta,
+}
+
+/// Change of a piece of the board caused by a move, see `BoardDelta`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SquareChange {
+    /// Square of the piece before the move, or `None` if the piece was added to the board (dropped)
+    pub from: Option<(BCoord, BCoord)>,
+    /// Square of the piece after the move, or `None` if the piece was removed from the board (captured or exploded)
+    pub to: Option<(BCoord, BCoord)>,
+    /// Id of the piece before the move (or after it, if the piece was added)
+    pub piece_id: PieceId,
+    /// If the piece was promoted, contains its new id
+    pub promotion: Option<PieceId>,
+    pub player: Player,
+}
+
+/// All the changes of the board caused by a move, so that the board doesn't need to be compared with the
+/// previous one. The changes are sorted: first the moved pieces (starting with the piece that made the move,
+/// then the castling rook), then the removed pieces and finally the added pieces.
+/// The pieces in hand are not included.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BoardDelta {
+    pub changes: Vec<SquareChange>,
+}
+
+/// Result of a finished game, see `Engine::game_result()`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct GameResult {
+    /// The reason why the game ended (never `Ok` or `IllegalMove`)
+    pub flag: MakeMoveResultFlag,
+    /// The winner of the game, or `None` if it's a draw
+    pub winner: MakeMoveResultWinner,
 }
 
 
@@ -50,9 +94,17 @@ impl From<String> for MakeMoveResultFlag {
             "LeaderCaptured" => Self::LeaderCaptured,
             "AllPiecesCaptured" => Self::AllPiecesCaptured,
             "PieceInWinSquare" => Self::PieceInWinSquare,
+            "WinConditionMet" => Self::WinConditionMet,
             "CheckLimit" => Self::CheckLimit,
+            "CaptureLimit" => Self::CaptureLimit,
+            "MoveLimit" => Self::MoveLimit,
             "Stalemate" => Self::Stalemate,
             "Repetition" => Self::Repetition,
+            "NoProgress" => Self::NoProgress,
+            "InsufficientMaterial" => Self::InsufficientMaterial,
+            "Resignation" => Self::Resignation,
+            "DrawAgreement" => Self::DrawAgreement,
+            "Adjudication" => Self::Adjudication,
             _ => panic!("Invalid flag"),
         }
     }
@@ -91,12 +143,22 @@ impl From<String> for MakeMoveResultWinner {
 
 
 impl MakeMoveResult {
+    pub fn game_over(result: GameResult, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
+        Self {
+            flag: result.flag,
+            winner: result.winner,
+            exploded,
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
     pub fn ok(exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
         Self {
             flag: MakeMoveResultFlag::Ok,
             winner: None.into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn illegal_move() -> Self {
@@ -105,6 +167,7 @@ impl MakeMoveResult {
             winner: None.into(),
             exploded: Vec::new(),
             move_notation: None,
+            delta: BoardDelta::default(),
         }
     }
     pub fn checkmate(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -113,6 +176,7 @@ impl MakeMoveResult {
             winner: Some(winner).into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn leader_captured(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -121,6 +185,7 @@ impl MakeMoveResult {
             winner: Some(winner).into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn all_pieces_captured(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -129,6 +194,7 @@ impl MakeMoveResult {
             winner: Some(winner).into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn piece_in_win_square(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -137,6 +203,16 @@ impl MakeMoveResult {
             winner: Some(winner).into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
+    pub fn win_condition_met(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
+        Self {
+            flag: MakeMoveResultFlag::WinConditionMet,
+            winner: Some(winner).into(),
+            exploded,
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn check_limit(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -145,6 +221,25 @@ impl MakeMoveResult {
             winner: Some(winner).into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
+    pub fn capture_limit(winner: Player, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
+        Self {
+            flag: MakeMoveResultFlag::CaptureLimit,
+            winner: Some(winner).into(),
+            exploded,
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
+    pub fn move_limit(winner: Option<Player>, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
+        Self {
+            flag: MakeMoveResultFlag::MoveLimit,
+            winner: winner.into(),
+            exploded,
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn stalemate(winner: Option<Player>, exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
@@ -153,6 +248,7 @@ impl MakeMoveResult {
             winner: winner.into(),
             exploded,
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
     pub fn repetition(move_notation: String) -> Self {
@@ -162,6 +258,26 @@ impl MakeMoveResult {
             // Since this is a repetition, this move cannot be a capture, so there is no explosion
             exploded: Vec::new(),
             move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
+    pub fn no_progress(move_notation: String) -> Self {
+        Self {
+            flag: MakeMoveResultFlag::NoProgress,
+            winner: None.into(),
+            // This move did not reset the halfmove clock, so it cannot be a capture
+            exploded: Vec::new(),
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
+        }
+    }
+    pub fn insufficient_material(exploded: Vec<(BCoord, BCoord)>, move_notation: String) -> Self {
+        Self {
+            flag: MakeMoveResultFlag::InsufficientMaterial,
+            winner: None.into(),
+            exploded,
+            move_notation: Some(move_notation),
+            delta: BoardDelta::default(),
         }
     }
 }
\ No newline at end of file
@@ -30,20 +30,35 @@ pub enum MoveType {
     DoubleJump = 0b1000,
     // Skip 0b1001 because there is no DoubleJumpCapture
     Null = 0b1010,
+    // Skip 0b1011 because there is no NullCapture
+    Drop = 0b1100,
+    // Skip 0b1101 because there is no DropCapture
+    Pass = 0b1110,
 }
 
+// Packed representation of the move fields, large enough for 3 indices and the move type
+#[cfg(not(feature = "large-boards"))]
+type MoveFields = u32;
+#[cfg(feature = "large-boards")]
+type MoveFields = u64;
+// Number of bits used to store each index in the packed fields
+const INDEX_BITS: u32 = BIndex::BITS;
+const INDEX_MASK: MoveFields = BIndex::MAX as MoveFields;
+const MOVE_TYPE_SHIFT: u32 = 3 * INDEX_BITS;
+
 #[derive(Eq, PartialEq, Copy, Clone)]
 #[must_use]
 pub struct Move {
-    /// Stores a move in a u32
+    /// Stores a move in a u32 (or a u64 with the `large-boards` feature, where each index takes 16 bits)
     ///0-7:   from index:u8
     ///8-15:  to index:u8
     ///16-23: target index:u8
     ///24-27 : movetype (see MoveType above)
     /// In captures, target is the index of the captured piece (usually the same as to, except for en passant)
     /// In DoubleJump, target is the index of the generated En Passant square
-    move_fields: u32, 
-    // Promotion piece
+    /// In Drop, from, to and target are all the index where the piece is dropped
+    move_fields: MoveFields,
+    // Promotion piece, dropped piece, or gated piece (placed on the from square, in other move types)
     promotion: PieceId
 }
 
@@ -51,11 +66,28 @@ impl Move {
     #[inline]
     pub fn new(from: BIndex, to: BIndex, target: BIndex, move_type: MoveType, promotion: Option<PieceId>) -> Move {
         Move {
-            move_fields: (from as u32) | (to as u32) << 8 | (target as u32) << 16 | (move_type as u32) << 24,
+            move_fields: (from as MoveFields)
+                | (to as MoveFields) << INDEX_BITS
+                | (target as MoveFields) << (2 * INDEX_BITS)
+                | (move_type as MoveFields) << MOVE_TYPE_SHIFT,
             promotion: promotion.unwrap_or('_')
         }
     }
 
+    /// Creates a move that places a piece from the hand of the player on an empty square
+    #[inline]
+    pub fn drop(index: BIndex, piece: PieceId) -> Move {
+        Move::new(index, index, index, MoveType::Drop, Some(piece))
+    }
+
+    /// Returns a copy of this move that also places a piece from the hand of the player on the square
+    /// that the moving piece leaves (gating). Only valid for non-promotion moves.
+    #[inline]
+    pub fn with_gate(mut self, piece: PieceId) -> Move {
+        self.promotion = piece;
+        self
+    }
+
     #[inline]
     pub fn null() -> Move {
         Move::new(0, 0, 0, MoveType::Null, None)
@@ -66,29 +98,44 @@ impl Move {
         self.get_move_type() == MoveType::Null
     }
     
+    /// Creates a move that skips the turn of the player (see `GlobalRules::passing_allowed`).
+    /// Unlike the null move (used internally by the search), it can be played in a game.
+    #[inline]
+    pub fn pass() -> Move {
+        Move::new(0, 0, 0, MoveType::Pass, None)
+    }
+    
+    #[inline]
+    pub fn is_pass(&self) -> bool {
+        self.get_move_type() == MoveType::Pass
+    }
+    
     pub fn is_quiet(&self) -> bool {
-        self.get_move_type() == MoveType::Quiet || self.get_move_type() == MoveType::DoubleJump
+        let move_type = self.get_move_type();
+        move_type == MoveType::Quiet || move_type == MoveType::DoubleJump || move_type == MoveType::Drop
     }
 
     pub fn get_from(&self) -> BIndex{
-        (self.move_fields & 0xFF) as BIndex
+        (self.move_fields & INDEX_MASK) as BIndex
     }
 
     pub fn get_to(&self) -> BIndex{
-        ((self.move_fields >> 8) & 0xFF) as BIndex
+        ((self.move_fields >> INDEX_BITS) & INDEX_MASK) as BIndex
     }
     
     // Get the index of the victim piece, if any. Usually the same as get_to(), except for en passant
     // In double jump, this is the index of the generated en passant square
     pub fn get_target(&self) -> BIndex {
-        ((self.move_fields >> 16) & 0xFF) as BIndex
+        ((self.move_fields >> (2 * INDEX_BITS)) & INDEX_MASK) as BIndex
     }
 
     pub fn is_capture(&self) -> bool {
         // The least significant bit of the move type is used to indicate capture
-        ((self.move_fields >> 24) & 1) != 0
+        ((self.move_fields >> MOVE_TYPE_SHIFT) & 1) != 0
     }
     
+    /// Returns `true` if the captured piece is not on the destination square. For pieces that capture
+    /// by replacement, this is an en passant capture.
     pub fn is_en_passant(&self) -> bool {
         self.get_move_type() == MoveType::Capture && self.get_target() != self.get_to()
     }
@@ -102,20 +149,24 @@ impl Move {
         let move_type = self.get_move_type();
         move_type == MoveType::KingsideCastle || move_type == MoveType::QueensideCastle
     }
+    
+    pub fn is_drop(&self) -> bool {
+        self.get_move_type() == MoveType::Drop
+    }
 
     #[inline]
     pub fn get_move_type(&self) -> MoveType {
         // Output a match statement that maps from "x if x == MoveType::XX as u32" to "MoveType::XX"
         macro_rules! match_move_type {
             ($($x:ident),*) => {
-                match self.move_fields >> 24 {
+                match self.move_fields >> MOVE_TYPE_SHIFT {
                     // For each argument x, generate a line of the match
-                    $( x if x == MoveType::$x as u32 => { MoveType::$x } )*
+                    $( x if x == MoveType::$x as MoveFields => { MoveType::$x } )*
                     _ => { panic!("Invalid move type") }
                 }
             }
         }
-        match_move_type!(Quiet, Capture, KingsideCastle, QueensideCastle, Promotion, PromotionCapture, DoubleJump, Null)
+        match_move_type!(Quiet, Capture, KingsideCastle, QueensideCastle, Promotion, PromotionCapture, DoubleJump, Null, Drop, Pass)
     }
 
     pub fn get_promotion_piece(&self) -> Option<PieceId> {
@@ -126,21 +177,37 @@ impl Move {
         }
     }
 
+    /// Returns the piece placed on the board, if this is a drop move
+    pub fn get_drop_piece(&self) -> Option<PieceId> {
+        if self.is_drop() {
+            Some(self.promotion)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the piece placed on the from square, if this move gates a piece from the hand
+    pub fn get_gate_piece(&self) -> Option<PieceId> {
+        if self.promotion == '_' || self.is_promotion() || self.is_drop() {
+            None
+        } else {
+            Some(self.promotion)
+        }
+    }
+
     /// Returns the squares that would explode if this move was made.
     /// **Important**: Call this function **before** making the move
     pub fn get_potential_explosion(&self, pos: &Position) -> Vec<(BCoord, BCoord)> {
-        let piece = pos.piece_at(self.get_from()).unwrap();
-        if !piece.explodes_on_capture() || !self.is_capture() {
+        if !self.is_capture() {
             return Vec::new();
         }
-        let to = self.get_to();
-        let mut explosion = piece.get_explosion(to).clone();
-        let mut explosion_squares = Vec::new();
-        while let Some(index) = explosion.lowest_one() {
-            explosion_squares.push(from_index(index));
-            explosion.clear_bit(index);
+        let piece = pos.piece_at(self.get_from()).unwrap();
+        if !piece.explodes_on_capture() {
+            return Vec::new();
         }
-        // Ensure that to square is included in the explosion
+        let to = piece.get_capture_square(*self);
+        let mut explosion_squares = piece.get_explosion(to).to_coords();
+        // Ensure that the capture square is included in the explosion
         if !explosion_squares.contains(&from_index(to)) {
             explosion_squares.push(from_index(to));
         }
@@ -153,6 +220,13 @@ impl fmt::Display for Move {
         if self.is_null() {
             return write!(f, "[NULL]");
         }
+        if self.is_pass() {
+            return write!(f, "0000");
+        }
+        if self.is_drop() {
+            let (x, y) = from_index(self.get_to());
+            return write!(f, "{}@{}", self.promotion, to_rank_file(x, y));
+        }
         let (x1, y1) = from_index(self.get_from());
         let (x2, y2) = {
             // Print castling moves as if the king moves to the rook square
@@ -160,7 +234,8 @@ impl fmt::Display for Move {
             else { from_index(self.get_to()) }
         };
         let suffix = {
-            if self.is_promotion() { format!("={}", self.promotion) }
+            // Gating moves use the same notation as promotions
+            if self.is_promotion() || self.get_gate_piece().is_some() { format!("={}", self.promotion) }
             else { "".to_string() }
         };
         write!(f, "{}{}{}", to_rank_file(x1, y1), to_rank_file(x2, y2), suffix)
@@ -171,6 +246,9 @@ impl fmt::Debug for Move {
         if self.is_null() {
             return write!(f, "[NULL]");
         }
+        if self.is_pass() {
+            return write!(f, "[PASS]");
+        }
         let (fx, fy) = from_index(self.get_from());
         let from = to_rank_file(fx, fy);
         let (to_x, to_y) = from_index(self.get_to());
@@ -181,9 +259,14 @@ impl fmt::Debug for Move {
             if self.is_promotion() { format!("={}", self.promotion) }
             else if self.is_capture() { "x".to_string() }
             else if self.is_castling() { "(Castle)".to_string() }
+            else if self.is_drop() { format!("(Drop {})", self.promotion) }
             else { "".to_string() }
         };
-        write!(f, "{from}->{to}{suffix} [t:{target}]")
+        let gate = match self.get_gate_piece() {
+            Some(gate) => format!("(Gate {gate})"),
+            None => "".to_string(),
+        };
+        write!(f, "{from}->{to}{suffix}{gate} [t:{target}]")
     }
 }
 
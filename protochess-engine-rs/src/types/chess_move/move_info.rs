@@ -1,16 +1,21 @@
 use std::convert::TryFrom;
 
-use regex::Regex;
-use scan_fmt::scan_fmt;
 
-use crate::{PieceId, err_assert, wrap_res, err};
-use crate::types::BCoord;
+use crate::{PieceId, Position, MoveGen, err_assert, wrap_res, err};
+use crate::types::{BCoord, MAX_BOARD_WIDTH, MAX_BOARD_HEIGHT};
 use crate::utils::from_index;
-use crate::utils::notation::tuple_to_rank_file;
+use crate::utils::notation::{tuple_to_rank_file, get_algebraic_notation, strip_san_suffixes};
 
 use super::Move;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Public representation of a move.
+/// Drops (crazyhouse) have `from == to` (the square where the piece is placed), and `promotion` is the id
+/// of the dropped piece for the player making the move (e.g. `'n'` when black drops a knight).
+/// In gating moves, `promotion` is the id of the piece from the hand that is placed on the `from` square.
+/// Passing the turn is represented as a move from `(0, 0)` to `(0, 0)` without promotion (written as `"0000"`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 #[must_use]
 pub struct MoveInfo {
     pub from: (BCoord, BCoord),
@@ -20,6 +25,13 @@ pub struct MoveInfo {
 
 impl From<Move> for MoveInfo {
     fn from(m: Move) -> Self {
+        if m.is_pass() {
+            return MoveInfo::pass();
+        }
+        if let Some(piece) = m.get_drop_piece() {
+            let square = from_index(m.get_to());
+            return MoveInfo { from: square, to: square, promotion: Some(piece) };
+        }
         let from = from_index(m.get_from());
         let to = {
             if m.is_castling() {
@@ -29,37 +41,170 @@ impl From<Move> for MoveInfo {
                 from_index(m.get_to())
             }
         };
-        MoveInfo { from, to, promotion: m.get_promotion_piece() }
+        MoveInfo { from, to, promotion: m.get_promotion_piece().or(m.get_gate_piece()) }
     }
 }
 
-// Create a MoveInfo from a string like "e2e4" or "e7e8=Q"
+impl MoveInfo {
+    /// Create a MoveInfo that drops a piece from the hand of the player on the given square
+    pub fn drop(piece: PieceId, square: (BCoord, BCoord)) -> Self {
+        MoveInfo { from: square, to: square, promotion: Some(piece) }
+    }
+    
+    /// Returns true if this move drops a piece from the hand of the player
+    pub fn is_drop(&self) -> bool {
+        self.from == self.to && self.promotion.is_some()
+    }
+    
+    /// Create a MoveInfo that skips the turn of the player (see `GlobalRules::passing_allowed`)
+    pub fn pass() -> Self {
+        MoveInfo { from: (0, 0), to: (0, 0), promotion: None }
+    }
+    
+    /// Returns true if this move skips the turn of the player
+    pub fn is_pass(&self) -> bool {
+        self.from == self.to && self.promotion.is_none()
+    }
+    
+    /// Create a MoveInfo from a move in Standard Algebraic Notation, like "Nf3", "exd5", "O-O" or "e8=Q+".
+    /// The move must be legal in the given position.
+    pub fn from_san(san: &str, pos: &mut Position) -> wrap_res!(Self) {
+        let target = strip_san_suffixes(san.trim());
+        // Some programs use zeros instead of the letter O for castling
+        let target = if target.starts_with("0-0") { target.replace('0', "O") } else { target.to_string() };
+        let moves = MoveGen::get_pseudo_moves(pos, true);
+        for mv in &moves {
+            if !MoveGen::is_move_legal(*mv, pos) {
+                continue;
+            }
+            let notation = get_algebraic_notation(pos, *mv, &moves);
+            let notation = strip_san_suffixes(&notation);
+            if notation == target {
+                return Ok(MoveInfo::from(*mv));
+            }
+            // Pieces without a prefix (pawns) are written without the starting file when capturing,
+            // but standard SAN includes it (as in "exd5")
+            if notation.starts_with('x') && target.len() == notation.len() + 1 && target.ends_with(notation)
+                && target.starts_with((b'a' + from_index(mv.get_from()).0) as char) {
+                return Ok(MoveInfo::from(*mv));
+            }
+        }
+        err!("Invalid or illegal move: '{san}'")
+    }
+    
+    /// Create a MoveInfo from a string in any of the formats of `try_from()` ("e2e4", "e7e8=Q", "N@f3", "0000"),
+    /// in Standard Algebraic Notation (see `from_san()`), or a castling move given by the origin and destination of
+    /// the king followed by `c` (like "e1g1c", useful when the king could also move there without castling).
+    /// Only the castling and SAN moves need to be legal in the given position.
+    pub fn parse(s: &str, pos: &mut Position) -> wrap_res!(Self) {
+        let s = s.trim();
+        match parse_move(s) {
+            Ok(ParsedMove::Castling(from, to)) => {
+                let castling = MoveGen::get_legal_moves(pos).into_iter()
+                    .find(|mv| mv.is_castling() && mv.get_gate_piece().is_none() && from_index(mv.get_from()) == from && from_index(mv.get_to()) == to);
+                match castling {
+                    Some(mv) => Ok(MoveInfo::from(mv)),
+                    None => err!("Invalid move '{s}': there is no legal castling move from {} to {}", tuple_to_rank_file(from), tuple_to_rank_file(to)),
+                }
+            },
+            // In SAN, drops use the same letter for both players ("P@e4"), otherwise the letter is the piece id
+            Ok(ParsedMove::Move(mv)) if mv.is_drop() => Ok(MoveInfo::from_san(s, pos).unwrap_or(mv)),
+            Ok(ParsedMove::Move(mv)) => Ok(mv),
+            // If the move starts with 2 squares, the error of the long notation is more useful than the SAN one
+            Err(e) => MoveInfo::from_san(s, pos).map_err(|san_err| {
+                let two_squares = split_square(s).and_then(|(_, rest)| split_square(rest)).is_some();
+                if two_squares { e } else { san_err }
+            }),
+        }
+    }
+}
+
+// Create a MoveInfo from a string like "e2e4", "e7e8=Q", "N@f3" (drop) or "0000" (pass).
+// Castling moves are written as the king moving to the square of the castling partner ("e1h1"); the form
+// with the destination of the king ("e1g1c") needs a position, see `MoveInfo::parse()`.
 impl TryFrom<&str> for MoveInfo {
     type Error = String;
     fn try_from(s: &str) -> wrap_res!(Self) {
-        const EXPECTED_REGEX: &str = r"^[a-p][0-9]+[a-p][0-9]+(=.)?$";
         let s = s.trim();
-        err_assert!(Regex::new(EXPECTED_REGEX).unwrap().is_match(s), "Invalid move format: '{s}' (expected 'e2e4', 'e7e8=Q')");
-        let (from_x, from_y, to_x, to_y) = match scan_fmt!(s, "{[a-p]}{d}{[a-p]}{d}", char, isize, char, isize) {
-            Ok(parts) => parts,
-            Err(_) => err!("Invalid move format: '{s}'"),
-        };
-        let promotion = match scan_fmt!(s, "{*[a-p]}{*d}{*[a-p]}{*d}={}", PieceId) {
-            Ok(promo) => Some(promo),
-            Err(_) => None,
+        match parse_move(s)? {
+            ParsedMove::Move(mv) => Ok(mv),
+            ParsedMove::Castling(..) => err!("Invalid move '{s}': castling with the destination of the king needs a position, use MoveInfo::parse()"),
+        }
+    }
+}
+
+// Result of parsing a move without knowing the position
+enum ParsedMove {
+    Move(MoveInfo),
+    // Castling move, given by the origin and destination of the king (like "e1g1c")
+    Castling((BCoord, BCoord), (BCoord, BCoord)),
+}
+
+// Parses the formats of `TryFrom<&str>`, plus castling with the destination of the king
+fn parse_move(s: &str) -> wrap_res!(ParsedMove) {
+    const EXPECTED: &str = "expected 'e2e4', 'e7e8=Q', 'e1g1c', 'N@f3' or '0000'";
+    if s == "0000" {
+        return Ok(ParsedMove::Move(MoveInfo::pass()));
+    }
+    if let Some((piece, square)) = s.split_once('@') {
+        let mut chars = piece.chars();
+        let piece = match (chars.next(), chars.next()) {
+            (Some(piece), None) if !piece.is_whitespace() => piece,
+            _ => err!("Invalid move '{s}': the dropped piece '{piece}' must be a single character"),
         };
-        // from_x, to_x are guaranteed to be between 'a' and 'p' (inclusive)
-        let from_x = from_x.to_digit(36).unwrap() as BCoord - 10;
-        let to_x = to_x.to_digit(36).unwrap() as BCoord - 10;
-        // Ranks are 1-indexed
-        err_assert!(from_y > 0 && to_y > 0 && from_y <= 16 && to_y <= 16,
-            "Invalid move format (rank must be between 1 and 16");
-        Ok(MoveInfo {
-            from: (from_x, from_y as BCoord - 1),
-            to: (to_x, to_y as BCoord - 1),
-            promotion,
-        })
+        let square = parse_square(square, s)?;
+        return Ok(ParsedMove::Move(MoveInfo::drop(piece, square)));
     }
+    let Some((from, rest)) = split_square(s) else {
+        err!("Invalid move format: '{s}' ({EXPECTED})");
+    };
+    let Some((to, rest)) = split_square(rest) else {
+        err!("Invalid move '{s}': expected a destination square after '{from}', found '{rest}'");
+    };
+    let (from, to) = (parse_square(from, s)?, parse_square(to, s)?);
+    if rest == "c" {
+        return Ok(ParsedMove::Castling(from, to));
+    }
+    let promotion = match rest.strip_prefix('=') {
+        None if rest.is_empty() => None,
+        None => err!("Invalid move '{s}': unexpected '{rest}' after the destination square ({EXPECTED})"),
+        Some(piece) => {
+            let mut chars = piece.chars();
+            match (chars.next(), chars.next()) {
+                (Some(piece), None) if !piece.is_whitespace() => Some(piece),
+                (None, _) => err!("Invalid move '{s}': missing promotion piece after '='"),
+                _ => err!("Invalid move '{s}': the promotion piece '{piece}' must be a single character"),
+            }
+        },
+    };
+    Ok(ParsedMove::Move(MoveInfo { from, to, promotion }))
+}
+
+// Splits the square at the start of `s` (a lowercase file followed by the digits of the rank) from the rest
+fn split_square(s: &str) -> Option<(&str, &str)> {
+    if !s.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return None;
+    }
+    let end = s[1..].find(|c: char| !c.is_ascii_digit()).map_or(s.len(), |i| i + 1);
+    if end == 1 {
+        return None;
+    }
+    Some(s.split_at(end))
+}
+
+// Converts a square like "e4" (a file between 'a' and 'z' and a 1-indexed rank) to coordinates.
+// `mv` is the whole move, for the error messages.
+fn parse_square(square: &str, mv: &str) -> wrap_res!((BCoord, BCoord)) {
+    let Some((file, rank)) = split_square(square).filter(|(_, rest)| rest.is_empty()).map(|_| square.split_at(1)) else {
+        err!("Invalid move '{mv}': '{square}' is not a square");
+    };
+    let x = file.as_bytes()[0] - b'a';
+    let last_file = (b'a' + MAX_BOARD_WIDTH - 1) as char;
+    err_assert!(x < MAX_BOARD_WIDTH, "Invalid move '{mv}': the file of '{square}' must be between a and {last_file}");
+    let max_rank = MAX_BOARD_HEIGHT as usize;
+    let rank = rank.parse::<usize>().unwrap_or(0);
+    err_assert!(rank > 0 && rank <= max_rank, "Invalid move '{mv}': the rank of '{square}' must be between 1 and {max_rank}");
+    Ok((x, rank as BCoord - 1))
 }
 
 impl PartialEq<Move> for MoveInfo {
@@ -72,6 +217,12 @@ impl PartialEq<Move> for MoveInfo {
 /// or check/checkmate indicators).
 impl std::fmt::Display for MoveInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_drop() {
+            return write!(f, "{}@{}", self.promotion.unwrap(), tuple_to_rank_file(self.to));
+        }
+        if self.is_pass() {
+            return write!(f, "0000");
+        }
         write!(f, "{}{}", tuple_to_rank_file(self.from), tuple_to_rank_file(self.to))?;
         if let Some(prom) = self.promotion {
             write!(f, "={prom}")?;
@@ -8,4 +8,13 @@ pub struct MoveList {
     pub x: BCoord, 
     pub y: BCoord,
     pub moves: Vec<MoveInfo>
-}
\ No newline at end of file
+}
+
+/// A single move in string form, in both long and short algebraic notation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveStr {
+    /// Long algebraic notation, in the format accepted by `Engine::make_move_str()` (e.g. "e2e4", "e7e8=Q")
+    pub long: String,
+    /// Short algebraic notation, including check/checkmate indicators (e.g. "e4", "e8=Q+")
+    pub san: String,
+}
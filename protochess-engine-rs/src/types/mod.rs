@@ -23,6 +23,18 @@ pub enum GameMode {
     RacingKings,
     ThreeCheck,
     FiveCheck,
+    Crazyhouse,
+    /// Fog of war: there is no check, and a player wins by capturing the enemy king.
+    /// See `Engine::visible_squares()` and `Engine::get_state_for_player()`.
+    DarkChess,
+}
+
+impl GameMode {
+    /// All the game modes, in declaration order
+    pub const ALL: [GameMode; 10] = [
+        GameMode::Standard, GameMode::Atomic, GameMode::Horde, GameMode::Antichess, GameMode::KingOfTheHill,
+        GameMode::RacingKings, GameMode::ThreeCheck, GameMode::FiveCheck, GameMode::Crazyhouse, GameMode::DarkChess,
+    ];
 }
 
 impl TryFrom<&str> for GameMode {
@@ -33,10 +45,13 @@ impl TryFrom<&str> for GameMode {
             "atomic" => Ok(GameMode::Atomic),
             "horde" => Ok(GameMode::Horde),
             "antichess" => Ok(GameMode::Antichess),
-            "kingofthehill" => Ok(GameMode::KingOfTheHill),
-            "racingkings" => Ok(GameMode::RacingKings),
-            "3check" => Ok(GameMode::ThreeCheck),
-            "5check" => Ok(GameMode::FiveCheck),
+            // Also accept the names used when displaying the game mode
+            "kingofthehill" | "king of the hill" => Ok(GameMode::KingOfTheHill),
+            "racingkings" | "racing kings" => Ok(GameMode::RacingKings),
+            "3check" | "three-check" => Ok(GameMode::ThreeCheck),
+            "5check" | "five-check" => Ok(GameMode::FiveCheck),
+            "crazyhouse" => Ok(GameMode::Crazyhouse),
+            "darkchess" | "dark chess" => Ok(GameMode::DarkChess),
             _ => err!("Invalid game mode '{value}'"),
         }
     }
@@ -53,6 +68,8 @@ impl std::fmt::Display for GameMode {
             GameMode::RacingKings => write!(f, "Racing Kings"),
             GameMode::ThreeCheck => write!(f, "Three-check"),
             GameMode::FiveCheck => write!(f, "Five-check"),
+            GameMode::Crazyhouse => write!(f, "Crazyhouse"),
+            GameMode::DarkChess => write!(f, "Dark Chess"),
         }
     }
 }
\ No newline at end of file
@@ -1,6 +1,13 @@
+use crate::PieceId;
+
+use super::{MoveInfo, BCoord, GameResult};
+
 // Type for storing the value of a position, must allow negative values
 pub type Centipawns = i32;
 
+/// Score of a forced win at the root. A win in N plies is scored as `MATE_SCORE - N`, and a loss as `N - MATE_SCORE`.
+pub const MATE_SCORE: Centipawns = 1_000_000;
+
 // Type for storing a zobrist hash key
 pub type ZobKey = u64;
 
@@ -21,3 +28,342 @@ impl std::fmt::Display for SearchTimeout {
     }
 }
 impl std::error::Error for SearchTimeout { }
+
+
+/// Clock of the player to move, used by `Engine::get_best_move_clock()` to decide how long to think
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeControl {
+    /// Time left on the clock, in milliseconds
+    pub remaining_ms: u64,
+    /// Time added to the clock after each move, in milliseconds
+    pub increment_ms: u64,
+    /// Number of moves until the next time control, or `None` if the remaining time must last for the
+    /// rest of the game (sudden death)
+    pub moves_to_go: Option<u32>,
+}
+
+/// Result of `Engine::get_best_move()` and the other searches that return a single move
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[must_use]
+pub enum SearchOutcome {
+    /// The best move, its score for the player to move and the depth searched
+    BestMove { mv: MoveInfo, score: Centipawns, depth: Depth },
+    /// The game is already over in the current position, so there is nothing to search
+    GameOver { result: GameResult },
+}
+
+impl SearchOutcome {
+    /// Returns the best move, its score and the depth searched, or `None` if the game is over
+    pub fn into_move(self) -> Option<(MoveInfo, Centipawns, Depth)> {
+        match self {
+            SearchOutcome::BestMove { mv, score, depth } => Some((mv, score, depth)),
+            SearchOutcome::GameOver { .. } => None,
+        }
+    }
+}
+
+/// Search algorithm used by `Engine::get_best_move()` and the other single-line searches,
+/// see `Engine::set_search_algorithm()`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Algorithm {
+    /// Iterative deepening alpha-beta search (the default)
+    #[default]
+    AlphaBeta,
+    /// Monte Carlo tree search with `simulations` simulations per search. Each simulation evaluates a new
+    /// position of the tree with the evaluator, instead of playing random moves until the end of the game.
+    /// `c_puct` is the exploration constant: higher values search more moves, lower values search the best
+    /// moves deeper (usually between 1 and 4).
+    Mcts { simulations: u32, c_puct: f64 },
+}
+
+/// Score of a position for the player to move, distinguishing the forced wins and losses from the evaluations
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScoreType {
+    /// Evaluation of the position, in centipawns
+    Cp(Centipawns),
+    /// The game ends in the given number of plies: positive if the player to move wins, negative if it loses
+    Mate(i32),
+}
+
+impl ScoreType {
+    /// Converts a score returned by the searches, where the forced wins and losses are encoded with `MATE_SCORE`
+    pub fn from_centipawns(score: Centipawns) -> ScoreType {
+        if score.abs() <= MATE_SCORE / 2 {
+            return ScoreType::Cp(score);
+        }
+        let plies = MATE_SCORE - score.abs();
+        ScoreType::Mate(if score > 0 { plies } else { -plies })
+    }
+    
+    /// Returns true if the game ends with the best moves of both players
+    pub fn is_mate(&self) -> bool {
+        matches!(self, ScoreType::Mate(_))
+    }
+}
+
+impl From<Centipawns> for ScoreType {
+    fn from(score: Centipawns) -> Self {
+        ScoreType::from_centipawns(score)
+    }
+}
+
+/// Result of a search, reported after each iteration of iterative deepening
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    /// Depth that has been fully searched
+    pub depth: Depth,
+    /// Score of the position for the player to move
+    pub score: Centipawns,
+    /// Same as `score`, but the mates are converted to their number of plies
+    pub score_type: ScoreType,
+    /// Principal variation, starting with the best move
+    pub pv: Vec<MoveInfo>,
+    /// Same as `pv`, in algebraic notation
+    pub pv_san: Vec<String>,
+    /// Number of nodes searched in this iteration
+    pub nodes: u64,
+    /// Number of nodes searched since the start of the search, in all the iterations
+    pub total_nodes: u64,
+    /// Time since the start of the search (not only this iteration), in milliseconds
+    pub elapsed_ms: u64,
+    /// Average search speed since the start of the search, in nodes per second
+    pub nps: u64,
+    /// Effective branching factor: growth of the number of nodes per extra ply with respect to the previous
+    /// completed iteration. 0 if there is no previous iteration.
+    pub branching_factor: f64,
+    /// Statistics of the search tree of this iteration
+    pub stats: SearchStats,
+    /// First plies of the search tree of this iteration, in pre-order (each node is followed by its children).
+    /// Empty unless requested with `Engine::get_best_move_with_tree_callback()`.
+    pub tree: Vec<SearchTreeNode>,
+}
+
+/// Number of move indexes tracked in `SearchStats::cutoffs_by_move_index`. Cutoffs produced by later moves
+/// are counted in the last slot.
+pub const CUTOFF_MOVE_INDEXES: usize = 8;
+
+/// Statistics of an iteration of the search, useful for diagnosing search problems (e.g. with custom pieces)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    /// Total number of nodes searched, including quiescence nodes
+    pub nodes: u64,
+    /// Number of nodes searched in the quiescence search
+    pub qnodes: u64,
+    /// Number of times the transposition table was probed
+    pub tt_probes: u64,
+    /// Number of probes that found an entry for the position
+    pub tt_hits: u64,
+    /// Number of static evaluations requested by the search
+    pub eval_probes: u64,
+    /// Number of evaluations that were found in the evaluation cache
+    pub eval_hits: u64,
+    /// Number of beta cutoffs, by index of the move that produced the cutoff (0 is the first move searched)
+    pub cutoffs_by_move_index: [u64; CUTOFF_MOVE_INDEXES],
+    /// Number of nodes pruned by the null move heuristic
+    pub null_move_prunes: u64,
+    /// Number of searches with late move reduction
+    pub lmr_searches: u64,
+    /// Number of reduced searches that had to be repeated at full depth
+    pub lmr_researches: u64,
+    /// Number of verification searches of singular extensions (searches that exclude the move from the
+    /// transposition table, to find out if it's much better than the others)
+    pub singular_searches: u64,
+    /// Number of moves that were extended by 1 ply because they were singular
+    pub singular_extensions: u64,
+}
+
+impl SearchStats {
+    /// Fraction of the transposition table probes that found an entry (0 if there were no probes)
+    pub fn tt_hit_rate(&self) -> f64 {
+        if self.tt_probes == 0 { 0.0 } else { self.tt_hits as f64 / self.tt_probes as f64 }
+    }
+    /// Fraction of the static evaluations that were found in the evaluation cache (0 if there were none)
+    pub fn eval_hit_rate(&self) -> f64 {
+        if self.eval_probes == 0 { 0.0 } else { self.eval_hits as f64 / self.eval_probes as f64 }
+    }
+    /// Total number of beta cutoffs
+    pub fn beta_cutoffs(&self) -> u64 {
+        self.cutoffs_by_move_index.iter().sum()
+    }
+    /// Fraction of the beta cutoffs that were produced by the first move (a measure of the move ordering quality)
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        let cutoffs = self.beta_cutoffs();
+        if cutoffs == 0 { 0.0 } else { self.cutoffs_by_move_index[0] as f64 / cutoffs as f64 }
+    }
+}
+
+/// Node of the search tree, reported by `Engine::get_best_move_with_tree_callback()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchTreeNode {
+    /// Distance from the root (0 for the root node)
+    pub ply: u8,
+    /// Move that leads to this node from its parent. `None` for the root and for null moves.
+    pub mv: Option<MoveInfo>,
+    /// Remaining depth of the search at this node
+    pub depth: Depth,
+    /// Search window of the node
+    pub alpha: Centipawns,
+    pub beta: Centipawns,
+    /// Score returned by the node, or `None` if the search timed out before the node was completed
+    pub score: Option<Centipawns>,
+}
+
+/// One of the lines returned by a multi-PV search (`Engine::get_best_moves()`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PvLine {
+    /// Score of the position for the player to move, if this line is played
+    pub score: Centipawns,
+    /// Same as `score`, but the mates are converted to their number of plies
+    pub score_type: ScoreType,
+    /// Moves of the line, starting with the root move
+    pub pv: Vec<MoveInfo>,
+}
+
+/// Result of `Engine::explain_best_move()`: the best move, and why the most promising alternatives are worse
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BestMoveExplanation {
+    pub best_move: MoveInfo,
+    /// Score of the position for the player to move
+    pub score: Centipawns,
+    /// Principal variation, starting with `best_move`
+    pub pv: Vec<MoveInfo>,
+    /// Most promising alternatives to `best_move`, sorted from best to worst
+    pub alternatives: Vec<AlternativeMove>,
+}
+
+/// A root move that is not the best move, along with the line that refutes it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlternativeMove {
+    pub move_info: MoveInfo,
+    /// Score after playing this move, for the player to move at the root
+    pub score: Centipawns,
+    /// Best line for the opponent after this move, starting with the opponent's reply.
+    /// Empty if the move ends the game.
+    pub refutation: Vec<MoveInfo>,
+}
+
+/// Position of a game in which the best move is much better than the others, found by `Engine::find_tactics()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PuzzleCandidate {
+    /// Number of moves of the game played before the position
+    pub ply: usize,
+    /// FEN of the position
+    pub fen: String,
+    /// Score of the best move, for the player to move
+    pub score: Centipawns,
+    /// Difference between the scores of the best and the second best move
+    pub gain: Centipawns,
+    /// Best line, starting with the move that solves the puzzle
+    pub solution: Vec<MoveInfo>,
+    /// Themes of the puzzle, like `"mate in 2"` or `"fork"` (see `Searcher::puzzle_themes()`)
+    pub themes: Vec<String>,
+}
+
+/// Quality of a move of the game, compared to the best move (see `PositionFactory::annotate_history()`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveClassification {
+    /// The move is the best move found by the search
+    Best,
+    /// The move loses less than 50 centipawns
+    Good,
+    /// The move loses at least 50 centipawns
+    Inaccuracy,
+    /// The move loses at least 100 centipawns
+    Mistake,
+    /// The move loses at least 300 centipawns
+    Blunder,
+}
+
+impl MoveClassification {
+    /// Returns the PGN Numeric Annotation Glyph of the classification: `$6` (?!) for inaccuracies,
+    /// `$2` (?) for mistakes and `$4` (??) for blunders
+    pub fn nag(&self) -> Option<u8> {
+        match self {
+            MoveClassification::Best | MoveClassification::Good => None,
+            MoveClassification::Inaccuracy => Some(6),
+            MoveClassification::Mistake => Some(2),
+            MoveClassification::Blunder => Some(4),
+        }
+    }
+}
+
+impl From<MoveClassification> for String {
+    fn from(c: MoveClassification) -> Self {
+        format!("{:?}", c)
+    }
+}
+impl From<String> for MoveClassification {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "Best" => Self::Best,
+            "Good" => Self::Good,
+            "Inaccuracy" => Self::Inaccuracy,
+            "Mistake" => Self::Mistake,
+            "Blunder" => Self::Blunder,
+            _ => panic!("Invalid classification"),
+        }
+    }
+}
+
+/// Evaluation of a move of the game, returned by `PositionFactory::annotate_history()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveAnnotation {
+    /// Move that was played
+    pub mv: MoveInfo,
+    /// Algebraic notation of the move, as in `PositionFactory::get_notation()`
+    pub notation: String,
+    /// Score of the best move, for the player who moved
+    pub best_score: Centipawns,
+    /// Score of the played move, for the player who moved
+    pub score: Centipawns,
+    /// Best line of the position, starting with the best move
+    pub best_line: Vec<MoveInfo>,
+    /// Algebraic notation of the best move
+    pub best_notation: String,
+    /// Best line for the opponent after the played move (the threat that the move allows), starting with the
+    /// opponent's reply. Empty if the move ends the game.
+    pub refutation: Vec<MoveInfo>,
+    pub classification: MoveClassification,
+}
+
+
+/// Detailed static evaluation of a position (`Engine::evaluate_position_detailed()`).
+/// All scores are from the point of view of the player to move: the opponent's pieces count
+/// as negative, and in variants where the goal is to lose (antichess) the signs are inverted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EvalBreakdown {
+    /// Total score, equal to `material + positional + mobility + pawn_structure + king_safety + checks + variant`
+    pub total: Centipawns,
+    /// Sum of the material values of all pieces (including the pieces in hand)
+    pub material: Centipawns,
+    /// Sum of the piece-square table values of all pieces
+    pub positional: Centipawns,
+    /// Sum of the mobility scores of all pieces
+    pub mobility: Centipawns,
+    /// Doubled, isolated and passed pawns (or pieces that move like pawns)
+    pub pawn_structure: Centipawns,
+    /// Pawn shield and enemy pieces near the leader
+    pub king_safety: Centipawns,
+    /// Penalty for the number of times each player has been in check (only in N-check variants)
+    pub checks: Centipawns,
+    /// Terms specific to the variant, such as the pawn cohesion in Horde (see `EvalProfile`), the explosion
+    /// threats on the leaders when some pieces explode (Atomic) and the distance to the win squares (King of the Hill)
+    pub variant: Centipawns,
+    /// If true, the endgame piece-square tables were used
+    pub is_endgame: bool,
+    /// Contribution of each piece on the board
+    pub pieces: Vec<PieceEval>,
+}
+
+/// Contribution of a single piece to the evaluation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PieceEval {
+    pub piece_id: PieceId,
+    pub position: (BCoord, BCoord),
+    /// Material value of the piece
+    pub material: Centipawns,
+    /// Value of the piece-square table at the current square
+    pub positional: Centipawns,
+    /// Bonus for the number of squares that the piece can move to
+    pub mobility: Centipawns,
+}
@@ -0,0 +1,78 @@
+// Fixed search benchmark, to catch performance regressions in the move generator and the search
+
+use instant::Instant;
+
+use crate::{Engine, GameState, VariantRegistry, wrap_res};
+use crate::types::Depth;
+
+/// Default depth of `Engine::bench()`, used by `cargo run -- bench` and `cargo bench`
+pub const BENCH_DEPTH: Depth = 6;
+
+/// Positions searched by `bench()`: name of the variant (see `VariantRegistry`) and FEN (`None` for the
+/// starting position of the variant)
+pub const BENCH_POSITIONS: [(&str, Option<&str>); 8] = [
+    ("standard", None),
+    ("standard", Some("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1")),
+    ("standard", Some("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1")),
+    ("atomic", None),
+    ("crazyhouse", Some("r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R[] w KQkq - 4 4")),
+    ("three-check", Some("rnbqkbnr/ppp2ppp/8/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3 +0+0")),
+    ("horde", None),
+    ("capablanca", None),
+];
+
+/// Search of one of the `BENCH_POSITIONS`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchPosition {
+    pub variant: String,
+    pub fen: Option<String>,
+    /// Number of nodes searched, in all the iterations
+    pub nodes: u64,
+    pub elapsed_ms: u64,
+}
+
+/// Result of `bench()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchResult {
+    pub depth: Depth,
+    pub positions: Vec<BenchPosition>,
+    /// Total number of nodes searched. The search is deterministic, so this is a signature of the engine:
+    /// it only changes when the move generation, the move ordering or the search change.
+    pub nodes: u64,
+    pub elapsed_ms: u64,
+}
+
+impl BenchResult {
+    /// Nodes searched per second
+    pub fn nps(&self) -> u64 {
+        self.nodes * 1000 / self.elapsed_ms.max(1)
+    }
+}
+
+/// Searches each of the `BENCH_POSITIONS` to the given depth with a single thread, using a new engine
+/// (with an empty transposition table) for each position
+pub fn bench(depth: Depth) -> wrap_res!(BenchResult) {
+    let start = Instant::now();
+    let mut positions = Vec::new();
+    for (variant, fen) in BENCH_POSITIONS {
+        let state = GameState {
+            initial_state: VariantRegistry::get(variant)?,
+            initial_fen: fen.map(String::from),
+            ..Default::default()
+        };
+        let mut engine = Engine::default();
+        engine.set_deterministic(true);
+        let _result = engine.set_state(state)?;
+        let position_start = Instant::now();
+        let mut nodes = 0;
+        let _best_move = engine.get_best_move_with_callback(depth, |result| nodes += result.nodes)?;
+        positions.push(BenchPosition {
+            variant: variant.to_string(),
+            fen: fen.map(String::from),
+            nodes,
+            elapsed_ms: position_start.elapsed().as_millis() as u64,
+        });
+    }
+    let nodes = positions.iter().map(|p| p.nodes).sum();
+    Ok(BenchResult { depth, positions, nodes, elapsed_ms: start.elapsed().as_millis() as u64 })
+}
@@ -2,16 +2,16 @@
 
 use std::collections::VecDeque;
 
-use crate::types::{BCoord, BIndex, Bitboard};
+use crate::types::{BCoord, BIndex, Bitboard, BOARD_WIDTH};
 
 #[must_use]
 pub fn to_index(x: BCoord, y: BCoord) -> BIndex{
-    (16 * y + x) as BIndex
+    BOARD_WIDTH as BIndex * y as BIndex + x as BIndex
 }
 
 #[must_use]
 pub fn from_index(index: BIndex) -> (BCoord, BCoord) {
-    ((index % 16) as BCoord , (index / 16) as BCoord)
+    ((index % BOARD_WIDTH as BIndex) as BCoord, (index / BOARD_WIDTH as BIndex) as BCoord)
 }
 
 // BFS to find distance to nearest 1, using a callback function to get neighbors
@@ -23,7 +23,7 @@ pub fn distance_to_one<F>(x_start: BCoord, y_start: BCoord, board: &Bitboard, ge
     queue.push_back((x_start, y_start, 0));
     while !queue.is_empty() {
         let (x, y, dist) = queue.pop_front().unwrap();
-        if x >= 16 || y >= 16 {
+        if x >= BOARD_WIDTH || y >= BOARD_WIDTH {
             continue;
         }
         if board.get_bit_at(x, y) {
@@ -0,0 +1,240 @@
+// Fuzzing of the parsers and of make_move()/unmake_move() with random (and often invalid) inputs
+
+use std::convert::TryFrom;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{MoveGen, Position, MoveInfo, GameState, VariantGenerator, VariantConstraints, VariantRegistry};
+use crate::move_generator::LegalityInfo;
+use crate::position::create::position_factory::PositionFactory;
+use crate::types::{BCoord, Depth};
+
+use super::perft::game_continues;
+
+// Characters used to corrupt the inputs: FEN syntax, digits, separators and some multi-byte characters
+const HOSTILE_CHARS: &[char] = &[
+    'K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p', 'w', '-', '*', '!', '[', ']', '(', ')', ',', '+', '/',
+    ' ', '0', '1', '8', '9', 'a', 'h', 'z', '{', '}', '"', ':', '\\', '\0', 'é', '♔', '𝄞',
+];
+
+// Depth of the make/unmake round trip after each successfully parsed input
+const ROUND_TRIP_DEPTH: Depth = 2;
+
+/// Sets up a position with the given FEN (as the `initial_fen` of some built-in variants). Parse errors are
+/// expected, but if the position is created it must pass `check_round_trip()`. Panics if an invariant is violated.
+pub fn fuzz_fen(fen: &str) {
+    for variant in ["standard", "crazyhouse", "capablanca"] {
+        let state = GameState {
+            initial_state: VariantRegistry::get(variant).unwrap(),
+            initial_fen: Some(fen.to_string()),
+            ..Default::default()
+        };
+        fuzz_game_state(state);
+    }
+}
+
+/// Loads a game state written in JSON (see `GameState::from_json()`) and sets it up, see `fuzz_game_state()`
+pub fn fuzz_game_state_json(json: &str) {
+    if let Ok(state) = GameState::from_json(json) {
+        fuzz_game_state(state);
+    }
+}
+
+/// Sets up a game state (which can be invalid, or contain illegal moves) with `PositionFactory::set_state()`.
+/// If it succeeds, the position must pass `check_round_trip()`. Panics if an invariant is violated.
+pub fn fuzz_game_state(state: GameState) {
+    // Very large boards with many pieces make the round trip too slow, only check that they are parsed
+    let check_moves = state.initial_state.piece_types.len() <= 16;
+    if let Ok(Some(mut position)) = PositionFactory::default().set_state(state, None) {
+        if check_moves {
+            check_round_trip(&mut position, ROUND_TRIP_DEPTH);
+        }
+    }
+}
+
+/// Entry point for byte-oriented fuzzers (e.g. `cargo fuzz` with `fuzz_target!(|data| fuzz_bytes(data))`).
+/// The first byte selects the input type (FEN, JSON game state or list of moves), the rest is the input.
+pub fn fuzz_bytes(data: &[u8]) {
+    let (selector, input) = match data.split_first() {
+        Some((selector, input)) => (*selector, String::from_utf8_lossy(input)),
+        None => return,
+    };
+    match selector % 3 {
+        0 => fuzz_fen(&input),
+        1 => fuzz_game_state_json(&input),
+        _ => {
+            let move_history = input.split_whitespace().filter_map(|mv| MoveInfo::try_from(mv).ok()).collect();
+            fuzz_game_state(GameState { move_history, ..Default::default() });
+        },
+    }
+}
+
+/// Makes and unmakes every legal move up to the given depth, and panics if the zobrist key, the occupied
+/// squares or the consistency of the position (see `Position::check_consistency()`) change after unmaking
+/// a move, or if the position becomes inconsistent after making it.
+pub fn check_round_trip(position: &mut Position, depth: Depth) {
+    if let Err(e) = position.check_consistency() {
+        panic!("Inconsistent position: {}", e);
+    }
+    if depth == 0 {
+        return;
+    }
+    let zobrist = position.get_zobrist();
+    let occupied = position.occ_or_out_bounds.clone();
+    let info = LegalityInfo::new(position);
+    for mv in MoveGen::get_pseudo_moves(position, true) {
+        if !MoveGen::make_move_if_legal_with(mv, position, &info) {
+            continue;
+        }
+        if game_continues(position) {
+            check_round_trip(position, depth - 1);
+        } else if let Err(e) = position.check_consistency() {
+            panic!("Inconsistent position after {}: {}", mv, e);
+        }
+        position.unmake_move();
+        assert_eq!(position.get_zobrist(), zobrist, "The zobrist key changed after making and unmaking {}", mv);
+        assert!(position.occ_or_out_bounds == occupied, "The occupied squares changed after making and unmaking {}", mv);
+    }
+}
+
+/// Deterministic generator of random inputs for the `fuzz_*` functions: valid inputs of random variants,
+/// and corrupted versions of them
+#[derive(Debug, Clone)]
+pub struct FuzzGenerator {
+    rng: StdRng,
+    variants: VariantGenerator,
+}
+
+impl FuzzGenerator {
+    pub fn new(seed: u64) -> Self {
+        let constraints = VariantConstraints { width: (6, 10), height: (6, 10), ..Default::default() };
+        FuzzGenerator {
+            rng: StdRng::seed_from_u64(seed),
+            variants: VariantGenerator::new(constraints, seed).unwrap(),
+        }
+    }
+
+    /// Random FEN string, with a random placement, side to move, castling rights, en passant squares,
+    /// clocks and check counts. Some of them are corrupted with `corrupt()`.
+    pub fn fen(&mut self) -> String {
+        let width = self.rng.gen_range(1, 12);
+        let height = self.rng.gen_range(1, 12);
+        let ranks: Vec<String> = (0..height).map(|_| self.fen_rank(width)).collect();
+        let mut fen = ranks.join("/");
+        if self.rng.gen_bool(0.2) {
+            let pocket = self.random_string("QRBNPqrbnp", 4);
+            fen = format!("{}[{}]", fen, pocket);
+        }
+        let side = ["w", "b", "W", "x", ""][self.rng.gen_range(0, 5)];
+        let castling = match self.rng.gen_range(0, 4) {
+            0 => "-".to_string(),
+            1 => "(ALL)".to_string(),
+            2 => self.random_string("KQkqABCHabch", 4),
+            _ => format!("({})", self.random_string("abcdefgh12345678,", 6)),
+        };
+        let ep = match self.rng.gen_range(0, 3) {
+            0 => "-".to_string(),
+            1 => self.square(width, height),
+            _ => format!("{},{}({})", self.square(width, height), self.square(width, height), self.square(width, height)),
+        };
+        let clocks = format!("{} {}", self.rng.gen_range(0, 120), self.rng.gen_range(1, 80));
+        let checks = if self.rng.gen_bool(0.2) { format!(" +{}+{}", self.rng.gen_range(0, 4), self.rng.gen_range(0, 4)) } else { String::new() };
+        let fen = format!("{} {} {} {} {}{}", fen, side, castling, ep, clocks, checks);
+        if self.rng.gen_bool(0.3) { self.corrupt(&fen) } else { fen }
+    }
+
+    /// Random game state: a random variant (see `VariantGenerator`) or a built-in one, an optional random FEN
+    /// and a list of moves, which are legal at the beginning and random at the end
+    pub fn game_state(&mut self) -> GameState {
+        let mut state = if self.rng.gen_bool(0.5) {
+            self.variants.generate().unwrap_or_default()
+        } else {
+            let names = VariantRegistry::names();
+            let name = &names[self.rng.gen_range(0, names.len())];
+            GameState::from_variant_name(name).unwrap()
+        };
+        if self.rng.gen_bool(0.2) {
+            state.initial_fen = Some(self.fen());
+        }
+        // Play random legal moves
+        if let Ok(Some(mut position)) = PositionFactory::default().set_state(state.clone(), None) {
+            for _ in 0..self.rng.gen_range(0, 20) {
+                let moves = MoveGen::get_legal_moves(&mut position);
+                if moves.is_empty() {
+                    break;
+                }
+                let mv = moves[self.rng.gen_range(0, moves.len())];
+                state.move_history.push(MoveInfo::from(mv));
+                position.make_move(mv);
+                if !game_continues(&mut position) {
+                    break;
+                }
+            }
+        }
+        // Followed by some random moves, which are probably illegal
+        let (width, height) = (state.initial_state.board_width, state.initial_state.board_height);
+        for _ in 0..self.rng.gen_range(0, 3) {
+            let from = (self.rng.gen_range(0, width + 2), self.rng.gen_range(0, height + 2));
+            let to = (self.rng.gen_range(0, width + 2), self.rng.gen_range(0, height + 2));
+            let promotion = if self.rng.gen_bool(0.1) { Some('Q') } else { None };
+            state.move_history.push(MoveInfo { from, to, promotion });
+        }
+        state
+    }
+
+    /// JSON of a random game state (see `GameState::to_json()`), sometimes corrupted
+    pub fn game_state_json(&mut self) -> String {
+        let json = self.game_state().to_json();
+        if self.rng.gen_bool(0.5) { self.corrupt(&json) } else { json }
+    }
+
+    /// Inserts, removes and replaces some characters of the string (keeping it valid UTF-8)
+    pub fn corrupt(&mut self, input: &str) -> String {
+        let mut chars: Vec<char> = input.chars().collect();
+        for _ in 0..self.rng.gen_range(1, 5) {
+            let index = self.rng.gen_range(0, chars.len() + 1);
+            let c = HOSTILE_CHARS[self.rng.gen_range(0, HOSTILE_CHARS.len())];
+            match self.rng.gen_range(0, 4) {
+                0 => chars.insert(index, c),
+                1 if index < chars.len() => { chars.remove(index); },
+                2 if index < chars.len() => chars[index] = c,
+                // Long numbers, to find overflows
+                _ => chars.splice(index..index, "9999999999".chars()).for_each(drop),
+            }
+        }
+        chars.into_iter().collect()
+    }
+
+    // Rank of a FEN string with about `width` squares: pieces, numbers of empty squares and walls
+    fn fen_rank(&mut self, width: BCoord) -> String {
+        let mut rank = String::new();
+        let mut x = 0;
+        while x < width {
+            let c = match self.rng.gen_range(0, 10) {
+                0..=3 => {
+                    let empty = self.rng.gen_range(1, width - x + 1);
+                    x += empty;
+                    rank.push_str(&empty.to_string());
+                    continue;
+                },
+                4 => '*',
+                _ => b"KQRBNPkqrbnp"[self.rng.gen_range(0, 12)] as char,
+            };
+            rank.push(c);
+            x += 1;
+        }
+        rank
+    }
+
+    // Square in algebraic notation, possibly outside of the board
+    fn square(&mut self, width: BCoord, height: BCoord) -> String {
+        let file = (b'a' + self.rng.gen_range(0, width + 1)) as char;
+        format!("{}{}", file, self.rng.gen_range(0, height as u32 + 2))
+    }
+
+    fn random_string(&mut self, alphabet: &str, max_len: usize) -> String {
+        let alphabet: Vec<char> = alphabet.chars().collect();
+        (0..self.rng.gen_range(0, max_len + 1)).map(|_| alphabet[self.rng.gen_range(0, alphabet.len())]).collect()
+    }
+}
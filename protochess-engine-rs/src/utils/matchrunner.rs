@@ -0,0 +1,198 @@
+// Matches between two engine configurations, used to test changes to the search and the evaluation
+
+use crate::{Engine, GameState, GameResult, MoveInfo, MakeMoveResultFlag, MakeMoveResultWinner, PgnHeaders, SearchOutcome, wrap_res, err, err_assert};
+use crate::types::Depth;
+use super::tuning::TuningParams;
+
+/// Limit of each search of a player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MatchLimit {
+    /// Search up to a fixed depth
+    Depth(Depth),
+    /// Search for a fixed number of seconds
+    Time(u64),
+    /// Search (approximately) a fixed number of nodes
+    Nodes(u64),
+}
+
+/// Settings of one of the engines of a match
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchPlayer {
+    /// Name of the engine, written in the PGN
+    pub name: String,
+    pub limit: MatchLimit,
+    /// Number of threads of the search, or `None` to use all of them (see `Engine::set_num_threads()`)
+    pub num_threads: Option<u32>,
+    /// Weights of the evaluation, e.g. to compare the tuned weights with the default ones (see `Engine::set_eval_weights()`)
+    pub eval_weights: TuningParams,
+}
+
+/// Settings of a match, see `run_match()`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchConfig {
+    pub players: [MatchPlayer; 2],
+    /// Starting positions (and variants). Each one is played twice in a row, once with each player as white.
+    /// If there are more games than openings, the openings are repeated.
+    pub openings: Vec<GameState>,
+    pub num_games: u32,
+    /// Games that reach this number of plies (without counting the moves of the opening) are adjudicated as a draw
+    pub max_plies: u32,
+}
+
+/// Game played by `run_match()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchGame {
+    /// Index in `MatchConfig::players` of the player with the white pieces
+    pub white: usize,
+    /// Result of the game, or `None` if it was adjudicated as a draw after `max_plies`
+    pub result: Option<GameResult>,
+    /// Number of plies played by the engines
+    pub plies: u32,
+    pub pgn: String,
+}
+
+impl MatchGame {
+    /// Returns the points of a player (index in `MatchConfig::players`): 1 for a win, 0.5 for a draw and 0 for a loss
+    pub fn score(&self, player: usize) -> f64 {
+        let winner = match &self.result {
+            Some(GameResult { winner: MakeMoveResultWinner::White, .. }) => self.white,
+            Some(GameResult { winner: MakeMoveResultWinner::Black, .. }) => 1 - self.white,
+            _ => return 0.5,
+        };
+        if winner == player { 1.0 } else { 0.0 }
+    }
+}
+
+/// Games of a match. The statistics are from the point of view of the first player.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchResult {
+    pub games: Vec<MatchGame>,
+}
+
+impl MatchResult {
+    /// Returns the number of wins, draws and losses of the first player
+    pub fn wins_draws_losses(&self) -> (u32, u32, u32) {
+        let mut result = (0, 0, 0);
+        for game in &self.games {
+            let score = game.score(0);
+            if score == 1.0 {
+                result.0 += 1;
+            } else if score == 0.0 {
+                result.2 += 1;
+            } else {
+                result.1 += 1;
+            }
+        }
+        result
+    }
+
+    /// Returns the average points per game of the first player, between 0 and 1
+    pub fn score(&self) -> f64 {
+        if self.games.is_empty() {
+            return 0.5;
+        }
+        self.games.iter().map(|game| game.score(0)).sum::<f64>() / self.games.len() as f64
+    }
+
+    /// Returns the estimated Elo difference between the first and the second player, or `None` if
+    /// there are no games or one of the players won all of them
+    pub fn elo_difference(&self) -> Option<f64> {
+        let elo = score_to_elo(self.score());
+        if self.games.is_empty() || elo.is_infinite() { None } else { Some(elo) }
+    }
+
+    /// Returns the margin of error of `elo_difference()`, with a 95% confidence. Can be infinite if there are few games.
+    pub fn elo_error_margin(&self) -> Option<f64> {
+        self.elo_difference()?;
+        let n = self.games.len() as f64;
+        let score = self.score();
+        let variance = self.games.iter().map(|game| (game.score(0) - score).powi(2)).sum::<f64>() / n;
+        // 1.96 standard errors on each side of the score
+        let margin = 1.96 * (variance / n).sqrt();
+        Some((score_to_elo(score + margin) - score_to_elo(score - margin)) / 2.0)
+    }
+
+    /// Returns the PGN of all the games, separated by an empty line
+    pub fn to_pgn(&self) -> String {
+        let games: Vec<&str> = self.games.iter().map(|game| game.pgn.trim_end()).collect();
+        games.join("\n\n") + "\n"
+    }
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    if score <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if score >= 1.0 {
+        return f64::INFINITY;
+    }
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+/// Plays a match between the two players of the configuration. The players alternate colors, and each game
+/// uses a new pair of engines so that the transposition tables don't carry over. `on_game` is called after
+/// each game, to report the progress.
+/// Returns an error if there are no openings, or one of them is invalid.
+pub fn run_match(config: &MatchConfig, mut on_game: impl FnMut(&MatchGame)) -> wrap_res!(MatchResult) {
+    err_assert!(!config.openings.is_empty(), "The match needs at least one opening");
+    let mut games = Vec::with_capacity(config.num_games as usize);
+    for round in 0..config.num_games as usize {
+        let opening = &config.openings[(round / 2) % config.openings.len()];
+        let game = play_game(config, opening, round % 2, round + 1)?;
+        on_game(&game);
+        games.push(game);
+    }
+    Ok(MatchResult { games })
+}
+
+// Plays a single game, where `white` is the index of the player with the white pieces
+fn play_game(config: &MatchConfig, opening: &GameState, white: usize, round: usize) -> wrap_res!(MatchGame) {
+    let mut engines = [Engine::default(), Engine::default()];
+    let mut start_result = None;
+    for (engine, player) in engines.iter_mut().zip(&config.players) {
+        if let Some(num_threads) = player.num_threads {
+            engine.set_num_threads(num_threads)?;
+        }
+        engine.set_eval_weights(player.eval_weights);
+        start_result = Some(engine.set_state(opening.clone())?);
+    }
+    let mut flag = start_result.map_or(MakeMoveResultFlag::Ok, |result| result.flag);
+    let mut winner = MakeMoveResultWinner::None;
+    let mut plies = 0;
+    while flag == MakeMoveResultFlag::Ok && plies < config.max_plies {
+        // Index of the player (and the engine) that moves
+        let mover = if engines[0].player_to_move() == 0 { white } else { 1 - white };
+        let mv = search(&mut engines[mover], config.players[mover].limit)?;
+        for engine in &mut engines {
+            let result = engine.make_move(&mv);
+            err_assert!(result.flag != MakeMoveResultFlag::IllegalMove, "The engine played an illegal move: {}", mv);
+            flag = result.flag;
+            winner = result.winner;
+        }
+        plies += 1;
+    }
+    let result = if flag == MakeMoveResultFlag::Ok { None } else { Some(GameResult { flag, winner }) };
+
+    let termination = if result.is_none() { "adjudication" } else { "normal" };
+    let headers: PgnHeaders = vec![
+        ("Event".to_string(), "Engine match".to_string()),
+        ("Round".to_string(), round.to_string()),
+        ("White".to_string(), config.players[white].name.clone()),
+        ("Black".to_string(), config.players[1 - white].name.clone()),
+        ("Termination".to_string(), termination.to_string()),
+    ];
+    let pgn = engines[0].to_pgn_with_headers(&headers);
+    Ok(MatchGame { white, result, plies, pgn })
+}
+
+fn search(engine: &mut Engine, limit: MatchLimit) -> wrap_res!(MoveInfo) {
+    let outcome = match limit {
+        MatchLimit::Depth(depth) => engine.get_best_move(depth)?,
+        MatchLimit::Time(seconds) => engine.get_best_move_timeout(seconds)?,
+        MatchLimit::Nodes(nodes) => engine.get_best_move_nodes(nodes)?,
+    };
+    match outcome {
+        SearchOutcome::BestMove { mv, .. } => Ok(mv),
+        SearchOutcome::GameOver { result } => err!("The game is already over ({:?})", result.flag),
+    }
+}
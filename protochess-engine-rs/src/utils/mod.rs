@@ -1,7 +1,15 @@
 mod board;
 pub mod perft;
-pub mod debug;
-pub mod notation;
+pub(crate) mod debug;
+pub(crate) mod notation;
+pub(crate) mod render;
+pub mod matchrunner;
+pub mod tuning;
+pub mod bench;
+#[cfg(feature = "fuzz")]
+pub mod fuzz;
+#[cfg(feature = "fuzz")]
+pub mod symmetry;
 
 pub use board::*;
 
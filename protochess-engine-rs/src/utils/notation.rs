@@ -1,8 +1,12 @@
 use crate::types::{BCoord, Move, MoveType};
-use crate::Position;
+use crate::{Position, PieceId, CaptureMode, MoveInfo};
 
 use super::from_index;
 
+pub const EN_PASSANT_SUFFIX: &str = " e.p.";
+// Notation for passing the turn, as used by most PGN readers for null moves
+pub const PASS_NOTATION: &str = "--";
+
 /// Converts an (x, y) location to chess rank-file notation
 /// Ex: `to_rank_file(0, 1)` = a2
 pub fn to_rank_file(x: BCoord, y: BCoord) -> String {
@@ -16,72 +20,154 @@ pub fn tuple_to_rank_file((x, y): (BCoord, BCoord)) -> String {
 /// Converts the move to user-friendly algebraic notation
 /// **IMPORTANT:** Call this **before** making the move
 pub fn get_algebraic_notation(pos: &mut Position, mv: Move, all_moves: &[Move]) -> String {
+    if mv.is_pass() {
+        return PASS_NOTATION.to_string();
+    }
     if mv.is_castling() {
-        return castling_notation(mv, all_moves);
+        let mut notation = castling_notation(mv, all_moves);
+        push_gate_suffix(pos, mv, &mut notation);
+        return notation;
+    }
+    if let Some(id) = mv.get_drop_piece() {
+        return drop_notation(pos, mv, id);
     }
+    // Build the notation in a single buffer, most moves fit in this capacity
+    let mut notation = String::with_capacity(16);
     let piece = pos.piece_at(mv.get_from()).unwrap();
-    let prefix = piece.get_notation_prefix();
-    let disamb = disambiguate(pos, mv, all_moves);
+    piece.push_notation_prefix(&mut notation);
+    // Pieces that capture by displacement also capture on a different square than the destination
+    let en_passant = mv.is_en_passant() && piece.get_movement().capture_mode == CaptureMode::Replace;
+    disambiguate(pos, mv, all_moves, &mut notation);
     
-    let capture = if mv.is_capture() { "x" } else { "" };
+    if mv.is_capture() {
+        notation.push('x');
+    }
     
-    let to = tuple_to_rank_file(from_index(mv.get_to()));
+    let (to_x, to_y) = from_index(mv.get_to());
+    push_rank_file(&mut notation, to_x, to_y);
     
-    let promo = {
-        if mv.is_promotion() {
-            let promo_piece = pos.lookup_piece(mv.get_promotion_piece().unwrap()).unwrap();
-            let promo = promo_piece.get_notation_prefix();
-            format!("={promo}")
-        } else {
-            "".to_string()
-        }
-    };
-    
-    let ep = if mv.is_en_passant() { " e.p." } else { "" };
+    if mv.is_promotion() {
+        let promo_piece = pos.lookup_piece(mv.get_promotion_piece().unwrap()).unwrap();
+        notation.push('=');
+        promo_piece.push_notation_prefix(&mut notation);
+    }
+    push_gate_suffix(pos, mv, &mut notation);
     
-    format!("{prefix}{disamb}{capture}{to}{promo}{ep}")
+    if en_passant {
+        notation.push_str(EN_PASSANT_SUFFIX);
+    }
+    notation
 }
-pub fn add_suffix(mv: String, suf: &str) -> String {
-    if mv.ends_with(" e.p.") {
-        mv.replace(" e.p.", format!("{suf} e.p.").as_str())
+pub fn add_suffix(mut mv: String, suf: &str) -> String {
+    if mv.ends_with(EN_PASSANT_SUFFIX) {
+        mv.insert_str(mv.len() - EN_PASSANT_SUFFIX.len(), suf);
     } else {
-        mv + suf
+        mv.push_str(suf);
     }
+    mv
 }
 
-fn castling_notation(mv: Move, all_moves: &[Move]) -> String {
-    let mut kingside_castles = 0;
-    let mut queenside_castles = 0;
-    for m in all_moves {
-        let ty = m.get_move_type();
-        if ty == MoveType::KingsideCastle {
-            kingside_castles += 1;
-        } else if ty == MoveType::QueensideCastle {
-            queenside_castles += 1;
+/// Converts a line of moves starting at `pos` (e.g. a principal variation) to algebraic notation, including the
+/// check and checkmate indicators. The line ends at the first illegal move.
+pub fn line_to_san(pos: &Position, line: &[MoveInfo]) -> Vec<String> {
+    let mut pos = pos.clone();
+    let mut notation = Vec::with_capacity(line.len());
+    for mv in line {
+        match pos.pub_make_move(mv).move_notation {
+            Some(san) => notation.push(san),
+            None => break,
         }
     }
+    notation
+}
+
+/// Removes the check, checkmate and annotation symbols (like "+", "#" or "!?") and the en passant
+/// suffix from a move in algebraic notation
+pub fn strip_san_suffixes(mv: &str) -> &str {
+    let mv = mv.trim_end_matches(['+', '#', '!', '?']);
+    mv.strip_suffix(EN_PASSANT_SUFFIX).unwrap_or(mv).trim_end_matches(['+', '#', '!', '?'])
+}
+
+// Same as to_rank_file(), but appends the result to an existing string
+fn push_rank_file(out: &mut String, x: BCoord, y: BCoord) {
+    out.push((b'a' + x) as char);
+    // Ranks can have 2 digits
+    let rank = y + 1;
+    if rank >= 10 {
+        out.push((b'0' + rank / 10) as char);
+    }
+    out.push((b'0' + rank % 10) as char);
+}
+
+// Drops are written as the piece prefix, followed by "@" and the destination (e.g. "N@f3")
+fn drop_notation(pos: &Position, mv: Move, id: PieceId) -> String {
+    let mut notation = String::with_capacity(8);
+    pos.lookup_piece(id).unwrap().push_notation_prefix(&mut notation);
+    // Pieces without a prefix (pawns) use their uppercase id, as in "P@e4"
+    if notation.is_empty() {
+        notation.extend(id.to_uppercase());
+    }
+    notation.push('@');
+    let (x, y) = from_index(mv.get_to());
+    push_rank_file(&mut notation, x, y);
+    notation
+}
+
+// Gating moves are written as the normal move, followed by "/" and the gated piece (e.g. "Nf3/H")
+fn push_gate_suffix(pos: &Position, mv: Move, out: &mut String) {
+    if let Some(id) = mv.get_gate_piece() {
+        out.push('/');
+        let len_before = out.len();
+        pos.lookup_piece(id).unwrap().push_notation_prefix(out);
+        // Same as in drops, pieces without a prefix use their uppercase id
+        if out.len() == len_before {
+            out.extend(id.to_uppercase());
+        }
+    }
+}
+
+fn castling_notation(mv: Move, all_moves: &[Move]) -> String {
+    let vertical = is_vertical_castling(mv);
+    // Gating variants of the same castling move don't need to be disambiguated
+    let same_castles = all_moves.iter()
+        .filter(|m| m.get_gate_piece().is_none())
+        .filter(|m| m.get_move_type() == mv.get_move_type() && is_vertical_castling(**m) == vertical)
+        .count();
     
-    let mv_rank = from_index(mv.get_from()).1;
-    if mv.get_move_type() == MoveType::KingsideCastle {
-        if kingside_castles > 1 { format!("O-O({})", mv_rank+1) }
-        else { "O-O".to_string() }
-    } else if mv.get_move_type() == MoveType::QueensideCastle {
-        if queenside_castles > 1 { format!("O-O-O({})", mv_rank+1) }
-        else { "O-O-O".to_string() }
-    } else {
-        panic!("Not a castling move");
+    // Vertical castling is written with the direction of the king: "O-O^" (north) or "O-Ov" (south)
+    let mut notation = match (mv.get_move_type(), vertical) {
+        (MoveType::KingsideCastle, false) => "O-O".to_string(),
+        (MoveType::QueensideCastle, false) => "O-O-O".to_string(),
+        (MoveType::KingsideCastle, true) => "O-O^".to_string(),
+        (MoveType::QueensideCastle, true) => "O-Ov".to_string(),
+        _ => panic!("Not a castling move"),
+    };
+    if same_castles > 1 {
+        // Disambiguate with the rank (or the file, for vertical castling) of the castling piece
+        let (mv_file, mv_rank) = from_index(mv.get_from());
+        if vertical {
+            notation.push_str(&format!("({})", (b'a' + mv_file) as char));
+        } else {
+            notation.push_str(&format!("({})", mv_rank+1));
+        }
     }
+    notation
+}
+
+// Returns true if the partner of a castling move is on the same file as the castling piece
+fn is_vertical_castling(mv: Move) -> bool {
+    mv.is_castling() && from_index(mv.get_from()).1 != from_index(mv.get_target()).1
 }
 
-/// Returns the necessary disambiguation for the move
-fn disambiguate(pos: &mut Position, mv: Move, all_moves: &[Move]) -> String {
+/// Appends the necessary disambiguation for the move
+fn disambiguate(pos: &mut Position, mv: Move, all_moves: &[Move], out: &mut String) {
     let from = from_index(mv.get_from());
     let mv_piece = pos.piece_at(mv.get_from()).unwrap().get_piece_id();
     let mut print_rank = false;
     let mut print_file = false;
     
     for m in all_moves {
-        if m.get_to() == mv.get_to() && m.get_from() != mv.get_from() 
+        if m.get_to() == mv.get_to() && m.get_from() != mv.get_from() && !m.is_drop() && !m.is_pass()
         && pos.piece_at(m.get_from()).unwrap().get_piece_id() == mv_piece {
             // Got a match, determine if we need to disambiguate rank or file
             let m_from = from_index(m.get_from());
@@ -93,12 +179,10 @@ fn disambiguate(pos: &mut Position, mv: Move, all_moves: &[Move]) -> String {
         }
     }
     
-    let mut result = String::new();
     if print_file {
-        result.push((b'a' + from.0) as char);
+        out.push((b'a' + from.0) as char);
     }
     if print_rank {
-        result.push((b'1' + from.1) as char);
+        out.push((b'1' + from.1) as char);
     }
-    result
 }
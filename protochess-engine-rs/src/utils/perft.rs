@@ -1,8 +1,11 @@
-use crate::{MoveGen, Position};
-use crate::types::Depth;
+use crate::{MoveGen, Position, MoveInfo, GameState, wrap_res, err, err_assert};
+use crate::move_generator::LegalityInfo;
+use crate::position::create::fen::FenData;
+use crate::position::create::position_factory::PositionFactory;
+use crate::types::{Depth, Move, ZobKey};
 
-use super::from_index;
-use super::notation::to_rank_file;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
 
 /// Returns the number of possible moves from a board position up to a given depth
 /// See <https://www.chessprogramming.org/Perft>
@@ -12,58 +15,304 @@ pub fn perft(position: &mut Position, depth: Depth) -> usize {
     if depth == 1 {
         return MoveGen::get_legal_moves(position).len();
     }
+    let info = LegalityInfo::new(position);
     for mv in MoveGen::get_pseudo_moves(position, true) {
-        if !MoveGen::make_move_if_legal(mv, position) {
+        if !MoveGen::make_move_if_legal_with(mv, position, &info) {
             continue;
         }
-        // Check for game over
-        if position.leader_is_captured() || position.piece_is_on_winning_square() {
-            position.unmake_move();
+        if game_continues(position) {
+            nodes += perft(position, depth - 1);
+        }
+        position.unmake_move();
+    }
+    nodes
+}
+
+/// Same as `perft()`, but the moves are made on a copy of the position (see `Position::clone_and_make()`)
+/// instead of being made and unmade. Used to benchmark and test `make_move()` and `unmake_move()`.
+pub fn perft_copy_make(position: &mut Position, depth: Depth) -> usize {
+    if depth == 1 {
+        return MoveGen::get_legal_moves(position).len();
+    }
+    let mut nodes = 0;
+    let info = LegalityInfo::new(position);
+    for mv in MoveGen::get_pseudo_moves(position, true) {
+        if !MoveGen::is_move_legal_with(mv, position, &info) {
             continue;
         }
-        if position.global_rules.checks_to_lose != 0 && MoveGen::in_check(position) && position.increment_num_checks() {
-            position.unmake_move();
+        let mut child = position.clone_and_make(mv);
+        if game_continues(&mut child) {
+            nodes += perft_copy_make(&mut child, depth - 1);
+        }
+    }
+    nodes
+}
+
+/// Same as `perft()`, but the subtrees of the legal moves are counted in parallel by `num_threads` threads
+/// (0 to use one thread per core). Without the `parallel` feature, the moves are counted sequentially.
+pub fn perft_parallel(position: &mut Position, depth: Depth, num_threads: usize) -> usize {
+    if depth <= 1 {
+        return perft(position, depth);
+    }
+    let moves = MoveGen::get_legal_moves(position);
+    let position: &Position = position;
+    let count = |mv: Move| {
+        let mut position = position.clone();
+        position.make_move(mv);
+        if game_continues(&mut position) { perft(&mut position, depth - 1) } else { 0 }
+    };
+    #[cfg(feature = "parallel")] {
+        match rayon::ThreadPoolBuilder::new().num_threads(num_threads).build() {
+            Ok(pool) => pool.install(|| moves.into_par_iter().map(count).sum()),
+            // If the threads can't be created, use the global thread pool
+            Err(_) => moves.into_par_iter().map(count).sum(),
+        }
+    }
+    #[cfg(not(feature = "parallel"))] {
+        let _ = num_threads;
+        moves.into_iter().map(count).sum()
+    }
+}
+
+// Number of buckets in the table used by `perft_tt()`, must be a power of 2
+const PERFT_TABLE_BUCKETS: usize = 1 << 18;
+
+#[derive(Clone, Copy)]
+struct PerftEntry {
+    key: ZobKey,
+    depth: Depth,
+    nodes: usize,
+}
+
+// Transposition table of `perft_tt()`. The first entry of each bucket keeps the deepest position (the most
+// expensive to recompute), the second one is always replaced.
+struct PerftTable {
+    buckets: Vec<[Option<PerftEntry>; 2]>,
+}
+
+impl PerftTable {
+    fn new() -> PerftTable {
+        PerftTable { buckets: vec![[None; 2]; PERFT_TABLE_BUCKETS] }
+    }
+
+    fn retrieve(&self, key: ZobKey, depth: Depth) -> Option<usize> {
+        let bucket = &self.buckets[key as usize & (PERFT_TABLE_BUCKETS - 1)];
+        bucket.iter().flatten()
+            .find(|entry| entry.key == key && entry.depth == depth)
+            .map(|entry| entry.nodes)
+    }
+
+    fn insert(&mut self, key: ZobKey, depth: Depth, nodes: usize) {
+        let bucket = &mut self.buckets[key as usize & (PERFT_TABLE_BUCKETS - 1)];
+        let slot = match bucket[0] {
+            Some(deepest) if deepest.depth > depth => 1,
+            _ => 0,
+        };
+        bucket[slot] = Some(PerftEntry { key, depth, nodes });
+    }
+}
+
+/// Same as `perft()`, but the number of nodes of each position is stored in a transposition table indexed by
+/// zobrist key, so that transpositions are only counted once.
+/// Falls back to `perft()` when the rules have a move limit, since the key doesn't include the turn number.
+pub fn perft_tt(position: &mut Position, depth: Depth) -> usize {
+    if position.turns_until_move_limit().is_some() {
+        return perft(position, depth);
+    }
+    perft_tt_impl(position, depth, &mut PerftTable::new())
+}
+
+fn perft_tt_impl(position: &mut Position, depth: Depth, table: &mut PerftTable) -> usize {
+    if depth == 1 {
+        return MoveGen::get_legal_moves(position).len();
+    }
+    let key = position.get_zobrist();
+    if let Some(nodes) = table.retrieve(key, depth) {
+        return nodes;
+    }
+    let mut nodes = 0;
+    let info = LegalityInfo::new(position);
+    for mv in MoveGen::get_pseudo_moves(position, true) {
+        if !MoveGen::make_move_if_legal_with(mv, position, &info) {
             continue;
         }
-        nodes += perft(position, depth - 1);
+        if game_continues(position) {
+            nodes += perft_tt_impl(position, depth - 1, table);
+        }
         position.unmake_move();
     }
+    table.insert(key, depth, nodes);
     nodes
 }
 
+/// Same as `perft_tt()`
+pub fn perft_hashed(position: &mut Position, depth: Depth) -> usize {
+    perft_tt(position, depth)
+}
+
+// Called after making a move: returns false if the game is over, so the position must not be expanded further
+pub(crate) fn game_continues(position: &mut Position) -> bool {
+    if position.leader_is_captured() || position.piece_is_on_winning_square() || position.win_condition_met().is_some() ||
+        position.capture_limit_reached().is_some() || position.move_limit_reached()
+    {
+        return false;
+    }
+    !(position.global_rules.checks_to_lose != 0 && MoveGen::in_check(position) && position.increment_num_checks())
+}
+
+/// Number of nodes after each legal move of a position, sorted by move (see `divide()`)
+pub type Divide = Vec<(String, usize)>;
+
+/// Returns each legal move (in the format of `MoveInfo::to_string()`) and the number of nodes after it, up to
+/// a given depth. The counts add up to `perft(position, depth)`. Moves that end the game count as 0 nodes, unless
+/// `depth` is 1. With the `parallel` feature, the moves are counted in parallel.
+pub fn divide(position: &mut Position, depth: Depth) -> Divide {
+    let count = |mut position: Position, mv: Move| {
+        let nodes = if depth == 1 {
+            1
+        } else {
+            position.make_move(mv);
+            if game_continues(&mut position) { perft(&mut position, depth - 1) } else { 0 }
+        };
+        (MoveInfo::from(mv).to_string(), nodes)
+    };
+    let moves = MoveGen::get_legal_moves(position);
+    #[cfg(feature = "parallel")]
+    let mut result: Vec<_> = moves.into_par_iter().map(|mv| count(position.clone(), mv)).collect();
+    #[cfg(not(feature = "parallel"))]
+    let mut result: Vec<_> = moves.into_iter().map(|mv| count(position.clone(), mv)).collect();
+    result.sort();
+    result
+}
+
 /// Like perft, but prints the moves at the first ply
 pub fn perft_divide(position: &mut Position, depth: Depth) -> usize {
     let mut nodes = 0;
+    for (mv, count) in divide(position, depth) {
+        println!("{mv}: {count}");
+        nodes += count;
+    }
+    nodes
+}
 
-    let mut printing = Vec::new();
-    for mv in MoveGen::get_pseudo_moves(position, true) {
-        if !MoveGen::is_move_legal(mv, position) {
+
+/// Position of a perft suite and the expected number of nodes at some depths
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftSuiteEntry {
+    /// FEN of the position, optionally followed by the name of the variant (see `GameState::from_debug_fen()`)
+    pub fen: String,
+    /// Depth and expected number of nodes, sorted by depth
+    pub expected: Vec<(Depth, usize)>,
+}
+
+/// Entry of a perft suite whose result doesn't match the expected one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftMismatch {
+    pub fen: String,
+    /// Lowest depth at which the number of nodes is wrong
+    pub depth: Depth,
+    pub expected: usize,
+    pub found: usize,
+    /// Result of `divide()` at `depth`, to be compared with the divide of another engine
+    pub divide: Divide,
+}
+
+/// First position where the move generator disagrees with a reference implementation (see `bisect()`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PerftDivergence {
+    /// Moves that lead from the initial position to the position where the move generators disagree
+    pub line: Vec<String>,
+    /// FEN of that position
+    pub fen: String,
+    /// Legal moves of the reference that the move generator doesn't generate
+    pub missing: Vec<String>,
+    /// Moves generated by the move generator that are not legal for the reference
+    pub extra: Vec<String>,
+}
+
+/// Parses a perft suite in EPD format: one position per line, followed by the expected number of nodes at
+/// each depth (`<FEN> ;D1 20 ;D2 400 ;D3 8902`). Empty lines and lines starting with `#` are ignored.
+pub fn parse_perft_suite(suite: &str) -> wrap_res!(Vec<PerftSuiteEntry>) {
+    let mut entries = Vec::new();
+    for (line_num, line) in suite.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
             continue;
         }
+        let mut parts = line.split(';');
+        let fen = parts.next().unwrap_or_default().trim().to_string();
+        err_assert!(!fen.is_empty(), "Missing FEN in line {} of the perft suite", line_num + 1);
+        let mut expected = Vec::new();
+        for part in parts {
+            let parsed = part.trim().strip_prefix('D')
+                .and_then(|s| s.split_once(char::is_whitespace))
+                .and_then(|(depth, nodes)| Some((depth.parse::<Depth>().ok()?, nodes.trim().parse::<usize>().ok()?)));
+            match parsed {
+                Some((depth, nodes)) if depth > 0 => expected.push((depth, nodes)),
+                _ => err!("Invalid depth '{}' in line {} of the perft suite", part.trim(), line_num + 1),
+            }
+        }
+        expected.sort_unstable();
+        entries.push(PerftSuiteEntry { fen, expected });
+    }
+    Ok(entries)
+}
 
-        let (x,y) = from_index(mv.get_from());
-        let (x2,y2) = from_index(mv.get_to());
-        let promo = mv.get_promotion_piece();
-        let promo_str = {
-            if let Some(p) = promo { p.to_string() }
-            else { "".to_string() }
-        };
-        if depth == 1 {
-            nodes += 1;
-            printing.push(format!("{}{}{}: 1", to_rank_file(x,y), to_rank_file(x2,y2), promo_str));
-        } else {
-            position.make_move(mv);
-            let plus = perft(position, depth - 1);
-            nodes += plus;
-            position.unmake_move();
-            //Print nodes
-            printing.push(format!("{}{}{}: {}", to_rank_file(x,y), to_rank_file(x2,y2), promo_str, plus));
+/// Runs the positions of a perft suite up to `max_depth`, and returns the ones whose number of nodes doesn't
+/// match. With the `parallel` feature, the positions are run in parallel.
+/// Returns an error if one of the FENs is invalid.
+pub fn run_perft_suite(entries: &[PerftSuiteEntry], max_depth: Depth) -> wrap_res!(Vec<PerftMismatch>) {
+    let run = |entry: &PerftSuiteEntry| -> wrap_res!(Option<PerftMismatch>) {
+        let mut position = position_from_fen(&entry.fen)?;
+        for &(depth, expected) in entry.expected.iter().filter(|(depth, _)| *depth <= max_depth) {
+            let found = perft(&mut position, depth);
+            if found != expected {
+                let divide = divide(&mut position, depth);
+                return Ok(Some(PerftMismatch { fen: entry.fen.clone(), depth, expected, found, divide }));
+            }
         }
+        Ok(None)
+    };
+    #[cfg(feature = "parallel")]
+    let results: wrap_res!(Vec<_>) = entries.par_iter().map(run).collect();
+    #[cfg(not(feature = "parallel"))]
+    let results: wrap_res!(Vec<_>) = entries.iter().map(run).collect();
+    Ok(results?.into_iter().flatten().collect())
+}
+
+/// Finds the first position where the move generator disagrees with a reference implementation, by comparing the
+/// divides and following the first move whose number of nodes is different.
+/// `reference` returns the divide of the position reached after playing a line of moves from `position`.
+/// Returns `None` if the number of nodes is the same for all the moves.
+pub fn bisect(position: &Position, depth: Depth, reference: &mut dyn FnMut(&[String], Depth) -> Divide) -> Option<PerftDivergence> {
+    let mut position = position.clone();
+    let mut line = Vec::new();
+    // If the game is over, the move generator doesn't have any moves
+    let mut game_over = false;
+    for depth in (1..=depth).rev() {
+        let found = if game_over { Vec::new() } else { divide(&mut position, depth) };
+        let expected = reference(&line, depth);
+        let missing: Vec<String> = expected.iter().map(|(mv, _)| mv).filter(|mv| !found.iter().any(|(m, _)| m == *mv)).cloned().collect();
+        let extra: Vec<String> = found.iter().map(|(mv, _)| mv).filter(|mv| !expected.iter().any(|(m, _)| m == *mv)).cloned().collect();
+        if !missing.is_empty() || !extra.is_empty() {
+            let fen = FenData::from(&position).to_string();
+            return Some(PerftDivergence { line, fen, missing, extra });
+        }
+        // Same moves, follow the first one with a different number of nodes
+        let (mv, _) = found.iter().find(|mv| !expected.contains(mv))?;
+        let target = MoveGen::get_legal_moves(&mut position).into_iter().find(|&m| MoveInfo::from(m).to_string() == *mv)?;
+        position.make_move(target);
+        game_over = !game_continues(&mut position);
+        line.push(mv.clone());
     }
-    printing.sort();
-    for s in printing {
-        println!("{s}");
+    None
+}
+
+// Creates a position from a FEN that can contain the name of the variant
+pub(crate) fn position_from_fen(fen: &str) -> wrap_res!(Position) {
+    let mut factory = PositionFactory::default();
+    match factory.set_state(GameState::from_debug_fen(fen), None)? {
+        Some(position) => Ok(position),
+        None => err!("Could not create a position from the FEN '{}'", fen),
     }
-    nodes
 }
-  
\ No newline at end of file
@@ -0,0 +1,172 @@
+// Diagrams of a position, to share positions or generate puzzles
+
+use std::fmt::Write;
+
+use crate::{Position, Piece};
+use crate::types::BCoord;
+
+use super::to_index;
+
+// Colors of the SVG diagram
+const LIGHT_SQUARE: &str = "#f0d9b5";
+const DARK_SQUARE: &str = "#b58863";
+const WALL_SQUARE: &str = "#555555";
+
+/// Options of `to_svg()` and `to_text()`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Draw the board from the point of view of black
+    pub flipped: bool,
+    /// Show the letters of the files and the numbers of the ranks
+    pub coordinates: bool,
+    /// Size of a square of the SVG diagram, in pixels
+    pub square_size: u32,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions { flipped: false, coordinates: true, square_size: 45 }
+    }
+}
+
+/// Draws the position as text, one rank per line. Pieces are drawn with the glyphs of their `PieceDisplay`
+/// (e.g. `♞`), or with their id if they don't have one. Empty squares are drawn as `.` and walls as `#`.
+/// Below the board, lists the pieces in hand and the player to move.
+pub fn to_text(position: &Position, options: &RenderOptions) -> String {
+    let (files, ranks) = drawing_order(position, options.flipped);
+    let label_width = position.dimensions.height.to_string().len();
+    let mut out = String::new();
+    for &y in &ranks {
+        if options.coordinates {
+            write!(out, "{:>label_width$} ", y + 1).unwrap();
+        }
+        let squares: Vec<String> = files.iter().map(|&x| match position.piece_at(to_index(x, y)) {
+            Some(piece) => glyph(piece),
+            None if position.dimensions.in_bounds(x, y) => ".".to_string(),
+            None => "#".to_string(),
+        }).collect();
+        out.push_str(&squares.join(" "));
+        out.push('\n');
+    }
+    if options.coordinates {
+        let labels: Vec<String> = files.iter().map(|&x| file_label(x).to_string()).collect();
+        writeln!(out, "{:label_width$} {}", "", labels.join(" ")).unwrap();
+    }
+    for (player, name) in [(0, "White"), (1, "Black")] {
+        let hand: Vec<String> = position.pieces[player].iter().filter(|p| p.get_num_in_hand() > 0)
+            .map(|p| if p.get_num_in_hand() == 1 { glyph(p) } else { format!("{}x{}", glyph(p), p.get_num_in_hand()) })
+            .collect();
+        if !hand.is_empty() {
+            writeln!(out, "{name} in hand: {}", hand.join(" ")).unwrap();
+        }
+    }
+    let player = if position.whos_turn == 0 { "White" } else { "Black" };
+    write!(out, "{player} to move").unwrap();
+    out
+}
+
+/// Draws the board as an SVG image. Pieces are drawn as text with the glyphs of their `PieceDisplay`, or with their id
+/// if they don't have one. Each piece has the classes `piece`, `white` or `black`, and its `svg_hint`, so that the
+/// images can be replaced with CSS. Walls are drawn as gray squares. The pieces in hand are not drawn.
+pub fn to_svg(position: &Position, options: &RenderOptions) -> String {
+    let (files, ranks) = drawing_order(position, options.flipped);
+    let size = options.square_size;
+    // Space for the coordinates, on the left and below the board
+    let margin = if options.coordinates { size / 2 } else { 0 };
+    let width = margin + size * files.len() as u32;
+    let height = margin + size * ranks.len() as u32;
+    let mut out = String::new();
+    writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">").unwrap();
+    for (row, &y) in ranks.iter().enumerate() {
+        for (col, &x) in files.iter().enumerate() {
+            let (left, top) = (margin + col as u32 * size, row as u32 * size);
+            let color = if !position.dimensions.in_bounds(x, y) {
+                WALL_SQUARE
+            } else if (x + y) % 2 == 0 {
+                DARK_SQUARE
+            } else {
+                LIGHT_SQUARE
+            };
+            writeln!(out, "<rect x=\"{left}\" y=\"{top}\" width=\"{size}\" height=\"{size}\" fill=\"{color}\"/>").unwrap();
+            if let Some(piece) = position.piece_at(to_index(x, y)) {
+                write_svg_piece(&mut out, piece, left + size / 2, top + size / 2, size);
+            }
+        }
+    }
+    if options.coordinates {
+        let font_size = size * 3 / 10;
+        for (row, &y) in ranks.iter().enumerate() {
+            let top = row as u32 * size + size / 2;
+            writeln!(out, "<text x=\"{}\" y=\"{top}\" font-size=\"{font_size}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                margin / 2, y + 1).unwrap();
+        }
+        for (col, &x) in files.iter().enumerate() {
+            let left = margin + col as u32 * size + size / 2;
+            writeln!(out, "<text x=\"{left}\" y=\"{}\" font-size=\"{font_size}\" text-anchor=\"middle\" dominant-baseline=\"central\">{}</text>",
+                height - margin / 2, escape_xml(&file_label(x).to_string())).unwrap();
+        }
+    }
+    out.push_str("</svg>\n");
+    out
+}
+
+// Files and ranks in the order in which they are drawn (left to right and top to bottom)
+fn drawing_order(position: &Position, flipped: bool) -> (Vec<BCoord>, Vec<BCoord>) {
+    let mut files: Vec<BCoord> = (0..position.dimensions.width).collect();
+    let mut ranks: Vec<BCoord> = (0..position.dimensions.height).rev().collect();
+    if flipped {
+        files.reverse();
+        ranks.reverse();
+    }
+    (files, ranks)
+}
+
+// Same letters as in the algebraic notation
+fn file_label(x: BCoord) -> char {
+    (b'a' + x) as char
+}
+
+// Glyph of the piece for its player, or its id if its definition doesn't have one
+fn glyph(piece: &Piece) -> String {
+    custom_glyph(piece).unwrap_or_else(|| piece.get_piece_id().to_string())
+}
+
+fn custom_glyph(piece: &Piece) -> Option<String> {
+    let display = piece.get_movement().display.as_ref()?;
+    display.glyphs[piece.get_player() as usize].clone()
+}
+
+// Writes the piece centered on (x, y)
+fn write_svg_piece(out: &mut String, piece: &Piece, x: u32, y: u32, size: u32) {
+    let player = if piece.get_player() == 0 { "white" } else { "black" };
+    let mut class = format!("piece {player}");
+    if let Some(hint) = piece.get_movement().display.as_ref().and_then(|d| d.svg_hint.as_ref()) {
+        class.push(' ');
+        class.push_str(hint);
+    }
+    // Unicode chess glyphs already have the color of the player, ids need a fill and an outline
+    let (text, font_size, style) = match custom_glyph(piece) {
+        Some(glyph) => (glyph, size * 4 / 5, "fill=\"#000000\"".to_string()),
+        None => {
+            let fill = if piece.get_player() == 0 { "#ffffff" } else { "#000000" };
+            (piece.get_piece_id().to_string(), size * 3 / 5, format!("fill=\"{fill}\" stroke=\"#000000\" font-weight=\"bold\""))
+        },
+    };
+    writeln!(out, "<text x=\"{x}\" y=\"{y}\" font-size=\"{font_size}\" text-anchor=\"middle\" dominant-baseline=\"central\" class=\"{}\" {style}>{}</text>",
+        escape_xml(&class), escape_xml(&text)).unwrap();
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
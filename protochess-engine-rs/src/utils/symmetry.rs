@@ -0,0 +1,143 @@
+// Symmetry checks of the evaluation and the search: a position and the same position with the colors swapped
+// must be equivalent for the player to move
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::{Engine, GameState, MoveGen, MoveInfo, PieceId, VariantGenerator, VariantConstraints, wrap_res, err, err_assert};
+use crate::position::create::fen::FenData;
+use crate::position::create::position_factory::PositionFactory;
+use crate::types::{Centipawns, Depth};
+
+use super::perft::game_continues;
+
+/// Returns the current position of the game state with the colors swapped (see `FenData::color_flipped()`),
+/// as the initial FEN of a new game state of the same variant. Only meaningful for variants where both players
+/// have the same pieces and rules.
+pub fn color_flipped(state: &GameState) -> wrap_res!(GameState) {
+    let position = PositionFactory::default().set_state(state.clone(), None)?.unwrap();
+    let fen = FenData::from(&position).color_flipped(flip_id_fn(state));
+    Ok(GameState {
+        initial_state: state.initial_state.clone(),
+        initial_fen: Some(fen.to_string()),
+        ..Default::default()
+    })
+}
+
+/// Returns the equivalent move in the position returned by `color_flipped()`
+pub fn flip_move(state: &GameState, mv: &MoveInfo) -> MoveInfo {
+    let height = state.initial_state.board_height;
+    MoveInfo {
+        from: (mv.from.0, height - 1 - mv.from.1),
+        to: (mv.to.0, height - 1 - mv.to.1),
+        promotion: mv.promotion.map(flip_id_fn(state)),
+    }
+}
+
+/// Returns an error if the static evaluation of the position (for the player to move) is not the same as the
+/// evaluation of the color-flipped position. From the point of view of White, the scores are negated.
+pub fn check_eval_symmetry(state: &GameState) -> wrap_res!() {
+    let (mut engine, mut flipped_engine) = setup_flipped(state)?;
+    let (score, flipped_score) = (engine.evaluate_position(), flipped_engine.evaluate_position());
+    err_assert!(score == flipped_score, "Asymmetric evaluation: {} for '{}', but {} for the color-flipped '{}'",
+        score, engine.get_state_diff().fen, flipped_score, flipped_engine.get_state_diff().fen);
+    Ok(())
+}
+
+/// Returns an error if a deterministic search of the given depth doesn't return the same score for the color-flipped
+/// position, or if the mirrored best move is not legal in it. The best moves can be different if several moves have
+/// the same score, since the moves are generated in a different order. Does nothing if the game is over.
+pub fn check_search_symmetry(state: &GameState, depth: Depth) -> wrap_res!() {
+    let (mut engine, mut flipped_engine) = setup_flipped(state)?;
+    if engine.game_result().is_some() || !engine.has_any_legal_move() {
+        return Ok(());
+    }
+    let (mv, score, _) = best_move(&mut engine, depth)?;
+    let (flipped_mv, flipped_score, _) = best_move(&mut flipped_engine, depth)?;
+    err_assert!(score == flipped_score,
+        "Asymmetric search at depth {}: {} ({}) for '{}', but {} ({}) for the color-flipped '{}'",
+        depth, mv, score, engine.get_state_diff().fen, flipped_mv, flipped_score, flipped_engine.get_state_diff().fen);
+    err_assert!(flipped_engine.would_be_legal(&flip_move(state, &mv)),
+        "The mirrored move of {} is not legal in the color-flipped '{}'", mv, flipped_engine.get_state_diff().fen);
+    Ok(())
+}
+
+/// Returns an error if the best move found by a search of the given depth is not legal. Does nothing if the
+/// game is over.
+pub fn check_search_legality(state: &GameState, depth: Depth) -> wrap_res!() {
+    let mut engine = Engine::default();
+    engine.set_deterministic(true);
+    let _result = engine.set_state(state.clone())?;
+    if engine.game_result().is_some() || !engine.has_any_legal_move() {
+        return Ok(());
+    }
+    let (mv, _score, _depth) = best_move(&mut engine, depth)?;
+    if !engine.would_be_legal(&mv) {
+        err!("The search returned the illegal move {} in '{}'", mv, engine.get_state_diff().fen);
+    }
+    Ok(())
+}
+
+/// Returns `count` positions of random variants (see `VariantGenerator`), reached by playing some random legal
+/// moves from the starting position. The positions only depend on the seed.
+pub fn random_positions(seed: u64, count: usize) -> Vec<GameState> {
+    let constraints = VariantConstraints { require_leader: false, ..Default::default() };
+    let mut variants = VariantGenerator::new(constraints, seed).unwrap();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut positions = Vec::with_capacity(count);
+    while positions.len() < count {
+        let mut state = match variants.generate() {
+            Ok(state) => state,
+            Err(_) => continue,
+        };
+        let mut position = PositionFactory::default().set_state(state.clone(), None).unwrap().unwrap();
+        for _ in 0..rng.gen_range(0, 30) {
+            let moves = MoveGen::get_legal_moves(&mut position);
+            if moves.is_empty() {
+                break;
+            }
+            let mv = moves[rng.gen_range(0, moves.len())];
+            state.move_history.push(MoveInfo::from(mv));
+            position.make_move(mv);
+            if !game_continues(&mut position) {
+                break;
+            }
+        }
+        positions.push(state);
+    }
+    positions
+}
+
+// Best move, score and depth of a search of the given depth, in a position where the game is not over
+fn best_move(engine: &mut Engine, depth: Depth) -> wrap_res!(MoveInfo, Centipawns, Depth) {
+    engine.get_best_move(depth)?.into_move().ok_or_else(|| "The game is already over".to_string())
+}
+
+// Engines set up with the game state and with its color-flipped version
+fn setup_flipped(state: &GameState) -> wrap_res!(Engine, Engine) {
+    let mut engine = Engine::default();
+    let mut flipped_engine = Engine::default();
+    engine.set_deterministic(true);
+    flipped_engine.set_deterministic(true);
+    let _result = engine.set_state(state.clone())?;
+    let _result = flipped_engine.set_state(color_flipped(state)?)?;
+    Ok((engine, flipped_engine))
+}
+
+// Maps the id of a piece to the id of the same piece for the other player. Pieces that only have an id
+// for one player (like pawns, which have a different definition for each player) use the opposite case.
+fn flip_id_fn(state: &GameState) -> impl Fn(PieceId) -> PieceId {
+    let pairs: Vec<(PieceId, PieceId)> = state.initial_state.piece_types.iter()
+        .filter_map(|piece| match piece.ids {
+            [Some(white), Some(black)] => Some((white, black)),
+            _ => None,
+        })
+        .collect();
+    move |id| {
+        for &(white, black) in &pairs {
+            if id == white { return black; }
+            if id == black { return white; }
+        }
+        if id.is_uppercase() { id.to_ascii_lowercase() } else { id.to_ascii_uppercase() }
+    }
+}
@@ -0,0 +1,240 @@
+// Tuning of the evaluation weights with a corpus of positions with known results
+// See <https://www.chessprogramming.org/Texel%27s_Tuning_Method>
+
+use crate::{PieceDefinition, wrap_res, err, err_assert};
+use crate::piece::{MaterialWeights, compute_material_score_with};
+use crate::searcher::eval::{EvalWeights, evaluate_breakdown, evaluate_breakdown_with_weights};
+use crate::types::{BDimensions, Centipawns};
+
+use super::perft::position_from_fen;
+
+/// Position of a tuning corpus, and the result of the game in which it was played
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningEntry {
+    /// FEN of the position, optionally followed by the name of the variant (see `GameState::from_debug_fen()`)
+    pub fen: String,
+    /// 1 if white won, 0.5 if the game was a draw, 0 if black won
+    pub result: f64,
+}
+
+/// Parameters of the evaluation that are optimized by `Tuner::tune()`, and used by the searches of an engine
+/// with `Engine::set_eval_weights()`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct TuningParams {
+    /// Weights of the material score of each piece, from its movement pattern
+    pub material: MaterialWeights,
+    /// Weights of the pawn structure, king safety and mobility terms
+    pub eval: EvalWeights,
+}
+
+/// Parses a tuning corpus: one position per line, followed by the result of the game (`<FEN> ;1-0`).
+/// The result can be `1-0`, `0-1`, `1/2-1/2` or a number between 0 and 1 (the score of white).
+/// Empty lines and lines starting with `#` are ignored.
+pub fn parse_tuning_corpus(corpus: &str) -> wrap_res!(Vec<TuningEntry>) {
+    let mut entries = Vec::new();
+    for (line_num, line) in corpus.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (fen, result) = match line.rsplit_once(';') {
+            Some((fen, result)) => (fen.trim(), result.trim()),
+            None => err!("Missing the result in line {} of the tuning corpus", line_num + 1),
+        };
+        err_assert!(!fen.is_empty(), "Missing FEN in line {} of the tuning corpus", line_num + 1);
+        let result = match result {
+            "1-0" => 1.0,
+            "0-1" => 0.0,
+            "1/2-1/2" => 0.5,
+            _ => match result.parse::<f64>() {
+                Ok(score) if (0.0..=1.0).contains(&score) => score,
+                _ => err!("Invalid result '{}' in line {} of the tuning corpus", result, line_num + 1),
+            },
+        };
+        entries.push(TuningEntry { fen: fen.to_string(), result });
+    }
+    Ok(entries)
+}
+
+// Number of values in `to_vector()`
+const NUM_PARAMS: usize = 13;
+// Amount by which each value of `to_vector()` is changed in each step of the local search
+const STEPS: [f64; NUM_PARAMS] = [0.5, 0.5, 10.0, 10.0, 2.0, 2.0, 5.0, 1.0, 1.0, 5.0, 1.0, 1.0, 2.0];
+// Index in `to_vector()` of the first value of `EvalWeights`
+const FIRST_EVAL_WEIGHT: usize = 7;
+// Weight used to measure the terms of `EvalWeights`, large enough to make the rounding errors negligible
+const UNIT_WEIGHT: Centipawns = 1000;
+
+fn to_vector(params: &TuningParams) -> [f64; NUM_PARAMS] {
+    let (m, e) = (&params.material, &params.eval);
+    [
+        m.attack_mul as f64, m.translate_mul as f64, m.color_bound_penalty as f64, m.one_direction_penalty as f64,
+        m.jump as f64, m.sliding_delta as f64, m.promotion as f64,
+        e.doubled_pawn as f64, e.isolated_pawn as f64, e.passed_pawn as f64, e.king_attacker as f64,
+        e.pawn_shield as f64, e.mobility as f64,
+    ]
+}
+
+fn from_vector(v: &[f64; NUM_PARAMS]) -> TuningParams {
+    let int = |x: f64| x.round() as Centipawns;
+    TuningParams {
+        material: MaterialWeights {
+            attack_mul: v[0] as f32,
+            translate_mul: v[1] as f32,
+            color_bound_penalty: int(v[2]),
+            one_direction_penalty: int(v[3]),
+            jump: int(v[4]),
+            sliding_delta: int(v[5]),
+            promotion: int(v[6]),
+        },
+        eval: EvalWeights {
+            doubled_pawn: int(v[7]),
+            isolated_pawn: int(v[8]),
+            passed_pawn: int(v[9]),
+            king_attacker: int(v[10]),
+            pawn_shield: int(v[11]),
+            mobility: int(v[12]),
+        },
+    }
+}
+
+// Terms of the evaluation of a position that don't depend on the parameters, from the point of view of white
+#[derive(Debug, Clone)]
+struct PositionFeatures {
+    // Index in `Tuner::pieces` and number of pieces on the board and in hand (negative for black)
+    material: Vec<(usize, f64)>,
+    // Positional score (piece-square tables)
+    positional: f64,
+    // Value of each term of `EvalWeights` with a weight of 1, in the same order as `to_vector()`
+    terms: [f64; 6],
+    // Score that doesn't depend on the parameters (number of checks and variant-specific terms)
+    constant: f64,
+    result: f64,
+}
+
+/// Optimizes the evaluation parameters to predict the results of a corpus of positions, by minimizing the
+/// error between the results and the static evaluation (mapped to a winning probability with a sigmoid).
+/// Use `Engine::set_eval_weights()` to search with the tuned parameters. The endgame detection of the tuner always
+/// uses the default material scores.
+#[derive(Debug, Clone)]
+pub struct Tuner {
+    // Piece types of all the positions, and the dimensions of their boards
+    pieces: Vec<(PieceDefinition, BDimensions)>,
+    positions: Vec<PositionFeatures>,
+}
+
+impl Tuner {
+    /// Evaluates the positions of the corpus. Returns an error if one of the FENs is invalid.
+    pub fn new(entries: &[TuningEntry]) -> wrap_res!(Tuner) {
+        let mut pieces: Vec<(PieceDefinition, BDimensions)> = Vec::new();
+        let mut positions = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let position = position_from_fen(&entry.fen)?;
+            // The breakdown is from the point of view of the player to move
+            let to_white = if position.whos_turn == 0 { 1.0 } else { -1.0 };
+            let invert = if position.global_rules.invert_win_conditions { -1.0 } else { 1.0 };
+            let mut material = Vec::new();
+            for ps in &position.pieces {
+                let sign = if ps.get_player_num() == 0 { invert } else { -invert };
+                for piece in ps.iter() {
+                    let count = piece.get_num_pieces() + piece.get_num_in_hand();
+                    if count == 0 {
+                        continue;
+                    }
+                    let key = (piece.get_movement().clone(), position.dimensions.clone());
+                    let index = match pieces.iter().position(|p| *p == key) {
+                        Some(index) => index,
+                        None => {
+                            pieces.push(key);
+                            pieces.len() - 1
+                        },
+                    };
+                    material.push((index, sign * count as f64));
+                }
+            }
+            let breakdown = evaluate_breakdown(&position);
+            let mut terms = [0.0; 6];
+            for (i, term) in terms.iter_mut().enumerate() {
+                // Only the weight of this term is not 0
+                let mut v = [0.0; NUM_PARAMS];
+                v[FIRST_EVAL_WEIGHT + i] = UNIT_WEIGHT as f64;
+                let unit = evaluate_breakdown_with_weights(&position, &from_vector(&v).eval);
+                let score = unit.pawn_structure + unit.king_safety + unit.mobility;
+                *term = to_white * score as f64 / UNIT_WEIGHT as f64;
+            }
+            positions.push(PositionFeatures {
+                material,
+                positional: to_white * breakdown.positional as f64,
+                terms,
+                constant: to_white * (breakdown.checks + breakdown.variant) as f64,
+                result: entry.result,
+            });
+        }
+        Ok(Tuner { pieces, positions })
+    }
+
+    /// Returns the mean squared error between the results of the corpus and the winning probability predicted
+    /// by the evaluation, `1 / (1 + 10^(-k * score / 400))`
+    pub fn error(&self, params: &TuningParams, k: f64) -> f64 {
+        if self.positions.is_empty() {
+            return 0.0;
+        }
+        let material_scores: Vec<f64> = self.pieces.iter()
+            .map(|(definition, dims)| compute_material_score_with(definition, dims, &params.material) as f64)
+            .collect();
+        let v = to_vector(params);
+        let total: f64 = self.positions.iter().map(|features| {
+            let material: f64 = features.material.iter().map(|&(index, count)| material_scores[index] * count).sum();
+            let terms: f64 = features.terms.iter().zip(&v[FIRST_EVAL_WEIGHT..]).map(|(term, weight)| term * weight).sum();
+            let score = material + features.positional + terms + features.constant;
+            let prediction = 1.0 / (1.0 + 10f64.powf(-k * score / 400.0));
+            (features.result - prediction).powi(2)
+        }).sum();
+        total / self.positions.len() as f64
+    }
+
+    /// Returns the scaling constant `k` of `error()` that minimizes the error with the given parameters
+    pub fn fit_scaling_constant(&self, params: &TuningParams) -> f64 {
+        // Ternary search, the error is unimodal in k
+        let (mut low, mut high) = (0.0, 10.0);
+        for _ in 0..100 {
+            let m1 = low + (high - low) / 3.0;
+            let m2 = high - (high - low) / 3.0;
+            if self.error(params, m1) < self.error(params, m2) {
+                high = m2;
+            } else {
+                low = m1;
+            }
+        }
+        (low + high) / 2.0
+    }
+
+    /// Optimizes the parameters with a local search, starting from `initial`: in each iteration, each parameter is
+    /// increased or decreased by a small step if that reduces the error. The scaling constant is fitted once, with
+    /// the initial parameters. Stops after `max_iterations`, or when no step reduces the error.
+    pub fn tune(&self, initial: &TuningParams, max_iterations: u32) -> TuningParams {
+        let k = self.fit_scaling_constant(initial);
+        let mut best = to_vector(initial);
+        let mut best_error = self.error(initial, k);
+        for _ in 0..max_iterations {
+            let mut improved = false;
+            for i in 0..NUM_PARAMS {
+                for direction in [1.0, -1.0] {
+                    let mut candidate = best;
+                    candidate[i] += direction * STEPS[i];
+                    let error = self.error(&from_vector(&candidate), k);
+                    if error < best_error {
+                        best = candidate;
+                        best_error = error;
+                        improved = true;
+                        break;
+                    }
+                }
+            }
+            if !improved {
+                break;
+            }
+        }
+        from_vector(&best)
+    }
+}
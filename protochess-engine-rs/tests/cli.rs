@@ -0,0 +1,102 @@
+#[cfg(test)]
+mod cli_test {
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+    use std::process::{Command, Output};
+
+    use protochess_engine_rs::{GameState, MoveInfo};
+
+    #[test]
+    fn help() {
+        for args in [&[][..], &["help"], &["--help"], &["-h"]] {
+            let output = run(args);
+            assert!(output.status.success(), "{:?}", args);
+            assert!(stdout(&output).starts_with("Usage:"), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn parse_options() {
+        // The options can go before or after the positional arguments, and are parsed even if the command ignores them
+        let fen = "4k3/8/8/8/8/8/8/3QK3 w - - 0 1";
+        let output = run(&["--color", "b", "analyze", fen, "--depth", "2", "--games", "3", "--opponent-depth", "1", "--plies", "4"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("Best move:"));
+        assert!(stdout(&output).contains("(depth 2)"));
+
+        let output = run(&["perft", "1"]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("Total: 20 nodes at depth 1"));
+    }
+
+    #[test]
+    fn invalid_options() {
+        expect_error(&["match", "--games"], "Missing value for option '--games'");
+        expect_error(&["match", "--games", "ten"], "Invalid value for option '--games': 'ten'");
+        expect_error(&["match", "--games", "-1"], "Invalid value for option '--games': '-1'");
+        expect_error(&["match", "--opponent-depth", "0"], "Invalid value for option '--opponent-depth': '0'");
+        expect_error(&["match", "--opponent-depth", "deep"], "Invalid value for option '--opponent-depth': 'deep'");
+        expect_error(&["match", "--opponent-depth", "256"], "Invalid value for option '--opponent-depth': '256'");
+        expect_error(&["play", "--color", "red"], "Invalid value for option '--color': 'red'");
+        expect_error(&["play", "--color"], "Missing value for option '--color'");
+        expect_error(&["analyze", "--depth", "0"], "Invalid value for option '--depth': '0'");
+        expect_error(&["analyze", "--seed", "1"], "Unknown option: '--seed'");
+        expect_error(&["analyse"], "Unknown command: 'analyse'");
+        expect_error(&["analyze"], "Missing the FEN to analyze");
+        expect_error(&["perft", "x"], "Invalid depth: 'x'");
+        expect_error(&["bench", "--time", "1"], "The benchmark searches to a fixed depth, use --depth");
+    }
+
+    #[test]
+    fn validate() {
+        expect_error(&["validate"], "Missing the path to the GameState file");
+        let missing = temp_file("missing.json");
+        expect_error(&["validate", missing.to_str().unwrap()], "Could not read");
+
+        let invalid = temp_file("invalid.json");
+        std::fs::write(&invalid, "{ \"initial_state\": 3 }").unwrap();
+        expect_error(&["validate", invalid.to_str().unwrap()], "Invalid GameState JSON");
+
+        // The JSON is well formed, but the move is illegal
+        let state = GameState { move_history: vec![MoveInfo::try_from("e2e5").unwrap()], ..Default::default() };
+        let illegal = temp_file("illegal.json");
+        std::fs::write(&illegal, serde_json::to_string(&state).unwrap()).unwrap();
+        expect_error(&["validate", illegal.to_str().unwrap()], "Invalid move: e2e5");
+
+        let valid = temp_file("valid.json");
+        std::fs::write(&valid, serde_json::to_string(&GameState::default()).unwrap()).unwrap();
+        let output = run(&["validate", valid.to_str().unwrap()]);
+        assert!(output.status.success(), "{}", stderr(&output));
+        assert!(stdout(&output).contains("The GameState is valid"));
+
+        for path in [invalid, illegal, valid] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn run(args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_protochess-engine-rs")).args(args).output().expect("Failed to run the binary")
+    }
+
+    // Runs the binary and checks that it fails with the given error, followed by the usage
+    fn expect_error(args: &[&str], error: &str) {
+        let output = run(args);
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        let stderr = stderr(&output);
+        assert!(stderr.starts_with(&format!("Error: {}", error)), "{:?}: {}", args, stderr);
+        assert!(stderr.contains("Usage:"), "{:?}", args);
+    }
+
+    fn stdout(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    fn stderr(output: &Output) -> String {
+        String::from_utf8_lossy(&output.stderr).into_owned()
+    }
+
+    // Path of a file in the temporary directory that is unique to this process
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("protochess-cli-{}-{}", std::process::id(), name))
+    }
+}
@@ -2,7 +2,8 @@ extern crate protochess_engine_rs;
 
 #[cfg(test)]
 mod custom_pieces {
-    use protochess_engine_rs::piece::{Piece, PieceFactory};
+    use protochess_engine_rs::piece::{Piece, PieceFactory, PieceDefinition, ValidationError};
+    use protochess_engine_rs::{Engine, GameState, InitialState};
     use protochess_engine_rs::types::BDimensions;
     use protochess_engine_rs::utils::to_index;
 
@@ -17,6 +18,7 @@ mod custom_pieces {
         assert_eq!(white_pawn.get_material_score(), 100);
         assert_eq!(white_pawn.get_material_score_all(), 0); // No pieces on the board
         assert_eq!(white_pawn.get_positional_score_all::<false>(), 0); // No pieces on the board
+        assert_eq!(white_pawn.get_forward_direction(), Some(1));
         
         println!("White pawn positional scores:");
         for y in (0..dims.height).rev() {
@@ -34,6 +36,7 @@ mod custom_pieces {
         assert_eq!(black_pawn.get_material_score(), 100);
         assert_eq!(black_pawn.get_material_score_all(), 0); // No pieces on the board
         assert_eq!(black_pawn.get_positional_score_all::<false>(), 0); // No pieces on the board
+        assert_eq!(black_pawn.get_forward_direction(), Some(-1));
         
         println!("Black pawn positional scores:");
         for y in (0..dims.height).rev() {
@@ -55,6 +58,7 @@ mod custom_pieces {
         assert_eq!(knight.get_material_score(), 320);
         assert_eq!(knight.get_material_score_all(), 0); // No pieces on the board
         assert_eq!(knight.get_positional_score_all::<false>(), 0); // No pieces on the board
+        assert_eq!(knight.get_forward_direction(), None);
         
         println!("Knight positional scores:");
         for y in (0..dims.height).rev() {
@@ -157,4 +161,120 @@ mod custom_pieces {
         }
     }
 
+    #[test]
+    fn piece_validation() {
+        let dims = BDimensions::new_without_walls(8, 8).unwrap();
+        let factory = PieceFactory::default();
+        for piece in [factory.make_pawn(true, 8, 8), factory.make_king(8, 8), factory.make_rook()] {
+            assert_eq!(piece.validate(&dims), Ok(()));
+        }
+        // Atomic pawns explode without being affected by explosions
+        let mut atomic_pawn = factory.make_pawn(false, 8, 8);
+        atomic_pawn.explode_on_capture = true;
+        atomic_pawn.immune_to_explosion = true;
+        assert_eq!(atomic_pawn.validate(&dims), Ok(()));
+        
+        // The standard castling files don't fit in a 6x6 board
+        let small = BDimensions::new_without_walls(6, 6).unwrap();
+        assert_eq!(factory.make_king(6, 6).validate(&small), Err(vec![ValidationError::CastlingOutOfBounds { destination: 6 }]));
+        
+        let mut pawn = factory.make_pawn(true, 8, 8);
+        pawn.ids[1] = Some('p');
+        pawn.promo_vals[1].clear();
+        pawn.promotion_squares = vec![(0, 7), (9, 7)];
+        pawn.explosion_deltas = vec![(0, 0)];
+        assert_eq!(pawn.validate(&dims), Err(vec![
+            ValidationError::PromotionMismatch { player: 1 },
+            ValidationError::SquareOutOfBounds { field: "promotion_squares", square: (9, 7) },
+            ValidationError::ExplosionOnOwnSquare,
+        ]));
+        pawn.promotion_squares = vec![(0, 0), (1, 0)];
+        pawn.ids[1] = None;
+        pawn.explosion_deltas.clear();
+        assert_eq!(pawn.validate(&dims), Err(vec![ValidationError::UnreachablePromotion]));
+        
+        let statue = PieceDefinition { ids: [Some('S'), None], ..Default::default() };
+        assert_eq!(statue.validate(&dims), Err(vec![ValidationError::NoMovement]));
+        // Pieces without ids are not used, but they are valid (see racing kings)
+        let unused = PieceDefinition { ids: [None, None], ..factory.make_pawn(true, 8, 8) };
+        assert_eq!(unused.validate(&dims), Ok(()));
+        
+        // The errors are returned by InitialState::validate_pieces() and set_state()
+        let mut state = InitialState::default();
+        assert_eq!(state.validate_pieces(), Ok(()));
+        state.piece_types.push(statue);
+        assert_eq!(state.validate_pieces(), Err(vec![(state.piece_types.len() - 1, ValidationError::NoMovement)]));
+        let mut engine = Engine::default();
+        let result = engine.set_state(GameState { initial_state: state, initial_fen: None, move_history: vec![], prior_fens: vec![], termination: None });
+        assert_eq!(result.unwrap_err(), "Invalid piece 'S': the piece cannot move, capture or castle");
+    }
+
+    #[test]
+    fn betza_notation() {
+        let factory = PieceFactory::default();
+        let same_movement = |a: &PieceDefinition, b: &PieceDefinition| {
+            let sorted = |v: &Vec<(i8, i8)>| { let mut v = v.clone(); v.sort(); v };
+            assert_eq!(sorted(&a.translate_jump_deltas), sorted(&b.translate_jump_deltas));
+            assert_eq!(sorted(&a.attack_jump_deltas), sorted(&b.attack_jump_deltas));
+            assert_eq!(a.translate_sliding_deltas, b.translate_sliding_deltas);
+            assert_eq!(a.attack_sliding_deltas, b.attack_sliding_deltas);
+            assert_eq!((a.translate_north, a.translate_northeast, a.attack_west, a.attack_southwest),
+                (b.translate_north, b.translate_northeast, b.attack_west, b.attack_southwest));
+        };
+        let ids = [Some('X'), Some('x')];
+        let standard = [
+            ("N", factory.make_knight()), ("B", factory.make_bishop()), ("R", factory.make_rook()), ("Q", factory.make_queen()),
+        ];
+        for (betza, piece) in &standard {
+            same_movement(&PieceDefinition::from_betza(betza, ids).unwrap(), piece);
+            assert_eq!(piece.to_betza().unwrap(), *betza);
+        }
+        let king = PieceDefinition::from_betza("K", ids).unwrap();
+        assert_eq!(king.translate_jump_deltas.len(), 8);
+        assert_eq!(king.to_betza().unwrap(), "K");
+        
+        // Pawns are asymmetric, forward is south for black pieces
+        let white_pawn = PieceDefinition::from_betza("fmWfcF", [Some('P'), None]).unwrap();
+        same_movement(&white_pawn, &factory.make_pawn(true, 8, 8));
+        let black_pawn = PieceDefinition::from_betza("fmWfcF", [None, Some('p')]).unwrap();
+        same_movement(&black_pawn, &factory.make_pawn(false, 8, 8));
+        assert_eq!(factory.make_pawn(false, 8, 8).to_betza().unwrap(), "fmWfcF");
+        assert!(PieceDefinition::from_betza("fmWfcF", ids).is_err());
+        
+        // Compounds, riders, limited range and hoppers
+        assert_eq!(PieceDefinition::from_betza("BN", ids).unwrap().to_betza().unwrap(), "BN");
+        assert_eq!(factory.make_archbishop().to_betza().unwrap(), "BN");
+        assert_eq!(factory.make_chancellor().to_betza().unwrap(), "RN");
+        let nightrider = PieceDefinition::from_betza("NN", ids).unwrap();
+        assert_eq!(nightrider.translate_sliding_deltas.len(), 8);
+        assert_eq!(nightrider.translate_sliding_deltas[0][..3], [(1, 2), (2, 4), (3, 6)]);
+        assert_eq!(nightrider.to_betza().unwrap(), "NN");
+        let short_rook = PieceDefinition::from_betza("R2", ids).unwrap();
+        assert!(!short_rook.translate_north);
+        assert!(short_rook.translate_sliding_deltas.contains(&vec![(0, 1), (0, 2)]));
+        assert_eq!(short_rook.to_betza().unwrap(), "R2");
+        let cannon = PieceDefinition::from_betza("mRcpR", ids).unwrap();
+        assert!(cannon.translate_north && !cannon.attack_north);
+        assert_eq!(cannon.attack_hops.len(), 4);
+        assert_eq!(cannon.to_betza().unwrap(), "mRcpR");
+        assert_eq!(PieceDefinition::from_betza("gQ", ids).unwrap().to_betza().unwrap(), "gQ");
+        
+        // Directions
+        for betza in ["fN", "ffN", "fsN", "sW", "vWF", "flF", "ffrN", "fKbW", "fFlF", "WmfF"] {
+            let piece = PieceDefinition::from_betza(betza, [Some('X'), None]).unwrap();
+            let written = piece.to_betza().unwrap();
+            same_movement(&PieceDefinition::from_betza(&written, [Some('X'), None]).unwrap(), &piece);
+        }
+        assert_eq!(PieceDefinition::from_betza("ffN", [Some('X'), None]).unwrap().translate_jump_deltas.len(), 2);
+        assert_eq!(PieceDefinition::from_betza("ffrN", [Some('X'), None]).unwrap().translate_jump_deltas, [(1, 2)]);
+        assert_eq!(PieceDefinition::from_betza("vW", ids).unwrap().to_betza().unwrap(), "vW");
+        
+        for invalid in ["", "X", "Nf", "pN", "fsF", "yW"] {
+            assert!(PieceDefinition::from_betza(invalid, ids).is_err(), "{}", invalid);
+        }
+        let mut irregular = factory.make_knight();
+        irregular.translate_sliding_deltas = vec![vec![(0, 1), (1, 1)]];
+        assert!(irregular.to_betza().is_err());
+    }
+
 }
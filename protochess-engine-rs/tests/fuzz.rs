@@ -0,0 +1,52 @@
+extern crate protochess_engine_rs;
+
+// Run with `cargo test --features fuzz --test fuzz`
+
+#[cfg(all(test, feature = "fuzz"))]
+mod fuzz {
+    use rand::{Rng, SeedableRng};
+    use rand::rngs::StdRng;
+
+    use protochess_engine_rs::utils::fuzz::{self, FuzzGenerator};
+
+    #[test]
+    fn random_fens() {
+        let mut generator = FuzzGenerator::new(1);
+        for _ in 0..2000 {
+            fuzz::fuzz_fen(&generator.fen());
+        }
+    }
+
+    #[test]
+    fn random_game_states() {
+        let mut generator = FuzzGenerator::new(2);
+        for _ in 0..200 {
+            fuzz::fuzz_game_state(generator.game_state());
+            fuzz::fuzz_game_state_json(&generator.game_state_json());
+        }
+    }
+
+    #[test]
+    fn random_bytes() {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..2000 {
+            let len = rng.gen_range(0, 100);
+            let data: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            fuzz::fuzz_bytes(&data);
+        }
+    }
+
+    #[test]
+    fn hostile_fens() {
+        let fens = [
+            "", " ", "/", "//////////", "[", "]", "8[", "8/8[]]", "99999999999999999999/8", "4294967296K",
+            "k!", "!k", "K!!", "8/8/8/8/8/8/8/8 w", "8/8/8/8/8/8/8/8 b KQkq", "4k3/8/8/8/8/8/8/4K3 w (a1,", "4k3/8/8/8/8/8/8/4K3 w ()",
+            "4k3/8/8/8/8/8/8/4K3 w Z", "4k3/8/8/8/8/8/8/4K3 w - a9", "4k3/8/8/8/8/8/8/4K3 b - a1", "4k3/8/8/8/8/8/8/4K3 w - z1(z2)",
+            "4k3/8/8/8/8/8/8/4K3 w - a3,a4(a99999999999)", "4k3/8/8/8/8/8/8/4K3 w - - 99999999 1", "4k3/8/8/8/8/8/8/4K3 w - - 0 1 +999+1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1 3+1", "***/***/***", "é♔/𝄞", "4k3/8/8/8/8/8/8/4K3[99999999999] w", "K{abc}/8", "K{}",
+        ];
+        for fen in fens {
+            fuzz::fuzz_fen(fen);
+        }
+    }
+}
@@ -1,9 +1,13 @@
 #[cfg(test)]
 mod move_generator_test {
+    use std::convert::TryFrom;
+    
     use protochess_engine_rs::position::create::position_factory::PositionFactory;
     use protochess_engine_rs::GameState;
-    use protochess_engine_rs::move_generator::MoveGen;
-    use protochess_engine_rs::types::{Move, MoveType};
+    use protochess_engine_rs::move_generator::{MoveGen, LegalityInfo};
+    use protochess_engine_rs::types::{Move, MoveType, Bitboard, BIndex, BCoord, BOARD_SIZE, BOARD_WIDTH};
+    use protochess_engine_rs::{Engine, MoveInfo, MoveList, MakeMoveResultFlag};
+    use protochess_engine_rs::utils::{to_index, from_index};
 
     #[test]
     fn capture_moves() {
@@ -41,4 +45,325 @@ mod move_generator_test {
         assert!(Move::new(0, 0, 0, MoveType::PromotionCapture, None).is_capture());
         assert!(!Move::new(0, 0, 0, MoveType::Null, None).is_capture());
     }
+    
+    #[test]
+    fn legal_moves_str() {
+        let mut engine = Engine::default();
+        let moves = engine.legal_moves_str();
+        assert_eq!(moves.len(), 20);
+        assert!(moves.windows(2).all(|w| w[0].long < w[1].long));
+        let nf3 = moves.iter().find(|mv| mv.long == "g1f3").unwrap();
+        assert_eq!(nf3.san, "Nf3");
+        
+        engine.load_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        let moves = engine.legal_moves_str();
+        let castle = moves.iter().find(|mv| mv.long == "e1a1").unwrap();
+        assert_eq!(castle.san, "O-O-O");
+        let check = moves.iter().find(|mv| mv.long == "a1a8").unwrap();
+        assert_eq!(check.san, "Ra8+");
+    }
+    
+    #[test]
+    fn legal_targets_from() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.legal_targets_from(6, 0), vec![(5, 2), (7, 2)]);
+        assert_eq!(engine.legal_targets_from(4, 1), vec![(4, 2), (4, 3)]);
+        assert!(engine.legal_targets_from(4, 0).is_empty());
+        // Enemy pieces, empty and out of bounds squares
+        assert!(engine.legal_targets_from(4, 6).is_empty());
+        assert!(engine.legal_targets_from(4, 4).is_empty());
+        assert!(engine.legal_targets_from(8, 8).is_empty());
+        assert!(engine.has_any_legal_move());
+        
+        // Pinned piece, castling and promotions (only included once)
+        engine.load_fen("4k3/1P6/8/8/4r3/8/4N3/R3K3 w Q - 0 1").unwrap();
+        assert!(engine.legal_targets_from(4, 1).is_empty());
+        assert!(engine.legal_targets_from(4, 0).contains(&(0, 0)));
+        assert_eq!(engine.legal_targets_from(1, 6), vec![(1, 7)]);
+        let moves = engine.legal_moves();
+        for list in moves {
+            let mut targets: Vec<_> = list.moves.iter().filter(|mv| !mv.is_drop()).map(|mv| mv.to).collect();
+            targets.sort_by_key(|&(x, y)| (y, x));
+            targets.dedup();
+            assert_eq!(engine.legal_targets_from(list.x, list.y), targets);
+        }
+        
+        // Checkmate and stalemate
+        engine.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert!(!engine.has_any_legal_move());
+        engine.load_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1").unwrap();
+        assert!(!engine.has_any_legal_move());
+    }
+    
+    #[test]
+    fn check_and_attackers() {
+        let mut engine = Engine::default();
+        assert!(!engine.is_in_check());
+        assert!(engine.checking_pieces().is_empty());
+        // f3 is defended by the pawns on e2 and g2 and by the knight on g1
+        assert_eq!(engine.attackers_of(5, 2, 0), vec![(6, 0), (4, 1), (6, 1)]);
+        assert!(engine.attackers_of(5, 2, 1).is_empty());
+        assert!(engine.attackers_of(16, 16, 0).is_empty());
+        
+        // Double check by a knight and a rook
+        engine.load_fen("4k3/8/3N4/8/8/8/8/4R1K1 b - - 0 1").unwrap();
+        assert!(engine.is_in_check());
+        assert_eq!(engine.checking_pieces(), vec![(4, 0), (3, 5)]);
+        // Same as the attackers of the king square
+        assert_eq!(engine.attackers_of(4, 7, 0), vec![(4, 0), (3, 5)]);
+        assert_eq!(engine.attackers_of(3, 7, 1), vec![(4, 7)]);
+        
+        // Checkmate, the game is over
+        engine.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert!(engine.is_in_check());
+        assert_eq!(engine.checking_pieces(), vec![(7, 3)]);
+    }
+    
+    #[test]
+    fn hypothetical_moves() {
+        let mut engine = Engine::default();
+        let mv = |s: &str| MoveInfo::try_from(s).unwrap();
+        let count = |lists: Vec<MoveList>| lists.iter().map(|list| list.moves.len()).sum::<usize>();
+        assert!(engine.would_be_legal(&mv("e2e4")));
+        assert!(!engine.would_be_legal(&mv("e2e5")));
+        assert!(!engine.would_be_legal(&mv("e7e5")));
+        
+        // The engine state doesn't change
+        let zobrist = engine.get_zobrist();
+        assert_eq!(count(engine.legal_moves_after(&mv("e2e4"))), 20);
+        assert!(engine.legal_moves_after(&mv("e2e5")).is_empty());
+        assert_eq!(engine.get_zobrist(), zobrist);
+        assert!(engine.get_move_history().is_empty());
+        assert_eq!(engine.player_to_move(), 0);
+        
+        // No replies after checkmate
+        for m in ["f2f3", "e7e5", "g2g4"] {
+            assert_eq!(engine.make_move(&mv(m)).flag, MakeMoveResultFlag::Ok);
+        }
+        assert!(engine.would_be_legal(&mv("d8h4")));
+        assert!(engine.legal_moves_after(&mv("d8h4")).is_empty());
+        assert_eq!(engine.get_move_history().len(), 3);
+        // Same replies as after making the move
+        let replies = count(engine.legal_moves_after(&mv("d8g5")));
+        assert_eq!(engine.make_move(&mv("d8g5")).flag, MakeMoveResultFlag::Ok);
+        assert_eq!(count(engine.legal_moves()), replies);
+    }
+    
+    #[test]
+    fn static_exchange_evaluation() {
+        let see = |fen: &str, from: (u8, u8), to: (u8, u8)| {
+            let gs = GameState::from_debug_fen(fen);
+            let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+            let (from, to) = (to_index(from.0, from.1), to_index(to.0, to.1));
+            let mv = MoveGen::get_pseudo_moves(&mut pos, true).into_iter()
+                .find(|mv| mv.get_from() == from && mv.get_to() == to)
+                .unwrap();
+            let victim = pos.piece_at(to).map_or(0, |piece| piece.get_material_score());
+            let attacker = pos.piece_at(from).unwrap().get_material_score();
+            (MoveGen::see(&pos, mv), victim, attacker)
+        };
+        // Queen takes a pawn defended by a pawn
+        let (score, pawn, queen) = see("4k3/8/4p3/3p4/8/8/8/3QK3 w - - 0 1", (3, 0), (3, 4));
+        assert_eq!(score, pawn - queen);
+        // Pawn takes an undefended knight
+        let (score, knight, _) = see("4k3/8/8/3n4/4P3/8/8/4K3 w - - 0 1", (4, 3), (3, 4));
+        assert_eq!(score, knight);
+        // The rook behind the attacker wins the exchange (x-ray)
+        let (score, pawn, _) = see("3rk3/8/8/3p4/8/8/3R4/3RK3 w - - 0 1", (3, 1), (3, 4));
+        assert_eq!(score, pawn);
+        // Without the second rook, the exchange loses material
+        let (score, pawn, rook) = see("3rk3/8/8/3p4/8/8/3R4/4K3 w - - 0 1", (3, 1), (3, 4));
+        assert_eq!(score, pawn - rook);
+        // Qxd6 cxd6: the queen is lost for a rook
+        let (score, rook, queen) = see("4k3/2p5/3r4/8/8/8/8/3QK3 w - - 0 1", (3, 0), (3, 5));
+        assert_eq!(score, rook - queen);
+    }
+    
+    #[test]
+    fn pinned_pieces() {
+        // The knight on d2 is pinned by the bishop, the rook on e2 is pinned by the queen and the pawn on f2
+        // shields the king from a rook that cannot attack diagonally
+        let gs = GameState::from_debug_fen("4q2k/8/8/b7/7r/8/3NRP2/4K3 w - - 0 1");
+        let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+        let info = LegalityInfo::new(&mut pos);
+        assert!(!info.in_check());
+        let pinned = info.get_pinned();
+        assert!(pinned.get_bit(to_index(3, 1)));
+        assert!(pinned.get_bit(to_index(4, 1)));
+        assert!(!pinned.get_bit(to_index(5, 1)));
+        
+        // The fast test agrees with making the moves
+        for mv in MoveGen::get_pseudo_moves(&mut pos, true) {
+            if info.is_known_legal(mv, &pos) {
+                assert!(MoveGen::is_move_legal(mv, &mut pos), "{} is not legal", mv);
+            }
+        }
+        let legal_moves = MoveGen::get_legal_moves(&mut pos);
+        assert!(legal_moves.iter().all(|mv| mv.get_from() != to_index(3, 1)));
+        assert!(legal_moves.iter().all(|mv| mv.get_from() != to_index(4, 1) || from_index(mv.get_to()).0 == 4));
+    }
+    
+    #[test]
+    fn staged_generation() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let gs = GameState::from_debug_fen(fen);
+            let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+            let all_moves = MoveGen::get_pseudo_moves(&mut pos, true);
+            let mut staged = MoveGen::get_pseudo_moves(&mut pos, false);
+            let captures_exist = !staged.is_empty();
+            staged.extend(MoveGen::get_pseudo_quiets(&mut pos, captures_exist));
+            assert_eq!(staged.len(), all_moves.len());
+            for mv in &all_moves {
+                assert!(staged.contains(mv));
+                assert!(MoveGen::is_pseudo_legal(*mv, &mut pos), "{} is pseudo-legal", mv);
+            }
+            // Moves of the other player are not pseudo-legal
+            pos.make_move(Move::null());
+            let enemy_moves = MoveGen::get_pseudo_moves(&mut pos, true);
+            pos.unmake_move();
+            for mv in enemy_moves {
+                assert!(!MoveGen::is_pseudo_legal(mv, &mut pos), "{} is not pseudo-legal", mv);
+            }
+        }
+    }
+    
+    #[test]
+    fn forced_captures() {
+        let fens = [
+            ("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b - - 0 1 antichess", false),
+            ("rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w - - 0 1 antichess", true),
+            ("8/8/8/3p4/8/8/8/3R4 w - - 0 1 antichess", true),
+            ("8/8/8/3p4/8/8/8/2R5 w - - 0 1 antichess", false),
+        ];
+        for (fen, captures_exist) in fens {
+            let gs = GameState::from_debug_fen(fen);
+            let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+            assert_eq!(MoveGen::has_captures(&pos), captures_exist, "{}", fen);
+            let moves = MoveGen::get_pseudo_moves(&mut pos, true);
+            assert_eq!(moves.iter().all(|mv| mv.is_capture()), captures_exist, "{}", fen);
+            let quiets = MoveGen::get_pseudo_quiets(&mut pos, captures_exist);
+            assert_eq!(quiets.is_empty(), captures_exist, "{}", fen);
+        }
+        // Quiet moves are not pseudo-legal if there is a capture
+        for fen in ["8/8/8/3p4/8/8/8/3R4 w - - 0 1 antichess", "8/8/8/3p4/8/8/8/2R5 w - - 0 1 antichess"] {
+            let gs = GameState::from_debug_fen(fen);
+            let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+            let captures_exist = MoveGen::has_captures(&pos);
+            for mv in MoveGen::get_pseudo_quiets(&mut pos, false) {
+                assert_eq!(MoveGen::is_pseudo_legal(mv, &mut pos), !captures_exist, "{} in {}", mv, fen);
+            }
+        }
+
+        // The engine searches the whole chain of captures
+        let mut engine = Engine::default();
+        let _result = engine.set_state(GameState::from_debug_fen("8/8/8/3p4/8/8/8/3R4 w - - 0 1 antichess")).unwrap();
+        let (mv, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "d1d5");
+    }
+
+    // Random occupancies with different densities, and all the combinations of directions
+    fn random_occupancies() -> Vec<Bitboard> {
+        use rand::{SeedableRng, Rng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(1234);
+        (0..64).map(|i| {
+            let mut occ = Bitboard::zero();
+            for index in 0..BOARD_SIZE {
+                if rng.gen_range(0, 64) < i {
+                    occ.set_bit(index as BIndex);
+                }
+            }
+            occ
+        }).collect()
+    }
+    fn directions(mask: u8) -> [bool; 8] {
+        let mut dirs = [false; 8];
+        for (i, dir) in dirs.iter_mut().enumerate() {
+            *dir = mask & (1 << i) != 0;
+        }
+        dirs
+    }
+    
+    #[test]
+    fn sliding_moves_match_classical() {
+        let tables = MoveGen::attack_tables();
+        for occ in random_occupancies() {
+            for index in 0..BOARD_SIZE {
+                let index = index as BIndex;
+                for mask in [0x01, 0x02, 0x04, 0x08, 0x10, 0x20, 0x40, 0x80, 0x0F, 0xF0, 0xFF, 0x5A] {
+                    let [n, e, s, w, ne, nw, se, sw] = directions(mask);
+                    assert_eq!(
+                        tables.get_sliding_moves_bb(index, &occ, n, e, s, w, ne, nw, se, sw),
+                        tables.get_sliding_moves_bb_classical(index, &occ, n, e, s, w, ne, nw, se, sw),
+                        "Square {}, directions {:#x}", index, mask
+                    );
+                }
+            }
+        }
+    }
+    
+    // Run with `cargo test --release --test move_generator -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_sliding_moves() {
+        let tables = MoveGen::attack_tables();
+        let occupancies = random_occupancies();
+        let bench = |name: &str, slide: &dyn Fn(BIndex, &Bitboard) -> Bitboard| {
+            let start = std::time::Instant::now();
+            let mut total = 0;
+            for _ in 0..20 {
+                for occ in &occupancies {
+                    for index in 0..BOARD_SIZE {
+                        total += slide(index as BIndex, occ).count_ones();
+                    }
+                }
+            }
+            println!("{}: {:?} ({} squares)", name, start.elapsed(), total);
+        };
+        bench("Line lookup", &|index, occ| tables.get_sliding_moves_bb(index, occ, true, true, true, true, true, true, true, true));
+        bench("Classical", &|index, occ| tables.get_sliding_moves_bb_classical(index, occ, true, true, true, true, true, true, true, true));
+    }
+    
+    #[test]
+    fn bitboard_api() {
+        for occ in random_occupancies() {
+            // The iterator yields the same indexes as popping the lowest bit
+            let mut expected = Vec::new();
+            let mut copy = occ.clone();
+            while let Some(index) = copy.lowest_one() {
+                expected.push(index);
+                copy.clear_bit(index);
+            }
+            assert_eq!(occ.iter().collect::<Vec<_>>(), expected);
+            assert_eq!((&occ).into_iter().count(), occ.count_ones() as usize);
+            // Coordinates and serialization round-trip
+            assert_eq!(Bitboard::from_coords(&occ.to_coords()), occ);
+            let json = serde_json::to_string(&occ).unwrap();
+            assert_eq!(serde_json::from_str::<Bitboard>(&json).unwrap(), occ);
+            // Shifts match moving each square individually, and don't wrap around the edges
+            for k in [0, 1, 3, BOARD_WIDTH - 1, BOARD_WIDTH, BOARD_WIDTH + 5] {
+                let shifted = |dx: i32, dy: i32| {
+                    let coords: Vec<_> = occ.to_coords().into_iter()
+                        .map(|(x, y)| (x as i32 + dx * k as i32, y as i32 + dy * k as i32))
+                        .filter(|&(x, y)| x >= 0 && y >= 0 && x < BOARD_WIDTH as i32 && y < BOARD_WIDTH as i32)
+                        .map(|(x, y)| (x as BCoord, y as BCoord))
+                        .collect();
+                    Bitboard::from_coords(&coords)
+                };
+                assert_eq!(occ.shift_n(k), shifted(0, 1));
+                assert_eq!(occ.shift_e(k), shifted(1, 0));
+                assert_eq!(occ.shift_s(k), shifted(0, -1));
+                assert_eq!(occ.shift_w(k), shifted(-1, 0));
+            }
+        }
+        assert_eq!(Bitboard::zero().iter().next(), None);
+        assert_eq!(Bitboard::from_coords(&[(2, 0), (0, 1)]).to_coords(), vec![(2, 0), (0, 1)]);
+        assert_eq!(serde_json::to_string(&Bitboard::from_coords(&[(1, 2)])).unwrap(), "[[1,2]]");
+        let outside = format!("[[0,0],[{},0]]", BOARD_WIDTH);
+        assert!(serde_json::from_str::<Bitboard>(&outside).is_err());
+    }
 }
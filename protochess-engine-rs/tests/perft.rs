@@ -5,6 +5,7 @@ extern crate protochess_engine_rs;
 #[cfg(test)]
 mod perft {
     use protochess_engine_rs::{Engine, GameState, MakeMoveResultFlag};
+    use protochess_engine_rs::utils::perft;
     // https://www.chessprogramming.org/Perft_Results
     
     #[test]
@@ -457,6 +458,191 @@ mod perft {
         ]);
     }
     
+    
+    // https://github.com/niklasf/python-chess/blob/master/examples/perft/crazyhouse.perft
+    
+    #[test]
+    fn crazyhouse_start() {
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1 CRAZYHOUSE";
+        test_perft(fen, vec![
+            20,
+            400,
+            8902,
+            197281,
+            4888832,
+        ]);
+    }
+    
+    #[test]
+    fn crazyhouse_drops() {
+        let fen = "2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1 CRAZYHOUSE";
+        test_perft(fen, vec![
+            301,
+            75353,
+        ]);
+    }
+    
+    #[test]
+    fn crazyhouse_middlegame() {
+        let fen = "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1 CRAZYHOUSE";
+        test_perft(fen, vec![
+            42,
+            1347,
+            58057,
+            2083382,
+        ]);
+    }
+    
+    #[test]
+    fn copy_make() {
+        // Explosions next to castling pieces, drops, checks and en passant
+        let fens = [
+            "8/8/8/8/8/8/2k5/rR4KR w BGH - 0 1 ATOMIC",
+            "Rr2k1rR/3K4/3p4/8/8/8/7P/8 w beg - 0 1 ATOMIC",
+            "r3k1nr/ppp2ppp/8/8/P3q3/1n1b1P2/RPPb2PP/1NBQKBNR w Kkq - 1 18 ATOMIC",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 +2+2 3CHECK",
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1 CRAZYHOUSE",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+        ];
+        for fen in fens {
+            let gs = GameState::from_debug_fen(fen);
+            let mut engine = Engine::default();
+            let result = engine.set_state(gs).expect("Invalid test FEN");
+            assert!(result.flag == MakeMoveResultFlag::Ok);
+            engine.check_consistency().unwrap();
+            assert_eq!(engine.perft_copy_make(3), engine.perft(3), "{}", fen);
+            engine.check_consistency().unwrap();
+        }
+    }
+    
+    #[test]
+    fn perft_suite() {
+        let suite = "
+            # Standard and variant positions
+            rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 ;D1 20 ;D2 400 ;D3 8902
+            8/8/8/8/8/8/2k5/rR4KR w BGH - 0 1 ATOMIC ;D1 18 ;D2 180 ;D3 4365
+            2k5/8/8/8/8/8/8/4K3[QRBNPqrbnp] w - - 0 1 CRAZYHOUSE ;D1 301
+        ";
+        let entries = perft::parse_perft_suite(suite).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].expected, vec![(1, 20), (2, 400), (3, 8902)]);
+        assert_eq!(entries[1].fen, "8/8/8/8/8/8/2k5/rR4KR w BGH - 0 1 ATOMIC");
+        
+        let mismatches = perft::run_perft_suite(&entries, 3).unwrap();
+        assert_eq!(mismatches.len(), 1);
+        let mismatch = &mismatches[0];
+        assert_eq!((mismatch.depth, mismatch.expected, mismatch.found), (3, 4365, 4364));
+        assert_eq!(mismatch.divide.len(), 18);
+        assert_eq!(mismatch.divide.iter().map(|(_, nodes)| nodes).sum::<usize>(), 4364);
+        // The wrong depth is not run
+        assert!(perft::run_perft_suite(&entries, 2).unwrap().is_empty());
+        
+        assert!(perft::parse_perft_suite("8/8/8/8/8/8/2k5/rR4KR w BGH - 0 1 ;D1").is_err());
+        assert!(perft::parse_perft_suite("8/8/8/8/8/8/2k5/rR4KR w BGH - 0 1 ;D0 1").is_err());
+        assert!(perft::run_perft_suite(&perft::parse_perft_suite("invalid fen ;D1 1").unwrap(), 1).is_err());
+    }
+    
+    #[test]
+    fn perft_hashed() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1 +2+2 3CHECK",
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1 CRAZYHOUSE",
+        ];
+        for fen in fens {
+            let mut engine = Engine::default();
+            let result = engine.set_state(GameState::from_debug_fen(fen)).expect("Invalid test FEN");
+            assert!(result.flag == MakeMoveResultFlag::Ok);
+            assert_eq!(engine.perft_hashed(4), engine.perft(4), "{}", fen);
+        }
+    }
+    
+    #[test]
+    fn perft_tt_and_parallel() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r1bqk2r/pppp1ppp/2n1p3/4P3/1b1Pn3/2NB1N2/PPP2PPP/R1BQK2R[] b KQkq - 0 1 CRAZYHOUSE",
+        ];
+        for fen in fens {
+            let mut engine = Engine::default();
+            let result = engine.set_state(GameState::from_debug_fen(fen)).expect("Invalid test FEN");
+            assert!(result.flag == MakeMoveResultFlag::Ok);
+            let expected = engine.perft(4);
+            assert_eq!(engine.perft_tt(4), expected, "{}", fen);
+            assert_eq!(engine.perft_parallel(4, 2), expected, "{}", fen);
+            assert_eq!(engine.perft_parallel(4, 0), expected, "{}", fen);
+            assert_eq!(engine.perft_parallel(1, 2), engine.perft(1), "{}", fen);
+        }
+    }
+    
+    #[test]
+    fn divide_after_search() {
+        // The workers of the parallel search must not block the threads used by divide()
+        let (sender, receiver) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let mut engine = Engine::default();
+            let _result = engine.get_best_move(3).unwrap();
+            let nodes = engine.divide(2).iter().map(|(_, nodes)| nodes).sum::<usize>();
+            let _result = engine.get_best_move(3).unwrap();
+            sender.send(nodes).unwrap();
+        });
+        let nodes = receiver.recv_timeout(std::time::Duration::from_secs(60)).expect("divide() did not finish");
+        assert_eq!(nodes, 400);
+    }
+    
+    #[test]
+    fn perft_bisect() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.divide(2).iter().map(|(_, nodes)| nodes).sum::<usize>(), 400);
+        
+        // Reference that doesn't allow e7e5 after e2e4
+        let mut reference = |line: &[String], depth| {
+            let mut reference_engine = Engine::default();
+            for mv in line {
+                assert!(reference_engine.make_move_str(mv).unwrap().flag == MakeMoveResultFlag::Ok);
+            }
+            let mut divide = reference_engine.divide(depth);
+            if line == ["e2e4"] {
+                divide.retain(|(mv, _)| mv != "e7e5");
+            }
+            if line.is_empty() {
+                divide.iter_mut().filter(|(mv, _)| mv == "e2e4").for_each(|(_, nodes)| *nodes -= 1);
+            }
+            divide
+        };
+        let divergence = engine.perft_bisect(2, &mut reference).unwrap();
+        assert_eq!(divergence.line, vec!["e2e4"]);
+        assert!(divergence.fen.starts_with("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b"));
+        assert!(divergence.missing.is_empty());
+        assert_eq!(divergence.extra, vec!["e7e5"]);
+        
+        let mut same = |line: &[String], depth| {
+            let mut reference_engine = Engine::default();
+            for mv in line {
+                assert!(reference_engine.make_move_str(mv).unwrap().flag == MakeMoveResultFlag::Ok);
+            }
+            reference_engine.divide(depth)
+        };
+        assert!(engine.perft_bisect(3, &mut same).is_none());
+    }
+    
+    // Run with `cargo test --release --test perft -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_copy_make() {
+        let mut engine = Engine::default();
+        let gs = GameState::from_debug_fen("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        let result = engine.set_state(gs).expect("Invalid test FEN");
+        assert!(result.flag == MakeMoveResultFlag::Ok);
+        let start = std::time::Instant::now();
+        let nodes = engine.perft(4);
+        println!("Make/unmake: {:?} ({} nodes)", start.elapsed(), nodes);
+        let start = std::time::Instant::now();
+        let nodes = engine.perft_copy_make(4);
+        println!("Copy-make: {:?} ({} nodes)", start.elapsed(), nodes);
+    }
+    
     fn test_perft(fen: &str, results: Vec<usize>) {
         let gs = GameState::from_debug_fen(fen);        
         let mut engine = Engine::default();
@@ -1,11 +1,21 @@
 #[cfg(test)]
+#[allow(clippy::field_reassign_with_default)]
 mod position_test {
 
+    use std::collections::HashMap;
     use std::convert::TryFrom;
 
     use protochess_engine_rs::position::create::position_factory::PositionFactory;
-    use protochess_engine_rs::{GameState, MoveInfo};
-    use protochess_engine_rs::types::Move;
+    use protochess_engine_rs::position::create::fen::FenData;
+    use protochess_engine_rs::{GameState, GameResult, MoveInfo, Engine, MakeMoveResultFlag, MakeMoveResultWinner, LeaderCaptureRule, StalemateRule, CaptureMode, WinCondition, SquareEffect};
+    use protochess_engine_rs::{PieceDefinition, PieceDisplay, HopMove, HopLanding, CastlingRule, VariantRegistry, RenderOptions, SquareChange};
+    use protochess_engine_rs::{MoveGen, PieceId, PieceChange, PositionListener, GameTermination, VariantGenerator, VariantConstraints, GlobalRules};
+    use protochess_engine_rs::types::{BIndex, Player, BOARD_SIZE};
+    use protochess_engine_rs::utils::to_index;
+    use protochess_engine_rs::types::{Centipawns, Move, GameMode};
+    use protochess_engine_rs::piece::PieceFactory;
+    use protochess_engine_rs::position::Position;
+    use protochess_engine_rs::position::feature_planes::{FeaturePlanes, NUM_AUX_PLANES};
     
     #[test]
     fn null_move_eq() {
@@ -23,6 +33,29 @@ mod position_test {
         assert_eq!(zob_0, pos.get_zobrist());
     }
     
+    #[test]
+    fn owner_at() {
+        // Atomic capture: the capturing piece and the pieces around the target explode
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 1 ATOMIC";
+        let mut pos = PositionFactory::default().set_state(GameState::from_debug_fen(fen), None).unwrap().unwrap();
+        let check_owners = |pos: &Position| {
+            for index in (0..BOARD_SIZE).map(|i| i as BIndex) {
+                let owner = pos.pieces.iter().position(|ps| ps.index_has_piece(index)).map(|p| p as Player);
+                assert_eq!(pos.owner_at(index), owner, "Wrong owner at index {}", index);
+                assert_eq!(pos.piece_at(index).map(|piece| piece.get_player()), owner);
+            }
+        };
+        let moves = MoveGen::get_legal_moves(&mut pos);
+        let capture = *moves.iter().find(|mv| mv.is_capture()).unwrap();
+        let quiet = *moves.iter().find(|mv| !mv.is_capture()).unwrap();
+        for mv in [capture, quiet] {
+            pos.make_move(mv);
+            check_owners(&pos);
+            pos.unmake_move();
+            check_owners(&pos);
+        }
+    }
+    
     #[test]
     fn game_state_eq_position() {
         let mut factory = PositionFactory::default();
@@ -202,6 +235,96 @@ mod position_test {
         assert_eq!(pos, pos_target);
     }
     
+    #[test]
+    fn history_navigation() {
+        let mut engine = Engine::default();
+        let moves = ["e4", "e5", "Nf3", "Nc6", "Bb5"];
+        for mv in moves {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+        }
+        let final_zobrist = engine.get_zobrist();
+        assert_eq!(engine.current_ply(), 5);
+        assert!(engine.redo().is_err());
+        
+        // Go back, the moves can still be redone
+        engine.goto_ply(2).unwrap();
+        assert_eq!(engine.current_ply(), 2);
+        assert_eq!(engine.history().len(), 5);
+        assert_eq!(engine.get_move_history(), &vec!["e4", "e5"]);
+        assert_eq!(engine.get_state().move_history, build_move_history(vec!["e2e4", "e7e5"]));
+        let mut target = Engine::default();
+        assert_eq!(target.set_state(engine.get_state().clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_zobrist(), target.get_zobrist());
+        assert_eq!(engine.redo().unwrap().move_notation.unwrap(), "Nf3");
+        engine.goto_ply(5).unwrap();
+        assert_eq!(engine.get_zobrist(), final_zobrist);
+        assert_eq!(engine.get_move_history(), &moves.to_vec());
+        assert!(engine.goto_ply(6).is_err());
+        
+        // Making the next move keeps the rest of the history, a different move discards it
+        engine.goto_ply(0).unwrap();
+        assert_eq!(engine.make_move_str("e4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.history().len(), 5);
+        engine.undo().unwrap();
+        assert_eq!(engine.make_move_str("d4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.history(), &build_move_history(vec!["d2d4"])[..]);
+        assert!(engine.redo().is_err());
+        
+        // Setting a new state replaces the history
+        assert_eq!(engine.set_state(GameState::default()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.history().is_empty());
+    }
+    
+    #[test]
+    fn variations() {
+        let mut engine = Engine::default();
+        for mv in ["e4", "e5", "Nf3", "Nc6"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+        }
+        // King's gambit after 2 plies, the position doesn't change
+        let zobrist = engine.get_zobrist();
+        assert_eq!(engine.add_variation(2, &build_move_history(vec!["f2f4", "e5f4"])), Ok(1));
+        assert_eq!(engine.get_zobrist(), zobrist);
+        assert_eq!(engine.game_tree().get_current_line(), 0);
+        assert_eq!(engine.game_tree().get_lines()[1].notation, vec!["f4", "xf4"]);
+        assert_eq!(engine.game_tree().line_notation(1), vec!["e4", "e5", "f4", "xf4"]);
+        assert!(engine.add_variation(5, &build_move_history(vec!["a2a3"])).is_err());
+        assert!(engine.add_variation(2, &build_move_history(vec!["f2f4", "f4f5"])).is_err());
+        assert!(engine.add_variation(2, &[]).is_err());
+        
+        // Switch to the variation, at its first new move
+        engine.select_line(1).unwrap();
+        assert_eq!(engine.current_ply(), 3);
+        assert_eq!(engine.get_move_history(), &vec!["e4", "e5", "f4"]);
+        assert_eq!(engine.history(), &build_move_history(vec!["e2e4", "e7e5", "f2f4", "e5f4"])[..]);
+        assert_eq!(engine.sibling_lines(), vec![0, 1]);
+        let mut target = Engine::default();
+        assert_eq!(target.set_state(engine.get_state().clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_zobrist(), target.get_zobrist());
+        engine.select_line(0).unwrap();
+        assert_eq!(engine.get_move_history(), &vec!["e4", "e5", "Nf3"]);
+        assert!(engine.select_line(2).is_err());
+        
+        // Playing the first move of a variation selects it, new moves extend it or create another variation
+        engine.goto_ply(2).unwrap();
+        assert_eq!(engine.make_move_str("f4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.game_tree().get_current_line(), 1);
+        assert_eq!(engine.redo().unwrap().move_notation.unwrap(), "xf4");
+        assert_eq!(engine.make_move_str("Nf3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.game_tree().line_notation(1), vec!["e4", "e5", "f4", "xf4", "Nf3"]);
+        engine.goto_ply(1).unwrap();
+        assert_eq!(engine.make_move_str("c5").unwrap().flag, MakeMoveResultFlag::Ok);
+        let lines = engine.game_tree().get_lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!((lines[2].parent, lines[2].start_ply), (Some(1), 1));
+        assert_eq!(engine.sibling_lines(), vec![0, 2]);
+        assert_eq!(engine.get_move_history(), &vec!["e4", "c5"]);
+        
+        // Setting a new state only keeps the mainline
+        assert_eq!(engine.set_state(GameState::default()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.game_tree().get_lines().len(), 1);
+    }
+    
     #[test]
     fn incremental_updates_fail() {
         let mut factory = PositionFactory::default();
@@ -225,6 +348,218 @@ mod position_test {
         assert_eq!(pos, pos_before);
     }
     
+    #[test]
+    fn parse_san() {
+        let mut engine = Engine::default();
+        for mv in ["e4", "d5", "exd5", "Qxd5", "Nf3", "e6", "Nc3", "Qd8", "Ne4", "Nf6"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok, "{mv}");
+        }
+        assert!(engine.make_move_str("Ng5").is_err()); // 2 knights can go to g5
+        assert!(engine.make_move_str("Ke3").is_err()); // Illegal
+        assert!(engine.make_move_str("Qx").is_err());
+        for mv in ["Nfg5", "Be7", "Be2", "O-O"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok, "{mv}");
+        }
+        assert_eq!(engine.make_move_str("e2e4").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        
+        // Promotions, check indicators and alternative castling notation
+        engine.load_fen("r3k3/1P6/8/8/8/8/8/R3K2R w KQq - 0 1").unwrap();
+        assert_eq!(engine.make_move_str("bxa8=Q+").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("Ke7").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("0-0-0").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_move_history().last().unwrap(), "O-O-O");
+    }
+    
+    #[test]
+    fn pgn_round_trip() {
+        let pgn = "[Event \"Test \\\"game\\\"\"]\n[White \"Alice\"]\n[Variant \"King of the Hill\"]\n\n\
+            1. e4 {best by test} e5 2. Nf3 (2. f4 exf4) Nc6 3. Bc4 $1 d6?! 4. O-O Bg4 5. h3 Bxf3 6. Qxf3 Qd7 \
+            7. d4 exd4 8. e5 O-O-O 9. exd6 Bxd6 10. Qxf7 Nf6 *";
+        let (state, headers) = GameState::from_pgn_with_headers(pgn).unwrap();
+        assert_eq!(headers[0], ("Event".to_string(), "Test \"game\"".to_string()));
+        assert_eq!(state.move_history.len(), 20);
+        
+        let mut factory = PositionFactory::default();
+        factory.set_state(state.clone(), None).unwrap();
+        let exported = factory.to_pgn_with_headers(&headers);
+        assert!(exported.starts_with("[Event \"Test \\\"game\\\"\"]\n[Site \"?\"]\n"));
+        assert!(exported.contains("[Variant \"King of the Hill\"]\n"));
+        assert!(exported.contains("1. e4 e5 2. Nf3 Nc6 3. Bc4 d6 4. O-O Bg4"));
+        assert!(exported.contains("7. d4 exd4 8. e5\nO-O-O 9. exd6 Bxd6")); // Lines are wrapped at 80 characters
+        assert!(exported.trim_end().ends_with("*"));
+        let (state2, headers2) = GameState::from_pgn_with_headers(&exported).unwrap();
+        assert_eq!(state, state2);
+        assert_eq!(factory.to_pgn_with_headers(&headers2), exported);
+    }
+    
+    #[test]
+    fn pgn_result_and_fen() {
+        // Black to move, the game ends in checkmate
+        let mut engine = Engine::default();
+        engine.load_fen("7k/8/8/8/8/8/R7/1R4K1 b - - 0 1").unwrap();
+        for mv in ["Kg8", "Rb7", "Kf8", "Ra8#"] {
+            let _result = engine.make_move_str(mv).unwrap();
+        }
+        let pgn = engine.to_pgn();
+        assert!(pgn.contains("[Result \"1-0\"]\n"));
+        assert!(pgn.contains("[SetUp \"1\"]\n[FEN \"7k/8/8/8/8/8/R7/1R4K1 b - - 0 1\"]\n"));
+        assert!(pgn.contains("\n1... Kg8 2. Rb7 Kf8 3. Ra8# 1-0\n"));
+        assert!(!pgn.contains("Variant"));
+        
+        let mut engine2 = Engine::default();
+        assert_eq!(engine2.load_pgn(&pgn).unwrap().flag, MakeMoveResultFlag::Checkmate);
+        assert_eq!(engine2.get_state(), engine.get_state());
+        assert!(GameState::from_pgn("1. e4 e5 2. Ke3").is_err());
+    }
+    
+    #[test]
+    fn evaluate_position() {
+        let mut engine = Engine::default();
+        let eval = engine.evaluate_position_detailed();
+        assert_eq!(eval.pieces.len(), 32);
+        assert_eq!(eval.material, 0);
+        assert!(!eval.is_endgame);
+        assert_eq!(eval.total, engine.evaluate_position());
+        
+        // Remove the black queen, white is ahead by the value of the queen
+        engine.remove_piece(3, 7).unwrap();
+        let queen_value = eval.pieces.iter().find(|p| p.piece_id == 'Q').unwrap().material;
+        let eval = engine.evaluate_position_detailed();
+        assert_eq!(eval.material, queen_value);
+        assert_eq!(eval.total, eval.material + eval.positional + eval.mobility + eval.pawn_structure + eval.king_safety + eval.checks);
+        assert_eq!(eval.total, engine.evaluate_position());
+        
+        // The score is for the player to move
+        let _result = engine.make_move_str("e4").unwrap();
+        let eval = engine.evaluate_position_detailed();
+        assert_eq!(eval.material, -queen_value);
+        assert_eq!(eval.pieces.iter().find(|p| p.position == (4, 3)).unwrap().piece_id, 'P');
+        assert_eq!(eval.total, engine.evaluate_position());
+        
+        // Passed pawns are rewarded (scaled with the ranks advanced), blocked pawns are not
+        engine.load_fen("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+        let passed = engine.evaluate_position_detailed();
+        assert_eq!(passed.pawn_structure, 80 * 4 / 7 - 10);
+        assert_eq!(passed.king_safety, 0);
+        engine.load_fen("4k3/3p4/8/3P4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(engine.evaluate_position_detailed().pawn_structure, 0);
+        // Isolated pawns are penalized
+        let second_rank_bonus = 80 / 7;
+        engine.load_fen("4k3/8/8/8/8/8/3PP3/4K3 w - - 0 1").unwrap();
+        assert_eq!(engine.evaluate_position_detailed().pawn_structure, 2 * second_rank_bonus);
+        engine.load_fen("4k3/8/8/8/8/8/2P1P3/4K3 w - - 0 1").unwrap();
+        assert_eq!(engine.evaluate_position_detailed().pawn_structure, 2 * (second_rank_bonus - 10));
+        // Doubled pawns are penalized
+        engine.load_fen("4k3/8/8/3P4/3P4/8/8/4K3 w - - 0 1").unwrap();
+        let doubled = engine.evaluate_position_detailed();
+        assert_eq!(doubled.pawn_structure, -15 - 2 * 10 + 80 * 4 / 7 + 80 * 3 / 7);
+        assert_eq!(doubled.total, engine.evaluate_position());
+        // The score is for the player to move
+        engine.load_fen("4k3/8/8/3P4/3P4/8/8/4K3 b - - 0 1").unwrap();
+        assert_eq!(engine.evaluate_position_detailed().pawn_structure, -doubled.pawn_structure);
+        
+        // Pawn shield and attackers next to the leader (only in the middlegame)
+        engine.load_fen("rnbqkbnr/8/8/8/8/8/5PPP/RNBQ1RK1 w - - 0 1").unwrap();
+        let sheltered = engine.evaluate_position_detailed();
+        assert!(!sheltered.is_endgame);
+        // The black king has no shield and no attackers
+        assert_eq!(sheltered.king_safety, 3 * 8);
+        engine.load_fen("rnbqkbnr/8/8/8/8/5n2/5PPP/RNBQ1RK1 w - - 0 1").unwrap();
+        assert_eq!(engine.evaluate_position_detailed().king_safety, 3 * 8 - 12);
+        
+        // Pieces with more available moves get a higher mobility score
+        let rook_mobility = |engine: &mut Engine| {
+            engine.evaluate_position_detailed().pieces.iter().find(|p| p.piece_id == 'R').unwrap().mobility
+        };
+        engine.load_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1").unwrap();
+        let open_rook = rook_mobility(&mut engine);
+        engine.load_fen("4k3/8/8/8/8/8/P7/RN2K3 w - - 0 1").unwrap();
+        assert!(open_rook > rook_mobility(&mut engine));
+        assert_eq!(rook_mobility(&mut engine), 0);
+        
+        // Checks received are penalized in N-check variants
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1 3check")).unwrap();
+        let _result = engine.make_move_str("Ra8+").unwrap();
+        let eval = engine.evaluate_position_detailed();
+        assert!(eval.is_endgame);
+        assert!(eval.checks < 0);
+        assert_eq!(eval.total, engine.evaluate_position());
+    }
+
+    #[test]
+    fn variant_eval_profiles() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.evaluate_position_detailed().variant, 0);
+
+        // The pawns of the horde are not penalized for being doubled, and they are rewarded for staying together
+        let fen = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1 horde";
+        let _result = engine.set_state(GameState::from_debug_fen(fen)).unwrap();
+        let eval = engine.evaluate_position_detailed();
+        assert!(eval.pawn_structure >= 0);
+        assert!(eval.variant > 0);
+        assert_eq!(eval.total, eval.material + eval.positional + eval.mobility + eval.pawn_structure + eval.king_safety + eval.checks + eval.variant);
+        assert_eq!(eval.total, engine.evaluate_position());
+        // Pawns close to the back rank of the defender are rewarded
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/8/8/8/8/P7/8 b - - 0 1 horde")).unwrap();
+        let behind = engine.evaluate_position_detailed().variant;
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/P7/8/8/8/8/8/8 b - - 0 1 horde")).unwrap();
+        let advanced = engine.evaluate_position_detailed().variant;
+        // The score is for black, the defender
+        assert!(advanced < behind);
+
+        // In antichess, only the material (which is bad) and the forced captures are evaluated
+        let _result = engine.set_state(GameState::from_debug_fen("n7/7p/8/8/8/8/8/R7 w - - 0 1 antichess")).unwrap();
+        let eval = engine.evaluate_position_detailed();
+        // The rook is worth more than the knight and the pawn
+        assert!(eval.material < 0);
+        assert_eq!((eval.positional, eval.mobility, eval.pawn_structure), (0, 0, 0));
+        assert!(eval.variant < 0);
+        assert_eq!(eval.total, engine.evaluate_position());
+        let _result = engine.set_state(GameState::from_debug_fen("n7/7p/8/8/8/8/8/R7 b - - 0 1 antichess")).unwrap();
+        assert!(engine.evaluate_position_detailed().variant > 0);
+
+        // Captures that would explode the enemy king are rewarded, pieces next to the own king are penalized
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/4p3/8/8/8/8/4R3/4K3 w - - 0 1")).unwrap();
+        assert_eq!(engine.evaluate_position_detailed().variant, 0);
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/4p3/8/8/8/8/4R3/4K3 w - - 0 1 atomic")).unwrap();
+        let threat = engine.evaluate_position_detailed();
+        assert!(threat.variant > 0);
+        assert_eq!(threat.total, engine.evaluate_position());
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/4p3/8/8/8/4R3/4K3 w - - 0 1 atomic")).unwrap();
+        assert!(engine.evaluate_position_detailed().variant < 0);
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/4p3/8/8/8/8/R3K3 w - - 0 1 atomic")).unwrap();
+        assert_eq!(engine.evaluate_position_detailed().variant, 0);
+
+        // The leader is rewarded for being close to its win squares
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1 kingofthehill")).unwrap();
+        assert_eq!(engine.evaluate_position_detailed().variant, 0);
+        let _result = engine.set_state(GameState::from_debug_fen("4k3/8/8/8/8/3K4/8/8 w - - 0 1 kingofthehill")).unwrap();
+        let close = engine.evaluate_position_detailed();
+        assert!(close.variant > 0);
+        assert_eq!(close.total, engine.evaluate_position());
+        let _result = engine.set_state(GameState::from_debug_fen("8/8/8/8/8/8/1k6/K7 w - - 0 1 racingkings")).unwrap();
+        assert!(engine.evaluate_position_detailed().variant < 0);
+    }
+
+    #[test]
+    fn eval_breakdown_matches_evaluation() {
+        for mode in GameMode::ALL {
+            let mut engine = Engine::default();
+            let _result = engine.set_state(GameState::from_variant_name(&mode.to_string()).unwrap()).unwrap();
+            for ply in 0..12 {
+                let eval = engine.evaluate_position_detailed();
+                assert_eq!(eval.total, engine.evaluate_position(), "{mode}, ply {ply}");
+                assert_eq!(eval.positional, eval.pieces.iter().map(|p| p.positional).sum::<Centipawns>(), "{mode}, ply {ply}");
+                assert_eq!(eval.mobility, eval.pieces.iter().map(|p| p.mobility).sum::<Centipawns>(), "{mode}, ply {ply}");
+                let (mv, _, _) = match engine.get_best_move(2).unwrap().into_move() {
+                    Some(result) => result,
+                    None => break,
+                };
+                let _result = engine.make_move(&mv);
+            }
+        }
+    }
+
     #[test]
     fn disambiguate_move_notation_1() {
         let mut factory = PositionFactory::default();
@@ -322,8 +657,1472 @@ mod position_test {
         let move_notation = factory.get_notation();
         assert_eq!(move_notation.len(), 2);
     }
+
+    #[test]
+    fn notation_cache_replay() {
+        let mut state = GameState::default();
+        state.move_history = build_move_history(vec!["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5e5", "f1e2", "e5e4"]);
+        let mut factory = PositionFactory::default();
+        factory.set_state(state.clone(), None).unwrap();
+        let notation = factory.get_notation().clone();
+        assert_eq!(notation, vec!["e4", "d5", "xd5", "Qxd5", "Nc3", "Qe5+", "Be2", "Qe4"]);
+        // Replaying the same moves (now cached) must produce the same notation
+        factory.set_state(state.clone(), None).unwrap();
+        assert_eq!(factory.get_notation(), &notation);
+
+        // The cache must not be reused for a different variant
+        let mut atomic_state = GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 atomic");
+        atomic_state.move_history = state.move_history.clone();
+        factory.set_state(atomic_state.clone(), None).unwrap();
+        let mut fresh_factory = PositionFactory::default();
+        fresh_factory.set_state(atomic_state, None).unwrap();
+        assert_eq!(factory.get_notation(), fresh_factory.get_notation());
+    }
+
+    #[test]
+    fn game_state_json_roundtrip() {
+        let mut state = GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 atomic");
+        state.move_history = build_move_history(vec!["e2e4", "e7e5"]);
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"initialState\""));
+        assert!(json.contains("\"moveHistory\""));
+        let state2: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, state2);
+    }
+
+    #[test]
+    fn piece_display() {
+        let mut engine = Engine::default();
+        let knight = engine.get_piece_display('n').unwrap();
+        assert_eq!(knight.name.as_deref(), Some("Knight"));
+        assert_eq!(knight.glyphs, [Some("♘".to_string()), Some("♞".to_string())]);
+        assert_eq!(knight.svg_hint.as_deref(), Some("knight"));
+        assert_eq!(engine.get_piece_display('x'), None);
+        
+        // The display information is carried through the serialization of the game state
+        let mut state = GameState::default();
+        let amazon_display = PieceDisplay { name: Some("Amazon".to_string()), glyphs: [None, None], svg_hint: Some("amazon".to_string()) };
+        let amazon = PieceDefinition { display: Some(amazon_display.clone()), ..PieceDefinition::from_betza("QN", [Some('M'), Some('m')]).unwrap() };
+        state.initial_state.piece_types.push(amazon);
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"svgHint\":\"amazon\""));
+        let state2: GameState = serde_json::from_str(&json).unwrap();
+        assert_eq!(state, state2);
+        assert_eq!(engine.set_state(state2).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_piece_display('m'), Some(amazon_display));
+        
+        // Older JSON without the display section is still valid
+        let json = json.replace("\"display\":", "\"unusedField\":");
+        let state3: GameState = serde_json::from_str(&json).unwrap();
+        assert!(state3.initial_state.piece_types.iter().all(|piece| piece.display.is_none()));
+    }
+
+    #[test]
+    fn escaped_piece_ids() {
+        let mut state = GameState::default();
+        state.initial_state.piece_types.push(PieceDefinition::from_betza("N", [Some('!'), Some(')')]).unwrap());
+        state.initial_state.piece_types.push(PieceDefinition::from_betza("R", [Some('α'), Some('[')]).unwrap());
+        state.initial_fen = Some("(U+0029)3k2(U+005B)/8/8/8/8/8/8/(!)3K2(U+03B1) w - - 0 1".to_string());
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_piece_at((0, 0)), Ok('!'));
+        assert_eq!(engine.get_piece_at((7, 0)), Ok('α'));
+        assert_eq!(engine.get_piece_at((0, 7)), Ok(')'));
+        assert_eq!(engine.get_piece_at((7, 7)), Ok('['));
+        let fen = engine.get_state_diff().fen;
+        assert!(fen.starts_with("(U+0029)3k2(U+005B)/8/8/8/8/8/8/(!)3K2α w"), "{}", fen);
+        
+        // The FEN can be loaded again
+        state.initial_fen = Some(fen.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_state_diff().fen, fen);
+        assert_eq!(engine.make_move_str("a1b3").unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        let data = FenData::parse_fen("4k3/8/8/8/8/8/8/4K3[(!)(U+005B)(q)] w - - 0 1").unwrap();
+        assert_eq!(data.pocket, Some(vec!['!', '[', 'q']));
+        assert!(data.to_string().starts_with("4k3/8/8/8/8/8/8/4K3[(!)(U+005B)q]"));
+
+        // Digits, slashes, asterisks (walls) and dashes (empty pocket) are also escaped. Only dashes can be used
+        // by the pieces of an engine, the rest are reserved for the FEN and the move notation.
+        let fen = "(/)3k2(-)/8/8/8/8/8/8/(1)3K2(*)[(1)(-)] w - - 0 1";
+        let data = FenData::parse_fen(fen).unwrap();
+        assert_eq!(data.pocket, Some(vec!['1', '-']));
+        assert_eq!(data.to_string(), fen);
+        let mut state = GameState::default();
+        state.initial_state.piece_types.push(PieceDefinition::from_betza("N", [Some('-'), Some('~')]).unwrap());
+        state.initial_fen = Some("4k3/8/8/8/8/8/8/(-)3K3 w - - 0 1".to_string());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_piece_at((0, 0)), Ok('-'));
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/(-)3K3 w"));
+        assert!(FenData::parse_fen("4k3/8/8/8/8/8/8/4K3[(-)] w - - 0 1").unwrap().to_string().starts_with("4k3/8/8/8/8/8/8/4K3[(-)]"));
+
+        for invalid in ["4k3/8/8/8/8/8/8/(!","4k3/8/8/8/8/8/8/(U+ZZ)", "4k3/8/8/8/8/8/8/(ab)", "4k3/8/8/8/8/8/8/4K3)"] {
+            assert!(FenData::parse_fen(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn game_state_versioned_json() {
+        let mut state = GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1 crazyhouse");
+        state.move_history = build_move_history(vec!["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "P@e4", "p@d4"]);
+        let json = state.to_json();
+        assert!(json.contains("\"version\":2"));
+        assert!(json.contains("\"moveHistory\":[\"e2e4\",\"d7d5\",\"e4d5\",\"d8d5\",\"b1c3\",\"d5a5\",\"P@e4\",\"p@d4\"]"));
+        assert_eq!(GameState::from_json(&json), Ok(state.clone()));
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(GameState::from_json(&json).unwrap()).unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        // Version 1 (without the version field) is migrated
+        let old_json = serde_json::to_string(&state).unwrap();
+        assert!(!old_json.contains("\"version\""));
+        assert_eq!(GameState::from_json(&old_json), Ok(state));
+        
+        assert!(GameState::from_json(&json.replace("\"version\":2", "\"version\":3")).is_err());
+        assert!(GameState::from_json(&json.replace("\"d5a5\"", "\"d5\"")).is_err());
+        assert!(GameState::from_json("{").is_err());
+    }
+
+    #[test]
+    fn game_state_binary_format() {
+        let mut state = GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w KQkq - 0 1 crazyhouse");
+        state.move_history = build_move_history(vec!["e2e4", "d7d5", "e4d5", "d8d5", "b1c3", "d5a5", "P@e4", "p@d4"]);
+        let bytes = state.to_bytes();
+        assert_eq!(GameState::from_bytes(&bytes), Ok(state.clone()));
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(GameState::from_bytes(&bytes).unwrap()).unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        // Built-in variants are stored by name
+        let mut standard = GameState::from_variant_name("standard").unwrap();
+        standard.move_history = build_move_history(vec!["e2e4", "e7e5", "g1f3"]);
+        let bytes = standard.to_bytes();
+        assert!(bytes.len() < 24);
+        assert_eq!(GameState::from_bytes(&bytes), Ok(standard.clone()));
+        
+        // Custom variants are stored in full
+        let mut custom = GameState::from_variant_name("standard").unwrap();
+        custom.initial_state.global_rules.checks_to_lose = 3;
+        custom.move_history = build_move_history(vec!["e2e4", "e7e5"]);
+        assert_eq!(GameState::from_bytes(&custom.to_bytes()), Ok(custom.clone()));
+        
+        // The hash only depends on the content
+        assert_eq!(standard.content_hash(), standard.clone().content_hash());
+        assert_ne!(standard.content_hash(), custom.content_hash());
+        assert_ne!(standard.content_hash(), GameState::from_variant_name("standard").unwrap().content_hash());
+        
+        let bytes = standard.to_bytes();
+        assert!(GameState::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(GameState::from_bytes(&[bytes.as_slice(), &[0]].concat()).is_err());
+        assert!(GameState::from_bytes(&[99]).is_err());
+        assert!(GameState::from_bytes(&[]).is_err());
+        // Unknown flags are rejected instead of ignored
+        for flag in [16, 32, 64, 128] {
+            let mut bytes = bytes.clone();
+            bytes[1] |= flag;
+            assert!(GameState::from_bytes(&bytes).is_err(), "flag {} was accepted", flag);
+        }
+    }
+
+    #[test]
+    fn fen_at_ply() {
+        let mut engine = Engine::default();
+        let mut fens = vec![engine.get_state_diff().fen];
+        for mv in ["e2e4", "d7d5", "e4d5", "d8d5"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+            fens.push(engine.get_state_diff().fen);
+        }
+        for (ply, fen) in fens.iter().enumerate() {
+            assert_eq!(&engine.fen_at_ply(ply).unwrap(), fen);
+        }
+        assert!(engine.fen_at_ply(5).is_err());
+        // The current position is not modified
+        assert_eq!(&engine.get_state_diff().fen, fens.last().unwrap());
+        assert_eq!(engine.notation_at_ply(1).unwrap(), "e4");
+        assert_eq!(engine.notation_at_ply(4).unwrap(), "Qxd5");
+        assert!(engine.notation_at_ply(0).is_err());
+        assert!(engine.notation_at_ply(5).is_err());
+        // Undone moves are not part of the move history
+        engine.undo().unwrap();
+        assert!(engine.fen_at_ply(4).is_err());
+        assert_eq!(engine.fen_at_ply(3).unwrap(), fens[3]);
+    }
+
+    #[test]
+    fn repetitions_before_initial_fen() {
+        let mut engine = Engine::default();
+        let mut fens = vec![engine.get_state_diff().fen];
+        for mv in ["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+            fens.push(engine.get_state_diff().fen);
+        }
+        assert_eq!(engine.repetition_count(), 2);
+
+        // Resume the game from its last position
+        let mut state = GameState::from_variant_name("standard").unwrap();
+        state.initial_fen = fens.pop();
+        state.prior_fens = fens;
+        let mut resumed = Engine::default();
+        assert_eq!(resumed.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(resumed.repetition_count(), 2);
+        assert_eq!(resumed.make_move_str("f3g1").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(resumed.make_move_str("f6g8").unwrap().flag, MakeMoveResultFlag::Repetition);
+        assert_eq!(resumed.get_state().prior_fens, state.prior_fens);
+        assert_eq!(GameState::from_json(&state.to_json()), Ok(state.clone()));
+        assert_eq!(GameState::from_bytes(&state.to_bytes()), Ok(state.clone()));
+
+        // The positions before a capture can't be repeated
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1".to_string();
+        let captured = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPP1/RNBQKBNR w KQkq - 0 1".to_string();
+        state.initial_fen = Some(start.clone());
+        state.prior_fens = vec![start.clone(), captured, start];
+        assert_eq!(resumed.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(resumed.repetition_count(), 2);
+        state.prior_fens = vec!["invalid".to_string()];
+        assert!(resumed.set_state(state).is_err());
+    }
+
+    #[test]
+    fn game_termination() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.make_move_str("e2e4").unwrap().flag, MakeMoveResultFlag::Ok);
+        let resignation = GameResult { flag: MakeMoveResultFlag::Resignation, winner: MakeMoveResultWinner::White };
+        assert_eq!(engine.resign(1), Ok(resignation.clone()));
+        assert_eq!(engine.game_result(), Some(resignation));
+        assert!(engine.resign(0).is_err());
+        assert_eq!(engine.make_move_str("e7e5").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert!(engine.to_pgn().contains("1. e4 1-0"));
+        // The termination is stored in the game state
+        let state = engine.get_state().clone();
+        assert_eq!(state.termination, Some(GameTermination::Resignation { player: 1 }));
+        assert_eq!(GameState::from_json(&state.to_json()), Ok(state.clone()));
+        assert_eq!(GameState::from_bytes(&state.to_bytes()), Ok(state.clone()));
+        let mut loaded = Engine::default();
+        assert_eq!(loaded.set_state(state).unwrap().flag, MakeMoveResultFlag::Resignation);
+        // Undoing the resignation resumes the game
+        engine.undo().unwrap();
+        assert_eq!(engine.game_result(), None);
+        assert_eq!(engine.current_ply(), 1);
+
+        // The offer is valid until the opponent moves
+        assert!(engine.accept_draw().is_err());
+        engine.offer_draw(1).unwrap();
+        assert_eq!(engine.make_move_str("e7e5").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.draw_offer(), Some(1));
+        assert_eq!(engine.make_move_str("g1f3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.draw_offer(), None);
+        assert!(engine.accept_draw().is_err());
+        engine.offer_draw(0).unwrap();
+        assert_eq!(engine.accept_draw(), Ok(GameResult { flag: MakeMoveResultFlag::DrawAgreement, winner: MakeMoveResultWinner::None }));
+        assert!(engine.to_pgn().contains("2. Nf3 1/2-1/2"));
+
+        engine.undo().unwrap();
+        assert_eq!(engine.adjudicate(MakeMoveResultWinner::Black), Ok(GameResult { flag: MakeMoveResultFlag::Adjudication, winner: MakeMoveResultWinner::Black }));
+        assert_eq!(engine.get_state().termination, Some(GameTermination::Adjudication { winner: Some(1) }));
+        assert!(engine.offer_draw(0).is_err());
+        engine.load_fen("4k3/8/8/8/8/8/8/4K2R w K - 0 1").unwrap();
+        assert_eq!(engine.game_result(), None);
+    }
+
+    #[test]
+    fn random_variants() {
+        let constraints = VariantConstraints { width: (5, 9), height: (6, 8), ..Default::default() };
+        let mut generator = VariantGenerator::new(constraints.clone(), 42).unwrap();
+        let mut same_seed = VariantGenerator::new(constraints, 42).unwrap();
+        for _ in 0..50 {
+            let state = generator.generate().unwrap();
+            assert_eq!(same_seed.generate().unwrap(), state);
+            let variant = &state.initial_state;
+            assert!((5..=9).contains(&variant.board_width) && (6..=8).contains(&variant.board_height));
+            // The black army mirrors the white army, each player has a king
+            let ranks: Vec<&str> = variant.fen.split('/').collect();
+            assert_eq!(ranks[0], ranks[ranks.len() - 1].to_lowercase());
+            assert_eq!(ranks[0].matches('k').count(), 1);
+            let mut engine = Engine::default();
+            assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+            assert!(engine.get_best_move(2).is_ok());
+        }
+
+        // The budget limits the pieces on the back rank
+        let constraints = VariantConstraints { material_budget: 0, require_leader: false, symmetric: false, ..Default::default() };
+        let mut generator = VariantGenerator::new(constraints, 1).unwrap();
+        for _ in 0..10 {
+            let state = generator.generate().unwrap();
+            let ranks: Vec<&str> = state.initial_state.fen.split('/').collect();
+            assert!(ranks[0].chars().all(|c| c == 'k' || c.is_ascii_digit()));
+        }
+
+        // Without pawns the armies only take 1 rank
+        let constraints = VariantConstraints { height: (4, 8), ..Default::default() };
+        assert!(VariantGenerator::new(constraints.clone(), 0).is_err());
+        assert!(VariantGenerator::new(VariantConstraints { pawns: false, ..constraints }, 0).unwrap().generate().is_ok());
+        let constraints = VariantConstraints { piece_pool: vec![PieceFactory::default().make_king(8, 8)], ..Default::default() };
+        assert!(VariantGenerator::new(constraints, 0).is_err());
+    }
+
+    #[test]
+    fn board_delta() {
+        let mut engine = Engine::default();
+        engine.load_fen("r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1").unwrap();
+        let changes = |engine: &mut Engine, mv: &str| {
+            let result = engine.make_move_str(mv).unwrap();
+            assert_eq!(result.flag, MakeMoveResultFlag::Ok);
+            result.delta.changes
+        };
+        // En passant
+        assert_eq!(changes(&mut engine, "e5d6"), vec![
+            SquareChange { from: Some((4, 4)), to: Some((3, 5)), piece_id: 'P', promotion: None, player: 0 },
+            SquareChange { from: Some((3, 4)), to: None, piece_id: 'p', promotion: None, player: 1 },
+        ]);
+        // Castling
+        assert_eq!(changes(&mut engine, "e8h8"), vec![
+            SquareChange { from: Some((4, 7)), to: Some((6, 7)), piece_id: 'k', promotion: None, player: 1 },
+            SquareChange { from: Some((7, 7)), to: Some((5, 7)), piece_id: 'r', promotion: None, player: 1 },
+        ]);
+        // Promotion with a capture
+        assert_eq!(changes(&mut engine, "b7a8=Q"), vec![
+            SquareChange { from: Some((1, 6)), to: Some((0, 7)), piece_id: 'P', promotion: Some('Q'), player: 0 },
+            SquareChange { from: Some((0, 7)), to: None, piece_id: 'r', promotion: None, player: 1 },
+        ]);
+        // Explosion, the capturing piece is also removed
+        assert_eq!(engine.set_state(GameState::from_debug_fen("4k3/p7/8/4n3/8/5N2/P7/4K3 w - - 0 1 atomic")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(changes(&mut engine, "f3e5"), vec![
+            SquareChange { from: Some((5, 2)), to: None, piece_id: 'N', promotion: None, player: 0 },
+            SquareChange { from: Some((4, 4)), to: None, piece_id: 'n', promotion: None, player: 1 },
+        ]);
+    }
+
+    #[test]
+    fn feature_planes() {
+        let position = |state: GameState| PositionFactory::default().set_state(state, None).unwrap().unwrap();
+        let start = position(GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"));
+        let planes = start.to_feature_planes();
+        assert_eq!((planes.batch_size, planes.num_planes, planes.height, planes.width), (1, 12 + NUM_AUX_PLANES, 8, 8));
+        assert_eq!(planes.data.len(), planes.num_planes * 64);
+        let plane = |planes: &FeaturePlanes, b: usize, p: usize| planes.data[(b * planes.num_planes + p) * 64..(b * planes.num_planes + p + 1) * 64].to_vec();
+        let count = |values: Vec<f32>| values.iter().filter(|v| **v == 1.0).count();
+        let white_pawns = planes.piece_types.iter().position(|t| *t == (0, 'P')).unwrap();
+        let pawns = plane(&planes, 0, white_pawns);
+        assert_eq!(count(pawns.clone()), 8);
+        assert_eq!(pawns[8..16], [1.0; 8]);
+        // Castling (king and rooks of each player), en passant, side to move, checks and walls
+        let aux = planes.piece_types.len();
+        assert_eq!(count(plane(&planes, 0, aux)), 3);
+        assert_eq!(count(plane(&planes, 0, aux + 1)), 3);
+        for p in aux + 2..planes.num_planes {
+            assert_eq!(count(plane(&planes, 0, p)), 0);
+        }
+
+        let ep = position(GameState::from_debug_fen("4k3/8/8/8/3pP3/8/8/4K3 b - e3 0 1"));
+        let batch = Position::batch_feature_planes(&[start.clone(), ep]).unwrap();
+        assert_eq!(batch.batch_size, 2);
+        assert_eq!(batch.data[..planes.data.len()], planes.data[..]);
+        let ep_plane = plane(&batch, 1, aux + 2);
+        assert_eq!(count(ep_plane.clone()), 1);
+        assert_eq!(ep_plane[2 * 8 + 4], 1.0);
+        assert_eq!(plane(&batch, 1, aux + 3), vec![1.0; 64]);
+
+        assert!(Position::batch_feature_planes(&[]).is_err());
+        let capablanca = position(GameState::from_variant_name("capablanca").unwrap());
+        assert!(Position::batch_feature_planes(&[start, capablanca]).is_err());
+    }
+
+    // Copy of the board, kept up to date with the changes of the position
+    #[derive(Clone, Default)]
+    struct BoardMirror(HashMap<BIndex, (PieceId, Player)>);
+    impl PositionListener for BoardMirror {
+        fn reset(&mut self, position: &Position) {
+            self.0.clear();
+            for y in 0..position.dimensions.height {
+                for x in 0..position.dimensions.width {
+                    if let Some(piece) = position.piece_at(to_index(x, y)) {
+                        self.0.insert(to_index(x, y), (piece.get_piece_id(), piece.get_player()));
+                    }
+                }
+            }
+        }
+        fn piece_changed(&mut self, change: PieceChange) {
+            match change {
+                PieceChange::Add { piece_id, player, index } => assert_eq!(self.0.insert(index, (piece_id, player)), None),
+                PieceChange::Remove { piece_id, player, index } => assert_eq!(self.0.remove(&index), Some((piece_id, player))),
+                PieceChange::Move { piece_id, player, from, to } => {
+                    assert_eq!(self.0.remove(&from), Some((piece_id, player)));
+                    assert_eq!(self.0.insert(to, (piece_id, player)), None);
+                },
+            }
+        }
+        fn clone_box(&self) -> Box<dyn PositionListener> {
+            Box::new(self.clone())
+        }
+    }
+
+    #[test]
+    fn position_listener() {
+        let check = |position: &Position| {
+            let mut expected = BoardMirror::default();
+            expected.reset(position);
+            assert_eq!(position.get_listener::<BoardMirror>().unwrap().0, expected.0, "{}", position);
+        };
+        let fens = [
+            "r3k2r/1P6/8/3pP3/8/8/8/R3K2R w KQkq d6 0 1",
+            "4k3/p7/8/3pn3/8/5N2/P7/4K3 w - - 0 1 atomic",
+        ];
+        for fen in fens {
+            let mut position = PositionFactory::default().set_state(GameState::from_debug_fen(fen), None).unwrap().unwrap();
+            position.set_listener(Some(Box::new(BoardMirror::default())));
+            check(&position);
+            for mv in MoveGen::get_legal_moves(&mut position) {
+                position.make_move(mv);
+                check(&position);
+                // Clones have their own copy of the listener
+                let mut clone = position.clone();
+                for reply in MoveGen::get_legal_moves(&mut clone) {
+                    clone.make_move(reply);
+                    check(&clone);
+                    clone.unmake_move();
+                }
+                position.unmake_move();
+                check(&position);
+            }
+        }
+        let mut position = PositionFactory::default().set_state(GameState::from_debug_fen(fens[0]), None).unwrap().unwrap();
+        position.set_listener(Some(Box::new(BoardMirror::default())));
+        position.public_remove_piece(to_index(0, 0)).unwrap();
+        position.public_add_piece('Q', to_index(3, 3), false).unwrap();
+        check(&position);
+        assert!(position.take_listener().is_some());
+        assert!(position.get_listener::<BoardMirror>().is_none());
+    }
+
+    #[test]
+    fn render_position() {
+        let mut engine = Engine::default();
+        let options = RenderOptions::default();
+        let text = engine.render_text(&options);
+        assert!(text.starts_with("8 ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜\n7 ♟ ♟"));
+        assert!(text.contains("\n4 . . . . . . . .\n"));
+        assert!(text.ends_with("\n  a b c d e f g h\nWhite to move"));
+        let flipped = engine.render_text(&RenderOptions { flipped: true, coordinates: false, ..options.clone() });
+        assert!(flipped.starts_with("♖ ♘ ♗ ♔ ♕ ♗ ♘ ♖\n"));
+        
+        // Walls, pieces without glyphs and pieces in hand
+        let mut state = GameState::from_variant_name("crazyhouse").unwrap();
+        state.initial_state.fen = "**k7/10/10/10/10/10/10/10/10/Q7**[Nn] w - - 0 1".to_string();
+        state.initial_state.board_width = 10;
+        state.initial_state.board_height = 10;
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let text = engine.render_text(&options);
+        assert!(text.starts_with("10 # # ♚ . . . . . . .\n"));
+        assert!(text.contains("\n 1 ♕ . . . . . . . # #\n   a b c d e f g h i j\n"));
+        assert!(text.ends_with("White in hand: ♘\nBlack in hand: ♞\nWhite to move"));
+        
+        let svg = engine.render_svg(&options);
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"472\" height=\"472\""));
+        assert_eq!(svg.matches("<rect").count(), 100);
+        assert_eq!(svg.matches("fill=\"#555555\"").count(), 4);
+        assert!(svg.contains("class=\"piece black king\" fill=\"#000000\">♚</text>"));
+        assert!(svg.trim_end().ends_with("</svg>"));
+        
+        let mut state = GameState::from_debug_fen("4k3/8/8/8/8/8/8/4K2A w - - 0 1");
+        let mut amazon = PieceFactory::default().make_queen();
+        amazon.ids = [Some('A'), Some('a')];
+        amazon.attack_jump_deltas.push((1, 2));
+        amazon.display = None;
+        state.initial_state.piece_types.push(amazon);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.render_text(&options).contains("\n1 . . . . ♔ . . A\n"));
+        assert!(engine.render_svg(&options).contains("class=\"piece white\" fill=\"#ffffff\" stroke=\"#000000\" font-weight=\"bold\">A</text>"));
+    }
+
+    #[test]
+    fn halfmove_clock() {
+        let mut engine = Engine::default();
+        engine.load_fen("4k3/4p3/8/8/8/8/8/1N2K3 w - - 97 80").unwrap();
+        assert!(engine.get_state_diff().fen.ends_with(" 97 80"));
+        // A pawn move resets the clock, and the fullmove number is incremented after Black moves
+        assert_eq!(engine.make_move_str("b1c3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.ends_with(" 98 80"));
+        assert_eq!(engine.make_move_str("e7e6").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.ends_with(" 0 81"));
+
+        engine.load_fen("4k3/4p3/8/8/8/8/8/1N2K3 w - - 97 80").unwrap();
+        assert_eq!(engine.make_move_str("b1c3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e8d8").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("c3d5").unwrap().flag, MakeMoveResultFlag::NoProgress);
+        
+        // The rules saved before the rule existed use the 50-move rule of the built-in variants
+        let mut json = serde_json::to_value(GlobalRules::default()).unwrap();
+        json.as_object_mut().unwrap().remove("movesWithoutProgressDraw").unwrap();
+        let rules: GlobalRules = serde_json::from_value(json).unwrap();
+        assert_eq!(rules, GlobalRules::default());
+        assert_eq!(rules.moves_without_progress_draw, 50);
+        
+        // In the states saved before the pieces had `resetsHalfmoveClock`, it's set for the pieces that can
+        // promote or only move forward (the custom pawns)
+        let mut json = serde_json::to_value(GameState::from_debug_fen("4k3/4p3/8/8/8/8/8/1N2K3 w - - 97 80")).unwrap();
+        let initial_state = json["initialState"].as_object_mut().unwrap();
+        initial_state["globalRules"].as_object_mut().unwrap().remove("movesWithoutProgressDraw").unwrap();
+        for piece in initial_state["pieceTypes"].as_array_mut().unwrap() {
+            piece.as_object_mut().unwrap().remove("resetsHalfmoveClock").unwrap();
+        }
+        let state: GameState = serde_json::from_value(json).unwrap();
+        assert!(state.initial_state.piece_types.iter().all(|piece| piece.resets_halfmove_clock.is_none()));
+        let _result = engine.set_state(state).unwrap();
+        assert_eq!(engine.make_move_str("b1c3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.ends_with(" 98 80"));
+        assert_eq!(engine.make_move_str("e7e6").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.ends_with(" 0 81"));
+    }
+
+    #[test]
+    fn insufficient_material() {
+        let check_capture = |fen: &str, mv: &str, expected: MakeMoveResultFlag| {
+            let mut engine = Engine::default();
+            engine.load_fen(fen).unwrap();
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, expected, "{fen} {mv}");
+        };
+        // K vs K
+        check_capture("4k3/8/8/8/8/8/3n4/4K3 w - - 0 1", "e1d2", MakeMoveResultFlag::InsufficientMaterial);
+        // K+N vs K and K+B vs K
+        check_capture("4k3/8/8/8/8/8/3n4/4K1N1 w - - 0 1", "e1d2", MakeMoveResultFlag::InsufficientMaterial);
+        check_capture("4k3/8/8/8/8/8/3n4/4KB2 w - - 0 1", "e1d2", MakeMoveResultFlag::InsufficientMaterial);
+        // K+B vs K+B with bishops on the same color
+        check_capture("2b1k3/8/8/8/8/8/3n4/4KB2 w - - 0 1", "e1d2", MakeMoveResultFlag::InsufficientMaterial);
+        // Enough material to checkmate
+        check_capture("4kb2/8/8/8/8/8/3n4/3K1B2 w - - 0 1", "d1d2", MakeMoveResultFlag::Ok);
+        check_capture("4k3/8/8/8/8/8/3n4/4K1NN w - - 0 1", "e1d2", MakeMoveResultFlag::Ok);
+        check_capture("4k3/8/8/8/8/8/3n4/4K2R w - - 0 1", "e1d2", MakeMoveResultFlag::Ok);
+        check_capture("4k3/p7/8/8/8/8/3n4/4K3 w - - 0 1", "e1d2", MakeMoveResultFlag::Ok);
+        
+        // Custom pieces are only considered if they move like a knight or a bishop
+        let check_custom_capture = |betza: &str, expected: MakeMoveResultFlag| {
+            let mut state = GameState::default();
+            state.initial_state.piece_types.push(PieceDefinition::from_betza(betza, [Some('X'), Some('x')]).unwrap());
+            state.initial_fen = Some("4k3/8/8/8/8/8/3n4/4K1X1 w - - 0 1".to_string());
+            let mut engine = Engine::default();
+            assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+            assert_eq!(engine.make_move_str("e1d2").unwrap().flag, expected, "{betza}");
+        };
+        check_custom_capture("N", MakeMoveResultFlag::InsufficientMaterial);
+        check_custom_capture("B", MakeMoveResultFlag::InsufficientMaterial);
+        // A wazir can only attack squares of the other color (like a knight), but it can checkmate with the king
+        check_custom_capture("W", MakeMoveResultFlag::Ok);
+        check_custom_capture("F", MakeMoveResultFlag::Ok);
+
+        // The searcher scores the dead position as a draw
+        let mut engine = Engine::default();
+        engine.load_fen("4k3/8/8/8/8/8/3n4/4K3 w - - 0 1").unwrap();
+        let (mv, score, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "e1d2");
+        assert_eq!(score, 0);
+    }
+
+    #[test]
+    fn crazyhouse_drops() {
+        let mut engine = Engine::default();
+        let state = GameState::from_debug_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 CRAZYHOUSE");
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[] w"));
+        for mv in ["e4", "d5", "exd5", "Qxd5"] {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok, "{mv}");
+        }
+        // Each player has captured a pawn
+        assert!(engine.get_state_diff().fen.starts_with("rnb1kbnr/ppp1pppp/8/3q4/8/8/PPPP1PPP/RNBQKBNR[Pp] w"));
+        assert_eq!(engine.make_move_str("P@e4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_move_history().last().unwrap(), "P@e4");
+        // Black drops using the piece id
+        let black_drop = MoveInfo::try_from("p@e5").unwrap();
+        assert_eq!(black_drop, MoveInfo::drop('p', (4, 4)));
+        assert_eq!(black_drop.to_string(), "p@e5");
+        assert_eq!(engine.make_move(&black_drop).flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("rnb1kbnr/ppp1pppp/8/3qp3/4P3/8/PPPP1PPP/RNBQKBNR[] w"));
+        // The hands are empty
+        assert_eq!(engine.make_move_str("P@e3").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("rnb1kbnr/ppp1pppp/8/3q4/4P3/8/PPPP1PPP/RNBQKBNR[p] b"));
+        
+        // Pieces in hand as an extra rank, pawns cannot be dropped on the first or last rank
+        engine.load_fen("4k3/8/8/8/8/8/8/4K3/PNn w - - 0 1").unwrap();
+        assert_eq!(engine.get_state_diff().fen, "4k3/8/8/8/8/8/8/4K3[NPn] w - - 0 1");
+        assert_eq!(engine.make_move_str("P@a1").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("P@a8").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("N@d6+").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_move_history().last().unwrap(), "N@d6+");
+        assert!(engine.load_fen("4k3/8/8/8/8/8/8/4K3[X] w - - 0 1").is_err());
+        assert!(engine.load_fen("4k3/8/8/8/8/8/8/4K3[P w - - 0 1").is_err());
+        
+        // The searcher finds a mate by dropping a piece
+        engine.load_fen("6rk/6pp/8/8/8/8/8/K7[N] w - - 0 1").unwrap();
+        let (mv, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv, MoveInfo::drop('N', (5, 6)));
+    }
+
+    #[test]
+    fn optional_promotion_and_demotion() {
+        let mut state = GameState::from_debug_fen("8/4k3/3n4/4P3/8/8/8/4K3 w - - 0 1 CRAZYHOUSE");
+        // Pawns promote to a non-leader king ('G') in the last 3 ranks, and it demotes back to a pawn when captured
+        let mut gold = PieceFactory::new(GameMode::Standard).make_king(8, 8);
+        gold.ids = [Some('G'), Some('g')];
+        gold.notation_prefix = [Some("G".to_string()), Some("G".to_string())];
+        gold.is_leader = false;
+        gold.castle_files = None;
+        gold.demotes_to = Some('P');
+        for definition in &mut state.initial_state.piece_types {
+            let zone_ranks = match definition.ids {
+                [Some('P'), _] => 5..8,
+                [_, Some('p')] => 0..3,
+                _ => continue,
+            };
+            definition.promotion_squares = (0..8).flat_map(|x| zone_ranks.clone().map(move |y| (x, y))).collect();
+            definition.promo_vals = [vec!['G'], vec!['g']];
+            definition.promotion_is_optional = true;
+        }
+        state.initial_state.piece_types.push(gold);
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        for mv in ["e5e6", "e5e6=G", "e5d6", "e5d6=G"] {
+            assert!(moves.contains(&mv.to_string()), "{}", mv);
+        }
+        assert_eq!(engine.make_move_str("e5d6=G").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e7d6").unwrap().flag, MakeMoveResultFlag::Ok);
+        // White captured a knight, black captured a promoted pawn
+        assert!(engine.get_state_diff().fen.starts_with("8/8/3k4/8/8/8/8/4K3[Np] w"));
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("8/4k3/3G4/8/8/8/8/4K3[N] b"));
+    }
+
+    #[test]
+    fn sliding_delta_promotions() {
+        // A nightrider that promotes to a queen on the last rank: its promotions are generated by the sliding deltas
+        let mut state = GameState::from_debug_fen("4k3/8/8/8/8/8/X7/4K3 w - - 0 1");
+        let mut nightrider = PieceDefinition::from_betza("NN", [Some('X'), Some('x')]).unwrap();
+        nightrider.promotion_squares = (0..8).map(|x| (x, 7)).collect();
+        nightrider.promo_vals = [vec!['Q'], vec!['q']];
+        state.initial_state.piece_types.push(nightrider);
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        assert!(moves.contains(&"a2d8=Q".to_string()));
+        assert!(!moves.contains(&"a2d8".to_string()));
+        assert_eq!(engine.make_move_str("a2d8=Q").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("3Qk3/8/8/8/8/8/8/4K3 b"));
+    }
+
+    #[test]
+    fn max_moves_and_frozen_pieces() {
+        let mut state = GameState::from_debug_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1");
+        // Rooks can only move once
+        for definition in &mut state.initial_state.piece_types {
+            if definition.is_castle_rook {
+                definition.max_moves = Some(1);
+            }
+        }
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a1a4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e8d8").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a4a5").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        // Pieces that cannot move anymore are stored as frozen
+        assert!(engine.get_state_diff().fen.starts_with("3k4/8/8/8/R!7/8/8/4K3 w"));
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.make_move_str("a1a5").unwrap().flag, MakeMoveResultFlag::Ok);
+
+        // Frozen pieces don't attack, and they can be captured
+        engine.load_fen("4k3/8/8/8/8/8/4q!3/4K3 w - - 0 1").unwrap();
+        assert_eq!(engine.make_move_str("e1d1").unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        assert!(!moves.is_empty() && moves.iter().all(|mv| mv.starts_with("e8")));
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/4q!3/3K4 b"));
+        assert_eq!(engine.make_move_str("e8d8").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("d1e2").unwrap().flag, MakeMoveResultFlag::InsufficientMaterial);
+        assert!(engine.load_fen("!4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(engine.load_fen("4k3/8/8/8/8/8/4q1!2/4K3 w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn multiple_leaders() {
+        let make_state = |fen: &str, royal_ids: &[char], rule: LeaderCaptureRule| {
+            let mut state = GameState::from_debug_fen(fen);
+            for definition in &mut state.initial_state.piece_types {
+                if royal_ids.iter().any(|id| definition.ids.contains(&Some(*id))) {
+                    definition.is_leader = true;
+                }
+            }
+            state.initial_state.global_rules.leader_capture_rule = rule;
+            state
+        };
+        let mut engine = Engine::default();
+        // The queen is also a leader, white only loses when both leaders are captured
+        let fen = "4k3/8/8/8/8/8/3r4/3QK3 w - - 0 1";
+        assert_eq!(engine.set_state(make_state(fen, &['Q'], LeaderCaptureRule::AllLeaders)).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.get_state_diff().in_check);
+        assert_eq!(engine.make_move_str("e1f1").unwrap().flag, MakeMoveResultFlag::Ok);
+
+        // Two-Kings: white loses if the queen is captured
+        assert_eq!(engine.set_state(make_state(fen, &['Q'], LeaderCaptureRule::AnyLeader)).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().in_check);
+        assert_eq!(engine.make_move_str("e1f1").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("d1d2").unwrap().flag, MakeMoveResultFlag::Ok);
+
+        // Extinction: capturing the last knight wins, the piece types that are not on the board are ignored
+        let fen = "4k3/8/8/8/8/1n6/8/1R2K3 w - - 0 1";
+        let all_ids = ['K', 'Q', 'R', 'B', 'N', 'P', 'k', 'q', 'r', 'b', 'n', 'p'];
+        assert_eq!(engine.set_state(make_state(fen, &all_ids, LeaderCaptureRule::AnyLeaderType)).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.get_state_diff().in_check);
+        let result = engine.make_move_str("b1b3").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::LeaderCaptured);
+        assert_eq!(result.winner, MakeMoveResultWinner::White);
+    }
+
+    #[test]
+    fn capture_modes() {
+        let make_state = |fen: &str, id: char, mode: CaptureMode| {
+            let mut state = GameState::from_debug_fen(fen);
+            for definition in &mut state.initial_state.piece_types {
+                if definition.ids.contains(&Some(id)) {
+                    definition.capture_mode = mode;
+                }
+            }
+            state
+        };
+        let mut engine = Engine::default();
+        // Rifle: the rook captures without moving
+        let state = make_state("4k3/8/8/n7/8/8/8/R3K3 w - - 0 1", 'R', CaptureMode::RifleStay);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("Rxa5").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/R3K3 b"));
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/n7/8/8/8/R3K3 w"));
+
+        // Withdraw: the queen captures the adjacent pawn by moving away from it
+        let state = make_state("4k3/8/8/8/3pQ3/8/8/4K3 w - - 0 1", 'Q', CaptureMode::Withdraw);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.san).collect();
+        for mv in ["Qxf4", "Qxh4", "Qe5"] {
+            assert!(moves.contains(&mv.to_string()), "{}", mv);
+        }
+        // The capture is not optional
+        assert!(!moves.contains(&"Qf4".to_string()));
+        assert_eq!(engine.make_move_str("e4g4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/6Q1/8/8/4K3 b"));
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/3pQ3/8/8/4K3 w"));
+        // The withdrawer only attacks adjacent pieces
+        let state = make_state("4k3/4Q3/8/8/8/8/8/4K3 b - - 0 1", 'Q', CaptureMode::Withdraw);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().in_check);
+        let state = make_state("4k3/8/4Q3/8/8/8/8/4K3 b - - 0 1", 'Q', CaptureMode::Withdraw);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.get_state_diff().in_check);
+
+        // Advance: the rook captures the knight by moving next to it
+        let state = make_state("4k3/8/8/8/8/8/8/R2n2K1 w - - 0 1", 'R', CaptureMode::Advance);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a1c1").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/2R3K1 b"));
+        engine.undo().unwrap();
+        assert_eq!(engine.make_move_str("a1b1").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/1R1n2K1 b"));
+    }
+
+    #[test]
+    fn hopping_pieces() {
+        let make_state = |fen: &str, piece: PieceDefinition| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.piece_types.push(piece);
+            state
+        };
+        let hops = |directions: &[(i8, i8)], landing: HopLanding| -> Vec<HopMove> {
+            directions.iter().map(|&direction| HopMove { direction, landing }).collect()
+        };
+        let orthogonal = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+        let all_directions = [(0, 1), (1, 0), (0, -1), (-1, 0), (1, 1), (-1, 1), (1, -1), (-1, -1)];
+        
+        // Cannon: moves like a rook, captures by jumping over a screen
+        let mut cannon = PieceFactory::new(GameMode::Standard).make_rook();
+        cannon.ids = [Some('C'), Some('c')];
+        cannon.notation_prefix = [Some("C".to_string()), Some("C".to_string())];
+        cannon.is_castle_rook = false;
+        cannon.attack_north = false;
+        cannon.attack_south = false;
+        cannon.attack_east = false;
+        cannon.attack_west = false;
+        cannon.attack_hops = hops(&orthogonal, HopLanding::AnyAfterScreen);
+        let mut engine = Engine::default();
+        let state = make_state("4k3/n7/8/8/p7/8/8/C3K3 w - - 0 1", cannon.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a1a4").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("a1a5").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("Cxa7").unwrap().flag, MakeMoveResultFlag::Ok);
+        // The cannon only gives check through a screen
+        let state = make_state("4k3/8/8/8/4p3/8/8/4C1K1 b - - 0 1", cannon.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().in_check);
+        let state = make_state("4k3/8/8/8/8/8/8/4C1K1 b - - 0 1", cannon.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.get_state_diff().in_check);
+        
+        // Grasshopper: lands on the square immediately after the first piece in any direction
+        let mut grasshopper = PieceDefinition::default();
+        grasshopper.ids = [Some('G'), Some('g')];
+        grasshopper.translate_hops = hops(&all_directions, HopLanding::ImmediatelyAfter);
+        grasshopper.attack_hops = hops(&all_directions, HopLanding::ImmediatelyAfter);
+        let state = make_state("4k3/8/8/8/8/2p5/8/G3K3 w - - 0 1", grasshopper.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let mut moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).filter(|mv| mv.starts_with("a1")).collect();
+        moves.sort();
+        assert_eq!(moves, vec!["a1d4", "a1f1"]);
+        let state = make_state("4k3/8/8/8/3n4/2p5/8/G3K3 w - - 0 1", grasshopper.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("Gxd4").unwrap().flag, MakeMoveResultFlag::Ok);
+        
+        // Hops must follow one of the main directions
+        grasshopper.translate_hops.push(HopMove { direction: (2, 1), landing: HopLanding::ImmediatelyAfter });
+        assert!(engine.set_state(make_state("4k3/8/8/8/8/8/8/G3K3 w - - 0 1", grasshopper)).is_err());
+    }
+
+    #[test]
+    fn seirawan_gating() {
+        let gating_state = |fen: &str| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.global_rules.gating = true;
+            state
+        };
+        let mut engine = Engine::default();
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Nn] w (ALL) - 0 1";
+        assert_eq!(engine.set_state(gating_state(fen)).unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        for mv in ["g1f3", "g1f3=N", "b1c3=N", "e2e4"] {
+            assert!(moves.contains(&mv.to_string()), "{}", mv);
+        }
+        // Pieces in hand cannot be dropped, and pawns are not on the back rank
+        assert!(!moves.iter().any(|mv| mv.contains('@') || mv.starts_with("e2e4=")));
+        assert_eq!(engine.make_move_str("Nf3/N").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_move_history().last().unwrap(), "Nf3/N");
+        assert!(engine.get_state_diff().fen.starts_with("rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKBNR[n] b"));
+        assert_eq!(engine.make_move(&MoveInfo::try_from("b8c6=n").unwrap()).flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("rnbqkbnr/pppppppp/2n5/8/8/5N2/PPPPPPPP/RNBQKBNR[] w"));
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR[Nn] w"));
+
+        // Gating while castling places the piece on the square of the king
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R[Nn] w KQkq - 0 1";
+        assert_eq!(engine.set_state(gating_state(fen)).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("O-O/N").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("r3k2r/8/8/8/8/8/8/R3NRK1[n] b"));
+        // Pieces that have already moved cannot gate
+        assert_eq!(engine.make_move_str("Kd8").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a1a2").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("d8e8=n").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        assert_eq!(engine.make_move_str("a8a7=n").unwrap().flag, MakeMoveResultFlag::Ok);
+    }
+
+    #[test]
+    fn leaders_cannot_face() {
+        let facing_state = |fen: &str| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.global_rules.leaders_cannot_face = true;
+            state
+        };
+        let mut engine = Engine::default();
+        // The rook cannot leave the file, since the kings would face each other
+        assert_eq!(engine.set_state(facing_state("4k3/8/8/8/4R3/8/8/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.make_move_str("Ra4").is_err());
+        assert_eq!(engine.make_move_str("Re5").unwrap().flag, MakeMoveResultFlag::Ok);
+
+        // The kings cannot face each other on a diagonal either
+        assert_eq!(engine.set_state(facing_state("7k/p7/8/8/8/8/8/1K6 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.san).collect();
+        assert!(!moves.contains(&"Ka1".to_string()));
+        assert!(!moves.contains(&"Kb2".to_string()));
+        assert!(moves.contains(&"Kc1".to_string()));
+        // Without the rule, the moves are legal
+        assert_eq!(engine.set_state(GameState::from_debug_fen("7k/p7/8/8/8/8/8/1K6 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("Ka1").unwrap().flag, MakeMoveResultFlag::Ok);
+    }
+
+    #[test]
+    fn multiple_moves_per_turn() {
+        // Marseillais chess: 2 moves per turn, except the first turn of the game
+        let mut state = GameState::default();
+        state.initial_state.global_rules.moves_per_turn = 2;
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let expected_players = [("e4", 1), ("e5", 1), ("d5", 0), ("d4", 0), ("Nf3", 1), ("h6", 1), ("Nf6", 0)];
+        for (mv, player) in expected_players {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+            assert_eq!(engine.player_to_move(), player, "{}", mv);
+        }
+        // A check ends the turn
+        assert_eq!(engine.make_move_str("Bb5").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.player_to_move(), 1);
+        // The search and undo keep track of the turns
+        let (best_move, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_ne!(engine.make_move(&best_move).flag, MakeMoveResultFlag::IllegalMove);
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.player_to_move(), 1);
+        assert_eq!(engine.make_move_str("Nc6").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.player_to_move(), 0);
+
+        // Progressive chess: 1 move for White, 2 for Black, 3 for White...
+        let mut state = GameState::default();
+        state.initial_state.global_rules.progressive = true;
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let expected_players = [("e4", 1), ("e5", 1), ("d5", 0), ("d4", 0), ("Nf3", 0), ("Nc3", 1)];
+        for (mv, player) in expected_players {
+            assert_eq!(engine.make_move_str(mv).unwrap().flag, MakeMoveResultFlag::Ok);
+            assert_eq!(engine.player_to_move(), player, "{}", mv);
+        }
+        let (best_move, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_ne!(engine.make_move(&best_move).flag, MakeMoveResultFlag::IllegalMove);
+    }
+
+    #[test]
+    fn passing_allowed() {
+        let passing_state = |fen: &str| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.global_rules.passing_allowed = true;
+            state
+        };
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(passing_state("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves = engine.legal_moves_str();
+        assert!(moves.iter().any(|mv| mv.long == "0000" && mv.san == "--"));
+        assert_eq!(engine.make_move_str("0000").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.player_to_move(), 1);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/4P3/4K3 b"));
+        assert_eq!(engine.get_move_history().last().unwrap(), "--");
+        assert_eq!(engine.make_move_str("--").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.player_to_move(), 0);
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.player_to_move(), 0);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/4P3/4K3 w"));
+        let (best_move, _, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        assert_ne!(engine.make_move(&best_move).flag, MakeMoveResultFlag::IllegalMove);
+
+        // A player in check cannot pass
+        assert_eq!(engine.set_state(passing_state("4k3/8/8/8/8/8/4r3/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.legal_moves_str().iter().any(|mv| mv.long == "0000"));
+        assert_eq!(engine.make_move_str("0000").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+        // Passing is not allowed by default
+        assert_eq!(engine.set_state(GameState::from_debug_fen("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("0000").unwrap().flag, MakeMoveResultFlag::IllegalMove);
+
+        // Passing repeatedly is a draw by repetition
+        assert_eq!(engine.set_state(passing_state("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        for _ in 0..3 {
+            assert_eq!(engine.make_move_str("0000").unwrap().flag, MakeMoveResultFlag::Ok);
+        }
+        assert_eq!(engine.make_move_str("0000").unwrap().flag, MakeMoveResultFlag::Repetition);
+    }
+
+    #[test]
+    fn configurable_castling() {
+        let castling_state = |fen: &str, height: u8, rules: &[CastlingRule], partner: char| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.board_height = height;
+            for definition in &mut state.initial_state.piece_types {
+                if definition.is_leader {
+                    definition.castle_files = None;
+                    definition.castling_rules = rules.to_vec();
+                }
+                definition.is_castle_rook = definition.ids[0] == Some(partner);
+            }
+            state
+        };
+        let rule = |direction, destination, partner_destination, partners: &[char]| {
+            CastlingRule { direction, destination, partner_destination, partners: partners.to_vec() }
+        };
+        let mut engine = Engine::default();
+        
+        // The king swaps places with the kingside rook, and ends on the b-file when castling queenside
+        let rules = [rule((1, 0), 7, 6, &[]), rule((-1, 0), 1, 2, &[])];
+        let state = castling_state("r3k3/8/8/8/8/8/8/R3K2R w KQq - 0 1", 8, &rules, 'R');
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("O-O").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("r3k3/8/8/8/8/8/8/R5RK b"));
+        assert_eq!(engine.make_move_str("O-O-O").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("1kr5/8/8/8/8/8/8/R5RK w"));
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        assert!(engine.get_state_diff().fen.starts_with("r3k3/8/8/8/8/8/8/R3K2R w"));
+        
+        // Only the listed partners can be used
+        let rules = [rule((1, 0), 6, 5, &['R', 'r']), rule((-1, 0), 2, 3, &['N', 'n'])];
+        let state = castling_state("4k3/8/8/8/8/8/8/1N2K2R w (b1,e1,h1) - 0 1", 8, &rules, 'N');
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.make_move_str("O-O").is_err());
+        assert_eq!(engine.make_move_str("O-O-O").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/2KN3R b"));
+        
+        // Vertical castling on a tall board, mirrored for black. The castling rights can mix both formats.
+        let rules = [rule((0, 1), 3, 2, &[])];
+        let fen = "3k4/8/8/8/3r4/4R3/8/8/8/4K3 w (e1,e5)(d10,d6) - 0 1";
+        assert_eq!(engine.set_state(castling_state(fen, 10, &rules, 'R')).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("O-O^").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("3k4/8/8/8/3r4/8/4K3/4R3/8/8 b"));
+        assert_eq!(engine.make_move_str("O-Ov").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("8/8/3r4/3k4/8/8/4K3/4R3/8/8 w"));
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.validate_position().unwrap();
+        // The king cannot castle through check
+        let fen = "3k4/8/8/8/3r3b/4R3/8/8/8/4K3 w (e1,e5) - 0 1";
+        assert_eq!(engine.set_state(castling_state(fen, 10, &rules, 'R')).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.make_move_str("O-O^").is_err());
+        
+        // Two rules in the same direction are not allowed
+        let rules = [rule((1, 0), 6, 5, &[]), rule((1, 0), 5, 4, &[])];
+        assert!(engine.set_state(castling_state("4k3/8/8/8/8/8/8/4K2R w K - 0 1", 8, &rules, 'R')).is_err());
+    }
+
+    #[test]
+    fn long_double_jumps() {
+        // On a tall board, pawns that haven't moved can advance up to 3 squares
+        let tall_state = |fen: &str| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.board_height = 12;
+            for definition in &mut state.initial_state.piece_types {
+                if definition.can_double_jump() {
+                    definition.double_jump_steps = Some(3);
+                }
+            }
+            state
+        };
+        let fen = "4k3/8/8/8/8/8/8/3p4/5p2/8/4P3/4K3 w - - 0 1";
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(tall_state(fen)).unwrap().flag, MakeMoveResultFlag::Ok);
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        for mv in ["e2e3", "e2e4", "e2e5"] {
+            assert!(moves.contains(&mv.to_string()), "{}", mv);
+        }
+        assert_eq!(engine.make_move_str("e2e4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.contains(" e3(e4) "));
+        engine.undo().unwrap();
+        
+        // Both squares passed over can be captured en passant
+        assert_eq!(engine.make_move_str("e2e5").unwrap().flag, MakeMoveResultFlag::Ok);
+        let fen = engine.get_state_diff().fen;
+        assert!(fen.contains(" e3,e4(e5) "));
+        let moves: Vec<String> = engine.legal_moves_str().into_iter().map(|mv| mv.long).collect();
+        assert!(moves.contains(&"d5e4".to_string()) && moves.contains(&"f4e3".to_string()));
+        assert_eq!(engine.make_move_str("f4e3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/3p4/8/4p3/8/4K3 w"));
+        
+        // The EP squares are restored from the FEN string
+        assert_eq!(engine.set_state(tall_state(&fen)).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("dxe4 e.p.").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.get_state_diff().fen.starts_with("4k3/8/8/8/8/8/8/8/4pp2/8/8/4K3 w"));
+        assert!(engine.set_state(tall_state("4k3/8/8/8/8/8/8/3p4/5p2/8/4P3/4K3 w - e3,e4,f6 0 1")).is_err());
+    }
+
+    #[test]
+    fn win_conditions() {
+        let goal_state = |fen: &str, white: Vec<WinCondition>, black: Vec<WinCondition>| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.global_rules.win_conditions = [white, black];
+            state
+        };
+        let center = vec![(3, 3), (4, 3), (3, 4), (4, 4)];
+        let mut engine = Engine::default();
+        
+        // Any piece of the player reaches the region
+        let region = vec![WinCondition::ReachRegion { squares: center.clone() }];
+        let state = goal_state("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", vec![], region.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e3").unwrap().flag, MakeMoveResultFlag::Ok);
+        let state = goal_state("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", region, vec![]);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let result = engine.make_move_str("e4").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::WinConditionMet);
+        assert_eq!(result.winner, MakeMoveResultWinner::White);
+        assert_eq!(result.move_notation.unwrap(), "e4#");
+        
+        // Capture the flag
+        let flag = vec![WinCondition::CapturePiece { piece: 'q' }];
+        let state = goal_state("4k3/8/8/8/8/8/q6r/R3K3 b - - 0 1", vec![], flag.clone());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("h2h3").unwrap().flag, MakeMoveResultFlag::Ok);
+        let state = goal_state("4k3/8/8/8/8/8/q6r/R3K3 w - - 0 1", flag, vec![]);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let result = engine.make_move_str("a1a2").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::WinConditionMet);
+        assert_eq!(result.winner, MakeMoveResultWinner::White);
+        
+        // A specific piece reaches a square, the search finds the winning move
+        let corner = vec![WinCondition::PieceReachesSquare { piece: 'N', squares: vec![(7, 7)] }];
+        let state = goal_state("4k3/8/6N1/8/8/8/8/4K3 w - - 0 1", corner, vec![]);
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        let (mv, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "g6h8");
+        assert_eq!(engine.make_move(&mv).flag, MakeMoveResultFlag::WinConditionMet);
+        
+        // The pieces and squares must exist
+        let missing = vec![WinCondition::CapturePiece { piece: 'z' }];
+        assert!(engine.set_state(goal_state("4k3/8/8/8/8/8/8/4K3 w - - 0 1", missing, vec![])).is_err());
+        let outside = vec![WinCondition::ReachRegion { squares: vec![(8, 0)] }];
+        assert!(engine.set_state(goal_state("4k3/8/8/8/8/8/8/4K3 w - - 0 1", vec![], outside)).is_err());
+    }
+
+    #[test]
+    fn capture_and_move_limits() {
+        let mut engine = Engine::default();
+        
+        // First player to capture 2 pieces wins
+        let mut state = GameState::from_debug_fen("4k3/8/8/8/8/8/pp6/R3K3 w - - 0 1");
+        state.initial_state.global_rules.captures_to_win = 2;
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("a1a2").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e8d8").unwrap().flag, MakeMoveResultFlag::Ok);
+        let (mv, _, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "a2b2");
+        let result = engine.make_move(&mv);
+        assert_eq!(result.flag, MakeMoveResultFlag::CaptureLimit);
+        assert_eq!(result.winner, MakeMoveResultWinner::White);
+        
+        // After 1 full move, the player with more material wins
+        let limit_state = |fen: &str| {
+            let mut state = GameState::from_debug_fen(fen);
+            state.initial_state.global_rules.move_limit = 1;
+            state
+        };
+        assert_eq!(engine.set_state(limit_state("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        // Close to the limit, only the material is evaluated
+        let breakdown = engine.evaluate_position_detailed();
+        assert_eq!(breakdown.total, breakdown.material);
+        assert_eq!(breakdown.total, engine.evaluate_position());
+        assert_eq!(engine.make_move_str("e3").unwrap().flag, MakeMoveResultFlag::Ok);
+        let result = engine.make_move_str("e8d8").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::MoveLimit);
+        assert_eq!(result.winner, MakeMoveResultWinner::White);
+        assert_eq!(result.move_notation.unwrap(), "Kd8#");
+        // Same material, the game is a draw
+        assert_eq!(engine.set_state(limit_state("4k3/3p4/8/8/8/8/3P4/4K3 w - - 0 1")).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e1e2").unwrap().flag, MakeMoveResultFlag::Ok);
+        let result = engine.make_move_str("e8e7").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::MoveLimit);
+        assert_eq!(result.winner, MakeMoveResultWinner::None);
+    }
+
+    #[test]
+    fn stalemate_rule() {
+        let mut engine = Engine::default();
+        let mut state = GameState::from_debug_fen("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1");
+        let rules = [
+            (StalemateRule::Draw, MakeMoveResultWinner::None),
+            (StalemateRule::StalematedLoses, MakeMoveResultWinner::White),
+            (StalemateRule::StalematedWins, MakeMoveResultWinner::Black),
+            (StalemateRule::MaterialAdjudication, MakeMoveResultWinner::White),
+        ];
+        for (rule, winner) in rules {
+            state.initial_state.global_rules.stalemate_rule = rule;
+            let _result = engine.set_state(state.clone()).unwrap();
+            assert_eq!(engine.game_result().map(|result| (result.flag, result.winner)), Some((MakeMoveResultFlag::Stalemate, winner)));
+        }
+        
+        // The search finds the stalemate that wins (a knight cannot checkmate)
+        let mut state = GameState::from_debug_fen("k7/8/1K6/8/3N4/8/8/8 w - - 0 1");
+        state.initial_state.global_rules.stalemate_rule = StalemateRule::StalematedLoses;
+        let _result = engine.set_state(state.clone()).unwrap();
+        let (mv, score, _) = engine.get_best_move(3).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "d4c6");
+        assert!(score > 0);
+        let result = engine.make_move(&mv);
+        assert_eq!((result.flag, result.winner), (MakeMoveResultFlag::Stalemate, MakeMoveResultWinner::White));
+        
+        // The boolean of older game states is still accepted
+        let json = serde_json::to_string(&state).unwrap();
+        assert!(json.contains("\"stalemateRule\":\"stalematedLoses\""));
+        for (old, rule) in [("true", StalemateRule::StalematedLoses), ("false", StalemateRule::Draw)] {
+            let old_json = json.replace("\"stalemateRule\":\"stalematedLoses\"", &format!("\"stalematedPlayerLoses\":{old}"));
+            let old_state: GameState = serde_json::from_str(&old_json).unwrap();
+            assert_eq!(old_state.initial_state.global_rules.stalemate_rule, rule);
+        }
+    }
+
+    #[test]
+    fn game_result() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.game_result(), None);
+        let result_of = |engine: &mut Engine, fen: &str| {
+            let _result = engine.set_state(GameState::from_debug_fen(fen)).unwrap();
+            engine.game_result().map(|result| (result.flag, result.winner))
+        };
+        
+        // Finished games loaded from a FEN
+        let checkmate = "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3";
+        assert_eq!(result_of(&mut engine, checkmate), Some((MakeMoveResultFlag::Checkmate, MakeMoveResultWinner::Black)));
+        let stalemate = "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1";
+        assert_eq!(result_of(&mut engine, stalemate), Some((MakeMoveResultFlag::Stalemate, MakeMoveResultWinner::None)));
+        let check_limit = "4k3/8/8/8/8/8/8/R3K3 b - - 0 1 +3+0 3check";
+        assert_eq!(result_of(&mut engine, check_limit), Some((MakeMoveResultFlag::CheckLimit, MakeMoveResultWinner::White)));
+        assert_eq!(result_of(&mut engine, "4k3/8/8/8/8/8/8/R3K3 b - - 0 1 +2+0 3check"), None);
+        let no_king = "8/8/8/8/8/8/4p3/R3K3 b - - 0 1";
+        assert_eq!(result_of(&mut engine, no_king), Some((MakeMoveResultFlag::LeaderCaptured, MakeMoveResultWinner::White)));
+        let bare_kings = "4k3/8/8/8/8/8/8/4K3 w - - 0 1";
+        assert_eq!(result_of(&mut engine, bare_kings), Some((MakeMoveResultFlag::InsufficientMaterial, MakeMoveResultWinner::None)));
+        
+        // The result matches the one returned by the last move, also after loading the move history
+        assert_eq!(result_of(&mut engine, "4k3/4p3/8/8/8/8/8/4R1K1 w - - 0 1 atomic"), None);
+        let result = engine.make_move_str("e1e7").unwrap();
+        assert_eq!(result.flag, MakeMoveResultFlag::LeaderCaptured);
+        assert_eq!(engine.game_result().unwrap().flag, result.flag);
+        let mut state = GameState::default();
+        state.move_history = build_move_history(vec!["g1f3", "g8f6", "f3g1", "f6g8", "g1f3", "g8f6", "f3g1", "f6g8"]);
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Repetition);
+        assert_eq!(engine.game_result().unwrap().flag, MakeMoveResultFlag::Repetition);
+        state.move_history.pop();
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.game_result(), None);
+    }
+
+    #[test]
+    fn variant_registry() {
+        let mut engine = Engine::default();
+        let crazyhouse = GameState::from_variant_name("Crazyhouse").unwrap();
+        assert_eq!(crazyhouse.initial_state, GameState::from_debug_fen("8/8/8/8/8/8/8/8 w - - 0 1 crazyhouse").initial_state);
+        assert!(GameState::from_variant_name("not-a-variant").is_err());
+        
+        // Built-in fairy variants
+        for (name, moves) in [("capablanca", 28), ("gothic", 28), ("crazyhouse-10x8", 28), ("los-alamos", 10)] {
+            let state = GameState::from_variant_name(name).unwrap();
+            assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+            assert_eq!(engine.perft(1), moves, "{name}");
+            assert!(VariantRegistry::names().contains(&name.to_string()));
+        }
+        assert!(VariantRegistry::get("crazyhouse-10x8").unwrap().global_rules.captured_pieces_to_hand);
+        let mut state = GameState::from_variant_name("Capablanca").unwrap();
+        state.initial_fen = Some("r4k3r/10/10/10/10/10/10/R4K3R w KQkq - 0 1".to_string());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("O-O").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_piece_at((8, 0)), Ok('K'));
+        assert_eq!(engine.get_piece_at((7, 0)), Ok('R'));
+        
+        // Variants registered at runtime
+        let mut variant = VariantRegistry::get("los-alamos").unwrap();
+        variant.global_rules.checks_to_lose = 3;
+        VariantRegistry::register("Three-check Los Alamos", variant.clone()).unwrap();
+        assert_eq!(VariantRegistry::get("three-check los alamos"), Ok(variant.clone()));
+        assert_eq!(GameState::from_variant_name("THREE-CHECK LOS ALAMOS").unwrap().initial_state, variant);
+        assert!(VariantRegistry::names().contains(&"three-check los alamos".to_string()));
+        assert!(VariantRegistry::register("atomic", variant.clone()).is_err());
+        assert!(VariantRegistry::register(" ", variant.clone()).is_err());
+        variant.fen = "rnqknr/pppppp/6/6/PPPPPP/RNQKNZ".to_string();
+        assert!(VariantRegistry::register("invalid", variant).is_err());
+        assert!(VariantRegistry::get("invalid").is_err());
+        assert!(VariantRegistry::unregister("Three-check Los Alamos"));
+        assert!(!VariantRegistry::unregister("Three-check Los Alamos"));
+        assert!(GameState::from_variant_name("three-check los alamos").is_err());
+    }
     
+    #[test]
+    fn board_size_limits() {
+        let mut engine = Engine::default();
+        let fen_16x16 = format!("15k/{}K15", "16/".repeat(14));
+        assert!(load_board(&mut engine, 16, 16, &fen_16x16).is_ok());
+        // 20x20 boards are only supported with the large-boards feature
+        let fen_20x20 = format!("19k/{}K19", "20/".repeat(18));
+        assert_eq!(load_board(&mut engine, 20, 20, &fen_20x20).is_ok(), cfg!(feature = "large-boards"));
+        // Files are named with a single letter, so there are at most 26 of them
+        let fen_27_files = "26k/27/27/27/27/27/27/K26";
+        assert!(load_board(&mut engine, 27, 8, fen_27_files).is_err());
+        assert!(MoveInfo::try_from("a1a40").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "large-boards")]
+    fn large_board() {
+        let mut engine = Engine::default();
+        let fen = format!("19k/{}9R10/{}K19", "20/".repeat(9), "20/".repeat(8));
+        load_board(&mut engine, 20, 20, &fen).unwrap();
+        // 38 rook moves and 3 king moves
+        assert_eq!(engine.perft(1), 41);
+        assert_eq!(engine.make_move_str("j10j20").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("t20t19").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("j20s20").unwrap().flag, MakeMoveResultFlag::Ok);
+        engine.validate_position().unwrap();
+        let (mv, _, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "t19s20");
+    }
+
+    #[test]
+    fn runtime_walls() {
+        let mut engine = Engine::default();
+        engine.load_fen("4k3/8/8/8/8/8/8/R3K1N1 w - - 0 1").unwrap();
+        let zobrist = engine.get_zobrist();
+        engine.add_wall(2, 0).unwrap();
+        engine.add_wall(5, 2).unwrap();
+        engine.check_consistency().unwrap();
+        assert_ne!(engine.get_zobrist(), zobrist);
+        let state = engine.get_state_diff();
+        assert_eq!(state.fen, "4k3/8/8/8/8/5*2/8/R1*1K1N1 w - - 0 1");
+        assert_eq!(state.walls, vec![(2, 0), (5, 2)]);
+        // The rook cannot slide through the wall, and the knight cannot jump onto it
+        assert_eq!(engine.legal_targets_from(0, 0), vec![(1, 0), (0, 1), (0, 2), (0, 3), (0, 4), (0, 5), (0, 6), (0, 7)]);
+        assert_eq!(engine.legal_targets_from(6, 0), vec![(4, 1), (7, 2)]);
+        // Same key as a variant that has the walls in its initial FEN
+        let mut wall_state = GameState::default();
+        wall_state.initial_state.fen = state.fen.clone();
+        let mut wall_engine = Engine::default();
+        assert_eq!(wall_engine.set_state(wall_state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(wall_engine.get_zobrist(), engine.get_zobrist());
+
+        // Walls can only be added to empty squares inside the board, and only removed where they exist
+        assert!(engine.add_wall(2, 0).is_err());
+        assert!(engine.add_wall(4, 0).is_err());
+        assert!(engine.add_wall(8, 0).is_err());
+        assert!(engine.remove_wall(3, 0).is_err());
+
+        // The walls are kept when moves are made and undone
+        assert_eq!(engine.make_move_str("a1a7").unwrap().flag, MakeMoveResultFlag::Ok);
+        engine.remove_wall(2, 0).unwrap();
+        assert_eq!(engine.make_move_str("e8d8").unwrap().flag, MakeMoveResultFlag::Ok);
+        engine.check_consistency().unwrap();
+        engine.undo().unwrap();
+        engine.undo().unwrap();
+        engine.remove_wall(5, 2).unwrap();
+        engine.check_consistency().unwrap();
+        assert_eq!(engine.get_zobrist(), zobrist);
+        assert!(engine.get_state_diff().walls.is_empty());
+    }
+
+    #[test]
+    fn piece_bitboards() {
+        let mut engine = Engine::default();
+        engine.load_fen("4k3/8/8/8/8/8/3PP3/R3K2R w K - 0 1").unwrap();
+        let pieces = engine.get_piece_bitboards();
+        assert_eq!(pieces, vec![
+            ('K', 0, vec![(4, 0)]),
+            ('R', 0, vec![(0, 0), (7, 0)]),
+            ('P', 0, vec![(3, 1), (4, 1)]),
+            ('k', 1, vec![(4, 7)]),
+        ]);
+        // Same layout as probing each square
+        for (id, _, squares) in &pieces {
+            for &(x, y) in squares {
+                assert_eq!(engine.get_piece_at((x, y)).unwrap(), *id);
+            }
+        }
+        assert_eq!(engine.get_state_diff().pieces, pieces);
+        // Castling moves both pieces
+        assert_eq!(engine.make_move_str("e1h1").unwrap().flag, MakeMoveResultFlag::Ok);
+        let pieces = engine.get_state_diff().pieces;
+        assert!(pieces.contains(&('K', 0, vec![(6, 0)])));
+        assert!(pieces.contains(&('R', 0, vec![(0, 0), (5, 0)])));
+    }
+
+    #[test]
+    fn dark_chess() {
+        let state = GameState { initial_state: VariantRegistry::get("dark chess").unwrap(), ..Default::default() };
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        // Each player sees the first 4 ranks from their side, but not the enemy pieces
+        assert_eq!(engine.visible_squares(0).count_ones(), 32);
+        assert_eq!(engine.get_state_for_player(0).fen, "8/8/8/8/8/8/PPPPPPPP/RNBQKBNR w (a1,e1,h1) - 0 1");
+        assert_eq!(engine.get_state_for_player(1).fen, "rnbqkbnr/pppppppp/8/8/8/8/8/8 w (a8,e8,h8) - 0 1");
+        assert_eq!(engine.make_move_str("e2e4").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("d7d5").unwrap().flag, MakeMoveResultFlag::Ok);
+        // The pawns can see each other because they can capture
+        assert_eq!(engine.get_state_for_player(0).fen, "8/8/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w (a1,e1,h1) d6(d5) 0 2");
+        let black_pawns = engine.get_state_for_player(0).pieces.into_iter().find(|(_, player, _)| *player == 1).unwrap();
+        assert_eq!(black_pawns, ('p', 1, vec![(3, 4)]));
+        
+        // The king can move to an attacked square, and capturing it wins the game
+        let mut state = state;
+        state.initial_fen = Some("4k3/8/8/8/8/8/3r3P/4K3 w - - 0 1".to_string());
+        assert_eq!(engine.set_state(state).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.is_in_check());
+        assert!(engine.legal_targets_from(4, 0).contains(&(4, 1)));
+        assert_eq!(engine.make_move_str("e1e2").unwrap().flag, MakeMoveResultFlag::Ok);
+        let (best_move, _score, _) = engine.get_best_move(2).unwrap().into_move().unwrap();
+        assert_eq!(best_move.to_string(), "d2e2");
+        let result = engine.make_move(&best_move);
+        assert_eq!(result.flag, MakeMoveResultFlag::WinConditionMet);
+        assert_eq!(result.winner, MakeMoveResultWinner::Black);
+    }
+    
+    #[test]
+    fn square_effects() {
+        let mut state = GameState::default();
+        state.initial_fen = Some("4k3/8/8/8/8/8/8/R3K1N1 w - - 0 1".to_string());
+        state.initial_state.global_rules.square_effects = vec![
+            SquareEffect::Portal { squares: [(0, 3), (7, 3)] },
+            SquareEffect::Ice { squares: vec![(5, 2)] },
+            SquareEffect::OneWay { square: (3, 0), direction: (1, 0) },
+        ];
+        let mut engine = Engine::default();
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        // The rook goes through the portal when it stops on a4, but it can still slide past it
+        let rook_targets = engine.legal_targets_from(0, 0);
+        assert!(rook_targets.contains(&(7, 3)) && rook_targets.contains(&(0, 4)));
+        assert!(!rook_targets.contains(&(0, 3)));
+        // The knight keeps jumping in the same direction after landing on ice
+        let knight_targets = engine.legal_targets_from(6, 0);
+        assert!(knight_targets.contains(&(4, 4)) && !knight_targets.contains(&(5, 2)));
+        // The rook can enter d1 moving east, but the king cannot enter it moving west
+        assert!(rook_targets.contains(&(3, 0)));
+        assert!(!engine.legal_targets_from(4, 0).contains(&(3, 0)));
+        assert_eq!(engine.make_move_str("g1e5").unwrap().flag, MakeMoveResultFlag::Ok);
+        engine.check_consistency().unwrap();
+        engine.undo().unwrap();
+        assert_eq!(engine.make_move_str("a1h4").unwrap().flag, MakeMoveResultFlag::Ok);
+        engine.check_consistency().unwrap();
+        
+        // A one-way square cannot be attacked from the other directions
+        state.initial_fen = Some("4k3/8/8/8/8/8/8/r3K3 w - - 0 1".to_string());
+        state.initial_state.global_rules.square_effects = vec![SquareEffect::OneWay { square: (4, 0), direction: (0, 1) }];
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(!engine.is_in_check());
+        state.initial_state.global_rules.square_effects = vec![SquareEffect::OneWay { square: (4, 0), direction: (1, 0) }];
+        assert_eq!(engine.set_state(state.clone()).unwrap().flag, MakeMoveResultFlag::Ok);
+        assert!(engine.is_in_check());
+        
+        // The squares must be on the board
+        state.initial_state.global_rules.square_effects = vec![SquareEffect::Ice { squares: vec![(8, 0)] }];
+        assert!(engine.set_state(state).is_err());
+    }
+
     
+    #[test]
+    fn move_parsing() {
+        let mv = MoveInfo::try_from("e7e8=Q").unwrap();
+        assert_eq!((mv.from, mv.to, mv.promotion), ((4, 6), (4, 7), Some('Q')));
+        assert_eq!(MoveInfo::try_from("N@e4").unwrap(), MoveInfo::drop('N', (4, 3)));
+        // The errors point at the offending token
+        let error = |s: &str| MoveInfo::try_from(s).unwrap_err();
+        assert!(error("e7e8=QN").contains("promotion piece 'QN'"));
+        assert!(error("e7e8=").contains("missing promotion piece"));
+        assert!(error("e2e4x").contains("unexpected 'x'"));
+        assert!(error("e2E4").contains("destination square after 'e2'"));
+        assert!(error("NN@e4").contains("dropped piece 'NN'"));
+        assert!(error("N@e").contains("'e' is not a square"));
+        assert!(error("e2e40").contains("rank of 'e40'"));
+        // Castling with the destination of the king needs a position
+        assert!(error("e1g1c").contains("MoveInfo::parse()"));
+        
+        let mut engine = Engine::default();
+        engine.load_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(engine.make_move_str("e1g1c").unwrap().move_notation.unwrap(), "O-O");
+        assert_eq!(engine.make_move_str("O-O-O").unwrap().move_notation.unwrap(), "O-O-O");
+        assert!(engine.make_move_str("e1c1c").unwrap_err().contains("no legal castling move"));
+        assert!(engine.make_move_str("e1e2=QN").unwrap_err().contains("promotion piece 'QN'"));
+    }
+
+
+    fn load_board(engine: &mut Engine, width: u8, height: u8, fen: &str) -> Result<(), String> {
+        let mut state = GameState::default();
+        state.initial_state.board_width = width;
+        state.initial_state.board_height = height;
+        state.initial_state.fen = fen.to_string();
+        engine.set_state(state).map(|_| ())
+    }
+
     fn build_move_history(moves: Vec<&str>) -> Vec<MoveInfo> {
         moves.iter().map(|mv| MoveInfo::try_from(*mv).unwrap()).collect()
     }
@@ -1,8 +1,16 @@
 #[cfg(test)]
 mod principal_variation {
+    use std::convert::TryFrom;
+
     use protochess_engine_rs::position::create::position_factory::PositionFactory;
-    use protochess_engine_rs::{GameState, MoveGen};
+    use protochess_engine_rs::{GameState, MakeMoveResultWinner, MoveGen};
     use protochess_engine_rs::searcher::Searcher;
+    use protochess_engine_rs::searcher::time_manager::{BestMoveTracker, TimeManager};
+    use protochess_engine_rs::types::{Move, MoveType};
+    use std::time::Duration;
+    use protochess_engine_rs::{Algorithm, Engine, MakeMoveResultFlag, MoveClassification, MoveInfo, ScoreType, SearchOutcome, TimeControl, MATE_SCORE};
+    use protochess_engine_rs::{Evaluator, HandCraftedEvaluator, Position, SearchHandle};
+    use protochess_engine_rs::utils::bench::BENCH_POSITIONS;
     #[test]
     fn starting_position_1() {
         test_pv("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1", 1);
@@ -93,7 +101,669 @@ mod principal_variation {
         test_pv("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1", 10);
     }
     
+    #[test]
+    fn explain_best_move() {
+        let mut engine = Engine::default();
+        engine.load_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 0 1").unwrap();
+        let explanation = engine.explain_best_move(3, 3).unwrap();
+        assert_eq!(explanation.best_move.to_string(), "f3f7");
+        assert_eq!(explanation.pv[0], explanation.best_move);
+        assert_eq!(explanation.alternatives.len(), 3);
+        for alternative in &explanation.alternatives {
+            assert!(alternative.score < explanation.score);
+            assert!(!alternative.refutation.is_empty());
+            // Make sure that the refutation is legal
+            let mut engine = engine.clone();
+            assert_eq!(engine.make_move(&alternative.move_info).flag, MakeMoveResultFlag::Ok);
+            for mv in &alternative.refutation {
+                assert_ne!(engine.make_move(mv).flag, MakeMoveResultFlag::IllegalMove, "Move {} is not legal", mv);
+            }
+        }
+        
+        // No best move to explain, the player to move is checkmated
+        engine.load_fen("rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3").unwrap();
+        assert!(engine.explain_best_move(3, 3).is_err());
+    }
+
+    #[test]
+    fn search_callback() {
+        let mut engine = Engine::default();
+        let mut results = Vec::new();
+        let (best_move, score, _) = engine.get_best_move_with_callback(4, |result| results.push(result)).unwrap().into_move().unwrap();
+        let depths: Vec<u8> = results.iter().map(|r| r.depth).collect();
+        assert_eq!(depths, vec![1, 2, 3, 4]);
+        for result in &results {
+            assert!(!result.pv.is_empty());
+            assert!(result.nodes > 0);
+            assert!(result.total_nodes >= result.nodes);
+            assert!(result.branching_factor >= 0.0);
+        }
+        // The first iteration has no previous one to compare with
+        assert_eq!(results[0].branching_factor, 0.0);
+        assert!(results[0].nps > 0);
+        let last = results.last().unwrap();
+        assert!(last.branching_factor > 1.0);
+        assert_eq!(last.pv[0], best_move);
+        assert_eq!(last.score, score);
+    }
+
+    #[test]
+    fn root_move_progress() {
+        let mut engine = Engine::default();
+        let handle = engine.search_handle();
+        assert!(handle.current_move().is_none());
+        let mut progress = Vec::new();
+        let _result = engine.get_best_move_with_callback(4, |_| progress.push(handle.current_move())).unwrap();
+        assert_eq!(progress.len(), 4);
+        for current_move in progress {
+            // The root move being searched, numbered among the 20 legal moves
+            let (mv, number) = current_move.unwrap();
+            assert!((1..=20).contains(&number));
+            assert_ne!(engine.clone().make_move(&mv).flag, MakeMoveResultFlag::IllegalMove, "Move {} is not legal", mv);
+        }
+    }
+
+    #[test]
+    fn multipv() {
+        let mut engine = Engine::default();
+        engine.load_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 0 1").unwrap();
+        let lines = engine.get_best_moves(4, 3).unwrap();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0].pv[0].to_string(), "f3f7");
+        let (best_move, _score, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        assert_eq!(lines[0].pv[0], best_move);
+        for i in 0..lines.len() {
+            if i > 0 {
+                assert!(lines[i].score <= lines[i-1].score);
+            }
+            // All lines start with a different move
+            for j in 0..i {
+                assert_ne!(lines[i].pv[0], lines[j].pv[0]);
+            }
+            // Make sure that the lines are legal
+            let mut engine = engine.clone();
+            for mv in &lines[i].pv {
+                assert_ne!(engine.make_move(mv).flag, MakeMoveResultFlag::IllegalMove, "Move {} is not legal", mv);
+            }
+        }
+    }
+
+    #[test]
+    fn multipv_few_moves() {
+        // Black only has 3 legal moves: Kh7, a6 and a5
+        let mut engine = Engine::default();
+        engine.load_fen("7k/p7/8/8/8/8/8/K5R1 b - - 0 1").unwrap();
+        let lines = engine.get_best_moves(3, 5).unwrap();
+        assert_eq!(lines.len(), 3);
+    }
+
+    #[test]
+    fn multipv_threads() {
+        // With several threads, the secondary lines are only searched by the main thread and all of them are returned
+        let mut engine = Engine::default();
+        engine.set_num_threads(Engine::get_max_threads()).unwrap();
+        engine.load_fen("r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5Q2/PPPP1PPP/RNB1K1NR w KQkq - 0 1").unwrap();
+        let lines = engine.get_best_moves(6, 4).unwrap();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0].pv[0].to_string(), "f3f7");
+        for i in 1..lines.len() {
+            assert!(lines[i].score <= lines[i-1].score);
+            assert!(lines[..i].iter().all(|line| line.pv[0] != lines[i].pv[0]));
+        }
+    }
+
+    #[test]
+    fn node_limited_search() {
+        // Single-threaded node-limited searches are reproducible
+        let mut engine = Engine::default();
+        engine.set_num_threads(1).unwrap();
+        let result1 = engine.get_best_move_nodes(50_000).unwrap().into_move().unwrap();
+        let result2 = engine.get_best_move_nodes(50_000).unwrap().into_move().unwrap();
+        assert!(result1.2 > 1);
+        assert_eq!(result1, result2);
+    }
+
+    #[test]
+    fn clock_search() {
+        let mut engine = Engine::default();
+        // With 1 second left and no increment, the move must be played well before the flag falls
+        let clock = TimeControl { remaining_ms: 1000, increment_ms: 0, moves_to_go: None };
+        let start = std::time::Instant::now();
+        let (mv, _, depth) = engine.get_best_move_clock(clock).unwrap().into_move().unwrap();
+        assert!(start.elapsed().as_millis() < 500);
+        assert!(depth >= 1);
+        assert_ne!(engine.make_move(&mv).flag, MakeMoveResultFlag::IllegalMove);
+        
+        // A forced move is played immediately, even with plenty of time
+        engine.load_fen("k7/8/8/8/8/8/1r6/K7 w - - 0 1").unwrap();
+        let clock = TimeControl { remaining_ms: 600_000, increment_ms: 5000, moves_to_go: Some(10) };
+        let start = std::time::Instant::now();
+        let (mv, _, _) = engine.get_best_move_clock(clock).unwrap().into_move().unwrap();
+        assert!(start.elapsed().as_millis() < 1000);
+        assert_eq!(mv.to_string(), "a1b2");
+    }
+
+    #[test]
+    fn stop_search() {
+        let mut engine = Engine::default();
+        let handle = engine.search_handle();
+        assert!(!handle.is_running());
+        let stopper = {
+            let handle = handle.clone();
+            std::thread::spawn(move || {
+                while !handle.is_running() {
+                    std::thread::sleep(std::time::Duration::from_millis(10));
+                }
+                std::thread::sleep(std::time::Duration::from_millis(200));
+                handle.stop();
+            })
+        };
+        let start = std::time::Instant::now();
+        let (mv, _, depth) = engine.get_best_move_timeout(100).unwrap().into_move().unwrap();
+        assert!(start.elapsed().as_secs() < 10);
+        assert!(depth >= 1);
+        assert!(!handle.is_running());
+        stopper.join().unwrap();
+        assert_ne!(engine.make_move(&mv).flag, MakeMoveResultFlag::IllegalMove);
+        // Stopping when no search is running doesn't affect the next search
+        handle.stop();
+        let mut depths = Vec::new();
+        let _result = engine.get_best_move_with_callback(3, |result| depths.push(result.depth)).unwrap();
+        assert_eq!(depths.last(), Some(&3));
+    }
+
+    #[test]
+    fn concurrent_searches() {
+        // Searches of different engines don't wait for each other, even if one of them is started from a callback
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let searches: Vec<_> = (0..2).map(|_| {
+            let sender = sender.clone();
+            std::thread::spawn(move || {
+                let mut engine = Engine::default();
+                let mut nested_moves = Vec::new();
+                let (mv, _, _) = engine.get_best_move_with_callback(4, |result| {
+                    if result.depth == 2 {
+                        let mut other = Engine::default();
+                        nested_moves.push(other.get_best_move(3).unwrap().into_move().unwrap().0);
+                    }
+                }).unwrap().into_move().unwrap();
+                sender.send((mv, nested_moves.len())).unwrap();
+            })
+        }).collect();
+        for _ in &searches {
+            let (mv, nested_searches) = receiver.recv_timeout(std::time::Duration::from_secs(60)).expect("Search did not finish");
+            assert_ne!(Engine::default().make_move(&mv).flag, MakeMoveResultFlag::IllegalMove);
+            assert_eq!(nested_searches, 1);
+        }
+        for search in searches {
+            search.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn deterministic_search() {
+        let mut engine = Engine::default();
+        engine.load_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3").unwrap();
+        assert!(!engine.is_deterministic());
+        engine.set_deterministic(true);
+        assert!(engine.is_deterministic());
+        let mut other = engine.clone();
+        assert_eq!(engine.get_best_move_nodes(30_000).unwrap(), other.get_best_move_nodes(30_000).unwrap());
+        assert_eq!(engine.get_best_moves(4, 3).unwrap(), other.get_best_moves(4, 3).unwrap());
+    }
+
+    #[test]
+    fn search_stats() {
+        let mut engine = Engine::default();
+        let mut last_result = None;
+        let _result = engine.get_best_move_with_callback(5, |result| last_result = Some(result)).unwrap();
+        let result = last_result.unwrap();
+        let stats = &result.stats;
+        assert_eq!(stats.nodes, result.nodes);
+        assert!(stats.qnodes > 0 && stats.qnodes < stats.nodes);
+        assert!(stats.tt_hits > 0 && stats.tt_hits <= stats.tt_probes);
+        assert!((0.0..=1.0).contains(&stats.tt_hit_rate()));
+        assert!(stats.eval_probes >= stats.qnodes);
+        assert!(stats.eval_hits > 0 && stats.eval_hits <= stats.eval_probes);
+        assert!(stats.beta_cutoffs() > 0);
+        // With good move ordering, most cutoffs are produced by the first move
+        assert!(stats.first_move_cutoff_rate() > 0.5);
+        assert!(stats.lmr_researches <= stats.lmr_searches);
+        assert!(result.tree.is_empty());
+    }
+
+    #[test]
+    fn singular_extensions() {
+        // After the queen trade, recapturing with the king is the only move that doesn't lose a queen
+        let mut engine = Engine::default();
+        engine.load_fen("rnbqkbnr/ppp2ppp/8/4p3/4P3/8/PPP2PPP/RNBQKBNR w KQkq - 0 4").unwrap();
+        let mut last_result = None;
+        let (best_move, _score, _) = engine.get_best_move_with_callback(7, |result| last_result = Some(result)).unwrap().into_move().unwrap();
+        assert_eq!(best_move.to_string(), "d1d8");
+        // The verification search doesn't store its result in the transposition table, otherwise the TT move
+        // would be replaced by a worse move and it would never be singular
+        let stats = last_result.unwrap().stats;
+        assert!(stats.singular_extensions > 0);
+        assert!(stats.singular_extensions <= stats.singular_searches);
+    }
+
+    #[test]
+    fn singular_extensions_three_check() {
+        // The verification search runs on a node that has already incremented the check counter, the check
+        // must not be counted again. With `--features consistency-checks`, the zobrist key of every node is checked.
+        let mut engine = Engine::default();
+        let gs = GameState::from_debug_fen("rnbqkbnr/ppp2ppp/8/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3 +0+0 3CHECK");
+        let _result = engine.set_state(gs).unwrap();
+        let mut last_result = None;
+        let _result = engine.get_best_move_with_callback(7, |result| last_result = Some(result)).unwrap();
+        let stats = last_result.unwrap().stats;
+        assert!(stats.singular_searches > 0);
+        engine.check_consistency().unwrap();
+    }
+
+    #[test]
+    fn search_tree() {
+        let mut engine = Engine::default();
+        let legal_moves: Vec<_> = engine.legal_moves().into_iter()
+            .flat_map(|list| list.moves)
+            .collect();
+        let mut results = Vec::new();
+        let (_best_move, score) = engine.get_best_move_with_tree_callback(3, 2, |result| results.push(result)).unwrap();
+        let tree = &results.last().unwrap().tree;
+        assert_eq!(tree[0].ply, 0);
+        assert_eq!(tree[0].mv, None);
+        assert_eq!(tree[0].depth, 3);
+        assert_eq!(tree[0].score, Some(score));
+        assert!(tree[1..].iter().all(|node| node.ply == 1 && node.score.is_some()));
+        for node in &tree[1..] {
+            assert!(legal_moves.contains(node.mv.as_ref().unwrap()));
+        }
+    }
+
+    #[test]
+    fn mate_search() {
+        let mut engine = Engine::default();
+        engine.load_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").unwrap();
+        assert!(engine.get_best_move_mate(1).is_err());
+        let line = engine.get_best_move_mate(2).unwrap();
+        assert_eq!(line.len(), 3);
+        let mut flag = MakeMoveResultFlag::Ok;
+        for mv in &line {
+            flag = engine.make_move(mv).flag;
+        }
+        assert_eq!(flag, MakeMoveResultFlag::Checkmate);
+    }
+
+    #[test]
+    fn score_type() {
+        assert_eq!(ScoreType::from_centipawns(-34), ScoreType::Cp(-34));
+        assert_eq!(ScoreType::from_centipawns(MATE_SCORE - 1), ScoreType::Mate(1));
+        assert_eq!(ScoreType::from_centipawns(2 - MATE_SCORE), ScoreType::Mate(-2));
+        let mut engine = Engine::default();
+        // Mate in 2 moves (3 plies)
+        engine.load_fen("7k/8/8/8/8/8/R7/1R4K1 w - - 0 1").unwrap();
+        let result = engine.get_best_move_result(4).unwrap();
+        assert_eq!(result.score_type, ScoreType::Mate(3));
+        assert!(result.score_type.is_mate());
+        let lines = engine.get_best_moves(4, 2).unwrap();
+        assert_eq!(lines[0].score_type, ScoreType::Mate(3));
+        // The opponent is mated after any move
+        assert_eq!(engine.make_move_str("a2a7").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.get_best_move_result(4).unwrap().score_type, ScoreType::Mate(-2));
+        engine.load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let result = engine.get_best_move_result(3).unwrap();
+        assert_eq!(result.score_type, ScoreType::Cp(result.score));
+    }
+
+    #[test]
+    fn pv_san() {
+        let mut engine = Engine::default();
+        let line: Vec<MoveInfo> = ["e2e4", "e7e5", "g1f3", "e1e2"].iter().map(|&mv| MoveInfo::try_from(mv).unwrap()).collect();
+        // The line ends at the illegal move
+        assert_eq!(engine.line_to_san(&line), vec!["e4", "e5", "Nf3"]);
+        engine.load_fen("7k/R7/8/8/8/8/8/1R4K1 w - - 0 1").unwrap();
+        let result = engine.get_best_move_result(2).unwrap();
+        assert_eq!(result.pv_san, vec!["Rb8#"]);
+        engine.load_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        let result = engine.get_best_move_result(4).unwrap();
+        assert_eq!(result.pv_san.len(), result.pv.len());
+        assert_eq!(result.pv_san, engine.line_to_san(&result.pv));
+    }
+
+    #[test]
+    fn mcts_search() {
+        let mut engine = Engine::default();
+        assert!(engine.set_search_algorithm(Algorithm::Mcts { simulations: 0, c_puct: 1.5 }).is_err());
+        engine.set_search_algorithm(Algorithm::Mcts { simulations: 2000, c_puct: 1.5 }).unwrap();
+        assert_eq!(engine.clone().search_algorithm(), engine.search_algorithm());
+        // Mate in 1, and winning a hanging queen
+        engine.load_fen("7k/R7/8/8/8/8/8/1R4K1 w - - 0 1").unwrap();
+        let (mv, score, _depth) = engine.get_best_move_nodes(500).unwrap().into_move().unwrap();
+        assert!(score > 10_000);
+        assert_eq!(engine.make_move(&mv).flag, MakeMoveResultFlag::Checkmate);
+        engine.load_fen("rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 1 3").unwrap();
+        let result = engine.get_best_move_result(20).unwrap();
+        assert_eq!(result.pv[0].to_string(), "c1g5");
+        assert_eq!(result.nodes, 2000);
+        assert!(result.score > 500);
+
+        // The search is reproducible, and the multi-PV search still uses alpha-beta
+        let repeated = engine.get_best_move_result(20).unwrap();
+        assert_eq!((repeated.pv, repeated.score), (result.pv, result.score));
+        assert_eq!(engine.get_best_moves(3, 2).unwrap().len(), 2);
+        engine.set_search_algorithm(Algorithm::AlphaBeta).unwrap();
+        assert_eq!(engine.get_best_move(3).unwrap().into_move().unwrap().0.to_string(), "c1g5");
+    }
+
+    #[test]
+    fn contempt() {
+        let mut engine = Engine::default();
+        assert!(engine.set_contempt(20_000).is_err());
+        engine.set_contempt(50).unwrap();
+        assert_eq!(engine.clone().contempt(), 50);
+        // Capturing the queen is the only move that doesn't lose, but it's a draw by insufficient material
+        engine.load_fen("7k/8/8/8/8/8/6q1/5K2 w - - 0 1").unwrap();
+        let (mv, score, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        assert_eq!((mv.to_string().as_str(), score), ("f1g2", -50));
+        engine.set_contempt(-50).unwrap();
+        assert_eq!(engine.get_best_move(4).unwrap().into_move().unwrap().1, 50);
+        engine.set_contempt(0).unwrap();
+        assert_eq!(engine.get_best_move(4).unwrap().into_move().unwrap().1, 0);
+        engine.set_play_for_win(true);
+        assert!(engine.plays_for_win());
+        assert_eq!(engine.get_best_move(4).unwrap().into_move().unwrap().1, -1000);
+    }
+
+    #[test]
+    fn skill_level() {
+        let mut engine = Engine::default();
+        assert_eq!(engine.skill_level(), 20);
+        assert!(engine.set_skill_level(21).is_err());
+        engine.set_skill_level(0).unwrap();
+        assert_eq!(engine.clone().skill_level(), 0);
+        let mut full_strength = Engine::default();
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R w KQkq - 4 4",
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+        ];
+        let mut weaker_moves = 0;
+        for fen in fens {
+            engine.load_fen(fen).unwrap();
+            full_strength.load_fen(fen).unwrap();
+            let (mv, _score, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+            // The choice is deterministic
+            assert_eq!(engine.get_best_move(4).unwrap().into_move().unwrap().0, mv);
+            if mv != full_strength.get_best_move(4).unwrap().into_move().unwrap().0 {
+                weaker_moves += 1;
+            }
+        }
+        assert!(weaker_moves >= 2);
+        // The back rank mate is still found by the mate search
+        assert_eq!(engine.get_best_move_mate(1).unwrap()[0].to_string(), "a1a8");
+        engine.set_skill_level(20).unwrap();
+        assert_eq!(engine.get_best_move(4).unwrap().into_move().unwrap().0.to_string(), "a1a8");
+    }
+
+    #[test]
+    fn opening_variety() {
+        let mut engine = Engine::default();
+        assert!(engine.set_opening_variety(2, -1).is_err());
+        engine.set_opening_variety(2, 30).unwrap();
+        assert_eq!(engine.clone().opening_variety(), Some((2, 30)));
+        let best_move = Engine::default().get_best_move(4).unwrap().into_move().unwrap().0;
+        let mut first_moves = Vec::new();
+        for seed in 0..6 {
+            engine.set_random_seed(seed);
+            let (mv, score, depth) = engine.get_best_move(4).unwrap().into_move().unwrap();
+            // The same seed always plays the same move, within the margin
+            assert_eq!(engine.get_best_move(4).unwrap(), SearchOutcome::BestMove { mv, score, depth });
+            assert!(score >= -30);
+            if !first_moves.contains(&mv) {
+                first_moves.push(mv);
+            }
+        }
+        assert!(first_moves.len() >= 3);
+        // After the first turns, the best move is played
+        engine.set_opening_variety(1, 30).unwrap();
+        assert_eq!(engine.make_move(&best_move).flag, MakeMoveResultFlag::Ok);
+        let mut full_strength = engine.clone();
+        full_strength.set_opening_variety(0, 0).unwrap();
+        assert_eq!(engine.get_best_move(4).unwrap(), full_strength.get_best_move(4).unwrap());
+    }
+
+    #[test]
+    fn search_moves() {
+        let mut engine = Engine::default();
+        let mv = |s: &str| MoveInfo::try_from(s).unwrap();
+        engine.load_fen("rnb1kbnr/pppp1ppp/8/4p1q1/4P3/3P4/PPP2PPP/RNBQKBNR w KQkq - 1 3").unwrap();
+        let (best_move, best_score, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        assert_eq!(best_move, mv("c1g5"));
+        // Restrict the search to some moves
+        let (among, score) = engine.get_best_move_among(&[mv("a2a3"), mv("h2h3")], 4).unwrap();
+        assert!(among == mv("a2a3") || among == mv("h2h3"));
+        assert!(score < best_score);
+        assert_eq!(engine.get_best_move_among(&[mv("c1g5")], 4).unwrap(), (best_move, best_score));
+        // Exclude the best move
+        let (avoiding, score) = engine.get_best_move_avoiding(&[mv("c1g5")], 4).unwrap();
+        assert_ne!(avoiding, best_move);
+        assert!(score < best_score);
+        assert_eq!(engine.get_best_move_avoiding(&[], 4).unwrap().0, best_move);
+        // Illegal moves, or no moves left
+        assert!(engine.get_best_move_among(&[mv("e1e3")], 4).is_err());
+        assert!(engine.get_best_move_among(&[], 4).is_err());
+        let all_moves: Vec<MoveInfo> = engine.legal_moves().iter()
+            .flat_map(|list| list.moves.iter().copied())
+            .collect();
+        assert!(engine.get_best_move_avoiding(&all_moves, 4).is_err());
+        assert_eq!(engine.get_best_move_avoiding(&all_moves[1..], 4).unwrap().0, all_moves[0]);
+    }
+
+    #[test]
+    fn find_tactics() {
+        let mut engine = Engine::default();
+        // Nc7+ forks the king and the rook
+        engine.load_fen("r3k3/8/8/1N6/8/8/5PPP/4K3 w - - 0 1").unwrap();
+        let puzzles = engine.find_tactics(3, 300).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].ply, 0);
+        assert_eq!(puzzles[0].fen, "r3k3/8/8/1N6/8/8/5PPP/4K3 w - - 0 1");
+        assert_eq!(puzzles[0].solution[0].to_string(), "b5c7");
+        assert!(puzzles[0].gain >= 300);
+        assert!(puzzles[0].themes.contains(&"fork".to_string()));
+        
+        // Back rank mate, in the initial position and after 2 moves
+        engine.load_fen("6k1/1p3ppp/8/8/8/8/5PPP/R5K1 w - - 0 1").unwrap();
+        assert_eq!(engine.make_move_str("h2h3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("b7b6").unwrap().flag, MakeMoveResultFlag::Ok);
+        let puzzles = engine.find_tactics(2, 300).unwrap();
+        assert_eq!(engine.current_ply(), 2);
+        assert_eq!(puzzles.iter().map(|p| p.ply).collect::<Vec<_>>(), vec![0, 2]);
+        for puzzle in &puzzles {
+            assert_eq!(puzzle.solution[0].to_string(), "a1a8");
+            assert!(puzzle.themes.contains(&"mate in 1".to_string()));
+        }
+        assert!(engine.find_tactics(0, 300).is_err());
+    }
+
+    #[test]
+    fn annotate_history() {
+        let mut engine = Engine::default();
+        // White misses the fork Nc7+
+        engine.load_fen("r3k3/8/8/1N6/8/8/5PPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(engine.make_move_str("h2h3").unwrap().flag, MakeMoveResultFlag::Ok);
+        assert_eq!(engine.make_move_str("e8d7").unwrap().flag, MakeMoveResultFlag::Ok);
+        let annotations = engine.annotate_history(3).unwrap();
+        assert_eq!(annotations.len(), 2);
+        let blunder = &annotations[0];
+        assert_eq!(blunder.mv.to_string(), "h2h3");
+        assert_eq!(blunder.notation, "h3");
+        assert_eq!(blunder.classification, MoveClassification::Blunder);
+        assert_eq!(blunder.classification.nag(), Some(4));
+        assert_eq!(blunder.best_line[0].to_string(), "b5c7");
+        assert_eq!(blunder.best_notation, "Nc7+");
+        assert!(blunder.best_score - blunder.score >= 300);
+        assert_eq!(annotations[1].mv.to_string(), "e8d7");
+
+        let pgn = engine.to_annotated_pgn(&annotations);
+        assert!(pgn.contains("1. h3 $4 {Best: Nc7+} 1..."), "{}", pgn);
+        let state = GameState::from_pgn(&pgn).unwrap();
+        assert_eq!(&state.move_history, &engine.get_state().move_history);
+        assert!(!engine.to_pgn().contains('$'));
+        assert!(engine.annotate_history(0).is_err());
+    }
+
+    // Prefers losing material
+    struct InvertedEvaluator;
+    impl Evaluator for InvertedEvaluator {
+        fn evaluate(&self, position: &Position) -> i32 {
+            -HandCraftedEvaluator.evaluate(position)
+        }
+    }
+
+    #[test]
+    fn best_move_result() {
+        let mut engine = Engine::default();
+        engine.set_deterministic(true);
+        let (best_move, score, _) = engine.get_best_move(4).unwrap().into_move().unwrap();
+        let result = engine.get_best_move_result(4).unwrap();
+        assert_eq!((result.pv[0], result.score, result.depth), (best_move, score, 4));
+        assert_eq!(result.nodes, result.stats.nodes);
+        assert!(result.nodes > 0);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn search_game_over() {
+        let mut engine = Engine::default();
+        engine.load_fen("R6k/6pp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        let SearchOutcome::GameOver { result } = engine.get_best_move(4).unwrap() else { panic!("Expected game over") };
+        assert_eq!((result.flag, result.winner), (MakeMoveResultFlag::Checkmate, MakeMoveResultWinner::White));
+        assert!(engine.get_best_move_nodes(1000).unwrap().into_move().is_none());
+        assert!(engine.get_best_move_or_err(4).is_err());
+        // Invalid arguments are still errors
+        assert!(engine.get_best_move(0).is_err());
+        // After resigning, the game is also over
+        engine.load_fen("7k/6pp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        assert!(engine.get_best_move(2).unwrap().into_move().is_some());
+        let _result = engine.resign(0).unwrap();
+        assert!(matches!(engine.get_best_move_timeout(1).unwrap(), SearchOutcome::GameOver { .. }));
+    }
+
+    #[test]
+    fn early_stop_on_mate() {
+        let mut engine = Engine::default();
+        // Back rank mate in 2 plies: deeper iterations are not searched once the mate is stable
+        engine.load_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let mut depths = Vec::new();
+        let outcome = engine.get_best_move_with_callback(30, |result| depths.push(result.depth)).unwrap();
+        let (mv, score, depth) = outcome.into_move().unwrap();
+        assert_eq!(mv.to_string(), "a1a8");
+        assert_eq!(ScoreType::from(score), ScoreType::Mate(1));
+        assert!(depth < 10);
+        assert_eq!(depths.last(), Some(&depth));
+        // A fixed timeout also returns early
+        let start = std::time::Instant::now();
+        let (mv, _, _) = engine.get_best_move_timeout(20).unwrap().into_move().unwrap();
+        assert_eq!(mv.to_string(), "a1a8");
+        assert!(start.elapsed().as_secs() < 10);
+    }
+
+    #[test]
+    fn timeout_ms() {
+        let mut engine = Engine::default();
+        let start = std::time::Instant::now();
+        let mut depths = Vec::new();
+        let (_mv, _, depth) = engine.get_best_move_timeout_ms_with_callback(200, |result| depths.push(result.depth)).unwrap().into_move().unwrap();
+        // The limit is 200 milliseconds, not 200 seconds
+        assert!(start.elapsed().as_secs() < 10);
+        assert!(depth >= 1);
+        assert_eq!(depths.last(), Some(&depth));
+    }
+
+    #[test]
+    fn fixed_time_budget() {
+        // The extra time for unstable searches is taken out of the budget instead of extending it
+        let time = TimeManager::fixed(1000);
+        assert!(time.optimum() < Duration::from_millis(1000));
+        assert_eq!(time.optimum() + time.extra_time(2.0), Duration::from_millis(1000));
+        assert_eq!(time.extra_time(0.0), Duration::ZERO);
+        
+        let mut engine = Engine::default();
+        let start = std::time::Instant::now();
+        let (_mv, _, depth) = engine.get_best_move_timeout_ms_with_callback(300, |_| {}).unwrap().into_move().unwrap();
+        assert!(depth >= 1);
+        // Leave some margin for the last node and the thread overhead
+        assert!(start.elapsed() < Duration::from_millis(300 + 200), "Search took {:?}", start.elapsed());
+    }
+    
+    #[test]
+    fn best_move_changes() {
+        let (a, b, c) = (Move::new(0, 1, 1, MoveType::Quiet, None), Move::new(0, 2, 2, MoveType::Quiet, None), Move::new(0, 3, 3, MoveType::Quiet, None));
+        let mut tracker = BestMoveTracker::default();
+        tracker.update(&[a], 10, 1);
+        tracker.update(&[a, b], 20, 2);
+        assert_eq!(tracker.instability(), 0.0);
+        assert_eq!(tracker.stable_iterations(), 2);
+        assert_eq!(tracker.fallback_line(), None);
+        
+        // A single change gives more time, but the last iteration is still trusted
+        tracker.update(&[b], 30, 3);
+        assert_eq!(tracker.instability(), 1.0);
+        assert_eq!(tracker.stable_iterations(), 0);
+        assert_eq!(tracker.fallback_line(), None);
+        
+        // Another change right after the first one falls back to the deepest stable line
+        tracker.update(&[c], 40, 4);
+        assert_eq!(tracker.instability(), 1.5);
+        assert_eq!(tracker.fallback_line(), Some(&(vec![a, b], 20, 2)));
+        
+        // Once the new move is confirmed, it becomes the stable line
+        tracker.update(&[c, a], 50, 5);
+        assert_eq!(tracker.instability(), 0.75);
+        assert_eq!(tracker.stable_iterations(), 1);
+        assert_eq!(tracker.fallback_line(), None);
+        tracker.update(&[a], 60, 6);
+        assert_eq!(tracker.instability(), 1.375);
+        assert_eq!(tracker.fallback_line(), Some(&(vec![c, a], 50, 5)));
+    }
     
+    #[test]
+    fn bench_signature() {
+        let result = Engine::bench(3).unwrap();
+        assert_eq!(result.positions.len(), BENCH_POSITIONS.len());
+        assert!(result.positions.iter().all(|position| position.nodes > 0));
+        assert_eq!(result.nodes, result.positions.iter().map(|position| position.nodes).sum::<u64>());
+        // The search is deterministic
+        assert_eq!(Engine::bench(3).unwrap().nodes, result.nodes);
+    }
+
+    #[test]
+    fn custom_evaluator() {
+        let mut engine = Engine::default();
+        // The rook can capture the undefended queen
+        engine.load_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1").unwrap();
+        let default_score = engine.evaluate_position();
+        assert_eq!(engine.get_best_move(3).unwrap().into_move().unwrap().0.to_string(), "d1d5");
+
+        engine.set_evaluator(Box::new(InvertedEvaluator));
+        assert_eq!(engine.evaluate_position(), -default_score);
+        assert_ne!(engine.get_best_move(3).unwrap().into_move().unwrap().0.to_string(), "d1d5");
+        let mut clone = engine.clone();
+        assert_ne!(clone.get_best_move(3).unwrap().into_move().unwrap().0.to_string(), "d1d5");
+
+        let gs = GameState::from_debug_fen("4k3/8/8/3q4/8/8/8/3RK3 w - - 0 1");
+        let pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
+        let handle = SearchHandle::with_evaluator(Box::new(InvertedEvaluator));
+        let (pv, _score, _depth) = Searcher::get_best_move_with_callback(&pos, 3, 1, &handle, &mut |_| {});
+        assert_ne!(pv[0].to_string(), "d1d5");
+
+        engine.use_default_evaluator();
+        assert_eq!(engine.evaluate_position(), default_score);
+        assert_eq!(engine.get_best_move(3).unwrap().into_move().unwrap().0.to_string(), "d1d5");
+    }
+
     fn test_pv(fen: &str, depth: u8) {
         let gs = GameState::from_debug_fen(fen);
         let mut pos = PositionFactory::default().set_state(gs, None).unwrap().unwrap();
@@ -0,0 +1,74 @@
+extern crate protochess_engine_rs;
+
+// Run with `cargo test --features fuzz --test symmetry`
+
+#[cfg(all(test, feature = "fuzz"))]
+mod symmetry {
+    use protochess_engine_rs::{Engine, GameState, VariantRegistry};
+    use protochess_engine_rs::utils::symmetry;
+
+    // Positions of symmetric built-in variants, with castling rights, en passant squares, pieces in hand and check counts
+    const POSITIONS: [(&str, &str); 8] = [
+        ("standard", "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1"),
+        ("standard", "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1"),
+        ("standard", "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3"),
+        ("standard", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1"),
+        ("crazyhouse", "r1bqkb1r/pppp1ppp/2n2n2/4p3/4P3/2N2N2/PPPP1PPP/R1BQKB1R[Nb] w KQkq - 4 4"),
+        ("three-check", "rnbqkbnr/ppp2ppp/8/3pp3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 0 3 +1+0"),
+        ("atomic", "rnbqkb1r/pppppppp/5n2/8/8/2N5/PPPPPPPP/R1BQKBNR w KQkq - 2 2"),
+        ("capablanca", "r1abqkbcnr/pppppppppp/2n7/10/4P5/10/PPPP1PPPPP/RNABQKBCNR w KQkq - 1 2"),
+    ];
+
+    fn state(variant: &str, fen: &str) -> GameState {
+        GameState {
+            initial_state: VariantRegistry::get(variant).unwrap(),
+            initial_fen: Some(fen.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn current_fen(state: &GameState) -> String {
+        let mut engine = Engine::default();
+        let _result = engine.set_state(state.clone()).unwrap();
+        engine.get_state_diff().fen
+    }
+
+    #[test]
+    fn color_flip_round_trip() {
+        for (variant, fen) in POSITIONS {
+            // Flipping the colors twice must return the same position
+            let state = state(variant, fen);
+            let flipped = symmetry::color_flipped(&state).unwrap();
+            let twice = symmetry::color_flipped(&flipped).unwrap();
+            assert_ne!(current_fen(&state), current_fen(&flipped));
+            assert_eq!(current_fen(&state), current_fen(&twice));
+        }
+    }
+
+    #[test]
+    fn eval_symmetry() {
+        for (variant, fen) in POSITIONS {
+            symmetry::check_eval_symmetry(&state(variant, fen)).unwrap();
+        }
+        for state in symmetry::random_positions(1, 300) {
+            symmetry::check_eval_symmetry(&state).unwrap();
+        }
+    }
+
+    #[test]
+    fn search_symmetry() {
+        for (variant, fen) in POSITIONS {
+            symmetry::check_search_symmetry(&state(variant, fen), 4).unwrap();
+        }
+        for state in symmetry::random_positions(2, 60) {
+            symmetry::check_search_symmetry(&state, 3).unwrap();
+        }
+    }
+
+    #[test]
+    fn search_legality() {
+        for state in symmetry::random_positions(3, 200) {
+            symmetry::check_search_legality(&state, 2).unwrap();
+        }
+    }
+}
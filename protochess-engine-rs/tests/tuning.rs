@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod tuning_test {
+    use protochess_engine_rs::{Engine, GameState, GameResult, MakeMoveResultFlag, MakeMoveResultWinner, EvalWeights};
+    use protochess_engine_rs::utils::tuning::{parse_tuning_corpus, Tuner, TuningParams};
+    use protochess_engine_rs::utils::matchrunner::{run_match, MatchConfig, MatchGame, MatchLimit, MatchPlayer, MatchResult};
+
+    #[test]
+    fn engine_match() {
+        let player = |name: &str, depth| MatchPlayer {
+            name: name.to_string(), limit: MatchLimit::Depth(depth), num_threads: Some(1), eval_weights: TuningParams::default(),
+        };
+        let config = MatchConfig {
+            players: [player("Deep", 2), player("Shallow", 1)],
+            openings: vec![GameState::from_debug_fen("4k3/8/8/8/8/8/8/R3K3 w - - 0 1"), GameState::from_debug_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1")],
+            num_games: 4,
+            max_plies: 20,
+        };
+        let mut reported = 0;
+        let result = run_match(&config, |_| reported += 1).unwrap();
+        assert_eq!(reported, 4);
+        assert_eq!(result.games.iter().map(|game| game.white).collect::<Vec<_>>(), vec![0, 1, 0, 1]);
+        // Bare kings are a draw by insufficient material after the first move
+        for game in &result.games[2..] {
+            assert_eq!(game.plies, 1);
+            assert_eq!(game.result.as_ref().unwrap().flag, MakeMoveResultFlag::InsufficientMaterial);
+            assert_eq!(game.score(0), 0.5);
+        }
+        for game in &result.games[..2] {
+            assert!(game.plies <= 20);
+            assert_eq!(game.result.is_none(), game.plies == 20);
+        }
+        let pgn = result.to_pgn();
+        assert_eq!(pgn.matches("[Event \"Engine match\"]").count(), 4);
+        assert!(pgn.contains("[Round \"2\"]\n[White \"Shallow\"]\n[Black \"Deep\"]"));
+        
+        // 3 wins, 1 draw and 1 loss
+        let game = |white, winner| MatchGame { white, result: Some(GameResult { flag: MakeMoveResultFlag::Checkmate, winner }), plies: 1, pgn: String::new() };
+        let mut result = MatchResult { games: vec![game(0, MakeMoveResultWinner::White), game(1, MakeMoveResultWinner::Black),
+            game(0, MakeMoveResultWinner::White), game(1, MakeMoveResultWinner::White)] };
+        result.games.push(MatchGame { white: 0, result: None, plies: 100, pgn: String::new() });
+        assert_eq!(result.wins_draws_losses(), (3, 1, 1));
+        assert_eq!(result.score(), 0.7);
+        assert!((result.elo_difference().unwrap() - 147.2).abs() < 0.1);
+        assert!(result.elo_error_margin().unwrap() > 100.0);
+        result.games.truncate(1);
+        assert_eq!(result.elo_difference(), None);
+        assert_eq!(result.elo_error_margin(), None);
+    }
+
+    #[test]
+    fn evaluation_tuning() {
+        let corpus = "
+            # White is up a queen
+            4k3/pppppppp/8/8/8/8/PPPPPPPP/3QK3 w - - 0 1 ;1-0
+            3qk3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1 ;0-1
+            4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 b - - 0 1 ;1/2-1/2
+            r3k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 b - - 0 1 ;0.25
+            4k3/8/8/8/8/8/8/R3K3 w - - 0 1 3check ;1-0
+        ";
+        let entries = parse_tuning_corpus(corpus).unwrap();
+        assert_eq!(entries.len(), 5);
+        assert_eq!(entries[3].result, 0.25);
+        assert!(entries[4].fen.ends_with("3check"));
+        assert!(parse_tuning_corpus("4k3/8/8/8/8/8/8/4K3 w - - 0 1").is_err());
+        assert!(parse_tuning_corpus("4k3/8/8/8/8/8/8/4K3 w - - 0 1 ;2-0").is_err());
+        assert!(Tuner::new(&parse_tuning_corpus("invalid ;1-0").unwrap()).is_err());
+        
+        let tuner = Tuner::new(&entries).unwrap();
+        let initial = TuningParams::default();
+        let k = tuner.fit_scaling_constant(&initial);
+        assert!(k > 0.0);
+        let initial_error = tuner.error(&initial, k);
+        assert!(initial_error < 0.25);
+        let tuned = tuner.tune(&initial, 50);
+        assert!(tuner.error(&tuned, k) < initial_error);
+        // The parameters don't change if there are no iterations
+        assert_eq!(tuner.tune(&initial, 0), initial);
+    }
+
+    #[test]
+    fn eval_weights() {
+        let mut engine = Engine::default();
+        engine.load_fen("4k3/8/8/8/8/8/3P4/4K3 w - - 0 1").unwrap();
+        let default_eval = engine.evaluate_position_detailed();
+        let (_, default_score, _) = engine.get_best_move(2).unwrap().into_move().unwrap();
+
+        let mut weights = TuningParams::default();
+        weights.eval.passed_pawn = 2000;
+        weights.material.promotion += 500;
+        engine.set_eval_weights(weights);
+        assert_eq!(engine.eval_weights(), weights);
+        let eval = engine.evaluate_position_detailed();
+        assert_eq!(eval.pawn_structure, 2000 / 7 - 10);
+        assert_eq!(eval.material, default_eval.material + 500);
+        assert_eq!(eval.total, engine.evaluate_position());
+        // The searches use the same weights
+        let (_, score, _) = engine.get_best_move(2).unwrap().into_move().unwrap();
+        assert!(score > default_score + 500, "{} <= {} + 500", score, default_score);
+
+        engine.set_eval_weights(TuningParams::default());
+        assert_eq!(engine.evaluate_position_detailed(), default_eval);
+    }
+
+    #[test]
+    fn engine_match_eval_weights() {
+        // Advancing a passed pawn by 1 rank is penalized more than losing a queen
+        let eval = EvalWeights { passed_pawn: -20000, ..EvalWeights::default() };
+        let reversed_weights = TuningParams { eval, ..TuningParams::default() };
+        let player = |name: &str, eval_weights| MatchPlayer {
+            name: name.to_string(), limit: MatchLimit::Depth(2), num_threads: Some(1), eval_weights,
+        };
+        let config = MatchConfig {
+            players: [player("Default", TuningParams::default()), player("Reversed", reversed_weights)],
+            openings: vec![GameState::from_debug_fen("4k3/8/8/3q4/4P3/8/8/4K3 w - - 0 1")],
+            num_games: 2,
+            max_plies: 1,
+        };
+        let result = run_match(&config, |_| {}).unwrap();
+        // Only the default player captures the queen, which would advance the passed pawn
+        assert_eq!(result.games[0].white, 0);
+        assert!(result.games[0].pgn.contains("1. exd5"), "{}", result.games[0].pgn);
+        assert!(!result.games[1].pgn.contains("exd5"), "{}", result.games[1].pgn);
+    }
+}
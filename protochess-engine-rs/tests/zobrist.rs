@@ -337,6 +337,51 @@ mod zobrist_test {
         assert_eq!(engine1.get_zobrist(), engine2.get_zobrist());
     }
 
+    #[test]
+    fn zobrist_crazyhouse_drop() {
+        let mut engine = build_engine_from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1 CRAZYHOUSE");
+        let zob_start = engine.get_zobrist();
+        for m in ["e2e4", "d7d5", "e4d5", "d8d5", "P@e4"] {
+            assert_eq!(engine.make_move_str(m).unwrap().flag, MakeMoveResultFlag::Ok);
+        }
+        let expected = build_engine_from_fen("rnb1kbnr/ppp1pppp/8/3q4/4P3/8/PPPP1PPP/RNBQKBNR[p] b KQkq - 0 3 CRAZYHOUSE");
+        assert_eq!(engine.get_zobrist(), expected.get_zobrist());
+        for _ in 0..5 {
+            engine.undo().unwrap();
+        }
+        assert_eq!(engine.get_zobrist(), zob_start);
+    }
+    
+    #[test]
+    fn hand_affects_zobrist() {
+        let empty = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3[] w - - 0 1 CRAZYHOUSE");
+        let one_pawn = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3[P] w - - 0 1 CRAZYHOUSE");
+        let two_pawns = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3[PP] w - - 0 1 CRAZYHOUSE");
+        let black_pawn = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3[p] w - - 0 1 CRAZYHOUSE");
+        assert_ne!(empty.get_zobrist(), one_pawn.get_zobrist());
+        assert_ne!(one_pawn.get_zobrist(), two_pawns.get_zobrist());
+        assert_ne!(one_pawn.get_zobrist(), black_pawn.get_zobrist());
+        // An empty hand doesn't change the zobrist key
+        let standard = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        assert_eq!(empty.get_zobrist(), standard.get_zobrist());
+    }
+    
+    #[test]
+    fn walls_keep_player_to_move() {
+        // The lowest bit of the key is the player to move, adding or removing a wall must not flip it
+        let mut engine = build_engine_from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1");
+        let zob_start = engine.get_zobrist();
+        for y in 1..7 {
+            for x in 0..8 {
+                engine.add_wall(x, y).unwrap();
+                assert_eq!(engine.get_zobrist() & 1, zob_start & 1, "wall at ({x}, {y})");
+                assert_ne!(engine.get_zobrist(), zob_start);
+                engine.remove_wall(x, y).unwrap();
+                assert_eq!(engine.get_zobrist(), zob_start);
+            }
+        }
+    }
+    
     fn test_zobrist_sequence(moves: &[&str], expected_fen: &str) {
         let mut engine1 = Engine::default();
         let mut engine2 = Engine::default();
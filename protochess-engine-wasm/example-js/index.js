@@ -113,7 +113,11 @@ async function engineMoveButtonClick() {
       throw 'Timeout must be >= 0'
     }
     // Get the engine to make a move
-    const {moveInfo, evaluation, depth} = await protochess.getBestMoveTimeout(timeout)
+    const bestMove = await protochess.getBestMoveTimeout(timeout)
+    if (bestMove.gameOver) {
+      throw 'The game is already over: ' + bestMove.gameOver.flag
+    }
+    const {moveInfo, evaluation, depth} = bestMove
     const makeMoveResult = await protochess.makeMove(moveInfo)
     const {flag, winner, exploded} = makeMoveResult
     await updateBoard(flag, winner, exploded)
@@ -0,0 +1,202 @@
+// Continuous analysis of a position, without blocking the JS thread between updates
+
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[cfg(feature = "parallel")]
+use protochess_engine_rs::prelude::SearchHandle;
+use protochess_engine_rs::prelude::{Engine, SearchResult, Depth};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+
+use crate::serialize_types::{AnalysisUpdateSer, GameStateSer};
+
+// Default value of `setUpdateInterval()`
+const DEFAULT_INTERVAL_MS: u32 = 200;
+
+// State shared between the search and the timer that reports its progress
+#[derive(Default)]
+struct Analysis {
+    // Incremented each time the analysis is restarted, the results of older searches are discarded
+    generation: u64,
+    // Last result that has not been reported yet
+    pending: Option<SearchResult>,
+    // Without threads, each update searches one more depth on the JS thread
+    #[cfg(not(feature = "parallel"))]
+    engine: Option<Engine>,
+    #[cfg(not(feature = "parallel"))]
+    next_depth: Depth,
+}
+
+// Interval created with the `setInterval()` of the JS global object
+struct Timer {
+    id: JsValue,
+    _callback: Closure<dyn FnMut()>,
+}
+
+/// Analyzes a position until it's stopped, calling `callback({depth, score, pv})` at regular intervals when
+/// the search has a new result. The position can be changed while the analysis is running.
+/// With the `parallel` feature the search runs on the thread pool, otherwise each update searches one more
+/// depth on the JS thread (so the updates become slower as the depth increases).
+#[wasm_bindgen]
+pub struct ProtochessAnalyzer {
+    engine: Engine,
+    interval_ms: u32,
+    analysis: Arc<Mutex<Analysis>>,
+    // Callback of the running analysis
+    callback: Option<js_sys::Function>,
+    timer: Option<Timer>,
+    #[cfg(feature = "parallel")]
+    search: SearchHandle,
+}
+
+#[wasm_bindgen]
+impl ProtochessAnalyzer {
+    #[wasm_bindgen(constructor)]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> ProtochessAnalyzer {
+        ProtochessAnalyzer {
+            engine: Engine::default(),
+            interval_ms: DEFAULT_INTERVAL_MS,
+            analysis: Arc::default(),
+            callback: None,
+            timer: None,
+            #[cfg(feature = "parallel")]
+            search: SearchHandle::default(),
+        }
+    }
+
+    /// Sets the position to analyze (a `GameState`). If the analysis is running, the current search is
+    /// aborted and the new position is analyzed from depth 1.
+    #[wasm_bindgen(js_name = setPosition)]
+    pub fn set_position(&mut self, state: JsValue) -> Result<(), String> {
+        let state = GameStateSer::from_js(state)?;
+        let _result = self.engine.set_state(state)?;
+        if self.is_running() {
+            self.engine.validate_position()?;
+            self.restart_search();
+        }
+        Ok(())
+    }
+
+    /// Sets the number of milliseconds between updates. Takes effect the next time `start()` is called.
+    #[wasm_bindgen(js_name = setUpdateInterval)]
+    pub fn set_update_interval(&mut self, interval_ms: u32) {
+        self.interval_ms = interval_ms.max(1);
+    }
+
+    /// Starts analyzing the current position, replacing the previous analysis
+    pub fn start(&mut self, callback: js_sys::Function) -> Result<(), String> {
+        self.engine.validate_position()?;
+        self.stop();
+        let analysis = self.analysis.clone();
+        let on_tick = callback.clone();
+        let tick = Closure::<dyn FnMut()>::new(move || report_progress(&analysis, &on_tick));
+        self.timer = Some(set_interval(tick, self.interval_ms).map_err(|e| format!("Could not start the timer: {e:?}"))?);
+        self.callback = Some(callback);
+        self.restart_search();
+        Ok(())
+    }
+
+    /// Stops the analysis. The results that have not been reported yet are discarded.
+    pub fn stop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            clear_interval(&timer.id);
+        }
+        self.callback = None;
+        #[cfg(feature = "parallel")]
+        self.search.stop();
+        let mut analysis = self.analysis.lock().unwrap_or_else(PoisonError::into_inner);
+        analysis.generation += 1;
+        analysis.pending = None;
+        #[cfg(not(feature = "parallel"))]
+        {
+            analysis.engine = None;
+        }
+    }
+
+    #[wasm_bindgen(js_name = isRunning)]
+    pub fn is_running(&self) -> bool {
+        self.callback.is_some()
+    }
+}
+
+impl ProtochessAnalyzer {
+    // Aborts the current search and starts searching the current position
+    fn restart_search(&mut self) {
+        let mut analysis = self.analysis.lock().unwrap_or_else(PoisonError::into_inner);
+        analysis.generation += 1;
+        analysis.pending = None;
+        #[cfg(feature = "parallel")]
+        {
+            self.search.stop();
+            let mut engine = self.engine.clone();
+            self.search = engine.search_handle();
+            let generation = analysis.generation;
+            let shared = self.analysis.clone();
+            rayon::spawn(move || {
+                // The search only ends when it's stopped, or when it reaches the maximum depth
+                let _result = engine.get_best_move_with_callback(Depth::MAX, |result| {
+                    let mut analysis = shared.lock().unwrap_or_else(PoisonError::into_inner);
+                    if analysis.generation == generation {
+                        analysis.pending = Some(result);
+                    }
+                });
+            });
+        }
+        #[cfg(not(feature = "parallel"))]
+        {
+            analysis.engine = Some(self.engine.clone());
+            analysis.next_depth = 1;
+        }
+    }
+}
+
+impl Drop for ProtochessAnalyzer {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Called by the timer: reports the last result of the search, if there is a new one
+fn report_progress(analysis: &Mutex<Analysis>, callback: &js_sys::Function) {
+    let pending = {
+        let mut analysis = analysis.lock().unwrap_or_else(PoisonError::into_inner);
+        #[cfg(not(feature = "parallel"))]
+        search_next_depth(&mut analysis);
+        analysis.pending.take()
+    };
+    if let Some(result) = pending {
+        callback.call1(&JsValue::NULL, &AnalysisUpdateSer::to_js(result)).ok();
+    }
+}
+
+#[cfg(not(feature = "parallel"))]
+fn search_next_depth(analysis: &mut Analysis) {
+    let depth = analysis.next_depth;
+    let engine = match &mut analysis.engine {
+        Some(engine) if depth > 0 => engine,
+        _ => return,
+    };
+    let mut last = None;
+    if engine.get_best_move_with_callback(depth, |result| last = Some(result)).is_ok() {
+        analysis.pending = last;
+    }
+    // Stop at the maximum depth (the next depth wraps around to 0)
+    analysis.next_depth = depth.wrapping_add(1);
+}
+
+fn set_interval(callback: Closure<dyn FnMut()>, interval_ms: u32) -> Result<Timer, JsValue> {
+    let global = js_sys::global();
+    let set_interval: js_sys::Function = js_sys::Reflect::get(&global, &"setInterval".into())?.dyn_into()?;
+    let id = set_interval.call2(&global, callback.as_ref().unchecked_ref(), &interval_ms.into())?;
+    Ok(Timer { id, _callback: callback })
+}
+
+fn clear_interval(id: &JsValue) {
+    let global = js_sys::global();
+    if let Ok(clear_interval) = js_sys::Reflect::get(&global, &"clearInterval".into()) {
+        if let Ok(clear_interval) = clear_interval.dyn_into::<js_sys::Function>() {
+            clear_interval.call1(&global, id).ok();
+        }
+    }
+}
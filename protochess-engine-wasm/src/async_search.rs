@@ -0,0 +1,111 @@
+// Searches that don't block the caller, exposed to JS as Promises
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+use protochess_engine_rs::prelude::{Engine, SearchOutcome, SearchResult};
+use wasm_bindgen::JsValue;
+
+use crate::serialize_types::{GameOverSer, MoveInfoWithEvalDepthSer, SearchResultSer};
+use crate::utils::best_line_san;
+
+// Outcome of the search and the principal variation in algebraic notation (empty if the game is over)
+type BestMove = Result<(SearchOutcome, Vec<String>), String>;
+
+// State shared between the thread that runs the search and the future that waits for it
+#[derive(Default)]
+struct SearchState {
+    // Results of the depths that have not been reported yet
+    results: Vec<SearchResult>,
+    best_move: Option<BestMove>,
+    waker: Option<Waker>,
+}
+
+impl SearchState {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Future that resolves to the result of a timed search on a copy of the engine.
+/// With the `parallel` feature the search runs on the rayon thread pool, so the JS thread can
+/// call `stopSearch()` while it's running. Otherwise it runs when the future is first polled.
+pub struct AsyncSearch {
+    state: Arc<Mutex<SearchState>>,
+    on_result: Option<js_sys::Function>,
+    #[cfg(not(feature = "parallel"))]
+    pending: Option<(Engine, u64)>,
+}
+
+impl AsyncSearch {
+    pub fn start(engine: Engine, time_ms: u64, on_result: Option<js_sys::Function>) -> AsyncSearch {
+        let state = Arc::new(Mutex::new(SearchState::default()));
+        #[cfg(feature = "parallel")]
+        {
+            let state = state.clone();
+            rayon::spawn(move || run_search(engine, time_ms, &state));
+        }
+        AsyncSearch {
+            state,
+            on_result,
+            #[cfg(not(feature = "parallel"))]
+            pending: Some((engine, time_ms)),
+        }
+    }
+}
+
+impl Future for AsyncSearch {
+    type Output = Result<JsValue, JsValue>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        // All the fields are Unpin
+        let this = self.get_mut();
+        #[cfg(not(feature = "parallel"))]
+        if let Some((engine, time_ms)) = this.pending.take() {
+            run_search(engine, time_ms, &this.state);
+        }
+        let (results, best_move) = {
+            let mut state = this.state.lock().unwrap_or_else(PoisonError::into_inner);
+            if state.best_move.is_none() {
+                state.waker = Some(cx.waker().clone());
+            }
+            (std::mem::take(&mut state.results), state.best_move.take())
+        };
+        // The callback is called from the JS thread, since js_sys::Function is not Send
+        if let Some(callback) = &this.on_result {
+            for result in results {
+                callback.call1(&JsValue::NULL, &SearchResultSer::to_js(result)).ok();
+            }
+        }
+        match best_move {
+            Some(Ok((SearchOutcome::BestMove { mv, score, depth }, pv_san))) => Poll::Ready(Ok(MoveInfoWithEvalDepthSer::to_js(mv, score, depth, pv_san))),
+            Some(Ok((SearchOutcome::GameOver { result }, _))) => Poll::Ready(Ok(GameOverSer::to_js(result))),
+            Some(Err(msg)) => Poll::Ready(Err(JsValue::from_str(&msg))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+fn run_search(mut engine: Engine, time_ms: u64, state: &Mutex<SearchState>) {
+    let mut last_result = None;
+    let result = engine.get_best_move_timeout_ms_with_callback(time_ms, |result| {
+        last_result = Some(result.clone());
+        let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        state.results.push(result);
+        state.wake();
+    });
+    let result = result.map(|outcome| {
+        let pv_san = match &outcome {
+            SearchOutcome::BestMove { mv, .. } => best_line_san(&engine, *mv, last_result),
+            SearchOutcome::GameOver { .. } => Vec::new(),
+        };
+        (outcome, pv_san)
+    });
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+    state.best_move = Some(result);
+    state.wake();
+}
@@ -1,12 +1,16 @@
 mod utils;
 mod serialize_types;
+mod async_search;
+mod analyzer;
 
-use protochess_engine_rs::Engine;
-use serde_wasm_bindgen::to_value;
+use protochess_engine_rs::prelude::{Algorithm, Engine, GameState, InitialState, PieceDefinition, RenderOptions, SearchHandle, TimeControl, VariantRegistry};
+use serde_wasm_bindgen::{to_value, from_value};
 use wasm_bindgen::prelude::*;
 
+pub use analyzer::ProtochessAnalyzer;
+use async_search::AsyncSearch;
 use serialize_types::*;
-use utils::{set_panic_hook, SerVec};
+use utils::{outcome_to_js, outcome_with_depth_to_js, set_panic_hook, SerVec};
 
 #[cfg(feature = "parallel")]
 pub use wasm_bindgen_rayon::init_thread_pool;
@@ -19,9 +23,24 @@ pub fn greet() {
     console_log!("Hello from protochess-engine-wasm! (multithreading disabled)");
 }
 
+/// Creates a piece definition from Betza's funny notation (e.g. `"fmWfcF"`)
+#[wasm_bindgen(js_name = pieceFromBetza)]
+pub fn piece_from_betza(betza: &str, white_id: Option<char>, black_id: Option<char>) -> Result<JsValue, String> {
+    let piece = PieceDefinition::from_betza(betza, [white_id, black_id])?;
+    Ok(PieceDefinitionSer::to_js(piece))
+}
+
+/// Returns the movement of a piece definition in Betza's funny notation
+#[wasm_bindgen(js_name = pieceToBetza)]
+pub fn piece_to_betza(piece: JsValue) -> Result<String, String> {
+    PieceDefinitionSer::from_js(piece)?.to_betza()
+}
+
 #[wasm_bindgen]
 pub struct Protochess {
-    engine: Engine
+    engine: Engine,
+    // Handle of the last search started with `getBestMoveAsync()`
+    async_search: SearchHandle,
 }
 
 #[wasm_bindgen]
@@ -31,7 +50,8 @@ impl Protochess {
     pub fn new() -> Protochess {
         set_panic_hook();
         Protochess {
-            engine: Engine::default()
+            engine: Engine::default(),
+            async_search: SearchHandle::default(),
         }
     }
 
@@ -46,6 +66,11 @@ impl Protochess {
         self.engine.player_to_move()
     }
 
+    #[wasm_bindgen(js_name = repetitionCount)]
+    pub fn repetition_count(&self) -> u32 {
+        self.engine.repetition_count()
+    }
+
     #[wasm_bindgen(js_name = validatePosition)]
     pub fn validate_position(&mut self) -> Result<(), String> {
         self.engine.validate_position()
@@ -63,17 +88,227 @@ impl Protochess {
         Ok(MakeMoveResultSer::to_js(move_result))
     }
 
+    /// Returns the best move and its evaluation, or `{ gameOver: gameResult }` if the game is already over.
+    /// The same applies to the other `getBestMove*` functions that return a single move.
     #[wasm_bindgen(js_name = getBestMove)]
     pub fn get_best_move(&mut self, depth: u8) -> Result<JsValue, String> {
-        let (best_move, eval) = self.engine.get_best_move(depth)?;
+        let outcome = self.engine.get_best_move(depth)?;
+        Ok(outcome_to_js(outcome))
+    }
+    /// Same as `getBestMove`, but only searches the given list of moves
+    #[wasm_bindgen(js_name = getBestMoveAmong)]
+    pub fn get_best_move_among(&mut self, moves: JsValue, depth: u8) -> Result<JsValue, String> {
+        let moves: SerVec<MoveInfoSer> = from_value(moves).map_err(|e| format!("Argument must be a list of moves. {e}"))?;
+        let moves: Vec<_> = moves.into();
+        let (best_move, eval) = self.engine.get_best_move_among(&moves, depth)?;
+        Ok(MoveInfoWithEvalSer::to_js(best_move, eval))
+    }
+    /// Same as `getBestMove`, but doesn't play any of the given list of moves
+    #[wasm_bindgen(js_name = getBestMoveAvoiding)]
+    pub fn get_best_move_avoiding(&mut self, moves: JsValue, depth: u8) -> Result<JsValue, String> {
+        let moves: SerVec<MoveInfoSer> = from_value(moves).map_err(|e| format!("Argument must be a list of moves. {e}"))?;
+        let moves: Vec<_> = moves.into();
+        let (best_move, eval) = self.engine.get_best_move_avoiding(&moves, depth)?;
         Ok(MoveInfoWithEvalSer::to_js(best_move, eval))
     }
     #[wasm_bindgen(js_name = getBestMoveTimeout)]
     pub fn get_best_move_timeout(&mut self, time: usize) -> Result<JsValue, String> {
-        let (best_move, eval, depth) = self.engine.get_best_move_timeout(time as u64)?;
-        Ok(MoveInfoWithEvalDepthSer::to_js(best_move, eval, depth))
+        let mut last_result = None;
+        let outcome = self.engine.get_best_move_timeout_with_callback(time as u64, |result| last_result = Some(result))?;
+        Ok(outcome_with_depth_to_js(&self.engine, outcome, last_result))
+    }
+    #[wasm_bindgen(js_name = getBestMoveClock)]
+    pub fn get_best_move_clock(&mut self, remaining_ms: usize, increment_ms: usize, moves_to_go: Option<u32>) -> Result<JsValue, String> {
+        let clock = TimeControl {
+            remaining_ms: remaining_ms as u64,
+            increment_ms: increment_ms as u64,
+            moves_to_go,
+        };
+        let mut last_result = None;
+        let outcome = self.engine.get_best_move_clock_with_callback(clock, |result| last_result = Some(result))?;
+        Ok(outcome_with_depth_to_js(&self.engine, outcome, last_result))
+    }
+    #[wasm_bindgen(js_name = getBestMoveNodes)]
+    pub fn get_best_move_nodes(&mut self, max_nodes: usize) -> Result<JsValue, String> {
+        let outcome = self.engine.get_best_move_nodes(max_nodes as u64)?;
+        Ok(outcome_with_depth_to_js(&self.engine, outcome, None))
+    }
+    #[wasm_bindgen(js_name = getBestMoveMate)]
+    pub fn get_best_move_mate(&mut self, mate_in: u8) -> Result<JsValue, String> {
+        let line: SerVec<MoveInfoSer> = self.engine.get_best_move_mate(mate_in)?.into();
+        Ok(to_value(&line).unwrap())
+    }
+
+    /// Calls `callback(searchResult)` each time a new depth has been searched
+    #[wasm_bindgen(js_name = getBestMoveWithCallback)]
+    pub fn get_best_move_with_callback(&mut self, depth: u8, callback: &js_sys::Function) -> Result<JsValue, String> {
+        let outcome = self.engine.get_best_move_with_callback(depth, |result| {
+            callback.call1(&JsValue::NULL, &SearchResultSer::to_js(result)).ok();
+        })?;
+        Ok(outcome_to_js(outcome))
+    }
+    /// Same as `getBestMoveWithCallback()`, but each `searchResult` contains the first `treePlies` plies of the search tree
+    #[wasm_bindgen(js_name = getBestMoveWithTreeCallback)]
+    pub fn get_best_move_with_tree_callback(&mut self, depth: u8, tree_plies: u8, callback: &js_sys::Function) -> Result<JsValue, String> {
+        let (best_move, eval) = self.engine.get_best_move_with_tree_callback(depth, tree_plies, |result| {
+            callback.call1(&JsValue::NULL, &SearchResultSer::to_js(result)).ok();
+        })?;
+        Ok(MoveInfoWithEvalSer::to_js(best_move, eval))
+    }
+    /// Calls `callback(searchResult)` each time a new depth has been searched
+    #[wasm_bindgen(js_name = getBestMoveTimeoutWithCallback)]
+    pub fn get_best_move_timeout_with_callback(&mut self, time: usize, callback: &js_sys::Function) -> Result<JsValue, String> {
+        let mut last_result = None;
+        let outcome = self.engine.get_best_move_timeout_with_callback(time as u64, |result| {
+            callback.call1(&JsValue::NULL, &SearchResultSer::to_js(result.clone())).ok();
+            last_result = Some(result);
+        })?;
+        Ok(outcome_with_depth_to_js(&self.engine, outcome, last_result))
+    }
+
+    /// Returns a Promise that resolves to the best move found in `time` milliseconds, and calls
+    /// `callback(searchResult)` (if provided) each time a new depth has been searched.
+    /// The search runs on a copy of the current position, and it can be stopped early with `stopSearch()`.
+    /// Without the `parallel` feature, the search blocks the JS thread until the Promise is resolved.
+    #[wasm_bindgen(js_name = getBestMoveAsync)]
+    pub fn get_best_move_async(&mut self, time: usize, callback: Option<js_sys::Function>) -> js_sys::Promise {
+        let engine = self.engine.clone();
+        self.async_search = engine.search_handle();
+        wasm_bindgen_futures::future_to_promise(AsyncSearch::start(engine, time as u64, callback))
+    }
+    /// Stops the running search, which returns the best move found so far
+    #[wasm_bindgen(js_name = stopSearch)]
+    pub fn stop_search(&self) {
+        self.async_search.stop();
+        self.engine.search_handle().stop();
+    }
+    #[wasm_bindgen(js_name = isSearching)]
+    pub fn is_searching(&self) -> bool {
+        self.async_search.is_running() || self.engine.search_handle().is_running()
+    }
+
+    #[wasm_bindgen(js_name = getBestMoves)]
+    pub fn get_best_moves(&mut self, depth: u8, num_lines: u32) -> Result<JsValue, String> {
+        let lines: SerVec<PvLineSer> = self.engine.get_best_moves(depth, num_lines)?.into();
+        Ok(to_value(&lines).unwrap())
+    }
+
+    #[wasm_bindgen(js_name = explainBestMove)]
+    pub fn explain_best_move(&mut self, depth: u8, num_alternatives: usize) -> Result<JsValue, String> {
+        let explanation = self.engine.explain_best_move(depth, num_alternatives)?;
+        Ok(BestMoveExplanationSer::to_js(explanation))
+    }
+
+    /// Searches the positions of the current game for puzzles, see `Engine::find_tactics()`
+    #[wasm_bindgen(js_name = findTactics)]
+    pub fn find_tactics(&mut self, depth: u8, threshold: i32) -> Result<JsValue, String> {
+        let puzzles: SerVec<PuzzleCandidateSer> = self.engine.find_tactics(depth, threshold)?.into();
+        Ok(to_value(&puzzles).unwrap())
+    }
+
+    /// Compares each move of the current game with the best move, see `Engine::annotate_history()`
+    #[wasm_bindgen(js_name = annotateHistory)]
+    pub fn annotate_history(&mut self, depth: u8) -> Result<JsValue, String> {
+        let annotations: SerVec<MoveAnnotationSer> = self.engine.annotate_history(depth)?.into();
+        Ok(to_value(&annotations).unwrap())
+    }
+
+    #[wasm_bindgen(js_name = evaluatePosition)]
+    pub fn evaluate_position(&self) -> i32 {
+        self.engine.evaluate_position()
+    }
+
+    #[wasm_bindgen(js_name = evaluatePositionDetailed)]
+    pub fn evaluate_position_detailed(&self) -> JsValue {
+        EvalBreakdownSer::to_js(self.engine.evaluate_position_detailed())
+    }
+
+    pub fn undo(&mut self) -> Result<(), String> {
+        self.engine.undo()
     }
     
+    pub fn redo(&mut self) -> Result<JsValue, String> {
+        let result = self.engine.redo()?;
+        Ok(MakeMoveResultSer::to_js(result))
+    }
+    
+    #[wasm_bindgen(js_name = gotoPly)]
+    pub fn goto_ply(&mut self, ply: usize) -> Result<(), String> {
+        self.engine.goto_ply(ply)
+    }
+    
+    #[wasm_bindgen(js_name = currentPly)]
+    pub fn current_ply(&self) -> usize {
+        self.engine.current_ply()
+    }
+    
+    /// Returns all the moves of the game, including the ones after the current ply that can be redone
+    pub fn history(&self) -> Result<JsValue, String> {
+        let moves: SerVec<MoveInfoSer> = self.engine.history().to_vec().into();
+        Ok(to_value(&moves).unwrap())
+    }
+
+    /// Returns the mainline and the variations of the game, the mainline is the first one
+    pub fn lines(&self) -> Result<JsValue, String> {
+        let lines: SerVec<GameLineSer> = self.engine.game_tree().get_lines().to_vec().into();
+        Ok(to_value(&lines).unwrap())
+    }
+    
+    #[wasm_bindgen(js_name = currentLine)]
+    pub fn current_line(&self) -> usize {
+        self.engine.game_tree().get_current_line()
+    }
+    
+    /// Adds a variation (a list of moves) after `atPly` moves of the selected line, and returns its index
+    #[wasm_bindgen(js_name = addVariation)]
+    pub fn add_variation(&mut self, at_ply: usize, moves: JsValue) -> Result<usize, String> {
+        let moves: SerVec<MoveInfoSer> = from_value(moves).map_err(|e| format!("Argument must be a list of moves. {e}"))?;
+        let moves: Vec<_> = moves.into();
+        self.engine.add_variation(at_ply, &moves)
+    }
+    
+    #[wasm_bindgen(js_name = selectLine)]
+    pub fn select_line(&mut self, index: usize) -> Result<(), String> {
+        self.engine.select_line(index)
+    }
+    
+    /// Returns the indexes of the lines with an alternative to the last move played
+    #[wasm_bindgen(js_name = siblingLines)]
+    pub fn sibling_lines(&self) -> Result<JsValue, String> {
+        Ok(to_value(&self.engine.sibling_lines()).unwrap())
+    }
+
+    /// Returns the names of the variants that can be loaded with `loadVariant()`
+    #[wasm_bindgen(js_name = variantNames)]
+    pub fn variant_names(&self) -> Result<JsValue, String> {
+        Ok(to_value(&VariantRegistry::names()).unwrap())
+    }
+    
+    /// Starts a new game of a named variant
+    #[wasm_bindgen(js_name = loadVariant)]
+    pub fn load_variant(&mut self, name: &str) -> Result<JsValue, String> {
+        let result = self.engine.set_state(GameState::from_variant_name(name)?)?;
+        Ok(MakeMoveResultSer::to_js(result))
+    }
+    
+    /// Registers a variant (an `InitialState`) that can then be loaded with `loadVariant()`
+    #[wasm_bindgen(js_name = registerVariant)]
+    pub fn register_variant(&mut self, name: &str, variant: JsValue) -> Result<(), String> {
+        VariantRegistry::register(name, InitialStateSer::from_js(variant)?)
+    }
+
+    /// Validates the pieces of a variant (an `InitialState`). Returns a list of `[pieceIndex, errorMessage]` pairs,
+    /// which is empty if all the pieces are valid
+    #[wasm_bindgen(js_name = validatePieces)]
+    pub fn validate_pieces(&self, variant: JsValue) -> Result<JsValue, String> {
+        let variant: InitialState = InitialStateSer::from_js(variant)?;
+        let errors: Vec<(usize, String)> = match variant.validate_pieces() {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|(i, e)| (i, e.to_string())).collect(),
+        };
+        Ok(to_value(&errors).unwrap())
+    }
+
     #[wasm_bindgen(js_name = setState)]
     pub fn set_state(&mut self, state: JsValue) -> Result<JsValue, String> {
         let state = GameStateSer::from_js(state)?;
@@ -86,24 +321,110 @@ impl Protochess {
         self.engine.load_fen(fen)?;
         Ok(())
     }
-    
+
+    #[wasm_bindgen(js_name = loadPgn)]
+    pub fn load_pgn(&mut self, pgn: &str) -> Result<JsValue, String> {
+        let result = self.engine.load_pgn(pgn)?;
+        Ok(MakeMoveResultSer::to_js(result))
+    }
+
+    #[wasm_bindgen(js_name = toPgn)]
+    pub fn to_pgn(&self) -> String {
+        self.engine.to_pgn()
+    }
+
+    /// Exports the game in PGN format, with the annotations returned by `annotateHistory()`
+    #[wasm_bindgen(js_name = toAnnotatedPgn)]
+    pub fn to_annotated_pgn(&self, annotations: JsValue) -> Result<String, String> {
+        let annotations: SerVec<MoveAnnotationSer> = from_value(annotations).map_err(|e| format!("Argument must be a list of annotations. {e}"))?;
+        let annotations: Vec<_> = annotations.into();
+        Ok(self.engine.to_annotated_pgn(&annotations))
+    }
+
     #[wasm_bindgen(js_name = getState)]
     pub fn get_state(&mut self) -> JsValue {
         let state = self.engine.get_state();
         GameStateSer::to_js(state.clone())
     }
     
+    /// Returns the game state in the versioned JSON format of `GameState::to_json()`
+    #[wasm_bindgen(js_name = getStateJson)]
+    pub fn get_state_json(&mut self) -> String {
+        self.engine.get_state().to_json()
+    }
+    
+    /// Loads a game state in the JSON format of `getStateJson()` (or the previous versions)
+    #[wasm_bindgen(js_name = setStateJson)]
+    pub fn set_state_json(&mut self, json: &str) -> Result<JsValue, String> {
+        let result = self.engine.set_state(GameState::from_json(json)?)?;
+        Ok(MakeMoveResultSer::to_js(result))
+    }
+    
+    /// Returns the game state in the compact binary format of `GameState::to_bytes()`
+    #[wasm_bindgen(js_name = getStateBytes)]
+    pub fn get_state_bytes(&mut self) -> Vec<u8> {
+        self.engine.get_state().to_bytes()
+    }
+    
+    /// Loads a game state in the binary format of `getStateBytes()`
+    #[wasm_bindgen(js_name = setStateBytes)]
+    pub fn set_state_bytes(&mut self, bytes: &[u8]) -> Result<JsValue, String> {
+        let result = self.engine.set_state(GameState::from_bytes(bytes)?)?;
+        Ok(MakeMoveResultSer::to_js(result))
+    }
+    
+    /// Returns the hash of the content of the game state (see `GameState::content_hash()`), as a hexadecimal string
+    #[wasm_bindgen(js_name = contentHash)]
+    pub fn content_hash(&mut self) -> String {
+        format!("{:016x}", self.engine.get_state().content_hash())
+    }
+    
+    /// Draws the current position as text with Unicode chess glyphs
+    #[wasm_bindgen(js_name = renderText)]
+    pub fn render_text(&mut self, flipped: bool) -> String {
+        self.engine.render_text(&RenderOptions { flipped, ..RenderOptions::default() })
+    }
+    
+    /// Draws the current position as an SVG image
+    #[wasm_bindgen(js_name = renderSvg)]
+    pub fn render_svg(&mut self, flipped: bool) -> String {
+        self.engine.render_svg(&RenderOptions { flipped, ..RenderOptions::default() })
+    }
+    
     #[wasm_bindgen(js_name = getStateDiff)]
     pub fn get_state_diff(&mut self) -> JsValue {
         let state = self.engine.get_state_diff();
         StateDiffSer::to_js(state)
     }
     
+    /// Same as `getStateDiff()`, but hiding the enemy pieces that the player cannot see (fog of war)
+    #[wasm_bindgen(js_name = getStateForPlayer)]
+    pub fn get_state_for_player(&mut self, player: u8) -> JsValue {
+        let state = self.engine.get_state_for_player(player);
+        StateDiffSer::to_js(state)
+    }
+    
+    /// Returns the squares that `player` can see in fog of war variants, as a list of `[x, y]` coordinates
+    #[wasm_bindgen(js_name = visibleSquares)]
+    pub fn visible_squares(&mut self, player: u8) -> Result<JsValue, String> {
+        Ok(to_value(&self.engine.visible_squares(player)).unwrap())
+    }
+    
     #[wasm_bindgen(js_name = getMoveHistory)]
     pub fn get_move_history(&mut self) -> JsValue {
         let hist = self.engine.get_move_history();
         to_value(hist).unwrap()
     }
+    /// Returns the algebraic notation of the `ply`-th move of the move history (starting at 1)
+    #[wasm_bindgen(js_name = notationAtPly)]
+    pub fn notation_at_ply(&self, ply: usize) -> Result<String, String> {
+        self.engine.notation_at_ply(ply).map(str::to_string)
+    }
+    /// Returns the FEN after the first `ply` moves of the move history (0 for the initial position)
+    #[wasm_bindgen(js_name = fenAtPly)]
+    pub fn fen_at_ply(&self, ply: usize) -> Result<String, String> {
+        self.engine.fen_at_ply(ply)
+    }
     
     #[wasm_bindgen(js_name = legalMoves)]
     pub fn legal_moves(&mut self) -> Result<JsValue, String> {
@@ -111,6 +432,105 @@ impl Protochess {
         Ok(to_value(&moves).unwrap())
     }
     
+    #[wasm_bindgen(js_name = wouldBeLegal)]
+    pub fn would_be_legal(&mut self, mv: JsValue) -> Result<bool, String> {
+        let mv = MoveInfoSer::from_js(mv)?;
+        Ok(self.engine.would_be_legal(&mv))
+    }
+    
+    /// Returns the legal replies after a move, without making it (empty if the move is illegal or ends the game)
+    #[wasm_bindgen(js_name = legalMovesAfter)]
+    pub fn legal_moves_after(&mut self, mv: JsValue) -> Result<JsValue, String> {
+        let mv = MoveInfoSer::from_js(mv)?;
+        let moves: SerVec<MoveListSer> = self.engine.legal_moves_after(&mv).into();
+        Ok(to_value(&moves).unwrap())
+    }
+    
+    /// Returns the squares that the piece at `(x, y)` can move to, as a list of `[x, y]` coordinates
+    #[wasm_bindgen(js_name = legalTargetsFrom)]
+    pub fn legal_targets_from(&mut self, x: u8, y: u8) -> Result<JsValue, String> {
+        let targets = self.engine.legal_targets_from(x, y);
+        Ok(to_value(&targets).unwrap())
+    }
+    
+    /// Returns the display information (name, glyphs, image) of a piece type, or `null` if it has none
+    #[wasm_bindgen(js_name = getPieceDisplay)]
+    pub fn get_piece_display(&self, piece_id: char) -> JsValue {
+        match self.engine.get_piece_display(piece_id) {
+            Some(display) => to_value(&display).unwrap(),
+            None => JsValue::NULL,
+        }
+    }
+    
+    /// Returns the result of the game if it's over in the current position, or `null` if the game continues
+    #[wasm_bindgen(js_name = gameResult)]
+    pub fn game_result(&mut self) -> JsValue {
+        match self.engine.game_result() {
+            Some(result) => GameResultSer::to_js(result),
+            None => JsValue::NULL,
+        }
+    }
+    
+    /// Ends the game with the resignation of `player`, and returns the result
+    pub fn resign(&mut self, player: u8) -> Result<JsValue, String> {
+        Ok(GameResultSer::to_js(self.engine.resign(player)?))
+    }
+    
+    /// Offers a draw to the opponent of `player`. The offer is declined when the opponent makes a move.
+    #[wasm_bindgen(js_name = offerDraw)]
+    pub fn offer_draw(&mut self, player: u8) -> Result<(), String> {
+        self.engine.offer_draw(player)
+    }
+    
+    /// Returns the player that has offered a draw, or `undefined` if there is no offer
+    #[wasm_bindgen(js_name = drawOffer)]
+    pub fn draw_offer(&self) -> Option<u8> {
+        self.engine.draw_offer()
+    }
+    
+    /// Accepts the draw offer and ends the game, returns the result
+    #[wasm_bindgen(js_name = acceptDraw)]
+    pub fn accept_draw(&mut self) -> Result<JsValue, String> {
+        Ok(GameResultSer::to_js(self.engine.accept_draw()?))
+    }
+    
+    /// Ends the game with the given winner (`"white"`, `"black"` or `"none"` for a draw), returns the result
+    pub fn adjudicate(&mut self, winner: String) -> Result<JsValue, String> {
+        let winner = match winner.as_str() {
+            "white" | "black" | "none" => winner.into(),
+            _ => return Err(format!("Invalid winner: {winner}")),
+        };
+        Ok(GameResultSer::to_js(self.engine.adjudicate(winner)?))
+    }
+    
+    #[wasm_bindgen(js_name = isInCheck)]
+    pub fn is_in_check(&mut self) -> bool {
+        self.engine.is_in_check()
+    }
+    
+    /// Returns the squares of the pieces that give check to the player to move, as a list of `[x, y]` coordinates
+    #[wasm_bindgen(js_name = checkingPieces)]
+    pub fn checking_pieces(&mut self) -> Result<JsValue, String> {
+        Ok(to_value(&self.engine.checking_pieces()).unwrap())
+    }
+    
+    /// Returns the squares of the pieces of `player` that attack `(x, y)`, as a list of `[x, y]` coordinates
+    #[wasm_bindgen(js_name = attackersOf)]
+    pub fn attackers_of(&mut self, x: u8, y: u8, player: u8) -> Result<JsValue, String> {
+        Ok(to_value(&self.engine.attackers_of(x, y, player)).unwrap())
+    }
+    
+    #[wasm_bindgen(js_name = hasAnyLegalMove)]
+    pub fn has_any_legal_move(&mut self) -> bool {
+        self.engine.has_any_legal_move()
+    }
+    
+    #[wasm_bindgen(js_name = legalMovesStr)]
+    pub fn legal_moves_str(&mut self) -> Result<JsValue, String> {
+        let moves: SerVec<MoveStrSer> = self.engine.legal_moves_str().into();
+        Ok(to_value(&moves).unwrap())
+    }
+    
     #[wasm_bindgen(js_name = possiblePromotions)]
     pub fn possible_promotions(&mut self, from_x: u8, from_y: u8, to_x: u8, to_y: u8) -> Result<JsValue, String> {
         let from = (from_x, from_y);
@@ -127,4 +547,43 @@ impl Protochess {
     pub fn set_num_threads(&mut self, num_threads: u32) -> Result<(), String> {
         self.engine.set_num_threads(num_threads)
     }
+    #[wasm_bindgen(js_name = setDeterministic)]
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.engine.set_deterministic(deterministic)
+    }
+    #[wasm_bindgen(js_name = isDeterministic)]
+    pub fn is_deterministic(&self) -> bool {
+        self.engine.is_deterministic()
+    }
+    /// Searches with Monte Carlo tree search (`simulations > 0`), or with alpha-beta (`simulations == 0`)
+    #[wasm_bindgen(js_name = setMcts)]
+    pub fn set_mcts(&mut self, simulations: u32, c_puct: f64) -> Result<(), String> {
+        if simulations == 0 {
+            self.engine.set_search_algorithm(Algorithm::AlphaBeta)
+        } else {
+            self.engine.set_search_algorithm(Algorithm::Mcts { simulations, c_puct })
+        }
+    }
+    #[wasm_bindgen(js_name = setContempt)]
+    pub fn set_contempt(&mut self, contempt: i32) -> Result<(), String> {
+        self.engine.set_contempt(contempt)
+    }
+    #[wasm_bindgen(js_name = setPlayForWin)]
+    pub fn set_play_for_win(&mut self, play_for_win: bool) {
+        self.engine.set_play_for_win(play_for_win)
+    }
+    /// Weakens the engine, from 0 (weakest) to 20 (full strength)
+    #[wasm_bindgen(js_name = setSkillLevel)]
+    pub fn set_skill_level(&mut self, level: u8) -> Result<(), String> {
+        self.engine.set_skill_level(level)
+    }
+    #[wasm_bindgen(js_name = setRandomSeed)]
+    pub fn set_random_seed(&mut self, seed: u64) {
+        self.engine.set_random_seed(seed)
+    }
+    /// Chooses at random among the moves within `margin` centipawns of the best one, during the first `turns` turns
+    #[wasm_bindgen(js_name = setOpeningVariety)]
+    pub fn set_opening_variety(&mut self, turns: u32, margin: i32) -> Result<(), String> {
+        self.engine.set_opening_variety(turns, margin)
+    }
 }
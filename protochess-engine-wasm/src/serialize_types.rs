@@ -1,4 +1,4 @@
-use protochess_engine_rs::*;
+use protochess_engine_rs::prelude::*;
 use serde_wasm_bindgen::{to_value, from_value};
 use wasm_bindgen::prelude::*;
 use super::utils::SerVec;
@@ -53,26 +53,93 @@ generate_wrapper!(MoveListSer, MoveList, [
     moves, SerVec<MoveInfoSer>
 ]);
 
+generate_wrapper!(MoveStrSer, MoveStr, [
+    long, String,
+    san, String
+]);
+
+
+generate_wrapper!(SquareChangeSer, SquareChange, [
+    from, Option<(u8, u8)>,
+    to, Option<(u8, u8)>,
+    piece_id, char,
+    promotion, Option<char>,
+    player, u8
+]);
+
+generate_wrapper!(BoardDeltaSer, BoardDelta, [
+    changes, SerVec<SquareChangeSer>
+]);
 
 generate_wrapper!(MakeMoveResultSer, MakeMoveResult, [
     flag, String,
     winner, String,
     exploded, SerVec<(u8, u8)>,
-    move_notation, Option<String>
+    move_notation, Option<String>,
+    delta, BoardDeltaSer
 ]);
 
+generate_wrapper!(GameLineSer, GameLine, [
+    parent, Option<usize>,
+    start_ply, usize,
+    moves, SerVec<MoveInfoSer>,
+    notation, Vec<String>
+]);
+
+generate_wrapper!(GameResultSer, GameResult, [
+    flag, String,
+    winner, String
+]);
+
+// Returned by the searches instead of the best move when the game is already over, as `{ gameOver: result }`
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameOverSer {
+    game_over: GameResultSer,
+}
+impl GameOverSer {
+    pub fn to_js(result: GameResult) -> JsValue {
+        to_value(&GameOverSer { game_over: result.into() }).unwrap()
+    }
+}
+
+
+// ScoreType, as `{ kind: "cp", value: centipawns }` or `{ kind: "mate", value: plies }`
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ScoreTypeSer {
+    kind: String,
+    value: i32,
+}
+impl From<ScoreType> for ScoreTypeSer {
+    fn from(score: ScoreType) -> Self {
+        match score {
+            ScoreType::Cp(value) => ScoreTypeSer { kind: "cp".to_string(), value },
+            ScoreType::Mate(value) => ScoreTypeSer { kind: "mate".to_string(), value },
+        }
+    }
+}
+impl From<ScoreTypeSer> for ScoreType {
+    fn from(score: ScoreTypeSer) -> Self {
+        match score.kind.as_str() {
+            "mate" => ScoreType::Mate(score.value),
+            _ => ScoreType::Cp(score.value),
+        }
+    }
+}
 
 #[derive(serde::Serialize, serde::Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MoveInfoWithEvalSer {
     move_info: MoveInfoSer,
     evaluation: i32,
+    score_type: ScoreTypeSer,
 }
 impl MoveInfoWithEvalSer {
     pub fn to_js(mv: MoveInfo, evaluation: i32) -> JsValue {
         let val = MoveInfoWithEvalSer {
             move_info: MoveInfoSer::from(mv),
-            evaluation
+            evaluation,
+            score_type: ScoreType::from_centipawns(evaluation).into(),
         };
         to_value(&val).unwrap()
     }
@@ -83,31 +150,196 @@ impl MoveInfoWithEvalSer {
 pub struct MoveInfoWithEvalDepthSer {
     move_info: MoveInfoSer,
     evaluation: i32,
+    score_type: ScoreTypeSer,
     depth: u8,
+    pv_san: SerVec<String>,
 }
 impl MoveInfoWithEvalDepthSer {
-    pub fn to_js(mv: MoveInfo, evaluation: i32, depth: u8) -> JsValue {
+    pub fn to_js(mv: MoveInfo, evaluation: i32, depth: u8, pv_san: Vec<String>) -> JsValue {
         let val = MoveInfoWithEvalDepthSer {
             move_info: MoveInfoSer::from(mv),
             evaluation,
-            depth
+            score_type: ScoreType::from_centipawns(evaluation).into(),
+            depth,
+            pv_san: pv_san.into(),
+        };
+        to_value(&val).unwrap()
+    }
+}
+
+// Progress of a ProtochessAnalyzer, a subset of SearchResult
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalysisUpdateSer {
+    depth: u8,
+    score: i32,
+    score_type: ScoreTypeSer,
+    pv: SerVec<MoveInfoSer>,
+    pv_san: SerVec<String>,
+    total_nodes: u64,
+    nps: u64,
+}
+impl AnalysisUpdateSer {
+    pub fn to_js(result: SearchResult) -> JsValue {
+        let val = AnalysisUpdateSer {
+            depth: result.depth,
+            score: result.score,
+            score_type: result.score_type.into(),
+            pv: result.pv.into(),
+            pv_san: result.pv_san.into(),
+            total_nodes: result.total_nodes,
+            nps: result.nps,
         };
         to_value(&val).unwrap()
     }
 }
 
+generate_wrapper!(SearchResultSer, SearchResult, [
+    depth, u8,
+    score, i32,
+    score_type, ScoreTypeSer,
+    pv, SerVec<MoveInfoSer>,
+    pv_san, SerVec<String>,
+    nodes, u64,
+    total_nodes, u64,
+    elapsed_ms, u64,
+    nps, u64,
+    branching_factor, f64,
+    stats, SearchStatsSer,
+    tree, SerVec<SearchTreeNodeSer>
+]);
+
+generate_wrapper!(SearchStatsSer, SearchStats, [
+    nodes, u64,
+    qnodes, u64,
+    tt_probes, u64,
+    tt_hits, u64,
+    eval_probes, u64,
+    eval_hits, u64,
+    cutoffs_by_move_index, [u64; CUTOFF_MOVE_INDEXES],
+    null_move_prunes, u64,
+    lmr_searches, u64,
+    lmr_researches, u64,
+    singular_searches, u64,
+    singular_extensions, u64
+]);
+
+// Option<MoveInfo> can't be converted with into(), so this wrapper is implemented manually
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchTreeNodeSer {
+    ply: u8,
+    mv: Option<MoveInfoSer>,
+    depth: u8,
+    alpha: i32,
+    beta: i32,
+    score: Option<i32>,
+}
+impl From<SearchTreeNode> for SearchTreeNodeSer {
+    fn from(val: SearchTreeNode) -> Self {
+        SearchTreeNodeSer {
+            ply: val.ply,
+            mv: val.mv.map(Into::into),
+            depth: val.depth,
+            alpha: val.alpha,
+            beta: val.beta,
+            score: val.score,
+        }
+    }
+}
+impl From<SearchTreeNodeSer> for SearchTreeNode {
+    fn from(val: SearchTreeNodeSer) -> Self {
+        SearchTreeNode {
+            ply: val.ply,
+            mv: val.mv.map(Into::into),
+            depth: val.depth,
+            alpha: val.alpha,
+            beta: val.beta,
+            score: val.score,
+        }
+    }
+}
+
+generate_wrapper!(PvLineSer, PvLine, [
+    score, i32,
+    score_type, ScoreTypeSer,
+    pv, SerVec<MoveInfoSer>
+]);
+
+generate_wrapper!(AlternativeMoveSer, AlternativeMove, [
+    move_info, MoveInfoSer,
+    score, i32,
+    refutation, SerVec<MoveInfoSer>
+]);
+
+generate_wrapper!(BestMoveExplanationSer, BestMoveExplanation, [
+    best_move, MoveInfoSer,
+    score, i32,
+    pv, SerVec<MoveInfoSer>,
+    alternatives, SerVec<AlternativeMoveSer>
+]);
+
+generate_wrapper!(PuzzleCandidateSer, PuzzleCandidate, [
+    ply, usize,
+    fen, String,
+    score, i32,
+    gain, i32,
+    solution, SerVec<MoveInfoSer>,
+    themes, Vec<String>
+]);
+
+generate_wrapper!(MoveAnnotationSer, MoveAnnotation, [
+    mv, MoveInfoSer,
+    notation, String,
+    best_score, i32,
+    score, i32,
+    best_line, SerVec<MoveInfoSer>,
+    best_notation, String,
+    refutation, SerVec<MoveInfoSer>,
+    classification, String
+]);
+
+generate_wrapper!(PieceEvalSer, PieceEval, [
+    piece_id, char,
+    position, (u8, u8),
+    material, i32,
+    positional, i32,
+    mobility, i32
+]);
+
+generate_wrapper!(EvalBreakdownSer, EvalBreakdown, [
+    total, i32,
+    material, i32,
+    positional, i32,
+    mobility, i32,
+    pawn_structure, i32,
+    king_safety, i32,
+    checks, i32,
+    variant, i32,
+    is_endgame, bool,
+    pieces, SerVec<PieceEvalSer>
+]);
+
 generate_wrapper!(PieceDefinitionSer, PieceDefinition, [
     ids, [Option<char>; 2],
     notation_prefix, [Option<String>; 2],
+    display, Option<PieceDisplay>,
     is_leader, bool,
     castle_files, Option<(u8, u8)>,
     is_castle_rook, bool,
+    castling_rules, Vec<CastlingRule>,
     explode_on_capture, bool,
     explosion_deltas, Vec<(i8, i8)>,
     immune_to_explosion, bool,
+    capture_mode, CaptureMode,
+    resets_halfmove_clock, Option<bool>,
+    max_moves, Option<u32>,
     promotion_squares, Vec<(u8, u8)>,
     promo_vals, [Vec<char>; 2],
+    promotion_is_optional, bool,
+    demotes_to, Option<char>,
     double_jump_squares, Vec<(u8, u8)>,
+    double_jump_steps, Option<u8>,
     attack_sliding_deltas, Vec<Vec<(i8, i8)>>,
     attack_jump_deltas, Vec<(i8, i8)>,
     attack_north, bool,
@@ -118,6 +350,7 @@ generate_wrapper!(PieceDefinitionSer, PieceDefinition, [
     attack_northwest, bool,
     attack_southeast, bool,
     attack_southwest, bool,
+    attack_hops, Vec<HopMove>,
     translate_jump_deltas, Vec<(i8, i8)>,
     translate_sliding_deltas, Vec<Vec<(i8, i8)>>,
     translate_north, bool,
@@ -128,16 +361,29 @@ generate_wrapper!(PieceDefinitionSer, PieceDefinition, [
     translate_northwest, bool,
     translate_southeast, bool,
     translate_southwest, bool,
+    translate_hops, Vec<HopMove>,
     win_squares, Vec<(u8, u8)>
 ]);
 
 generate_wrapper!(GlobalRulesSer, GlobalRules, [
     capturing_is_forced, bool,
     check_is_forbidden, bool,
-    stalemated_player_loses, bool,
+    stalemate_rule, StalemateRule,
     invert_win_conditions, bool,
     repetitions_draw, u8,
-    checks_to_lose, u8
+    checks_to_lose, u8,
+    moves_without_progress_draw, u8,
+    captured_pieces_to_hand, bool,
+    gating, bool,
+    leader_capture_rule, LeaderCaptureRule,
+    leaders_cannot_face, bool,
+    moves_per_turn, u8,
+    progressive, bool,
+    passing_allowed, bool,
+    win_conditions, [Vec<WinCondition>; 2],
+    move_limit, u16,
+    captures_to_win, u8,
+    square_effects, Vec<SquareEffect>
 ]);
 
 generate_wrapper!(InitialStateSer, InitialState, [
@@ -149,14 +395,58 @@ generate_wrapper!(InitialStateSer, InitialState, [
     global_rules, GlobalRulesSer
 ]);
 
-generate_wrapper!(GameStateSer, GameState, [
-    initial_state, InitialStateSer,
-    initial_fen, Option<String>,
-    move_history, SerVec<MoveInfoSer>
-]);
+// Implemented manually, so that priorFens and termination can be omitted
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GameStateSer {
+    initial_state: InitialStateSer,
+    initial_fen: Option<String>,
+    move_history: SerVec<MoveInfoSer>,
+    #[serde(default)]
+    prior_fens: Vec<String>,
+    #[serde(default)]
+    termination: Option<GameTermination>,
+}
+impl GameStateSer {
+    pub fn to_js(val: GameState) -> JsValue {
+        to_value(&Self::from(val)).unwrap()
+    }
+    pub fn from_js(val: JsValue) -> Result<GameState, String> {
+        let wrapper = from_value::<GameStateSer>(val)
+            .map_err(|e| format!("Argument must be of type GameStateSer. {e}"))?;
+        Ok(wrapper.into())
+    }
+}
+impl From<GameState> for GameStateSer {
+    fn from(val: GameState) -> Self {
+        GameStateSer {
+            initial_state: val.initial_state.into(),
+            initial_fen: val.initial_fen,
+            move_history: val.move_history.into(),
+            prior_fens: val.prior_fens,
+            termination: val.termination,
+        }
+    }
+}
+impl From<GameStateSer> for GameState {
+    fn from(val: GameStateSer) -> Self {
+        GameState {
+            initial_state: val.initial_state.into(),
+            initial_fen: val.initial_fen,
+            move_history: val.move_history.into(),
+            prior_fens: val.prior_fens,
+            termination: val.termination,
+        }
+    }
+}
+
+// Same as `PieceSquares`: piece id, player and coordinates of the pieces
+type PieceSquaresSer = (char, u8, Vec<(u8, u8)>);
 
 generate_wrapper!(StateDiffSer, StateDiff, [
     fen, String,
     in_check, bool,
-    player_to_move, u8
+    player_to_move, u8,
+    walls, Vec<(u8, u8)>,
+    pieces, Vec<PieceSquaresSer>
 ]);
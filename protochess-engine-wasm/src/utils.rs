@@ -1,4 +1,7 @@
-use wasm_bindgen::prelude::wasm_bindgen;
+use protochess_engine_rs::prelude::{Engine, MoveInfo, SearchOutcome, SearchResult};
+use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
+
+use crate::serialize_types::{GameOverSer, MoveInfoWithEvalDepthSer, MoveInfoWithEvalSer};
 
 pub fn set_panic_hook() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -11,7 +14,32 @@ pub fn set_panic_hook() {
     console_error_panic_hook::set_once();
 }
 
+// Principal variation (in algebraic notation) of a search that returned `best_move`, taken from its last result.
+// If the last result starts with another move (e.g. the skill level chose a worse line), only the best move is returned.
+pub fn best_line_san(engine: &Engine, best_move: MoveInfo, last_result: Option<SearchResult>) -> Vec<String> {
+    match last_result {
+        Some(result) if result.pv.first() == Some(&best_move) => result.pv_san,
+        _ => engine.line_to_san(&[best_move]),
+    }
+}
+
+// Best move and evaluation of a search, or `{ gameOver: result }` if the game was already over
+pub fn outcome_to_js(outcome: SearchOutcome) -> JsValue {
+    match outcome {
+        SearchOutcome::BestMove { mv, score, .. } => MoveInfoWithEvalSer::to_js(mv, score),
+        SearchOutcome::GameOver { result } => GameOverSer::to_js(result),
+    }
+}
 
+// Same as `outcome_to_js()`, but also includes the depth and the principal variation of the search
+pub fn outcome_with_depth_to_js(engine: &Engine, outcome: SearchOutcome, last_result: Option<SearchResult>) -> JsValue {
+    match outcome {
+        SearchOutcome::BestMove { mv, score, depth } => {
+            MoveInfoWithEvalDepthSer::to_js(mv, score, depth, best_line_san(engine, mv, last_result))
+        },
+        SearchOutcome::GameOver { result } => GameOverSer::to_js(result),
+    }
+}
 
 // Serializable replacement for Vec<T> that implements From/Into for the element type
 pub struct SerVec<T>(Vec<T>);